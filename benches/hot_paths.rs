@@ -0,0 +1,173 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Conversion and comparison hot paths on synthetic snapshots of 200, 1000
+//! and 5000 tickers: currency conversion, rate map construction, market
+//! shares and the trend analysis behind `trend-analysis`.
+//!
+//! Run with `cargo bench --bench hot_paths`; Criterion compares each run with
+//! the previous one in `target/criterion/`.
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use std::collections::BTreeMap;
+use std::hint::black_box;
+use top200_core::conversion;
+use top200_rs::advanced_comparisons::{self, MarketCapRecord};
+use top200_rs::compare_marketcaps;
+use top200_rs::corporate_actions::CorporateActionIndex;
+use top200_rs::currencies::convert_currency_with_rate;
+use top200_rs::ticker_aliases::AppliedAliases;
+
+const SIZES: [usize; 3] = [200, 1000, 5000];
+
+/// Listing currencies with their USD rate, as quoted by the forex providers
+const QUOTES: [(&str, f64); 12] = [
+    ("EUR/USD", 1.08),
+    ("GBP/USD", 1.27),
+    ("USD/JPY", 151.2),
+    ("USD/CHF", 0.89),
+    ("USD/SEK", 10.4),
+    ("USD/DKK", 6.9),
+    ("USD/HKD", 7.8),
+    ("USD/CNY", 7.2),
+    ("USD/INR", 83.1),
+    ("USD/KRW", 1350.0),
+    ("USD/CAD", 1.36),
+    ("AUD/USD", 0.66),
+];
+
+/// Currency of the `i`th synthetic company
+fn currency(i: usize) -> &'static str {
+    const CURRENCIES: [&str; 13] = [
+        "USD", "EUR", "GBP", "JPY", "CHF", "SEK", "DKK", "HKD", "CNY", "INR", "KRW", "CAD", "AUD",
+    ];
+    CURRENCIES[i % CURRENCIES.len()]
+}
+
+fn market_cap(i: usize, tickers: usize, period: usize) -> f64 {
+    1e9 * (tickers - i) as f64 * (1.0 + ((i + period) % 7) as f64 / 50.0)
+}
+
+fn trend_record(i: usize, tickers: usize, period: usize) -> MarketCapRecord {
+    let market_cap = market_cap(i, tickers, period);
+    MarketCapRecord {
+        rank: Some(i + 1),
+        ticker: format!("T{}", i),
+        name: format!("Company {}", i),
+        market_cap_original: Some(market_cap),
+        original_currency: Some(currency(i).to_string()),
+        market_cap_eur: Some(market_cap * 0.92),
+        market_cap_usd: Some(market_cap),
+        exchange: Some("NYSE".to_string()),
+    }
+}
+
+fn comparison_record(i: usize, tickers: usize) -> compare_marketcaps::MarketCapRecord {
+    let record = trend_record(i, tickers, 0);
+    compare_marketcaps::MarketCapRecord {
+        rank: record.rank,
+        ticker: record.ticker,
+        name: record.name,
+        market_cap_original: record.market_cap_original,
+        original_currency: record.original_currency,
+        market_cap_eur: record.market_cap_eur,
+        market_cap_usd: record.market_cap_usd,
+        exchange: record.exchange,
+    }
+}
+
+fn bench_conversion(c: &mut Criterion) {
+    let rates = conversion::rate_map(QUOTES);
+    let mut group = c.benchmark_group("convert_currency_with_rate");
+    for tickers in SIZES {
+        let amounts: Vec<(f64, &str)> = (0..tickers)
+            .map(|i| (market_cap(i, tickers, 0), currency(i)))
+            .collect();
+        group.bench_with_input(
+            BenchmarkId::from_parameter(tickers),
+            &amounts,
+            |b, amounts| {
+                b.iter(|| {
+                    for (amount, currency) in amounts {
+                        black_box(convert_currency_with_rate(*amount, currency, "USD", &rates));
+                    }
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn bench_rate_map(c: &mut Criterion) {
+    c.bench_function("rate_map", |b| {
+        b.iter(|| conversion::rate_map(black_box(QUOTES)))
+    });
+}
+
+fn bench_market_shares(c: &mut Criterion) {
+    let mut group = c.benchmark_group("calculate_market_shares");
+    for tickers in SIZES {
+        let records: Vec<compare_marketcaps::MarketCapRecord> = (0..tickers)
+            .map(|i| comparison_record(i, tickers))
+            .collect();
+        group.bench_with_input(
+            BenchmarkId::from_parameter(tickers),
+            &records,
+            |b, records| b.iter(|| compare_marketcaps::calculate_market_shares(records)),
+        );
+    }
+    group.finish();
+}
+
+/// Trend analysis over 12 monthly snapshots already loaded, the part of
+/// `analyze_trends` after reading the CSVs (see the `trend_streaming` bench)
+fn bench_trends(c: &mut Criterion) {
+    let rates = conversion::rate_map(QUOTES);
+    let dates: Vec<String> = (1..=12)
+        .map(|month| format!("2024-{:02}-01", month))
+        .collect();
+    let mut group = c.benchmark_group("analyze_trends");
+    group.sample_size(10);
+    for tickers in SIZES {
+        let all_data: BTreeMap<String, BTreeMap<String, MarketCapRecord>> = dates
+            .iter()
+            .enumerate()
+            .map(|(period, date)| {
+                let records = (0..tickers)
+                    .map(|i| trend_record(i, tickers, period))
+                    .map(|r| (r.ticker.clone(), r))
+                    .collect();
+                (date.clone(), records)
+            })
+            .collect();
+        group.bench_with_input(
+            BenchmarkId::from_parameter(tickers),
+            &all_data,
+            |b, all_data| {
+                b.iter(|| {
+                    advanced_comparisons::compute_trends(
+                        &dates,
+                        all_data,
+                        &rates,
+                        CorporateActionIndex::default(),
+                        false,
+                        None,
+                        AppliedAliases::default(),
+                    )
+                    .unwrap()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_conversion,
+    bench_rate_map,
+    bench_market_shares,
+    bench_trends
+);
+criterion_main!(benches);
@@ -0,0 +1,154 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Trend analysis over large historical exports: every snapshot loaded into
+//! memory before aggregating (the previous approach, still behind
+//! `compute_trends`) against streaming each CSV into a `TrendBuilder`.
+//! Criterion times both; the peak heap each one reaches is measured with a
+//! counting allocator and printed before the timings.
+//!
+//! Run with `cargo bench --bench trend_streaming`.
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use top200_rs::advanced_comparisons::{self, MarketCapRecord, TrendBuilder};
+use top200_rs::corporate_actions::CorporateActionIndex;
+use top200_rs::ticker_aliases::AppliedAliases;
+
+/// System allocator that counts the bytes in use and their peak
+struct CountingAlloc;
+
+static IN_USE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            let in_use = IN_USE.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(in_use, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        IN_USE.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAlloc = CountingAlloc;
+
+/// Most heap `run` held at once beyond what was allocated before it, in MiB
+fn peak_heap_mib(run: impl FnOnce()) -> f64 {
+    let before = IN_USE.load(Ordering::Relaxed);
+    PEAK.store(before, Ordering::Relaxed);
+    run();
+    (PEAK.load(Ordering::Relaxed) - before) as f64 / (1024.0 * 1024.0)
+}
+
+/// Monthly snapshots of `tickers` synthetic companies over `dates` months
+fn write_snapshots(dir: &Path, tickers: usize, dates: usize) -> Vec<(String, PathBuf)> {
+    (0..dates)
+        .map(|month| {
+            let date = format!("{}-{:02}-01", 2020 + month / 12, month % 12 + 1);
+            let path = dir.join(format!("marketcaps_{}.csv", date));
+            let mut file = BufWriter::new(File::create(&path).unwrap());
+            writeln!(
+                file,
+                "Rank,Ticker,Name,Market Cap (Original),Original Currency,Market Cap (EUR),Market Cap (USD),Exchange"
+            )
+            .unwrap();
+            for rank in 0..tickers {
+                let market_cap = 1e9 * (tickers - rank) as f64 * (1.0 + month as f64 / 100.0);
+                writeln!(
+                    file,
+                    "{},T{},Company {},{},USD,{},{},NYSE",
+                    rank + 1,
+                    rank,
+                    rank,
+                    market_cap,
+                    market_cap * 0.9,
+                    market_cap
+                )
+                .unwrap();
+            }
+            (date, path)
+        })
+        .collect()
+}
+
+fn load_all(snapshots: &[(String, PathBuf)], dates: &[String], rates: &HashMap<String, f64>) {
+    let all_data: BTreeMap<String, BTreeMap<String, MarketCapRecord>> = snapshots
+        .iter()
+        .map(|(date, path)| {
+            let records =
+                advanced_comparisons::read_market_cap_csv(path.to_str().unwrap()).unwrap();
+            let by_ticker = records.into_iter().map(|r| (r.ticker.clone(), r)).collect();
+            (date.clone(), by_ticker)
+        })
+        .collect();
+    advanced_comparisons::compute_trends(
+        dates,
+        &all_data,
+        rates,
+        CorporateActionIndex::default(),
+        false,
+        None,
+        AppliedAliases::default(),
+    )
+    .unwrap();
+}
+
+fn stream(snapshots: &[(String, PathBuf)], dates: &[String], rates: &HashMap<String, f64>) {
+    let mut builder = TrendBuilder::new(dates, rates);
+    for (date, path) in snapshots {
+        let records = advanced_comparisons::market_cap_csv_records(path.to_str().unwrap())
+            .unwrap()
+            .map(Result::unwrap);
+        builder.add_snapshot(date, records).unwrap();
+    }
+    builder
+        .finish(
+            CorporateActionIndex::default(),
+            false,
+            None,
+            AppliedAliases::default(),
+        )
+        .unwrap();
+}
+
+fn bench_trends(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let rates = HashMap::new();
+    let mut group = c.benchmark_group("trend_analysis");
+    group.sample_size(10);
+    for (tickers, months) in [(200, 24), (500, 60)] {
+        let snapshots = write_snapshots(dir.path(), tickers, months);
+        let dates: Vec<String> = snapshots.iter().map(|(date, _)| date.clone()).collect();
+        let size = format!("{}x{}", tickers, months);
+        println!(
+            "trend_analysis/{}: peak heap load_all {:.1} MiB, streaming {:.1} MiB",
+            size,
+            peak_heap_mib(|| load_all(&snapshots, &dates, &rates)),
+            peak_heap_mib(|| stream(&snapshots, &dates, &rates)),
+        );
+        group.bench_with_input(BenchmarkId::new("load_all", &size), &snapshots, |b, s| {
+            b.iter(|| load_all(s, &dates, &rates))
+        });
+        group.bench_with_input(BenchmarkId::new("streaming", &size), &snapshots, |b, s| {
+            b.iter(|| stream(s, &dates, &rates))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_trends);
+criterion_main!(benches);
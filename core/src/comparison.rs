@@ -0,0 +1,71 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Change columns of a comparison between two snapshots
+
+use std::collections::HashMap;
+
+/// Absolute and percentage change between two market caps, when both are
+/// known. A change from zero counts as 0%.
+pub fn change(from: Option<f64>, to: Option<f64>) -> (Option<f64>, Option<f64>) {
+    match (from, to) {
+        (Some(from), Some(to)) => {
+            let absolute = to - from;
+            let percentage = if from != 0.0 {
+                (absolute / from) * 100.0
+            } else {
+                0.0
+            };
+            (Some(absolute), Some(percentage))
+        }
+        _ => (None, None),
+    }
+}
+
+/// Places gained (positive) or lost between two ranks
+pub fn rank_change(from: Option<usize>, to: Option<usize>) -> Option<i32> {
+    Some(from? as i32 - to? as i32)
+}
+
+/// Share of the total market cap per ticker, in percent. Companies without
+/// a market cap have no share; no shares at all when the total is zero.
+pub fn market_shares<'a>(
+    market_caps: impl IntoIterator<Item = (&'a str, Option<f64>)>,
+) -> HashMap<String, f64> {
+    let market_caps: Vec<(&str, f64)> = market_caps
+        .into_iter()
+        .filter_map(|(ticker, market_cap)| Some((ticker, market_cap?)))
+        .collect();
+    let total: f64 = market_caps.iter().map(|(_, market_cap)| market_cap).sum();
+    if total <= 0.0 {
+        return HashMap::new();
+    }
+    market_caps
+        .into_iter()
+        .map(|(ticker, market_cap)| (ticker.to_string(), market_cap / total * 100.0))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_change_and_rank_change() {
+        assert_eq!(change(Some(100.0), Some(110.0)), (Some(10.0), Some(10.0)));
+        assert_eq!(change(Some(0.0), Some(5.0)), (Some(5.0), Some(0.0)));
+        assert_eq!(change(None, Some(5.0)), (None, None));
+        assert_eq!(rank_change(Some(5), Some(2)), Some(3));
+        assert_eq!(rank_change(Some(1), None), None);
+    }
+
+    #[test]
+    fn test_market_shares() {
+        let shares = market_shares([("A", Some(75.0)), ("B", Some(25.0)), ("C", None)]);
+        assert_eq!(shares.len(), 2);
+        assert_eq!(shares["A"], 75.0);
+        assert_eq!(shares["B"], 25.0);
+        assert!(market_shares([("A", Some(0.0))]).is_empty());
+    }
+}
@@ -0,0 +1,222 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Currency conversion with a complete rate map
+//!
+//! Rate maps are keyed `FROM/TO`. [`rate_map`] adds the reverse of every
+//! quote and the cross rates from [`RateGraph`], so [`convert`] normally
+//! finds a direct rate; the reverse and one-step cross lookups remain for
+//! maps built elsewhere.
+
+use std::collections::HashMap;
+
+use crate::rate_graph::RateGraph;
+
+/// Built-in currency subunits as (code, parent, divisor)
+pub const BUILT_IN_SUBUNITS: &[(&str, &str, f64)] = &[
+    ("GBp", "GBP", 100.0),
+    ("ZAc", "ZAR", 100.0),
+    ("ILA", "ILS", 1.0),
+];
+
+/// `resolve` for [`convert`] with only the built-in subunits
+pub fn resolve_built_in(code: &str) -> (&str, f64) {
+    BUILT_IN_SUBUNITS
+        .iter()
+        .find(|(subunit, _, _)| *subunit == code)
+        .map_or((code, 1.0), |&(_, parent, divisor)| (parent, divisor))
+}
+
+/// Complete rate map from `FROM/TO` quotes: each quote in both directions
+/// plus the cross rates between every connected pair of currencies
+pub fn rate_map<'a>(quotes: impl IntoIterator<Item = (&'a str, f64)>) -> HashMap<String, f64> {
+    let mut rates = HashMap::new();
+    for (symbol, rate) in quotes {
+        // Skip symbols that don't have the expected format (e.g., "EUR/USD")
+        if let Some((from, to)) = symbol.split_once('/') {
+            rates.insert(format!("{}/{}", from, to), rate);
+            rates.insert(format!("{}/{}", to, from), 1.0 / rate);
+        }
+    }
+    RateGraph::from_rates(&rates).complete()
+}
+
+/// Result of a currency conversion including the rate used
+#[derive(Debug, Clone, Default)]
+pub struct ConversionResult {
+    /// The converted amount
+    pub amount: f64,
+    /// The effective rate used for conversion (from_currency -> to_currency)
+    pub rate: f64,
+    /// How the rate was determined: "direct", "reverse", "cross", "same", or "not_found"
+    pub rate_source: &'static str,
+    /// Warnings generated during conversion (e.g., rate validation issues)
+    pub warnings: Vec<String>,
+}
+
+impl ConversionResult {
+    /// Create a new ConversionResult with no warnings
+    pub fn new(amount: f64, rate: f64, rate_source: &'static str) -> Self {
+        Self {
+            amount,
+            rate,
+            rate_source,
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Add a warning to this result
+    pub fn with_warning(mut self, warning: String) -> Self {
+        self.warnings.push(warning);
+        self
+    }
+
+    /// Check if this result has any warnings
+    pub fn has_warnings(&self) -> bool {
+        !self.warnings.is_empty()
+    }
+}
+
+/// Validate an exchange rate for reasonableness
+/// Returns None if valid, Some(warning_message) if suspicious
+pub fn validate_rate(rate: f64, from_currency: &str, to_currency: &str) -> Option<String> {
+    // Check for invalid rates
+    if rate <= 0.0 {
+        return Some(format!(
+            "Invalid rate {:.6} for {}/{}: rate must be positive",
+            rate, from_currency, to_currency
+        ));
+    }
+
+    if rate.is_nan() || rate.is_infinite() {
+        return Some(format!(
+            "Invalid rate for {}/{}: rate is NaN or infinite",
+            from_currency, to_currency
+        ));
+    }
+
+    // Check for suspiciously extreme rates (more than 10,000:1 or less than 1:10,000)
+    // This catches potential data errors while allowing legitimate high-ratio pairs like JPY
+    if rate > 10_000.0 {
+        return Some(format!(
+            "Suspicious rate {:.6} for {}/{}: unusually high (>10,000)",
+            rate, from_currency, to_currency
+        ));
+    }
+
+    if rate < 0.0001 {
+        return Some(format!(
+            "Suspicious rate {:.6} for {}/{}: unusually low (<0.0001)",
+            rate, from_currency, to_currency
+        ));
+    }
+
+    None
+}
+
+/// Convert an amount from one currency to another, or `None` when no
+/// direct, reverse or cross rate exists. `resolve` maps a currency subunit
+/// to the currency it is quoted in and the number of subunits per unit, e.g.
+/// [`resolve_built_in`].
+pub fn convert(
+    amount: f64,
+    from_currency: &str,
+    to_currency: &str,
+    rate_map: &HashMap<String, f64>,
+    resolve: impl Fn(&str) -> (&str, f64),
+) -> Option<ConversionResult> {
+    if from_currency == to_currency {
+        return Some(ConversionResult::new(amount, 1.0, "same"));
+    }
+
+    // Currency subunits (e.g. pence) convert through their parent currency
+    let (adjusted_from_currency, subunit_divisor) = resolve(from_currency);
+    let adjusted_amount = amount / subunit_divisor;
+
+    // Also handle a subunit as target currency
+    let (adjusted_to_currency, target_multiplier) = resolve(to_currency);
+
+    // Try direct conversion first
+    let direct_rate = format!("{}/{}", adjusted_from_currency, adjusted_to_currency);
+    if let Some(&rate) = rate_map.get(&direct_rate) {
+        let result = adjusted_amount * rate * target_multiplier;
+        // Effective rate accounts for subunit conversions
+        let effective_rate = rate * target_multiplier / subunit_divisor;
+        let mut conversion = ConversionResult::new(result, effective_rate, "direct");
+        if let Some(warning) = validate_rate(rate, adjusted_from_currency, adjusted_to_currency) {
+            conversion = conversion.with_warning(warning);
+        }
+        return Some(conversion);
+    }
+
+    // Try reverse rate
+    let reverse_rate = format!("{}/{}", adjusted_to_currency, adjusted_from_currency);
+    if let Some(&rate) = rate_map.get(&reverse_rate) {
+        let inverse_rate = 1.0 / rate;
+        let result = adjusted_amount * inverse_rate * target_multiplier;
+        let effective_rate = inverse_rate * target_multiplier / subunit_divisor;
+        let mut conversion = ConversionResult::new(result, effective_rate, "reverse");
+        if let Some(warning) = validate_rate(rate, adjusted_to_currency, adjusted_from_currency) {
+            conversion = conversion.with_warning(warning);
+        }
+        return Some(conversion);
+    }
+
+    // Try conversion through an intermediate currency, the alphabetically
+    // first one when there are several so every run uses the same route
+    let cross = rate_map
+        .iter()
+        .filter_map(|(pair, &rate1)| {
+            let (from1, to1) = pair.split_once('/')?;
+            if from1 != adjusted_from_currency {
+                return None;
+            }
+            let rate2 = *rate_map.get(&format!("{}/{}", to1, adjusted_to_currency))?;
+            Some((to1, rate1, rate2))
+        })
+        .min_by(|a, b| a.0.cmp(b.0));
+    if let Some((via, rate1, rate2)) = cross {
+        let combined_rate = rate1 * rate2;
+        let result = adjusted_amount * combined_rate * target_multiplier;
+        let effective_rate = combined_rate * target_multiplier / subunit_divisor;
+        let mut conversion = ConversionResult::new(result, effective_rate, "cross");
+        // Validate both legs of the cross rate
+        if let Some(warning) = validate_rate(rate1, adjusted_from_currency, via) {
+            conversion = conversion.with_warning(warning);
+        }
+        if let Some(warning) = validate_rate(rate2, via, adjusted_to_currency) {
+            conversion = conversion.with_warning(warning);
+        }
+        return Some(conversion);
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_map_and_convert() {
+        let rates = rate_map([("EUR/USD", 1.25), ("USD/GBP", 0.8), ("bad", 2.0)]);
+        assert_eq!(rates.len(), 6);
+        let eur = convert(100.0, "EUR", "GBP", &rates, resolve_built_in).unwrap();
+        assert!((eur.amount - 100.0).abs() < 1e-9);
+        assert_eq!(eur.rate_source, "direct");
+
+        // 500 pence are 5 GBP, 6.25 USD
+        let pence = convert(500.0, "GBp", "USD", &rates, resolve_built_in).unwrap();
+        assert!((pence.amount - 6.25).abs() < 1e-9);
+        assert!((pence.rate - 0.0125).abs() < 1e-12);
+
+        assert_eq!(
+            convert(1.0, "JPY", "JPY", &rates, resolve_built_in)
+                .unwrap()
+                .amount,
+            1.0
+        );
+        assert!(convert(1.0, "JPY", "USD", &rates, resolve_built_in).is_none());
+    }
+}
@@ -0,0 +1,18 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Conversion and comparison math of `top200-rs`
+//!
+//! The pure computations behind the reports: building a complete rate map,
+//! currency conversion, the change columns of a comparison and the trend
+//! statistics. Nothing here touches the database, the network or the
+//! clock, so the crate also compiles to WASM (`--features wasm`) for the
+//! browser calculator, which then gives the same numbers as the CLI.
+
+pub mod comparison;
+pub mod conversion;
+pub mod rate_graph;
+pub mod trend;
+#[cfg(feature = "wasm")]
+pub mod wasm;
@@ -0,0 +1,253 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Graph-based cross rates for the rate map
+//!
+//! Currencies are nodes and every rate in the map is an edge. A missing pair
+//! is resolved by a breadth-first search, so the route with the fewest
+//! conversions wins. Among equally short routes the USD and EUR pivots come
+//! first, then the other currencies alphabetically, so every run picks the
+//! same route. Completing the map takes one search per currency,
+//! O(V·(V+E)), instead of comparing every pair with every other pair.
+
+use std::collections::{BTreeSet, HashMap, VecDeque};
+
+/// Intermediate currencies tried before any other, in this order
+pub const PIVOTS: [&str; 2] = ["USD", "EUR"];
+
+/// Exchange rates as a graph of currencies
+#[derive(Debug, Default, Clone)]
+pub struct RateGraph {
+    /// Currency codes, sorted; nodes are indices into this
+    currencies: Vec<String>,
+    /// Outgoing rates per node, pivots first and then by code
+    edges: Vec<Vec<(usize, f64)>>,
+}
+
+/// Order in which neighbours are visited: pivots, then alphabetical
+fn visit_order(code: &str) -> (usize, &str) {
+    let rank = PIVOTS
+        .iter()
+        .position(|p| *p == code)
+        .unwrap_or(PIVOTS.len());
+    (rank, code)
+}
+
+impl RateGraph {
+    /// Graph of the `FROM/TO` rates in `rates`
+    pub fn from_rates(rates: &HashMap<String, f64>) -> Self {
+        let pairs: Vec<(&str, &str, f64)> = rates
+            .iter()
+            .filter_map(|(pair, &rate)| {
+                let (from, to) = pair.split_once('/')?;
+                (from != to).then_some((from, to, rate))
+            })
+            .collect();
+        let currencies: Vec<String> = pairs
+            .iter()
+            .flat_map(|&(from, to, _)| [from, to])
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        let index: HashMap<&str, usize> = currencies
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (c.as_str(), i))
+            .collect();
+        let mut edges = vec![Vec::new(); currencies.len()];
+        for (from, to, rate) in pairs {
+            edges[index[from]].push((index[to], rate));
+        }
+        for neighbours in &mut edges {
+            neighbours.sort_by(|a: &(usize, f64), b| {
+                visit_order(&currencies[a.0]).cmp(&visit_order(&currencies[b.0]))
+            });
+        }
+        Self { currencies, edges }
+    }
+
+    /// Rates from node `from` to every node reachable from it
+    fn rates_from_node(&self, from: usize) -> Vec<(usize, f64)> {
+        let mut rates = Vec::new();
+        let mut seen = vec![false; self.currencies.len()];
+        seen[from] = true;
+        let mut queue = VecDeque::from([(from, 1.0)]);
+        while let Some((node, rate)) = queue.pop_front() {
+            for &(next, edge) in &self.edges[node] {
+                if !seen[next] {
+                    seen[next] = true;
+                    rates.push((next, rate * edge));
+                    queue.push_back((next, rate * edge));
+                }
+            }
+        }
+        rates
+    }
+
+    /// Rates from `from` to every currency reachable from it
+    pub fn rates_from(&self, from: &str) -> Vec<(&str, f64)> {
+        let Ok(node) = self.currencies.binary_search_by(|c| c.as_str().cmp(from)) else {
+            return Vec::new();
+        };
+        self.rates_from_node(node)
+            .into_iter()
+            .map(|(to, rate)| (self.currencies[to].as_str(), rate))
+            .collect()
+    }
+
+    /// Rate from `from` to `to` over the shortest route, if connected
+    #[allow(dead_code)]
+    pub fn rate(&self, from: &str, to: &str) -> Option<f64> {
+        if from == to {
+            return Some(1.0);
+        }
+        self.rates_from(from)
+            .into_iter()
+            .find(|(currency, _)| *currency == to)
+            .map(|(_, rate)| rate)
+    }
+
+    /// Rate map with a rate for every connected pair of currencies
+    pub fn complete(&self) -> HashMap<String, f64> {
+        let n = self.currencies.len();
+        let mut rates = HashMap::with_capacity(n * n.saturating_sub(1));
+        for (from, code) in self.currencies.iter().enumerate() {
+            for (to, rate) in self.rates_from_node(from) {
+                let to = &self.currencies[to];
+                let mut pair = String::with_capacity(code.len() + to.len() + 1);
+                pair.push_str(code);
+                pair.push('/');
+                pair.push_str(to);
+                rates.insert(pair, rate);
+            }
+        }
+        rates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn rates(pairs: &[(&str, f64)]) -> HashMap<String, f64> {
+        let mut map = HashMap::new();
+        for (pair, rate) in pairs {
+            let (from, to) = pair.split_once('/').unwrap();
+            map.insert(pair.to_string(), *rate);
+            map.insert(format!("{}/{}", to, from), 1.0 / rate);
+        }
+        map
+    }
+
+    #[test]
+    fn test_direct_rates_are_kept() {
+        let graph = RateGraph::from_rates(&rates(&[("EUR/USD", 1.1)]));
+        let complete = graph.complete();
+        assert_eq!(complete.len(), 2);
+        assert_eq!(complete["EUR/USD"], 1.1);
+        assert_eq!(complete["USD/EUR"], 1.0 / 1.1);
+    }
+
+    #[test]
+    fn test_routes_prefer_pivots_then_alphabetical() {
+        // Rates that disagree: via CHF gives 161.5, via USD 165
+        let graph = RateGraph::from_rates(&rates(&[
+            ("EUR/USD", 1.1),
+            ("USD/JPY", 150.0),
+            ("EUR/CHF", 0.95),
+            ("CHF/JPY", 170.0),
+        ]));
+        assert_eq!(graph.rate("EUR", "JPY"), Some(1.1 * 150.0));
+
+        // Without a pivot on the route, the alphabetically first currency
+        let graph = RateGraph::from_rates(&rates(&[
+            ("SEK/NOK", 1.0),
+            ("NOK/JPY", 14.0),
+            ("SEK/DKK", 0.65),
+            ("DKK/JPY", 20.0),
+        ]));
+        assert_eq!(graph.rate("SEK", "JPY"), Some(0.65 * 20.0));
+    }
+
+    #[test]
+    fn test_multi_hop_and_disconnected() {
+        let graph = RateGraph::from_rates(&rates(&[
+            ("GBP/EUR", 1.2),
+            ("EUR/USD", 1.1),
+            ("USD/JPY", 150.0),
+            ("XAU/XAG", 80.0),
+        ]));
+        let gbp_jpy = graph.rate("GBP", "JPY").unwrap();
+        assert!((gbp_jpy - 1.2 * 1.1 * 150.0).abs() < 1e-9);
+        assert_eq!(graph.rate("GBP", "XAU"), None);
+        assert_eq!(graph.rate("GBP", "GBP"), Some(1.0));
+        let complete = graph.complete();
+        // 4 connected currencies and 2 others: 4·3 + 2·1 pairs
+        assert_eq!(complete.len(), 14);
+        assert!((complete["JPY/GBP"] * gbp_jpy - 1.0).abs() < 1e-9);
+    }
+
+    /// The cross-rate loop this module replaced: every pair against every
+    /// other pair, on a cloned map
+    fn pairwise_cross_rates(mut rate_map: HashMap<String, f64>) -> HashMap<String, f64> {
+        let mut pairs: Vec<_> = rate_map.clone().into_iter().collect();
+        pairs.sort_by(|a, b| a.0.cmp(&b.0));
+        for (pair1, rate1) in &pairs {
+            if let Some((from1, to1)) = pair1.split_once('/') {
+                for (pair2, rate2) in &pairs {
+                    if let Some((from2, to2)) = pair2.split_once('/')
+                        && to1 == from2
+                        && from1 != to2
+                    {
+                        let cross_pair = format!("{}/{}", from1, to2);
+                        if !rate_map.contains_key(&cross_pair) {
+                            rate_map.insert(cross_pair.clone(), rate1 * rate2);
+                            rate_map.insert(format!("{}/{}", to2, from1), 1.0 / (rate1 * rate2));
+                        }
+                    }
+                }
+            }
+        }
+        rate_map
+    }
+
+    /// Timing of both approaches on 120 currencies, each quoted against
+    /// the next 15. Run with `cargo test --release -- --ignored --nocapture bench_`
+    #[test]
+    #[ignore]
+    fn bench_cross_rates_large_rate_set() {
+        let codes: Vec<String> = (0..120).map(|i| format!("C{:03}", i)).collect();
+        let mut quotes = Vec::new();
+        for (i, code) in codes.iter().enumerate() {
+            for j in 1..=15 {
+                let other = &codes[(i + j) % codes.len()];
+                quotes.push((format!("{}/{}", code, other), 1.0 + j as f64 / 100.0));
+            }
+        }
+        let quotes: Vec<(&str, f64)> = quotes.iter().map(|(p, r)| (p.as_str(), *r)).collect();
+        let direct = rates(&quotes);
+
+        let started = Instant::now();
+        let pairwise = pairwise_cross_rates(direct.clone());
+        let pairwise_time = started.elapsed();
+        let started = Instant::now();
+        let graph = RateGraph::from_rates(&direct).complete();
+        let graph_time = started.elapsed();
+
+        println!(
+            "{} direct rates: pairwise {:?} ({} pairs), graph {:?} ({} pairs), {:.1}x faster",
+            direct.len(),
+            pairwise_time,
+            pairwise.len(),
+            graph_time,
+            graph.len(),
+            pairwise_time.as_secs_f64() / graph_time.as_secs_f64()
+        );
+        // Every pair the old loop found is found too
+        assert!(pairwise.keys().all(|pair| graph.contains_key(pair)));
+        assert!(graph_time < pairwise_time);
+    }
+}
@@ -0,0 +1,162 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Statistics of one company's market caps over a series of dates
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// Trend statistics of a series of market caps, in date order. Percentages
+/// are in percent; every statistic is `None` when there are too few values.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TrendStats {
+    pub overall_change_pct: Option<f64>,
+    pub overall_change_abs: Option<f64>,
+    /// Compound annual growth rate
+    pub cagr: Option<f64>,
+    /// Standard deviation of the period-over-period returns
+    pub volatility: Option<f64>,
+    /// Mean period-over-period return per unit of volatility (a Sharpe ratio
+    /// without a risk-free rate)
+    pub return_volatility_ratio: Option<f64>,
+    /// Root mean square of the negative period-over-period returns
+    pub downside_deviation: Option<f64>,
+    /// Largest fall from an earlier peak
+    pub max_drawdown: Option<f64>,
+}
+
+impl TrendStats {
+    /// Statistics of `values` spanning `years` from the first to the last
+    pub fn from_values(values: &[f64], years: f64) -> Self {
+        Self {
+            overall_change_pct: overall_change_pct(values),
+            overall_change_abs: overall_change_abs(values),
+            cagr: cagr(values, years),
+            volatility: volatility(values),
+            return_volatility_ratio: return_volatility_ratio(values),
+            downside_deviation: downside_deviation(values),
+            max_drawdown: max_drawdown(values),
+        }
+    }
+}
+
+/// First and last value, when there are at least two
+fn endpoints(values: &[f64]) -> Option<(f64, f64)> {
+    match values {
+        [first, .., last] => Some((*first, *last)),
+        _ => None,
+    }
+}
+
+/// Change from the first to the last value in percent
+pub fn overall_change_pct(values: &[f64]) -> Option<f64> {
+    let (first, last) = endpoints(values)?;
+    (first > 0.0).then(|| (last - first) / first * 100.0)
+}
+
+/// Change from the first to the last value
+pub fn overall_change_abs(values: &[f64]) -> Option<f64> {
+    let (first, last) = endpoints(values)?;
+    Some(last - first)
+}
+
+/// Compound annual growth rate in percent over `years`
+pub fn cagr(values: &[f64], years: f64) -> Option<f64> {
+    let (first, last) = endpoints(values)?;
+    (first > 0.0 && years > 0.0).then(|| ((last / first).powf(1.0 / years) - 1.0) * 100.0)
+}
+
+/// Returns between consecutive values in percent; `None` with fewer than
+/// three values, which is too few for a spread
+fn period_returns(values: &[f64]) -> Option<Vec<f64>> {
+    if values.len() < 3 {
+        return None;
+    }
+    Some(
+        values
+            .windows(2)
+            .map(|w| (w[1] - w[0]) / w[0] * 100.0)
+            .collect(),
+    )
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Standard deviation of the returns between consecutive values, in
+/// percentage points; needs at least three values
+pub fn volatility(values: &[f64]) -> Option<f64> {
+    let returns = period_returns(values)?;
+    let mean = mean(&returns);
+    let variance = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / returns.len() as f64;
+    Some(variance.sqrt())
+}
+
+/// Mean return between consecutive values divided by their volatility;
+/// `None` when the returns don't vary
+pub fn return_volatility_ratio(values: &[f64]) -> Option<f64> {
+    let volatility = volatility(values).filter(|v| *v > 1e-9)?;
+    Some(mean(&period_returns(values)?) / volatility)
+}
+
+/// Downside deviation of the returns between consecutive values: like the
+/// volatility, but only falls count (against a target of 0%)
+pub fn downside_deviation(values: &[f64]) -> Option<f64> {
+    let returns = period_returns(values)?;
+    let squares = returns.iter().map(|r| r.min(0.0).powi(2)).sum::<f64>();
+    Some((squares / returns.len() as f64).sqrt())
+}
+
+/// Largest fall from a running peak in percent
+pub fn max_drawdown(values: &[f64]) -> Option<f64> {
+    endpoints(values)?;
+    let mut peak = values[0];
+    let mut max_drawdown = 0.0f64;
+    for &value in values {
+        peak = peak.max(value);
+        max_drawdown = max_drawdown.max((peak - value) / peak * 100.0);
+    }
+    Some(max_drawdown)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trend_stats() {
+        let stats = TrendStats::from_values(&[100.0, 120.0, 90.0, 121.0], 2.0);
+        assert_eq!(stats.overall_change_pct, Some(21.0));
+        assert_eq!(stats.overall_change_abs, Some(21.0));
+        assert!((stats.cagr.unwrap() - 10.0).abs() < 1e-9);
+        assert_eq!(stats.max_drawdown, Some(25.0));
+        assert!(stats.volatility.unwrap() > 0.0);
+
+        assert_eq!(
+            TrendStats::from_values(&[100.0], 1.0),
+            TrendStats::default()
+        );
+        assert_eq!(volatility(&[100.0, 110.0]), None);
+        assert_eq!(return_volatility_ratio(&[100.0, 110.0]), None);
+        assert_eq!(downside_deviation(&[100.0, 110.0]), None);
+        assert_eq!(cagr(&[100.0, 110.0], 0.0), None);
+        assert_eq!(overall_change_pct(&[0.0, 10.0]), None);
+    }
+
+    #[test]
+    fn test_risk_adjusted_stats() {
+        // Returns +10%, -10%, +10%: mean 3.33, volatility 9.43
+        let values = [100.0, 110.0, 99.0, 108.9];
+        let ratio = return_volatility_ratio(&values).unwrap();
+        assert!((ratio - (10.0 / 3.0) / (800.0f64 / 9.0).sqrt()).abs() < 1e-9);
+        // Only the -10% counts: sqrt(100 / 3)
+        assert!((downside_deviation(&values).unwrap() - (100.0f64 / 3.0).sqrt()).abs() < 1e-9);
+
+        // Steady growth has no volatility to divide by and no downside
+        assert_eq!(return_volatility_ratio(&[100.0, 110.0, 121.0]), None);
+        assert_eq!(downside_deviation(&[100.0, 110.0, 121.0]), Some(0.0));
+    }
+}
@@ -0,0 +1,68 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! JS API of the browser calculator (`--features wasm`)
+//!
+//! ```js
+//! import init, { Rates, percentageChange, trendStats } from "./pkg/top200_core.js";
+//! await init();
+//! const rates = new Rates('{"EUR/USD": 1.08, "GBP/USD": 1.27}');
+//! rates.convert(1e9, "GBp", "EUR"); // pence are converted through GBP
+//! percentageChange(120, 150);       // 25
+//! trendStats(new Float64Array([100, 120, 90]), 2).max_drawdown; // 25
+//! ```
+
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+use crate::comparison;
+use crate::conversion::{self, resolve_built_in};
+use crate::trend::TrendStats;
+
+/// A complete rate map, built once from direct quotes
+#[wasm_bindgen]
+pub struct Rates {
+    map: HashMap<String, f64>,
+}
+
+#[wasm_bindgen]
+impl Rates {
+    /// Rates from a JSON object of quotes like `{"EUR/USD": 1.08}`; the
+    /// reverse and cross rates are derived as in the CLI
+    #[wasm_bindgen(constructor)]
+    pub fn new(quotes_json: &str) -> Result<Rates, JsError> {
+        let quotes: HashMap<String, f64> = serde_json::from_str(quotes_json)?;
+        Ok(Rates {
+            map: conversion::rate_map(quotes.iter().map(|(symbol, rate)| (symbol.as_str(), *rate))),
+        })
+    }
+
+    /// `amount` converted, or `undefined` without a rate
+    pub fn convert(&self, amount: f64, from: &str, to: &str) -> Option<f64> {
+        conversion::convert(amount, from, to, &self.map, resolve_built_in).map(|c| c.amount)
+    }
+
+    /// Effective rate from one currency (or subunit) to another
+    pub fn rate(&self, from: &str, to: &str) -> Option<f64> {
+        conversion::convert(1.0, from, to, &self.map, resolve_built_in).map(|c| c.rate)
+    }
+}
+
+/// Change from one market cap to another in percent, as in comparisons
+#[wasm_bindgen(js_name = percentageChange)]
+pub fn percentage_change(from: f64, to: f64) -> Option<f64> {
+    comparison::change(Some(from), Some(to)).1
+}
+
+/// Places gained (positive) or lost between two ranks
+#[wasm_bindgen(js_name = rankChange)]
+pub fn rank_change(from: usize, to: usize) -> Option<i32> {
+    comparison::rank_change(Some(from), Some(to))
+}
+
+/// Trend statistics of market caps in date order spanning `years`
+#[wasm_bindgen(js_name = trendStats)]
+pub fn trend_stats(values: Vec<f64>, years: f64) -> TrendStats {
+    TrendStats::from_values(&values, years)
+}
@@ -0,0 +1,49 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Compare two stored snapshots and list the trend of every company
+//!
+//! cargo run --example compare -- 2025-01-01 2025-02-01
+
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use top200_rs::{client, db};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    dotenvy::dotenv().ok();
+    let dates: Vec<NaiveDate> = std::env::args()
+        .skip(1)
+        .map(|arg| NaiveDate::parse_from_str(&arg, "%Y-%m-%d").context("Dates are YYYY-MM-DD"))
+        .collect::<Result<_>>()?;
+    let (Some(from), Some(to)) = (dates.first(), dates.last()) else {
+        anyhow::bail!("Usage: compare <from-date> <to-date> [more dates...]");
+    };
+
+    let database_url =
+        std::env::var("DATABASE_URL").unwrap_or_else(|_| "sqlite:data.db".to_string());
+    let pool = db::create_db_pool(&database_url).await?;
+
+    let comparison = client::compare_snapshots(&pool, *from, *to).await?;
+    for row in comparison.rows.iter().take(10) {
+        println!(
+            "{:<10} {:>8.2}%",
+            row.ticker,
+            row.percentage_change.unwrap_or_default()
+        );
+    }
+    println!(
+        "Total: {:.0} -> {:.0} USD",
+        comparison.attribution.total_from, comparison.attribution.total_to
+    );
+
+    let (trends, summary) = client::trends(&pool, &dates).await?;
+    println!(
+        "{} companies over {} periods, total change {:.2}%",
+        trends.len(),
+        summary.num_periods,
+        summary.total_change_pct
+    );
+    Ok(())
+}
@@ -0,0 +1,45 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Fetch the market caps of a few tickers on a date without storing them
+//!
+//! cargo run --example snapshot -- 2025-01-02 NKE ITX.MC MC.PA
+
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use top200_rs::{api, client, db};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    dotenvy::dotenv().ok();
+    let mut args = std::env::args().skip(1);
+    let date = args.next().context("Usage: snapshot <date> <ticker>...")?;
+    let date = NaiveDate::parse_from_str(&date, "%Y-%m-%d")?;
+    let tickers: Vec<String> = args.collect();
+
+    let api_key = std::env::var("FINANCIALMODELINGPREP_API_KEY")
+        .context("FINANCIALMODELINGPREP_API_KEY must be set")?;
+    let fmp_client = api::FMPClient::new(api_key);
+    let database_url =
+        std::env::var("DATABASE_URL").unwrap_or_else(|_| "sqlite:data.db".to_string());
+    let pool = db::create_db_pool(&database_url).await?;
+
+    let snapshot = client::fetch_snapshot(&fmp_client, &pool, date, &tickers, 4).await?;
+    for row in &snapshot.rows {
+        println!(
+            "{:<10} {:>20.0} {} = {:>20.0} USD",
+            row.ticker,
+            row.market_cap_original,
+            row.original_currency,
+            row.market_cap_usd.unwrap_or(f64::NAN)
+        );
+    }
+    for (ticker, error) in &snapshot.failed {
+        println!("{:<10} failed: {}", ticker, error);
+    }
+    for gap in &snapshot.rate_gaps {
+        println!("Rate {} not recorded on {}", gap.symbol, date);
+    }
+    Ok(())
+}
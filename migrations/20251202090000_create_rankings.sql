@@ -0,0 +1,17 @@
+-- SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+
+-- Rank of each company per market cap snapshot (ordered by EUR market cap)
+CREATE TABLE IF NOT EXISTS rankings (
+    ticker TEXT NOT NULL,
+    date TEXT NOT NULL,
+    timestamp INTEGER NOT NULL,
+    rank INTEGER NOT NULL,
+    market_cap_eur REAL,
+    market_cap_usd REAL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (ticker, timestamp)
+);
+
+CREATE INDEX IF NOT EXISTS idx_rankings_ticker ON rankings(ticker);
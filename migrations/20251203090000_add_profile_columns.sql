@@ -0,0 +1,14 @@
+-- SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+
+-- Cache company profile fields used by the `show` command
+ALTER TABLE ticker_details ADD COLUMN name TEXT;
+ALTER TABLE ticker_details ADD COLUMN exchange TEXT;
+ALTER TABLE ticker_details ADD COLUMN currency TEXT;
+ALTER TABLE ticker_details ADD COLUMN pe_ratio REAL;
+ALTER TABLE ticker_details ADD COLUMN eps REAL;
+ALTER TABLE ticker_details ADD COLUMN quick_ratio REAL;
+ALTER TABLE ticker_details ADD COLUMN working_capital_ratio REAL;
+ALTER TABLE ticker_details ADD COLUMN debt_equity_ratio REAL;
+ALTER TABLE ticker_details ADD COLUMN roe REAL;
@@ -0,0 +1,16 @@
+-- SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+
+-- Named ticker lists tracked outside the config.toml universe
+CREATE TABLE IF NOT EXISTS watchlists (
+    name TEXT PRIMARY KEY,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS watchlist_tickers (
+    watchlist TEXT NOT NULL REFERENCES watchlists(name) ON DELETE CASCADE,
+    ticker TEXT NOT NULL,
+    added_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (watchlist, ticker)
+);
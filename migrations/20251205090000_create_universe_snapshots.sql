@@ -0,0 +1,13 @@
+-- SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+
+-- The config.toml ticker universe that was active for each fetched date
+CREATE TABLE IF NOT EXISTS universe_snapshots (
+    date TEXT NOT NULL,
+    ticker TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (date, ticker)
+);
+
+CREATE INDEX IF NOT EXISTS idx_universe_snapshots_ticker ON universe_snapshots(ticker);
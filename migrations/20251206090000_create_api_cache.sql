@@ -0,0 +1,12 @@
+-- SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+
+-- Raw API responses keyed by request URL (without API key) and UTC day
+CREATE TABLE IF NOT EXISTS api_cache (
+    url TEXT NOT NULL,
+    day TEXT NOT NULL,
+    body TEXT NOT NULL,
+    fetched_at INTEGER NOT NULL,
+    PRIMARY KEY (url, day)
+);
@@ -0,0 +1,13 @@
+-- SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+
+-- API requests per UTC day and endpoint, accumulated over all runs
+CREATE TABLE IF NOT EXISTS api_usage (
+    date TEXT NOT NULL,
+    endpoint TEXT NOT NULL,
+    count INTEGER NOT NULL DEFAULT 0,
+    retries INTEGER NOT NULL DEFAULT 0,
+    rate_limit_hits INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (date, endpoint)
+);
@@ -0,0 +1,23 @@
+-- SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+
+-- Open/high/low/close EUR market cap and average rank per company and week or month
+CREATE TABLE IF NOT EXISTS marketcap_aggregates (
+    ticker TEXT NOT NULL,
+    granularity TEXT NOT NULL,
+    period TEXT NOT NULL,
+    period_start TEXT NOT NULL,
+    period_end TEXT NOT NULL,
+    name TEXT,
+    open_eur REAL,
+    high_eur REAL,
+    low_eur REAL,
+    close_eur REAL,
+    avg_rank REAL NOT NULL,
+    snapshots INTEGER NOT NULL,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (ticker, granularity, period)
+);
+
+CREATE INDEX IF NOT EXISTS idx_marketcap_aggregates_period ON marketcap_aggregates(granularity, period);
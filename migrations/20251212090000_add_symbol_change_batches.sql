@@ -0,0 +1,11 @@
+-- SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+
+-- The config.toml backup written by the apply-symbol-changes run that applied
+-- a change; changes applied together share it, so the batch can be undone
+ALTER TABLE symbol_changes ADD COLUMN applied_batch TEXT;
+
+-- Order in which batches were applied. The backup path in applied_batch
+-- can't order them: its timestamp could come from --as-of.
+ALTER TABLE symbol_changes ADD COLUMN applied_seq INTEGER;
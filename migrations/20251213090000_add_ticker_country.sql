@@ -0,0 +1,6 @@
+-- SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+
+-- Headquarters country from the FMP profile, for `geo-report`
+ALTER TABLE ticker_details ADD COLUMN country TEXT;
@@ -0,0 +1,6 @@
+-- SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+
+-- Industry from the FMP profile, for the medians of `efficiency-report`
+ALTER TABLE ticker_details ADD COLUMN industry TEXT;
@@ -0,0 +1,25 @@
+-- SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+
+-- FMP price target and rating consensus per ticker and fetch date, with the
+-- share price at that time (fetched by `export-combined --with-analyst`)
+CREATE TABLE IF NOT EXISTS analyst_targets (
+    ticker TEXT NOT NULL,
+    date TEXT NOT NULL,
+    name TEXT NOT NULL,
+    currency TEXT,
+    price REAL,
+    target_consensus REAL,
+    target_median REAL,
+    target_high REAL,
+    target_low REAL,
+    strong_buy INTEGER,
+    buy INTEGER,
+    hold INTEGER,
+    sell INTEGER,
+    strong_sell INTEGER,
+    consensus TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (ticker, date)
+);
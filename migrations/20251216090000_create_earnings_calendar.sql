@@ -0,0 +1,20 @@
+-- SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+
+-- Earnings reports of the tracked tickers from the FMP earnings calendar
+-- (`earnings-calendar`), used to flag reports between compared dates
+CREATE TABLE IF NOT EXISTS earnings_calendar (
+    ticker TEXT NOT NULL,
+    date TEXT NOT NULL,
+    time TEXT,
+    eps REAL,
+    eps_estimated REAL,
+    revenue REAL,
+    revenue_estimated REAL,
+    fiscal_date_ending TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (ticker, date)
+);
+
+CREATE INDEX IF NOT EXISTS idx_earnings_calendar_date ON earnings_calendar(date);
@@ -0,0 +1,12 @@
+-- SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+
+-- Full-text index over the tickers, latest names and descriptions of the
+-- stored companies (`search`, `/api/search`); rebuilt by `search::rebuild_index`
+CREATE VIRTUAL TABLE IF NOT EXISTS company_search USING fts5(
+    ticker,
+    name,
+    description,
+    tokenize = 'porter unicode61 remove_diacritics 2'
+);
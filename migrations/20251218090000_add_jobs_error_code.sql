@@ -0,0 +1,6 @@
+-- SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+
+-- Kind of failure (error::ErrorCode, e.g. csv_not_found) next to the message
+ALTER TABLE jobs ADD COLUMN error_code TEXT;
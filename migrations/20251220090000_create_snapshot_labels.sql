@@ -0,0 +1,13 @@
+-- SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+
+-- Intraday snapshots stored by `export-combined --label`: the `market_caps`
+-- timestamp of the snapshot labeled e.g. `open` or `close` on a day
+CREATE TABLE IF NOT EXISTS snapshot_labels (
+    date TEXT NOT NULL,
+    label TEXT NOT NULL,
+    timestamp INTEGER NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    PRIMARY KEY (date, label)
+);
@@ -0,0 +1,12 @@
+-- SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+
+-- Validators of cached responses, sent back as If-None-Match / If-Modified-Since
+-- once the entry has expired
+ALTER TABLE api_cache ADD COLUMN etag TEXT;
+ALTER TABLE api_cache ADD COLUMN last_modified TEXT;
+
+-- Response payload per endpoint and requests answered with 304 Not Modified
+ALTER TABLE api_usage ADD COLUMN bytes INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE api_usage ADD COLUMN not_modified INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,12 @@
+-- SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+
+-- FMP requests per UTC day and API key (identified by its last characters)
+CREATE TABLE IF NOT EXISTS api_key_usage (
+    date TEXT NOT NULL,
+    key_id TEXT NOT NULL,
+    count INTEGER NOT NULL DEFAULT 0,
+    rate_limit_hits INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (date, key_id)
+);
@@ -0,0 +1,12 @@
+-- SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+
+-- Provider (fmp, polygon, import) and UTC fetch time of each market cap, so
+-- every figure can be traced to where and when it was retrieved. Rows stored
+-- before were inserted when fetched, so their fetch time is their creation
+-- time; their provider is unknown.
+ALTER TABLE market_caps ADD COLUMN data_source TEXT;
+ALTER TABLE market_caps ADD COLUMN fetched_at TEXT;
+UPDATE market_caps SET fetched_at = strftime('%Y-%m-%dT%H:%M:%SZ', created_at)
+WHERE created_at IS NOT NULL;
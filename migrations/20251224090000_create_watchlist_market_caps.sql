@@ -0,0 +1,25 @@
+-- SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+
+-- Market caps fetched for watchlist tickers outside the universe. Kept apart
+-- from market_caps so rankings, aggregates and the other universe readers
+-- never see them.
+CREATE TABLE IF NOT EXISTS watchlist_market_caps (
+    ticker TEXT NOT NULL,
+    name TEXT NOT NULL,
+    market_cap_original DECIMAL,
+    original_currency TEXT,
+    market_cap_eur DECIMAL,
+    market_cap_usd DECIMAL,
+    eur_rate DECIMAL,
+    usd_rate DECIMAL,
+    exchange TEXT,
+    price DECIMAL,
+    active BOOLEAN,
+    timestamp INTEGER NOT NULL,
+    data_source TEXT,
+    fetched_at TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (ticker, timestamp)
+);
@@ -0,0 +1,86 @@
+-- SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+
+-- Core tables for DATABASE_URL=postgres://..., matching the SQLite schema
+-- after all migrations in migrations/. Market caps and rates are DOUBLE
+-- PRECISION, timestamps are Unix seconds in BIGINT.
+
+CREATE TABLE IF NOT EXISTS currencies (
+    code TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS forex_rates (
+    symbol TEXT NOT NULL,
+    ask DOUBLE PRECISION NOT NULL,
+    bid DOUBLE PRECISION NOT NULL,
+    timestamp BIGINT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (symbol, timestamp)
+);
+
+CREATE TABLE IF NOT EXISTS market_caps (
+    ticker TEXT NOT NULL,
+    name TEXT NOT NULL,
+    market_cap_original DOUBLE PRECISION,
+    original_currency TEXT,
+    market_cap_eur DOUBLE PRECISION,
+    market_cap_usd DOUBLE PRECISION,
+    exchange TEXT,
+    price DOUBLE PRECISION,
+    active BOOLEAN,
+    employees BIGINT,
+    revenue DOUBLE PRECISION,
+    revenue_usd DOUBLE PRECISION,
+    working_capital_ratio DOUBLE PRECISION,
+    quick_ratio DOUBLE PRECISION,
+    eps DOUBLE PRECISION,
+    pe_ratio DOUBLE PRECISION,
+    de_ratio DOUBLE PRECISION,
+    roe DOUBLE PRECISION,
+    timestamp BIGINT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    eur_rate DOUBLE PRECISION,
+    usd_rate DOUBLE PRECISION,
+    PRIMARY KEY (ticker, timestamp)
+);
+
+-- employees is TEXT because FMP reports it as a string
+CREATE TABLE IF NOT EXISTS ticker_details (
+    ticker TEXT PRIMARY KEY,
+    description TEXT,
+    homepage_url TEXT,
+    employees TEXT,
+    ceo TEXT,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    name TEXT,
+    exchange TEXT,
+    currency TEXT,
+    pe_ratio DOUBLE PRECISION,
+    eps DOUBLE PRECISION,
+    quick_ratio DOUBLE PRECISION,
+    working_capital_ratio DOUBLE PRECISION,
+    debt_equity_ratio DOUBLE PRECISION,
+    roe DOUBLE PRECISION
+);
+
+CREATE TABLE IF NOT EXISTS symbol_changes (
+    id BIGSERIAL PRIMARY KEY,
+    old_symbol TEXT NOT NULL,
+    new_symbol TEXT NOT NULL,
+    change_date TEXT,
+    company_name TEXT,
+    reason TEXT,
+    applied BIGINT DEFAULT 0,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (old_symbol, new_symbol, change_date)
+);
+
+CREATE INDEX IF NOT EXISTS idx_symbol_changes_old_symbol ON symbol_changes(old_symbol);
+CREATE INDEX IF NOT EXISTS idx_symbol_changes_applied ON symbol_changes(applied);
@@ -0,0 +1,8 @@
+-- SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+
+-- ISIN and LEI, to join with datasets that don't use tickers (`isin-map`)
+ALTER TABLE ticker_details ADD COLUMN isin TEXT;
+ALTER TABLE ticker_details ADD COLUMN lei TEXT;
+CREATE INDEX IF NOT EXISTS idx_ticker_details_isin ON ticker_details (isin);
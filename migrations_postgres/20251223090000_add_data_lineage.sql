@@ -0,0 +1,9 @@
+-- SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+
+-- Provider (fmp, polygon, import) and UTC fetch time of each market cap
+ALTER TABLE market_caps ADD COLUMN data_source TEXT;
+ALTER TABLE market_caps ADD COLUMN fetched_at TEXT;
+UPDATE market_caps SET fetched_at = to_char(created_at, 'YYYY-MM-DD"T"HH24:MI:SS"Z"')
+WHERE created_at IS NOT NULL;
@@ -0,0 +1,11 @@
+-- SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+
+-- Business days on which FMP has no rate for a pair (market holidays), found
+-- by `verify-rates`, so later runs don't fetch them again
+CREATE TABLE IF NOT EXISTS forex_rate_holidays (
+    symbol TEXT NOT NULL,
+    date TEXT NOT NULL,
+    PRIMARY KEY (symbol, date)
+);
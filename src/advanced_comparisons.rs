@@ -13,17 +13,35 @@
 //! - Peer group comparisons
 
 use anyhow::{Context, Result};
-use chrono::{Datelike, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime};
 use csv::{Reader, Writer};
-use indicatif::{ProgressBar, ProgressStyle};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use sqlx::sqlite::SqlitePool;
-use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs::File;
 use std::io::Write as IoWrite;
-use std::path::Path;
+use top200_core::comparison;
+use top200_core::trend::TrendStats;
 
+use crate::clock;
+use crate::concentration::{self, Concentration};
+use crate::config::{self, OutputConfig};
+use crate::corporate_actions::CorporateActionIndex;
 use crate::currencies::{convert_currency, get_rate_map_from_db_for_date};
+use crate::error::Error;
+use crate::locale::{Locale, Translations};
+use crate::outliers::{self, OutlierOptions};
+use crate::progress;
+use crate::rankings;
+use crate::regions::{self, GroupTotal};
+use crate::run_context;
+use crate::snapshot_labels;
+use crate::ticker_aliases::{AppliedAliases, TickerAliases};
+use crate::universe::{self, UniverseDiff};
+use crate::vega;
+use crate::visualizations;
+use crate::watchlists;
 
 /// Market cap record from CSV file
 #[derive(Debug, Deserialize, Clone)]
@@ -42,6 +60,9 @@ pub struct MarketCapRecord {
     pub market_cap_eur: Option<f64>,
     #[serde(rename = "Market Cap (USD)")]
     pub market_cap_usd: Option<f64>,
+    /// Listing exchange; missing in older exports
+    #[serde(rename = "Exchange", default)]
+    pub exchange: Option<String>,
 }
 
 /// Data point for trend analysis
@@ -63,7 +84,14 @@ pub struct TickerTrend {
     pub overall_change_abs: Option<f64>,
     pub cagr: Option<f64>, // Compound Annual Growth Rate
     pub volatility: Option<f64>,
+    /// Mean period return per unit of volatility (Sharpe-like)
+    pub return_volatility_ratio: Option<f64>,
+    pub downside_deviation: Option<f64>,
     pub max_drawdown: Option<f64>,
+    /// Corporate actions in the analysed period that affect this company
+    pub corporate_action: Option<String>,
+    /// Earlier symbols of a renamed company, from `symbol_changes`
+    pub formerly: Option<String>,
 }
 
 /// Summary statistics for multi-date analysis
@@ -79,6 +107,26 @@ pub struct TrendSummary {
     pub worst_performer: Option<(String, f64)>,
     pub most_volatile: Option<(String, f64)>,
     pub most_stable: Option<(String, f64)>,
+    /// Concentration of the first and last date (normalized USD market caps)
+    pub concentration_start: Option<Concentration>,
+    pub concentration_end: Option<Concentration>,
+    /// Market cap per region on the first and last date
+    pub regions_start: Vec<GroupTotal>,
+    pub regions_end: Vec<GroupTotal>,
+    /// Set when the analysis was restricted with `--consistent-universe`
+    #[serde(skip)]
+    pub universe: Option<UniverseDiff>,
+    /// Corporate actions between the first and last date
+    #[serde(skip)]
+    pub corporate_actions: CorporateActionIndex,
+    /// Whether companies affected by corporate actions were left out
+    pub corporate_actions_excluded: bool,
+    /// Renamed symbols re-keyed while loading the snapshots
+    #[serde(skip)]
+    pub aliases: AppliedAliases,
+    /// Companies left out by `--min-market-cap`
+    #[serde(skip)]
+    pub below_floor: BTreeSet<String>,
 }
 
 /// Rolling period configuration
@@ -92,6 +140,26 @@ pub enum RollingPeriod {
 }
 
 impl RollingPeriod {
+    /// Parse `30d`, `90d`, `180d`, `1y` or a number of days like `45d`
+    pub fn parse(period: &str) -> Result<Self> {
+        Ok(match period.to_lowercase().as_str() {
+            "30d" => RollingPeriod::Days30,
+            "90d" => RollingPeriod::Days90,
+            "180d" => RollingPeriod::Days180,
+            "1y" | "1year" | "365d" => RollingPeriod::Year1,
+            _ => {
+                // Try to parse as number of days
+                let days: i64 = period.trim_end_matches('d').parse().map_err(|_| {
+                    anyhow::anyhow!(
+                        "Invalid period '{}'. Use: 30d, 90d, 180d, 1y, or a number of days (e.g., 45d)",
+                        period
+                    )
+                })?;
+                RollingPeriod::Custom(days)
+            }
+        })
+    }
+
     pub fn days(&self) -> i64 {
         match self {
             RollingPeriod::Days30 => 30,
@@ -157,8 +225,25 @@ impl Default for PeerGroup {
     }
 }
 
-/// Predefined peer groups for the fashion/retail industry
+/// Peer groups of this run: the `[[peer_groups]]` of the config (or of the
+/// `--profile`), else the built-in fashion/retail groups
 pub fn get_predefined_peer_groups() -> Vec<PeerGroup> {
+    crate::config::load_config()
+        .map(|c| peer_groups_of(&c))
+        .unwrap_or_else(|_| builtin_peer_groups())
+}
+
+/// Peer groups of a loaded config, the built-in ones when it defines none
+pub fn peer_groups_of(config: &crate::config::Config) -> Vec<PeerGroup> {
+    if config.peer_groups.is_empty() {
+        builtin_peer_groups()
+    } else {
+        config.peer_groups.clone()
+    }
+}
+
+/// Predefined peer groups for the fashion/retail industry
+fn builtin_peer_groups() -> Vec<PeerGroup> {
     vec![
         PeerGroup {
             name: "Luxury".to_string(),
@@ -284,75 +369,55 @@ pub fn get_predefined_peer_groups() -> Vec<PeerGroup> {
 }
 
 /// Find the most recent CSV file for a given date
-pub fn find_csv_for_date(date: &str) -> Result<String> {
-    let output_dir = Path::new("output");
-    let pattern = format!("marketcaps_{}_", date);
-
-    let mut matching_files = Vec::new();
-    for entry in std::fs::read_dir(output_dir)? {
-        let entry = entry?;
-        let file_name = entry.file_name();
-        let file_name_str = file_name.to_string_lossy();
-
-        if file_name_str.starts_with(&pattern) && file_name_str.ends_with(".csv") {
-            matching_files.push(file_name_str.to_string());
+pub fn find_csv_for_date(date: &str, watchlist: Option<&str>) -> Result<String> {
+    let output = config::load_output_config();
+    match output.find_latest(
+        &watchlists::scoped_kind(watchlist, "marketcaps"),
+        date,
+        "csv",
+    )? {
+        Some(path) => Ok(path.display().to_string()),
+        None => Err(Error::CsvNotFound {
+            date: date.to_string(),
+            watchlist: watchlist.map(str::to_string),
         }
+        .into()),
     }
-
-    if matching_files.is_empty() {
-        anyhow::bail!(
-            "No CSV file found for date {}. Please run 'fetch-specific-date-market-caps {}' first.",
-            date,
-            date
-        );
-    }
-
-    // Sort to get the most recent file (by filename timestamp)
-    matching_files.sort();
-    let selected_file = matching_files.last().unwrap();
-
-    Ok(format!("output/{}", selected_file))
 }
 
-/// Read market cap data from CSV file
-pub fn read_market_cap_csv(file_path: &str) -> Result<Vec<MarketCapRecord>> {
+/// Records of a market cap CSV file, deserialized one row at a time (as
+/// this module's or `compare_marketcaps`' `MarketCapRecord`)
+pub fn market_cap_csv_records<T: DeserializeOwned>(
+    file_path: &str,
+) -> Result<impl Iterator<Item = Result<T>> + use<T>> {
     let file =
         File::open(file_path).with_context(|| format!("Failed to open CSV file: {}", file_path))?;
+    run_context::record_input(file_path);
 
-    let mut reader = Reader::from_reader(file);
-    let mut records = Vec::new();
-
-    for result in reader.deserialize() {
-        let record: MarketCapRecord = result?;
-        records.push(record);
-    }
+    Ok(Reader::from_reader(file)
+        .into_deserialize()
+        .map(|result| result.map_err(anyhow::Error::from)))
+}
 
-    Ok(records)
+/// Read market cap data from CSV file
+pub fn read_market_cap_csv(file_path: &str) -> Result<Vec<MarketCapRecord>> {
+    market_cap_csv_records(file_path)?.collect()
 }
 
 /// Calculate market shares for records
-fn calculate_market_shares(records: &[MarketCapRecord]) -> HashMap<String, f64> {
-    let total_market_cap: f64 = records.iter().filter_map(|r| r.market_cap_usd).sum();
-
-    let mut shares = HashMap::new();
-    if total_market_cap > 0.0 {
-        for record in records {
-            if let Some(market_cap) = record.market_cap_usd {
-                let share = (market_cap / total_market_cap) * 100.0;
-                shares.insert(record.ticker.clone(), share);
-            }
-        }
-    }
-    shares
+fn calculate_market_shares<'a>(
+    records: impl Iterator<Item = &'a MarketCapRecord>,
+) -> HashMap<String, f64> {
+    comparison::market_shares(records.map(|r| (r.ticker.as_str(), r.market_cap_usd)))
 }
 
 /// Get available dates from the output directory
-pub fn get_available_dates() -> Result<Vec<String>> {
-    let output_dir = Path::new("output");
+pub fn get_available_dates(watchlist: Option<&str>) -> Result<Vec<String>> {
+    let output = config::load_output_config();
     let mut dates = HashSet::new();
 
     // Return empty list if output directory doesn't exist
-    if !output_dir.exists() {
+    if !output.directory().exists() {
         println!("Output directory does not exist. No market cap data available.");
         println!(
             "Run 'cargo run -- fetch-specific-date-market-caps YYYY-MM-DD' to fetch data first."
@@ -360,20 +425,18 @@ pub fn get_available_dates() -> Result<Vec<String>> {
         return Ok(Vec::new());
     }
 
-    for entry in std::fs::read_dir(output_dir)? {
-        let entry = entry?;
-        let file_name = entry.file_name();
-        let file_name_str = file_name.to_string_lossy();
-
-        if file_name_str.starts_with("marketcaps_") && file_name_str.ends_with(".csv") {
-            // Extract date from filename: marketcaps_YYYY-MM-DD_...
-            if let Some(date_part) = file_name_str.strip_prefix("marketcaps_") {
-                if let Some(date) = date_part.split('_').next() {
-                    if NaiveDate::parse_from_str(date, "%Y-%m-%d").is_ok() {
-                        dates.insert(date.to_string());
-                    }
-                }
-            }
+    for path in output.list(&watchlists::scoped_kind(watchlist, "marketcaps"), "csv")? {
+        let file_name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        // Extract the first YYYY-MM-DD date from the filename; labeled
+        // intraday snapshots are listed by `get_labeled_snapshots`
+        if let Some(date) = extract_date(&file_name)
+            && label_after(&file_name, &date).is_none()
+        {
+            dates.insert(date);
         }
     }
 
@@ -382,6 +445,45 @@ pub fn get_available_dates() -> Result<Vec<String>> {
     Ok(sorted_dates)
 }
 
+/// Labeled intraday snapshots in the output directory, as `date@label` specs
+pub fn get_labeled_snapshots(watchlist: Option<&str>) -> Result<Vec<String>> {
+    let output = config::load_output_config();
+    if !output.directory().exists() {
+        return Ok(Vec::new());
+    }
+    let mut specs = BTreeSet::new();
+    for path in output.list(&watchlists::scoped_kind(watchlist, "marketcaps"), "csv")? {
+        let file_name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if let Some(date) = extract_date(&file_name)
+            && let Some(label) = label_after(&file_name, &date)
+        {
+            specs.insert(format!("{}{}{}", date, snapshot_labels::SEPARATOR, label));
+        }
+    }
+    Ok(specs.into_iter().collect())
+}
+
+/// Label following the date in a file name like `marketcaps_2025-08-01@close_...`
+fn label_after<'a>(file_name: &'a str, date: &str) -> Option<&'a str> {
+    let (_, rest) = file_name.split_once(date)?;
+    let rest = rest.strip_prefix(snapshot_labels::SEPARATOR)?;
+    rest.split('_').next().filter(|label| !label.is_empty())
+}
+
+/// Find the first YYYY-MM-DD date embedded in a file name
+fn extract_date(file_name: &str) -> Option<String> {
+    let bytes = file_name.as_bytes();
+    (0..bytes.len().saturating_sub(9)).find_map(|start| {
+        let candidate = file_name.get(start..start + 10)?;
+        NaiveDate::parse_from_str(candidate, "%Y-%m-%d")
+            .ok()
+            .map(|_| candidate.to_string())
+    })
+}
+
 // =====================================================
 // Multi-date Trend Analysis
 // =====================================================
@@ -390,10 +492,18 @@ pub fn get_available_dates() -> Result<Vec<String>> {
 pub async fn analyze_trends(
     pool: &SqlitePool,
     dates: Vec<String>,
+    watchlist: Option<&str>,
+    consistent_universe: bool,
+    exclude_corporate_actions: bool,
 ) -> Result<(Vec<TickerTrend>, TrendSummary)> {
     if dates.len() < 2 {
         anyhow::bail!("At least 2 dates are required for trend analysis");
     }
+    if consistent_universe && watchlist.is_some() {
+        anyhow::bail!(
+            "--consistent-universe applies to the config universe and cannot be combined with --watchlist"
+        );
+    }
 
     println!(
         "Analyzing trends across {} dates: {} to {}",
@@ -402,13 +512,7 @@ pub async fn analyze_trends(
         dates.last().unwrap()
     );
 
-    let progress = ProgressBar::new(dates.len() as u64 + 2);
-    progress.set_style(
-        ProgressStyle::default_bar()
-            .template("[{elapsed_precise}] {bar:40.cyan/blue} {msg}")
-            .unwrap()
-            .progress_chars("=>-"),
-    );
+    let progress = progress::step_bar(dates.len() as u64 + 2, "trend");
 
     // Get exchange rates for normalization (use the latest date)
     let latest_date = dates.last().unwrap();
@@ -421,213 +525,371 @@ pub async fn analyze_trends(
     let normalization_rates = get_rate_map_from_db_for_date(pool, Some(latest_timestamp)).await?;
     progress.inc(1);
 
-    // Load data for each date
-    let mut all_data: BTreeMap<String, HashMap<String, MarketCapRecord>> = BTreeMap::new();
-    let mut all_tickers: HashSet<String> = HashSet::new();
-    let mut ticker_names: HashMap<String, String> = HashMap::new();
+    // M&A and similar events distort the trend of the companies involved
+    let corporate_actions =
+        CorporateActionIndex::load_for_period(dates.first().unwrap(), latest_date)?;
+    let exclude_affected = exclude_corporate_actions && !corporate_actions.is_empty();
+
+    // Only keep companies tracked on every date so universe edits don't skew
+    // totals; this needs a first pass over the tickers of every snapshot
+    let aliases = TickerAliases::load(pool).await?;
+    let universe_diff = if consistent_universe {
+        let mut exported: Vec<(String, BTreeSet<String>)> = Vec::new();
+        for date in &dates {
+            progress.set_message(format!("Listing companies for {}...", date));
+            let records =
+                load_trend_snapshot(date, watchlist, &aliases, &mut AppliedAliases::default())?;
+            exported.push((
+                date.clone(),
+                records.into_iter().map(|r| r.ticker).collect(),
+            ));
+        }
+        Some(universe::consistent_universe(pool, &exported, &aliases).await?)
+    } else {
+        None
+    };
 
+    // Aggregate one snapshot at a time, with renamed symbols under their current symbol
+    let mut applied_aliases = AppliedAliases::default();
+    let mut builder = TrendBuilder::new(&dates, &normalization_rates);
     for date in &dates {
         progress.set_message(format!("Loading data for {}...", date));
-        let file_path = find_csv_for_date(date)?;
-        let records = read_market_cap_csv(&file_path)?;
-
-        let mut date_map = HashMap::new();
-        for record in records {
-            all_tickers.insert(record.ticker.clone());
-            ticker_names.insert(record.ticker.clone(), record.name.clone());
-            date_map.insert(record.ticker.clone(), record);
+        let mut records = load_trend_snapshot(date, watchlist, &aliases, &mut applied_aliases)?;
+        if let Some(diff) = &universe_diff {
+            records.retain(|r| diff.contains(&r.ticker));
         }
-        all_data.insert(date.clone(), date_map);
+        if exclude_affected {
+            records.retain(|r| !corporate_actions.is_affected(&r.ticker));
+        }
+        builder.add_snapshot(date, records)?;
         progress.inc(1);
     }
+    if exclude_affected {
+        println!("Excluding companies affected by corporate actions in this period");
+    }
 
     progress.set_message("Calculating trends...");
+    let result = builder.finish(
+        corporate_actions,
+        exclude_corporate_actions,
+        universe_diff,
+        applied_aliases,
+    )?;
+    progress.inc(1);
+    progress.finish_with_message("Trend analysis complete");
 
-    // Build trend data for each ticker
-    let mut trends: Vec<TickerTrend> = Vec::new();
+    Ok(result)
+}
 
-    for ticker in &all_tickers {
-        let name = ticker_names.get(ticker).cloned().unwrap_or_default();
-        let mut data_points = Vec::new();
-        let mut values: Vec<f64> = Vec::new();
+/// Records of the snapshot of `date` as analysed for trends: the `--top`
+/// entries, with renamed symbols under their current symbol
+pub(crate) fn load_trend_snapshot(
+    date: &str,
+    watchlist: Option<&str>,
+    aliases: &TickerAliases,
+    applied: &mut AppliedAliases,
+) -> Result<Vec<MarketCapRecord>> {
+    let file_path = find_csv_for_date(date, watchlist)?;
+    let mut records = market_cap_csv_records(&file_path)?
+        .take(rankings::top().unwrap_or(usize::MAX))
+        .collect::<Result<Vec<MarketCapRecord>>>()?;
+    aliases.apply(
+        NaiveDate::parse_from_str(date, "%Y-%m-%d")?,
+        &mut records,
+        |r| &mut r.ticker,
+        applied,
+    );
+    Ok(records)
+}
 
-        for date in &dates {
-            if let Some(date_data) = all_data.get(date) {
-                if let Some(record) = date_data.get(ticker) {
-                    // Normalize market cap using latest exchange rates
-                    let market_cap_usd = record.market_cap_original.map(|orig| {
-                        let currency = record.original_currency.as_deref().unwrap_or("USD");
-                        if normalization_rates.is_empty() {
-                            record.market_cap_usd.unwrap_or(orig)
-                        } else {
-                            convert_currency(orig, currency, "USD", &normalization_rates)
-                        }
-                    });
-
-                    let shares =
-                        calculate_market_shares(&date_data.values().cloned().collect::<Vec<_>>());
-
-                    data_points.push(TrendDataPoint {
-                        date: date.clone(),
-                        market_cap_usd,
-                        rank: record.rank,
-                        market_share: shares.get(ticker).copied(),
-                    });
-
-                    if let Some(v) = market_cap_usd {
-                        values.push(v);
-                    }
-                } else {
-                    // Ticker not present on this date
-                    data_points.push(TrendDataPoint {
-                        date: date.clone(),
-                        market_cap_usd: None,
-                        rank: None,
-                        market_share: None,
-                    });
-                }
-            }
+/// Market cap per region of one loaded snapshot
+fn region_totals<'a>(records: impl Iterator<Item = &'a MarketCapRecord>) -> Vec<GroupTotal> {
+    let listings: Vec<regions::Listing> = records
+        .map(|r| regions::Listing {
+            exchange: r.exchange.as_deref().unwrap_or_default(),
+            currency: r.original_currency.as_deref().unwrap_or_default(),
+            market_cap_eur: r.market_cap_eur,
+            market_cap_usd: r.market_cap_usd,
+        })
+        .collect();
+    regions::by_region(&listings)
+}
+
+/// Trends per company over snapshots loaded per date, sorted by overall
+/// change. Market caps are converted to USD with `normalization_rates` (the
+/// rates of the last date) so currency moves don't count as growth.
+pub fn compute_trends(
+    dates: &[String],
+    all_data: &BTreeMap<String, BTreeMap<String, MarketCapRecord>>,
+    normalization_rates: &HashMap<String, f64>,
+    corporate_actions: CorporateActionIndex,
+    exclude_corporate_actions: bool,
+    universe: Option<UniverseDiff>,
+    aliases: AppliedAliases,
+) -> Result<(Vec<TickerTrend>, TrendSummary)> {
+    let mut builder = TrendBuilder::new(dates, normalization_rates);
+    for date in dates {
+        if let Some(records) = all_data.get(date) {
+            builder.add_snapshot(date, records.values().cloned())?;
         }
+    }
+    builder.finish(
+        corporate_actions,
+        exclude_corporate_actions,
+        universe,
+        aliases,
+    )
+}
 
-        // Calculate statistics
-        let overall_change_pct = if values.len() >= 2 {
-            let first = values.first().unwrap();
-            let last = values.last().unwrap();
-            if *first > 0.0 {
-                Some(((last - first) / first) * 100.0)
-            } else {
-                None
-            }
-        } else {
-            None
-        };
+/// Points of one company, one slot per trend date
+#[derive(Debug, Default)]
+struct TickerPoints {
+    name: String,
+    market_caps_usd: Vec<Option<f64>>,
+    ranks: Vec<Option<usize>>,
+    market_shares: Vec<Option<f64>>,
+}
 
-        let overall_change_abs = if values.len() >= 2 {
-            let first = values.first().unwrap();
-            let last = values.last().unwrap();
-            Some(last - first)
-        } else {
-            None
-        };
+/// Incremental trend aggregation. Snapshots are added in date order and
+/// reduced to a few numbers per company right away, so a multi-year analysis
+/// holds a single snapshot's records in memory instead of every date's.
+pub struct TrendBuilder<'a> {
+    dates: &'a [String],
+    normalization_rates: &'a HashMap<String, f64>,
+    /// Dates a snapshot was added for; the others get no data points
+    added: Vec<bool>,
+    points: BTreeMap<String, TickerPoints>,
+    regions_start: Vec<GroupTotal>,
+    regions_end: Vec<GroupTotal>,
+    outliers: OutlierOptions,
+    /// Tickers whose first market cap has been seen
+    sized: BTreeSet<String>,
+    /// Tickers left out under `--min-market-cap`
+    below_floor: BTreeSet<String>,
+}
+
+impl<'a> TrendBuilder<'a> {
+    pub fn new(dates: &'a [String], normalization_rates: &'a HashMap<String, f64>) -> Self {
+        Self {
+            dates,
+            normalization_rates,
+            added: vec![false; dates.len()],
+            points: BTreeMap::new(),
+            regions_start: Vec::new(),
+            regions_end: Vec::new(),
+            outliers: outliers::current(),
+            sized: BTreeSet::new(),
+            below_floor: BTreeSet::new(),
+        }
+    }
 
-        // Calculate CAGR (Compound Annual Growth Rate)
-        let cagr = if values.len() >= 2 && dates.len() >= 2 {
-            let first = *values.first().unwrap();
-            let last = *values.last().unwrap();
-            let first_date = NaiveDate::parse_from_str(dates.first().unwrap(), "%Y-%m-%d")?;
-            let last_date = NaiveDate::parse_from_str(dates.last().unwrap(), "%Y-%m-%d")?;
-            let years = (last_date - first_date).num_days() as f64 / 365.25;
-            if first > 0.0 && years > 0.0 {
-                Some(((last / first).powf(1.0 / years) - 1.0) * 100.0)
+    /// Market cap in USD at the normalization rates
+    fn normalized_market_cap(&self, record: &MarketCapRecord) -> Option<f64> {
+        record.market_cap_original.map(|orig| {
+            let currency = record.original_currency.as_deref().unwrap_or("USD");
+            if self.normalization_rates.is_empty() {
+                record.market_cap_usd.unwrap_or(orig)
             } else {
-                None
+                convert_currency(orig, currency, "USD", self.normalization_rates)
             }
-        } else {
-            None
-        };
+        })
+    }
 
-        // Calculate volatility (standard deviation of returns)
-        let volatility = if values.len() >= 3 {
-            let returns: Vec<f64> = values
-                .windows(2)
-                .map(|w| (w[1] - w[0]) / w[0] * 100.0)
-                .collect();
-            let mean = returns.iter().sum::<f64>() / returns.len() as f64;
-            let variance =
-                returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / returns.len() as f64;
-            Some(variance.sqrt())
-        } else {
-            None
-        };
+    /// Add the snapshot of `date`, which must come after the dates added so
+    /// far. A ticker listed twice keeps its last record.
+    pub fn add_snapshot(
+        &mut self,
+        date: &str,
+        records: impl IntoIterator<Item = MarketCapRecord>,
+    ) -> Result<()> {
+        let index = self
+            .dates
+            .iter()
+            .position(|d| d == date)
+            .with_context(|| format!("{} is not one of the trend dates", date))?;
+        if self.added[index..].iter().any(|added| *added) {
+            anyhow::bail!("Snapshot of {} added out of date order", date);
+        }
+        self.added[index] = true;
 
-        // Calculate max drawdown
-        let max_drawdown = if values.len() >= 2 {
-            let mut max_so_far = values[0];
-            let mut max_dd = 0.0f64;
-            for &v in &values {
-                if v > max_so_far {
-                    max_so_far = v;
-                }
-                let dd = (max_so_far - v) / max_so_far * 100.0;
-                if dd > max_dd {
-                    max_dd = dd;
+        let mut records: BTreeMap<String, MarketCapRecord> = records
+            .into_iter()
+            .map(|record| (record.ticker.clone(), record))
+            .collect();
+
+        // Under --min-market-cap, companies are sized by their first market
+        // cap and left out of shares and regions as well as the trends
+        if self.outliers.min_market_cap.is_some() {
+            for (ticker, record) in &records {
+                let market_cap_usd = self.normalized_market_cap(record);
+                if market_cap_usd.is_some()
+                    && self.sized.insert(ticker.clone())
+                    && self.outliers.below_floor(market_cap_usd)
+                {
+                    self.below_floor.insert(ticker.clone());
                 }
             }
-            Some(max_dd)
-        } else {
-            None
-        };
-
-        trends.push(TickerTrend {
-            ticker: ticker.clone(),
-            name,
-            data_points,
-            overall_change_pct,
-            overall_change_abs,
-            cagr,
-            volatility,
-            max_drawdown,
-        });
-    }
+            records.retain(|ticker, _| !self.below_floor.contains(ticker));
+        }
 
-    // Sort by overall change percentage
-    trends.sort_by(|a, b| {
-        let a_pct = a.overall_change_pct.unwrap_or(f64::NEG_INFINITY);
-        let b_pct = b.overall_change_pct.unwrap_or(f64::NEG_INFINITY);
-        b_pct.partial_cmp(&a_pct).unwrap()
-    });
+        let shares = calculate_market_shares(records.values());
+        if index == 0 {
+            self.regions_start = region_totals(records.values());
+        }
+        if index + 1 == self.dates.len() {
+            self.regions_end = region_totals(records.values());
+        }
 
-    // Calculate summary statistics
-    let total_start: f64 = trends
-        .iter()
-        .filter_map(|t| t.data_points.first().and_then(|dp| dp.market_cap_usd))
-        .sum();
-    let total_end: f64 = trends
-        .iter()
-        .filter_map(|t| t.data_points.last().and_then(|dp| dp.market_cap_usd))
-        .sum();
+        let slots = self.dates.len();
+        for (ticker, record) in records {
+            // Normalize market cap using latest exchange rates
+            let market_cap_usd = self.normalized_market_cap(&record);
+            let market_share = shares.get(&ticker).copied();
+            // Named as on the latest date the company appears
+            let points = self.points.entry(ticker).or_insert_with(|| TickerPoints {
+                market_caps_usd: vec![None; slots],
+                ranks: vec![None; slots],
+                market_shares: vec![None; slots],
+                ..TickerPoints::default()
+            });
+            points.name = record.name;
+            points.market_caps_usd[index] = market_cap_usd;
+            points.ranks[index] = record.rank;
+            points.market_shares[index] = market_share;
+        }
+        Ok(())
+    }
 
-    let best_performer = trends
-        .iter()
-        .filter_map(|t| t.overall_change_pct.map(|p| (t.ticker.clone(), p)))
-        .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+    /// Trends per company sorted by overall change, with the summary
+    pub fn finish(
+        self,
+        corporate_actions: CorporateActionIndex,
+        exclude_corporate_actions: bool,
+        universe: Option<UniverseDiff>,
+        aliases: AppliedAliases,
+    ) -> Result<(Vec<TickerTrend>, TrendSummary)> {
+        let dates = self.dates;
+
+        // Calculate statistics over the years between the first and last date
+        let years = match (dates.first(), dates.last()) {
+            (Some(first), Some(last)) if dates.len() >= 2 => {
+                let first_date = NaiveDate::parse_from_str(first, "%Y-%m-%d")?;
+                let last_date = NaiveDate::parse_from_str(last, "%Y-%m-%d")?;
+                (last_date - first_date).num_days() as f64 / 365.25
+            }
+            _ => 0.0,
+        };
 
-    let worst_performer = trends
-        .iter()
-        .filter_map(|t| t.overall_change_pct.map(|p| (t.ticker.clone(), p)))
-        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        let mut trends: Vec<TickerTrend> = Vec::with_capacity(self.points.len());
+        for (ticker, points) in self.points {
+            let data_points: Vec<TrendDataPoint> = (0..dates.len())
+                .filter(|index| self.added[*index])
+                .map(|index| TrendDataPoint {
+                    date: dates[index].clone(),
+                    market_cap_usd: points.market_caps_usd[index],
+                    rank: points.ranks[index],
+                    market_share: points.market_shares[index],
+                })
+                .collect();
+            let values: Vec<f64> = data_points
+                .iter()
+                .filter_map(|point| point.market_cap_usd)
+                .collect();
+            let stats = TrendStats::from_values(&values, years);
+
+            trends.push(TickerTrend {
+                corporate_action: corporate_actions.annotation(&ticker),
+                formerly: aliases.annotation(&ticker),
+                ticker,
+                name: points.name,
+                data_points,
+                overall_change_pct: stats.overall_change_pct,
+                overall_change_abs: stats.overall_change_abs,
+                cagr: stats.cagr,
+                volatility: stats.volatility,
+                return_volatility_ratio: stats.return_volatility_ratio,
+                downside_deviation: stats.downside_deviation,
+                max_drawdown: stats.max_drawdown,
+            });
+        }
 
-    let most_volatile = trends
-        .iter()
-        .filter_map(|t| t.volatility.map(|v| (t.ticker.clone(), v)))
-        .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        // Companies that fell below the floor after an earlier snapshot
+        trends.retain(|trend| !self.below_floor.contains(&trend.ticker));
 
-    let most_stable = trends
-        .iter()
-        .filter_map(|t| t.volatility.map(|v| (t.ticker.clone(), v)))
-        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
-
-    let summary = TrendSummary {
-        start_date: dates.first().unwrap().clone(),
-        end_date: dates.last().unwrap().clone(),
-        num_periods: dates.len(),
-        total_market_cap_start: total_start,
-        total_market_cap_end: total_end,
-        total_change_pct: if total_start > 0.0 {
-            ((total_end - total_start) / total_start) * 100.0
-        } else {
-            0.0
-        },
-        best_performer,
-        worst_performer,
-        most_volatile,
-        most_stable,
-    };
+        // Sort by overall change percentage, ties by name and ticker
+        trends.sort_by(|a, b| {
+            rankings::rank_order(
+                (a.overall_change_pct, &a.name, &a.ticker),
+                (b.overall_change_pct, &b.name, &b.ticker),
+            )
+        });
 
-    progress.inc(1);
-    progress.finish_with_message("Trend analysis complete");
+        // Calculate summary statistics
+        let total_start: f64 = trends
+            .iter()
+            .filter_map(|t| t.data_points.first().and_then(|dp| dp.market_cap_usd))
+            .sum();
+        let total_end: f64 = trends
+            .iter()
+            .filter_map(|t| t.data_points.last().and_then(|dp| dp.market_cap_usd))
+            .sum();
+
+        let best_performer = trends
+            .iter()
+            .filter_map(|t| t.overall_change_pct.map(|p| (t.ticker.clone(), p)))
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+
+        let worst_performer = trends
+            .iter()
+            .filter_map(|t| t.overall_change_pct.map(|p| (t.ticker.clone(), p)))
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+
+        let most_volatile = trends
+            .iter()
+            .filter_map(|t| t.volatility.map(|v| (t.ticker.clone(), v)))
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+
+        let most_stable = trends
+            .iter()
+            .filter_map(|t| t.volatility.map(|v| (t.ticker.clone(), v)))
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+
+        let summary = TrendSummary {
+            start_date: dates.first().unwrap().clone(),
+            end_date: dates.last().unwrap().clone(),
+            num_periods: dates.len(),
+            total_market_cap_start: total_start,
+            total_market_cap_end: total_end,
+            total_change_pct: if total_start > 0.0 {
+                ((total_end - total_start) / total_start) * 100.0
+            } else {
+                0.0
+            },
+            best_performer,
+            worst_performer,
+            most_volatile,
+            most_stable,
+            concentration_start: Concentration::from_values(
+                trends
+                    .iter()
+                    .filter_map(|t| t.data_points.first().and_then(|dp| dp.market_cap_usd)),
+            ),
+            concentration_end: Concentration::from_values(
+                trends
+                    .iter()
+                    .filter_map(|t| t.data_points.last().and_then(|dp| dp.market_cap_usd)),
+            ),
+            regions_start: self.regions_start,
+            regions_end: self.regions_end,
+            universe,
+            corporate_actions,
+            corporate_actions_excluded: exclude_corporate_actions,
+            aliases,
+            below_floor: self.below_floor,
+        };
 
-    Ok((trends, summary))
+        Ok((trends, summary))
+    }
 }
 
 /// Export trend analysis results
@@ -635,16 +897,21 @@ pub fn export_trend_analysis(
     trends: &[TickerTrend],
     summary: &TrendSummary,
     dates: &[String],
+    watchlist: Option<&str>,
+    output: &OutputConfig,
 ) -> Result<()> {
-    let timestamp = Local::now().format("%Y%m%d_%H%M%S");
-    let csv_filename = format!(
-        "output/trend_analysis_{}_to_{}_{}.csv",
-        summary.start_date, summary.end_date, timestamp
-    );
-    let md_filename = format!(
-        "output/trend_analysis_{}_to_{}_summary_{}.md",
-        summary.start_date, summary.end_date, timestamp
-    );
+    output.ensure_directory()?;
+    let timestamp = OutputConfig::timestamp();
+    let kind = watchlists::scoped_kind(watchlist, "trend_analysis");
+    let range = format!("{}_to_{}", summary.start_date, summary.end_date);
+    let csv_filename = output
+        .file_path_at(&kind, &range, &timestamp, "csv")
+        .display()
+        .to_string();
+    let md_filename = output
+        .file_path_at(&kind, &format!("{}_summary", range), &timestamp, "md")
+        .display()
+        .to_string();
 
     // Export CSV
     let file = File::create(&csv_filename)?;
@@ -658,7 +925,11 @@ pub fn export_trend_analysis(
         "Overall Change ($)".to_string(),
         "CAGR (%)".to_string(),
         "Volatility".to_string(),
+        "Return/Volatility".to_string(),
+        "Downside Deviation".to_string(),
         "Max Drawdown (%)".to_string(),
+        "Corporate Action".to_string(),
+        "Formerly".to_string(),
     ];
     for date in dates {
         headers.push(format!("Market Cap {}", date));
@@ -687,10 +958,20 @@ pub fn export_trend_analysis(
                 .volatility
                 .map(|v| format!("{:.2}", v))
                 .unwrap_or_else(|| "N/A".to_string()),
+            trend
+                .return_volatility_ratio
+                .map(|v| format!("{:.2}", v))
+                .unwrap_or_else(|| "N/A".to_string()),
+            trend
+                .downside_deviation
+                .map(|v| format!("{:.2}", v))
+                .unwrap_or_else(|| "N/A".to_string()),
             trend
                 .max_drawdown
                 .map(|v| format!("{:.2}", v))
                 .unwrap_or_else(|| "N/A".to_string()),
+            trend.corporate_action.clone().unwrap_or_default(),
+            trend.formerly.clone().unwrap_or_default(),
         ];
 
         for date in dates {
@@ -720,6 +1001,28 @@ pub fn export_trend_analysis(
         summary.start_date, summary.end_date
     )?;
     writeln!(file)?;
+    if let Some(diff) = &summary.universe {
+        write!(file, "{}", diff.markdown_note())?;
+    }
+    if !summary.corporate_actions.is_empty() {
+        write!(
+            file,
+            "{}",
+            summary
+                .corporate_actions
+                .markdown_section(summary.corporate_actions_excluded)
+        )?;
+    }
+    if !summary.aliases.is_empty() {
+        write!(file, "{}", summary.aliases.markdown_section())?;
+    }
+    if outliers::current().is_active() {
+        write!(
+            file,
+            "{}",
+            outliers::current().markdown_note(&summary.below_floor, false)
+        )?;
+    }
     writeln!(file, "## Overview")?;
     writeln!(
         file,
@@ -740,6 +1043,34 @@ pub fn export_trend_analysis(
     writeln!(file, "- **Total Change**: {:.2}%", summary.total_change_pct)?;
     writeln!(file)?;
 
+    writeln!(file, "## Market Concentration")?;
+    write!(
+        file,
+        "{}",
+        concentration::markdown_table(
+            &summary.start_date,
+            &summary.end_date,
+            summary.concentration_start,
+            summary.concentration_end,
+            &Translations::load(Locale::En)?,
+        )
+    )?;
+    writeln!(file)?;
+
+    writeln!(file, "## Regional Breakdown")?;
+    write!(
+        file,
+        "{}",
+        regions::markdown_table(
+            &summary.start_date,
+            &summary.end_date,
+            &summary.regions_start,
+            &summary.regions_end,
+            &Translations::load(Locale::En)?,
+        )
+    )?;
+    writeln!(file)?;
+
     writeln!(file, "## Key Performers")?;
     if let Some((ticker, pct)) = &summary.best_performer {
         writeln!(file, "- **Best Performer**: {} (+{:.2}%)", ticker, pct)?;
@@ -763,17 +1094,23 @@ pub fn export_trend_analysis(
     }
     writeln!(file)?;
 
-    writeln!(file, "## Top 10 Performers")?;
+    let section_size = rankings::section_size();
+    writeln!(file, "## Top {} Performers", section_size)?;
     writeln!(file, "| Rank | Ticker | Name | Change (%) | CAGR (%) |")?;
     writeln!(file, "|------|--------|------|------------|----------|")?;
-    for (i, trend) in trends.iter().take(10).enumerate() {
+    for (i, trend) in trends.iter().take(section_size).enumerate() {
         writeln!(
             file,
-            "| {} | [{}](https://finance.yahoo.com/quote/{}/) | {} | {:.2}% | {}% |",
+            "| {} | [{}](https://finance.yahoo.com/quote/{}/) | {}{} | {:.2}% | {}% |",
             i + 1,
             trend.ticker,
             trend.ticker,
             trend.name,
+            if trend.corporate_action.is_some() {
+                " †"
+            } else {
+                ""
+            },
             trend.overall_change_pct.unwrap_or(0.0),
             trend
                 .cagr
@@ -783,18 +1120,23 @@ pub fn export_trend_analysis(
     }
     writeln!(file)?;
 
-    writeln!(file, "## Bottom 10 Performers")?;
+    writeln!(file, "## Bottom {} Performers", section_size)?;
     writeln!(file, "| Rank | Ticker | Name | Change (%) | CAGR (%) |")?;
     writeln!(file, "|------|--------|------|------------|----------|")?;
-    let bottom_10: Vec<_> = trends.iter().rev().take(10).collect();
-    for (i, trend) in bottom_10.iter().enumerate() {
+    let bottom: Vec<_> = trends.iter().rev().take(section_size).collect();
+    for (i, trend) in bottom.iter().enumerate() {
         writeln!(
             file,
-            "| {} | [{}](https://finance.yahoo.com/quote/{}/) | {} | {:.2}% | {}% |",
+            "| {} | [{}](https://finance.yahoo.com/quote/{}/) | {}{} | {:.2}% | {}% |",
             i + 1,
             trend.ticker,
             trend.ticker,
             trend.name,
+            if trend.corporate_action.is_some() {
+                " †"
+            } else {
+                ""
+            },
             trend.overall_change_pct.unwrap_or(0.0),
             trend
                 .cagr
@@ -804,11 +1146,54 @@ pub fn export_trend_analysis(
     }
     writeln!(file)?;
 
+    // Growth per unit of volatility; companies with too few dates are left out
+    let mut risk_adjusted: Vec<&TickerTrend> = trends
+        .iter()
+        .filter(|t| t.return_volatility_ratio.is_some())
+        .collect();
+    risk_adjusted.sort_by(|a, b| {
+        rankings::rank_order(
+            (a.return_volatility_ratio, &a.name, &a.ticker),
+            (b.return_volatility_ratio, &b.name, &b.ticker),
+        )
+    });
+    if !risk_adjusted.is_empty() {
+        writeln!(file, "## Best Risk-Adjusted Performers")?;
+        writeln!(
+            file,
+            "| Rank | Ticker | Name | Change (%) | Volatility | Return/Volatility | Downside Deviation |"
+        )?;
+        writeln!(
+            file,
+            "|------|--------|------|------------|------------|-------------------|--------------------|"
+        )?;
+        for (i, trend) in risk_adjusted.iter().take(section_size).enumerate() {
+            writeln!(
+                file,
+                "| {} | [{}](https://finance.yahoo.com/quote/{}/) | {}{} | {:.2}% | {:.2} | {:.2} | {:.2} |",
+                i + 1,
+                trend.ticker,
+                trend.ticker,
+                trend.name,
+                if trend.corporate_action.is_some() {
+                    " †"
+                } else {
+                    ""
+                },
+                trend.overall_change_pct.unwrap_or(0.0),
+                trend.volatility.unwrap_or(0.0),
+                trend.return_volatility_ratio.unwrap_or(0.0),
+                trend.downside_deviation.unwrap_or(0.0),
+            )?;
+        }
+        writeln!(file)?;
+    }
+
     writeln!(file, "---")?;
     writeln!(
         file,
         "*Generated on {}*",
-        Local::now().format("%Y-%m-%d %H:%M:%S")
+        clock::now().format("%Y-%m-%d %H:%M:%S")
     )?;
 
     println!("Summary report exported to {}", md_filename);
@@ -844,14 +1229,21 @@ pub fn get_yoy_dates(reference_date: &str, num_years: i32) -> Result<Vec<String>
 }
 
 /// Perform YoY comparison
-pub async fn compare_yoy(pool: &SqlitePool, reference_date: &str, num_years: i32) -> Result<()> {
+pub async fn compare_yoy(
+    pool: &SqlitePool,
+    reference_date: &str,
+    num_years: i32,
+    watchlist: Option<&str>,
+    consistent_universe: bool,
+    exclude_corporate_actions: bool,
+) -> Result<()> {
     println!(
         "Performing Year-over-Year comparison for {} ({} years back)",
         reference_date, num_years
     );
 
     let dates = get_yoy_dates(reference_date, num_years)?;
-    let available_dates = get_available_dates()?;
+    let available_dates = get_available_dates(watchlist)?;
 
     // Filter to only available dates
     let valid_dates: Vec<String> = dates
@@ -874,8 +1266,21 @@ pub async fn compare_yoy(pool: &SqlitePool, reference_date: &str, num_years: i32
         println!("  - {}", date);
     }
 
-    let (trends, summary) = analyze_trends(pool, valid_dates.clone()).await?;
-    export_trend_analysis(&trends, &summary, &valid_dates)?;
+    let (trends, summary) = analyze_trends(
+        pool,
+        valid_dates.clone(),
+        watchlist,
+        consistent_universe,
+        exclude_corporate_actions,
+    )
+    .await?;
+    export_trend_analysis(
+        &trends,
+        &summary,
+        &valid_dates,
+        watchlist,
+        &config::load_output_config(),
+    )?;
 
     Ok(())
 }
@@ -923,14 +1328,21 @@ fn get_quarter_end(date: NaiveDate) -> Result<NaiveDate> {
 }
 
 /// Perform QoQ comparison
-pub async fn compare_qoq(pool: &SqlitePool, reference_date: &str, num_quarters: i32) -> Result<()> {
+pub async fn compare_qoq(
+    pool: &SqlitePool,
+    reference_date: &str,
+    num_quarters: i32,
+    watchlist: Option<&str>,
+    consistent_universe: bool,
+    exclude_corporate_actions: bool,
+) -> Result<()> {
     println!(
         "Performing Quarter-over-Quarter comparison for {} ({} quarters back)",
         reference_date, num_quarters
     );
 
     let dates = get_qoq_dates(reference_date, num_quarters)?;
-    let available_dates = get_available_dates()?;
+    let available_dates = get_available_dates(watchlist)?;
 
     // Filter to only available dates
     let valid_dates: Vec<String> = dates
@@ -953,8 +1365,21 @@ pub async fn compare_qoq(pool: &SqlitePool, reference_date: &str, num_quarters:
         println!("  - {}", date);
     }
 
-    let (trends, summary) = analyze_trends(pool, valid_dates.clone()).await?;
-    export_trend_analysis(&trends, &summary, &valid_dates)?;
+    let (trends, summary) = analyze_trends(
+        pool,
+        valid_dates.clone(),
+        watchlist,
+        consistent_universe,
+        exclude_corporate_actions,
+    )
+    .await?;
+    export_trend_analysis(
+        &trends,
+        &summary,
+        &valid_dates,
+        watchlist,
+        &config::load_output_config(),
+    )?;
 
     Ok(())
 }
@@ -965,9 +1390,13 @@ pub async fn compare_qoq(pool: &SqlitePool, reference_date: &str, num_quarters:
 
 /// Perform rolling period comparison
 pub async fn compare_rolling(
-    _pool: &SqlitePool,
+    pool: &SqlitePool,
     reference_date: &str,
     period: RollingPeriod,
+    report_currencies: &[String],
+    watchlist: Option<&str>,
+    consistent_universe: bool,
+    exclude_corporate_actions: bool,
 ) -> Result<()> {
     let ref_date = NaiveDate::parse_from_str(reference_date, "%Y-%m-%d")
         .context("Invalid date format. Use YYYY-MM-DD")?;
@@ -983,28 +1412,30 @@ pub async fn compare_rolling(
     );
 
     // Check if we have data for both dates
-    let available_dates = get_available_dates()?;
-
-    if !available_dates.contains(&start_date_str) {
-        anyhow::bail!(
-            "No data found for start date {}. Please run:\n  \
-            cargo run -- fetch-specific-date-market-caps {}",
-            start_date_str,
-            start_date_str
-        );
-    }
+    let available_dates = get_available_dates(watchlist)?;
 
-    if !available_dates.contains(&reference_date.to_string()) {
-        anyhow::bail!(
-            "No data found for reference date {}. Please run:\n  \
-            cargo run -- fetch-specific-date-market-caps {}",
-            reference_date,
-            reference_date
-        );
+    for date in [start_date_str.as_str(), reference_date] {
+        if !available_dates.iter().any(|d| d == date) {
+            return Err(Error::CsvNotFound {
+                date: date.to_string(),
+                watchlist: watchlist.map(str::to_string),
+            }
+            .into());
+        }
     }
 
     // Use the existing comparison function
-    crate::compare_marketcaps::compare_market_caps(&start_date_str, reference_date).await?;
+    crate::compare_marketcaps::compare_market_caps(
+        pool,
+        &start_date_str,
+        reference_date,
+        report_currencies,
+        watchlist,
+        consistent_universe,
+        exclude_corporate_actions,
+        false,
+    )
+    .await?;
 
     Ok(())
 }
@@ -1030,6 +1461,7 @@ pub async fn compare_with_benchmark(
     from_date: &str,
     to_date: &str,
     benchmark: Benchmark,
+    watchlist: Option<&str>,
 ) -> Result<()> {
     println!(
         "Comparing performance against {} ({}) from {} to {}",
@@ -1047,17 +1479,19 @@ pub async fn compare_with_benchmark(
     let normalization_rates = get_rate_map_from_db_for_date(pool, Some(to_timestamp)).await?;
 
     // Load market cap data
-    let from_file = find_csv_for_date(from_date)?;
-    let to_file = find_csv_for_date(to_date)?;
+    let from_file = find_csv_for_date(from_date, watchlist)?;
+    let to_file = find_csv_for_date(to_date, watchlist)?;
 
-    let from_records = read_market_cap_csv(&from_file)?;
-    let to_records = read_market_cap_csv(&to_file)?;
+    let mut from_records = read_market_cap_csv(&from_file)?;
+    let mut to_records = read_market_cap_csv(&to_file)?;
+    rankings::truncate_to_top(&mut from_records);
+    rankings::truncate_to_top(&mut to_records);
 
-    let from_map: HashMap<String, MarketCapRecord> = from_records
+    let from_map: BTreeMap<String, MarketCapRecord> = from_records
         .into_iter()
         .map(|r| (r.ticker.clone(), r))
         .collect();
-    let to_map: HashMap<String, MarketCapRecord> = to_records
+    let to_map: BTreeMap<String, MarketCapRecord> = to_records
         .into_iter()
         .map(|r| (r.ticker.clone(), r))
         .collect();
@@ -1083,7 +1517,7 @@ pub async fn compare_with_benchmark(
     // Calculate relative performance for each ticker
     let mut comparisons: Vec<BenchmarkComparison> = Vec::new();
 
-    let all_tickers: HashSet<_> = from_map.keys().chain(to_map.keys()).cloned().collect();
+    let all_tickers: BTreeSet<_> = from_map.keys().chain(to_map.keys()).cloned().collect();
 
     for ticker in all_tickers {
         let from_record = from_map.get(&ticker);
@@ -1135,15 +1569,16 @@ pub async fn compare_with_benchmark(
         });
     }
 
-    // Sort by relative performance
+    // Sort by relative performance, ties by name and ticker
     comparisons.sort_by(|a, b| {
-        let a_rel = a.relative_performance.unwrap_or(f64::NEG_INFINITY);
-        let b_rel = b.relative_performance.unwrap_or(f64::NEG_INFINITY);
-        b_rel.partial_cmp(&a_rel).unwrap()
+        rankings::rank_order(
+            (a.relative_performance, &a.name, &a.ticker),
+            (b.relative_performance, &b.name, &b.ticker),
+        )
     });
 
     // Export results
-    export_benchmark_comparison(&comparisons, from_date, to_date, &benchmark)?;
+    export_benchmark_comparison(&comparisons, from_date, to_date, &benchmark, watchlist)?;
 
     Ok(())
 }
@@ -1154,17 +1589,27 @@ fn export_benchmark_comparison(
     from_date: &str,
     to_date: &str,
     benchmark: &Benchmark,
+    watchlist: Option<&str>,
 ) -> Result<()> {
-    let timestamp = Local::now().format("%Y%m%d_%H%M%S");
-    let benchmark_name = benchmark.name().replace(' ', "_").to_lowercase();
-    let csv_filename = format!(
-        "output/benchmark_{}_{}_{}_to_{}_{}.csv",
-        benchmark_name, from_date, to_date, from_date, timestamp
-    );
-    let md_filename = format!(
-        "output/benchmark_{}_{}_{}_to_{}_summary_{}.md",
-        benchmark_name, from_date, to_date, from_date, timestamp
+    let output = config::load_output_config();
+    output.ensure_directory()?;
+    let timestamp = OutputConfig::timestamp();
+    let kind = watchlists::scoped_kind(
+        watchlist,
+        &format!(
+            "benchmark_{}",
+            benchmark.name().replace(' ', "_").to_lowercase()
+        ),
     );
+    let range = format!("{}_to_{}", from_date, to_date);
+    let csv_filename = output
+        .file_path_at(&kind, &range, &timestamp, "csv")
+        .display()
+        .to_string();
+    let md_filename = output
+        .file_path_at(&kind, &format!("{}_summary", range), &timestamp, "md")
+        .display()
+        .to_string();
 
     // Export CSV
     let file = File::create(&csv_filename)?;
@@ -1241,13 +1686,14 @@ fn export_benchmark_comparison(
     writeln!(file, "- **Underperformers**: {}", underperformers)?;
     writeln!(file)?;
 
-    writeln!(file, "## Top 10 Outperformers")?;
+    let section_size = rankings::section_size();
+    writeln!(file, "## Top {} Outperformers", section_size)?;
     writeln!(file, "| Ticker | Name | Return (%) | Relative (%) |")?;
     writeln!(file, "|--------|------|------------|--------------|")?;
     for comp in comparisons
         .iter()
         .filter(|c| c.relative_performance.map(|r| r > 0.0).unwrap_or(false))
-        .take(10)
+        .take(section_size)
     {
         writeln!(
             file,
@@ -1261,14 +1707,14 @@ fn export_benchmark_comparison(
     }
     writeln!(file)?;
 
-    writeln!(file, "## Top 10 Underperformers")?;
+    writeln!(file, "## Top {} Underperformers", section_size)?;
     writeln!(file, "| Ticker | Name | Return (%) | Relative (%) |")?;
     writeln!(file, "|--------|------|------------|--------------|")?;
     for comp in comparisons
         .iter()
         .filter(|c| c.relative_performance.map(|r| r < 0.0).unwrap_or(false))
         .rev()
-        .take(10)
+        .take(section_size)
     {
         writeln!(
             file,
@@ -1286,7 +1732,7 @@ fn export_benchmark_comparison(
     writeln!(
         file,
         "*Generated on {}*",
-        Local::now().format("%Y-%m-%d %H:%M:%S")
+        clock::now().format("%Y-%m-%d %H:%M:%S")
     )?;
 
     println!("Summary report exported to {}", md_filename);
@@ -1320,20 +1766,12 @@ pub struct PeerMemberResult {
     pub change_pct: Option<f64>,
     pub rank_from: Option<usize>,
     pub rank_to: Option<usize>,
+    /// Earlier symbols of a renamed company, from `symbol_changes`
+    pub formerly: Option<String>,
 }
 
-/// Perform peer group comparison
-pub async fn compare_peer_groups(
-    pool: &SqlitePool,
-    from_date: &str,
-    to_date: &str,
-    groups: Option<Vec<String>>, // None = all predefined groups
-) -> Result<()> {
-    println!(
-        "Performing peer group comparison from {} to {}",
-        from_date, to_date
-    );
-
+/// The peer groups named in `groups` (case-insensitive), or all of them
+pub fn select_peer_groups(groups: Option<Vec<String>>) -> Result<Vec<PeerGroup>> {
     let peer_groups = get_predefined_peer_groups();
 
     // Filter groups if specified
@@ -1351,6 +1789,23 @@ pub async fn compare_peer_groups(
             "No peer groups found. Available groups: Luxury, Sportswear, Fast Fashion, Department Stores, Value Retail, Footwear, E-commerce, Asian Fashion"
         );
     }
+    Ok(selected_groups)
+}
+
+/// Perform peer group comparison
+pub async fn compare_peer_groups(
+    pool: &SqlitePool,
+    from_date: &str,
+    to_date: &str,
+    groups: Option<Vec<String>>, // None = all predefined groups
+    watchlist: Option<&str>,
+) -> Result<()> {
+    println!(
+        "Performing peer group comparison from {} to {}",
+        from_date, to_date
+    );
+
+    let selected_groups = select_peer_groups(groups)?;
 
     // Get exchange rates
     let to_date_parsed = NaiveDate::parse_from_str(to_date, "%Y-%m-%d")?;
@@ -1360,22 +1815,41 @@ pub async fn compare_peer_groups(
     let normalization_rates = get_rate_map_from_db_for_date(pool, Some(to_timestamp)).await?;
 
     // Load market cap data
-    let from_file = find_csv_for_date(from_date)?;
-    let to_file = find_csv_for_date(to_date)?;
-
-    let from_records = read_market_cap_csv(&from_file)?;
-    let to_records = read_market_cap_csv(&to_file)?;
+    let from_file = find_csv_for_date(from_date, watchlist)?;
+    let to_file = find_csv_for_date(to_date, watchlist)?;
+
+    let mut from_records = read_market_cap_csv(&from_file)?;
+    let mut to_records = read_market_cap_csv(&to_file)?;
+
+    // Renamed symbols (FB -> META) are looked up under their current symbol
+    let from_date_parsed = NaiveDate::parse_from_str(from_date, "%Y-%m-%d")?;
+    let aliases = TickerAliases::load(pool).await?;
+    let mut applied_aliases = AppliedAliases::default();
+    aliases.apply(
+        from_date_parsed,
+        &mut from_records,
+        |r| &mut r.ticker,
+        &mut applied_aliases,
+    );
+    aliases.apply(
+        to_date_parsed,
+        &mut to_records,
+        |r| &mut r.ticker,
+        &mut applied_aliases,
+    );
 
-    let from_map: HashMap<String, MarketCapRecord> = from_records
+    let from_map: BTreeMap<String, MarketCapRecord> = from_records
         .into_iter()
         .map(|r| (r.ticker.clone(), r))
         .collect();
-    let to_map: HashMap<String, MarketCapRecord> = to_records
+    let to_map: BTreeMap<String, MarketCapRecord> = to_records
         .into_iter()
         .map(|r| (r.ticker.clone(), r))
         .collect();
 
     // Analyze each peer group
+    let outliers = outliers::current();
+    let mut below_floor = BTreeSet::new();
     let mut results: Vec<PeerGroupResult> = Vec::new();
 
     for group in &selected_groups {
@@ -1387,6 +1861,7 @@ pub async fn compare_peer_groups(
         let mut changes: Vec<f64> = Vec::new();
 
         for ticker in &group.tickers {
+            let ticker = &aliases.current_symbol(ticker, from_date_parsed).to_string();
             let from_record = from_map.get(ticker);
             let to_record = to_map.get(ticker);
 
@@ -1417,6 +1892,12 @@ pub async fn compare_peer_groups(
                 })
             });
 
+            // Under --min-market-cap, sized by the start-date market cap
+            if outliers.below_floor(market_cap_from.or(market_cap_to)) {
+                below_floor.insert(ticker.clone());
+                continue;
+            }
+
             let change_pct = match (market_cap_from, market_cap_to) {
                 (Some(from_val), Some(to_val)) if from_val > 0.0 => {
                     let pct = ((to_val - from_val) / from_val) * 100.0;
@@ -1441,14 +1922,16 @@ pub async fn compare_peer_groups(
                 change_pct,
                 rank_from: from_record.and_then(|r| r.rank),
                 rank_to: to_record.and_then(|r| r.rank),
+                formerly: applied_aliases.annotation(ticker),
             });
         }
 
-        // Sort members by change percentage
+        // Sort members by change percentage, ties by name and ticker
         members.sort_by(|a, b| {
-            let a_pct = a.change_pct.unwrap_or(f64::NEG_INFINITY);
-            let b_pct = b.change_pct.unwrap_or(f64::NEG_INFINITY);
-            b_pct.partial_cmp(&a_pct).unwrap()
+            rankings::rank_order(
+                (a.change_pct, &a.name, &a.ticker),
+                (b.change_pct, &b.name, &b.ticker),
+            )
         });
 
         let total_change_pct = if total_from > 0.0 {
@@ -1457,11 +1940,7 @@ pub async fn compare_peer_groups(
             0.0
         };
 
-        let avg_change_pct = if !changes.is_empty() {
-            changes.iter().sum::<f64>() / changes.len() as f64
-        } else {
-            0.0
-        };
+        let avg_change_pct = outliers.average(&changes).unwrap_or(0.0);
 
         let best = members
             .first()
@@ -1482,11 +1961,28 @@ pub async fn compare_peer_groups(
         });
     }
 
-    // Sort groups by performance
-    results.sort_by(|a, b| b.total_change_pct.partial_cmp(&a.total_change_pct).unwrap());
+    // Sort groups by performance, ties by name
+    results.sort_by(|a, b| {
+        rankings::rank_order(
+            (Some(a.total_change_pct), &a.group_name, ""),
+            (Some(b.total_change_pct), &b.group_name, ""),
+        )
+    });
 
     // Export results
-    export_peer_group_comparison(&results, from_date, to_date)?;
+    let outlier_note = if outliers.is_active() {
+        outliers.markdown_note(&below_floor, true)
+    } else {
+        String::new()
+    };
+    export_peer_group_comparison(
+        &results,
+        from_date,
+        to_date,
+        watchlist,
+        &applied_aliases,
+        &outlier_note,
+    )?;
 
     Ok(())
 }
@@ -1496,16 +1992,23 @@ fn export_peer_group_comparison(
     results: &[PeerGroupResult],
     from_date: &str,
     to_date: &str,
+    watchlist: Option<&str>,
+    aliases: &AppliedAliases,
+    notes: &str,
 ) -> Result<()> {
-    let timestamp = Local::now().format("%Y%m%d_%H%M%S");
-    let csv_filename = format!(
-        "output/peer_groups_{}_to_{}_{}.csv",
-        from_date, to_date, timestamp
-    );
-    let md_filename = format!(
-        "output/peer_groups_{}_to_{}_summary_{}.md",
-        from_date, to_date, timestamp
-    );
+    let output = config::load_output_config();
+    output.ensure_directory()?;
+    let timestamp = OutputConfig::timestamp();
+    let kind = watchlists::scoped_kind(watchlist, "peer_groups");
+    let range = format!("{}_to_{}", from_date, to_date);
+    let csv_filename = output
+        .file_path_at(&kind, &range, &timestamp, "csv")
+        .display()
+        .to_string();
+    let md_filename = output
+        .file_path_at(&kind, &format!("{}_summary", range), &timestamp, "md")
+        .display()
+        .to_string();
 
     // Export CSV
     let file = File::create(&csv_filename)?;
@@ -1520,6 +2023,7 @@ fn export_peer_group_comparison(
         "Change (%)",
         "Rank From",
         "Rank To",
+        "Formerly",
     ])?;
 
     for result in results {
@@ -1548,6 +2052,7 @@ fn export_peer_group_comparison(
                     .rank_to
                     .map(|v| v.to_string())
                     .unwrap_or_else(|| "N/A".to_string()),
+                member.formerly.clone().unwrap_or_default(),
             ])?;
         }
     }
@@ -1563,6 +2068,10 @@ fn export_peer_group_comparison(
         from_date, to_date
     )?;
     writeln!(file)?;
+    if !aliases.is_empty() {
+        write!(file, "{}", aliases.markdown_section())?;
+    }
+    write!(file, "{}", notes)?;
 
     writeln!(file, "## Group Performance Summary")?;
     writeln!(
@@ -1638,7 +2147,7 @@ fn export_peer_group_comparison(
     writeln!(
         file,
         "*Generated on {}*",
-        Local::now().format("%Y-%m-%d %H:%M:%S")
+        clock::now().format("%Y-%m-%d %H:%M:%S")
     )?;
 
     println!("Summary report exported to {}", md_filename);
@@ -1651,9 +2160,34 @@ fn export_peer_group_comparison(
 // =====================================================
 
 /// Multi-date trend analysis command
-pub async fn multi_date_comparison(pool: &SqlitePool, dates: Vec<String>) -> Result<()> {
-    let (trends, summary) = analyze_trends(pool, dates.clone()).await?;
-    export_trend_analysis(&trends, &summary, &dates)?;
+pub async fn multi_date_comparison(
+    pool: &SqlitePool,
+    dates: Vec<String>,
+    watchlist: Option<&str>,
+    consistent_universe: bool,
+    exclude_corporate_actions: bool,
+) -> Result<()> {
+    let (trends, summary) = analyze_trends(
+        pool,
+        dates.clone(),
+        watchlist,
+        consistent_universe,
+        exclude_corporate_actions,
+    )
+    .await?;
+    let output = config::load_output_config();
+    export_trend_analysis(&trends, &summary, &dates, watchlist, &output)?;
+
+    // Rank churn at a glance
+    let bump_path = output.named_path(&format!(
+        "{}_{}_to_{}_bump.{}",
+        watchlists::scoped_kind(watchlist, "trend_analysis"),
+        summary.start_date,
+        summary.end_date,
+        vega::extension()
+    ));
+    visualizations::create_bump_chart(&trends, &dates, &bump_path)?;
+    println!("✅ Generated bump chart: {}", bump_path.display());
     Ok(())
 }
 
@@ -1701,6 +2235,21 @@ mod tests {
         assert_eq!(RollingPeriod::Days90.days(), 90);
         assert_eq!(RollingPeriod::Year1.days(), 365);
         assert_eq!(RollingPeriod::Custom(45).days(), 45);
+        assert_eq!(RollingPeriod::parse("1Y").unwrap().days(), 365);
+        assert_eq!(RollingPeriod::parse("45d").unwrap().days(), 45);
+        assert!(RollingPeriod::parse("quarter").is_err());
+    }
+
+    #[test]
+    fn test_label_after_date() {
+        let labeled = "marketcaps_2025-08-01@close_20250801_160500.csv";
+        let date = extract_date(labeled).unwrap();
+        assert_eq!(date, "2025-08-01");
+        assert_eq!(label_after(labeled, &date), Some("close"));
+        assert_eq!(
+            label_after("marketcaps_2025-08-01_20250801_160500.csv", &date),
+            None
+        );
     }
 
     #[test]
@@ -1742,4 +2291,96 @@ mod tests {
         let q4 = get_quarter_end(NaiveDate::from_ymd_opt(2025, 11, 30).unwrap()).unwrap();
         assert_eq!(q4, NaiveDate::from_ymd_opt(2025, 12, 31).unwrap());
     }
+
+    fn record(ticker: &str, name: &str, market_cap_usd: f64) -> MarketCapRecord {
+        MarketCapRecord {
+            rank: None,
+            ticker: ticker.to_string(),
+            name: name.to_string(),
+            market_cap_original: Some(market_cap_usd),
+            original_currency: Some("USD".to_string()),
+            market_cap_eur: None,
+            market_cap_usd: Some(market_cap_usd),
+            exchange: None,
+        }
+    }
+
+    #[test]
+    fn test_trend_builder_aggregates_snapshots_incrementally() {
+        let dates: Vec<String> = ["2024-01-01", "2024-07-01", "2025-01-01"]
+            .map(String::from)
+            .to_vec();
+        let rates = HashMap::new();
+        let mut builder = TrendBuilder::new(&dates, &rates);
+        builder
+            .add_snapshot(
+                &dates[0],
+                vec![record("NKE", "Nike", 75.0), record("TJX", "TJX", 25.0)],
+            )
+            .unwrap();
+        // The middle date is missing: it gets no data points
+        builder
+            .add_snapshot(&dates[2], vec![record("NKE", "Nike, Inc.", 150.0)])
+            .unwrap();
+        assert!(builder.add_snapshot(&dates[1], Vec::new()).is_err());
+        assert!(builder.add_snapshot("2023-01-01", Vec::new()).is_err());
+
+        let (trends, summary) = builder
+            .finish(
+                CorporateActionIndex::default(),
+                false,
+                None,
+                AppliedAliases::default(),
+            )
+            .unwrap();
+        assert_eq!(trends[0].ticker, "NKE");
+        assert_eq!(trends[0].name, "Nike, Inc.");
+        assert_eq!(trends[0].data_points.len(), 2);
+        assert_eq!(trends[0].overall_change_pct, Some(100.0));
+        assert_eq!(trends[0].data_points[0].market_share, Some(75.0));
+        assert_eq!(trends[1].data_points[1].market_cap_usd, None);
+        assert_eq!(summary.total_market_cap_start, 100.0);
+        assert_eq!(summary.total_market_cap_end, 150.0);
+    }
+
+    #[test]
+    fn test_trend_builder_leaves_companies_below_floor_out_of_shares_and_regions() {
+        let dates: Vec<String> = ["2024-01-01", "2025-01-01"].map(String::from).to_vec();
+        let rates = HashMap::new();
+        let mut builder = TrendBuilder::new(&dates, &rates);
+        builder.outliers = OutlierOptions {
+            min_market_cap: Some(50.0),
+            winsorize_pct: None,
+        };
+        builder
+            .add_snapshot(
+                &dates[0],
+                vec![record("NKE", "Nike", 75.0), record("TINY", "Tiny", 25.0)],
+            )
+            .unwrap();
+        // TINY grew past the floor but is sized by its first market cap
+        builder
+            .add_snapshot(
+                &dates[1],
+                vec![record("NKE", "Nike", 150.0), record("TINY", "Tiny", 60.0)],
+            )
+            .unwrap();
+
+        let (trends, summary) = builder
+            .finish(
+                CorporateActionIndex::default(),
+                false,
+                None,
+                AppliedAliases::default(),
+            )
+            .unwrap();
+        assert_eq!(trends.len(), 1);
+        assert_eq!(trends[0].data_points[0].market_share, Some(100.0));
+        assert_eq!(trends[0].data_points[1].market_share, Some(100.0));
+        assert_eq!(summary.below_floor, BTreeSet::from(["TINY".to_string()]));
+        for regions in [&summary.regions_start, &summary.regions_end] {
+            let companies: usize = regions.iter().map(|r| r.companies).sum();
+            assert_eq!(companies, 1);
+        }
+    }
 }
@@ -0,0 +1,431 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Weekly and monthly market cap aggregates
+//!
+//! Rolls the stored snapshots up into one row per company and period with the
+//! open, high, low and close EUR market cap and the average rank. The rows are
+//! kept in the `marketcap_aggregates` table and exported as a tidy CSV (one row
+//! per ticker and period) for BI tools.
+
+use anyhow::{Context, Result};
+use chrono::{Datelike, Days, NaiveDate};
+use csv::Writer;
+use sqlx::Row;
+use sqlx::sqlite::SqlitePool;
+use std::collections::BTreeMap;
+
+use crate::config::{self, OutputConfig};
+use crate::rankings;
+
+/// Length of an aggregation period
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Granularity {
+    Weekly,
+    Monthly,
+}
+
+impl Granularity {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "weekly" => Ok(Granularity::Weekly),
+            "monthly" => Ok(Granularity::Monthly),
+            other => anyhow::bail!("Unknown granularity '{}': use weekly or monthly", other),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Granularity::Weekly => "weekly",
+            Granularity::Monthly => "monthly",
+        }
+    }
+
+    /// The ISO week (`2025-W03`) or month (`2025-01`) containing `date`
+    pub fn period_of(&self, date: NaiveDate) -> Period {
+        match self {
+            Granularity::Weekly => {
+                let start = date - Days::new(date.weekday().num_days_from_monday() as u64);
+                Period {
+                    label: date.format("%G-W%V").to_string(),
+                    start,
+                    end: start + Days::new(6),
+                }
+            }
+            Granularity::Monthly => {
+                let start = date.with_day(1).unwrap_or(date);
+                let end = start
+                    .checked_add_months(chrono::Months::new(1))
+                    .and_then(|next| next.pred_opt())
+                    .unwrap_or(date);
+                Period {
+                    label: date.format("%Y-%m").to_string(),
+                    start,
+                    end,
+                }
+            }
+        }
+    }
+}
+
+/// A week or month
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Period {
+    pub label: String,
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+/// A company's rank and market cap in one snapshot
+#[derive(Debug, Clone)]
+pub struct Observation {
+    pub ticker: String,
+    pub name: String,
+    pub date: NaiveDate,
+    pub rank: i64,
+    pub market_cap_eur: Option<f64>,
+}
+
+/// OHLC market cap and average rank of a company over one period
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aggregate {
+    pub ticker: String,
+    pub name: String,
+    pub period: Period,
+    pub open: Option<f64>,
+    pub high: Option<f64>,
+    pub low: Option<f64>,
+    pub close: Option<f64>,
+    pub avg_rank: f64,
+    pub snapshots: usize,
+}
+
+/// Aggregate snapshots per ticker and period. Open and close are the first
+/// and last market caps within the period; the name is the latest one.
+/// Results are ordered by period, then average rank, then ticker.
+pub fn aggregate(observations: &[Observation], granularity: Granularity) -> Vec<Aggregate> {
+    let mut groups: BTreeMap<(Period, &str), Vec<&Observation>> = BTreeMap::new();
+    for observation in observations {
+        groups
+            .entry((
+                granularity.period_of(observation.date),
+                observation.ticker.as_str(),
+            ))
+            .or_default()
+            .push(observation);
+    }
+
+    let mut aggregates: Vec<Aggregate> = groups
+        .into_iter()
+        .map(|((period, ticker), mut snapshots)| {
+            snapshots.sort_by_key(|o| o.date);
+            let caps: Vec<f64> = snapshots
+                .iter()
+                .filter_map(|o| o.market_cap_eur)
+                .filter(|v| !v.is_nan())
+                .collect();
+            Aggregate {
+                ticker: ticker.to_string(),
+                name: snapshots.last().map(|o| o.name.clone()).unwrap_or_default(),
+                period,
+                open: caps.first().copied(),
+                high: caps.iter().copied().reduce(f64::max),
+                low: caps.iter().copied().reduce(f64::min),
+                close: caps.last().copied(),
+                avg_rank: snapshots.iter().map(|o| o.rank as f64).sum::<f64>()
+                    / snapshots.len() as f64,
+                snapshots: snapshots.len(),
+            }
+        })
+        .collect();
+
+    aggregates.sort_by(|a, b| {
+        a.period
+            .cmp(&b.period)
+            .then_with(|| a.avg_rank.total_cmp(&b.avg_rank))
+            .then_with(|| a.ticker.cmp(&b.ticker))
+    });
+    aggregates
+}
+
+/// Every ranked snapshot, with the company name stored with it
+async fn load_observations(pool: &SqlitePool) -> Result<Vec<Observation>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT r.ticker, COALESCE(m.name, r.ticker) as name, r.date, r.rank, r.market_cap_eur
+        FROM rankings r
+        LEFT JOIN market_caps m ON m.ticker = r.ticker AND m.timestamp = r.timestamp
+        ORDER BY r.timestamp, r.rank
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    rows.iter()
+        .map(|row| {
+            let date: String = row.get("date");
+            Ok(Observation {
+                ticker: row.get("ticker"),
+                name: row.get("name"),
+                date: NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+                    .with_context(|| format!("Invalid ranking date: {}", date))?,
+                rank: row.get("rank"),
+                market_cap_eur: row.get("market_cap_eur"),
+            })
+        })
+        .collect()
+}
+
+/// Replace the stored aggregates of `granularity`
+async fn store_aggregates(
+    pool: &SqlitePool,
+    granularity: Granularity,
+    aggregates: &[Aggregate],
+) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM marketcap_aggregates WHERE granularity = ?")
+        .bind(granularity.as_str())
+        .execute(&mut *tx)
+        .await?;
+    for aggregate in aggregates {
+        sqlx::query(
+            r#"
+            INSERT INTO marketcap_aggregates (
+                ticker, granularity, period, period_start, period_end, name,
+                open_eur, high_eur, low_eur, close_eur, avg_rank, snapshots
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&aggregate.ticker)
+        .bind(granularity.as_str())
+        .bind(&aggregate.period.label)
+        .bind(aggregate.period.start.to_string())
+        .bind(aggregate.period.end.to_string())
+        .bind(&aggregate.name)
+        .bind(aggregate.open)
+        .bind(aggregate.high)
+        .bind(aggregate.low)
+        .bind(aggregate.close)
+        .bind(aggregate.avg_rank)
+        .bind(aggregate.snapshots as i64)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+fn format_cap(value: Option<f64>) -> String {
+    value.map(|v| format!("{:.0}", v)).unwrap_or_default()
+}
+
+fn export_aggregates_csv(
+    granularity: Granularity,
+    aggregates: &[Aggregate],
+    output: &OutputConfig,
+) -> Result<String> {
+    output.ensure_directory()?;
+    let filename = output
+        .file_path("marketcap_aggregates", granularity.as_str(), "csv")
+        .display()
+        .to_string();
+    let mut writer = Writer::from_path(&filename)?;
+
+    writer.write_record([
+        "Ticker",
+        "Name",
+        "Granularity",
+        "Period",
+        "Period Start",
+        "Period End",
+        "Open (EUR)",
+        "High (EUR)",
+        "Low (EUR)",
+        "Close (EUR)",
+        "Average Rank",
+        "Snapshots",
+    ])?;
+    for aggregate in aggregates {
+        writer.write_record(&[
+            aggregate.ticker.clone(),
+            aggregate.name.clone(),
+            granularity.as_str().to_string(),
+            aggregate.period.label.clone(),
+            aggregate.period.start.to_string(),
+            aggregate.period.end.to_string(),
+            format_cap(aggregate.open),
+            format_cap(aggregate.high),
+            format_cap(aggregate.low),
+            format_cap(aggregate.close),
+            format!("{:.2}", aggregate.avg_rank),
+            aggregate.snapshots.to_string(),
+        ])?;
+    }
+    writer.flush()?;
+
+    Ok(filename)
+}
+
+/// Compute, store and export the aggregates for `aggregate --granularity`
+pub async fn aggregate_marketcaps(pool: &SqlitePool, granularity: &str) -> Result<()> {
+    let granularity = Granularity::parse(granularity)?;
+
+    // Snapshots fetched before rankings were tracked have no ranks yet
+    let backfilled = rankings::backfill_rankings(pool).await?;
+    if backfilled > 0 {
+        println!("Ranked {} older snapshots", backfilled);
+    }
+
+    let observations = load_observations(pool).await?;
+    if observations.is_empty() {
+        println!("No market cap snapshots stored yet");
+        return Ok(());
+    }
+
+    let aggregates = aggregate(&observations, granularity);
+    store_aggregates(pool, granularity, &aggregates).await?;
+    let periods = aggregates
+        .iter()
+        .map(|a| &a.period.label)
+        .collect::<std::collections::BTreeSet<_>>()
+        .len();
+    println!(
+        "📊 {} {} aggregates over {} periods from {} snapshots",
+        aggregates.len(),
+        granularity.as_str(),
+        periods,
+        observations.len()
+    );
+
+    let file = export_aggregates_csv(granularity, &aggregates, &config::load_output_config())?;
+    println!("✅ Aggregates exported to {}", file);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    fn date(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+    }
+
+    fn observation(ticker: &str, day: &str, rank: i64, cap: Option<f64>) -> Observation {
+        Observation {
+            ticker: ticker.to_string(),
+            name: format!("{} Inc", ticker),
+            date: date(day),
+            rank,
+            market_cap_eur: cap,
+        }
+    }
+
+    #[test]
+    fn test_periods() {
+        let week = Granularity::Weekly.period_of(date("2025-01-01"));
+        assert_eq!(week.label, "2025-W01");
+        assert_eq!(week.start, date("2024-12-30"));
+        assert_eq!(week.end, date("2025-01-05"));
+
+        let month = Granularity::Monthly.period_of(date("2024-02-14"));
+        assert_eq!(month.label, "2024-02");
+        assert_eq!(month.start, date("2024-02-01"));
+        assert_eq!(month.end, date("2024-02-29"));
+
+        assert!(Granularity::parse("daily").is_err());
+        assert_eq!(Granularity::parse("Weekly").unwrap(), Granularity::Weekly);
+    }
+
+    #[test]
+    fn test_aggregate_ohlc_and_average_rank() {
+        let observations = vec![
+            observation("NKE", "2025-01-31", 2, Some(90.0)),
+            observation("NKE", "2025-01-02", 1, Some(100.0)),
+            observation("NKE", "2025-01-15", 1, Some(120.0)),
+            observation("NKE", "2025-01-20", 3, None),
+            observation("TJX", "2025-01-02", 2, Some(80.0)),
+            observation("NKE", "2025-02-03", 2, Some(95.0)),
+        ];
+
+        let aggregates = aggregate(&observations, Granularity::Monthly);
+        let summary: Vec<_> = aggregates
+            .iter()
+            .map(|a| {
+                (
+                    a.period.label.as_str(),
+                    a.ticker.as_str(),
+                    [a.open, a.high, a.low, a.close],
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    "2025-01",
+                    "NKE",
+                    [Some(100.0), Some(120.0), Some(90.0), Some(90.0)]
+                ),
+                ("2025-01", "TJX", [Some(80.0); 4]),
+                ("2025-02", "NKE", [Some(95.0); 4]),
+            ]
+        );
+        assert_eq!(aggregates[0].avg_rank, 1.75);
+        assert_eq!(aggregates[0].snapshots, 4);
+    }
+
+    #[tokio::test]
+    async fn test_aggregate_marketcaps_stores_rows() {
+        let pool = db::create_db_pool("sqlite::memory:").await.unwrap();
+        let day1 = 1_735_689_600; // 2025-01-01 (ISO week 1)
+        for (ticker, eur, timestamp) in [
+            ("NKE", 100.0, day1),
+            ("TJX", 200.0, day1),
+            ("NKE", 300.0, day1 + 86_400),
+            ("TJX", 200.0, day1 + 86_400),
+            ("NKE", 250.0, day1 + 7 * 86_400),
+        ] {
+            sqlx::query(
+                "INSERT INTO market_caps (ticker, name, market_cap_eur, market_cap_usd, timestamp)
+                 VALUES (?, ?, ?, ?, ?)",
+            )
+            .bind(ticker)
+            .bind(ticker)
+            .bind(eur)
+            .bind(eur * 1.1)
+            .bind(timestamp)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+        rankings::backfill_rankings(&pool).await.unwrap();
+
+        let observations = load_observations(&pool).await.unwrap();
+        let aggregates = aggregate(&observations, Granularity::Weekly);
+        store_aggregates(&pool, Granularity::Weekly, &aggregates)
+            .await
+            .unwrap();
+        // Storing again replaces rather than duplicates
+        store_aggregates(&pool, Granularity::Weekly, &aggregates)
+            .await
+            .unwrap();
+
+        let rows = sqlx::query(
+            "SELECT ticker, period, open_eur, close_eur, avg_rank, snapshots
+             FROM marketcap_aggregates WHERE granularity = 'weekly' ORDER BY period, ticker",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].get::<String, _>("ticker"), "NKE");
+        assert_eq!(rows[0].get::<String, _>("period"), "2025-W01");
+        assert_eq!(rows[0].get::<f64, _>("open_eur"), 100.0);
+        assert_eq!(rows[0].get::<f64, _>("close_eur"), 300.0);
+        assert_eq!(rows[0].get::<f64, _>("avg_rank"), 1.5);
+        assert_eq!(rows[0].get::<i64, _>("snapshots"), 2);
+        assert_eq!(rows[2].get::<String, _>("period"), "2025-W02");
+    }
+}
@@ -0,0 +1,549 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Analyst price targets and ratings (`analyst-summary`)
+//!
+//! Fetching costs two FMP requests per ticker, so it only runs with
+//! `export-combined --with-analyst`. The consensus is stored per ticker and
+//! day together with the share price of that fetch; targets and price are both
+//! in the listing currency, so the upside needs no conversion.
+
+use anyhow::Result;
+use chrono::NaiveDate;
+use csv::Writer;
+use sqlx::Row;
+use sqlx::sqlite::SqlitePool;
+use std::fs::File;
+use std::io::Write as IoWrite;
+use std::path::Path;
+
+use crate::advanced_comparisons::{self, PeerGroup};
+use crate::api::FMPClient;
+use crate::clock;
+use crate::config::{self, OutputConfig};
+use crate::progress;
+use crate::utils;
+
+/// A fetched company with its current share price
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quote {
+    pub ticker: String,
+    pub name: String,
+    pub currency: Option<String>,
+    pub price: Option<f64>,
+}
+
+/// Stored analyst consensus of one company
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AnalystTarget {
+    pub ticker: String,
+    pub name: String,
+    pub currency: Option<String>,
+    pub price: Option<f64>,
+    pub target_consensus: Option<f64>,
+    pub target_median: Option<f64>,
+    pub target_high: Option<f64>,
+    pub target_low: Option<f64>,
+    pub strong_buy: Option<i64>,
+    pub buy: Option<i64>,
+    pub hold: Option<i64>,
+    pub sell: Option<i64>,
+    pub strong_sell: Option<i64>,
+    /// FMP's label, e.g. "Buy"
+    pub consensus: Option<String>,
+}
+
+impl AnalystTarget {
+    /// Consensus target above (or below) the price, in percent
+    pub fn upside_pct(&self) -> Option<f64> {
+        match (self.target_consensus, self.price) {
+            (Some(target), Some(price)) if price > 0.0 && target > 0.0 => {
+                Some((target / price - 1.0) * 100.0)
+            }
+            _ => None,
+        }
+    }
+
+    /// Number of ratings as (buy, hold, sell), strong ones included
+    pub fn ratings(&self) -> (i64, i64, i64) {
+        let count = |c: Option<i64>| c.unwrap_or(0);
+        (
+            count(self.strong_buy) + count(self.buy),
+            count(self.hold),
+            count(self.sell) + count(self.strong_sell),
+        )
+    }
+}
+
+/// Analyst view of one peer group
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupSummary {
+    pub group: String,
+    /// Members with stored analyst data
+    pub covered: usize,
+    pub members: usize,
+    pub median_upside_pct: Option<f64>,
+    pub average_upside_pct: Option<f64>,
+    pub buy: i64,
+    pub hold: i64,
+    pub sell: i64,
+}
+
+/// Upside and ratings per peer group, in the order of `groups`
+pub fn by_peer_group(targets: &[AnalystTarget], groups: &[PeerGroup]) -> Vec<GroupSummary> {
+    groups
+        .iter()
+        .map(|group| {
+            let members: Vec<&AnalystTarget> = targets
+                .iter()
+                .filter(|t| group.tickers.contains(&t.ticker))
+                .collect();
+            let upsides: Vec<f64> = members.iter().filter_map(|t| t.upside_pct()).collect();
+            let (buy, hold, sell) = members
+                .iter()
+                .map(|t| t.ratings())
+                .fold((0, 0, 0), |(b, h, s), (buy, hold, sell)| {
+                    (b + buy, h + hold, s + sell)
+                });
+            GroupSummary {
+                group: group.name.clone(),
+                covered: members.len(),
+                members: group.tickers.len(),
+                average_upside_pct: (!upsides.is_empty())
+                    .then(|| upsides.iter().sum::<f64>() / upsides.len() as f64),
+                median_upside_pct: utils::median(&upsides),
+                buy,
+                hold,
+                sell,
+            }
+        })
+        .collect()
+}
+
+/// Fetch and store the analyst consensus of the fetched companies. Returns the
+/// number of companies stored; uncovered ones and failures are skipped.
+pub async fn update_targets(
+    pool: &SqlitePool,
+    fmp_client: &FMPClient,
+    quotes: &[Quote],
+    date: &str,
+    concurrency: usize,
+) -> Result<usize> {
+    println!(
+        "Fetching analyst price targets and ratings ({} requests)...",
+        quotes.len() * 2
+    );
+    let progress = progress::fetch_bar(quotes.len() as u64, "analysts", 2);
+    let fetched = utils::fetch_ordered(quotes, concurrency, |quote| {
+        let progress = progress.clone();
+        async move {
+            let consensus = fmp_client.get_analyst_consensus(&quote.ticker).await;
+            progress.inc(1);
+            consensus
+        }
+    })
+    .await;
+    progress.finish();
+
+    let mut stored = 0;
+    for (quote, result) in quotes.iter().zip(fetched) {
+        let (targets, ratings) = match result {
+            Ok((None, None)) => continue,
+            Ok(consensus) => consensus,
+            Err(e) => {
+                eprintln!("Failed to fetch analyst data for {}: {}", quote.ticker, e);
+                continue;
+            }
+        };
+        let target = AnalystTarget {
+            ticker: quote.ticker.clone(),
+            name: quote.name.clone(),
+            currency: quote.currency.clone(),
+            price: quote.price.filter(|p| *p > 0.0),
+            target_consensus: targets.as_ref().and_then(|t| t.target_consensus),
+            target_median: targets.as_ref().and_then(|t| t.target_median),
+            target_high: targets.as_ref().and_then(|t| t.target_high),
+            target_low: targets.as_ref().and_then(|t| t.target_low),
+            strong_buy: ratings.as_ref().and_then(|r| r.strong_buy),
+            buy: ratings.as_ref().and_then(|r| r.buy),
+            hold: ratings.as_ref().and_then(|r| r.hold),
+            sell: ratings.as_ref().and_then(|r| r.sell),
+            strong_sell: ratings.as_ref().and_then(|r| r.strong_sell),
+            consensus: ratings.and_then(|r| r.consensus),
+        };
+        store_target(pool, date, &target).await?;
+        stored += 1;
+    }
+
+    println!(
+        "✅ Analyst data stored for {} of {} companies",
+        stored,
+        quotes.len()
+    );
+    Ok(stored)
+}
+
+/// Store a company's consensus for a day, replacing an earlier fetch that day
+pub async fn store_target(pool: &SqlitePool, date: &str, target: &AnalystTarget) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT OR REPLACE INTO analyst_targets (
+            ticker, date, name, currency, price, target_consensus, target_median,
+            target_high, target_low, strong_buy, buy, hold, sell, strong_sell, consensus
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&target.ticker)
+    .bind(date)
+    .bind(&target.name)
+    .bind(&target.currency)
+    .bind(target.price)
+    .bind(target.target_consensus)
+    .bind(target.target_median)
+    .bind(target.target_high)
+    .bind(target.target_low)
+    .bind(target.strong_buy)
+    .bind(target.buy)
+    .bind(target.hold)
+    .bind(target.sell)
+    .bind(target.strong_sell)
+    .bind(&target.consensus)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Latest fetch date on or before `date`, or the latest overall
+async fn fetch_date(pool: &SqlitePool, date: Option<&str>) -> Result<Option<String>> {
+    let bound = match date {
+        Some(date) => NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map_err(|e| anyhow::anyhow!("Invalid date format. Use YYYY-MM-DD: {}", e))?
+            .format("%Y-%m-%d")
+            .to_string(),
+        None => "9999-12-31".to_string(),
+    };
+    Ok(
+        sqlx::query_scalar("SELECT MAX(date) FROM analyst_targets WHERE date <= ?")
+            .bind(bound)
+            .fetch_one(pool)
+            .await?,
+    )
+}
+
+/// Stored consensus of one fetch date, highest upside first
+pub async fn load_targets(pool: &SqlitePool, date: &str) -> Result<Vec<AnalystTarget>> {
+    let rows = sqlx::query("SELECT * FROM analyst_targets WHERE date = ?")
+        .bind(date)
+        .fetch_all(pool)
+        .await?;
+    let mut targets: Vec<AnalystTarget> = rows
+        .into_iter()
+        .map(|row| AnalystTarget {
+            ticker: row.get("ticker"),
+            name: row.get("name"),
+            currency: row.get("currency"),
+            price: row.get("price"),
+            target_consensus: row.get("target_consensus"),
+            target_median: row.get("target_median"),
+            target_high: row.get("target_high"),
+            target_low: row.get("target_low"),
+            strong_buy: row.get("strong_buy"),
+            buy: row.get("buy"),
+            hold: row.get("hold"),
+            sell: row.get("sell"),
+            strong_sell: row.get("strong_sell"),
+            consensus: row.get("consensus"),
+        })
+        .collect();
+    targets.sort_by(|a, b| {
+        let upside = |t: &AnalystTarget| t.upside_pct().unwrap_or(f64::NEG_INFINITY);
+        upside(b)
+            .total_cmp(&upside(a))
+            .then_with(|| a.ticker.cmp(&b.ticker))
+    });
+    Ok(targets)
+}
+
+fn optional(value: Option<f64>) -> String {
+    value.map(|v| format!("{:.2}", v)).unwrap_or_default()
+}
+
+fn format_pct(value: Option<f64>) -> String {
+    value
+        .map(|v| format!("{:+.1}%", v))
+        .unwrap_or_else(|| "N/A".to_string())
+}
+
+fn write_csv(path: &Path, targets: &[AnalystTarget]) -> Result<()> {
+    let mut writer = Writer::from_path(path)?;
+    writer.write_record([
+        "Ticker",
+        "Name",
+        "Currency",
+        "Price",
+        "Target Consensus",
+        "Target Median",
+        "Target High",
+        "Target Low",
+        "Upside (%)",
+        "Buy",
+        "Hold",
+        "Sell",
+        "Consensus",
+    ])?;
+    for target in targets {
+        let (buy, hold, sell) = target.ratings();
+        writer.write_record([
+            target.ticker.clone(),
+            target.name.clone(),
+            target.currency.clone().unwrap_or_default(),
+            optional(target.price),
+            optional(target.target_consensus),
+            optional(target.target_median),
+            optional(target.target_high),
+            optional(target.target_low),
+            optional(target.upside_pct()),
+            buy.to_string(),
+            hold.to_string(),
+            sell.to_string(),
+            target.consensus.clone().unwrap_or_default(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_markdown(
+    path: &Path,
+    targets: &[AnalystTarget],
+    groups: &[GroupSummary],
+    date: &str,
+) -> Result<()> {
+    let mut file = File::create(path)?;
+    writeln!(file, "# Analyst Summary: {}", date)?;
+    writeln!(file)?;
+    writeln!(file, "- Companies with analyst data: {}", targets.len())?;
+    writeln!(
+        file,
+        "- Median upside to the consensus target: {}",
+        format_pct(utils::median(
+            &targets
+                .iter()
+                .filter_map(AnalystTarget::upside_pct)
+                .collect::<Vec<_>>()
+        ))
+    )?;
+    writeln!(file)?;
+
+    writeln!(file, "## Peer Groups")?;
+    writeln!(file)?;
+    writeln!(
+        file,
+        "| Peer Group | Covered | Median Upside | Average Upside | Buy | Hold | Sell |"
+    )?;
+    writeln!(
+        file,
+        "|------------|--------:|--------------:|---------------:|----:|-----:|-----:|"
+    )?;
+    for group in groups {
+        writeln!(
+            file,
+            "| {} | {}/{} | {} | {} | {} | {} | {} |",
+            group.group,
+            group.covered,
+            group.members,
+            format_pct(group.median_upside_pct),
+            format_pct(group.average_upside_pct),
+            group.buy,
+            group.hold,
+            group.sell
+        )?;
+    }
+    writeln!(file)?;
+
+    writeln!(file, "## Companies")?;
+    writeln!(file)?;
+    writeln!(
+        file,
+        "| Ticker | Name | Price | Target | Upside | Buy/Hold/Sell | Consensus |"
+    )?;
+    writeln!(
+        file,
+        "|--------|------|------:|-------:|-------:|:-------------:|-----------|"
+    )?;
+    for target in targets {
+        let (buy, hold, sell) = target.ratings();
+        let currency = target.currency.as_deref().unwrap_or_default();
+        writeln!(
+            file,
+            "| {} | {} | {} {} | {} {} | {} | {}/{}/{} | {} |",
+            target.ticker,
+            target.name,
+            optional(target.price),
+            currency,
+            optional(target.target_consensus),
+            currency,
+            format_pct(target.upside_pct()),
+            buy,
+            hold,
+            sell,
+            target.consensus.as_deref().unwrap_or_default()
+        )?;
+    }
+    writeln!(file)?;
+
+    writeln!(file, "---")?;
+    writeln!(
+        file,
+        "*Generated on {}*",
+        clock::now().format("%Y-%m-%d %H:%M:%S")
+    )?;
+    Ok(())
+}
+
+/// Consensus target versus price per company and peer group, as CSV and markdown
+pub async fn analyst_summary(pool: &SqlitePool, date: Option<&str>) -> Result<()> {
+    let Some(date) = fetch_date(pool, date).await? else {
+        anyhow::bail!(
+            "No analyst data stored for {}; fetch it with export-combined --with-analyst",
+            date.unwrap_or("any date")
+        );
+    };
+    let targets = load_targets(pool, &date).await?;
+    let groups = by_peer_group(
+        &targets,
+        &advanced_comparisons::get_predefined_peer_groups(),
+    );
+
+    println!(
+        "📊 Analyst consensus fetched on {} ({} companies):",
+        date,
+        targets.len()
+    );
+    for group in &groups {
+        println!(
+            "  {:<20} {:>2}/{:<2} covered  median upside {:>8}  buy/hold/sell {}/{}/{}",
+            group.group,
+            group.covered,
+            group.members,
+            format_pct(group.median_upside_pct),
+            group.buy,
+            group.hold,
+            group.sell
+        );
+    }
+
+    let output = config::load_output_config();
+    output.ensure_directory()?;
+    let stamp = OutputConfig::timestamp();
+    let csv_path = output.file_path_at("analyst_summary", &date, &stamp, "csv");
+    write_csv(&csv_path, &targets)?;
+    println!("✅ Analyst targets exported to {}", csv_path.display());
+
+    let md_path = output.file_path_at(
+        "analyst_summary",
+        &format!("{}_summary", date),
+        &stamp,
+        "md",
+    );
+    write_markdown(&md_path, &targets, &groups, &date)?;
+    println!("✅ Analyst summary exported to {}", md_path.display());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    fn target(ticker: &str, price: Option<f64>, consensus: Option<f64>) -> AnalystTarget {
+        AnalystTarget {
+            ticker: ticker.to_string(),
+            name: format!("{} Inc", ticker),
+            currency: Some("EUR".to_string()),
+            price,
+            target_consensus: consensus,
+            buy: Some(3),
+            hold: Some(2),
+            strong_sell: Some(1),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_upside_and_ratings() {
+        let t = target("MC.PA", Some(500.0), Some(600.0));
+        assert!((t.upside_pct().unwrap() - 20.0).abs() < 1e-9);
+        assert_eq!(t.ratings(), (3, 2, 1));
+        assert_eq!(target("MC.PA", None, Some(600.0)).upside_pct(), None);
+        assert_eq!(target("MC.PA", Some(0.0), Some(600.0)).upside_pct(), None);
+    }
+
+    #[test]
+    fn test_by_peer_group() {
+        let groups = vec![
+            PeerGroup {
+                name: "Luxury".to_string(),
+                description: None,
+                tickers: vec!["MC.PA".into(), "RMS.PA".into(), "KER.PA".into()],
+            },
+            PeerGroup {
+                name: "Sportswear".to_string(),
+                description: None,
+                tickers: vec!["NKE".into()],
+            },
+        ];
+        let summary = by_peer_group(
+            &[
+                target("MC.PA", Some(100.0), Some(110.0)),
+                target("RMS.PA", Some(100.0), Some(130.0)),
+                target("KER.PA", Some(100.0), None),
+            ],
+            &groups,
+        );
+
+        assert_eq!(summary[0].covered, 3);
+        assert_eq!(summary[0].members, 3);
+        assert!((summary[0].median_upside_pct.unwrap() - 20.0).abs() < 1e-9);
+        assert!((summary[0].average_upside_pct.unwrap() - 20.0).abs() < 1e-9);
+        assert_eq!(
+            (summary[0].buy, summary[0].hold, summary[0].sell),
+            (9, 6, 3)
+        );
+        assert_eq!(summary[1].covered, 0);
+        assert_eq!(summary[1].median_upside_pct, None);
+    }
+
+    #[tokio::test]
+    async fn test_store_and_load_latest_fetch() {
+        let pool = db::create_db_pool("sqlite::memory:").await.unwrap();
+        for (date, t) in [
+            ("2025-12-01", target("NKE", Some(60.0), Some(90.0))),
+            ("2025-12-08", target("NKE", Some(80.0), Some(88.0))),
+            ("2025-12-08", target("MC.PA", Some(500.0), Some(650.0))),
+            ("2025-12-08", target("BOO.L", Some(20.0), None)),
+        ] {
+            store_target(&pool, date, &t).await.unwrap();
+        }
+
+        assert_eq!(
+            fetch_date(&pool, None).await.unwrap().as_deref(),
+            Some("2025-12-08")
+        );
+        assert_eq!(
+            fetch_date(&pool, Some("2025-12-05"))
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("2025-12-01")
+        );
+        assert_eq!(fetch_date(&pool, Some("2025-11-30")).await.unwrap(), None);
+
+        let targets = load_targets(&pool, "2025-12-08").await.unwrap();
+        let tickers: Vec<&str> = targets.iter().map(|t| t.ticker.as_str()).collect();
+        assert_eq!(tickers, vec!["MC.PA", "NKE", "BOO.L"]);
+        assert_eq!(targets[1], target("NKE", Some(80.0), Some(88.0)));
+    }
+}
@@ -4,19 +4,79 @@
 
 use anyhow::{Context, Result};
 use chrono::{DateTime, NaiveDate, Utc};
-use reqwest::Client;
+use reqwest::{Client, RequestBuilder, StatusCode};
 use serde::Deserialize;
 use serde_json::{self, Value};
 use std::collections::HashMap;
 use std::sync::Arc;
-use std::{env, time::Duration};
-use tokio::sync::Semaphore;
-use tokio::time::sleep;
+use std::time::Duration;
 
+use crate::api_cache::{self, Validators};
+use crate::api_usage;
+use crate::circuit_breaker;
 use crate::currencies::convert_currency;
+use crate::error::Error;
+use crate::fmp_keys::{self, FmpKeys};
+use crate::identifiers;
+use crate::metrics;
 use crate::models::{
-    Details, FMPCompanyProfile, FMPExecutive, FMPIncomeStatement, FMPRatios, PolygonResponse,
+    Details, FMPCompanyProfile, FMPExecutive, FMPIncomeStatement, FMPQuote, FMPRatios,
+    PolygonResponse,
 };
+use crate::rate_limit::{self, RateLimiter};
+use crate::retry;
+
+/// Entry of the FMP delisted-companies list
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct DelistedCompany {
+    pub symbol: String,
+    #[serde(rename = "companyName")]
+    pub company_name: Option<String>,
+    pub exchange: Option<String>,
+    #[serde(rename = "ipoDate")]
+    pub ipo_date: Option<String>,
+    #[serde(rename = "delistedDate")]
+    pub delisted_date: Option<String>,
+}
+
+/// Consensus of the analysts' price targets for a ticker (FMP v4)
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PriceTargetConsensus {
+    pub symbol: String,
+    pub target_high: Option<f64>,
+    pub target_low: Option<f64>,
+    pub target_consensus: Option<f64>,
+    pub target_median: Option<f64>,
+}
+
+/// Number of analysts per rating for a ticker, with FMP's consensus label
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RatingConsensus {
+    pub symbol: String,
+    pub strong_buy: Option<i64>,
+    pub buy: Option<i64>,
+    pub hold: Option<i64>,
+    pub sell: Option<i64>,
+    pub strong_sell: Option<i64>,
+    pub consensus: Option<String>,
+}
+
+/// Entry of the FMP earnings calendar; past reports carry the actual figures
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct EarningsCalendarEntry {
+    pub symbol: String,
+    pub date: NaiveDate,
+    pub eps: Option<f64>,
+    pub eps_estimated: Option<f64>,
+    /// "bmo" (before market open) or "amc" (after market close)
+    pub time: Option<String>,
+    pub revenue: Option<f64>,
+    pub revenue_estimated: Option<f64>,
+    pub fiscal_date_ending: Option<String>,
+}
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct SymbolChange {
@@ -28,94 +88,238 @@ pub struct SymbolChange {
     pub name: Option<String>,
 }
 
+/// HTTP client with the connect and request timeouts of `[api]`, so a
+/// hanging response fails instead of stalling the run
+pub(crate) fn http_client() -> Client {
+    let api = crate::config::load_api_config();
+    Client::builder()
+        .connect_timeout(Duration::from_secs(api.connect_timeout_secs))
+        .timeout(Duration::from_secs(api.request_timeout_secs))
+        .build()
+        .expect("Failed to build HTTP client")
+}
+
+/// Tickers per batch `/quote/` request
+pub const QUOTE_BATCH_SIZE: usize = 50;
+
+/// Response of a GET, or the cached body when the server answered
+/// `304 Not Modified`
+struct Fetched {
+    status: StatusCode,
+    text: String,
+    validators: Validators,
+    /// Wait asked for by a `Retry-After` header
+    retry_after: Option<Duration>,
+}
+
+/// Send `request` for `url`. With `use_cache`, when an expired cached
+/// response with an `ETag` or `Last-Modified` exists the request is made
+/// conditional, and a `304 Not Modified` answer returns the cached body. The
+/// client accepts gzip, brotli and deflate; the decompressed payload size is
+/// added to the API usage.
+async fn send_revalidating(request: RequestBuilder, url: &str, use_cache: bool) -> Result<Fetched> {
+    let stale = match use_cache {
+        true => api_cache::get_revalidatable(url).await,
+        false => None,
+    };
+    let request = match &stale {
+        Some(stale) => request.headers(stale.validators.conditional_headers()),
+        None => request,
+    };
+    let response = request
+        .send()
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to send request: {}", e))?;
+    let status = response.status();
+    let validators = Validators::from_headers(response.headers());
+    let retry_after = retry::retry_after(response.headers());
+
+    if status == StatusCode::NOT_MODIFIED
+        && let Some(stale) = stale
+    {
+        api_usage::record_not_modified(url);
+        return Ok(Fetched {
+            status: StatusCode::OK,
+            text: stale.body,
+            // A 304 may omit the validators it confirmed
+            validators: if validators.is_empty() {
+                stale.validators
+            } else {
+                validators
+            },
+            retry_after,
+        });
+    }
+
+    let text = response
+        .text()
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to get response text: {}", e))?;
+    api_usage::record_payload(url, text.len());
+    Ok(Fetched {
+        status,
+        text,
+        validators,
+        retry_after,
+    })
+}
+
 pub struct PolygonClient {
     client: Client,
     api_key: String,
 }
 
+/// Daily bar of the Polygon aggregates endpoint
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct PolygonBar {
+    /// Close price
+    #[serde(rename = "c")]
+    pub close: f64,
+    /// Start of the bar, in milliseconds since the epoch
+    #[serde(rename = "t")]
+    pub timestamp_ms: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct PolygonAggregates {
+    /// Missing when there are no bars in the range
+    #[serde(default)]
+    results: Vec<PolygonBar>,
+}
+
 #[derive(Clone)]
 pub struct FMPClient {
     client: Client,
     api_key: String,
-    rate_limiter: Arc<Semaphore>,
+    /// Keys requests are actually sent with, from `FMP_API_KEYS` and `api_key`
+    keys: Arc<FmpKeys>,
+    rate_limiter: Arc<RateLimiter>,
 }
 
 impl FMPClient {
     pub fn new(api_key: String) -> Self {
-        // Allow up to 300 concurrent requests per minute
-        let rate_limiter = Arc::new(Semaphore::new(300));
-
         Self {
-            client: Client::new(),
+            client: http_client(),
+            keys: Arc::new(FmpKeys::from_env(api_key.clone())),
             api_key,
-            rate_limiter,
+            rate_limiter: rate_limit::fmp_limiter(),
         }
     }
 
+    /// Client for the keys in the environment: `FINANCIALMODELINGPREP_API_KEY`
+    /// and/or `FMP_API_KEYS`
+    pub fn from_env() -> Result<Self> {
+        let api_key = fmp_keys::primary_key().with_context(|| {
+            format!(
+                "{} or {} must be set",
+                fmp_keys::PRIMARY_ENV,
+                fmp_keys::KEYS_ENV
+            )
+        })?;
+        Ok(Self::new(api_key))
+    }
+
     async fn make_request<T: for<'de> Deserialize<'de>>(&self, url: String) -> Result<T> {
-        let mut retries = 0;
-        let max_retries = 3;
-        let mut delay = Duration::from_secs(5);
+        self.request(url, true).await
+    }
 
-        loop {
-            // Wait for rate limit permit
-            let _permit = self.rate_limiter.acquire().await.unwrap();
-
-            // Helper to schedule permit release after 200ms (ensures permits are always released)
-            let schedule_permit_release = || {
-                let rate_limiter = self.rate_limiter.clone();
-                tokio::spawn(async move {
-                    sleep(Duration::from_millis(200)).await;
-                    rate_limiter.add_permits(1);
-                });
-            };
+    /// Request `url`; without `use_cache` neither today's cached response
+    /// nor a stale one to revalidate is used, but the answer is still cached
+    async fn request<T: for<'de> Deserialize<'de>>(
+        &self,
+        url: String,
+        use_cache: bool,
+    ) -> Result<T> {
+        let mut attempts = retry::Attempts::default();
+
+        // Reuse today's response for the same request when cached
+        if use_cache
+            && let Some(text) = api_cache::get(&url).await
+            && let Ok(result) = serde_json::from_str::<T>(&text)
+        {
+            return Ok(result);
+        }
 
-            let response = match self.client.get(&url).send().await {
-                Ok(resp) => resp,
-                Err(e) => {
-                    schedule_permit_release();
-                    return Err(anyhow::anyhow!("Failed to send request: {}", e));
-                }
-            };
+        // Keys rejected with a rate limit since the last backoff
+        let mut tried_keys = Vec::new();
+
+        loop {
+            circuit_breaker::check(&url)?;
+            // Wait for a token from the shared limiter
+            circuit_breaker::excluded_from_deadline(self.rate_limiter.acquire()).await;
+            let key_index = self.keys.active();
+            let key_label = fmp_keys::key_label(self.keys.key(key_index));
+            let request_url = fmp_keys::with_api_key(&url, self.keys.key(key_index));
+            api_usage::record_request(&url);
+            api_usage::record_key_request(&key_label);
 
             // Get the response text first to log in case of error
-            let text = match response.text().await {
-                Ok(t) => t,
-                Err(e) => {
-                    schedule_permit_release();
-                    return Err(anyhow::anyhow!("Failed to get response text: {}", e));
+            let fetched =
+                match send_revalidating(self.client.get(&request_url), &url, use_cache).await {
+                    Ok(fetched) if !fetched.status.is_server_error() => Ok(fetched),
+                    Ok(fetched) => {
+                        metrics::record_api_error(&url, "status");
+                        Err(format!("API error: {}", fetched.status))
+                    }
+                    Err(e) => {
+                        metrics::record_api_error(&url, "request");
+                        Err(e.to_string())
+                    }
+                };
+            let Fetched {
+                status,
+                text,
+                validators,
+                retry_after,
+            } = match fetched {
+                Ok(fetched) => fetched,
+                Err(failure) => {
+                    circuit_breaker::record_failure(&url);
+                    if attempts.retry(&url, &failure).await {
+                        continue;
+                    }
+                    anyhow::bail!("{} (after {} retries)", failure, attempts.retries());
                 }
             };
+            circuit_breaker::record_success(&url);
 
             // Check for rate limit error
-            if text.contains("Limit Reach") {
-                // Release permit before retry
-                schedule_permit_release();
-
-                if retries >= max_retries {
-                    return Err(anyhow::anyhow!(
-                        "Rate limit reached after {} retries",
-                        max_retries
-                    ));
+            if status == StatusCode::TOO_MANY_REQUESTS || text.contains("Limit Reach") {
+                self.rate_limiter.record_rejection();
+                api_usage::record_key_rate_limit(&key_label);
+                metrics::record_api_error(&url, "rate_limit");
+
+                // Fail over to a key whose quota may not be used up yet
+                tried_keys.push(key_index);
+                if let Some(next) = self.keys.rotate(key_index, &tried_keys) {
+                    api_usage::record_rate_limit_hit(&url, true);
+                    eprintln!(
+                        "Rate limit hit for FMP key {}. Switching to key {}...",
+                        key_label,
+                        fmp_keys::key_label(self.keys.key(next))
+                    );
+                    continue;
                 }
-                eprintln!(
-                    "Rate limit hit for {}. Retrying in {} seconds...",
-                    url,
-                    delay.as_secs()
-                );
-                sleep(delay).await;
-                delay *= 2; // Exponential backoff
-                retries += 1;
-                continue;
+                tried_keys.clear();
+
+                // The retry itself is counted by `retry`
+                api_usage::record_rate_limit_hit(&url, false);
+                if attempts.retry_rate_limited(&url, retry_after).await {
+                    continue;
+                }
+                return Err(Error::RateLimited {
+                    retries: attempts.retries(),
+                }
+                .into());
             }
 
             match serde_json::from_str::<T>(&text) {
                 Ok(result) => {
-                    schedule_permit_release();
+                    api_cache::put(&url, &text, &validators).await;
                     return Ok(result);
                 }
                 Err(e) => {
-                    schedule_permit_release();
+                    metrics::record_api_error(&url, "parse");
                     eprintln!("Failed to parse response for URL {}: {}", url, e);
                     eprintln!("Response text: {}", text);
                     return Err(anyhow::anyhow!("Failed to parse response: {}", e));
@@ -124,6 +328,21 @@ impl FMPClient {
         }
     }
 
+    /// Raw JSON of an FMP endpoint, `path` relative to
+    /// `https://financialmodelingprep.com/api/` (e.g. `v3/profile/NKE`).
+    /// Skips the API cache, so contract checks see what FMP sends now; the
+    /// rate limiter still applies.
+    pub async fn fetch_json(&self, path: &str) -> Result<Value> {
+        let separator = if path.contains('?') { '&' } else { '?' };
+        let url = format!(
+            "https://financialmodelingprep.com/api/{}{}apikey={}",
+            path, separator, self.api_key
+        );
+        self.request(url, false)
+            .await
+            .with_context(|| format!("Failed to fetch {} from FMP API", path))
+    }
+
     pub async fn fetch_symbol_changes(&self) -> Result<Vec<SymbolChange>> {
         let url = format!(
             "https://financialmodelingprep.com/api/v4/symbol_change?apikey={}",
@@ -138,6 +357,65 @@ impl FMPClient {
         Ok(response)
     }
 
+    /// One page of recently delisted companies, most recent first
+    pub async fn fetch_delisted_companies(&self, page: u32) -> Result<Vec<DelistedCompany>> {
+        let url = format!(
+            "https://financialmodelingprep.com/api/v3/delisted-companies?page={}&apikey={}",
+            page, self.api_key
+        );
+
+        let response: Vec<DelistedCompany> = self
+            .make_request(url)
+            .await
+            .context("Failed to fetch delisted companies from FMP API")?;
+
+        Ok(response)
+    }
+
+    /// Earnings reports of all companies between two dates (at most three
+    /// months apart)
+    pub async fn fetch_earnings_calendar(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<EarningsCalendarEntry>> {
+        let url = format!(
+            "https://financialmodelingprep.com/api/v3/earning_calendar?from={}&to={}&apikey={}",
+            from, to, self.api_key
+        );
+
+        let response: Vec<EarningsCalendarEntry> = self
+            .make_request(url)
+            .await
+            .context("Failed to fetch earnings calendar from FMP API")?;
+
+        Ok(response)
+    }
+
+    /// Price target and rating consensus of a ticker; either is `None` when
+    /// no analyst covers it. Costs two requests.
+    pub async fn get_analyst_consensus(
+        &self,
+        ticker: &str,
+    ) -> Result<(Option<PriceTargetConsensus>, Option<RatingConsensus>)> {
+        let targets_url = format!(
+            "https://financialmodelingprep.com/api/v4/price-target-consensus?symbol={}&apikey={}",
+            ticker, self.api_key
+        );
+        let ratings_url = format!(
+            "https://financialmodelingprep.com/api/v4/upgrades-downgrades-consensus?symbol={}&apikey={}",
+            ticker, self.api_key
+        );
+
+        let (targets, ratings) = tokio::try_join!(
+            self.make_request::<Vec<PriceTargetConsensus>>(targets_url),
+            self.make_request::<Vec<RatingConsensus>>(ratings_url)
+        )
+        .context("Failed to fetch analyst consensus from FMP API")?;
+
+        Ok((targets.into_iter().next(), ratings.into_iter().next()))
+    }
+
     pub async fn get_details(
         &self,
         ticker: &str,
@@ -174,7 +452,7 @@ impl FMPClient {
         )?;
 
         if profiles.is_empty() {
-            anyhow::bail!("No data found for ticker");
+            return Err(Error::TickerNotFound(ticker.to_string()).into());
         }
 
         let profile = &profiles[0];
@@ -209,6 +487,23 @@ impl FMPClient {
             revenue_usd: None,
             timestamp: Some(timestamp),
             ceo: ceo_name,
+            country: profile
+                .country
+                .as_deref()
+                .map(str::trim)
+                .filter(|c| !c.is_empty())
+                .map(str::to_uppercase),
+            industry: profile
+                .industry
+                .as_deref()
+                .map(str::trim)
+                .filter(|i| !i.is_empty())
+                .map(str::to_string),
+            isin: profile
+                .isin
+                .as_deref()
+                .and_then(identifiers::normalize_isin),
+            lei: None,
             working_capital_ratio: ratios.as_ref().and_then(|r| r.current_ratio),
             quick_ratio: ratios.as_ref().and_then(|r| r.quick_ratio),
             eps: ratios.as_ref().and_then(|r| r.eps),
@@ -219,7 +514,7 @@ impl FMPClient {
                 let mut map = std::collections::HashMap::new();
                 map.insert(
                     "exchange".to_string(),
-                    Value::String(profile.exchange.clone()),
+                    Value::String(profile.exchange_code()),
                 );
                 map.insert(
                     "price".to_string(),
@@ -240,6 +535,22 @@ impl FMPClient {
         Ok(details)
     }
 
+    /// Quotes of up to `QUOTE_BATCH_SIZE` tickers in one request. Tickers FMP
+    /// doesn't know are left out of the result instead of failing the batch.
+    pub async fn get_batch_quotes(&self, tickers: &[String]) -> Result<Vec<FMPQuote>> {
+        if tickers.is_empty() {
+            return Ok(Vec::new());
+        }
+        let url = format!(
+            "https://financialmodelingprep.com/api/v3/quote/{}?apikey={}",
+            tickers.join(","),
+            self.api_key
+        );
+        self.make_request(url)
+            .await
+            .context("Failed to fetch batch quotes from FMP API")
+    }
+
     pub async fn get_historical_market_cap(
         &self,
         ticker: &str,
@@ -273,7 +584,7 @@ impl FMPClient {
                     name: profile.company_name.clone(),
                     market_cap_original: market_cap,
                     original_currency: profile.currency.clone(), // Use actual currency from profile
-                    exchange: profile.exchange.clone(),
+                    exchange: profile.exchange_code(),
                     price,
                 });
             }
@@ -304,13 +615,13 @@ impl FMPClient {
                     name: profile.company_name.clone(),
                     market_cap_original: market_cap,
                     original_currency: profile.currency.clone(), // Use actual currency from profile
-                    exchange: profile.exchange.clone(),
+                    exchange: profile.exchange_code(),
                     price,
                 });
             }
         }
 
-        anyhow::bail!("No market cap data found for ticker {}", ticker)
+        Err(Error::TickerNotFound(ticker.to_string()).into())
     }
 
     pub async fn get_exchange_rates(&self) -> Result<Vec<ExchangeRate>> {
@@ -319,22 +630,7 @@ impl FMPClient {
             self.api_key
         );
 
-        let response = self
-            .client
-            .get(&url)
-            .send()
-            .await
-            .context("Failed to send request to FMP forex API")?;
-
-        if !response.status().is_success() {
-            anyhow::bail!("API request failed with status: {}", response.status());
-        }
-
-        let rates: Vec<ExchangeRate> = response
-            .json()
-            .await
-            .context("Failed to parse forex rates response")?;
-        Ok(rates)
+        self.make_request(url).await
     }
 
     /// Fetch historical exchange rates for a specific currency pair within a date range
@@ -382,56 +678,126 @@ impl FMPClient {
 impl PolygonClient {
     pub fn new(api_key: String) -> Self {
         Self {
-            client: Client::new(),
+            client: http_client(),
             api_key,
         }
     }
 
-    pub async fn get_details(&self, ticker: &str, date: NaiveDate) -> Result<Details> {
-        if ticker.is_empty() {
-            anyhow::bail!("ticker empty");
+    async fn make_request<T: for<'de> Deserialize<'de>>(&self, url: String) -> Result<T> {
+        if let Some(text) = api_cache::get(&url).await
+            && let Ok(result) = serde_json::from_str::<T>(&text)
+        {
+            return Ok(result);
         }
 
-        let url = format!(
-            "https://api.polygon.io/v3/reference/tickers/{}?date={}",
-            ticker,
-            date.format("%Y-%m-%d")
-        );
-
-        let response = self
-            .client
-            .get(&url)
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .send()
-            .await
-            .context("Failed to send request")?;
-
-        let status = response.status();
-        let text = response
-            .text()
-            .await
-            .context("Failed to get response text")?;
+        let mut attempts = retry::Attempts::default();
+        let Fetched {
+            status,
+            text,
+            validators,
+            ..
+        } = loop {
+            circuit_breaker::check(&url)?;
+            api_usage::record_request(&url);
+            let request = self
+                .client
+                .get(&url)
+                .header("Authorization", format!("Bearer {}", self.api_key));
+            let failure = match send_revalidating(request, &url, true).await {
+                Ok(fetched) if !retry::is_retryable(fetched.status) => {
+                    circuit_breaker::record_success(&url);
+                    break fetched;
+                }
+                Ok(fetched) if fetched.status == StatusCode::TOO_MANY_REQUESTS => {
+                    // Rate limited, but the endpoint answered
+                    circuit_breaker::record_success(&url);
+                    metrics::record_api_error(&url, "rate_limit");
+                    api_usage::record_rate_limit_hit(&url, false);
+                    if attempts.retry_rate_limited(&url, fetched.retry_after).await {
+                        continue;
+                    }
+                    format!("API error: {} - {}", fetched.status, fetched.text)
+                }
+                Ok(fetched) => {
+                    metrics::record_api_error(&url, "status");
+                    circuit_breaker::record_failure(&url);
+                    let failure = format!("API error: {} - {}", fetched.status, fetched.text);
+                    if attempts.retry(&url, &failure).await {
+                        continue;
+                    }
+                    failure
+                }
+                Err(e) => {
+                    metrics::record_api_error(&url, "request");
+                    circuit_breaker::record_failure(&url);
+                    let failure = e.to_string();
+                    if attempts.retry(&url, &failure).await {
+                        continue;
+                    }
+                    failure
+                }
+            };
+            anyhow::bail!("{} (after {} retries)", failure, attempts.retries());
+        };
 
         if !status.is_success() {
+            metrics::record_api_error(&url, "status");
             anyhow::bail!("API error: {} - {}", status, text);
         }
 
         // Try to parse the response, if it fails, print the raw response for debugging
-        match serde_json::from_str::<PolygonResponse>(&text) {
-            Ok(polygon_response) => Ok(polygon_response.results),
+        match serde_json::from_str::<T>(&text) {
+            Ok(result) => {
+                api_cache::put(&url, &text, &validators).await;
+                Ok(result)
+            }
             Err(e) => {
+                metrics::record_api_error(&url, "parse");
                 eprintln!("Failed to parse response: {}", e);
                 eprintln!("Raw response: {}", text);
                 Err(e).context("Failed to parse response")
             }
         }
     }
+
+    pub async fn get_details(&self, ticker: &str, date: NaiveDate) -> Result<Details> {
+        if ticker.is_empty() {
+            anyhow::bail!("ticker empty");
+        }
+
+        let url = format!(
+            "https://api.polygon.io/v3/reference/tickers/{}?date={}",
+            ticker,
+            date.format("%Y-%m-%d")
+        );
+        let response: PolygonResponse = self.make_request(url).await?;
+        Ok(response.results)
+    }
+
+    /// Split-adjusted daily bars of a ticker from `from` to `to`, latest first
+    pub async fn get_daily_bars(
+        &self,
+        ticker: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<PolygonBar>> {
+        if ticker.is_empty() {
+            anyhow::bail!("ticker empty");
+        }
+
+        let url = format!(
+            "https://api.polygon.io/v2/aggs/ticker/{}/range/1/day/{}/{}?adjusted=true&sort=desc",
+            ticker,
+            from.format("%Y-%m-%d"),
+            to.format("%Y-%m-%d")
+        );
+        let response: PolygonAggregates = self.make_request(url).await?;
+        Ok(response.results)
+    }
 }
 
 pub async fn get_details_eu(ticker: &str, rate_map: &HashMap<String, f64>) -> Result<Details> {
-    let api_key = env::var("FINANCIALMODELINGPREP_API_KEY")
-        .expect("FINANCIALMODELINGPREP_API_KEY must be set");
-    let client = FMPClient::new(api_key);
+    let client = FMPClient::from_env()?;
     client.get_details(ticker, rate_map).await
 }
 
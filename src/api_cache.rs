@@ -0,0 +1,382 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! SQLite-backed cache of raw API responses
+//!
+//! Responses are keyed by request URL (with the API key removed) and UTC day, so
+//! re-running a command on the same day reuses profiles, ratios and quotes
+//! instead of spending API quota. Disabled with `--no-cache`.
+//!
+//! The `ETag` and `Last-Modified` headers of a response are stored with it.
+//! Once the entry has expired it is kept for [`REVALIDATE_DAYS`] so the next
+//! request can send them back as `If-None-Match` / `If-Modified-Since`; a
+//! `304 Not Modified` answer reuses the stored body instead of downloading it.
+
+use anyhow::Result;
+use chrono::Utc;
+use reqwest::header::{self, HeaderMap, HeaderValue};
+use sqlx::Row;
+use sqlx::sqlite::SqlitePool;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub struct ApiCache {
+    pool: SqlitePool,
+    ttl_hours: i64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+static API_CACHE: OnceLock<ApiCache> = OnceLock::new();
+
+/// Days an expired entry with validators is kept for revalidation
+pub const REVALIDATE_DAYS: i64 = 30;
+
+/// `ETag` and `Last-Modified` of a response
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let get = |name| {
+            headers
+                .get(name)
+                .and_then(|v: &HeaderValue| v.to_str().ok())
+                .map(str::to_string)
+        };
+        Self {
+            etag: get(header::ETAG),
+            last_modified: get(header::LAST_MODIFIED),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+
+    /// Conditional request headers for revalidating the response
+    pub fn conditional_headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if let Some(value) = self.etag.as_deref().and_then(|v| v.parse().ok()) {
+            headers.insert(header::IF_NONE_MATCH, value);
+        }
+        if let Some(value) = self.last_modified.as_deref().and_then(|v| v.parse().ok()) {
+            headers.insert(header::IF_MODIFIED_SINCE, value);
+        }
+        headers
+    }
+}
+
+/// Stored response that can be revalidated with a conditional request
+#[derive(Debug, Clone, PartialEq)]
+pub struct Revalidatable {
+    pub body: String,
+    pub validators: Validators,
+}
+
+/// Cache key for a URL: the URL without its `apikey` query parameter
+pub fn cache_key(url: &str) -> String {
+    let Some((base, query)) = url.split_once('?') else {
+        return url.to_string();
+    };
+    let params: Vec<&str> = query
+        .split('&')
+        .filter(|p| !p.is_empty() && !p.starts_with("apikey="))
+        .collect();
+    if params.is_empty() {
+        base.to_string()
+    } else {
+        format!("{}?{}", base, params.join("&"))
+    }
+}
+
+/// Error payloads are never cached
+fn is_cacheable(body: &str) -> bool {
+    !body.contains("Error Message") && !body.contains("Limit Reach")
+}
+
+impl ApiCache {
+    pub fn new(pool: SqlitePool, ttl_hours: i64) -> Self {
+        Self {
+            pool,
+            ttl_hours,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    fn today() -> String {
+        Utc::now().format("%Y-%m-%d").to_string()
+    }
+
+    /// Cached body for `url` fetched today and within the TTL
+    pub async fn get(&self, url: &str) -> Result<Option<String>> {
+        let oldest = Utc::now().timestamp() - self.ttl_hours * 3600;
+        let body: Option<String> = sqlx::query_scalar(
+            "SELECT body FROM api_cache WHERE url = ? AND day = ? AND fetched_at >= ?",
+        )
+        .bind(cache_key(url))
+        .bind(Self::today())
+        .bind(oldest)
+        .fetch_optional(&self.pool)
+        .await?;
+        let counter = if body.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        Ok(body)
+    }
+
+    /// Latest stored body for `url` that came with validators, whatever its age
+    pub async fn get_revalidatable(&self, url: &str) -> Result<Option<Revalidatable>> {
+        let row = sqlx::query(
+            r#"
+            SELECT body, etag, last_modified FROM api_cache
+            WHERE url = ? AND (etag IS NOT NULL OR last_modified IS NOT NULL)
+            ORDER BY fetched_at DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(cache_key(url))
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.map(|row| Revalidatable {
+            body: row.get("body"),
+            validators: Validators {
+                etag: row.get("etag"),
+                last_modified: row.get("last_modified"),
+            },
+        }))
+    }
+
+    /// Store today's response for `url`, replacing those of earlier days
+    pub async fn put(&self, url: &str, body: &str, validators: &Validators) -> Result<()> {
+        if !is_cacheable(body) {
+            return Ok(());
+        }
+        let key = cache_key(url);
+        let today = Self::today();
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            r#"
+            INSERT OR REPLACE INTO api_cache (url, day, body, fetched_at, etag, last_modified)
+            VALUES (?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&key)
+        .bind(&today)
+        .bind(body)
+        .bind(Utc::now().timestamp())
+        .bind(&validators.etag)
+        .bind(&validators.last_modified)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM api_cache WHERE url = ? AND day <> ?")
+            .bind(&key)
+            .bind(&today)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Delete entries older than the TTL, returning how many were removed.
+    /// Entries with validators are kept for [`REVALIDATE_DAYS`].
+    pub async fn prune(&self) -> Result<u64> {
+        let now = Utc::now().timestamp();
+        let oldest = now - self.ttl_hours * 3600;
+        let oldest_revalidatable = oldest.min(now - REVALIDATE_DAYS * 86400);
+        let result = sqlx::query(
+            r#"
+            DELETE FROM api_cache
+            WHERE fetched_at < ?
+              AND ((etag IS NULL AND last_modified IS NULL) OR fetched_at < ?)
+            "#,
+        )
+        .bind(oldest)
+        .bind(oldest_revalidatable)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+}
+
+/// Enable the process-wide cache and drop expired entries
+pub async fn init(pool: &SqlitePool, ttl_hours: i64) -> Result<()> {
+    let cache = API_CACHE.get_or_init(|| ApiCache::new(pool.clone(), ttl_hours));
+    cache.prune().await?;
+    Ok(())
+}
+
+/// Cached response for `url`, if the cache is enabled and has one.
+/// Cache errors are reported and treated as a miss.
+pub async fn get(url: &str) -> Option<String> {
+    let cache = API_CACHE.get()?;
+    match cache.get(url).await {
+        Ok(body) => body,
+        Err(e) => {
+            eprintln!("⚠️  API cache lookup failed: {}", e);
+            None
+        }
+    }
+}
+
+/// Expired response for `url` to revalidate, if the cache is enabled and
+/// has one with validators. Cache errors are reported and treated as none.
+pub async fn get_revalidatable(url: &str) -> Option<Revalidatable> {
+    let cache = API_CACHE.get()?;
+    match cache.get_revalidatable(url).await {
+        Ok(entry) => entry,
+        Err(e) => {
+            eprintln!("⚠️  API cache lookup failed: {}", e);
+            None
+        }
+    }
+}
+
+/// Store a successful response, if the cache is enabled
+pub async fn put(url: &str, body: &str, validators: &Validators) {
+    if let Some(cache) = API_CACHE.get()
+        && let Err(e) = cache.put(url, body, validators).await
+    {
+        eprintln!("⚠️  API cache write failed: {}", e);
+    }
+}
+
+/// Print cache hits for this run, if the cache was used
+pub fn print_stats() {
+    let Some(cache) = API_CACHE.get() else {
+        return;
+    };
+    let hits = cache.hits.load(Ordering::Relaxed);
+    let misses = cache.misses.load(Ordering::Relaxed);
+    if hits + misses > 0 {
+        println!(
+            "💾 API cache: {} hits, {} misses (disable with --no-cache)",
+            hits, misses
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    #[test]
+    fn test_cache_key_strips_api_key() {
+        assert_eq!(
+            cache_key("https://example.com/api/v3/profile/NKE?apikey=secret"),
+            "https://example.com/api/v3/profile/NKE"
+        );
+        assert_eq!(
+            cache_key("https://example.com/x?limit=1&apikey=secret&page=2"),
+            "https://example.com/x?limit=1&page=2"
+        );
+        assert_eq!(cache_key("https://example.com/x"), "https://example.com/x");
+    }
+
+    #[tokio::test]
+    async fn test_cache_roundtrip_and_ttl() {
+        let pool = db::create_db_pool("sqlite::memory:").await.unwrap();
+        let cache = ApiCache::new(pool.clone(), 24);
+        let url = "https://example.com/api/v3/profile/NKE?apikey=secret";
+
+        assert_eq!(cache.get(url).await.unwrap(), None);
+        cache
+            .put(url, r#"[{"symbol":"NKE"}]"#, &Validators::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            cache.get(url).await.unwrap().as_deref(),
+            Some(r#"[{"symbol":"NKE"}]"#)
+        );
+        // Same request with another key hits the same entry
+        assert!(
+            cache
+                .get("https://example.com/api/v3/profile/NKE?apikey=other")
+                .await
+                .unwrap()
+                .is_some()
+        );
+        assert_eq!(cache.hits.load(Ordering::Relaxed), 2);
+        assert_eq!(cache.misses.load(Ordering::Relaxed), 1);
+
+        // Error payloads are not stored
+        let error_url = "https://example.com/api/v3/profile/ERR";
+        cache
+            .put(
+                error_url,
+                r#"{"Error Message":"Invalid API KEY"}"#,
+                &Validators::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(cache.get(error_url).await.unwrap(), None);
+
+        // Expired entries are ignored and pruned
+        sqlx::query("UPDATE api_cache SET fetched_at = fetched_at - 48 * 3600")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert_eq!(cache.get(url).await.unwrap(), None);
+        assert_eq!(cache.prune().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_expired_entries_with_validators_are_revalidatable() {
+        let pool = db::create_db_pool("sqlite::memory:").await.unwrap();
+        let cache = ApiCache::new(pool.clone(), 24);
+        let url = "https://example.com/api/v3/historical-market-capitalization/NKE?apikey=x";
+        let validators = Validators {
+            etag: Some("\"abc123\"".to_string()),
+            last_modified: Some("Wed, 01 Jan 2025 00:00:00 GMT".to_string()),
+        };
+        cache.put(url, "[1]", &validators).await.unwrap();
+        let plain_url = "https://example.com/api/v3/profile/NKE";
+        cache
+            .put(plain_url, "[2]", &Validators::default())
+            .await
+            .unwrap();
+        assert_eq!(cache.get_revalidatable(plain_url).await.unwrap(), None);
+
+        // Expired for reuse, but kept with its validators for revalidation
+        sqlx::query("UPDATE api_cache SET fetched_at = fetched_at - 48 * 3600, day = '2000-01-01'")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert_eq!(cache.get(url).await.unwrap(), None);
+        assert_eq!(cache.prune().await.unwrap(), 1);
+        let entry = cache.get_revalidatable(url).await.unwrap().unwrap();
+        assert_eq!(entry.body, "[1]");
+        assert_eq!(entry.validators, validators);
+        let headers = entry.validators.conditional_headers();
+        assert_eq!(headers[header::IF_NONE_MATCH], "\"abc123\"");
+        assert_eq!(
+            headers[header::IF_MODIFIED_SINCE],
+            "Wed, 01 Jan 2025 00:00:00 GMT"
+        );
+
+        // Storing today's response replaces the expired one
+        cache.put(url, "[1]", &validators).await.unwrap();
+        let rows: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM api_cache")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(rows, 1);
+        assert_eq!(cache.get(url).await.unwrap().as_deref(), Some("[1]"));
+
+        // Dropped after REVALIDATE_DAYS
+        sqlx::query("UPDATE api_cache SET fetched_at = fetched_at - 31 * 86400")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert_eq!(cache.prune().await.unwrap(), 1);
+    }
+}
@@ -0,0 +1,196 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! API keys for machine-to-machine access to the web server
+//!
+//! Keys are created with `create-api-key`, shown once, and sent as
+//! `Authorization: Bearer t2k_...`. Only their SHA-256 is stored. Each scope
+//! unlocks one group of routes: `read` the viewer routes, `compare` the
+//! analyst routes and `fetch` only the fetch routes. No scope reaches the
+//! other admin routes.
+
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use sqlx::Row;
+use sqlx::sqlite::SqlitePool;
+
+use crate::web::middleware::auth::AuthUser;
+use crate::web::models::auth::{Role, User};
+
+/// Prefix that distinguishes API keys from JWTs
+pub const KEY_PREFIX: &str = "t2k_";
+
+/// Permission granted to an API key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Scope {
+    Read,
+    Compare,
+    Fetch,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Scope::Read => "read",
+            Scope::Compare => "compare",
+            Scope::Fetch => "fetch",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "read" => Ok(Scope::Read),
+            "compare" => Ok(Scope::Compare),
+            "fetch" => Ok(Scope::Fetch),
+            other => anyhow::bail!("Unknown scope '{}': use read, compare or fetch", other),
+        }
+    }
+}
+
+fn hash_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+fn generate_key() -> String {
+    format!(
+        "{}{}{}",
+        KEY_PREFIX,
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    )
+}
+
+/// Create a key with the given scopes and return it; it cannot be shown again
+pub async fn create_api_key(pool: &SqlitePool, name: &str, scopes: &[Scope]) -> Result<String> {
+    if name.trim().is_empty() {
+        anyhow::bail!("API key name must not be empty");
+    }
+    if scopes.is_empty() {
+        anyhow::bail!("An API key needs at least one scope");
+    }
+    let existing: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM api_keys WHERE name = ?")
+        .bind(name)
+        .fetch_one(pool)
+        .await?;
+    if existing > 0 {
+        anyhow::bail!("API key '{}' already exists", name);
+    }
+
+    let mut scopes = scopes.to_vec();
+    scopes.sort();
+    scopes.dedup();
+    let scopes = scopes
+        .iter()
+        .map(|s| s.as_str())
+        .collect::<Vec<_>>()
+        .join(",");
+
+    let key = generate_key();
+    sqlx::query("INSERT INTO api_keys (name, key_hash, scopes, created_at) VALUES (?, ?, ?, ?)")
+        .bind(name)
+        .bind(hash_key(&key))
+        .bind(scopes)
+        .bind(chrono::Utc::now().to_rfc3339())
+        .execute(pool)
+        .await?;
+    Ok(key)
+}
+
+/// Revoke a key so it is no longer accepted
+pub async fn revoke_api_key(pool: &SqlitePool, name: &str) -> Result<()> {
+    let result =
+        sqlx::query("UPDATE api_keys SET revoked_at = ? WHERE name = ? AND revoked_at IS NULL")
+            .bind(chrono::Utc::now().to_rfc3339())
+            .bind(name)
+            .execute(pool)
+            .await?;
+    if result.rows_affected() == 0 {
+        anyhow::bail!("No active API key named '{}'", name);
+    }
+    Ok(())
+}
+
+/// The service user for a valid, unrevoked key, with the key's scopes.
+/// Records when the key was last used.
+pub async fn authenticate(pool: &SqlitePool, key: &str) -> Result<Option<AuthUser>> {
+    let row = sqlx::query(
+        "SELECT id, name, scopes FROM api_keys WHERE key_hash = ? AND revoked_at IS NULL",
+    )
+    .bind(hash_key(key))
+    .fetch_optional(pool)
+    .await?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+
+    let id: i64 = row.get("id");
+    let name: String = row.get("name");
+    let scopes: String = row.get("scopes");
+    let scopes: Vec<Scope> = scopes
+        .split(',')
+        .filter_map(|s| Scope::parse(s).ok())
+        .collect();
+    if scopes.is_empty() {
+        return Ok(None);
+    }
+
+    sqlx::query("UPDATE api_keys SET last_used_at = ? WHERE id = ?")
+        .bind(chrono::Utc::now().to_rfc3339())
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+    // The lowest role, so handlers never treat a key as an admin; the
+    // scopes decide which routes it reaches
+    Ok(Some(AuthUser {
+        user: User {
+            id: format!("api_key_{}", id),
+            email: format!("api-key:{}", name),
+            name: Some(name),
+            role: Role::Viewer,
+        },
+        scopes: Some(scopes),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    #[test]
+    fn test_parse_scope() {
+        assert_eq!(Scope::parse(" Fetch ").unwrap(), Scope::Fetch);
+        assert_eq!(Scope::parse("read").unwrap(), Scope::Read);
+        assert!(Scope::parse("write").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_create_authenticate_and_revoke() {
+        let pool = db::create_db_pool("sqlite::memory:").await.unwrap();
+
+        let key = create_api_key(&pool, "ci", &[Scope::Read, Scope::Fetch])
+            .await
+            .unwrap();
+        assert!(key.starts_with(KEY_PREFIX));
+
+        let stored: String = sqlx::query_scalar("SELECT key_hash FROM api_keys")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_ne!(stored, key);
+
+        let auth = authenticate(&pool, &key).await.unwrap().unwrap();
+        assert_eq!(auth.user.role, Role::Viewer);
+        assert_eq!(auth.user.email, "api-key:ci");
+        assert_eq!(auth.scopes, Some(vec![Scope::Read, Scope::Fetch]));
+        assert!(authenticate(&pool, "t2k_wrong").await.unwrap().is_none());
+
+        assert!(create_api_key(&pool, "ci", &[Scope::Read]).await.is_err());
+
+        revoke_api_key(&pool, "ci").await.unwrap();
+        assert!(authenticate(&pool, &key).await.unwrap().is_none());
+        assert!(revoke_api_key(&pool, "ci").await.is_err());
+    }
+}
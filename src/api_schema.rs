@@ -0,0 +1,529 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Contract test of the FMP endpoints the fetchers depend on
+//!
+//! `check-api-schema` fetches one known ticker from each endpoint and checks
+//! the response against the fields the models read, so a renamed or retyped
+//! field is reported before a scheduled `marketcaps` run fails on it.
+//! Required fields make a model fail to parse when missing; the others only
+//! leave a column empty. Fields not listed here are reported as new for the
+//! endpoints whose complete field list is known.
+
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::fmt;
+
+use crate::api::FMPClient;
+use crate::models::{FMPCompanyProfile, FMPExecutive, FMPIncomeStatement, FMPQuote, FMPRatios};
+use crate::notify::webhook;
+
+/// Ticker checked when none is given: listed on NYSE, reports in USD
+pub const DEFAULT_TICKER: &str = "NKE";
+
+/// JSON type a field is expected to have
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldType {
+    Number,
+    String,
+    Bool,
+}
+
+impl FieldType {
+    fn matches(self, value: &Value) -> bool {
+        matches!(
+            (self, value),
+            (FieldType::Number, Value::Number(_))
+                | (FieldType::String, Value::String(_))
+                | (FieldType::Bool, Value::Bool(_))
+        )
+    }
+
+    /// Whether the lenient deserializers in `models` still read the value
+    fn coerces(self, value: &Value) -> bool {
+        match (self, value) {
+            (FieldType::Number, Value::String(s)) => {
+                s.trim().replace(',', "").parse::<f64>().is_ok()
+            }
+            (FieldType::String, Value::Number(_) | Value::Bool(_)) => true,
+            (FieldType::Bool, Value::String(s)) => {
+                s.eq_ignore_ascii_case("true") || s.eq_ignore_ascii_case("false")
+            }
+            (FieldType::Bool, Value::Number(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for FieldType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldType::Number => write!(f, "number"),
+            FieldType::String => write!(f, "string"),
+            FieldType::Bool => write!(f, "boolean"),
+        }
+    }
+}
+
+/// A field a model reads, under any of the names FMP has used for it
+#[derive(Debug, Clone, Copy)]
+pub struct ExpectedField {
+    pub names: &'static [&'static str],
+    pub kind: FieldType,
+    pub required: bool,
+}
+
+const fn required(names: &'static [&'static str], kind: FieldType) -> ExpectedField {
+    ExpectedField {
+        names,
+        kind,
+        required: true,
+    }
+}
+
+const fn optional(names: &'static [&'static str], kind: FieldType) -> ExpectedField {
+    ExpectedField {
+        names,
+        kind,
+        required: false,
+    }
+}
+
+/// Expected shape of one endpoint's response (an array of objects)
+pub struct Contract {
+    pub name: &'static str,
+    /// Path relative to `https://financialmodelingprep.com/api/`, with
+    /// `{ticker}` substituted
+    pub path: &'static str,
+    pub fields: &'static [ExpectedField],
+    /// Fields returned but not read; `None` when the endpoint returns too
+    /// many to list and new fields are not reported
+    pub ignored: Option<&'static [&'static str]>,
+    /// Parse the first entry into the model the fetchers use
+    parse: fn(&Value) -> Result<(), String>,
+}
+
+impl Contract {
+    pub fn path_for(&self, ticker: &str) -> String {
+        self.path.replace("{ticker}", ticker)
+    }
+
+    fn is_known(&self, name: &str) -> bool {
+        self.fields.iter().any(|f| f.names.contains(&name))
+            || self.ignored.is_some_and(|ignored| ignored.contains(&name))
+    }
+}
+
+fn parses_as<T: DeserializeOwned>(value: &Value) -> Result<(), String> {
+    serde_json::from_value::<T>(value.clone())
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// Endpoints `marketcaps` and `company-profile` depend on
+pub const CONTRACTS: &[Contract] = &[
+    Contract {
+        name: "profile",
+        path: "v3/profile/{ticker}",
+        fields: &[
+            required(&["symbol"], FieldType::String),
+            required(&["companyName"], FieldType::String),
+            required(&["currency"], FieldType::String),
+            optional(&["mktCap", "marketCap"], FieldType::Number),
+            optional(&["price"], FieldType::Number),
+            optional(&["exchangeShortName", "exchange"], FieldType::String),
+            optional(&["isActivelyTrading"], FieldType::Bool),
+            optional(&["description"], FieldType::String),
+            optional(&["website"], FieldType::String),
+            optional(&["fullTimeEmployees"], FieldType::String),
+            optional(&["ceo"], FieldType::String),
+            optional(&["country"], FieldType::String),
+            optional(&["industry"], FieldType::String),
+            optional(&["isin"], FieldType::String),
+        ],
+        ignored: Some(&[
+            "beta",
+            "volAvg",
+            "lastDiv",
+            "range",
+            "changes",
+            "cik",
+            "cusip",
+            "sector",
+            "phone",
+            "address",
+            "city",
+            "state",
+            "zip",
+            "dcfDiff",
+            "dcf",
+            "image",
+            "ipoDate",
+            "defaultImage",
+            "isEtf",
+            "isAdr",
+            "isFund",
+        ]),
+        parse: parses_as::<FMPCompanyProfile>,
+    },
+    Contract {
+        name: "quote",
+        path: "v3/quote/{ticker}",
+        fields: &[
+            required(&["symbol"], FieldType::String),
+            optional(&["name"], FieldType::String),
+            optional(&["price"], FieldType::Number),
+            optional(&["marketCap"], FieldType::Number),
+            optional(&["exchange"], FieldType::String),
+        ],
+        ignored: Some(&[
+            "changesPercentage",
+            "change",
+            "dayLow",
+            "dayHigh",
+            "yearHigh",
+            "yearLow",
+            "priceAvg50",
+            "priceAvg200",
+            "volume",
+            "avgVolume",
+            "open",
+            "previousClose",
+            "eps",
+            "pe",
+            "earningsAnnouncement",
+            "sharesOutstanding",
+            "timestamp",
+        ]),
+        parse: parses_as::<FMPQuote>,
+    },
+    Contract {
+        name: "ratios",
+        path: "v3/ratios/{ticker}",
+        fields: &[
+            required(&["symbol"], FieldType::String),
+            optional(&["currentRatio"], FieldType::Number),
+            optional(&["quickRatio"], FieldType::Number),
+            optional(
+                &["priceEarningsRatio", "priceToEarningsRatio"],
+                FieldType::Number,
+            ),
+            optional(&["debtEquityRatio", "debtToEquityRatio"], FieldType::Number),
+            optional(&["returnOnEquity"], FieldType::Number),
+        ],
+        ignored: None,
+        parse: parses_as::<FMPRatios>,
+    },
+    Contract {
+        name: "income-statement",
+        path: "v3/income-statement/{ticker}?limit=1",
+        fields: &[
+            required(&["date"], FieldType::String),
+            required(&["symbol"], FieldType::String),
+            optional(&["revenue"], FieldType::Number),
+        ],
+        ignored: None,
+        parse: parses_as::<FMPIncomeStatement>,
+    },
+    Contract {
+        name: "key-executives",
+        path: "v3/key-executives/{ticker}",
+        fields: &[
+            required(&["title"], FieldType::String),
+            required(&["name"], FieldType::String),
+            optional(&["pay"], FieldType::Number),
+            optional(&["currencyPay"], FieldType::String),
+            optional(&["gender"], FieldType::String),
+            optional(&["yearBorn"], FieldType::Number),
+        ],
+        ignored: Some(&["titleSince"]),
+        parse: parses_as::<FMPExecutive>,
+    },
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// A field the fetchers read was added, e.g. a field FMP renamed
+    Info,
+    /// An optional field is missing or retyped; its column will be empty
+    Warning,
+    /// The model no longer parses; fetching this endpoint will fail
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Info => write!(f, "INFO"),
+            Severity::Warning => write!(f, "WARN"),
+            Severity::Error => write!(f, "ERROR"),
+        }
+    }
+}
+
+/// A difference between a response and its contract
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub endpoint: &'static str,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}: {}", self.severity, self.endpoint, self.message)
+    }
+}
+
+fn json_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Compare a response (the array an FMP endpoint returns) with its contract
+pub fn validate(contract: &Contract, response: &Value) -> Vec<Finding> {
+    let finding = |severity, message: String| Finding {
+        endpoint: contract.name,
+        severity,
+        message,
+    };
+
+    let entry = match response {
+        Value::Array(entries) => match entries.first() {
+            Some(entry) => entry,
+            None => return vec![finding(Severity::Error, "empty response".to_string())],
+        },
+        Value::Object(_) => response,
+        other => {
+            return vec![finding(
+                Severity::Error,
+                format!("expected an array, got {}", json_type(other)),
+            )];
+        }
+    };
+    let Some(object) = entry.as_object() else {
+        return vec![finding(
+            Severity::Error,
+            format!("expected objects, got {}", json_type(entry)),
+        )];
+    };
+
+    let mut findings = Vec::new();
+    for field in contract.fields {
+        let names = field.names.join("/");
+        // null counts as present: FMP sends it for values it doesn't have
+        let Some(value) = field.names.iter().find_map(|name| object.get(*name)) else {
+            let severity = if field.required {
+                Severity::Error
+            } else {
+                Severity::Warning
+            };
+            findings.push(finding(severity, format!("missing field {}", names)));
+            continue;
+        };
+        if value.is_null() || field.kind.matches(value) {
+            continue;
+        }
+        if field.kind.coerces(value) {
+            findings.push(finding(
+                Severity::Info,
+                format!(
+                    "{}: {} instead of {} (still read)",
+                    names,
+                    json_type(value),
+                    field.kind
+                ),
+            ));
+        } else {
+            let severity = if field.required {
+                Severity::Error
+            } else {
+                Severity::Warning
+            };
+            findings.push(finding(
+                severity,
+                format!("{}: {}, expected {}", names, json_type(value), field.kind),
+            ));
+        }
+    }
+
+    if contract.ignored.is_some() {
+        let mut new_fields: Vec<&str> = object
+            .keys()
+            .map(String::as_str)
+            .filter(|name| !contract.is_known(name))
+            .collect();
+        new_fields.sort_unstable();
+        if !new_fields.is_empty() {
+            findings.push(finding(
+                Severity::Info,
+                format!("new fields: {}", new_fields.join(", ")),
+            ));
+        }
+    }
+
+    if let Err(e) = (contract.parse)(entry) {
+        findings.push(finding(
+            Severity::Error,
+            format!("does not parse into the model: {}", e),
+        ));
+    }
+
+    findings
+}
+
+/// Fetch `ticker` from every contracted endpoint and print the findings.
+/// Fails when any endpoint has an error-level finding or can't be fetched;
+/// with `notify` the failure is also posted to the configured webhooks.
+pub async fn check_api_schema(client: &FMPClient, ticker: &str, notify: bool) -> Result<()> {
+    println!("Checking FMP API schema with {}...", ticker);
+
+    let mut findings = Vec::new();
+    for contract in CONTRACTS {
+        match client.fetch_json(&contract.path_for(ticker)).await {
+            Ok(response) => {
+                let endpoint_findings = validate(contract, &response);
+                if endpoint_findings.is_empty() {
+                    println!("✅ {}", contract.name);
+                }
+                findings.extend(endpoint_findings);
+            }
+            Err(e) => findings.push(Finding {
+                endpoint: contract.name,
+                severity: Severity::Error,
+                message: format!("request failed: {:#}", e),
+            }),
+        }
+    }
+
+    findings.sort_by_key(|f| std::cmp::Reverse(f.severity));
+    for finding in &findings {
+        println!("{}", finding);
+    }
+
+    let errors = findings
+        .iter()
+        .filter(|f| f.severity == Severity::Error)
+        .count();
+    let warnings = findings
+        .iter()
+        .filter(|f| f.severity == Severity::Warning)
+        .count();
+    println!(
+        "\n{} endpoints checked: {} errors, {} warnings",
+        CONTRACTS.len(),
+        errors,
+        warnings
+    );
+
+    if errors > 0 {
+        if notify {
+            let lines: Vec<String> = findings
+                .iter()
+                .filter(|f| f.severity > Severity::Info)
+                .map(ToString::to_string)
+                .collect();
+            webhook::notify_message("FMP API schema check failed", &lines).await?;
+        }
+        anyhow::bail!("FMP API schema check found {} errors", errors);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn contract(name: &str) -> &'static Contract {
+        CONTRACTS.iter().find(|c| c.name == name).unwrap()
+    }
+
+    fn profile() -> Value {
+        json!([{
+            "symbol": "NKE",
+            "companyName": "NIKE, Inc.",
+            "currency": "USD",
+            "mktCap": 110e9,
+            "price": 73.5,
+            "exchange": "New York Stock Exchange",
+            "exchangeShortName": "NYSE",
+            "isActivelyTrading": true,
+            "description": "Athletic footwear",
+            "website": "https://www.nike.com",
+            "fullTimeEmployees": "79400",
+            "ceo": "Elliott Hill",
+            "country": "US",
+            "industry": "Apparel",
+            "isin": "US6541061031",
+            "beta": 1.1
+        }])
+    }
+
+    #[test]
+    fn test_matching_response_has_no_findings() {
+        assert!(validate(contract("profile"), &profile()).is_empty());
+    }
+
+    #[test]
+    fn test_drift_is_classified_by_severity() {
+        let mut response = profile();
+        let entry = response[0].as_object_mut().unwrap();
+        entry.remove("currency");
+        entry.remove("ceo");
+        entry.insert("price".to_string(), json!("73.50"));
+        entry.insert("website".to_string(), json!(["https://www.nike.com"]));
+        entry.insert("exchangeFullName".to_string(), json!("NYSE"));
+
+        let findings = validate(contract("profile"), &response);
+        let messages: Vec<(Severity, &str)> = findings
+            .iter()
+            .map(|f| (f.severity, f.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (Severity::Error, "missing field currency"),
+                (
+                    Severity::Info,
+                    "price: string instead of number (still read)"
+                ),
+                (Severity::Warning, "website: array, expected string"),
+                (Severity::Warning, "missing field ceo"),
+                (Severity::Info, "new fields: exchangeFullName"),
+                (
+                    Severity::Error,
+                    "does not parse into the model: missing field `currency`"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_renamed_fields_and_empty_responses() {
+        // Stable API names satisfy the contract through the aliases
+        let ratios = json!([{
+            "symbol": "NKE",
+            "currentRatio": 2.4,
+            "quickRatio": 1.6,
+            "priceToEarningsRatio": 28.1,
+            "debtToEquityRatio": 0.8,
+            "returnOnEquity": null,
+            "grossProfitMargin": 0.44
+        }]);
+        assert!(validate(contract("ratios"), &ratios).is_empty());
+
+        let findings = validate(contract("quote"), &json!([]));
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].severity, Severity::Error);
+        assert_eq!(findings[0].to_string(), "[ERROR] quote: empty response");
+    }
+}
@@ -0,0 +1,471 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! API request counting per endpoint
+//!
+//! Clients record every request sent (cache hits are not counted), the size of
+//! the (decompressed) response payloads and the requests answered with
+//! `304 Not Modified`. Counts are kept in memory during a run, summarised at
+//! the end, and added to the `api_usage` table so quota consumption can be
+//! reviewed with `api-usage`. FMP requests are also counted per API key when
+//! several are configured (`FMP_API_KEYS`, see `fmp_keys`).
+
+use anyhow::Result;
+use chrono::{Duration, Utc};
+use serde::Serialize;
+use sqlx::Row;
+use sqlx::sqlite::SqlitePool;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use crate::run_context;
+
+/// Requests sent to one endpoint
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct EndpointUsage {
+    pub count: i64,
+    pub retries: i64,
+    pub rate_limit_hits: i64,
+    /// Response payload received, after decompression
+    pub bytes: i64,
+    /// Requests answered with `304 Not Modified` from the cached response
+    pub not_modified: i64,
+}
+
+/// Requests sent with one API key
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct KeyUsage {
+    pub count: i64,
+    pub rate_limit_hits: i64,
+}
+
+static RUN_USAGE: Mutex<BTreeMap<String, EndpointUsage>> = Mutex::new(BTreeMap::new());
+static RUN_KEY_USAGE: Mutex<BTreeMap<String, KeyUsage>> = Mutex::new(BTreeMap::new());
+
+/// Endpoint name for a request URL, e.g. `fmp /api/v3/profile/{symbol}`.
+/// Path segments without lowercase letters (tickers, currency pairs) become `{symbol}`.
+pub fn endpoint_name(url: &str) -> String {
+    let without_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let without_query = without_scheme.split('?').next().unwrap_or_default();
+    let (host, path) = without_query.split_once('/').unwrap_or((without_query, ""));
+    let provider = match host {
+        "financialmodelingprep.com" => "fmp",
+        "api.polygon.io" => "polygon",
+        other => other,
+    };
+    let path: Vec<&str> = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| {
+            if segment.chars().any(|c| c.is_ascii_lowercase()) {
+                segment
+            } else {
+                "{symbol}"
+            }
+        })
+        .collect();
+    format!("{} /{}", provider, path.join("/"))
+}
+
+fn update(url: &str, apply: impl FnOnce(&mut EndpointUsage)) {
+    let mut usage = RUN_USAGE.lock().unwrap();
+    apply(usage.entry(endpoint_name(url)).or_default());
+}
+
+/// Count a request sent to `url`
+pub fn record_request(url: &str) {
+    update(url, |u| u.count += 1);
+}
+
+/// Count a rate limit response from `url` that will be retried (or given up on)
+pub fn record_rate_limit_hit(url: &str, retrying: bool) {
+    update(url, |u| {
+        u.rate_limit_hits += 1;
+        if retrying {
+            u.retries += 1;
+        }
+    });
+}
+
+/// Count a failed request to `url` (no answer or a server error) that will be retried
+pub fn record_retry(url: &str) {
+    update(url, |u| u.retries += 1);
+}
+
+/// Count the payload size of a response from `url`
+pub fn record_payload(url: &str, bytes: usize) {
+    update(url, |u| u.bytes += bytes as i64);
+}
+
+/// Count a `304 Not Modified` answer from `url`
+pub fn record_not_modified(url: &str) {
+    update(url, |u| u.not_modified += 1);
+}
+
+/// Count a request sent with the key labeled `key_id`
+pub fn record_key_request(key_id: &str) {
+    let mut usage = RUN_KEY_USAGE.lock().unwrap();
+    usage.entry(key_id.to_string()).or_default().count += 1;
+}
+
+/// Count a rate limit response to a request sent with the key labeled `key_id`
+pub fn record_key_rate_limit(key_id: &str) {
+    let mut usage = RUN_KEY_USAGE.lock().unwrap();
+    usage.entry(key_id.to_string()).or_default().rate_limit_hits += 1;
+}
+
+/// Usage recorded so far in this run
+pub fn run_usage() -> BTreeMap<String, EndpointUsage> {
+    RUN_USAGE.lock().unwrap().clone()
+}
+
+/// Add usage to today's rows in `api_usage`
+pub async fn store_usage(
+    pool: &SqlitePool,
+    date: &str,
+    usage: &BTreeMap<String, EndpointUsage>,
+) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    for (endpoint, u) in usage {
+        sqlx::query(
+            r#"
+            INSERT INTO api_usage (date, endpoint, count, retries, rate_limit_hits, bytes, not_modified)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT(date, endpoint) DO UPDATE SET
+                count = count + excluded.count,
+                retries = retries + excluded.retries,
+                rate_limit_hits = rate_limit_hits + excluded.rate_limit_hits,
+                bytes = bytes + excluded.bytes,
+                not_modified = not_modified + excluded.not_modified
+            "#,
+        )
+        .bind(date)
+        .bind(endpoint)
+        .bind(u.count)
+        .bind(u.retries)
+        .bind(u.rate_limit_hits)
+        .bind(u.bytes)
+        .bind(u.not_modified)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Add per-key usage to today's rows in `api_key_usage`
+pub async fn store_key_usage(
+    pool: &SqlitePool,
+    date: &str,
+    usage: &BTreeMap<String, KeyUsage>,
+) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    for (key_id, u) in usage {
+        sqlx::query(
+            r#"
+            INSERT INTO api_key_usage (date, key_id, count, rate_limit_hits)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT(date, key_id) DO UPDATE SET
+                count = count + excluded.count,
+                rate_limit_hits = rate_limit_hits + excluded.rate_limit_hits
+            "#,
+        )
+        .bind(date)
+        .bind(key_id)
+        .bind(u.count)
+        .bind(u.rate_limit_hits)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Per-key usage over the last `days` days (including today)
+pub async fn get_key_usage_since(
+    pool: &SqlitePool,
+    days: i64,
+) -> Result<BTreeMap<String, KeyUsage>> {
+    let since = (Utc::now() - Duration::days(days - 1))
+        .format("%Y-%m-%d")
+        .to_string();
+    let rows =
+        sqlx::query("SELECT key_id, count, rate_limit_hits FROM api_key_usage WHERE date >= ?")
+            .bind(&since)
+            .fetch_all(pool)
+            .await?;
+    let mut per_key: BTreeMap<String, KeyUsage> = BTreeMap::new();
+    for row in rows {
+        let usage = per_key.entry(row.get("key_id")).or_default();
+        usage.count += row.get::<i64, _>("count");
+        usage.rate_limit_hits += row.get::<i64, _>("rate_limit_hits");
+    }
+    Ok(per_key)
+}
+
+/// Per-key table, only worth printing when more than one key was used
+fn print_key_table(usage: &BTreeMap<String, KeyUsage>) {
+    if usage.len() < 2 {
+        return;
+    }
+    println!("\nPer FMP API key:");
+    println!("  {:<12} {:>8} {:>12}", "Key", "Requests", "Rate limited");
+    for (key_id, u) in usage {
+        println!("  {:<12} {:>8} {:>12}", key_id, u.count, u.rate_limit_hits);
+    }
+}
+
+/// Payload size for the usage table, e.g. `512 B` or `3.4 MB`
+pub fn format_bytes(bytes: i64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+fn print_table(usage: &BTreeMap<String, EndpointUsage>) {
+    let total = usage
+        .values()
+        .fold(EndpointUsage::default(), |acc, u| EndpointUsage {
+            count: acc.count + u.count,
+            retries: acc.retries + u.retries,
+            rate_limit_hits: acc.rate_limit_hits + u.rate_limit_hits,
+            bytes: acc.bytes + u.bytes,
+            not_modified: acc.not_modified + u.not_modified,
+        });
+    println!(
+        "  {:<55} {:>8} {:>8} {:>12} {:>10} {:>6}",
+        "Endpoint", "Requests", "Retries", "Rate limited", "Payload", "304"
+    );
+    let mut rows: Vec<_> = usage.iter().collect();
+    rows.sort_by(|a, b| b.1.count.cmp(&a.1.count).then(a.0.cmp(b.0)));
+    for (endpoint, u) in rows {
+        println!(
+            "  {:<55} {:>8} {:>8} {:>12} {:>10} {:>6}",
+            endpoint,
+            u.count,
+            u.retries,
+            u.rate_limit_hits,
+            format_bytes(u.bytes),
+            u.not_modified
+        );
+    }
+    println!(
+        "  {:<55} {:>8} {:>8} {:>12} {:>10} {:>6}",
+        "Total",
+        total.count,
+        total.retries,
+        total.rate_limit_hits,
+        format_bytes(total.bytes),
+        total.not_modified
+    );
+}
+
+/// Print this run's usage and add it to the `api_usage` table
+pub async fn finish_run(pool: &SqlitePool) -> Result<()> {
+    let usage = run_usage();
+    run_context::record_api_usage(&usage);
+    if usage.is_empty() {
+        return Ok(());
+    }
+    let key_usage = RUN_KEY_USAGE.lock().unwrap().clone();
+    println!("\n📊 API usage this run:");
+    print_table(&usage);
+    print_key_table(&key_usage);
+    let today = Utc::now().format("%Y-%m-%d").to_string();
+    store_usage(pool, &today, &usage).await?;
+    store_key_usage(pool, &today, &key_usage).await?;
+    RUN_USAGE.lock().unwrap().clear();
+    RUN_KEY_USAGE.lock().unwrap().clear();
+    Ok(())
+}
+
+/// Parse a lookback like `30d`, `30` or `12w` into days
+pub fn parse_lookback_days(value: &str) -> Result<i64> {
+    let value = value.trim().to_lowercase();
+    let (number, multiplier) = if let Some(n) = value.strip_suffix('w') {
+        (n, 7)
+    } else {
+        (value.strip_suffix('d').unwrap_or(&value), 1)
+    };
+    let days: i64 = number
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Invalid period '{}'. Use e.g. 30d, 12w or 30", value))?;
+    if days <= 0 {
+        anyhow::bail!("Period must be positive, got '{}'", value);
+    }
+    Ok(days * multiplier)
+}
+
+/// Usage per day and per endpoint over the last `days` days (including today)
+pub async fn get_usage_since(
+    pool: &SqlitePool,
+    days: i64,
+) -> Result<(BTreeMap<String, i64>, BTreeMap<String, EndpointUsage>)> {
+    let since = (Utc::now() - Duration::days(days - 1))
+        .format("%Y-%m-%d")
+        .to_string();
+    let rows = sqlx::query(
+        "SELECT date, endpoint, count, retries, rate_limit_hits, bytes, not_modified FROM api_usage WHERE date >= ?",
+    )
+    .bind(&since)
+    .fetch_all(pool)
+    .await?;
+
+    let mut per_day: BTreeMap<String, i64> = BTreeMap::new();
+    let mut per_endpoint: BTreeMap<String, EndpointUsage> = BTreeMap::new();
+    for row in rows {
+        let count: i64 = row.get("count");
+        *per_day.entry(row.get("date")).or_default() += count;
+        let usage = per_endpoint.entry(row.get("endpoint")).or_default();
+        usage.count += count;
+        usage.retries += row.get::<i64, _>("retries");
+        usage.rate_limit_hits += row.get::<i64, _>("rate_limit_hits");
+        usage.bytes += row.get::<i64, _>("bytes");
+        usage.not_modified += row.get::<i64, _>("not_modified");
+    }
+    Ok((per_day, per_endpoint))
+}
+
+/// Print stored usage for the `api-usage` command
+pub async fn show_usage(pool: &SqlitePool, last: &str) -> Result<()> {
+    let days = parse_lookback_days(last)?;
+    let (per_day, per_endpoint) = get_usage_since(pool, days).await?;
+    if per_day.is_empty() {
+        println!("No API usage recorded in the last {} days.", days);
+        return Ok(());
+    }
+
+    println!("📊 API usage over the last {} days\n", days);
+    println!("Per day:");
+    for (date, count) in &per_day {
+        println!("  {}  {:>8}", date, count);
+    }
+    let busiest = per_day.iter().max_by_key(|(_, count)| **count);
+    if let Some((date, count)) = busiest {
+        println!("  Busiest day: {} ({} requests)", date, count);
+    }
+    println!("\nPer endpoint:");
+    print_table(&per_endpoint);
+    print_key_table(&get_key_usage_since(pool, days).await?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    #[test]
+    fn test_endpoint_name() {
+        assert_eq!(
+            endpoint_name("https://financialmodelingprep.com/api/v3/profile/NKE?apikey=x"),
+            "fmp /api/v3/profile/{symbol}"
+        );
+        assert_eq!(
+            endpoint_name("https://financialmodelingprep.com/api/v3/profile/C.PA?apikey=x"),
+            "fmp /api/v3/profile/{symbol}"
+        );
+        assert_eq!(
+            endpoint_name(
+                "https://financialmodelingprep.com/api/v3/symbol/available-forex-currency-pairs?apikey=x"
+            ),
+            "fmp /api/v3/symbol/available-forex-currency-pairs"
+        );
+        assert_eq!(
+            endpoint_name("https://api.polygon.io/v3/reference/tickers/NKE?date=2025-01-01"),
+            "polygon /v3/reference/tickers/{symbol}"
+        );
+    }
+
+    #[test]
+    fn test_parse_lookback_days() {
+        assert_eq!(parse_lookback_days("30d").unwrap(), 30);
+        assert_eq!(parse_lookback_days("7").unwrap(), 7);
+        assert_eq!(parse_lookback_days("2w").unwrap(), 14);
+        assert!(parse_lookback_days("0d").is_err());
+        assert!(parse_lookback_days("month").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_store_key_usage() {
+        let pool = db::create_db_pool("sqlite::memory:").await.unwrap();
+        let today = Utc::now().format("%Y-%m-%d").to_string();
+        let usage = BTreeMap::from([
+            (
+                "…aaaa".to_string(),
+                KeyUsage {
+                    count: 5,
+                    rate_limit_hits: 1,
+                },
+            ),
+            (
+                "…bbbb".to_string(),
+                KeyUsage {
+                    count: 2,
+                    rate_limit_hits: 0,
+                },
+            ),
+        ]);
+        store_key_usage(&pool, &today, &usage).await.unwrap();
+        store_key_usage(&pool, &today, &usage).await.unwrap();
+        let per_key = get_key_usage_since(&pool, 1).await.unwrap();
+        assert_eq!(
+            per_key["…aaaa"],
+            KeyUsage {
+                count: 10,
+                rate_limit_hits: 2,
+            }
+        );
+        assert_eq!(per_key["…bbbb"].count, 4);
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(2048), "2.0 KB");
+        assert_eq!(format_bytes(3 * 1024 * 1024 + 400 * 1024), "3.4 MB");
+    }
+
+    #[tokio::test]
+    async fn test_store_usage_accumulates() {
+        let pool = db::create_db_pool("sqlite::memory:").await.unwrap();
+        let today = Utc::now().format("%Y-%m-%d").to_string();
+        let usage = BTreeMap::from([(
+            "fmp /api/v3/profile/{symbol}".to_string(),
+            EndpointUsage {
+                count: 10,
+                retries: 1,
+                rate_limit_hits: 2,
+                bytes: 2048,
+                not_modified: 3,
+            },
+        )]);
+        store_usage(&pool, &today, &usage).await.unwrap();
+        store_usage(&pool, &today, &usage).await.unwrap();
+        store_usage(&pool, "2000-01-01", &usage).await.unwrap();
+
+        let (per_day, per_endpoint) = get_usage_since(&pool, 30).await.unwrap();
+        assert_eq!(per_day.get(&today), Some(&20));
+        assert_eq!(per_day.len(), 1);
+        assert_eq!(
+            per_endpoint["fmp /api/v3/profile/{symbol}"],
+            EndpointUsage {
+                count: 20,
+                retries: 2,
+                rate_limit_hits: 4,
+                bytes: 4096,
+                not_modified: 6,
+            }
+        );
+    }
+}
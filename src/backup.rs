@@ -0,0 +1,451 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! `db backup` and `db restore`: move the database between environments
+//!
+//! The `sqlite` format is a consistent copy of the whole file made with
+//! `VACUUM INTO`, which reads inside one transaction like the backup API. The
+//! `json` format is one file and `csv` a directory with one CSV per table
+//! (`\N` for NULL); both are read inside one transaction too. Restoring
+//! replaces the rows of every table in the backup and leaves other tables
+//! alone. Columns are matched by name, so a backup from an older schema can be
+//! restored after newer migrations. The full-text search index is not backed
+//! up; it is rebuilt from the restored data.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::sqlite::{SqliteConnection, SqlitePool};
+use sqlx::{Column, Row, TypeInfo, ValueRef};
+use std::path::Path;
+
+use crate::run_context;
+use crate::search;
+
+/// Field written for NULL in CSV dumps, as in PostgreSQL's `COPY`
+const CSV_NULL: &str = "\\N";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    /// A copy of the SQLite file
+    Sqlite,
+    /// One JSON file with every table
+    Json,
+    /// A directory with one CSV per table
+    Csv,
+}
+
+impl DumpFormat {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "sqlite" => Ok(DumpFormat::Sqlite),
+            "json" => Ok(DumpFormat::Json),
+            "csv" => Ok(DumpFormat::Csv),
+            other => anyhow::bail!("Unknown backup format '{}': use sqlite, json or csv", other),
+        }
+    }
+
+    /// Format of an existing backup: a directory is CSV, `.json` is JSON,
+    /// anything else a SQLite file
+    pub fn infer(path: &Path) -> Self {
+        if path.is_dir() {
+            DumpFormat::Csv
+        } else if path.extension().is_some_and(|ext| ext == "json") {
+            DumpFormat::Json
+        } else {
+            DumpFormat::Sqlite
+        }
+    }
+}
+
+/// Rows of one table; values are JSON null, numbers or strings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct TableDump {
+    name: String,
+    columns: Vec<String>,
+    rows: Vec<Vec<Value>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JsonDump {
+    created_at: String,
+    tables: Vec<TableDump>,
+}
+
+/// Tables holding data, without SQLite's and sqlx's bookkeeping tables and
+/// without full-text indexes (virtual tables and their shadow tables)
+pub(crate) async fn list_tables(conn: &mut SqliteConnection, schema: &str) -> Result<Vec<String>> {
+    let tables = sqlx::query_scalar(&format!(
+        "SELECT t.name FROM {0}.sqlite_master t WHERE t.type = 'table' \
+         AND t.name NOT LIKE 'sqlite_%' AND t.name <> '_sqlx_migrations' \
+         AND t.sql NOT LIKE 'CREATE VIRTUAL TABLE%' \
+         AND NOT EXISTS (SELECT 1 FROM {0}.sqlite_master v \
+             WHERE v.sql LIKE 'CREATE VIRTUAL TABLE%' AND t.name LIKE v.name || '\\_%' ESCAPE '\\') \
+         ORDER BY t.name",
+        schema
+    ))
+    .fetch_all(&mut *conn)
+    .await?;
+    Ok(tables)
+}
+
+async fn table_columns(
+    conn: &mut SqliteConnection,
+    schema: &str,
+    table: &str,
+) -> Result<Vec<String>> {
+    let columns = sqlx::query_scalar(&format!(
+        "SELECT name FROM pragma_table_info(?, '{}') ORDER BY cid",
+        schema
+    ))
+    .bind(table)
+    .fetch_all(&mut *conn)
+    .await?;
+    Ok(columns)
+}
+
+pub(crate) fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// All rows of `schema.table` with their SQLite storage class kept
+async fn read_table(conn: &mut SqliteConnection, schema: &str, table: &str) -> Result<TableDump> {
+    let columns = table_columns(conn, schema, table).await?;
+    let rows = sqlx::query(&format!("SELECT * FROM {}.{}", schema, quote_ident(table)))
+        .fetch_all(&mut *conn)
+        .await?;
+
+    let mut values = Vec::with_capacity(rows.len());
+    for row in rows {
+        let mut record = Vec::with_capacity(row.columns().len());
+        for (i, column) in row.columns().iter().enumerate() {
+            let raw = row.try_get_raw(i)?;
+            let value = if raw.is_null() {
+                Value::Null
+            } else {
+                match raw.type_info().name() {
+                    "INTEGER" => Value::from(row.try_get::<i64, _>(i)?),
+                    "REAL" => Value::from(row.try_get::<f64, _>(i)?),
+                    "BLOB" => anyhow::bail!(
+                        "{}.{} holds a BLOB, which backups don't support",
+                        table,
+                        column.name()
+                    ),
+                    _ => Value::String(row.try_get::<String, _>(i)?),
+                }
+            };
+            record.push(value);
+        }
+        values.push(record);
+    }
+
+    Ok(TableDump {
+        name: table.to_string(),
+        columns,
+        rows: values,
+    })
+}
+
+/// Every table, read inside one transaction so the dump is consistent
+async fn dump_tables(pool: &SqlitePool) -> Result<Vec<TableDump>> {
+    let mut tx = pool.begin().await?;
+    let mut tables = Vec::new();
+    for table in list_tables(&mut tx, "main").await? {
+        tables.push(read_table(&mut tx, "main", &table).await?);
+    }
+    tx.commit().await?;
+    Ok(tables)
+}
+
+/// Replace the rows of each dumped table in one transaction. Columns missing
+/// from the database are skipped, as are tables it doesn't have.
+async fn restore_tables(pool: &SqlitePool, tables: &[TableDump]) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    // Rows are inserted in table order, so check foreign keys at commit
+    sqlx::query("PRAGMA defer_foreign_keys = ON")
+        .execute(&mut *tx)
+        .await?;
+
+    let existing = list_tables(&mut tx, "main").await?;
+    let tables: Vec<&TableDump> = tables
+        .iter()
+        .filter(|table| {
+            let known = existing.contains(&table.name);
+            if !known {
+                println!("⚠️  Skipping table {}: not in this database", table.name);
+            }
+            known
+        })
+        .collect();
+
+    // Clear everything first so ON DELETE CASCADE can't remove restored rows
+    for table in &tables {
+        sqlx::query(&format!("DELETE FROM {}", quote_ident(&table.name)))
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    for table in &tables {
+        let columns = table_columns(&mut tx, "main", &table.name).await?;
+        let kept: Vec<usize> = (0..table.columns.len())
+            .filter(|&i| columns.contains(&table.columns[i]))
+            .collect();
+        if kept.is_empty() {
+            continue;
+        }
+        let sql = format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            quote_ident(&table.name),
+            kept.iter()
+                .map(|&i| quote_ident(&table.columns[i]))
+                .collect::<Vec<_>>()
+                .join(", "),
+            vec!["?"; kept.len()].join(", ")
+        );
+        for row in &table.rows {
+            let mut query = sqlx::query(&sql);
+            for &i in &kept {
+                query = match row.get(i).unwrap_or(&Value::Null) {
+                    Value::Null => query.bind(None::<String>),
+                    Value::Bool(b) => query.bind(*b),
+                    Value::Number(n) => match n.as_i64() {
+                        Some(n) => query.bind(n),
+                        None => query.bind(n.as_f64()),
+                    },
+                    Value::String(s) => query.bind(s.as_str()),
+                    other => query.bind(other.to_string()),
+                };
+            }
+            query
+                .execute(&mut *tx)
+                .await
+                .with_context(|| format!("Failed to restore a row of {}", table.name))?;
+        }
+        println!("✅ Restored {} rows into {}", table.rows.len(), table.name);
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+fn write_csv_dir(dir: &Path, tables: &[TableDump]) -> Result<()> {
+    std::fs::create_dir_all(dir)?;
+    for table in tables {
+        let mut writer = csv::Writer::from_path(dir.join(format!("{}.csv", table.name)))?;
+        writer.write_record(&table.columns)?;
+        for row in &table.rows {
+            writer.write_record(row.iter().map(|value| match value {
+                Value::Null => CSV_NULL.to_string(),
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            }))?;
+        }
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+/// Tables of a CSV dump; values are text and SQLite's column affinity turns
+/// numbers back into INTEGER or REAL on insert
+fn read_csv_dir(dir: &Path) -> Result<Vec<TableDump>> {
+    let mut paths: Vec<_> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "csv"))
+        .collect();
+    paths.sort();
+
+    let mut tables = Vec::new();
+    for path in paths {
+        let name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .context("Invalid CSV file name")?
+            .to_string();
+        let mut reader = csv::Reader::from_path(&path)?;
+        let columns = reader.headers()?.iter().map(str::to_string).collect();
+        let mut rows = Vec::new();
+        for record in reader.records() {
+            rows.push(
+                record?
+                    .iter()
+                    .map(|field| match field {
+                        CSV_NULL => Value::Null,
+                        field => Value::String(field.to_string()),
+                    })
+                    .collect(),
+            );
+        }
+        tables.push(TableDump {
+            name,
+            columns,
+            rows,
+        });
+    }
+    Ok(tables)
+}
+
+/// Restore from a SQLite backup file by attaching it and reading its tables
+async fn restore_sqlite_file(pool: &SqlitePool, path: &Path) -> Result<()> {
+    let mut conn = pool.acquire().await?;
+    sqlx::query("ATTACH DATABASE ? AS backup")
+        .bind(path.display().to_string())
+        .execute(&mut *conn)
+        .await?;
+
+    let tables = async {
+        let mut tables = Vec::new();
+        for table in list_tables(&mut conn, "backup").await? {
+            tables.push(read_table(&mut conn, "backup", &table).await?);
+        }
+        Ok::<_, anyhow::Error>(tables)
+    }
+    .await;
+
+    sqlx::query("DETACH DATABASE backup")
+        .execute(&mut *conn)
+        .await?;
+    drop(conn);
+    restore_tables(pool, &tables?).await
+}
+
+/// Write a backup of the database to `path`
+pub async fn backup(pool: &SqlitePool, path: &Path, format: DumpFormat) -> Result<()> {
+    if path.exists() && !(format == DumpFormat::Csv && path.is_dir()) {
+        anyhow::bail!("{} already exists; choose a new path", path.display());
+    }
+    run_context::record_output(path);
+
+    match format {
+        DumpFormat::Sqlite => {
+            sqlx::query("VACUUM INTO ?")
+                .bind(path.display().to_string())
+                .execute(pool)
+                .await
+                .context("Failed to write the SQLite snapshot")?;
+        }
+        DumpFormat::Json => {
+            let dump = JsonDump {
+                created_at: chrono::Utc::now().to_rfc3339(),
+                tables: dump_tables(pool).await?,
+            };
+            let file = std::fs::File::create(path)?;
+            serde_json::to_writer(std::io::BufWriter::new(file), &dump)?;
+        }
+        DumpFormat::Csv => write_csv_dir(path, &dump_tables(pool).await?)?,
+    }
+
+    println!("✅ Database backed up to {}", path.display());
+    Ok(())
+}
+
+/// Replace the database contents with the backup at `path`
+pub async fn restore(pool: &SqlitePool, path: &Path, format: Option<DumpFormat>) -> Result<()> {
+    if !path.exists() {
+        anyhow::bail!("Backup {} not found", path.display());
+    }
+    run_context::record_input(path);
+
+    match format.unwrap_or_else(|| DumpFormat::infer(path)) {
+        DumpFormat::Sqlite => restore_sqlite_file(pool, path).await?,
+        DumpFormat::Json => {
+            let file = std::fs::File::open(path)?;
+            let dump: JsonDump = serde_json::from_reader(std::io::BufReader::new(file))
+                .with_context(|| format!("{} is not a JSON database backup", path.display()))?;
+            restore_tables(pool, &dump.tables).await?;
+        }
+        DumpFormat::Csv => restore_tables(pool, &read_csv_dir(path)?).await?,
+    }
+    search::rebuild_index(pool).await?;
+
+    println!("✅ Database restored from {}", path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    async fn seeded_pool(db_url: &str) -> SqlitePool {
+        let pool = db::create_db_pool(db_url).await.unwrap();
+        for sql in [
+            "INSERT INTO currencies (code, name) VALUES ('EUR', 'Euro'), ('USD', 'US Dollar')",
+            "INSERT INTO forex_rates (symbol, ask, bid, timestamp) VALUES ('EUR/USD', 1.0825, 1.0824, 1735689600)",
+            "INSERT INTO ticker_details (ticker, description, homepage_url) VALUES ('NKE', NULL, '')",
+            "INSERT INTO watchlists (name) VALUES ('sportswear')",
+            "INSERT INTO watchlist_tickers (watchlist, ticker) VALUES ('sportswear', 'NKE')",
+        ] {
+            sqlx::query(sql).execute(&pool).await.unwrap();
+        }
+        pool
+    }
+
+    #[tokio::test]
+    async fn test_backup_and_restore_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        // In-memory databases open VACUUM INTO and ATTACH targets in memory too
+        let source_url = format!("sqlite:{}", dir.path().join("source.db").display());
+        let source = seeded_pool(&source_url).await;
+        let expected = dump_tables(&source).await.unwrap();
+
+        for (format, name) in [
+            (DumpFormat::Sqlite, "backup.db"),
+            (DumpFormat::Json, "backup.json"),
+            (DumpFormat::Csv, "backup-csv"),
+        ] {
+            let path = dir.path().join(name);
+            backup(&source, &path, format).await.unwrap();
+            assert_eq!(DumpFormat::infer(&path), format);
+
+            let target_url = format!(
+                "sqlite:{}",
+                dir.path().join(format!("{}.db", name)).display()
+            );
+            let target = db::create_db_pool(&target_url).await.unwrap();
+            sqlx::query("INSERT INTO currencies (code, name) VALUES ('JPY', 'Yen')")
+                .execute(&target)
+                .await
+                .unwrap();
+            restore(&target, &path, None).await.unwrap();
+            assert_eq!(
+                dump_tables(&target).await.unwrap(),
+                expected,
+                "{:?}",
+                format
+            );
+        }
+
+        // Backups never overwrite an existing file
+        let existing = dir.path().join("backup.json");
+        assert!(backup(&source, &existing, DumpFormat::Json).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_restore_skips_unknown_tables_and_columns() {
+        let pool = db::create_db_pool("sqlite::memory:").await.unwrap();
+        let tables = vec![
+            TableDump {
+                name: "currencies".to_string(),
+                columns: vec!["code".to_string(), "name".to_string(), "symbol".to_string()],
+                rows: vec![vec![
+                    Value::from("EUR"),
+                    Value::from("Euro"),
+                    Value::from("€"),
+                ]],
+            },
+            TableDump {
+                name: "dropped_table".to_string(),
+                columns: vec!["id".to_string()],
+                rows: vec![vec![Value::from(1)]],
+            },
+        ];
+        restore_tables(&pool, &tables).await.unwrap();
+
+        let currencies: Vec<(String, String)> = sqlx::query_as("SELECT code, name FROM currencies")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(currencies, vec![("EUR".to_string(), "Euro".to_string())]);
+    }
+}
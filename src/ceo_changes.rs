@@ -0,0 +1,339 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! CEO changes between snapshots (`detect-ceo-changes --from --to`)
+//!
+//! The database keeps only the current CEO per ticker, but every export CSV
+//! carries the CEO known when it was written. The snapshots of the dates in
+//! the range are walked in order; a ticker whose CEO differs from the last one
+//! seen for it is a change, first observed on the date of that snapshot.
+//! Empty CEO fields are missing data, not a change, and names are compared
+//! without honorifics and case ("Mr. John Donahoe" = "John Donahoe").
+//!
+//! The CEO column is filled from `ticker_details` when the CSV is written, so
+//! a snapshot backfilled later carries the CEO of its export day, not of its
+//! date. Only snapshots exported on their own date (the `{timestamp}` in the
+//! file name) are used; the others are skipped with a warning.
+
+use anyhow::{Context, Result};
+use chrono::{NaiveDate, NaiveDateTime};
+use csv::Writer;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Write as IoWrite;
+use std::path::Path;
+
+use crate::advanced_comparisons::{find_csv_for_date, get_available_dates};
+use crate::clock;
+use crate::config::{self, OutputConfig};
+use crate::snapshot_diff::{self, Field};
+
+/// Honorifics FMP puts in front of executive names
+const HONORIFICS: [&str; 6] = ["mr.", "mrs.", "ms.", "dr.", "prof.", "sir"];
+
+/// CEO of a ticker that changed between two snapshots
+#[derive(Debug, Clone, PartialEq)]
+pub struct CeoChange {
+    pub ticker: String,
+    pub name: String,
+    pub old_ceo: String,
+    pub new_ceo: String,
+    /// Last snapshot date with the old CEO
+    pub last_seen_old: String,
+    /// First snapshot date with the new CEO
+    pub first_seen_new: String,
+}
+
+/// Name without honorifics, whitespace runs or case, for comparing CEOs
+pub fn normalize_name(name: &str) -> String {
+    name.split_whitespace()
+        .map(str::to_lowercase)
+        .skip_while(|word| HONORIFICS.contains(&word.as_str()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Company name and CEO per ticker of one snapshot date
+pub type CeoSnapshot = BTreeMap<String, (String, String)>;
+
+/// CEO changes across snapshots ordered by date, by first observation
+pub fn find_changes(snapshots: &[(String, CeoSnapshot)]) -> Vec<CeoChange> {
+    // Last known CEO of each ticker and the last date it was seen
+    let mut known: BTreeMap<&str, (&str, &str)> = BTreeMap::new();
+    let mut changes = Vec::new();
+    for (date, snapshot) in snapshots {
+        for (ticker, (name, ceo)) in snapshot {
+            if ceo.trim().is_empty() {
+                continue;
+            }
+            if let Some((old_ceo, last_seen)) = known.get(ticker.as_str())
+                && normalize_name(old_ceo) != normalize_name(ceo)
+            {
+                changes.push(CeoChange {
+                    ticker: ticker.clone(),
+                    name: name.clone(),
+                    old_ceo: old_ceo.to_string(),
+                    new_ceo: ceo.clone(),
+                    last_seen_old: last_seen.to_string(),
+                    first_seen_new: date.clone(),
+                });
+            }
+            known.insert(ticker, (ceo, date));
+        }
+    }
+    changes.sort_by(|a, b| {
+        a.first_seen_new
+            .cmp(&b.first_seen_new)
+            .then_with(|| a.ticker.cmp(&b.ticker))
+    });
+    changes
+}
+
+/// Day an export CSV was written, from the `YYYYMMDD_HHMMSS` timestamp in its
+/// file name; `None` when the name has none
+fn export_date(path: &str) -> Option<NaiveDate> {
+    let stem = Path::new(path).file_stem()?.to_str()?;
+    (0..=stem.len().checked_sub(15)?).rev().find_map(|start| {
+        let stamp = stem.get(start..start + 15)?;
+        NaiveDateTime::parse_from_str(stamp, "%Y%m%d_%H%M%S")
+            .ok()
+            .map(|time| time.date())
+    })
+}
+
+/// Whether the export at `path` was written on the day of the snapshot
+/// `date` (`2025-01-01` or a labeled `2025-01-01@close`)
+fn exported_on_own_date(path: &str, date: &str) -> bool {
+    let day = date.split('@').next().unwrap_or(date);
+    let day = NaiveDate::parse_from_str(day, "%Y-%m-%d").ok();
+    day.is_some() && export_date(path) == day
+}
+
+/// Name and CEO per ticker of an export CSV, or `None` when the export has no
+/// CEO column
+fn load_ceos(path: &str) -> Result<Option<CeoSnapshot>> {
+    let snapshot = snapshot_diff::read_snapshot_csv(path)?;
+    if !snapshot.fields.contains(&Field::Ceo) {
+        return Ok(None);
+    }
+    Ok(Some(
+        snapshot
+            .rows
+            .into_iter()
+            .map(|(ticker, mut values)| {
+                let name = values.remove(&Field::Name).unwrap_or_default();
+                let ceo = values.remove(&Field::Ceo).unwrap_or_default();
+                (ticker, (name, ceo))
+            })
+            .collect(),
+    ))
+}
+
+/// List the companies whose CEO changed between `from` and `to`
+pub fn detect_ceo_changes(from: &str, to: &str, watchlist: Option<&str>) -> Result<Vec<CeoChange>> {
+    for date in [from, to] {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .with_context(|| format!("Invalid date {:?}: use YYYY-MM-DD", date))?;
+    }
+    let dates: Vec<String> = get_available_dates(watchlist)?
+        .into_iter()
+        .filter(|date| date.as_str() >= from && date.as_str() <= to)
+        .collect();
+    if dates.len() < 2 {
+        anyhow::bail!(
+            "Need at least two snapshots between {} and {}, found {}; fetch them with fetch-specific-date-market-caps",
+            from,
+            to,
+            dates.len()
+        );
+    }
+
+    let mut snapshots = Vec::new();
+    for date in &dates {
+        let path = find_csv_for_date(date, watchlist)?;
+        if !exported_on_own_date(&path, date) {
+            println!(
+                "⚠️  Skipping {}: exported on another day, so its CEOs are not those of {}",
+                date, date
+            );
+            continue;
+        }
+        match load_ceos(&path)? {
+            Some(ceos) => snapshots.push((date.clone(), ceos)),
+            None => println!("⚠️  Skipping {}: its export has no CEO column", date),
+        }
+    }
+    let changes = find_changes(&snapshots);
+    println!(
+        "👔 {} CEO changes across {} snapshots from {} to {}",
+        changes.len(),
+        snapshots.len(),
+        from,
+        to
+    );
+    for change in &changes {
+        println!(
+            "  {:<10} {:<30} {} → {} (first seen {})",
+            change.ticker, change.name, change.old_ceo, change.new_ceo, change.first_seen_new
+        );
+    }
+
+    let output = config::load_output_config();
+    output.ensure_directory()?;
+    let stamp = OutputConfig::timestamp();
+    let range = format!("{}_to_{}", from, to);
+    let csv_path = output.file_path_at("ceo_changes", &range, &stamp, "csv");
+    write_csv(&csv_path, &changes)?;
+    println!("✅ CEO changes exported to {}", csv_path.display());
+    let md_path = output.file_path_at("ceo_changes", &format!("{}_summary", range), &stamp, "md");
+    write_markdown(&md_path, &changes, from, to, snapshots.len())?;
+    println!("✅ CEO change report exported to {}", md_path.display());
+
+    Ok(changes)
+}
+
+fn write_csv(path: &Path, changes: &[CeoChange]) -> Result<()> {
+    let mut writer = Writer::from_path(path)?;
+    writer.write_record([
+        "Ticker",
+        "Name",
+        "Old CEO",
+        "New CEO",
+        "Last Seen (Old)",
+        "First Seen (New)",
+    ])?;
+    for change in changes {
+        writer.write_record([
+            &change.ticker,
+            &change.name,
+            &change.old_ceo,
+            &change.new_ceo,
+            &change.last_seen_old,
+            &change.first_seen_new,
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_markdown(
+    path: &Path,
+    changes: &[CeoChange],
+    from: &str,
+    to: &str,
+    snapshots: usize,
+) -> Result<()> {
+    let mut file = File::create(path)?;
+    writeln!(file, "# CEO Changes: {} to {}", from, to)?;
+    writeln!(file)?;
+    writeln!(
+        file,
+        "{} changes across {} snapshots. A change was first observed in the snapshot of the date given; it happened after the last snapshot with the old CEO.",
+        changes.len(),
+        snapshots
+    )?;
+    writeln!(file)?;
+    if changes.is_empty() {
+        writeln!(file, "No CEO changes.")?;
+    } else {
+        writeln!(file, "| Company | Ticker | Old CEO | New CEO | Between |")?;
+        writeln!(file, "|---------|--------|---------|---------|---------|")?;
+        for change in changes {
+            writeln!(
+                file,
+                "| {} | {} | {} | {} | {} and {} |",
+                change.name,
+                change.ticker,
+                change.old_ceo,
+                change.new_ceo,
+                change.last_seen_old,
+                change.first_seen_new
+            )?;
+        }
+    }
+    writeln!(file)?;
+    writeln!(file, "---")?;
+    writeln!(
+        file,
+        "*Generated on {}*",
+        clock::now().format("%Y-%m-%d %H:%M:%S")
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(date: &str, rows: &[(&str, &str)]) -> (String, CeoSnapshot) {
+        (
+            date.to_string(),
+            rows.iter()
+                .map(|(ticker, ceo)| {
+                    (
+                        ticker.to_string(),
+                        (format!("{} Inc", ticker), ceo.to_string()),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_normalize_name() {
+        assert_eq!(normalize_name("Mr. John  Donahoe II"), "john donahoe ii");
+        assert_eq!(normalize_name("john donahoe ii"), "john donahoe ii");
+        assert_eq!(normalize_name("Dr. Ms. Jane Doe"), "jane doe");
+    }
+
+    #[test]
+    fn test_find_changes_across_snapshots() {
+        let changes = find_changes(&[
+            snapshot("2025-01-01", &[("NKE", "Mr. John Donahoe"), ("TJX", "")]),
+            snapshot(
+                "2025-02-01",
+                &[("NKE", "John Donahoe"), ("TJX", "Ernie Herrman")],
+            ),
+            // Missing CEO data is not a change
+            snapshot("2025-03-01", &[("NKE", ""), ("TJX", "Ernie Herrman")]),
+            snapshot(
+                "2025-04-01",
+                &[("NKE", "Elliott Hill"), ("TJX", "Ernie Herrman")],
+            ),
+        ]);
+        assert_eq!(
+            changes,
+            vec![CeoChange {
+                ticker: "NKE".to_string(),
+                name: "NKE Inc".to_string(),
+                old_ceo: "John Donahoe".to_string(),
+                new_ceo: "Elliott Hill".to_string(),
+                last_seen_old: "2025-02-01".to_string(),
+                first_seen_new: "2025-04-01".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_only_snapshots_exported_on_their_date() {
+        let path = "output/marketcaps_2025-01-01_20250101_230000.csv";
+        assert_eq!(export_date(path), NaiveDate::from_ymd_opt(2025, 1, 1));
+        assert!(exported_on_own_date(path, "2025-01-01"));
+        // Backfilled months later
+        assert!(!exported_on_own_date(
+            "output/marketcaps_2025-01-01_20250601_090000.csv",
+            "2025-01-01"
+        ));
+        assert!(exported_on_own_date(
+            "output/marketcaps_2025-01-01@close_20250101_220000.csv",
+            "2025-01-01@close"
+        ));
+        // A file name template without a timestamp gives no export day
+        assert_eq!(export_date("output/marketcaps_2025-01-01.csv"), None);
+        assert!(!exported_on_own_date(
+            "output/marketcaps_2025-01-01.csv",
+            "2025-01-01"
+        ));
+    }
+}
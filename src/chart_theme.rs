@@ -0,0 +1,311 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Colors, font and watermark of every chart (`[charts]` in config.toml)
+//!
+//! Charts never name a color directly: they ask the theme for its role
+//! (text, positive change, palette entry, ...). The theme is set once per run
+//! from the config, like the locale, and defaults to `light`, which keeps the
+//! original palette.
+
+use anyhow::Result;
+use plotters::style::{IntoFont, RGBColor, TextStyle};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::config::ChartConfig;
+use crate::logos;
+
+// Palette of the `light` theme
+pub const COLOR_EMERALD: RGBColor = RGBColor(16, 185, 129);
+pub const COLOR_ROSE: RGBColor = RGBColor(244, 63, 94);
+pub const COLOR_BLUE: RGBColor = RGBColor(59, 130, 246);
+pub const COLOR_AMBER: RGBColor = RGBColor(245, 158, 11);
+pub const COLOR_TEAL: RGBColor = RGBColor(20, 184, 166);
+pub const COLOR_CORAL: RGBColor = RGBColor(251, 113, 133);
+pub const COLOR_PURPLE: RGBColor = RGBColor(139, 92, 246);
+pub const COLOR_PINK: RGBColor = RGBColor(236, 72, 153);
+pub const COLOR_LIME: RGBColor = RGBColor(132, 204, 22);
+pub const COLOR_ORANGE: RGBColor = RGBColor(249, 115, 22);
+pub const COLOR_SLATE: RGBColor = RGBColor(100, 116, 139);
+pub const COLOR_GRAY_LIGHT: RGBColor = RGBColor(243, 244, 246);
+
+pub const CHART_COLORS: [RGBColor; 10] = [
+    COLOR_BLUE,
+    COLOR_EMERALD,
+    COLOR_AMBER,
+    COLOR_ROSE,
+    COLOR_PURPLE,
+    COLOR_PINK,
+    COLOR_TEAL,
+    COLOR_ORANGE,
+    COLOR_LIME,
+    COLOR_SLATE,
+];
+
+/// Names accepted by `[charts] theme`
+pub const THEMES: [&str; 3] = ["light", "dark", "fashionunited"];
+
+/// Colors by role, font family and optional watermark of the charts
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChartTheme {
+    pub name: String,
+    pub background: RGBColor,
+    /// Titles, labels and axes
+    pub text: RGBColor,
+    /// Subtitles, secondary labels and neutral values
+    pub muted: RGBColor,
+    /// Gridlines, "Others" segments and missing data
+    pub subtle: RGBColor,
+    /// Text drawn on top of colored bars and tiles
+    pub on_accent: RGBColor,
+    pub primary: RGBColor,
+    pub positive: RGBColor,
+    pub negative: RGBColor,
+    /// Second pair for charts that show two kinds of moves (rank changes)
+    pub positive_alt: RGBColor,
+    pub negative_alt: RGBColor,
+    /// Segment and series colors, in order
+    pub palette: [RGBColor; 10],
+    pub font: String,
+    /// Image placed faintly in the bottom-right corner of every chart
+    pub watermark: Option<PathBuf>,
+}
+
+impl ChartTheme {
+    /// The original palette on white
+    pub fn light() -> Self {
+        Self {
+            name: "light".to_string(),
+            background: RGBColor(255, 255, 255),
+            text: RGBColor(0, 0, 0),
+            muted: COLOR_SLATE,
+            subtle: COLOR_GRAY_LIGHT,
+            on_accent: RGBColor(255, 255, 255),
+            primary: COLOR_BLUE,
+            positive: COLOR_EMERALD,
+            negative: COLOR_ROSE,
+            positive_alt: COLOR_TEAL,
+            negative_alt: COLOR_CORAL,
+            palette: CHART_COLORS,
+            font: "sans-serif".to_string(),
+            watermark: None,
+        }
+    }
+
+    /// Lighter shades of the same palette on a near-black background
+    pub fn dark() -> Self {
+        Self {
+            name: "dark".to_string(),
+            background: RGBColor(17, 24, 39),
+            text: RGBColor(243, 244, 246),
+            muted: RGBColor(156, 163, 175),
+            subtle: RGBColor(55, 65, 81),
+            on_accent: RGBColor(17, 24, 39),
+            primary: RGBColor(96, 165, 250),
+            positive: RGBColor(52, 211, 153),
+            negative: RGBColor(251, 113, 133),
+            positive_alt: RGBColor(45, 212, 191),
+            negative_alt: RGBColor(253, 164, 175),
+            palette: [
+                RGBColor(96, 165, 250),
+                RGBColor(52, 211, 153),
+                RGBColor(251, 191, 36),
+                RGBColor(251, 113, 133),
+                RGBColor(167, 139, 250),
+                RGBColor(244, 114, 182),
+                RGBColor(45, 212, 191),
+                RGBColor(251, 146, 60),
+                RGBColor(163, 230, 53),
+                RGBColor(148, 163, 184),
+            ],
+            font: "sans-serif".to_string(),
+            watermark: None,
+        }
+    }
+
+    /// Editorial black and white with red and green for moves, for charts
+    /// published on fashionunited.com
+    pub fn fashionunited() -> Self {
+        Self {
+            name: "fashionunited".to_string(),
+            background: RGBColor(255, 255, 255),
+            text: RGBColor(17, 17, 17),
+            muted: RGBColor(102, 102, 102),
+            subtle: RGBColor(235, 235, 235),
+            on_accent: RGBColor(255, 255, 255),
+            primary: RGBColor(17, 17, 17),
+            positive: RGBColor(0, 140, 100),
+            negative: RGBColor(210, 35, 42),
+            positive_alt: RGBColor(0, 115, 120),
+            negative_alt: RGBColor(230, 95, 95),
+            palette: [
+                RGBColor(17, 17, 17),
+                RGBColor(210, 35, 42),
+                RGBColor(120, 120, 120),
+                RGBColor(190, 155, 100),
+                RGBColor(0, 140, 100),
+                RGBColor(0, 90, 160),
+                RGBColor(175, 175, 175),
+                RGBColor(110, 60, 130),
+                RGBColor(230, 120, 40),
+                RGBColor(70, 70, 70),
+            ],
+            font: "Helvetica, Arial, sans-serif".to_string(),
+            watermark: None,
+        }
+    }
+
+    /// Built-in theme by name
+    pub fn named(name: &str) -> Result<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "light" => Ok(Self::light()),
+            "dark" => Ok(Self::dark()),
+            "fashionunited" => Ok(Self::fashionunited()),
+            other => anyhow::bail!(
+                "Unknown chart theme '{}'; use one of: {}",
+                other,
+                THEMES.join(", ")
+            ),
+        }
+    }
+
+    /// Theme from `[charts]`: a built-in theme with the font and watermark
+    /// overrides applied
+    pub fn from_config(config: &ChartConfig) -> Result<Self> {
+        let mut theme = Self::named(&config.theme)?;
+        if let Some(font) = config.font.as_ref().filter(|f| !f.trim().is_empty()) {
+            theme.font = font.clone();
+        }
+        theme.watermark = config
+            .watermark
+            .as_ref()
+            .filter(|w| !w.trim().is_empty())
+            .map(PathBuf::from);
+        Ok(theme)
+    }
+
+    /// Text of the given size in the theme's font and text color
+    pub fn text_style(&self, size: u32) -> TextStyle<'_> {
+        TextStyle::from((self.font.as_str(), size).into_font()).color(&self.text)
+    }
+
+    /// Add the watermark to a finished SVG chart of the given size
+    pub fn apply_watermark(&self, svg_path: &Path, (width, height): (u32, u32)) -> Result<()> {
+        let Some(watermark) = &self.watermark else {
+            return Ok(());
+        };
+        let Some(uri) = logos::data_uri(watermark) else {
+            anyhow::bail!("Chart watermark {} cannot be read", watermark.display());
+        };
+        let (w, h) = (120, 40);
+        let element = format!(
+            r#"<image x="{}" y="{}" width="{}" height="{}" opacity="0.25" preserveAspectRatio="xMaxYMax meet" href="{}"/>"#,
+            width.saturating_sub(w + 16),
+            height.saturating_sub(h + 12),
+            w,
+            h,
+            uri
+        );
+        logos::embed_in_svg(svg_path, &[element])
+    }
+}
+
+static CURRENT: OnceLock<ChartTheme> = OnceLock::new();
+
+/// Set the chart theme for this run; later calls are ignored
+pub fn init(config: &ChartConfig) -> Result<()> {
+    let theme = ChartTheme::from_config(config)?;
+    let _ = CURRENT.set(theme);
+    Ok(())
+}
+
+/// Theme set with `init`, `light` when none was set
+pub fn current() -> &'static ChartTheme {
+    CURRENT.get_or_init(ChartTheme::light)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_light_theme_keeps_original_palette() {
+        let theme = ChartTheme::light();
+        assert_eq!(theme.positive, COLOR_EMERALD);
+        assert_eq!(theme.negative, COLOR_ROSE);
+        assert_eq!(theme.palette, CHART_COLORS);
+        assert_eq!(theme.background, RGBColor(255, 255, 255));
+    }
+
+    #[test]
+    fn test_named_themes() {
+        for name in THEMES {
+            assert_eq!(ChartTheme::named(name).unwrap().name, name);
+        }
+        assert_eq!(ChartTheme::named(" Dark ").unwrap().name, "dark");
+        let err = ChartTheme::named("neon").unwrap_err().to_string();
+        assert!(err.contains("light, dark, fashionunited"));
+    }
+
+    #[test]
+    fn test_dark_theme_contrasts_with_its_background() {
+        let theme = ChartTheme::dark();
+        let luminance =
+            |c: RGBColor| 0.2126 * c.0 as f64 + 0.7152 * c.1 as f64 + 0.0722 * c.2 as f64;
+        assert!(luminance(theme.text) - luminance(theme.background) > 150.0);
+        assert!(
+            theme
+                .palette
+                .iter()
+                .all(|c| luminance(*c) > luminance(theme.background))
+        );
+    }
+
+    #[test]
+    fn test_from_config_applies_overrides() {
+        let config = ChartConfig {
+            theme: "fashionunited".to_string(),
+            font: Some("Georgia".to_string()),
+            watermark: Some("assets/fu.png".to_string()),
+        };
+        let theme = ChartTheme::from_config(&config).unwrap();
+        assert_eq!(theme.name, "fashionunited");
+        assert_eq!(theme.font, "Georgia");
+        assert_eq!(theme.watermark, Some(PathBuf::from("assets/fu.png")));
+
+        let theme = ChartTheme::from_config(&ChartConfig::default()).unwrap();
+        assert_eq!(theme, ChartTheme::light());
+    }
+
+    #[test]
+    fn test_apply_watermark() {
+        let dir = tempfile::tempdir().unwrap();
+        let svg = dir.path().join("chart.svg");
+        std::fs::write(&svg, "<svg></svg>").unwrap();
+
+        // Without a watermark the chart is left alone
+        ChartTheme::light()
+            .apply_watermark(&svg, (1000, 600))
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&svg).unwrap(), "<svg></svg>");
+
+        let mark = dir.path().join("mark.svg");
+        std::fs::write(&mark, "<svg/>").unwrap();
+        let theme = ChartTheme {
+            watermark: Some(mark),
+            ..ChartTheme::light()
+        };
+        theme.apply_watermark(&svg, (1000, 600)).unwrap();
+        let written = std::fs::read_to_string(&svg).unwrap();
+        assert!(written.contains(r#"<image x="864" y="548" width="120" height="40""#));
+        assert!(written.contains("data:image/svg+xml;base64,"));
+
+        let missing = ChartTheme {
+            watermark: Some(dir.path().join("missing.png")),
+            ..ChartTheme::light()
+        };
+        assert!(missing.apply_watermark(&svg, (1000, 600)).is_err());
+    }
+}
@@ -0,0 +1,329 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Circuit breaker and per-ticker deadline of the API clients
+//!
+//! After `[api] breaker_threshold` consecutive failures (no answer within the
+//! timeouts, or a server error) of an endpoint, its requests fail at once
+//! with `Error::CircuitOpen` for `breaker_cooldown_secs`. The first request
+//! after the cool-down is let through as a trial: success closes the circuit,
+//! failure opens it again; so does any other answer, such as a `429`.
+//! `within_deadline` caps all requests of one ticker at
+//! `ticker_deadline_secs`, not counting the time spent waiting for the rate
+//! limiter or backing off from rate limits (`excluded_from_deadline`).
+//! Fetchers report the tickers skipped either way separately from the ones
+//! that failed.
+
+use anyhow::Result;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::api_usage;
+use crate::config;
+use crate::error::{self, Error, ErrorCode};
+use crate::run_context;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct EndpointState {
+    consecutive_failures: u32,
+    /// Requests are refused until then
+    open_until: Option<Instant>,
+}
+
+/// Failure counts per endpoint, driven by explicit instants so it is testable
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    endpoints: HashMap<String, EndpointState>,
+}
+
+impl CircuitBreaker {
+    /// Breaker opening after `threshold` consecutive failures (never when 0)
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            cooldown,
+            endpoints: HashMap::new(),
+        }
+    }
+
+    /// Whether a request to `endpoint` may be sent, or how long it stays
+    /// open. Letting the trial request through after the cool-down keeps the
+    /// circuit open for the others until the trial reports back.
+    pub fn check(&mut self, endpoint: &str, now: Instant) -> Result<(), Duration> {
+        let Some(state) = self.endpoints.get_mut(endpoint) else {
+            return Ok(());
+        };
+        match state.open_until {
+            Some(until) if until > now => Err(until - now),
+            Some(_) => {
+                state.open_until = Some(now + self.cooldown);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    pub fn record_success(&mut self, endpoint: &str) {
+        self.endpoints.remove(endpoint);
+    }
+
+    /// Count a failure; returns true when it opened the circuit
+    pub fn record_failure(&mut self, endpoint: &str, now: Instant) -> bool {
+        if self.threshold == 0 {
+            return false;
+        }
+        let state = self.endpoints.entry(endpoint.to_string()).or_default();
+        state.consecutive_failures += 1;
+        let was_open = state.open_until.is_some();
+        if state.consecutive_failures >= self.threshold {
+            state.open_until = Some(now + self.cooldown);
+        }
+        !was_open && state.open_until.is_some()
+    }
+}
+
+static BREAKER: OnceLock<Mutex<CircuitBreaker>> = OnceLock::new();
+
+/// The process-wide breaker, configured from `[api]` in config.toml
+fn breaker() -> &'static Mutex<CircuitBreaker> {
+    BREAKER.get_or_init(|| {
+        let api = config::load_api_config();
+        Mutex::new(CircuitBreaker::new(
+            api.breaker_threshold,
+            Duration::from_secs(api.breaker_cooldown_secs),
+        ))
+    })
+}
+
+/// Fail with `Error::CircuitOpen` while the endpoint of `url` is open
+pub fn check(url: &str) -> Result<()> {
+    let endpoint = api_usage::endpoint_name(url);
+    let open_for = breaker().lock().unwrap().check(&endpoint, Instant::now());
+    match open_for {
+        Ok(()) => Ok(()),
+        Err(remaining) => Err(Error::CircuitOpen {
+            endpoint,
+            retry_in_secs: remaining.as_secs().max(1),
+        }
+        .into()),
+    }
+}
+
+pub fn record_success(url: &str) {
+    let endpoint = api_usage::endpoint_name(url);
+    breaker().lock().unwrap().record_success(&endpoint);
+}
+
+/// Count a failed request to `url`, warning when it opens the circuit
+pub fn record_failure(url: &str) {
+    let endpoint = api_usage::endpoint_name(url);
+    let mut breaker = breaker().lock().unwrap();
+    if breaker.record_failure(&endpoint, Instant::now()) {
+        let message = format!(
+            "{} failed {} times in a row; skipping it for {}s",
+            endpoint,
+            breaker.threshold,
+            breaker.cooldown.as_secs()
+        );
+        eprintln!("⚠️  {}", message);
+        run_context::record_warning(message);
+    }
+}
+
+/// Run the requests of one ticker, giving up with `Error::DeadlineExceeded`
+/// after `[api] ticker_deadline_secs`
+pub async fn within_deadline<T>(ticker: &str, fetch: impl Future<Output = Result<T>>) -> Result<T> {
+    static DEADLINE_SECS: OnceLock<u64> = OnceLock::new();
+    let secs = *DEADLINE_SECS.get_or_init(|| config::load_api_config().ticker_deadline_secs);
+    with_deadline(ticker, Duration::from_secs(secs), fetch).await
+}
+
+/// Time the ticker being fetched spent in waits its deadline doesn't count
+#[derive(Debug, Default)]
+struct Paused {
+    total: Duration,
+    /// Start of the wait in progress
+    since: Option<tokio::time::Instant>,
+}
+
+impl Paused {
+    fn at(&self, now: tokio::time::Instant) -> Duration {
+        self.total + self.since.map_or(Duration::ZERO, |since| now - since)
+    }
+}
+
+tokio::task_local! {
+    static PAUSED: Arc<Mutex<Paused>>;
+}
+
+/// Run `wait` (for the rate limiter or a rate-limit backoff) without counting
+/// it against the deadline of the ticker being fetched
+pub async fn excluded_from_deadline<T>(wait: impl Future<Output = T>) -> T {
+    let Ok(paused) = PAUSED.try_with(Arc::clone) else {
+        return wait.await;
+    };
+    let started = {
+        let mut paused = paused.lock().unwrap();
+        // A wait inside another one is already excluded
+        match paused.since {
+            Some(_) => None,
+            None => {
+                let now = tokio::time::Instant::now();
+                paused.since = Some(now);
+                Some(now)
+            }
+        }
+    };
+    let result = wait.await;
+    if let Some(started) = started {
+        let mut paused = paused.lock().unwrap();
+        paused.total += started.elapsed();
+        paused.since = None;
+    }
+    result
+}
+
+async fn with_deadline<T>(
+    ticker: &str,
+    limit: Duration,
+    fetch: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    let paused = Arc::new(Mutex::new(Paused::default()));
+    let fetch = PAUSED.scope(Arc::clone(&paused), fetch);
+    tokio::pin!(fetch);
+    let start = tokio::time::Instant::now();
+    loop {
+        // Excluded waits push the deadline back by their length
+        let deadline = start + limit + paused.lock().unwrap().at(tokio::time::Instant::now());
+        tokio::select! {
+            result = &mut fetch => return result,
+            _ = tokio::time::sleep_until(deadline) => {
+                let now = tokio::time::Instant::now();
+                if now >= start + limit + paused.lock().unwrap().at(now) {
+                    return Err(Error::DeadlineExceeded {
+                        ticker: ticker.to_string(),
+                        secs: limit.as_secs(),
+                    }
+                    .into());
+                }
+            }
+        }
+    }
+}
+
+/// Whether a ticker's error means it was skipped (open circuit or deadline)
+/// rather than failed
+pub fn is_skipped(err: &anyhow::Error) -> bool {
+    error::code_of(err) == Some(ErrorCode::ApiUnavailable)
+}
+
+/// Print the tickers skipped because of an open circuit or the deadline
+pub fn report_skipped(skipped: &[(String, String)]) {
+    if skipped.is_empty() {
+        return;
+    }
+    println!(
+        "\n⏭️  Skipped {} tickers (endpoint unavailable or deadline exceeded):",
+        skipped.len()
+    );
+    for (ticker, reason) in skipped {
+        println!("  {} - {}", ticker, reason);
+        run_context::record_warning(format!("{}: {}", ticker, reason));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROFILE: &str = "fmp /api/v3/profile/{symbol}";
+
+    #[test]
+    fn test_opens_after_consecutive_failures_and_cools_down() {
+        let start = Instant::now();
+        let mut breaker = CircuitBreaker::new(3, Duration::from_secs(60));
+
+        assert!(!breaker.record_failure(PROFILE, start));
+        assert!(!breaker.record_failure(PROFILE, start));
+        // A success in between resets the count
+        breaker.record_success(PROFILE);
+        assert!(!breaker.record_failure(PROFILE, start));
+        assert!(!breaker.record_failure(PROFILE, start));
+        assert!(breaker.check(PROFILE, start).is_ok());
+        assert!(breaker.record_failure(PROFILE, start));
+
+        let later = start + Duration::from_secs(20);
+        assert_eq!(breaker.check(PROFILE, later), Err(Duration::from_secs(40)));
+        // Other endpoints are not affected
+        assert!(breaker.check("fmp /api/v3/quote/{symbol}", later).is_ok());
+
+        // One trial after the cool-down; the others wait for its outcome
+        let cooled = start + Duration::from_secs(60);
+        assert!(breaker.check(PROFILE, cooled).is_ok());
+        assert!(breaker.check(PROFILE, cooled).is_err());
+        breaker.record_success(PROFILE);
+        assert!(breaker.check(PROFILE, cooled).is_ok());
+    }
+
+    #[test]
+    fn test_failed_trial_reopens_at_once() {
+        let start = Instant::now();
+        let mut breaker = CircuitBreaker::new(2, Duration::from_secs(60));
+        breaker.record_failure(PROFILE, start);
+        assert!(breaker.record_failure(PROFILE, start));
+
+        let cooled = start + Duration::from_secs(61);
+        assert!(breaker.check(PROFILE, cooled).is_ok());
+        // Still open from the trial: not reported as newly opened
+        assert!(!breaker.record_failure(PROFILE, cooled));
+        assert_eq!(breaker.check(PROFILE, cooled), Err(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_threshold_zero_never_opens() {
+        let start = Instant::now();
+        let mut breaker = CircuitBreaker::new(0, Duration::from_secs(60));
+        for _ in 0..100 {
+            assert!(!breaker.record_failure(PROFILE, start));
+        }
+        assert!(breaker.check(PROFILE, start).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_skipped_errors_are_told_apart() {
+        let slow = with_deadline("NKE", Duration::ZERO, async {
+            tokio::time::sleep(Duration::from_secs(3600)).await;
+            Ok(())
+        });
+        let err = slow.await.unwrap_err();
+        assert!(is_skipped(&err));
+        assert_eq!(err.to_string(), "Skipped: NKE took longer than 0s");
+
+        assert!(!is_skipped(&anyhow::anyhow!("Failed to parse response")));
+    }
+
+    #[tokio::test]
+    async fn test_deadline_excludes_rate_limit_waits() {
+        let ms = Duration::from_millis;
+        // 100ms of work and 400ms waiting for the rate limiter fit in 200ms
+        let throttled = with_deadline("NKE", ms(200), async {
+            tokio::time::sleep(ms(50)).await;
+            excluded_from_deadline(tokio::time::sleep(ms(400))).await;
+            tokio::time::sleep(ms(50)).await;
+            Ok(())
+        });
+        assert!(throttled.await.is_ok());
+
+        let slow = with_deadline("NKE", ms(200), async {
+            excluded_from_deadline(tokio::time::sleep(ms(100))).await;
+            tokio::time::sleep(ms(400)).await;
+            Ok(())
+        });
+        assert!(is_skipped(&slow.await.unwrap_err()));
+    }
+}
@@ -0,0 +1,83 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Shell completions (`completions <shell>`) and man pages (`--generate-manpage`)
+//!
+//! Both are generated from the clap definition of the CLI, so they always
+//! list the subcommands and options of the binary that wrote them.
+
+use anyhow::{Context, Result};
+use clap::Command;
+use clap_complete::Shell;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Write the completion script for `shell` to `out`
+pub fn write_completions(shell: Shell, mut cmd: Command, out: &mut dyn Write) {
+    let name = cmd.get_name().to_string();
+    clap_complete::generate(shell, &mut cmd, name, out);
+}
+
+/// Write the man page of the top-level command to `out`
+pub fn write_manpage(cmd: Command, out: &mut dyn Write) -> Result<()> {
+    clap_mangen::Man::new(cmd).render(out)?;
+    Ok(())
+}
+
+/// Write `<name>.1` and one `<name>-<subcommand>.1` page per (nested)
+/// subcommand into `dir`, returning the written paths
+pub fn write_manpages(cmd: Command, dir: &Path) -> Result<Vec<PathBuf>> {
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    clap_mangen::generate_to(cmd, dir)?;
+    let mut pages: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "1"))
+        .collect();
+    pages.sort();
+    Ok(pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cli;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_completions_list_subcommands_and_global_options() {
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish, Shell::PowerShell] {
+            let mut out = Vec::new();
+            write_completions(shell, Cli::command(), &mut out);
+            let script = String::from_utf8(out).unwrap();
+            assert!(script.contains("top200-rs"), "{shell}");
+            assert!(script.contains("compare-market-caps"), "{shell}");
+            assert!(script.contains("chart-backend"), "{shell}");
+        }
+    }
+
+    #[test]
+    fn test_manpage_of_top_level_command() {
+        let mut out = Vec::new();
+        write_manpage(Cli::command(), &mut out).unwrap();
+        let page = String::from_utf8(out).unwrap();
+        assert!(page.starts_with(".ie \\n(.g .ds Aq"));
+        assert!(page.contains(".TH top200-rs 1"));
+        assert!(page.contains("generate\\-charts"));
+    }
+
+    #[test]
+    fn test_manpages_per_subcommand() {
+        let dir = tempfile::tempdir().unwrap();
+        let pages = write_manpages(Cli::command(), &dir.path().join("man")).unwrap();
+        let names: Vec<String> = pages
+            .iter()
+            .map(|p| p.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert!(names.contains(&"top200-rs.1".to_string()));
+        assert!(names.contains(&"top200-rs-compare-market-caps.1".to_string()));
+        // Nested subcommands get their own page too
+        assert!(names.contains(&"top200-rs-db-backup.1".to_string()));
+    }
+}
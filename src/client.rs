@@ -0,0 +1,300 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Typed API for services that embed the crate
+//!
+//! The CLI commands print progress and write CSV, markdown and chart files.
+//! These functions run the same snapshot fetch, comparison and trend
+//! analysis but return the results instead, and never print: rate gaps and
+//! failed tickers are part of the returned values.
+
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use sqlx::sqlite::SqlitePool;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::advanced_comparisons::{self, TickerTrend, TrendSummary};
+use crate::api::FMPClient;
+use crate::compare_marketcaps::{
+    self, ChangeAttribution, MarketCapComparison, MarketCapRecord, RowAnnotations,
+};
+use crate::config;
+use crate::corporate_actions::CorporateActionIndex;
+use crate::currencies::{self, RateGap};
+use crate::earnings::EarningsIndex;
+use crate::ticker_aliases::{AppliedAliases, TickerAliases};
+use crate::utils;
+
+/// Midnight UTC of a date, the timestamp snapshots are stored under
+fn date_timestamp(date: NaiveDate) -> i64 {
+    date.and_time(chrono::NaiveTime::MIN).and_utc().timestamp()
+}
+
+/// One company of a fetched snapshot
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnapshotRow {
+    pub ticker: String,
+    pub name: String,
+    pub market_cap_original: f64,
+    pub original_currency: String,
+    /// `None` when no rate to the currency was available for the date
+    pub market_cap_eur: Option<f64>,
+    pub market_cap_usd: Option<f64>,
+    pub exchange: String,
+    pub price: f64,
+}
+
+/// Market caps of a set of tickers on one date
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    /// Fetched companies, in the order of the requested tickers
+    pub rows: Vec<SnapshotRow>,
+    /// Tickers that could not be fetched, with the error
+    pub failed: Vec<(String, String)>,
+    /// Exchange rates that were stale, interpolated or missing on the date
+    pub rate_gaps: Vec<RateGap>,
+}
+
+/// Fetch the market caps of `tickers` on `date` and convert them to EUR and
+/// USD with the stored rates of that date. Nothing is written to the database.
+pub async fn fetch_snapshot(
+    fmp_client: &FMPClient,
+    pool: &SqlitePool,
+    date: NaiveDate,
+    tickers: &[String],
+    concurrency: usize,
+) -> Result<Snapshot> {
+    let forex = config::load_forex_config();
+    let (rate_map, rate_gaps) =
+        currencies::get_rate_map_with_gaps(pool, Some(date_timestamp(date)), &forex).await?;
+
+    let datetime = date.and_time(chrono::NaiveTime::MIN).and_utc();
+    let fetched = utils::fetch_ordered(tickers, concurrency, |ticker| {
+        fmp_client.get_historical_market_cap(ticker, &datetime)
+    })
+    .await;
+
+    let mut snapshot = Snapshot {
+        rate_gaps,
+        ..Snapshot::default()
+    };
+    for (ticker, result) in tickers.iter().zip(fetched) {
+        match result {
+            Ok(market_cap) => {
+                let convert = |to: &str| {
+                    currencies::try_convert_currency(
+                        market_cap.market_cap_original,
+                        &market_cap.original_currency,
+                        to,
+                        &rate_map,
+                    )
+                    .ok()
+                    .map(|c| c.amount)
+                };
+                snapshot.rows.push(SnapshotRow {
+                    ticker: ticker.clone(),
+                    market_cap_eur: convert("EUR"),
+                    market_cap_usd: convert("USD"),
+                    name: market_cap.name,
+                    market_cap_original: market_cap.market_cap_original,
+                    original_currency: market_cap.original_currency,
+                    exchange: market_cap.exchange,
+                    price: market_cap.price,
+                });
+            }
+            Err(e) => snapshot.failed.push((ticker.clone(), e.to_string())),
+        }
+    }
+    Ok(snapshot)
+}
+
+/// Change between two stored snapshots
+#[derive(Debug)]
+pub struct Comparison {
+    /// One row per company, largest percentage gain first
+    pub rows: Vec<MarketCapComparison>,
+    /// Bridge from the total USD market cap of the first date to the second
+    pub attribution: ChangeAttribution,
+    /// Renamed symbols compared under their current symbol
+    pub aliases: AppliedAliases,
+}
+
+/// Compare the snapshots stored for two dates, like `compare-market-caps`
+/// but read from the database instead of the exported CSV files
+pub async fn compare_snapshots(
+    pool: &SqlitePool,
+    from_date: NaiveDate,
+    to_date: NaiveDate,
+) -> Result<Comparison> {
+    let (from, to) = (from_date.to_string(), to_date.to_string());
+    let mut from_records = compare_marketcaps::load_market_cap_records(pool, &from).await?;
+    let mut to_records = compare_marketcaps::load_market_cap_records(pool, &to).await?;
+    if from_records.is_empty() || to_records.is_empty() {
+        anyhow::bail!("No stored market caps for {} or {}", from, to);
+    }
+
+    let ticker_aliases = TickerAliases::load(pool).await?;
+    let mut aliases = AppliedAliases::default();
+    ticker_aliases.apply(
+        from_date,
+        &mut from_records,
+        |r| &mut r.ticker,
+        &mut aliases,
+    );
+    ticker_aliases.apply(to_date, &mut to_records, |r| &mut r.ticker, &mut aliases);
+
+    let corporate_actions = CorporateActionIndex::load_for_period(&from, &to)?;
+    let earnings = EarningsIndex::load_for_period(pool, &from, &to).await?;
+    let annotations = RowAnnotations {
+        corporate_actions: &corporate_actions,
+        aliases: &aliases,
+        earnings: &earnings,
+    };
+    let rows = compare_marketcaps::build_comparisons(
+        &from_records,
+        &to_records,
+        annotations,
+        &[],
+        &HashMap::new(),
+        &HashMap::new(),
+    );
+    let attribution = compare_marketcaps::attribute_change(
+        &from_records,
+        &to_records,
+        compare_marketcaps::WATERFALL_CONTRIBUTORS,
+    );
+
+    Ok(Comparison {
+        rows,
+        attribution,
+        aliases,
+    })
+}
+
+/// Trends per company over the snapshots stored for `dates` (at least two),
+/// like `trend-analysis`. USD values are normalized with the rates of the
+/// last date.
+pub async fn trends(
+    pool: &SqlitePool,
+    dates: &[NaiveDate],
+) -> Result<(Vec<TickerTrend>, TrendSummary)> {
+    let (Some(first), Some(last)) = (dates.first(), dates.last()) else {
+        anyhow::bail!("At least 2 dates are required for trend analysis");
+    };
+    if first == last {
+        anyhow::bail!("At least 2 dates are required for trend analysis");
+    }
+
+    let forex = config::load_forex_config();
+    let (normalization_rates, _) =
+        currencies::get_rate_map_with_gaps(pool, Some(date_timestamp(*last)), &forex).await?;
+
+    let ticker_aliases = TickerAliases::load(pool).await?;
+    let mut aliases = AppliedAliases::default();
+    let mut all_data = BTreeMap::new();
+    for date in dates {
+        let mut records: Vec<MarketCapRecord> =
+            compare_marketcaps::load_market_cap_records(pool, &date.to_string())
+                .await
+                .with_context(|| format!("Failed to load market caps for {}", date))?;
+        ticker_aliases.apply(*date, &mut records, |r| &mut r.ticker, &mut aliases);
+        let by_ticker: BTreeMap<String, advanced_comparisons::MarketCapRecord> = records
+            .into_iter()
+            .map(|r| {
+                (
+                    r.ticker.clone(),
+                    advanced_comparisons::MarketCapRecord {
+                        rank: r.rank,
+                        ticker: r.ticker,
+                        name: r.name,
+                        market_cap_original: r.market_cap_original,
+                        original_currency: r.original_currency,
+                        market_cap_eur: r.market_cap_eur,
+                        market_cap_usd: r.market_cap_usd,
+                        exchange: r.exchange,
+                    },
+                )
+            })
+            .collect();
+        all_data.insert(date.to_string(), by_ticker);
+    }
+
+    let corporate_actions =
+        CorporateActionIndex::load_for_period(&first.to_string(), &last.to_string())?;
+    let date_keys: Vec<String> = dates.iter().map(NaiveDate::to_string).collect();
+    advanced_comparisons::compute_trends(
+        &date_keys,
+        &all_data,
+        &normalization_rates,
+        corporate_actions,
+        false,
+        None,
+        aliases,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    async fn store(pool: &SqlitePool, date: &str, rows: &[(&str, f64)]) -> Result<()> {
+        let timestamp = date_timestamp(NaiveDate::parse_from_str(date, "%Y-%m-%d")?);
+        for (ticker, market_cap) in rows {
+            sqlx::query(
+                "INSERT INTO market_caps (ticker, name, market_cap_original, original_currency, \
+                 market_cap_eur, market_cap_usd, timestamp) VALUES ($1, $1, $2, 'USD', $2, $2, $3)",
+            )
+            .bind(ticker)
+            .bind(market_cap)
+            .bind(timestamp)
+            .execute(pool)
+            .await?;
+        }
+        Ok(())
+    }
+
+    fn date(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_compare_and_trends_from_stored_snapshots() -> Result<()> {
+        let pool = db::create_db_pool("sqlite::memory:").await?;
+        store(&pool, "2025-01-01", &[("NKE", 100.0), ("ITX.MC", 50.0)]).await?;
+        store(&pool, "2025-02-01", &[("NKE", 90.0), ("ITX.MC", 75.0)]).await?;
+        store(&pool, "2025-03-01", &[("NKE", 110.0), ("ITX.MC", 60.0)]).await?;
+
+        let comparison = compare_snapshots(&pool, date("2025-01-01"), date("2025-02-01")).await?;
+        let rows: Vec<(&str, Option<f64>, Option<i32>)> = comparison
+            .rows
+            .iter()
+            .map(|r| (r.ticker.as_str(), r.percentage_change, r.rank_change))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                ("ITX.MC", Some(50.0), Some(0)),
+                ("NKE", Some(-10.0), Some(0))
+            ]
+        );
+        assert_eq!(comparison.attribution.total_from, 150.0);
+        assert_eq!(comparison.attribution.total_to, 165.0);
+
+        let dates = [date("2025-01-01"), date("2025-02-01"), date("2025-03-01")];
+        let (ticker_trends, summary) = trends(&pool, &dates).await?;
+        assert_eq!(summary.num_periods, 3);
+        assert_eq!(ticker_trends.len(), 2);
+        assert_eq!(ticker_trends[0].ticker, "ITX.MC");
+        assert_eq!(ticker_trends[0].overall_change_pct, Some(20.0));
+
+        assert!(
+            compare_snapshots(&pool, date("2025-01-01"), date("2025-04-01"))
+                .await
+                .is_err()
+        );
+        assert!(trends(&pool, &dates[..1]).await.is_err());
+        Ok(())
+    }
+}
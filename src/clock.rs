@@ -0,0 +1,154 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! "Today" for report file names, "Generated on" lines and date defaults
+//!
+//! Date-dependent logic asks a [`Clock`] instead of calling `Local::now()`.
+//! The run clock is the system clock unless `--as-of` fixes it, so a past run
+//! can be reproduced. Tests freeze the clock per thread with `freeze()`.
+//! When data was fetched or stored (DB timestamps, API cache and usage) is
+//! always recorded with the real time.
+
+use anyhow::{Context, Result};
+use chrono::{Local, NaiveDate, NaiveDateTime, NaiveTime};
+use std::cell::Cell;
+use std::sync::OnceLock;
+
+/// Source of the current local date and time
+pub trait Clock: Send + Sync {
+    fn now(&self) -> NaiveDateTime;
+
+    fn today(&self) -> NaiveDate {
+        self.now().date()
+    }
+}
+
+/// The computer's wall clock
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> NaiveDateTime {
+        Local::now().naive_local()
+    }
+}
+
+/// A clock that always shows the same time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedClock(pub NaiveDateTime);
+
+impl FixedClock {
+    /// Parse an `--as-of` value: `YYYY-MM-DD` (midnight) or `YYYY-MM-DDTHH:MM:SS`
+    pub fn parse(value: &str) -> Result<Self> {
+        if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+            return Ok(FixedClock(date.and_time(NaiveTime::MIN)));
+        }
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S")
+            .map(FixedClock)
+            .with_context(|| {
+                format!(
+                    "Invalid --as-of '{}': use YYYY-MM-DD or YYYY-MM-DDTHH:MM:SS",
+                    value
+                )
+            })
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> NaiveDateTime {
+        self.0
+    }
+}
+
+static RUN_CLOCK: OnceLock<Box<dyn Clock>> = OnceLock::new();
+
+thread_local! {
+    static FROZEN: Cell<Option<FixedClock>> = const { Cell::new(None) };
+}
+
+/// Set the clock for this run; later calls are ignored
+pub fn init(clock: Box<dyn Clock>) {
+    let _ = RUN_CLOCK.set(clock);
+}
+
+/// The clock of this run: a frozen test clock, `--as-of`, or the system clock
+pub fn current() -> &'static dyn Clock {
+    struct Frozen;
+    impl Clock for Frozen {
+        fn now(&self) -> NaiveDateTime {
+            FROZEN.with(Cell::get).map_or_else(
+                || {
+                    RUN_CLOCK
+                        .get()
+                        .map_or_else(|| SystemClock.now(), |c| c.now())
+                },
+                |clock| clock.now(),
+            )
+        }
+    }
+    &Frozen
+}
+
+/// Current local time of the run clock
+pub fn now() -> NaiveDateTime {
+    current().now()
+}
+
+/// Keeps the clock frozen until dropped
+#[cfg(test)]
+pub struct FrozenClock {
+    previous: Option<FixedClock>,
+}
+
+/// Make the run clock return `at` on this thread until the guard is dropped
+#[cfg(test)]
+pub fn freeze(at: NaiveDateTime) -> FrozenClock {
+    FrozenClock {
+        previous: FROZEN.with(|frozen| frozen.replace(Some(FixedClock(at)))),
+    }
+}
+
+#[cfg(test)]
+impl Drop for FrozenClock {
+    fn drop(&mut self) {
+        FROZEN.with(|frozen| frozen.set(self.previous));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(y: i32, m: u32, d: u32, h: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, m, d)
+            .unwrap()
+            .and_hms_opt(h, 0, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_freeze_and_release() {
+        {
+            let _clock = freeze(at(2025, 4, 1, 9));
+            assert_eq!(now(), at(2025, 4, 1, 9));
+            assert_eq!(
+                current().today(),
+                NaiveDate::from_ymd_opt(2025, 4, 1).unwrap()
+            );
+        }
+        assert_ne!(now(), at(2025, 4, 1, 9));
+    }
+
+    #[test]
+    fn test_parse_as_of() {
+        assert_eq!(
+            FixedClock::parse("2024-12-31").unwrap().now(),
+            at(2024, 12, 31, 0)
+        );
+        assert_eq!(
+            FixedClock::parse("2024-12-31T18:00:00").unwrap().now(),
+            at(2024, 12, 31, 18)
+        );
+        assert!(FixedClock::parse("yesterday").is_err());
+    }
+}
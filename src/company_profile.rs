@@ -0,0 +1,411 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Company profile cards for quick ad-hoc lookups (`show <ticker>`)
+
+use crate::api::FMPClient;
+use crate::config;
+use crate::currencies::{convert_currency, get_rate_map_from_db};
+use crate::models::Details;
+use crate::ticker_details::{self, TickerDetails};
+use anyhow::Result;
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use sqlx::Row;
+use sqlx::sqlite::SqlitePool;
+
+/// Everything shown on a company card
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CompanyProfile {
+    pub ticker: String,
+    pub name: Option<String>,
+    pub exchange: Option<String>,
+    pub currency: Option<String>,
+    pub ceo: Option<String>,
+    pub employees: Option<String>,
+    pub isin: Option<String>,
+    pub lei: Option<String>,
+    pub homepage_url: Option<String>,
+    pub description: Option<String>,
+    pub pe_ratio: Option<f64>,
+    pub eps: Option<f64>,
+    pub quick_ratio: Option<f64>,
+    pub working_capital_ratio: Option<f64>,
+    pub debt_equity_ratio: Option<f64>,
+    pub roe: Option<f64>,
+    pub market_cap_eur: Option<f64>,
+    pub market_cap_usd: Option<f64>,
+    /// Date of the market cap figures
+    pub market_cap_date: Option<String>,
+    /// When the profile fields were last fetched from FMP
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+/// Whether a cached profile updated at `updated_at` is still within the TTL
+pub fn is_fresh(updated_at: NaiveDateTime, now: NaiveDateTime, ttl_hours: i64) -> bool {
+    now - updated_at < Duration::hours(ttl_hours)
+}
+
+/// Load the cached profile fields for a ticker
+pub(crate) async fn load_cached_profile(
+    pool: &SqlitePool,
+    ticker: &str,
+) -> Result<Option<CompanyProfile>> {
+    let row = sqlx::query(
+        r#"
+        SELECT ticker, name, exchange, currency, ceo, CAST(employees AS TEXT) as employees,
+            homepage_url, description, pe_ratio, eps, quick_ratio, working_capital_ratio,
+            debt_equity_ratio, roe, isin, lei, updated_at
+        FROM ticker_details
+        WHERE ticker = ?
+        "#,
+    )
+    .bind(ticker)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| CompanyProfile {
+        ticker: row.get("ticker"),
+        name: row.get("name"),
+        exchange: row.get("exchange"),
+        currency: row.get("currency"),
+        ceo: row.get("ceo"),
+        employees: row.get("employees"),
+        isin: row.get("isin"),
+        lei: row.get("lei"),
+        homepage_url: row.get("homepage_url"),
+        description: row.get("description"),
+        pe_ratio: row.get("pe_ratio"),
+        eps: row.get("eps"),
+        quick_ratio: row.get("quick_ratio"),
+        working_capital_ratio: row.get("working_capital_ratio"),
+        debt_equity_ratio: row.get("debt_equity_ratio"),
+        roe: row.get("roe"),
+        updated_at: row
+            .get::<Option<String>, _>("updated_at")
+            .and_then(|s| NaiveDateTime::parse_from_str(&s, "%Y-%m-%d %H:%M:%S").ok()),
+        ..CompanyProfile::default()
+    }))
+}
+
+/// Store the profile fields of freshly fetched details
+async fn store_profile(pool: &SqlitePool, details: &Details) -> Result<()> {
+    ticker_details::update_ticker_details(
+        pool,
+        &TickerDetails {
+            ticker: details.ticker.clone(),
+            description: details.description.clone(),
+            homepage_url: details.homepage_url.clone(),
+            employees: details.employees.clone(),
+            ceo: details.ceo.clone(),
+            country: details.country.clone(),
+            industry: details.industry.clone(),
+            isin: details.isin.clone(),
+            lei: details.lei.clone(),
+        },
+    )
+    .await?;
+
+    sqlx::query(
+        r#"
+        UPDATE ticker_details SET
+            name = ?, exchange = ?, currency = ?, pe_ratio = ?, eps = ?, quick_ratio = ?,
+            working_capital_ratio = ?, debt_equity_ratio = ?, roe = ?
+        WHERE ticker = ?
+        "#,
+    )
+    .bind(&details.name)
+    .bind(
+        details
+            .extra
+            .get("exchange")
+            .and_then(|v| v.as_str())
+            .map(str::to_string),
+    )
+    .bind(&details.currency_symbol)
+    .bind(details.pe_ratio)
+    .bind(details.eps)
+    .bind(details.quick_ratio)
+    .bind(details.working_capital_ratio)
+    .bind(details.debt_equity_ratio)
+    .bind(details.roe)
+    .bind(&details.ticker)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Latest stored EUR/USD market cap and its date
+async fn latest_market_cap(
+    pool: &SqlitePool,
+    ticker: &str,
+) -> Result<Option<(Option<f64>, Option<f64>, i64)>> {
+    let row = sqlx::query(
+        r#"
+        SELECT CAST(market_cap_eur AS REAL) as market_cap_eur,
+            CAST(market_cap_usd AS REAL) as market_cap_usd,
+            timestamp
+        FROM market_caps
+        WHERE ticker = ?
+        ORDER BY timestamp DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(ticker)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| {
+        (
+            row.get("market_cap_eur"),
+            row.get("market_cap_usd"),
+            row.get("timestamp"),
+        )
+    }))
+}
+
+/// Cached profile when fresh, otherwise refreshed from FMP
+pub async fn get_company_profile(
+    pool: &SqlitePool,
+    ticker: &str,
+    force_refresh: bool,
+) -> Result<CompanyProfile> {
+    let ttl_hours = config::load_company_profile_config().cache_ttl_hours;
+    let now = Utc::now().naive_utc();
+
+    let cached = load_cached_profile(pool, ticker).await?;
+    let fresh = cached.as_ref().is_some_and(|p| {
+        // Rows written before profiles were cached have no name yet
+        p.name.is_some() && p.updated_at.is_some_and(|t| is_fresh(t, now, ttl_hours))
+    });
+
+    let mut profile = match cached {
+        Some(profile) if fresh && !force_refresh => profile,
+        _ => {
+            println!("Fetching profile for {} from FMP...", ticker);
+            let fmp_client = FMPClient::from_env()?;
+            let rate_map = get_rate_map_from_db(pool).await?;
+            let details = fmp_client.get_details(ticker, &rate_map).await?;
+            store_profile(pool, &details).await?;
+
+            let mut profile = load_cached_profile(pool, ticker).await?.unwrap_or_default();
+            // Prefer the live market cap over the last stored snapshot
+            if let (Some(market_cap), Some(currency)) =
+                (details.market_cap, details.currency_symbol.as_deref())
+            {
+                profile.market_cap_eur =
+                    Some(convert_currency(market_cap, currency, "EUR", &rate_map));
+                profile.market_cap_usd =
+                    Some(convert_currency(market_cap, currency, "USD", &rate_map));
+                profile.market_cap_date = Some(now.format("%Y-%m-%d").to_string());
+            }
+            profile
+        }
+    };
+
+    if profile.market_cap_date.is_none()
+        && let Some((eur, usd, timestamp)) = latest_market_cap(pool, ticker).await?
+    {
+        profile.market_cap_eur = eur;
+        profile.market_cap_usd = usd;
+        profile.market_cap_date =
+            DateTime::from_timestamp(timestamp, 0).map(|dt| dt.format("%Y-%m-%d").to_string());
+    }
+
+    Ok(profile)
+}
+
+pub(crate) fn format_billions(symbol: &str, value: Option<f64>) -> String {
+    value
+        .map(|v| format!("{}{:.2}B", symbol, v / 1_000_000_000.0))
+        .unwrap_or_else(|| "NA".to_string())
+}
+
+pub(crate) fn format_ratio(value: Option<f64>) -> String {
+    value
+        .map(|v| format!("{:.2}", v))
+        .unwrap_or_else(|| "NA".to_string())
+}
+
+/// Wrap text at word boundaries
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.len() + word.len() + 1 > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Render a profile as a text card
+pub fn format_card(profile: &CompanyProfile) -> String {
+    let na = |v: &Option<String>| v.clone().unwrap_or_else(|| "NA".to_string());
+    let mut lines = vec![
+        format!(
+            "🏢 {} ({})",
+            profile.name.as_deref().unwrap_or(&profile.ticker),
+            profile.ticker
+        ),
+        String::new(),
+        format!(
+            "  Market cap:   {} / {}{}",
+            format_billions("€", profile.market_cap_eur),
+            format_billions("$", profile.market_cap_usd),
+            profile
+                .market_cap_date
+                .as_ref()
+                .map(|d| format!(" (as of {})", d))
+                .unwrap_or_default()
+        ),
+        format!("  Exchange:     {}", na(&profile.exchange)),
+        format!("  Currency:     {}", na(&profile.currency)),
+        format!("  CEO:          {}", na(&profile.ceo)),
+        format!("  Employees:    {}", na(&profile.employees)),
+        format!("  Website:      {}", na(&profile.homepage_url)),
+        format!(
+            "  ISIN / LEI:   {} / {}",
+            na(&profile.isin),
+            na(&profile.lei)
+        ),
+        String::new(),
+        format!(
+            "  P/E: {}  EPS: {}  ROE: {}  Debt/Equity: {}  Quick: {}  Current: {}",
+            format_ratio(profile.pe_ratio),
+            format_ratio(profile.eps),
+            format_ratio(profile.roe),
+            format_ratio(profile.debt_equity_ratio),
+            format_ratio(profile.quick_ratio),
+            format_ratio(profile.working_capital_ratio)
+        ),
+    ];
+
+    if let Some(description) = profile.description.as_deref().filter(|d| !d.is_empty()) {
+        lines.push(String::new());
+        lines.extend(
+            wrap(description, 76)
+                .into_iter()
+                .map(|l| format!("  {}", l)),
+        );
+    }
+
+    if let Some(updated_at) = profile.updated_at {
+        lines.push(String::new());
+        lines.push(format!(
+            "  Profile updated {} UTC",
+            updated_at.format("%Y-%m-%d %H:%M")
+        ));
+    }
+
+    lines.join("\n")
+}
+
+/// Print the company card for a ticker
+pub async fn show_company(pool: &SqlitePool, ticker: &str, force_refresh: bool) -> Result<()> {
+    let profile = get_company_profile(pool, ticker, force_refresh).await?;
+    println!("{}", format_card(&profile));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use chrono::NaiveDate;
+
+    fn at(hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2025, 1, 1)
+            .unwrap()
+            .and_hms_opt(hour, 0, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_is_fresh() {
+        assert!(is_fresh(at(0), at(23), 24));
+        assert!(!is_fresh(at(0), at(12), 12));
+    }
+
+    #[test]
+    fn test_format_card() {
+        let profile = CompanyProfile {
+            ticker: "NKE".to_string(),
+            name: Some("Nike, Inc.".to_string()),
+            ceo: Some("Elliott Hill".to_string()),
+            pe_ratio: Some(28.456),
+            market_cap_eur: Some(83_490_000_000.0),
+            market_cap_usd: Some(97_080_000_000.0),
+            market_cap_date: Some("2025-12-05".to_string()),
+            description: Some("Nike designs footwear. ".repeat(6)),
+            ..CompanyProfile::default()
+        };
+
+        let card = format_card(&profile);
+        assert!(card.starts_with("🏢 Nike, Inc. (NKE)"));
+        assert!(card.contains("€83.49B / $97.08B (as of 2025-12-05)"));
+        assert!(card.contains("CEO:          Elliott Hill"));
+        assert!(card.contains("ISIN / LEI:   NA / NA"));
+        assert!(card.contains("P/E: 28.46  EPS: NA"));
+        assert!(card.lines().all(|l| l.chars().count() <= 80));
+    }
+
+    #[tokio::test]
+    async fn test_cached_profile_roundtrip() {
+        let pool = db::create_db_pool("sqlite::memory:").await.unwrap();
+        // The ceo column migration is a no-op; existing databases have it added by hand
+        sqlx::query("ALTER TABLE ticker_details ADD COLUMN ceo TEXT")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let details = Details {
+            ticker: "NKE".to_string(),
+            market_cap: Some(1e11),
+            name: Some("Nike, Inc.".to_string()),
+            currency_name: None,
+            currency_symbol: Some("USD".to_string()),
+            active: Some(true),
+            description: None,
+            homepage_url: None,
+            weighted_shares_outstanding: None,
+            employees: Some("79400".to_string()),
+            revenue: None,
+            revenue_usd: None,
+            timestamp: None,
+            ceo: Some("Elliott Hill".to_string()),
+            country: Some("US".to_string()),
+            industry: Some("Apparel - Footwear & Accessories".to_string()),
+            isin: Some("US6541061031".to_string()),
+            lei: None,
+            working_capital_ratio: None,
+            quick_ratio: None,
+            eps: Some(2.5),
+            pe_ratio: Some(30.0),
+            debt_equity_ratio: None,
+            roe: None,
+            extra: [(
+                "exchange".to_string(),
+                serde_json::Value::String("NYSE".to_string()),
+            )]
+            .into_iter()
+            .collect(),
+        };
+        store_profile(&pool, &details).await.unwrap();
+
+        let profile = load_cached_profile(&pool, "NKE").await.unwrap().unwrap();
+        assert_eq!(profile.name.as_deref(), Some("Nike, Inc."));
+        assert_eq!(profile.exchange.as_deref(), Some("NYSE"));
+        assert_eq!(profile.employees.as_deref(), Some("79400"));
+        assert_eq!(profile.pe_ratio, Some(30.0));
+        assert_eq!(profile.isin.as_deref(), Some("US6541061031"));
+        assert!(profile.updated_at.is_some());
+    }
+}
@@ -0,0 +1,788 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! `company-report <TICKER> --from --to`: a one-company dossier
+//!
+//! Puts together what otherwise takes `rank-history`, `show`,
+//! `compare-peer-groups` and `list-symbol-changes`: the rank and market cap
+//! history between the two dates with its chart, the cached profile and
+//! ratios, the company's position in each peer group it belongs to and its
+//! performance against those groups and all companies, and the symbol changes
+//! involving the ticker. Performance converts the market caps of the latest
+//! exported snapshots on or before both dates at the rates of the end date, so
+//! currency moves don't count as growth; the reported USD change and the
+//! difference (the FX effect) are shown next to it. Without a snapshot on or
+//! before a date, the report leaves those sections empty. Writes `company_report_<TICKER>_<from>_to_<to>` as
+//! Markdown and HTML next to the chart.
+
+use anyhow::{Context, Result};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use sqlx::Row;
+use sqlx::sqlite::SqlitePool;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+
+use crate::advanced_comparisons::{self, MarketCapRecord};
+use crate::clock;
+use crate::company_profile::{self, CompanyProfile, format_billions, format_ratio};
+use crate::config::{self, OutputConfig};
+use crate::currencies::{convert_currency, get_rate_map_from_db_for_date};
+use crate::fmp_keys;
+use crate::notify::email;
+use crate::peer_momentum::aggregate_return;
+use crate::rankings::{self, RankPoint};
+use crate::ticker_aliases::{AppliedAliases, TickerAliases};
+use crate::vega;
+
+/// A `symbol_changes` row involving the company
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolChange {
+    pub old_symbol: String,
+    pub new_symbol: String,
+    pub change_date: Option<String>,
+    pub reason: Option<String>,
+    pub applied: bool,
+}
+
+/// USD market caps of one snapshot, per ticker
+#[derive(Debug, Clone, Default)]
+pub struct SnapshotCaps {
+    /// Converted at the end date's rates
+    pub constant_fx: HashMap<String, f64>,
+    /// As exported, at the snapshot's own rates
+    pub reported: HashMap<String, f64>,
+}
+
+impl SnapshotCaps {
+    fn from_records(records: &[MarketCapRecord], rates: &HashMap<String, f64>) -> Self {
+        let mut caps = Self::default();
+        for record in records {
+            if let Some(orig) = record.market_cap_original {
+                let currency = record.original_currency.as_deref().unwrap_or("USD");
+                let usd = if rates.is_empty() {
+                    record.market_cap_usd.unwrap_or(orig)
+                } else {
+                    convert_currency(orig, currency, "USD", rates)
+                };
+                caps.constant_fx.insert(record.ticker.clone(), usd);
+            }
+            if let Some(usd) = record.market_cap_usd {
+                caps.reported.insert(record.ticker.clone(), usd);
+            }
+        }
+        caps
+    }
+}
+
+/// Change of the combined market cap of a set of companies
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupPerformance {
+    pub name: String,
+    /// Companies listed on both dates
+    pub members: usize,
+    pub constant_fx: Option<f64>,
+    pub reported: Option<f64>,
+}
+
+impl GroupPerformance {
+    /// Performance of `tickers` (all companies when `None`)
+    pub fn compute(
+        name: &str,
+        from: &SnapshotCaps,
+        to: &SnapshotCaps,
+        tickers: Option<&HashSet<String>>,
+    ) -> Self {
+        let (constant_fx, members) = aggregate_return(&from.constant_fx, &to.constant_fx, tickers);
+        let (reported, _) = aggregate_return(&from.reported, &to.reported, tickers);
+        Self {
+            name: name.to_string(),
+            members,
+            constant_fx,
+            reported,
+        }
+    }
+
+    /// Reported minus constant-FX change, in percentage points
+    pub fn fx_effect(&self) -> Option<f64> {
+        Some(self.reported? - self.constant_fx?)
+    }
+}
+
+/// A company of a peer group on the end date
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerRow {
+    pub ticker: String,
+    pub name: String,
+    pub rank_from: Option<usize>,
+    pub rank_to: Option<usize>,
+    /// Constant-FX USD market cap on the end date
+    pub market_cap_to: Option<f64>,
+    pub change_pct: Option<f64>,
+}
+
+/// Members of one peer group, largest first
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerTable {
+    pub group: String,
+    pub rows: Vec<PeerRow>,
+}
+
+/// Everything the dossier shows
+#[derive(Debug, Clone, Default)]
+pub struct CompanyReport {
+    pub ticker: String,
+    pub name: String,
+    pub from_date: String,
+    pub to_date: String,
+    /// Rank points between the two dates, oldest first
+    pub history: Vec<RankPoint>,
+    /// Chart path relative to the report
+    pub chart: Option<String>,
+    pub profile: Option<CompanyProfile>,
+    /// The company first, then its peer groups without it, then all companies
+    pub performance: Vec<GroupPerformance>,
+    pub peers: Vec<PeerTable>,
+    pub symbol_changes: Vec<SymbolChange>,
+}
+
+fn pct(value: Option<f64>) -> String {
+    value
+        .map(|v| format!("{:+.2}%", v))
+        .unwrap_or_else(|| "N/A".to_string())
+}
+
+fn pp(value: Option<f64>) -> String {
+    value
+        .map(|v| format!("{:+.2}", v))
+        .unwrap_or_else(|| "N/A".to_string())
+}
+
+fn rank(value: Option<usize>) -> String {
+    value
+        .map(|r| format!("#{}", r))
+        .unwrap_or_else(|| "-".to_string())
+}
+
+impl CompanyReport {
+    fn history_section(&self) -> String {
+        let mut section = String::from("## Market Cap and Rank History\n\n");
+        let (Some(first), Some(last)) = (self.history.first(), self.history.last()) else {
+            section.push_str(&format!(
+                "No ranked snapshots of {} between {} and {}.\n\n",
+                self.ticker, self.from_date, self.to_date
+            ));
+            return section;
+        };
+        // Lowest rank number is the best; ties go to the earliest date
+        let best = self.history.iter().min_by_key(|p| p.rank).unwrap();
+        let worst = self.history.iter().max_by_key(|p| p.rank).unwrap();
+        section.push_str(&format!(
+            "- **Rank:** #{} on {} → #{} on {} (best #{} on {}, worst #{} on {})\n",
+            first.rank,
+            first.date,
+            last.rank,
+            last.date,
+            best.rank,
+            best.date,
+            worst.rank,
+            worst.date
+        ));
+        let change = match (first.market_cap_eur, last.market_cap_eur) {
+            (Some(from), Some(to)) if from > 0.0 => {
+                format!(" ({})", pct(Some((to - from) / from * 100.0)))
+            }
+            _ => String::new(),
+        };
+        section.push_str(&format!(
+            "- **Market cap (EUR):** {} → {}{}\n",
+            format_billions("€", first.market_cap_eur),
+            format_billions("€", last.market_cap_eur),
+            change
+        ));
+        section.push_str(&format!("- **Snapshots:** {}\n\n", self.history.len()));
+        if let Some(chart) = &self.chart {
+            if chart.ends_with(".svg") {
+                section.push_str(&format!("![Rank history]({})\n\n", chart));
+            } else {
+                section.push_str(&format!("[Rank history]({})\n\n", chart));
+            }
+        }
+        section
+    }
+
+    fn fundamentals_section(&self) -> String {
+        let mut section = String::from("## Fundamentals\n\n");
+        let Some(profile) = &self.profile else {
+            section.push_str(&format!(
+                "No cached profile: run `show {}` with an FMP API key set.\n\n",
+                self.ticker
+            ));
+            return section;
+        };
+        let na = |v: &Option<String>| v.clone().unwrap_or_else(|| "NA".to_string());
+        section.push_str("| Field | Value |\n|-------|-------|\n");
+        for (field, value) in [
+            ("Exchange", na(&profile.exchange)),
+            ("Currency", na(&profile.currency)),
+            ("CEO", na(&profile.ceo)),
+            ("Employees", na(&profile.employees)),
+            ("ISIN", na(&profile.isin)),
+            ("Website", na(&profile.homepage_url)),
+            ("P/E", format_ratio(profile.pe_ratio)),
+            ("EPS", format_ratio(profile.eps)),
+            ("ROE", format_ratio(profile.roe)),
+            ("Debt/Equity", format_ratio(profile.debt_equity_ratio)),
+            ("Quick ratio", format_ratio(profile.quick_ratio)),
+            ("Current ratio", format_ratio(profile.working_capital_ratio)),
+        ] {
+            section.push_str(&format!("| {} | {} |\n", field, value));
+        }
+        section.push('\n');
+        if let Some(updated_at) = profile.updated_at {
+            section.push_str(&format!(
+                "*Profile updated {} UTC*\n\n",
+                updated_at.format("%Y-%m-%d %H:%M")
+            ));
+        }
+        section
+    }
+
+    fn performance_section(&self) -> String {
+        let mut section = String::from("## Performance vs Peers\n\n");
+        section.push_str(&format!(
+            "Market caps in USD at the {} rates (constant FX), so currency moves don't count as growth; \
+             reported USD uses each date's rates. Groups count the companies listed on both dates, without {}.\n\n",
+            self.to_date, self.ticker
+        ));
+        let company = self.performance.first().and_then(|p| p.constant_fx);
+        section.push_str(&format!(
+            "| | Companies | Change (constant FX) | Change (reported USD) | FX Effect (pp) | {} vs Group (pp) |\n",
+            self.ticker
+        ));
+        section.push_str("|---|---:|---:|---:|---:|---:|\n");
+        for (i, group) in self.performance.iter().enumerate() {
+            let relative = match (i, company, group.constant_fx) {
+                (0, _, _) => "-".to_string(),
+                (_, Some(company), Some(group)) => pp(Some(company - group)),
+                _ => "N/A".to_string(),
+            };
+            section.push_str(&format!(
+                "| {} | {} | {} | {} | {} | {} |\n",
+                group.name,
+                group.members,
+                pct(group.constant_fx),
+                pct(group.reported),
+                pp(group.fx_effect()),
+                relative
+            ));
+        }
+        section.push('\n');
+        section
+    }
+
+    fn peers_section(&self) -> String {
+        let mut section = String::from("## Rank vs Peers\n\n");
+        if self.peers.is_empty() {
+            section.push_str(&format!("{} is not in any peer group.\n\n", self.ticker));
+            return section;
+        }
+        for table in &self.peers {
+            let position = table
+                .rows
+                .iter()
+                .position(|row| row.ticker == self.ticker)
+                .map(|i| format!(": #{} of {}", i + 1, table.rows.len()))
+                .unwrap_or_default();
+            section.push_str(&format!("### {}{}\n\n", table.group, position));
+            section.push_str(&format!(
+                "| # | Company | Ticker | Rank {} | Rank {} | Market Cap (USD) | Change |\n",
+                self.from_date, self.to_date
+            ));
+            section.push_str("|---:|---|---|---:|---:|---:|---:|\n");
+            for (i, row) in table.rows.iter().enumerate() {
+                let bold = |s: String| {
+                    if row.ticker == self.ticker {
+                        format!("**{}**", s)
+                    } else {
+                        s
+                    }
+                };
+                section.push_str(&format!(
+                    "| {} | {} | {} | {} | {} | {} | {} |\n",
+                    i + 1,
+                    bold(row.name.clone()),
+                    bold(row.ticker.clone()),
+                    rank(row.rank_from),
+                    rank(row.rank_to),
+                    format_billions("$", row.market_cap_to),
+                    pct(row.change_pct)
+                ));
+            }
+            section.push('\n');
+        }
+        section
+    }
+
+    fn symbol_changes_section(&self) -> String {
+        let mut section = String::from("## Symbol Changes\n\n");
+        if self.symbol_changes.is_empty() {
+            section.push_str(&format!(
+                "No symbol changes recorded for {}.\n\n",
+                self.ticker
+            ));
+            return section;
+        }
+        section.push_str(
+            "| Date | From | To | Reason | Status |\n|------|------|----|--------|--------|\n",
+        );
+        for change in &self.symbol_changes {
+            section.push_str(&format!(
+                "| {} | {} | {} | {} | {} |\n",
+                change.change_date.as_deref().unwrap_or("-"),
+                change.old_symbol,
+                change.new_symbol,
+                change.reason.as_deref().unwrap_or("-"),
+                if change.applied { "applied" } else { "pending" }
+            ));
+        }
+        section.push('\n');
+        section
+    }
+
+    /// The dossier as markdown
+    pub fn markdown(&self) -> String {
+        format!(
+            "# {} ({}): {} to {}\n\n{}{}{}{}{}---\n*Generated on {}*\n",
+            self.name,
+            self.ticker,
+            self.from_date,
+            self.to_date,
+            self.history_section(),
+            self.fundamentals_section(),
+            self.performance_section(),
+            self.peers_section(),
+            self.symbol_changes_section(),
+            clock::now().format("%Y-%m-%d %H:%M:%S")
+        )
+    }
+}
+
+/// Symbol changes from or to `ticker`, newest first
+async fn symbol_changes_for(pool: &SqlitePool, ticker: &str) -> Result<Vec<SymbolChange>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT old_symbol, new_symbol, change_date, reason, applied
+        FROM symbol_changes
+        WHERE old_symbol = ? OR new_symbol = ?
+        ORDER BY change_date DESC, id DESC
+        "#,
+    )
+    .bind(ticker)
+    .bind(ticker)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| SymbolChange {
+            old_symbol: row.get("old_symbol"),
+            new_symbol: row.get("new_symbol"),
+            change_date: row.get("change_date"),
+            reason: row.get("reason"),
+            applied: row.get::<Option<i64>, _>("applied").unwrap_or(0) != 0,
+        })
+        .collect())
+}
+
+/// Cached profile, refreshed from FMP when stale and an API key is set
+async fn load_profile(
+    pool: &SqlitePool,
+    ticker: &str,
+    force_refresh: bool,
+) -> Result<Option<CompanyProfile>> {
+    if force_refresh || fmp_keys::primary_key().is_some() {
+        return Ok(Some(
+            company_profile::get_company_profile(pool, ticker, force_refresh).await?,
+        ));
+    }
+    Ok(company_profile::load_cached_profile(pool, ticker)
+        .await?
+        .filter(|profile| profile.name.is_some()))
+}
+
+/// Latest of the (sorted) snapshot dates on or before `date`
+fn snapshot_on_or_before<'a>(available: &'a [String], date: &str) -> Option<&'a str> {
+    available
+        .iter()
+        .rev()
+        .find(|d| d.as_str() <= date)
+        .map(String::as_str)
+}
+
+/// Latest snapshot on or before `date` with renamed symbols under their
+/// current symbol; empty when there is none
+fn load_snapshot(
+    available: &[String],
+    date: &str,
+    aliases: &TickerAliases,
+    applied: &mut AppliedAliases,
+) -> Result<Vec<MarketCapRecord>> {
+    let Some(snapshot) = snapshot_on_or_before(available, date) else {
+        println!(
+            "⚠️  No exported snapshot on or before {}: performance and peer sections stay empty",
+            date
+        );
+        return Ok(Vec::new());
+    };
+    if snapshot != date {
+        println!("Using the snapshot of {} for {}", snapshot, date);
+    }
+    let mut records = advanced_comparisons::read_market_cap_csv(
+        &advanced_comparisons::find_csv_for_date(snapshot, None)?,
+    )?;
+    aliases.apply(
+        NaiveDate::parse_from_str(snapshot, "%Y-%m-%d")?,
+        &mut records,
+        |r| &mut r.ticker,
+        applied,
+    );
+    Ok(records)
+}
+
+/// Build the dossier of `ticker` from `from_date` to `to_date` and write it
+/// as markdown and HTML
+pub async fn company_report(
+    pool: &SqlitePool,
+    ticker: &str,
+    from_date: &str,
+    to_date: &str,
+    force_refresh: bool,
+) -> Result<()> {
+    let ticker = ticker.trim().to_uppercase();
+    let ticker = ticker.as_str();
+    let from = NaiveDate::parse_from_str(from_date, "%Y-%m-%d")
+        .context("Invalid --from date. Use YYYY-MM-DD")?;
+    let to = NaiveDate::parse_from_str(to_date, "%Y-%m-%d")
+        .context("Invalid --to date. Use YYYY-MM-DD")?;
+    if from >= to {
+        anyhow::bail!("--from must be before --to");
+    }
+    println!(
+        "📋 Company report for {} from {} to {}",
+        ticker, from_date, to_date
+    );
+
+    let backfilled = rankings::backfill_rankings(pool).await?;
+    if backfilled > 0 {
+        println!("Computed rankings for {} earlier snapshots", backfilled);
+    }
+    let history: Vec<RankPoint> = rankings::get_rank_history(pool, ticker)
+        .await?
+        .into_iter()
+        .filter(|p| p.date.as_str() >= from_date && p.date.as_str() <= to_date)
+        .collect();
+
+    // Constant rates of the end date, like compare-peer-groups
+    let to_timestamp = NaiveDateTime::new(to, NaiveTime::default())
+        .and_utc()
+        .timestamp();
+    let rates = get_rate_map_from_db_for_date(pool, Some(to_timestamp)).await?;
+    let aliases = TickerAliases::load(pool).await?;
+    let mut applied = AppliedAliases::default();
+    let available = advanced_comparisons::get_available_dates(None)?;
+    let from_records = load_snapshot(&available, from_date, &aliases, &mut applied)?;
+    let to_records = load_snapshot(&available, to_date, &aliases, &mut applied)?;
+    let from_caps = SnapshotCaps::from_records(&from_records, &rates);
+    let to_caps = SnapshotCaps::from_records(&to_records, &rates);
+
+    let from_map: HashMap<&str, &MarketCapRecord> = from_records
+        .iter()
+        .map(|r| (r.ticker.as_str(), r))
+        .collect();
+    let to_map: HashMap<&str, &MarketCapRecord> =
+        to_records.iter().map(|r| (r.ticker.as_str(), r)).collect();
+    let name = to_map
+        .get(ticker)
+        .or_else(|| from_map.get(ticker))
+        .map(|r| r.name.clone());
+    if name.is_none() && history.is_empty() {
+        anyhow::bail!(
+            "No stored market caps found for {} between {} and {}",
+            ticker,
+            from_date,
+            to_date
+        );
+    }
+
+    let company = HashSet::from([ticker.to_string()]);
+    let mut performance = vec![GroupPerformance::compute(
+        ticker,
+        &from_caps,
+        &to_caps,
+        Some(&company),
+    )];
+    let mut peers = Vec::new();
+    for group in advanced_comparisons::get_predefined_peer_groups() {
+        let tickers: HashSet<String> = group
+            .tickers
+            .iter()
+            .map(|t| aliases.current_symbol(t, from).to_string())
+            .collect();
+        if !tickers.contains(ticker) {
+            continue;
+        }
+        let others: HashSet<String> = tickers.iter().filter(|t| *t != ticker).cloned().collect();
+        performance.push(GroupPerformance::compute(
+            &group.name,
+            &from_caps,
+            &to_caps,
+            Some(&others),
+        ));
+
+        let mut rows: Vec<PeerRow> = tickers
+            .iter()
+            .filter(|t| from_map.contains_key(t.as_str()) || to_map.contains_key(t.as_str()))
+            .map(|t| {
+                let (from_record, to_record) = (from_map.get(t.as_str()), to_map.get(t.as_str()));
+                let (start, end) = (from_caps.constant_fx.get(t), to_caps.constant_fx.get(t));
+                PeerRow {
+                    ticker: t.clone(),
+                    name: to_record
+                        .or(from_record)
+                        .map(|r| r.name.clone())
+                        .unwrap_or_else(|| t.clone()),
+                    rank_from: from_record.and_then(|r| r.rank),
+                    rank_to: to_record.and_then(|r| r.rank),
+                    market_cap_to: end.copied(),
+                    change_pct: match (start, end) {
+                        (Some(start), Some(end)) if *start > 0.0 => {
+                            Some((end - start) / start * 100.0)
+                        }
+                        _ => None,
+                    },
+                }
+            })
+            .collect();
+        rows.sort_by(|a, b| {
+            rankings::rank_order(
+                (a.market_cap_to, &a.name, &a.ticker),
+                (b.market_cap_to, &b.name, &b.ticker),
+            )
+        });
+        peers.push(PeerTable {
+            group: group.name,
+            rows,
+        });
+    }
+    performance.push(GroupPerformance::compute(
+        "All companies",
+        &from_caps,
+        &to_caps,
+        None,
+    ));
+
+    let output = config::load_output_config();
+    output.ensure_directory()?;
+    let range = format!("{}_{}_to_{}", ticker, from_date, to_date);
+    let chart = if history.is_empty() {
+        None
+    } else {
+        let path = output.named_path(&format!(
+            "company_report_{}_rank_history.{}",
+            range,
+            vega::extension()
+        ));
+        rankings::create_rank_history_chart(ticker, &history, &path)?;
+        path.file_name()
+            .map(|name| name.to_string_lossy().to_string())
+    };
+
+    let report = CompanyReport {
+        ticker: ticker.to_string(),
+        name: name.unwrap_or_else(|| ticker.to_string()),
+        from_date: from_date.to_string(),
+        to_date: to_date.to_string(),
+        history,
+        chart,
+        profile: load_profile(pool, ticker, force_refresh).await?,
+        performance,
+        peers,
+        symbol_changes: symbol_changes_for(pool, ticker).await?,
+    };
+    write_report(&report, &output, &range)
+}
+
+fn write_report(report: &CompanyReport, output: &OutputConfig, range: &str) -> Result<()> {
+    let markdown = report.markdown();
+    let timestamp = OutputConfig::timestamp();
+    for (ext, body) in [
+        ("md", markdown.clone()),
+        ("html", email::markdown_to_html(&markdown)),
+    ] {
+        let path = output.file_path_at("company_report", range, &timestamp, ext);
+        fs::write(&path, body).with_context(|| format!("Failed to write {}", path.display()))?;
+        println!("✅ Company report written to {}", path.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    fn caps(entries: &[(&str, f64, f64)]) -> SnapshotCaps {
+        SnapshotCaps {
+            constant_fx: entries
+                .iter()
+                .map(|(t, c, _)| (t.to_string(), *c))
+                .collect(),
+            reported: entries
+                .iter()
+                .map(|(t, _, r)| (t.to_string(), *r))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_group_performance_separates_fx_effect() {
+        // A weaker dollar lifts the reported USD value of the euro listing
+        let from = caps(&[
+            ("NKE", 100.0, 100.0),
+            ("ITX.MC", 100.0, 90.0),
+            ("HM-B.ST", 50.0, 50.0),
+        ]);
+        let to = caps(&[("NKE", 110.0, 110.0), ("ITX.MC", 120.0, 120.0)]);
+
+        let company = HashSet::from(["NKE".to_string()]);
+        let nke = GroupPerformance::compute("NKE", &from, &to, Some(&company));
+        assert_eq!(nke.members, 1);
+        assert!((nke.constant_fx.unwrap() - 10.0).abs() < 1e-9);
+        assert!(nke.fx_effect().unwrap().abs() < 1e-9);
+
+        // HM-B.ST is missing on the end date and doesn't count
+        let peers = HashSet::from(["ITX.MC".to_string(), "HM-B.ST".to_string()]);
+        let group = GroupPerformance::compute("Fast Fashion", &from, &to, Some(&peers));
+        assert_eq!(group.members, 1);
+        assert!((group.constant_fx.unwrap() - 20.0).abs() < 1e-9);
+        assert!((group.reported.unwrap() - 100.0 / 3.0).abs() < 1e-9);
+        assert!((group.fx_effect().unwrap() - 40.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_snapshot_on_or_before_picks_latest_earlier_date() {
+        let available = ["2024-12-31", "2025-01-31", "2025-02-28"].map(String::from);
+        assert_eq!(
+            snapshot_on_or_before(&available, "2025-01-31"),
+            Some("2025-01-31")
+        );
+        assert_eq!(
+            snapshot_on_or_before(&available, "2025-02-15"),
+            Some("2025-01-31")
+        );
+        assert_eq!(snapshot_on_or_before(&available, "2024-06-30"), None);
+    }
+
+    #[test]
+    fn test_markdown_has_every_section() {
+        let report = CompanyReport {
+            ticker: "NKE".to_string(),
+            name: "Nike".to_string(),
+            from_date: "2023-01-01".to_string(),
+            to_date: "2025-01-01".to_string(),
+            history: vec![
+                RankPoint {
+                    date: "2023-01-02".to_string(),
+                    timestamp: 0,
+                    rank: 3,
+                    market_cap_eur: Some(100e9),
+                    market_cap_usd: None,
+                },
+                RankPoint {
+                    date: "2024-01-02".to_string(),
+                    timestamp: 1,
+                    rank: 2,
+                    market_cap_eur: Some(120e9),
+                    market_cap_usd: None,
+                },
+                RankPoint {
+                    date: "2024-12-31".to_string(),
+                    timestamp: 2,
+                    rank: 4,
+                    market_cap_eur: Some(90e9),
+                    market_cap_usd: None,
+                },
+            ],
+            chart: Some("company_report_NKE_rank_history.svg".to_string()),
+            profile: None,
+            performance: vec![
+                GroupPerformance {
+                    name: "NKE".to_string(),
+                    members: 1,
+                    constant_fx: Some(-10.0),
+                    reported: Some(-8.0),
+                },
+                GroupPerformance {
+                    name: "Sportswear".to_string(),
+                    members: 4,
+                    constant_fx: Some(5.0),
+                    reported: None,
+                },
+            ],
+            peers: vec![PeerTable {
+                group: "Sportswear".to_string(),
+                rows: vec![
+                    PeerRow {
+                        ticker: "ADS.DE".to_string(),
+                        name: "Adidas".to_string(),
+                        rank_from: Some(5),
+                        rank_to: Some(3),
+                        market_cap_to: Some(40e9),
+                        change_pct: Some(20.0),
+                    },
+                    PeerRow {
+                        ticker: "NKE".to_string(),
+                        name: "Nike".to_string(),
+                        rank_from: Some(3),
+                        rank_to: Some(4),
+                        market_cap_to: Some(30e9),
+                        change_pct: Some(-10.0),
+                    },
+                ],
+            }],
+            symbol_changes: Vec::new(),
+        };
+        let markdown = report.markdown();
+        assert!(markdown.starts_with("# Nike (NKE): 2023-01-01 to 2025-01-01\n"));
+        assert!(markdown.contains(
+            "- **Rank:** #3 on 2023-01-02 → #4 on 2024-12-31 (best #2 on 2024-01-02, worst #4 on 2024-12-31)\n"
+        ));
+        assert!(markdown.contains("- **Market cap (EUR):** €100.00B → €90.00B (-10.00%)\n"));
+        assert!(markdown.contains("![Rank history](company_report_NKE_rank_history.svg)"));
+        assert!(markdown.contains("No cached profile: run `show NKE`"));
+        assert!(markdown.contains("| NKE | 1 | -10.00% | -8.00% | +2.00 | - |\n"));
+        assert!(markdown.contains("| Sportswear | 4 | +5.00% | N/A | N/A | -15.00 |\n"));
+        assert!(markdown.contains("### Sportswear: #2 of 2\n"));
+        assert!(markdown.contains("| 2 | **Nike** | **NKE** | #3 | #4 | $30.00B | -10.00% |\n"));
+        assert!(markdown.contains("No symbol changes recorded for NKE."));
+    }
+
+    #[tokio::test]
+    async fn test_symbol_changes_for_ticker() {
+        let pool = db::create_db_pool("sqlite::memory:").await.unwrap();
+        sqlx::query(
+            "INSERT INTO symbol_changes (old_symbol, new_symbol, change_date, reason, applied) VALUES ('FB', 'META', '2022-06-09', 'Rebrand', 1), ('META', 'MTA', '2025-01-21', NULL, 0), ('SQ', 'XYZ', '2025-01-21', NULL, 0)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let changes = symbol_changes_for(&pool, "META").await.unwrap();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].new_symbol, "MTA");
+        assert!(!changes[0].applied);
+        assert_eq!(changes[1].old_symbol, "FB");
+        assert_eq!(changes[1].reason.as_deref(), Some("Rebrand"));
+        assert!(changes[1].applied);
+    }
+}
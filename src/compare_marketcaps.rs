@@ -2,122 +2,238 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-only
 
+use crate::advanced_comparisons;
+use crate::clock;
+use crate::concentration::{self, Concentration};
+use crate::config::{self, OutputConfig};
+use crate::corporate_actions::CorporateActionIndex;
+use crate::currencies::{
+    ensure_report_rates, extra_report_currencies, get_rate_map_from_db_for_date,
+    report_currency_values,
+};
+use crate::data_package;
+use crate::earnings::EarningsIndex;
+use crate::error::Error;
+use crate::locale;
+use crate::notify::{self, Mover, RunSummary};
+use crate::outliers;
+use crate::progress;
+use crate::rankings;
+use crate::regions;
+use crate::snapshot_labels::{self, Snapshot};
+use crate::ticker_aliases::{AppliedAliases, TickerAliases};
+use crate::universe;
+use crate::utils;
+use crate::vega;
+use crate::visualizations;
+use crate::watchlists;
 use anyhow::{Context, Result};
-use chrono::Local;
-use csv::{Reader, Writer};
-use indicatif::{ProgressBar, ProgressStyle};
-use serde::Deserialize;
-use std::collections::HashMap;
+use chrono::{NaiveDate, NaiveTime};
+use csv::Writer;
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use sqlx::sqlite::SqlitePool;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs::File;
 use std::io::Write as IoWrite;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use top200_core::comparison;
 
 #[derive(Debug, Deserialize)]
-struct MarketCapRecord {
+pub struct MarketCapRecord {
     #[serde(rename = "Rank")]
-    rank: Option<usize>,
+    pub rank: Option<usize>,
     #[serde(rename = "Ticker")]
-    ticker: String,
+    pub ticker: String,
     #[serde(rename = "Name")]
-    name: String,
+    pub name: String,
     #[serde(rename = "Market Cap (Original)")]
-    market_cap_original: Option<f64>,
+    pub market_cap_original: Option<f64>,
     #[serde(rename = "Original Currency")]
-    original_currency: Option<String>,
+    pub original_currency: Option<String>,
     #[serde(rename = "Market Cap (EUR)")]
-    market_cap_eur: Option<f64>,
+    pub market_cap_eur: Option<f64>,
     #[serde(rename = "Market Cap (USD)")]
-    market_cap_usd: Option<f64>,
+    pub market_cap_usd: Option<f64>,
+    /// Listing exchange; missing in older exports
+    #[serde(rename = "Exchange", default)]
+    pub exchange: Option<String>,
 }
 
-#[derive(Debug)]
-struct MarketCapComparison {
-    ticker: String,
-    name: String,
-    original_currency: Option<String>,
-    market_cap_from: Option<f64>,
-    market_cap_to: Option<f64>,
-    absolute_change: Option<f64>,
-    percentage_change: Option<f64>,
-    rank_from: Option<usize>,
-    rank_to: Option<usize>,
-    rank_change: Option<i32>,
-    market_share_from: Option<f64>,
-    market_share_to: Option<f64>,
+/// One row of the comparison CSV; serializes with the CSV column names
+#[derive(Debug, Serialize)]
+pub struct MarketCapComparison {
+    #[serde(rename = "Ticker")]
+    pub ticker: String,
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Currency")]
+    pub original_currency: Option<String>,
+    #[serde(rename = "Market Cap From")]
+    pub market_cap_from: Option<f64>,
+    #[serde(rename = "Market Cap To")]
+    pub market_cap_to: Option<f64>,
+    #[serde(rename = "Absolute Change")]
+    pub absolute_change: Option<f64>,
+    #[serde(rename = "Percentage Change (%)")]
+    pub percentage_change: Option<f64>,
+    #[serde(rename = "Rank From")]
+    pub rank_from: Option<usize>,
+    #[serde(rename = "Rank To")]
+    pub rank_to: Option<usize>,
+    #[serde(rename = "Rank Change")]
+    pub rank_change: Option<i32>,
+    #[serde(rename = "Market Share From (%)")]
+    pub market_share_from: Option<f64>,
+    #[serde(rename = "Market Share To (%)")]
+    pub market_share_to: Option<f64>,
+    /// Market cap from/to per extra report currency, formatted for the CSV
+    #[serde(skip)]
+    pub report_values: Vec<(String, String)>,
+    /// Corporate actions in the period that affect this company
+    #[serde(rename = "Corporate Action")]
+    pub corporate_action: Option<String>,
+    /// Earlier symbols of a renamed company, from `symbol_changes`
+    #[serde(rename = "Formerly")]
+    pub formerly: Option<String>,
+    /// Earnings reports between the two dates, from `earnings_calendar`
+    #[serde(rename = "Earnings")]
+    pub earnings: Option<String>,
+}
+
+/// Rate map for a comparison side: rates on or before its snapshot, i.e.
+/// midnight UTC of a date or the time of a labeled snapshot
+async fn rate_map_for_date(pool: &SqlitePool, spec: &str) -> Result<Arc<HashMap<String, f64>>> {
+    let (date, _) = snapshot_labels::split_spec(spec)?;
+    let snapshot = match snapshot_labels::resolve(pool, spec).await? {
+        Some(snapshot) => snapshot,
+        None => Snapshot::daily(NaiveDate::parse_from_str(date, "%Y-%m-%d")?),
+    };
+    get_rate_map_from_db_for_date(pool, Some(snapshot.timestamp)).await
 }
 
 /// Find the most recent CSV file for a given date
-fn find_csv_for_date(date: &str) -> Result<String> {
-    let output_dir = Path::new("output");
-    let pattern = format!("marketcaps_{}_", date);
-
-    let mut matching_files = Vec::new();
-    for entry in std::fs::read_dir(output_dir)? {
-        let entry = entry?;
-        let file_name = entry.file_name();
-        let file_name_str = file_name.to_string_lossy();
-
-        if file_name_str.starts_with(&pattern) && file_name_str.ends_with(".csv") {
-            matching_files.push(file_name_str.to_string());
+fn find_csv_for_date(date: &str, output: &OutputConfig, watchlist: Option<&str>) -> Result<String> {
+    let kind = watchlists::scoped_kind(watchlist, "marketcaps");
+    match output.find_latest(&kind, date, "csv")? {
+        Some(path) => Ok(path.display().to_string()),
+        None => Err(Error::CsvNotFound {
+            date: date.to_string(),
+            watchlist: watchlist.map(str::to_string),
         }
+        .into()),
     }
+}
 
-    if matching_files.is_empty() {
-        anyhow::bail!(
-            "No CSV file found for date {}. Please run 'fetch-specific-date-market-caps {}' first.",
-            date,
-            date
-        );
-    }
-
-    // Sort to get the most recent file (by filename timestamp)
-    matching_files.sort();
-    let selected_file = matching_files.last().unwrap();
-
-    Ok(format!("output/{}", selected_file))
+/// Load a stored snapshot from SQLite, ranked by EUR market cap like the
+/// market cap CSV. Restricted to the recorded universe for the date, if any.
+pub async fn load_market_cap_records(
+    pool: &SqlitePool,
+    date: &str,
+) -> Result<Vec<MarketCapRecord>> {
+    let timestamp = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map(|d| d.and_time(NaiveTime::MIN).and_utc().timestamp())
+        .with_context(|| format!("Invalid date format: {}", date))?;
+
+    let rows = sqlx::query(
+        r#"
+        SELECT
+            ticker,
+            name,
+            CAST(market_cap_original AS REAL) as market_cap_original,
+            original_currency,
+            CAST(market_cap_eur AS REAL) as market_cap_eur,
+            CAST(market_cap_usd AS REAL) as market_cap_usd,
+            exchange
+        FROM market_caps
+        WHERE timestamp = ?
+        ORDER BY market_cap_eur DESC, name, ticker
+        "#,
+    )
+    .bind(timestamp)
+    .fetch_all(pool)
+    .await?;
+
+    let universe = universe::get_universe(pool, date).await?;
+    let records = rows
+        .into_iter()
+        .map(|row| MarketCapRecord {
+            rank: None,
+            ticker: row.get("ticker"),
+            name: row.get("name"),
+            market_cap_original: row.get("market_cap_original"),
+            original_currency: row.get("original_currency"),
+            market_cap_eur: row.get("market_cap_eur"),
+            market_cap_usd: row.get("market_cap_usd"),
+            exchange: row.get("exchange"),
+        })
+        .filter(|r| universe.as_ref().is_none_or(|u| u.contains(&r.ticker)))
+        .enumerate()
+        .map(|(index, record)| MarketCapRecord {
+            rank: Some(index + 1),
+            ..record
+        })
+        .collect();
+    Ok(records)
 }
 
 /// Read market cap data from CSV file
-fn read_market_cap_csv(file_path: &str) -> Result<Vec<MarketCapRecord>> {
-    let file =
-        File::open(file_path).with_context(|| format!("Failed to open CSV file: {}", file_path))?;
-
-    let mut reader = Reader::from_reader(file);
-    let mut records = Vec::new();
-
-    for result in reader.deserialize() {
-        let record: MarketCapRecord = result?;
-        records.push(record);
-    }
-
-    Ok(records)
+pub fn read_market_cap_csv(file_path: &str) -> Result<Vec<MarketCapRecord>> {
+    advanced_comparisons::market_cap_csv_records(file_path)?.collect()
 }
 
 /// Calculate market share for each company
-fn calculate_market_shares(records: &[MarketCapRecord]) -> HashMap<String, f64> {
-    let total_market_cap: f64 = records.iter().filter_map(|r| r.market_cap_usd).sum();
-
-    let mut shares = HashMap::new();
-
-    if total_market_cap > 0.0 {
-        for record in records {
-            if let Some(market_cap) = record.market_cap_usd {
-                let share = (market_cap / total_market_cap) * 100.0;
-                shares.insert(record.ticker.clone(), share);
-            }
-        }
-    }
-
-    shares
+pub fn calculate_market_shares(records: &[MarketCapRecord]) -> HashMap<String, f64> {
+    comparison::market_shares(
+        records
+            .iter()
+            .map(|r| (r.ticker.as_str(), r.market_cap_usd)),
+    )
 }
 
 /// Compare market caps between two dates
-pub async fn compare_market_caps(from_date: &str, to_date: &str) -> Result<()> {
+#[allow(clippy::too_many_arguments)]
+pub async fn compare_market_caps(
+    pool: &SqlitePool,
+    from_date: &str,
+    to_date: &str,
+    report_currencies: &[String],
+    watchlist: Option<&str>,
+    consistent_universe: bool,
+    exclude_corporate_actions: bool,
+    with_charts: bool,
+) -> Result<()> {
     println!("Comparing market caps from {} to {}", from_date, to_date);
+    if let Some(name) = watchlist {
+        println!("Restricted to watchlist: {}", name);
+    }
+    if consistent_universe && watchlist.is_some() {
+        anyhow::bail!(
+            "--consistent-universe applies to the config universe and cannot be combined with --watchlist"
+        );
+    }
+    // A side may be a labeled intraday snapshot (`2025-08-01@close`): its
+    // file is selected by the full spec, aliases, universe and events by the day
+    let (from_day, _) = snapshot_labels::split_spec(from_date)?;
+    let (to_day, _) = snapshot_labels::split_spec(to_date)?;
+
+    let output = config::load_output_config();
+    let report_currencies = extra_report_currencies(report_currencies);
+    let (from_rates, to_rates) = if report_currencies.is_empty() {
+        (Arc::default(), Arc::default())
+    } else {
+        (
+            rate_map_for_date(pool, from_date).await?,
+            rate_map_for_date(pool, to_date).await?,
+        )
+    };
+    ensure_report_rates(&report_currencies, &from_rates)?;
+    ensure_report_rates(&report_currencies, &to_rates)?;
 
     // Find CSV files for both dates
-    let from_file = find_csv_for_date(from_date)?;
-    let to_file = find_csv_for_date(to_date)?;
+    let from_file = find_csv_for_date(from_date, &output, watchlist)?;
+    let to_file = find_csv_for_date(to_date, &output, watchlist)?;
 
     println!("Using files:");
     println!("  From: {}", from_file);
@@ -126,25 +242,83 @@ pub async fn compare_market_caps(from_date: &str, to_date: &str) -> Result<()> {
     println!("\n📊 Comparing market caps using original currency values...");
 
     // Read data from both files
-    let progress = ProgressBar::new(4);
-    progress.set_style(
-        ProgressStyle::default_bar()
-            .template("[{elapsed_precise}] {bar:40.cyan/blue} {msg}")
-            .unwrap()
-            .progress_chars("=>-"),
-    );
+    let progress = progress::step_bar(4, "compare");
 
     progress.set_message("Reading from date CSV...");
-    let from_records = read_market_cap_csv(&from_file)?;
+    let mut from_records = read_market_cap_csv(&from_file)?;
     progress.inc(1);
 
     progress.set_message("Reading to date CSV...");
-    let to_records = read_market_cap_csv(&to_file)?;
+    let mut to_records = read_market_cap_csv(&to_file)?;
     progress.inc(1);
 
-    // Create lookup maps
-    let mut from_map: HashMap<String, MarketCapRecord> = HashMap::new();
-    let mut to_map: HashMap<String, MarketCapRecord> = HashMap::new();
+    // Only compare the N largest companies of each date under --top
+    rankings::truncate_to_top(&mut from_records);
+    rankings::truncate_to_top(&mut to_records);
+
+    // Renamed symbols (FB -> META) are compared under their current symbol
+    let aliases = TickerAliases::load(pool).await?;
+    let mut applied_aliases = AppliedAliases::default();
+    for (date, records) in [(from_day, &mut from_records), (to_day, &mut to_records)] {
+        let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .with_context(|| format!("Invalid date format: {}", date))?;
+        aliases.apply(date, records, |r| &mut r.ticker, &mut applied_aliases);
+    }
+
+    // Only keep companies tracked on both dates so universe edits don't skew totals
+    let universe_diff = if consistent_universe {
+        let diff = universe::consistent_universe(
+            pool,
+            &[
+                (
+                    from_day.to_string(),
+                    from_records.iter().map(|r| r.ticker.clone()).collect(),
+                ),
+                (
+                    to_day.to_string(),
+                    to_records.iter().map(|r| r.ticker.clone()).collect(),
+                ),
+            ],
+            &aliases,
+        )
+        .await?;
+        from_records.retain(|r| diff.contains(&r.ticker));
+        to_records.retain(|r| diff.contains(&r.ticker));
+        Some(diff)
+    } else {
+        None
+    };
+
+    // M&A and similar events distort the change of the companies involved
+    let corporate_actions = CorporateActionIndex::load_for_period(from_day, to_day)?;
+    if exclude_corporate_actions && !corporate_actions.is_empty() {
+        from_records.retain(|r| !corporate_actions.is_affected(&r.ticker));
+        to_records.retain(|r| !corporate_actions.is_affected(&r.ticker));
+        println!("Excluding companies affected by corporate actions in this period");
+    }
+
+    // Small caps with outsized moves would dominate the tables under --min-market-cap
+    let outliers = outliers::current();
+    let below_floor = outliers.below_floor_tickers(
+        from_records
+            .iter()
+            .map(|r| (r.ticker.as_str(), r.market_cap_usd)),
+        to_records
+            .iter()
+            .map(|r| (r.ticker.as_str(), r.market_cap_usd)),
+    );
+    if !below_floor.is_empty() {
+        from_records.retain(|r| !below_floor.contains(&r.ticker));
+        to_records.retain(|r| !below_floor.contains(&r.ticker));
+        println!(
+            "Excluding {} companies below the market cap floor",
+            below_floor.len()
+        );
+    }
+
+    // Create lookup maps, ordered so totals add up the same way every run
+    let mut from_map: BTreeMap<String, MarketCapRecord> = BTreeMap::new();
+    let mut to_map: BTreeMap<String, MarketCapRecord> = BTreeMap::new();
 
     for record in from_records.iter() {
         from_map.insert(
@@ -157,6 +331,7 @@ pub async fn compare_market_caps(from_date: &str, to_date: &str) -> Result<()> {
                 original_currency: record.original_currency.clone(),
                 market_cap_eur: record.market_cap_eur,
                 market_cap_usd: record.market_cap_usd,
+                exchange: record.exchange.clone(),
             },
         );
     }
@@ -172,35 +347,261 @@ pub async fn compare_market_caps(from_date: &str, to_date: &str) -> Result<()> {
                 original_currency: record.original_currency.clone(),
                 market_cap_eur: record.market_cap_eur,
                 market_cap_usd: record.market_cap_usd,
+                exchange: record.exchange.clone(),
             },
         );
     }
 
-    // Calculate market shares
-    progress.set_message("Calculating market shares...");
-    let from_shares = calculate_market_shares(&from_records);
-    let to_shares = calculate_market_shares(&to_records);
-    progress.inc(1);
+    // Earnings reports usually explain the largest moves
+    let earnings = EarningsIndex::load_for_period(pool, from_day, to_day).await?;
 
-    // Build comparison data
     progress.set_message("Analyzing changes...");
+    progress.inc(2);
+    progress.finish_with_message("Analysis complete");
+
+    // Universe and corporate action notes for the top of the summary
+    let mut report_notes = String::new();
+    if outliers.is_active() {
+        report_notes.push_str(&outliers.markdown_note(&below_floor, true));
+    }
+    if let Some(diff) = &universe_diff {
+        report_notes.push_str(&diff.markdown_note());
+    }
+    if !corporate_actions.is_empty() {
+        report_notes.push_str(&corporate_actions.markdown_section(exclude_corporate_actions));
+    }
+    if !applied_aliases.is_empty() {
+        report_notes.push_str(&applied_aliases.markdown_section());
+    }
+    if !earnings.is_empty() {
+        report_notes.push_str(&earnings.markdown_section());
+    }
+
+    // Export the comparison CSV and summary report
+    let kind = watchlists::scoped_kind(watchlist, "comparison");
+    let report = ComparisonReport {
+        from_date,
+        to_date,
+        output: &output,
+        kind: &kind,
+        corporate_actions: &corporate_actions,
+        aliases: &applied_aliases,
+        earnings: &earnings,
+        report_currencies: &report_currencies,
+        from_rates: &from_rates,
+        to_rates: &to_rates,
+        notes: &report_notes,
+        with_charts,
+        inputs: &[from_file, to_file],
+    };
+    let comparisons = report.write(&from_records, &to_records)?;
+
+    // Under --with-charts the report already drew it for the summary
+    if !with_charts {
+        write_waterfall_chart(
+            &from_records,
+            &to_records,
+            from_date,
+            to_date,
+            &output,
+            &kind,
+        )?;
+    }
+
+    // Ping Slack/Teams when configured and the moves are large enough
+    let summary = build_run_summary(&comparisons, &from_map, &to_map, from_date, to_date);
+    if let Err(e) = notify::notify_run(summary).await {
+        eprintln!("⚠️  Webhook notification failed: {:#}", e);
+    }
+
+    Ok(())
+}
+
+/// Comparison of two loaded snapshots and where to write it. Everything that
+/// depends on the environment (config, database, corporate_actions.toml) is
+/// resolved by the caller, so tests can run it against fixtures.
+pub struct ComparisonReport<'a> {
+    pub from_date: &'a str,
+    pub to_date: &'a str,
+    pub output: &'a OutputConfig,
+    pub kind: &'a str,
+    pub corporate_actions: &'a CorporateActionIndex,
+    /// Renamed symbols re-keyed while loading the snapshots
+    pub aliases: &'a AppliedAliases,
+    /// Earnings reports between the two dates
+    pub earnings: &'a EarningsIndex,
+    pub report_currencies: &'a [String],
+    pub from_rates: &'a HashMap<String, f64>,
+    pub to_rates: &'a HashMap<String, f64>,
+    /// Universe and corporate action notes for the top of the summary
+    pub notes: &'a str,
+    /// Also draw the `generate-charts` charts and the waterfall, and embed
+    /// them in the summary
+    pub with_charts: bool,
+    /// Snapshot CSVs the records were read from, for the data package
+    pub inputs: &'a [String],
+}
+
+impl ComparisonReport<'_> {
+    /// Compare the snapshots and write the comparison CSV and markdown summary
+    /// (and the charts under `with_charts`)
+    pub fn write(
+        &self,
+        from_records: &[MarketCapRecord],
+        to_records: &[MarketCapRecord],
+    ) -> Result<Vec<MarketCapComparison>> {
+        let comparisons = build_comparisons(
+            from_records,
+            to_records,
+            RowAnnotations {
+                corporate_actions: self.corporate_actions,
+                aliases: self.aliases,
+                earnings: self.earnings,
+            },
+            self.report_currencies,
+            self.from_rates,
+            self.to_rates,
+        );
+        let csv_path = export_comparison_csv(
+            &comparisons,
+            self.from_date,
+            self.to_date,
+            self.output,
+            self.kind,
+            self.report_currencies,
+            self.inputs,
+        )?;
+        let mut charts = Vec::new();
+        if self.with_charts {
+            charts = visualizations::write_comparison_charts(
+                &csv_path,
+                self.from_date,
+                self.to_date,
+                self.output,
+            )?;
+            charts.push((
+                "waterfall",
+                write_waterfall_chart(
+                    from_records,
+                    to_records,
+                    self.from_date,
+                    self.to_date,
+                    self.output,
+                    self.kind,
+                )?,
+            ));
+        }
+        let tr = locale::current();
+        let regional = regions::markdown_table(
+            &tr.date_str(self.from_date),
+            &tr.date_str(self.to_date),
+            &regions::by_region(&listings(from_records)),
+            &regions::by_region(&listings(to_records)),
+            tr,
+        );
+        export_summary_report(
+            &comparisons,
+            self.from_date,
+            self.to_date,
+            self.output,
+            self.kind,
+            self.notes,
+            &regional,
+            &charts,
+        )?;
+        Ok(comparisons)
+    }
+}
+
+/// Waterfall bridging the from-date total to the to-date total through the
+/// largest contributors
+fn write_waterfall_chart(
+    from_records: &[MarketCapRecord],
+    to_records: &[MarketCapRecord],
+    from_date: &str,
+    to_date: &str,
+    output: &OutputConfig,
+    kind: &str,
+) -> Result<PathBuf> {
+    let attribution = attribute_change(from_records, to_records, WATERFALL_CONTRIBUTORS);
+    let path = output.named_path(&format!(
+        "{}_{}_to_{}_waterfall.{}",
+        kind,
+        from_date,
+        to_date,
+        vega::extension()
+    ));
+    visualizations::create_waterfall_chart(&attribution, from_date, to_date, &path)?;
+    println!("✅ Generated waterfall chart: {}", path.display());
+    Ok(path)
+}
+
+/// Exchange, currency and market caps of each record, for the regional breakdown
+fn listings(records: &[MarketCapRecord]) -> Vec<regions::Listing<'_>> {
+    records
+        .iter()
+        .map(|r| regions::Listing {
+            exchange: r.exchange.as_deref().unwrap_or_default(),
+            currency: r.original_currency.as_deref().unwrap_or_default(),
+            market_cap_eur: r.market_cap_eur,
+            market_cap_usd: r.market_cap_usd,
+        })
+        .collect()
+}
+
+/// Sources of the per-company notes in a comparison
+#[derive(Clone, Copy)]
+pub struct RowAnnotations<'a> {
+    pub corporate_actions: &'a CorporateActionIndex,
+    /// Renamed symbols re-keyed while loading the snapshots
+    pub aliases: &'a AppliedAliases,
+    pub earnings: &'a EarningsIndex,
+}
+
+/// Compare two snapshots company by company, using original currency values.
+/// Sorted by percentage change, largest gain first.
+pub fn build_comparisons(
+    from_records: &[MarketCapRecord],
+    to_records: &[MarketCapRecord],
+    annotations: RowAnnotations<'_>,
+    report_currencies: &[String],
+    from_rates: &HashMap<String, f64>,
+    to_rates: &HashMap<String, f64>,
+) -> Vec<MarketCapComparison> {
+    let from_shares = calculate_market_shares(from_records);
+    let to_shares = calculate_market_shares(to_records);
+
+    let from_map: HashMap<&str, &MarketCapRecord> = from_records
+        .iter()
+        .map(|r| (r.ticker.as_str(), r))
+        .collect();
+    let to_map: HashMap<&str, &MarketCapRecord> =
+        to_records.iter().map(|r| (r.ticker.as_str(), r)).collect();
+
     let mut comparisons = Vec::new();
-    let mut all_tickers = std::collections::HashSet::new();
+    let mut all_tickers = BTreeSet::new();
 
     for ticker in from_map.keys() {
-        all_tickers.insert(ticker.clone());
+        all_tickers.insert(ticker.to_string());
     }
     for ticker in to_map.keys() {
-        all_tickers.insert(ticker.clone());
+        all_tickers.insert(ticker.to_string());
     }
 
     for ticker in all_tickers {
-        let from_record = from_map.get(&ticker);
-        let to_record = to_map.get(&ticker);
+        let from_record = from_map.get(ticker.as_str()).copied();
+        let to_record = to_map.get(ticker.as_str()).copied();
 
-        let name = from_record
+        // Renamed companies go by their later name
+        let formerly = annotations.aliases.annotation(&ticker);
+        let (first, second) = if formerly.is_some() {
+            (to_record, from_record)
+        } else {
+            (from_record, to_record)
+        };
+        let name = first
             .map(|r| r.name.clone())
-            .or_else(|| to_record.map(|r| r.name.clone()))
+            .or_else(|| second.map(|r| r.name.clone()))
             .unwrap_or_else(|| ticker.clone());
 
         // Get original currency (should be the same for both dates for the same ticker)
@@ -212,26 +613,25 @@ pub async fn compare_market_caps(from_date: &str, to_date: &str) -> Result<()> {
         let market_cap_from = from_record.and_then(|r| r.market_cap_original);
         let market_cap_to = to_record.and_then(|r| r.market_cap_original);
 
-        let (absolute_change, percentage_change) = match (market_cap_from, market_cap_to) {
-            (Some(from_val), Some(to_val)) => {
-                let abs_change = to_val - from_val;
-                let pct_change = if from_val != 0.0 {
-                    (abs_change / from_val) * 100.0
-                } else {
-                    0.0
-                };
-                (Some(abs_change), Some(pct_change))
-            }
-            _ => (None, None),
-        };
+        let (absolute_change, percentage_change) =
+            comparison::change(market_cap_from, market_cap_to);
 
         let rank_from = from_record.and_then(|r| r.rank);
         let rank_to = to_record.and_then(|r| r.rank);
-
-        let rank_change = match (rank_from, rank_to) {
-            (Some(from_rank), Some(to_rank)) => Some(from_rank as i32 - to_rank as i32),
-            _ => None,
-        };
+        let rank_change = comparison::rank_change(rank_from, rank_to);
+
+        // Each side is converted with the rates of its own date
+        let currency = original_currency.as_deref().unwrap_or_default();
+        let report_values =
+            report_currency_values(market_cap_from, currency, report_currencies, from_rates)
+                .into_iter()
+                .zip(report_currency_values(
+                    market_cap_to,
+                    currency,
+                    report_currencies,
+                    to_rates,
+                ))
+                .collect();
 
         comparisons.push(MarketCapComparison {
             ticker: ticker.clone(),
@@ -246,26 +646,162 @@ pub async fn compare_market_caps(from_date: &str, to_date: &str) -> Result<()> {
             rank_change,
             market_share_from: from_shares.get(&ticker).copied(),
             market_share_to: to_shares.get(&ticker).copied(),
+            report_values,
+            corporate_action: annotations.corporate_actions.annotation(&ticker),
+            formerly,
+            earnings: annotations.earnings.annotation(&ticker),
         });
     }
 
-    // Sort by percentage change (descending)
+    // Sort by percentage change (descending), ties by name and ticker
     comparisons.sort_by(|a, b| {
-        let a_pct = a.percentage_change.unwrap_or(f64::NEG_INFINITY);
-        let b_pct = b.percentage_change.unwrap_or(f64::NEG_INFINITY);
-        b_pct.partial_cmp(&a_pct).unwrap()
+        rankings::rank_order(
+            (a.percentage_change, &a.name, &a.ticker),
+            (b.percentage_change, &b.name, &b.ticker),
+        )
     });
 
-    progress.inc(1);
-    progress.finish_with_message("Analysis complete");
+    comparisons
+}
 
-    // Export main comparison CSV
-    export_comparison_csv(&comparisons, from_date, to_date)?;
+/// Number of companies shown individually in the change waterfall
+pub const WATERFALL_CONTRIBUTORS: usize = 8;
 
-    // Export summary report
-    export_summary_report(&comparisons, from_date, to_date)?;
+/// One company's contribution to the change of the total market cap (USD)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contribution {
+    pub ticker: String,
+    pub name: String,
+    pub change_usd: f64,
+}
 
-    Ok(())
+/// Total USD market cap on both dates and the companies that moved it most.
+/// `total_from + sum(top) + other == total_to`, so the steps add up exactly.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangeAttribution {
+    pub total_from: f64,
+    pub total_to: f64,
+    /// Largest contributors by absolute change, gains first, then losses
+    pub top: Vec<Contribution>,
+    /// Combined change of every other company
+    pub other: f64,
+}
+
+/// Attribute the change of the total USD market cap to the companies. A
+/// company present on one date only contributes its whole market cap, so new
+/// entrants and drop-outs are part of the bridge between the two totals.
+pub fn attribute_change(
+    from_records: &[MarketCapRecord],
+    to_records: &[MarketCapRecord],
+    top_n: usize,
+) -> ChangeAttribution {
+    let mut by_ticker: BTreeMap<&str, (&str, f64)> = BTreeMap::new();
+    for record in from_records {
+        let entry = by_ticker
+            .entry(record.ticker.as_str())
+            .or_insert((record.name.as_str(), 0.0));
+        entry.1 -= record.market_cap_usd.unwrap_or(0.0);
+    }
+    for record in to_records {
+        let entry = by_ticker
+            .entry(record.ticker.as_str())
+            .or_insert((record.name.as_str(), 0.0));
+        // Later names win, as in the comparison rows
+        entry.0 = record.name.as_str();
+        entry.1 += record.market_cap_usd.unwrap_or(0.0);
+    }
+
+    let total_from: f64 = from_records.iter().filter_map(|r| r.market_cap_usd).sum();
+    let total_to: f64 = to_records.iter().filter_map(|r| r.market_cap_usd).sum();
+
+    let mut contributions: Vec<Contribution> = by_ticker
+        .into_iter()
+        .map(|(ticker, (name, change_usd))| Contribution {
+            ticker: ticker.to_string(),
+            name: name.to_string(),
+            change_usd,
+        })
+        .collect();
+    contributions.sort_by(|a, b| {
+        rankings::rank_order(
+            (Some(a.change_usd.abs()), &a.name, &a.ticker),
+            (Some(b.change_usd.abs()), &b.name, &b.ticker),
+        )
+    });
+    let mut top: Vec<Contribution> = contributions
+        .into_iter()
+        .take(top_n)
+        .filter(|c| c.change_usd != 0.0)
+        .collect();
+    top.sort_by(|a, b| {
+        rankings::rank_order(
+            (Some(a.change_usd), &a.name, &a.ticker),
+            (Some(b.change_usd), &b.name, &b.ticker),
+        )
+    });
+    let other = total_to - total_from - top.iter().map(|c| c.change_usd).sum::<f64>();
+
+    ChangeAttribution {
+        total_from,
+        total_to,
+        top,
+        other,
+    }
+}
+
+/// Summarize a comparison for webhook notifications. The total change is
+/// computed in USD over companies present on both dates.
+fn build_run_summary(
+    comparisons: &[MarketCapComparison],
+    from_map: &BTreeMap<String, MarketCapRecord>,
+    to_map: &BTreeMap<String, MarketCapRecord>,
+    from_date: &str,
+    to_date: &str,
+) -> RunSummary {
+    let (total_from, total_to) = from_map
+        .iter()
+        .filter_map(|(ticker, from)| {
+            let to = to_map.get(ticker)?;
+            Some((from.market_cap_usd?, to.market_cap_usd?))
+        })
+        .fold((0.0, 0.0), |(a, b), (f, t)| (a + f, b + t));
+    let total_change_pct = if total_from > 0.0 {
+        Some((total_to - total_from) / total_from * 100.0)
+    } else {
+        None
+    };
+
+    let to_mover = |c: &MarketCapComparison| Mover {
+        ticker: c.ticker.clone(),
+        name: c.name.clone(),
+        percentage_change: c.percentage_change.unwrap_or(0.0),
+    };
+    let with_change = || comparisons.iter().filter(|c| c.percentage_change.is_some());
+    let top_gainer = with_change()
+        .filter(|c| c.percentage_change.unwrap_or(0.0) > 0.0)
+        .max_by(|a, b| {
+            a.percentage_change
+                .partial_cmp(&b.percentage_change)
+                .unwrap()
+        })
+        .map(to_mover);
+    let top_loser = with_change()
+        .filter(|c| c.percentage_change.unwrap_or(0.0) < 0.0)
+        .min_by(|a, b| {
+            a.percentage_change
+                .partial_cmp(&b.percentage_change)
+                .unwrap()
+        })
+        .map(to_mover);
+
+    RunSummary {
+        from_date: from_date.to_string(),
+        to_date: to_date.to_string(),
+        total_change_pct,
+        top_gainer,
+        top_loser,
+        artifacts_url: None,
+    }
 }
 
 /// Export comparison data to CSV
@@ -273,18 +809,19 @@ fn export_comparison_csv(
     comparisons: &[MarketCapComparison],
     from_date: &str,
     to_date: &str,
-) -> Result<()> {
-    let timestamp = Local::now().format("%Y%m%d_%H%M%S");
-    let filename = format!(
-        "output/comparison_{}_to_{}_{}.csv",
-        from_date, to_date, timestamp
-    );
-
-    let file = File::create(&filename)?;
+    output: &OutputConfig,
+    kind: &str,
+    report_currencies: &[String],
+    inputs: &[String],
+) -> Result<PathBuf> {
+    let path = output.file_path(kind, &format!("{}_to_{}", from_date, to_date), "csv");
+    let filename = path.display().to_string();
+
+    let file = File::create(&path)?;
     let mut writer = Writer::from_writer(file);
 
-    // Write headers
-    writer.write_record(&[
+    // Write headers, with from/to columns per report currency
+    let mut headers: Vec<String> = [
         "Ticker",
         "Name",
         "Currency",
@@ -297,11 +834,22 @@ fn export_comparison_csv(
         "Rank Change",
         "Market Share From (%)",
         "Market Share To (%)",
-    ])?;
+        "Corporate Action",
+        "Formerly",
+        "Earnings",
+    ]
+    .iter()
+    .map(|h| h.to_string())
+    .collect();
+    for currency in report_currencies {
+        headers.push(format!("Market Cap From ({})", currency));
+        headers.push(format!("Market Cap To ({})", currency));
+    }
+    writer.write_record(&headers)?;
 
     // Write data
     for comp in comparisons {
-        writer.write_record(&[
+        let mut row = vec![
             comp.ticker.clone(),
             comp.name.clone(),
             comp.original_currency
@@ -340,55 +888,136 @@ fn export_comparison_csv(
             comp.market_share_to
                 .map(|v| format!("{:.4}", v))
                 .unwrap_or_else(|| "NA".to_string()),
-        ])?;
+            comp.corporate_action.clone().unwrap_or_default(),
+            comp.formerly.clone().unwrap_or_default(),
+            comp.earnings.clone().unwrap_or_default(),
+        ];
+        for (from, to) in &comp.report_values {
+            row.push(from.clone());
+            row.push(to.clone());
+        }
+        writer.write_record(&row)?;
     }
 
     writer.flush()?;
     println!("✅ Comparison data exported to {}", filename);
+    data_package::write_for(
+        &path,
+        data_package::Provenance {
+            kind,
+            title: format!("Market cap comparison {} to {}", from_date, to_date),
+            dates: vec![from_date.to_string(), to_date.to_string()],
+            requested_date: None,
+            inputs: inputs.iter().map(PathBuf::from).collect(),
+        },
+    )?;
 
-    Ok(())
+    Ok(path)
+}
+
+/// Tie-breaker for report sections so equal values list in a stable order
+fn by_name(a: &MarketCapComparison, b: &MarketCapComparison) -> std::cmp::Ordering {
+    a.name.cmp(&b.name).then_with(|| a.ticker.cmp(&b.ticker))
+}
+
+/// Footnote markers for rows affected by a corporate action (†) or with an
+/// earnings report in the period (‡)
+fn footnote_markers(comp: &MarketCapComparison) -> String {
+    let mut markers = String::new();
+    if comp.corporate_action.is_some() {
+        markers.push_str(" †");
+    }
+    if comp.earnings.is_some() {
+        markers.push_str(" ‡");
+    }
+    markers
 }
 
 /// Export summary report in Markdown format
+#[allow(clippy::too_many_arguments)]
 fn export_summary_report(
     comparisons: &[MarketCapComparison],
     from_date: &str,
     to_date: &str,
+    output: &OutputConfig,
+    kind: &str,
+    notes: &str,
+    regional: &str,
+    charts: &[(&str, PathBuf)],
 ) -> Result<()> {
-    let timestamp = Local::now().format("%Y%m%d_%H%M%S");
-    let filename = format!(
-        "output/comparison_{}_to_{}_summary_{}.md",
-        from_date, to_date, timestamp
-    );
+    let path = output.file_path(kind, &format!("{}_to_{}_summary", from_date, to_date), "md");
+    let filename = path.display().to_string();
 
-    let mut file = File::create(&filename)?;
+    let mut file = File::create(&path)?;
+    let tr = locale::current();
+    let section_size = rankings::section_size();
+    let section_label = section_size.to_string();
+    let top_n = [("n", section_label.as_str())];
 
     writeln!(
         file,
-        "# Market Cap Comparison: {} to {}",
-        from_date, to_date
+        "# {}",
+        tr.t(
+            "comparison_title",
+            &[
+                ("from", &tr.date_str(from_date)),
+                ("to", &tr.date_str(to_date))
+            ]
+        )
     )?;
     writeln!(file)?;
 
-    writeln!(
-        file,
-        "> **Note:** All values are shown in each company's original currency. Percentage changes reflect actual local currency performance."
-    )?;
+    writeln!(file, "{}", tr.t("currency_note", &[]))?;
     writeln!(file)?;
 
+    write!(file, "{}", notes)?;
+
     // Overview statistics
-    writeln!(file, "## Overview Statistics")?;
+    writeln!(file, "## {}", tr.t("overview", &[]))?;
     let total_companies = comparisons.len();
     let companies_with_data = comparisons
         .iter()
         .filter(|c| c.market_cap_from.is_some() && c.market_cap_to.is_some())
         .count();
-    writeln!(file, "- Total companies tracked: {}", total_companies)?;
     writeln!(
         file,
-        "- Companies with data for both dates: {}",
-        companies_with_data
+        "- {}",
+        tr.t(
+            "total_companies",
+            &[("count", &total_companies.to_string())]
+        )
+    )?;
+    writeln!(
+        file,
+        "- {}",
+        tr.t(
+            "companies_with_data",
+            &[("count", &companies_with_data.to_string())]
+        )
     )?;
+    // Only under --min-market-cap or --winsorize, which change this average
+    let outliers = outliers::current();
+    let changes: Vec<f64> = comparisons
+        .iter()
+        .filter_map(|c| c.percentage_change)
+        .collect();
+    if let (true, Some(average), Some(median)) = (
+        outliers.is_active(),
+        outliers.average(&changes),
+        utils::median(&changes),
+    ) {
+        writeln!(
+            file,
+            "- {}",
+            tr.t(
+                "average_change",
+                &[
+                    ("average", &tr.percent(average, true)),
+                    ("median", &tr.percent(median, true))
+                ]
+            )
+        )?;
+    }
     writeln!(file)?;
 
     // Filter out comparisons with valid percentage changes
@@ -397,8 +1026,8 @@ fn export_summary_report(
         .filter(|c| c.percentage_change.is_some())
         .collect();
 
-    // Top 10 gainers (only positive changes)
-    writeln!(file, "## Top 10 Gainers (by percentage)")?;
+    // Top gainers (only positive changes)
+    writeln!(file, "## {}", tr.t("top_gainers", &top_n))?;
     let mut gainers: Vec<_> = valid_comparisons
         .iter()
         .filter(|c| c.percentage_change.unwrap_or(0.0) > 0.0)
@@ -409,29 +1038,36 @@ fn export_summary_report(
             .unwrap()
             .partial_cmp(&a.percentage_change.unwrap())
             .unwrap()
+            .then_with(|| by_name(a, b))
     });
 
-    for (i, comp) in gainers.iter().take(10).enumerate() {
+    for (i, comp) in gainers.iter().take(section_size).enumerate() {
         let pct = comp.percentage_change.unwrap();
         let abs_change = comp.absolute_change.unwrap_or(0.0);
         let currency = comp.original_currency.as_deref().unwrap_or("USD");
 
         writeln!(
             file,
-            "{}. **{}** ([{}](https://finance.yahoo.com/quote/{}/)): +{:.2}% ({:.2}M {} increase)",
+            "{}. **{}** ([{}](https://finance.yahoo.com/quote/{}/)): {} ({}){}",
             i + 1,
             comp.name,
             comp.ticker,
             comp.ticker,
-            pct,
-            abs_change / 1_000_000.0,
-            currency
+            tr.percent(pct, true),
+            tr.t(
+                "millions_increase",
+                &[
+                    ("amount", &tr.number(abs_change / 1_000_000.0, 2)),
+                    ("currency", currency)
+                ]
+            ),
+            footnote_markers(comp)
         )?;
     }
     writeln!(file)?;
 
-    // Top 10 losers (only negative changes)
-    writeln!(file, "## Top 10 Losers (by percentage)")?;
+    // Top losers (only negative changes)
+    writeln!(file, "## {}", tr.t("top_losers", &top_n))?;
     let mut losers: Vec<_> = valid_comparisons
         .iter()
         .filter(|c| c.percentage_change.unwrap_or(0.0) < 0.0)
@@ -442,104 +1078,136 @@ fn export_summary_report(
             .unwrap()
             .partial_cmp(&b.percentage_change.unwrap())
             .unwrap()
+            .then_with(|| by_name(a, b))
     });
 
-    for (i, comp) in valid_comparisons.iter().take(10).enumerate() {
+    for (i, comp) in losers.iter().take(section_size).enumerate() {
         let currency = comp.original_currency.as_deref().unwrap_or("USD");
         writeln!(
             file,
-            "{}. **{}** ([{}](https://finance.yahoo.com/quote/{}/)): {:.2}% ({:.2}M {} decrease)",
+            "{}. **{}** ([{}](https://finance.yahoo.com/quote/{}/)): {} ({}){}",
             i + 1,
             comp.name,
             comp.ticker,
             comp.ticker,
-            comp.percentage_change.unwrap(),
-            comp.absolute_change.unwrap_or(0.0).abs() / 1_000_000.0,
-            currency
+            tr.percent(comp.percentage_change.unwrap(), false),
+            tr.t(
+                "millions_decrease",
+                &[
+                    (
+                        "amount",
+                        &tr.number(comp.absolute_change.unwrap_or(0.0).abs() / 1_000_000.0, 2)
+                    ),
+                    ("currency", currency)
+                ]
+            ),
+            footnote_markers(comp)
         )?;
     }
     writeln!(file)?;
 
-    // Top 10 by absolute gain (note: different currencies, so not directly comparable)
-    writeln!(file, "## Top 10 by Absolute Gain")?;
-    writeln!(
-        file,
-        "_Note: Values are in original currencies and may not be directly comparable._"
-    )?;
+    // Top by absolute gain (note: different currencies, so not directly comparable)
+    writeln!(file, "## {}", tr.t("top_absolute_gain", &top_n))?;
+    writeln!(file, "{}", tr.t("absolute_note", &[]))?;
     writeln!(file)?;
     valid_comparisons.sort_by(|a, b| {
         b.absolute_change
             .unwrap_or(0.0)
             .partial_cmp(&a.absolute_change.unwrap_or(0.0))
             .unwrap()
+            .then_with(|| by_name(a, b))
     });
 
-    for (i, comp) in valid_comparisons.iter().take(10).enumerate() {
+    for (i, comp) in valid_comparisons.iter().take(section_size).enumerate() {
         let currency = comp.original_currency.as_deref().unwrap_or("USD");
         writeln!(
             file,
-            "{}. **{}** ([{}](https://finance.yahoo.com/quote/{}/)): {:.2}B {} gain ({:.2}%)",
+            "{}. **{}** ([{}](https://finance.yahoo.com/quote/{}/)): {} ({})",
             i + 1,
             comp.name,
             comp.ticker,
             comp.ticker,
-            comp.absolute_change.unwrap_or(0.0) / 1_000_000_000.0,
-            currency,
-            comp.percentage_change.unwrap_or(0.0)
+            tr.t(
+                "billions_gain",
+                &[
+                    (
+                        "amount",
+                        &tr.number(comp.absolute_change.unwrap_or(0.0) / 1_000_000_000.0, 2)
+                    ),
+                    ("currency", currency)
+                ]
+            ),
+            tr.percent(comp.percentage_change.unwrap_or(0.0), false)
         )?;
     }
     writeln!(file)?;
 
-    // Top 10 by absolute loss (only negative changes)
-    writeln!(file, "## Top 10 by Absolute Loss")?;
-    writeln!(
-        file,
-        "_Note: Values are in original currencies and may not be directly comparable._"
-    )?;
+    // Top by absolute loss (only negative changes)
+    writeln!(file, "## {}", tr.t("top_absolute_loss", &top_n))?;
+    writeln!(file, "{}", tr.t("absolute_note", &[]))?;
     writeln!(file)?;
     valid_comparisons.sort_by(|a, b| {
         a.absolute_change
             .unwrap_or(0.0)
             .partial_cmp(&b.absolute_change.unwrap_or(0.0))
             .unwrap()
+            .then_with(|| by_name(a, b))
     });
 
-    for (i, comp) in valid_comparisons.iter().take(10).enumerate() {
+    for (i, comp) in valid_comparisons.iter().take(section_size).enumerate() {
         if comp.absolute_change.unwrap_or(0.0) < 0.0 {
             let currency = comp.original_currency.as_deref().unwrap_or("USD");
             writeln!(
                 file,
-                "{}. **{}** ([{}](https://finance.yahoo.com/quote/{}/)): {:.2}B {} loss ({:.2}%)",
+                "{}. **{}** ([{}](https://finance.yahoo.com/quote/{}/)): {} ({})",
                 i + 1,
                 comp.name,
                 comp.ticker,
                 comp.ticker,
-                comp.absolute_change.unwrap_or(0.0).abs() / 1_000_000_000.0,
-                currency,
-                comp.percentage_change.unwrap_or(0.0)
+                tr.t(
+                    "billions_loss",
+                    &[
+                        (
+                            "amount",
+                            &tr.number(
+                                comp.absolute_change.unwrap_or(0.0).abs() / 1_000_000_000.0,
+                                2
+                            )
+                        ),
+                        ("currency", currency)
+                    ]
+                ),
+                tr.percent(comp.percentage_change.unwrap_or(0.0), false)
             )?;
         }
     }
     writeln!(file)?;
 
     // Biggest rank improvements
-    writeln!(file, "## Biggest Rank Improvements")?;
+    writeln!(file, "## {}", tr.t("rank_improvements", &[]))?;
     let mut rank_comparisons: Vec<_> = comparisons
         .iter()
         .filter(|c| c.rank_change.is_some())
         .collect();
-    rank_comparisons.sort_by(|a, b| b.rank_change.unwrap().cmp(&a.rank_change.unwrap()));
+    rank_comparisons.sort_by(|a, b| {
+        b.rank_change
+            .cmp(&a.rank_change)
+            .then_with(|| by_name(a, b))
+    });
 
-    for (i, comp) in rank_comparisons.iter().take(10).enumerate() {
+    for (i, comp) in rank_comparisons.iter().take(section_size).enumerate() {
         if comp.rank_change.unwrap() > 0 {
             writeln!(
                 file,
-                "{}. **{}** ([{}](https://finance.yahoo.com/quote/{}/)): +{} positions (#{} → #{})",
+                "{}. **{}** ([{}](https://finance.yahoo.com/quote/{}/)): {} (#{} → #{})",
                 i + 1,
                 comp.name,
                 comp.ticker,
                 comp.ticker,
-                comp.rank_change.unwrap(),
+                tr.t(
+                    "positions",
+                    &[("change", &format!("+{}", comp.rank_change.unwrap()))]
+                ),
                 comp.rank_from.unwrap_or(0),
                 comp.rank_to.unwrap_or(0)
             )?;
@@ -548,19 +1216,26 @@ fn export_summary_report(
     writeln!(file)?;
 
     // Biggest rank declines
-    writeln!(file, "## Biggest Rank Declines")?;
-    rank_comparisons.sort_by(|a, b| a.rank_change.unwrap().cmp(&b.rank_change.unwrap()));
+    writeln!(file, "## {}", tr.t("rank_declines", &[]))?;
+    rank_comparisons.sort_by(|a, b| {
+        a.rank_change
+            .cmp(&b.rank_change)
+            .then_with(|| by_name(a, b))
+    });
 
-    for (i, comp) in rank_comparisons.iter().take(10).enumerate() {
+    for (i, comp) in rank_comparisons.iter().take(section_size).enumerate() {
         if comp.rank_change.unwrap() < 0 {
             writeln!(
                 file,
-                "{}. **{}** ([{}](https://finance.yahoo.com/quote/{}/)): {} positions (#{} → #{})",
+                "{}. **{}** ([{}](https://finance.yahoo.com/quote/{}/)): {} (#{} → #{})",
                 i + 1,
                 comp.name,
                 comp.ticker,
                 comp.ticker,
-                comp.rank_change.unwrap(),
+                tr.t(
+                    "positions",
+                    &[("change", &comp.rank_change.unwrap().to_string())]
+                ),
                 comp.rank_from.unwrap_or(0),
                 comp.rank_to.unwrap_or(0)
             )?;
@@ -569,7 +1244,24 @@ fn export_summary_report(
     writeln!(file)?;
 
     // Market concentration analysis
-    writeln!(file, "## Market Concentration Analysis")?;
+    writeln!(file, "## {}", tr.t("concentration", &[]))?;
+    write!(
+        file,
+        "{}",
+        concentration::markdown_table(
+            &tr.date_str(from_date),
+            &tr.date_str(to_date),
+            Concentration::from_values(comparisons.iter().filter_map(|c| c.market_share_from)),
+            Concentration::from_values(comparisons.iter().filter_map(|c| c.market_share_to)),
+            tr,
+        )
+    )?;
+    writeln!(file)?;
+
+    // Regional breakdown
+    writeln!(file, "## {}", tr.t("regional_breakdown", &[]))?;
+    write!(file, "{}", regional)?;
+    writeln!(file)?;
 
     let companies_with_increase = comparisons
         .iter()
@@ -591,36 +1283,58 @@ fn export_summary_report(
         .filter(|c| c.market_cap_from.is_some() && c.market_cap_to.is_none())
         .count();
 
-    writeln!(
-        file,
-        "- Companies with increased market cap: {}",
-        companies_with_increase
-    )?;
-    writeln!(
-        file,
-        "- Companies with decreased market cap: {}",
-        companies_with_decrease
-    )?;
-    writeln!(file, "- New companies in list: {}", new_companies)?;
-    writeln!(
-        file,
-        "- Companies no longer in list: {}",
-        delisted_companies
-    )?;
+    for (key, count) in [
+        ("companies_increased", companies_with_increase),
+        ("companies_decreased", companies_with_decrease),
+        ("new_companies", new_companies),
+        ("companies_removed", delisted_companies),
+    ] {
+        writeln!(file, "- {}", tr.t(key, &[("count", &count.to_string())]))?;
+    }
     writeln!(file)?;
 
+    if !charts.is_empty() {
+        write!(
+            file,
+            "{}",
+            charts_section(charts, path.parent().unwrap_or(Path::new("")), tr)
+        )?;
+    }
+
     writeln!(file, "---")?;
-    writeln!(
-        file,
-        "*Generated on {}*",
-        Local::now().format("%Y-%m-%d %H:%M:%S")
-    )?;
+    writeln!(file, "*{}*", tr.generated_on(clock::now()))?;
 
     println!("✅ Summary report exported to {}", filename);
 
     Ok(())
 }
 
+/// Charts section of the summary: SVGs are embedded as images, Vega-Lite specs
+/// linked. Paths are relative to `summary_dir`, so the report can be moved
+/// together with its charts.
+fn charts_section(
+    charts: &[(&str, PathBuf)],
+    summary_dir: &Path,
+    tr: &locale::Translations,
+) -> String {
+    let mut section = format!("## {}\n\n", tr.t("charts", &[]));
+    for (kind, path) in charts {
+        let title = tr.t(&format!("chart_{}", kind), &[]);
+        let link = path
+            .strip_prefix(summary_dir)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .replace('\\', "/");
+        section.push_str(&format!("### {}\n\n", title));
+        if path.extension().is_some_and(|ext| ext == "svg") {
+            section.push_str(&format!("![{}]({})\n\n", title, link));
+        } else {
+            section.push_str(&format!("[{}]({})\n\n", title, link));
+        }
+    }
+    section
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -734,6 +1448,7 @@ mod tests {
                 original_currency: Some("USD".to_string()),
                 market_cap_eur: Some(1800000000000.0),
                 market_cap_usd: Some(2000000000000.0),
+                exchange: None,
             },
             MarketCapRecord {
                 rank: Some(2),
@@ -743,6 +1458,7 @@ mod tests {
                 original_currency: Some("USD".to_string()),
                 market_cap_eur: Some(900000000000.0),
                 market_cap_usd: Some(1000000000000.0),
+                exchange: None,
             },
         ];
 
@@ -754,4 +1470,199 @@ mod tests {
         assert!((shares.get("AAPL").unwrap() - 66.666666).abs() < 0.01);
         assert!((shares.get("MSFT").unwrap() - 33.333333).abs() < 0.01);
     }
+
+    fn record(ticker: &str, usd: f64) -> MarketCapRecord {
+        MarketCapRecord {
+            rank: None,
+            ticker: ticker.to_string(),
+            name: ticker.to_string(),
+            market_cap_original: Some(usd),
+            original_currency: Some("USD".to_string()),
+            market_cap_eur: None,
+            market_cap_usd: Some(usd),
+            exchange: None,
+        }
+    }
+
+    fn comparison(ticker: &str, pct: Option<f64>) -> MarketCapComparison {
+        MarketCapComparison {
+            ticker: ticker.to_string(),
+            name: ticker.to_string(),
+            original_currency: Some("USD".to_string()),
+            market_cap_from: None,
+            market_cap_to: None,
+            absolute_change: None,
+            percentage_change: pct,
+            rank_from: None,
+            rank_to: None,
+            rank_change: None,
+            market_share_from: None,
+            market_share_to: None,
+            report_values: Vec::new(),
+            corporate_action: None,
+            formerly: None,
+            earnings: None,
+        }
+    }
+
+    #[test]
+    fn test_build_run_summary() {
+        let from_map: BTreeMap<_, _> = [("A", 100.0), ("B", 100.0), ("GONE", 50.0)]
+            .into_iter()
+            .map(|(t, v)| (t.to_string(), record(t, v)))
+            .collect();
+        let to_map: BTreeMap<_, _> = [("A", 120.0), ("B", 90.0), ("NEW", 70.0)]
+            .into_iter()
+            .map(|(t, v)| (t.to_string(), record(t, v)))
+            .collect();
+        let comparisons = vec![
+            comparison("A", Some(20.0)),
+            comparison("B", Some(-10.0)),
+            comparison("NEW", None),
+        ];
+
+        let summary =
+            build_run_summary(&comparisons, &from_map, &to_map, "2025-01-01", "2025-02-01");
+
+        // Only A and B exist on both dates: 200 -> 210
+        assert!((summary.total_change_pct.unwrap() - 5.0).abs() < 1e-9);
+        assert_eq!(summary.top_gainer.unwrap().ticker, "A");
+        assert_eq!(summary.top_loser.unwrap().ticker, "B");
+    }
+
+    #[test]
+    fn test_attribute_change_bridges_the_totals() {
+        let from = [
+            record("A", 100.0),
+            record("B", 100.0),
+            record("C", 100.0),
+            record("GONE", 50.0),
+        ];
+        let to = [
+            record("A", 160.0),
+            record("B", 70.0),
+            record("C", 101.0),
+            record("NEW", 40.0),
+        ];
+
+        let attribution = attribute_change(&from, &to, 3);
+        assert_eq!(attribution.total_from, 350.0);
+        assert_eq!(attribution.total_to, 371.0);
+        // Largest by absolute change, shown gains first: A +60, NEW +40, GONE -50
+        let top: Vec<_> = attribution
+            .top
+            .iter()
+            .map(|c| (c.ticker.as_str(), c.change_usd))
+            .collect();
+        assert_eq!(top, [("A", 60.0), ("NEW", 40.0), ("GONE", -50.0)]);
+        // B -30 and C +1 end up in Other
+        assert_eq!(attribution.other, -29.0);
+        let bridged = attribution.total_from
+            + attribution.top.iter().map(|c| c.change_usd).sum::<f64>()
+            + attribution.other;
+        assert_eq!(bridged, attribution.total_to);
+    }
+
+    #[test]
+    fn test_attribute_change_skips_unchanged_companies() {
+        let from = [record("A", 100.0), record("B", 100.0)];
+        let to = [record("A", 100.0), record("B", 90.0)];
+        let attribution = attribute_change(&from, &to, WATERFALL_CONTRIBUTORS);
+        assert_eq!(attribution.top.len(), 1);
+        assert_eq!(attribution.top[0].ticker, "B");
+        assert_eq!(attribution.other, 0.0);
+    }
+
+    #[test]
+    fn test_build_comparisons_orders_ties_by_name() {
+        let from = [
+            record("ZZ", 100.0),
+            record("AA", 100.0),
+            record("MM", 100.0),
+        ];
+        let to = [
+            record("ZZ", 110.0),
+            record("AA", 110.0),
+            record("MM", 150.0),
+        ];
+
+        let comparisons = build_comparisons(
+            &from,
+            &to,
+            RowAnnotations {
+                corporate_actions: &CorporateActionIndex::default(),
+                aliases: &AppliedAliases::default(),
+                earnings: &EarningsIndex::default(),
+            },
+            &[],
+            &HashMap::new(),
+            &HashMap::new(),
+        );
+
+        let tickers: Vec<&str> = comparisons.iter().map(|c| c.ticker.as_str()).collect();
+        assert_eq!(tickers, vec!["MM", "AA", "ZZ"]);
+    }
+
+    #[test]
+    fn test_build_comparisons_stitches_renamed_symbols() {
+        let aliases = TickerAliases::new(vec![crate::ticker_aliases::Rename {
+            old_symbol: "FB".to_string(),
+            new_symbol: "META".to_string(),
+            date: NaiveDate::from_ymd_opt(2022, 6, 9),
+        }]);
+        let mut applied = AppliedAliases::default();
+        let mut from = [record("FB", 100.0), record("NKE", 100.0)];
+        from[0].name = "Facebook".to_string();
+        let mut to = [record("META", 150.0), record("NKE", 100.0)];
+        to[0].name = "Meta Platforms".to_string();
+        for (date, records) in [("2021-12-31", &mut from), ("2022-12-31", &mut to)] {
+            let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap();
+            aliases.apply(date, records, |r| &mut r.ticker, &mut applied);
+        }
+
+        let comparisons = build_comparisons(
+            &from,
+            &to,
+            RowAnnotations {
+                corporate_actions: &CorporateActionIndex::default(),
+                aliases: &applied,
+                earnings: &EarningsIndex::default(),
+            },
+            &[],
+            &HashMap::new(),
+            &HashMap::new(),
+        );
+
+        assert_eq!(comparisons.len(), 2);
+        let meta = &comparisons[0];
+        assert_eq!(meta.ticker, "META");
+        assert_eq!(meta.name, "Meta Platforms");
+        assert_eq!(meta.percentage_change, Some(50.0));
+        assert_eq!(meta.formerly.as_deref(), Some("FB"));
+        assert_eq!(comparisons[1].formerly, None);
+    }
+
+    #[test]
+    fn test_charts_section_links_relative_to_summary() {
+        let tr = locale::Translations::load(locale::Locale::En).unwrap();
+        let dir = Path::new("output");
+        let charts = [
+            (
+                "gainers_losers",
+                dir.join("comparison_2025-01-01_to_2025-02-01_gainers_losers.svg"),
+            ),
+            (
+                "treemap",
+                dir.join("comparison_2025-01-01_to_2025-02-01_treemap.vl.json"),
+            ),
+        ];
+        assert_eq!(
+            charts_section(&charts, dir, &tr),
+            "## Charts\n\n\
+             ### Top gainers and losers\n\n\
+             ![Top gainers and losers](comparison_2025-01-01_to_2025-02-01_gainers_losers.svg)\n\n\
+             ### Market map\n\n\
+             [Market map](comparison_2025-01-01_to_2025-02-01_treemap.vl.json)\n\n"
+        );
+    }
 }
@@ -0,0 +1,181 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Market concentration metrics for a snapshot
+//!
+//! Herfindahl-Hirschman Index, Gini coefficient and the combined share of the
+//! 5 and 10 largest companies, computed from USD market caps (or shares).
+//! Comparison and trend summaries report them for the first and last date
+//! with the change, to show whether the industry is concentrating.
+
+use serde::Serialize;
+use std::fmt::Write;
+
+use crate::locale::Translations;
+
+/// Concentration of one snapshot
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Concentration {
+    pub companies: usize,
+    /// Sum of squared percentage shares, 0 to 10000
+    pub hhi: f64,
+    /// 0 when all companies are equal, towards 1 when one dominates
+    pub gini: f64,
+    /// Percentage of the total held by the 5 largest companies
+    pub top5_share: f64,
+    /// Percentage of the total held by the 10 largest companies
+    pub top10_share: f64,
+}
+
+impl Concentration {
+    /// Metrics over positive values; `None` when there are none
+    pub fn from_values(values: impl IntoIterator<Item = f64>) -> Option<Self> {
+        let mut values: Vec<f64> = values
+            .into_iter()
+            .filter(|v| v.is_finite() && *v > 0.0)
+            .collect();
+        let total: f64 = values.iter().sum();
+        if values.is_empty() || total <= 0.0 {
+            return None;
+        }
+        values.sort_by(|a, b| b.total_cmp(a));
+
+        let shares: Vec<f64> = values.iter().map(|v| v / total * 100.0).collect();
+        let n = values.len() as f64;
+        // Gini from the ascending order: sum of (2i - n - 1) * x_i / (n * total)
+        let weighted: f64 = values
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(i, v)| (2.0 * (i as f64 + 1.0) - n - 1.0) * v)
+            .sum();
+
+        Some(Concentration {
+            companies: values.len(),
+            hhi: shares.iter().map(|s| s * s).sum(),
+            gini: weighted / (n * total),
+            top5_share: shares.iter().take(5).sum(),
+            top10_share: shares.iter().take(10).sum(),
+        })
+    }
+}
+
+/// Markdown table with the metrics of both dates and the change
+pub fn markdown_table(
+    from_label: &str,
+    to_label: &str,
+    from: Option<Concentration>,
+    to: Option<Concentration>,
+    tr: &Translations,
+) -> String {
+    type Metric = (String, fn(&Concentration) -> f64, usize, bool);
+    let metrics: [Metric; 4] = [
+        (tr.t("hhi", &[]), |c| c.hhi, 0, false),
+        (tr.t("gini", &[]), |c| c.gini, 3, false),
+        (tr.t("top_share", &[("n", "5")]), |c| c.top5_share, 2, true),
+        (
+            tr.t("top_share", &[("n", "10")]),
+            |c| c.top10_share,
+            2,
+            true,
+        ),
+    ];
+
+    let mut table = String::new();
+    let _ = writeln!(
+        table,
+        "| {} | {} | {} | {} |",
+        tr.t("concentration_metric", &[]),
+        from_label,
+        to_label,
+        tr.t("concentration_change", &[])
+    );
+    let _ = writeln!(table, "|---|---:|---:|---:|");
+    for (label, value, decimals, percent) in metrics {
+        let format = |c: &Option<Concentration>| {
+            c.as_ref().map_or("-".to_string(), |c| {
+                if percent {
+                    tr.percent(value(c), false)
+                } else {
+                    tr.number(value(c), decimals)
+                }
+            })
+        };
+        let change = match (&from, &to) {
+            (Some(a), Some(b)) => {
+                let delta = value(b) - value(a);
+                let number = tr.signed_number(delta, decimals);
+                if percent {
+                    tr.t("percentage_points", &[("value", &number)])
+                } else {
+                    number
+                }
+            }
+            _ => "-".to_string(),
+        };
+        let _ = writeln!(
+            table,
+            "| {} | {} | {} | {} |",
+            label,
+            format(&from),
+            format(&to),
+            change
+        );
+    }
+    let _ = writeln!(table);
+    let _ = writeln!(table, "{}", tr.t("hhi_note", &[]));
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locale::Locale;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn test_equal_companies() {
+        let c = Concentration::from_values(vec![10.0; 20]).unwrap();
+        assert_eq!(c.companies, 20);
+        assert!(close(c.hhi, 500.0));
+        assert!(close(c.gini, 0.0));
+        assert!(close(c.top5_share, 25.0));
+        assert!(close(c.top10_share, 50.0));
+    }
+
+    #[test]
+    fn test_unequal_companies() {
+        // Shares 50%, 30%, 20%
+        let c = Concentration::from_values([20.0, 50.0, 30.0, 0.0, f64::NAN]).unwrap();
+        assert_eq!(c.companies, 3);
+        assert!(close(c.hhi, 2500.0 + 900.0 + 400.0));
+        // Ascending 20, 30, 50: (-2*20 + 0*30 + 2*50) / (3 * 100)
+        assert!(close(c.gini, 0.2));
+        assert!(close(c.top5_share, 100.0));
+
+        let monopoly = Concentration::from_values([100.0]).unwrap();
+        assert!(close(monopoly.hhi, 10000.0));
+        assert!(close(monopoly.gini, 0.0));
+
+        assert!(Concentration::from_values(Vec::new()).is_none());
+    }
+
+    #[test]
+    fn test_markdown_table() {
+        let tr = Translations::load(Locale::En).unwrap();
+        let from = Concentration::from_values([50.0, 50.0]);
+        let to = Concentration::from_values([75.0, 25.0]);
+        let table = markdown_table("2025-01-31", "2025-02-28", from, to, &tr);
+
+        assert!(table.contains("| Metric | 2025-01-31 | 2025-02-28 | Change |"));
+        assert!(table.contains("| Herfindahl-Hirschman Index (HHI) | 5000 | 6250 | +1250 |"));
+        assert!(table.contains("| Top 5 share | 100.00% | 100.00% | 0.00 pp |"));
+
+        let missing = markdown_table("a", "b", from, None, &tr);
+        assert!(missing.contains("| Gini coefficient | 0.000 | - | - |"));
+    }
+}
@@ -3,13 +3,594 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::advanced_comparisons::PeerGroup;
+use crate::error::Error;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub non_us_tickers: Vec<String>,
     pub us_tickers: Vec<String>,
+    #[serde(default)]
+    pub output: OutputConfig,
+    #[serde(default)]
+    pub storage: StorageConfig,
+    #[serde(default)]
+    pub notifications: NotificationConfig,
+    #[serde(default)]
+    pub forex: ForexConfig,
+    #[serde(default)]
+    pub company_profiles: CompanyProfileConfig,
+    #[serde(default)]
+    pub api: ApiConfig,
+    #[serde(default)]
+    pub polygon: PolygonConfig,
+    #[serde(default)]
+    pub jobs: JobsConfig,
+    #[serde(default)]
+    pub logos: LogoConfig,
+    #[serde(default)]
+    pub charts: ChartConfig,
+    /// Custom KPIs: column name to expression, see `src/kpis.rs`
+    #[serde(default)]
+    pub kpis: BTreeMap<String, String>,
+    /// Peer groups replacing the built-in fashion ones (`[[peer_groups]]`)
+    #[serde(default)]
+    pub peer_groups: Vec<PeerGroup>,
+    /// Database of a profile, see [`database_url`]
+    #[serde(default)]
+    pub database_url: Option<String>,
+}
+
+/// Look of the generated charts
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChartConfig {
+    /// Built-in theme: `light`, `dark` or `fashionunited`
+    #[serde(default = "default_chart_theme")]
+    pub theme: String,
+    /// Font family replacing the theme's
+    #[serde(default)]
+    pub font: Option<String>,
+    /// Image (PNG, SVG or JPEG) placed faintly in the corner of every chart
+    #[serde(default)]
+    pub watermark: Option<String>,
+}
+
+fn default_chart_theme() -> String {
+    "light".to_string()
+}
+
+impl Default for ChartConfig {
+    fn default() -> Self {
+        Self {
+            theme: default_chart_theme(),
+            font: None,
+            watermark: None,
+        }
+    }
+}
+
+/// Company logos downloaded by `fetch-logos` into `<output>/assets/logos/`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LogoConfig {
+    /// Logo URL with a `{ticker}` placeholder
+    #[serde(default = "default_logo_url_template")]
+    pub url_template: String,
+    /// Cached logos older than this are downloaded again
+    #[serde(default = "default_logo_max_age_days")]
+    pub max_age_days: i64,
+}
+
+fn default_logo_url_template() -> String {
+    "https://financialmodelingprep.com/image-stock/{ticker}.png".to_string()
+}
+
+fn default_logo_max_age_days() -> i64 {
+    30
+}
+
+impl Default for LogoConfig {
+    fn default() -> Self {
+        Self {
+            url_template: default_logo_url_template(),
+            max_age_days: default_logo_max_age_days(),
+        }
+    }
+}
+
+/// Request pacing, retries and timeouts of the FMP and Polygon APIs
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ApiConfig {
+    /// Requests allowed per minute, shared by every FMP client in the process
+    #[serde(default = "default_fmp_requests_per_minute")]
+    pub fmp_requests_per_minute: u32,
+    /// Requests that may be sent back-to-back before pacing kicks in
+    #[serde(default = "default_fmp_burst")]
+    pub fmp_burst: u32,
+    /// Cached API responses are reused for the same UTC day while younger than this
+    #[serde(default = "default_api_cache_ttl_hours")]
+    pub cache_ttl_hours: i64,
+    /// Retries of a failed request after the first attempt
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    /// Wait before the first retry; doubled after every further failure
+    #[serde(default = "default_base_delay_secs")]
+    pub base_delay_secs: u64,
+    /// Upper bound for the wait between attempts
+    #[serde(default = "default_max_delay_secs")]
+    pub max_delay_secs: u64,
+    /// Random extra wait, as a fraction of the delay (0 to 1)
+    #[serde(default = "default_jitter")]
+    pub jitter: f64,
+    /// Time allowed to establish a connection
+    #[serde(default = "default_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+    /// Time allowed for a whole request, from connecting to reading the body
+    #[serde(default = "default_request_timeout_secs")]
+    pub request_timeout_secs: u64,
+    /// Time allowed for all requests of one ticker, retries included
+    #[serde(default = "default_ticker_deadline_secs")]
+    pub ticker_deadline_secs: u64,
+    /// Consecutive failures after which an endpoint is skipped; 0 disables
+    /// the circuit breaker
+    #[serde(default = "default_breaker_threshold")]
+    pub breaker_threshold: u32,
+    /// How long a tripped endpoint is skipped before it is tried again
+    #[serde(default = "default_breaker_cooldown_secs")]
+    pub breaker_cooldown_secs: u64,
+}
+
+/// US tickers whose snapshots come from Polygon instead of FMP
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PolygonConfig {
+    /// Fetched from Polygon on every run; `export-combined --provider polygon`
+    /// does so for all US tickers
+    #[serde(default)]
+    pub tickers: Vec<String>,
+    /// Polygon and FMP market caps further apart than this (in percent) are
+    /// reported as discrepancies
+    #[serde(default = "default_discrepancy_threshold_pct")]
+    pub discrepancy_threshold_pct: f64,
+}
+
+fn default_discrepancy_threshold_pct() -> f64 {
+    5.0
+}
+
+impl Default for PolygonConfig {
+    fn default() -> Self {
+        Self {
+            tickers: Vec::new(),
+            discrepancy_threshold_pct: default_discrepancy_threshold_pct(),
+        }
+    }
+}
+
+fn default_fmp_requests_per_minute() -> u32 {
+    300
+}
+
+fn default_fmp_burst() -> u32 {
+    10
+}
+
+fn default_api_cache_ttl_hours() -> i64 {
+    24
+}
+
+fn default_max_retries() -> u32 {
+    3
+}
+
+fn default_base_delay_secs() -> u64 {
+    5
+}
+
+fn default_max_delay_secs() -> u64 {
+    60
+}
+
+fn default_jitter() -> f64 {
+    0.1
+}
+
+fn default_connect_timeout_secs() -> u64 {
+    10
+}
+
+fn default_request_timeout_secs() -> u64 {
+    60
+}
+
+fn default_ticker_deadline_secs() -> u64 {
+    180
+}
+
+fn default_breaker_threshold() -> u32 {
+    5
+}
+
+fn default_breaker_cooldown_secs() -> u64 {
+    60
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            fmp_requests_per_minute: default_fmp_requests_per_minute(),
+            fmp_burst: default_fmp_burst(),
+            cache_ttl_hours: default_api_cache_ttl_hours(),
+            max_retries: default_max_retries(),
+            base_delay_secs: default_base_delay_secs(),
+            max_delay_secs: default_max_delay_secs(),
+            jitter: default_jitter(),
+            connect_timeout_secs: default_connect_timeout_secs(),
+            request_timeout_secs: default_request_timeout_secs(),
+            ticker_deadline_secs: default_ticker_deadline_secs(),
+            breaker_threshold: default_breaker_threshold(),
+            breaker_cooldown_secs: default_breaker_cooldown_secs(),
+        }
+    }
+}
+
+/// Retries of failed background jobs
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct JobsConfig {
+    /// Attempts per job, including the first, before it is dead-lettered
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// Wait before the first retry; doubled after every further failure
+    #[serde(default = "default_initial_backoff_secs")]
+    pub initial_backoff_secs: u64,
+    /// Upper bound for the wait between attempts
+    #[serde(default = "default_max_backoff_secs")]
+    pub max_backoff_secs: u64,
+    /// On SIGTERM/SIGINT, how long `serve` waits for running jobs and open
+    /// requests before exiting; keep it below the pod's termination grace period
+    #[serde(default = "default_shutdown_timeout_secs")]
+    pub shutdown_timeout_secs: u64,
+}
+
+fn default_max_attempts() -> u32 {
+    3
+}
+
+fn default_initial_backoff_secs() -> u64 {
+    30
+}
+
+fn default_max_backoff_secs() -> u64 {
+    600
+}
+
+fn default_shutdown_timeout_secs() -> u64 {
+    25
+}
+
+impl Default for JobsConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_max_attempts(),
+            initial_backoff_secs: default_initial_backoff_secs(),
+            max_backoff_secs: default_max_backoff_secs(),
+            shutdown_timeout_secs: default_shutdown_timeout_secs(),
+        }
+    }
+}
+
+impl JobsConfig {
+    /// Wait before retrying after `failed_attempts` failures
+    pub fn backoff(&self, failed_attempts: u32) -> std::time::Duration {
+        let factor = 2u64.saturating_pow(failed_attempts.saturating_sub(1));
+        std::time::Duration::from_secs(
+            self.initial_backoff_secs
+                .saturating_mul(factor)
+                .min(self.max_backoff_secs),
+        )
+    }
+}
+
+/// Caching of company profiles shown by the `show` command
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CompanyProfileConfig {
+    /// Cached profiles older than this are refreshed from FMP
+    #[serde(default = "default_cache_ttl_hours")]
+    pub cache_ttl_hours: i64,
+    /// `export-combined` fetches the full details (CEO, country, industry,
+    /// ISIN, ...) of tickers whose details are older than this, even without
+    /// `--full-details`
+    #[serde(default = "default_details_max_age_days")]
+    pub details_max_age_days: i64,
+}
+
+fn default_cache_ttl_hours() -> i64 {
+    24
+}
+
+fn default_details_max_age_days() -> i64 {
+    30
+}
+
+impl Default for CompanyProfileConfig {
+    fn default() -> Self {
+        Self {
+            cache_ttl_hours: default_cache_ttl_hours(),
+            details_max_age_days: default_details_max_age_days(),
+        }
+    }
+}
+
+/// How missing exchange rates for a historical date are handled
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ForexConfig {
+    /// Rates older than this many days before the requested date are not used
+    #[serde(default = "default_max_staleness_days")]
+    pub max_staleness_days: i64,
+    /// Interpolate linearly between the surrounding rates instead of carrying
+    /// the older rate forward
+    #[serde(default)]
+    pub interpolate: bool,
+    /// Providers to fetch rates from, in priority order
+    #[serde(default = "default_forex_providers")]
+    pub providers: Vec<ForexSource>,
+    /// Preferred provider per currency code, e.g. `ILS = "ecb"`
+    #[serde(default)]
+    pub prefer: BTreeMap<String, ForexSource>,
+    /// Currency subunits on top of the built-in ones (`[[forex.subunits]]`)
+    #[serde(default)]
+    pub subunits: Vec<CurrencySubunit>,
+    /// Fail runs with missing exchange rates (same as `--strict-currency`)
+    #[serde(default)]
+    pub strict: bool,
+    /// Side of the stored quotes conversions use
+    #[serde(default)]
+    pub rate_side: RateSide,
+}
+
+/// Which side of a stored bid/ask quote conversions use
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RateSide {
+    #[default]
+    Ask,
+    Bid,
+    /// `(ask + bid) / 2`
+    Mid,
+}
+
+impl RateSide {
+    /// Name as written in config.toml
+    pub fn label(self) -> &'static str {
+        match self {
+            RateSide::Ask => "ask",
+            RateSide::Bid => "bid",
+            RateSide::Mid => "mid",
+        }
+    }
+
+    /// Rate of a quote for this side
+    pub fn rate(self, ask: f64, bid: f64) -> f64 {
+        match self {
+            RateSide::Ask => ask,
+            RateSide::Bid => bid,
+            RateSide::Mid => (ask + bid) / 2.0,
+        }
+    }
+}
+
+/// Quote currency whose amounts are a fraction of a parent currency, e.g.
+/// pence (`GBp`) of pounds
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CurrencySubunit {
+    /// Code used by the data provider, case-sensitive (`GBp`)
+    pub code: String,
+    /// ISO code of the currency exchange rates are quoted in (`GBP`)
+    pub parent: String,
+    /// Subunits per parent unit (100 for pence)
+    pub divisor: f64,
+}
+
+/// Exchange rate provider
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ForexSource {
+    /// Financial Modeling Prep quotes (requires `FINANCIALMODELINGPREP_API_KEY` or `FMP_API_KEYS`)
+    Fmp,
+    /// European Central Bank daily reference rates
+    Ecb,
+}
+
+fn default_forex_providers() -> Vec<ForexSource> {
+    vec![ForexSource::Fmp]
+}
+
+fn default_max_staleness_days() -> i64 {
+    5
+}
+
+impl Default for ForexConfig {
+    fn default() -> Self {
+        Self {
+            max_staleness_days: default_max_staleness_days(),
+            interpolate: false,
+            providers: default_forex_providers(),
+            prefer: BTreeMap::new(),
+            subunits: Vec::new(),
+            strict: false,
+            rate_side: RateSide::Ask,
+        }
+    }
+}
+
+/// Slack/Teams webhook notifications after comparison runs
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct NotificationConfig {
+    pub slack_webhook_url: Option<String>,
+    pub teams_webhook_url: Option<String>,
+    /// Only notify when the total or a single company moved at least this many percent
+    #[serde(default = "default_threshold_pct")]
+    pub threshold_pct: f64,
+    /// Link to the generated artifacts included in the message
+    pub artifacts_url: Option<String>,
+}
+
+fn default_threshold_pct() -> f64 {
+    3.0
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        Self {
+            slack_webhook_url: None,
+            teams_webhook_url: None,
+            threshold_pct: default_threshold_pct(),
+            artifacts_url: None,
+        }
+    }
+}
+
+impl NotificationConfig {
+    pub fn is_configured(&self) -> bool {
+        self.slack_webhook_url.is_some() || self.teams_webhook_url.is_some()
+    }
+}
+
+/// Optional upload of generated files to object storage
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct StorageConfig {
+    /// Destination such as `s3://bucket/prefix` or `gs://bucket/prefix`
+    pub upload_url: Option<String>,
+}
+
+/// Where generated files are written and how they are named.
+///
+/// `filename_template` supports the placeholders `{kind}` (e.g. `marketcaps`,
+/// `comparison`), `{date}` (the snapshot date or `FROM_to_TO` range) and
+/// `{timestamp}` (generation time as `YYYYMMDD_HHMMSS`). The file extension is
+/// appended automatically.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OutputConfig {
+    #[serde(default = "default_output_directory")]
+    pub directory: String,
+    #[serde(default = "default_filename_template")]
+    pub filename_template: String,
+    /// Write a Frictionless `datapackage.json` next to every snapshot and
+    /// comparison CSV (also `--data-package`)
+    #[serde(default)]
+    pub data_package: bool,
+}
+
+fn default_output_directory() -> String {
+    "output".to_string()
+}
+
+fn default_filename_template() -> String {
+    "{kind}_{date}_{timestamp}".to_string()
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        Self {
+            directory: default_output_directory(),
+            filename_template: default_filename_template(),
+            data_package: false,
+        }
+    }
+}
+
+impl OutputConfig {
+    /// Directory all generated files are written to
+    pub fn directory(&self) -> &Path {
+        Path::new(&self.directory)
+    }
+
+    /// Create the output directory if it doesn't exist yet
+    pub fn ensure_directory(&self) -> anyhow::Result<()> {
+        fs::create_dir_all(self.directory())?;
+        Ok(())
+    }
+
+    /// Render a file name from the template. An empty `date` drops the
+    /// placeholder together with one adjacent separator.
+    pub fn render_filename(&self, kind: &str, date: &str, timestamp: &str, ext: &str) -> String {
+        let mut name = self.filename_template.clone();
+        if date.is_empty() {
+            for token in ["_{date}", "-{date}", "{date}_", "{date}-"] {
+                name = name.replacen(token, "", 1);
+            }
+        }
+        let name = name
+            .replace("{kind}", kind)
+            .replace("{date}", date)
+            .replace("{timestamp}", timestamp);
+        format!("{}.{}", name, ext)
+    }
+
+    /// Full path for a new file of the given kind, stamped with the current time
+    pub fn file_path(&self, kind: &str, date: &str, ext: &str) -> PathBuf {
+        self.file_path_at(kind, date, &Self::timestamp(), ext)
+    }
+
+    /// Full path for a file with an explicit timestamp, so that related files
+    /// (e.g. a CSV and its summary) share the same stamp
+    pub fn file_path_at(&self, kind: &str, date: &str, timestamp: &str, ext: &str) -> PathBuf {
+        let path = self
+            .directory()
+            .join(self.render_filename(kind, date, timestamp, ext));
+        crate::run_context::record_output(&path);
+        path
+    }
+
+    /// Current time formatted for the `{timestamp}` placeholder
+    pub fn timestamp() -> String {
+        crate::clock::now().format("%Y%m%d_%H%M%S").to_string()
+    }
+
+    /// All generated files of the given kind, regardless of date or timestamp
+    pub fn list(&self, kind: &str, ext: &str) -> anyhow::Result<Vec<PathBuf>> {
+        self.glob(&glob::Pattern::escape(kind), "*", ext)
+    }
+
+    /// Path for a file with a fixed name (e.g. charts) inside the output directory
+    pub fn named_path(&self, file_name: &str) -> PathBuf {
+        let path = self.directory().join(file_name);
+        crate::run_context::record_output(&path);
+        path
+    }
+
+    /// Find the most recently generated file of the given kind and date
+    pub fn find_latest(
+        &self,
+        kind: &str,
+        date: &str,
+        ext: &str,
+    ) -> anyhow::Result<Option<PathBuf>> {
+        let mut matches = self.glob(
+            &glob::Pattern::escape(kind),
+            &glob::Pattern::escape(date),
+            ext,
+        )?;
+        // Timestamps sort lexicographically, so the last match is the newest
+        Ok(matches.pop())
+    }
+
+    /// Timestamps start with a digit, so fixed names next to a snapshot such
+    /// as `marketcaps_<date>_by_region.csv` are not matched
+    fn glob(&self, kind: &str, date: &str, ext: &str) -> anyhow::Result<Vec<PathBuf>> {
+        let pattern = self
+            .directory()
+            .join(self.render_filename(kind, date, "[0-9]*", ext))
+            .to_string_lossy()
+            .to_string();
+
+        let mut matches: Vec<PathBuf> = glob::glob(&pattern)?.filter_map(|p| p.ok()).collect();
+        matches.sort();
+        Ok(matches)
+    }
 }
 
 impl Default for Config {
@@ -27,25 +608,221 @@ impl Default for Config {
                 "ITX.MC".to_string(),
             ],
             us_tickers: vec!["NKE".to_string(), "TJX".to_string(), "VFC".to_string()],
+            output: OutputConfig::default(),
+            storage: StorageConfig::default(),
+            notifications: NotificationConfig::default(),
+            forex: ForexConfig::default(),
+            company_profiles: CompanyProfileConfig::default(),
+            api: ApiConfig::default(),
+            polygon: PolygonConfig::default(),
+            jobs: JobsConfig::default(),
+            logos: LogoConfig::default(),
+            charts: ChartConfig::default(),
+            kpis: BTreeMap::new(),
+            peer_groups: Vec::new(),
+            database_url: None,
         }
     }
 }
 
-fn get_config_path() -> PathBuf {
+/// Location of config.toml
+pub fn get_config_path() -> PathBuf {
     let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
     path.push("config.toml");
     path
 }
 
+static PROFILE: OnceLock<Option<String>> = OnceLock::new();
+
+/// Location of a profile's overlay file, `profiles/<name>.toml` next to
+/// config.toml
+pub fn profile_path(name: &str) -> PathBuf {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("profiles");
+    path.push(format!("{}.toml", name));
+    path
+}
+
+/// Names of the profiles in `profiles/`, sorted
+pub fn available_profiles() -> Vec<String> {
+    let mut dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    dir.push("profiles");
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+        .filter_map(|path| path.file_stem()?.to_str().map(str::to_string))
+        .collect();
+    names.sort();
+    names
+}
+
+/// Use the named profile (`--profile`) for this run; later calls are ignored
+pub fn init_profile(name: Option<&str>) -> anyhow::Result<()> {
+    if let Some(name) = name {
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            anyhow::bail!(
+                "Invalid --profile {:?}: use letters, digits, '-' and '_'",
+                name
+            );
+        }
+        if !profile_path(name).exists() {
+            anyhow::bail!(
+                "Unknown profile {:?}: expected {} (available: {})",
+                name,
+                profile_path(name).display(),
+                available_profiles().join(", ")
+            );
+        }
+    }
+    let _ = PROFILE.set(name.map(str::to_string));
+    Ok(())
+}
+
+/// The `--profile` of this run, if any
+pub fn profile() -> Option<&'static str> {
+    PROFILE.get().and_then(|p| p.as_deref())
+}
+
+/// Database of this run: `DATABASE_URL` when set, as everywhere else.
+/// Otherwise a profile gets its own database so its snapshots and rankings
+/// never mix with another universe (the profile's `database_url`, or
+/// `sqlite:data_<profile>.db`), and a run without one `sqlite:data.db`.
+pub fn database_url() -> String {
+    resolve_database_url(std::env::var("DATABASE_URL").ok(), profile(), || {
+        load_config().ok().and_then(|c| c.database_url)
+    })
+}
+
+fn resolve_database_url(
+    env: Option<String>,
+    profile: Option<&str>,
+    configured: impl FnOnce() -> Option<String>,
+) -> String {
+    env.unwrap_or_else(|| match profile {
+        Some(name) => configured().unwrap_or_else(|| profile_sqlite_url(name)),
+        None => "sqlite:data.db".to_string(),
+    })
+}
+
+/// SQLite database for everything but the core tables when those are in
+/// PostgreSQL: `SQLITE_DATABASE_URL` when set, else the profile's or
+/// `sqlite:data.db`
+pub fn sqlite_database_url() -> String {
+    std::env::var("SQLITE_DATABASE_URL").unwrap_or_else(|_| match profile() {
+        Some(name) => profile_sqlite_url(name),
+        None => "sqlite:data.db".to_string(),
+    })
+}
+
+fn profile_sqlite_url(name: &str) -> String {
+    format!("sqlite:data_{}.db", name)
+}
+
+/// Merge `overlay` into `base`: tables merge key by key, anything else
+/// (values, arrays such as the ticker lists) is replaced
+fn merge_toml(base: &mut toml::Value, overlay: toml::Value) {
+    match (base, overlay) {
+        (toml::Value::Table(base), toml::Value::Table(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_toml(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Move the deprecated `[profiles]` table (company profile caching, named
+/// before `--profile` existed) to `[company_profiles]`, with a warning.
+/// `source` names the file in the warning.
+fn migrate_deprecated_keys(config: &mut toml::Value, source: &str) {
+    let Some(table) = config.as_table_mut() else {
+        return;
+    };
+    let Some(old) = table.remove("profiles") else {
+        return;
+    };
+    if table.contains_key("company_profiles") {
+        eprintln!(
+            "Warning: {} has both [profiles] and [company_profiles]; ignoring the deprecated [profiles]",
+            source
+        );
+    } else {
+        eprintln!(
+            "Warning: [profiles] in {} is deprecated, rename it to [company_profiles]",
+            source
+        );
+        table.insert("company_profiles".to_string(), old);
+    }
+}
+
+/// Parse a config file, accepting deprecated keys
+fn parse_config(config_str: &str, source: &str) -> Result<Config, toml::de::Error> {
+    let mut config: toml::Value = toml::from_str(config_str)?;
+    migrate_deprecated_keys(&mut config, source);
+    config.try_into()
+}
+
+/// Config of a profile: its overlay on top of the base config. Unless the
+/// profile sets `[output] directory`, its files go to a `<profile>`
+/// subdirectory of the base output directory.
+fn apply_profile(base_str: &str, profile_str: &str, name: &str) -> anyhow::Result<Config> {
+    let mut config: toml::Value =
+        toml::from_str(base_str).map_err(|e| Error::ConfigInvalid(e.to_string()))?;
+    let mut overlay: toml::Value = toml::from_str(profile_str)
+        .map_err(|e| Error::ConfigInvalid(format!("profile {}: {}", name, e)))?;
+    migrate_deprecated_keys(&mut config, "config.toml");
+    migrate_deprecated_keys(&mut overlay, &format!("profiles/{}.toml", name));
+    let own_directory = overlay
+        .get("output")
+        .and_then(|output| output.get("directory"))
+        .is_some();
+    merge_toml(&mut config, overlay);
+    let mut config: Config = config
+        .try_into()
+        .map_err(|e: toml::de::Error| Error::ConfigInvalid(format!("profile {}: {}", name, e)))?;
+    if !own_directory {
+        config.output.directory = Path::new(&config.output.directory)
+            .join(name)
+            .to_string_lossy()
+            .into_owned();
+    }
+    Ok(config)
+}
+
 pub fn load_config() -> anyhow::Result<Config> {
-    let config_path = get_config_path();
-    match fs::read_to_string(&config_path) {
+    load_config_from(&get_config_path())
+}
+
+/// Read and parse a config file, with the overlay of this run's
+/// `--profile` applied
+pub fn load_config_from(config_path: &Path) -> anyhow::Result<Config> {
+    match fs::read_to_string(config_path) {
         Ok(config_str) => {
-            match toml::from_str(&config_str) {
+            crate::run_context::record_input(config_path);
+            if let Some(name) = profile() {
+                let path = profile_path(name);
+                let profile_str = fs::read_to_string(&path)
+                    .map_err(|e| anyhow::anyhow!("Failed to read profile {:?}: {}", path, e))?;
+                crate::run_context::record_input(&path);
+                return apply_profile(&config_str, &profile_str, name);
+            }
+            match parse_config(&config_str, &config_path.display().to_string()) {
                 Ok(config) => Ok(config),
                 Err(e) => {
                     eprintln!("Failed to parse config.toml: {}", e); // Log error
-                    Err(e.into())
+                    Err(Error::ConfigInvalid(e.to_string()).into())
                 }
             }
         }
@@ -59,6 +836,117 @@ pub fn load_config() -> anyhow::Result<Config> {
     }
 }
 
+/// Checks that go beyond parsing: blank or duplicate tickers and settings
+/// that would stall fetches. Used before a running server swaps in a new config.
+pub fn validate_config(config: &Config) -> Result<(), Error> {
+    let mut seen = std::collections::HashSet::new();
+    for ticker in config.us_tickers.iter().chain(&config.non_us_tickers) {
+        if ticker.trim().is_empty() || ticker.trim() != ticker {
+            return Err(Error::ConfigInvalid(format!(
+                "Invalid ticker {:?}: blank or surrounded by spaces",
+                ticker
+            )));
+        }
+        if !seen.insert(ticker) {
+            return Err(Error::ConfigInvalid(format!(
+                "Ticker {} is listed more than once",
+                ticker
+            )));
+        }
+    }
+    if seen.is_empty() {
+        return Err(Error::ConfigInvalid(
+            "No tickers configured in us_tickers or non_us_tickers".to_string(),
+        ));
+    }
+    if config.api.fmp_requests_per_minute == 0 {
+        return Err(Error::ConfigInvalid(
+            "[api] fmp_requests_per_minute must be at least 1".to_string(),
+        ));
+    }
+    if config.api.connect_timeout_secs == 0
+        || config.api.request_timeout_secs == 0
+        || config.api.ticker_deadline_secs == 0
+    {
+        return Err(Error::ConfigInvalid(
+            "[api] timeouts and the ticker deadline must be at least 1 second".to_string(),
+        ));
+    }
+    crate::retry::RetryPolicy::from(&config.api)
+        .validate()
+        .map_err(|e| Error::ConfigInvalid(format!("[api] {}", e)))?;
+    for subunit in &config.forex.subunits {
+        let parent_is_iso =
+            subunit.parent.len() == 3 && subunit.parent.chars().all(|c| c.is_ascii_uppercase());
+        if subunit.code.trim().is_empty() || subunit.code == subunit.parent || !parent_is_iso {
+            return Err(Error::ConfigInvalid(format!(
+                "[[forex.subunits]] {:?}: needs a code and a different three-letter uppercase parent",
+                subunit.code
+            )));
+        }
+        if !(subunit.divisor.is_finite() && subunit.divisor > 0.0) {
+            return Err(Error::ConfigInvalid(format!(
+                "[[forex.subunits]] {}: divisor must be positive",
+                subunit.code
+            )));
+        }
+    }
+    for (name, expression) in &config.kpis {
+        crate::kpis::Kpi::parse(name, expression)?;
+    }
+    if config.jobs.max_attempts == 0 {
+        return Err(Error::ConfigInvalid(
+            "[jobs] max_attempts must be at least 1".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Output settings from config.toml, falling back to the defaults when the
+/// config can't be loaded
+pub fn load_output_config() -> OutputConfig {
+    load_config().map(|c| c.output).unwrap_or_default()
+}
+
+pub fn load_forex_config() -> ForexConfig {
+    load_config().map(|c| c.forex).unwrap_or_default()
+}
+
+pub fn load_jobs_config() -> JobsConfig {
+    load_config().map(|c| c.jobs).unwrap_or_default()
+}
+
+pub fn load_chart_config() -> ChartConfig {
+    load_config().map(|c| c.charts).unwrap_or_default()
+}
+
+/// Custom KPI definitions from config.toml, none when it can't be loaded
+pub fn load_kpis() -> BTreeMap<String, String> {
+    load_config().map(|c| c.kpis).unwrap_or_default()
+}
+
+pub fn load_polygon_config() -> PolygonConfig {
+    load_config().map(|c| c.polygon).unwrap_or_default()
+}
+
+pub fn load_company_profile_config() -> CompanyProfileConfig {
+    load_config()
+        .map(|c| c.company_profiles)
+        .unwrap_or_default()
+}
+
+/// API pacing from config.toml; `FMP_REQUESTS_PER_MINUTE` overrides the quota
+pub fn load_api_config() -> ApiConfig {
+    let mut api = load_config().map(|c| c.api).unwrap_or_default();
+    if let Some(per_minute) = std::env::var("FMP_REQUESTS_PER_MINUTE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+    {
+        api.fmp_requests_per_minute = per_minute;
+    }
+    api
+}
+
 #[allow(dead_code)]
 pub fn save_config(config: &Config) -> anyhow::Result<()> {
     let config_path = get_config_path();
@@ -83,6 +971,19 @@ mod tests {
                 "ITX.MC".to_string(),
             ],
             us_tickers: vec!["NKE".to_string(), "TJX".to_string(), "VFC".to_string()],
+            output: OutputConfig::default(),
+            storage: StorageConfig::default(),
+            notifications: NotificationConfig::default(),
+            forex: ForexConfig::default(),
+            company_profiles: CompanyProfileConfig::default(),
+            api: ApiConfig::default(),
+            polygon: PolygonConfig::default(),
+            jobs: JobsConfig::default(),
+            logos: LogoConfig::default(),
+            charts: ChartConfig::default(),
+            kpis: BTreeMap::new(),
+            peer_groups: Vec::new(),
+            database_url: None,
         };
 
         assert!(!default_config.non_us_tickers.is_empty());
@@ -96,6 +997,19 @@ mod tests {
         let config = Config {
             non_us_tickers: vec!["MC.PA".to_string(), "9983.T".to_string()],
             us_tickers: vec!["NKE".to_string(), "LULU".to_string()],
+            output: OutputConfig::default(),
+            storage: StorageConfig::default(),
+            notifications: NotificationConfig::default(),
+            forex: ForexConfig::default(),
+            company_profiles: CompanyProfileConfig::default(),
+            api: ApiConfig::default(),
+            polygon: PolygonConfig::default(),
+            jobs: JobsConfig::default(),
+            logos: LogoConfig::default(),
+            charts: ChartConfig::default(),
+            kpis: BTreeMap::new(),
+            peer_groups: Vec::new(),
+            database_url: None,
         };
 
         // Serialize to TOML
@@ -147,6 +1061,19 @@ us_tickers = []
                 "LVMH.PA".to_string(), // Two-letter exchange
             ],
             us_tickers: vec!["BRK.B".to_string()],
+            output: OutputConfig::default(),
+            storage: StorageConfig::default(),
+            notifications: NotificationConfig::default(),
+            forex: ForexConfig::default(),
+            company_profiles: CompanyProfileConfig::default(),
+            api: ApiConfig::default(),
+            polygon: PolygonConfig::default(),
+            jobs: JobsConfig::default(),
+            logos: LogoConfig::default(),
+            charts: ChartConfig::default(),
+            kpis: BTreeMap::new(),
+            peer_groups: Vec::new(),
+            database_url: None,
         };
 
         let toml_str = toml::to_string_pretty(&config).expect("Failed to serialize");
@@ -183,6 +1110,19 @@ non_us_tickers = ["MC.PA"]
         let config = Config {
             non_us_tickers: vec!["TEST.PA".to_string()],
             us_tickers: vec!["TEST".to_string()],
+            output: OutputConfig::default(),
+            storage: StorageConfig::default(),
+            notifications: NotificationConfig::default(),
+            forex: ForexConfig::default(),
+            company_profiles: CompanyProfileConfig::default(),
+            api: ApiConfig::default(),
+            polygon: PolygonConfig::default(),
+            jobs: JobsConfig::default(),
+            logos: LogoConfig::default(),
+            charts: ChartConfig::default(),
+            kpis: BTreeMap::new(),
+            peer_groups: Vec::new(),
+            database_url: None,
         };
 
         // Create a temp file
@@ -199,4 +1139,373 @@ non_us_tickers = ["MC.PA"]
         assert_eq!(config.non_us_tickers, loaded.non_us_tickers);
         assert_eq!(config.us_tickers, loaded.us_tickers);
     }
+
+    #[test]
+    fn test_validate_config() {
+        let parse = |toml_content: &str| -> Config {
+            toml::from_str(toml_content).expect("Failed to parse TOML")
+        };
+
+        let valid = parse(
+            r#"
+non_us_tickers = ["MC.PA"]
+us_tickers = ["NKE"]
+"#,
+        );
+        assert!(validate_config(&valid).is_ok());
+
+        let duplicate = parse(
+            r#"
+non_us_tickers = ["NKE"]
+us_tickers = ["NKE"]
+"#,
+        );
+        let error = validate_config(&duplicate).unwrap_err().to_string();
+        assert!(error.contains("NKE is listed more than once"), "{}", error);
+
+        assert!(validate_config(&parse("non_us_tickers = []\nus_tickers = [\" NKE\"]")).is_err());
+        assert!(validate_config(&parse("non_us_tickers = []\nus_tickers = []")).is_err());
+
+        let mut no_quota = valid.clone();
+        no_quota.api.fmp_requests_per_minute = 0;
+        assert!(validate_config(&no_quota).is_err());
+
+        let mut jitter_too_large = valid.clone();
+        jitter_too_large.api.jitter = 2.0;
+        let error = validate_config(&jitter_too_large).unwrap_err().to_string();
+        assert!(error.contains("[api] retry jitter"), "{}", error);
+
+        let mut no_timeout = valid.clone();
+        no_timeout.api.request_timeout_secs = 0;
+        assert!(validate_config(&no_timeout).is_err());
+
+        let subunits = |extra: &str| {
+            parse(&format!(
+                "non_us_tickers = [\"MC.PA\"]\nus_tickers = []\n[[forex.subunits]]\n{}",
+                extra
+            ))
+        };
+        let kuwait = subunits("code = \"KWf\"\nparent = \"KWD\"\ndivisor = 1000");
+        assert_eq!(kuwait.forex.subunits[0].divisor, 1000.0);
+        assert!(validate_config(&kuwait).is_ok());
+        assert!(
+            validate_config(&subunits(
+                "code = \"KWf\"\nparent = \"kwd\"\ndivisor = 1000"
+            ))
+            .is_err()
+        );
+        assert!(
+            validate_config(&subunits("code = \"KWf\"\nparent = \"KWD\"\ndivisor = 0")).is_err()
+        );
+    }
+
+    #[test]
+    fn test_output_config_defaults_when_section_missing() {
+        let toml_content = r#"
+non_us_tickers = ["MC.PA"]
+us_tickers = ["NKE"]
+"#;
+
+        let config: Config = toml::from_str(toml_content).expect("Failed to parse TOML");
+
+        assert_eq!(config.output, OutputConfig::default());
+        assert_eq!(config.output.directory, "output");
+        assert_eq!(config.storage.upload_url, None);
+    }
+
+    #[test]
+    fn test_notification_config_from_toml() {
+        let toml_content = r#"
+non_us_tickers = []
+us_tickers = []
+
+[notifications]
+slack_webhook_url = "https://hooks.slack.com/services/T/B/X"
+"#;
+
+        let config: Config = toml::from_str(toml_content).expect("Failed to parse TOML");
+
+        assert!(config.notifications.is_configured());
+        assert_eq!(config.notifications.threshold_pct, 3.0);
+        assert!(!NotificationConfig::default().is_configured());
+    }
+
+    #[test]
+    fn test_forex_config_from_toml() {
+        let toml_content = r#"
+non_us_tickers = []
+us_tickers = []
+
+[forex]
+interpolate = true
+"#;
+
+        let config: Config = toml::from_str(toml_content).expect("Failed to parse TOML");
+
+        assert!(config.forex.interpolate);
+        assert_eq!(config.forex.max_staleness_days, 5);
+        assert_eq!(config.forex.providers, vec![ForexSource::Fmp]);
+        assert_eq!(config.forex.rate_side, RateSide::Ask);
+    }
+
+    #[test]
+    fn test_rate_side_from_toml() {
+        let toml_content = r#"
+non_us_tickers = []
+us_tickers = []
+
+[forex]
+rate_side = "mid"
+"#;
+
+        let config: Config = toml::from_str(toml_content).expect("Failed to parse TOML");
+
+        assert_eq!(config.forex.rate_side, RateSide::Mid);
+        assert_eq!(config.forex.rate_side.rate(1.10, 1.08), 1.09);
+        assert_eq!(RateSide::Bid.rate(1.10, 1.08), 1.08);
+        assert_eq!(RateSide::Ask.label(), "ask");
+    }
+
+    #[test]
+    fn test_forex_providers_from_toml() {
+        let toml_content = r#"
+non_us_tickers = []
+us_tickers = []
+
+[forex]
+providers = ["fmp", "ecb"]
+
+[forex.prefer]
+ILS = "ecb"
+KRW = "ecb"
+"#;
+
+        let config: Config = toml::from_str(toml_content).expect("Failed to parse TOML");
+
+        assert_eq!(
+            config.forex.providers,
+            vec![ForexSource::Fmp, ForexSource::Ecb]
+        );
+        assert_eq!(config.forex.prefer.get("ILS"), Some(&ForexSource::Ecb));
+    }
+
+    #[test]
+    fn test_storage_config_from_toml() {
+        let toml_content = r#"
+non_us_tickers = []
+us_tickers = []
+
+[storage]
+upload_url = "s3://reports/top200"
+"#;
+
+        let config: Config = toml::from_str(toml_content).expect("Failed to parse TOML");
+
+        assert_eq!(
+            config.storage.upload_url.as_deref(),
+            Some("s3://reports/top200")
+        );
+    }
+
+    #[test]
+    fn test_jobs_config_backoff() {
+        let toml_content = r#"
+non_us_tickers = []
+us_tickers = []
+
+[jobs]
+max_attempts = 5
+"#;
+
+        let config: Config = toml::from_str(toml_content).expect("Failed to parse TOML");
+
+        assert_eq!(config.jobs.max_attempts, 5);
+        assert_eq!(config.jobs.backoff(1).as_secs(), 30);
+        assert_eq!(config.jobs.backoff(2).as_secs(), 60);
+        assert_eq!(config.jobs.backoff(3).as_secs(), 120);
+        assert_eq!(config.jobs.backoff(10).as_secs(), 600);
+    }
+
+    #[test]
+    fn test_output_config_from_toml() {
+        let toml_content = r#"
+non_us_tickers = []
+us_tickers = []
+
+[output]
+directory = "/mnt/datalake/top200"
+filename_template = "top200-{kind}-{date}"
+"#;
+
+        let config: Config = toml::from_str(toml_content).expect("Failed to parse TOML");
+
+        assert_eq!(config.output.directory, "/mnt/datalake/top200");
+        assert_eq!(
+            config
+                .output
+                .render_filename("marketcaps", "2025-01-01", "20250101_120000", "csv"),
+            "top200-marketcaps-2025-01-01.csv"
+        );
+    }
+
+    #[test]
+    fn test_render_filename_default_template() {
+        let output = OutputConfig::default();
+
+        assert_eq!(
+            output.render_filename("marketcaps", "2025-03-15", "20250315_120000", "csv"),
+            "marketcaps_2025-03-15_20250315_120000.csv"
+        );
+        assert_eq!(
+            output.render_filename(
+                "comparison",
+                "2025-01-01_to_2025-02-01",
+                "20250201_120000",
+                "md"
+            ),
+            "comparison_2025-01-01_to_2025-02-01_20250201_120000.md"
+        );
+    }
+
+    #[test]
+    fn test_render_filename_without_date() {
+        let output = OutputConfig::default();
+
+        assert_eq!(
+            output.render_filename("combined_marketcaps", "", "20250315_120000", "csv"),
+            "combined_marketcaps_20250315_120000.csv"
+        );
+    }
+
+    #[test]
+    fn test_find_latest_picks_newest_timestamp() {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let output = OutputConfig {
+            directory: dir.path().to_string_lossy().to_string(),
+            ..OutputConfig::default()
+        };
+
+        for name in [
+            "marketcaps_2025-01-01_20250101_090000.csv",
+            "marketcaps_2025-01-01_20250101_120000.csv",
+            "marketcaps_2025-01-02_20250102_080000.csv",
+            "marketcaps_2025-01-01_by_region.csv",
+        ] {
+            fs::write(dir.path().join(name), "").expect("Failed to write");
+        }
+
+        let latest = output
+            .find_latest("marketcaps", "2025-01-01", "csv")
+            .expect("glob failed")
+            .expect("no match");
+        assert_eq!(
+            latest.file_name().unwrap(),
+            "marketcaps_2025-01-01_20250101_120000.csv"
+        );
+
+        assert!(
+            output
+                .find_latest("marketcaps", "2024-12-31", "csv")
+                .expect("glob failed")
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_apply_profile() {
+        let base = r#"
+non_us_tickers = ["MC.PA", "ITX.MC"]
+us_tickers = ["NKE"]
+
+[output]
+directory = "output"
+
+[api]
+fmp_requests_per_minute = 100
+"#;
+        let beauty = r#"
+non_us_tickers = ["OR.PA"]
+us_tickers = ["EL", "ULTA"]
+
+[api]
+cache_ttl_hours = 6
+
+[[peer_groups]]
+name = "Prestige"
+tickers = ["EL", "OR.PA"]
+"#;
+        let config = apply_profile(base, beauty, "beauty").unwrap();
+        assert_eq!(config.non_us_tickers, vec!["OR.PA"]);
+        assert_eq!(config.us_tickers, vec!["EL", "ULTA"]);
+        // Tables merge key by key
+        assert_eq!(config.api.fmp_requests_per_minute, 100);
+        assert_eq!(config.api.cache_ttl_hours, 6);
+        assert_eq!(config.peer_groups.len(), 1);
+        assert_eq!(config.peer_groups[0].name, "Prestige");
+        assert_eq!(config.peer_groups[0].description, None);
+        assert_eq!(
+            config.output.directory(),
+            Path::new("output").join("beauty")
+        );
+        assert_eq!(config.database_url, None);
+
+        let own = "database_url = \"sqlite:beauty.db\"\n[output]\ndirectory = \"beauty\"";
+        let config = apply_profile(base, own, "beauty").unwrap();
+        assert_eq!(config.output.directory, "beauty");
+        assert_eq!(config.database_url.as_deref(), Some("sqlite:beauty.db"));
+        assert_eq!(config.us_tickers, vec!["NKE"]);
+
+        let error = apply_profile(base, "us_tickers = \"EL\"", "beauty")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("profile beauty"), "{}", error);
+    }
+
+    #[test]
+    fn test_deprecated_profiles_section_is_read_as_company_profiles() {
+        let base = r#"
+non_us_tickers = []
+us_tickers = ["NKE"]
+
+[profiles]
+cache_ttl_hours = 12
+"#;
+        let config = parse_config(base, "config.toml").unwrap();
+        assert_eq!(config.company_profiles.cache_ttl_hours, 12);
+        assert_eq!(config.company_profiles.details_max_age_days, 30);
+
+        // The new name wins when both are present
+        let both = format!("{}\n[company_profiles]\ncache_ttl_hours = 48\n", base);
+        let config = parse_config(&both, "config.toml").unwrap();
+        assert_eq!(config.company_profiles.cache_ttl_hours, 48);
+
+        // A profile overlay using the old name still merges over the base
+        let overlay = "[profiles]\ndetails_max_age_days = 7";
+        let config = apply_profile(base, overlay, "beauty").unwrap();
+        assert_eq!(config.company_profiles.cache_ttl_hours, 12);
+        assert_eq!(config.company_profiles.details_max_age_days, 7);
+    }
+
+    #[test]
+    fn test_database_url_env_takes_precedence() {
+        let configured = || Some("sqlite:beauty.db".to_string());
+        let env = Some("postgres://db/top200".to_string());
+        assert_eq!(
+            resolve_database_url(env.clone(), Some("beauty"), configured),
+            "postgres://db/top200"
+        );
+        assert_eq!(
+            resolve_database_url(env, None, || None),
+            "postgres://db/top200"
+        );
+        assert_eq!(
+            resolve_database_url(None, Some("beauty"), configured),
+            "sqlite:beauty.db"
+        );
+        assert_eq!(
+            resolve_database_url(None, Some("beauty"), || None),
+            "sqlite:data_beauty.db"
+        );
+        assert_eq!(resolve_database_url(None, None, || None), "sqlite:data.db");
+    }
 }
@@ -0,0 +1,218 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Corporate actions (M&A, spin-offs, ...) from `corporate_actions.toml`, used to
+//! annotate or exclude companies whose market cap moves are distorted by them
+
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ActionType {
+    Merger,
+    Acquisition,
+    Spinoff,
+    Delisting,
+    Other,
+}
+
+impl fmt::Display for ActionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self {
+            ActionType::Merger => "merger",
+            ActionType::Acquisition => "acquisition",
+            ActionType::Spinoff => "spin-off",
+            ActionType::Delisting => "delisting",
+            ActionType::Other => "corporate action",
+        };
+        write!(f, "{}", label)
+    }
+}
+
+/// One event affecting one or more tickers
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct CorporateAction {
+    pub date: NaiveDate,
+    pub tickers: Vec<String>,
+    #[serde(rename = "type")]
+    pub action_type: ActionType,
+    #[serde(default)]
+    pub note: String,
+}
+
+impl fmt::Display for CorporateAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.date, self.action_type)?;
+        if !self.note.is_empty() {
+            write!(f, ": {}", self.note)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct CorporateActionsFile {
+    #[serde(default)]
+    actions: Vec<CorporateAction>,
+}
+
+fn get_corporate_actions_path() -> PathBuf {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("corporate_actions.toml");
+    path
+}
+
+pub fn parse_corporate_actions(content: &str) -> Result<Vec<CorporateAction>> {
+    let file: CorporateActionsFile =
+        toml::from_str(content).context("Failed to parse corporate_actions.toml")?;
+    Ok(file.actions)
+}
+
+/// Load all corporate actions; a missing file means there are none
+pub fn load_corporate_actions() -> Result<Vec<CorporateAction>> {
+    let path = get_corporate_actions_path();
+    match fs::read_to_string(&path) {
+        Ok(content) => {
+            crate::run_context::record_input(&path);
+            parse_corporate_actions(&content)
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e).context("Failed to read corporate_actions.toml"),
+    }
+}
+
+/// Events between two dates (after `from`, up to and including `to`), per affected ticker
+#[derive(Debug, Default, Clone)]
+pub struct CorporateActionIndex {
+    by_ticker: BTreeMap<String, Vec<CorporateAction>>,
+    events: Vec<CorporateAction>,
+}
+
+impl CorporateActionIndex {
+    pub fn for_period(actions: &[CorporateAction], from: NaiveDate, to: NaiveDate) -> Self {
+        let mut index = Self::default();
+        for action in actions.iter().filter(|a| a.date > from && a.date <= to) {
+            for ticker in &action.tickers {
+                index
+                    .by_ticker
+                    .entry(ticker.clone())
+                    .or_default()
+                    .push(action.clone());
+            }
+            index.events.push(action.clone());
+        }
+        index.events.sort_by_key(|a| a.date);
+        index
+    }
+
+    /// Load `corporate_actions.toml` and index the events between two YYYY-MM-DD dates
+    pub fn load_for_period(from: &str, to: &str) -> Result<Self> {
+        let from = NaiveDate::parse_from_str(from, "%Y-%m-%d")?;
+        let to = NaiveDate::parse_from_str(to, "%Y-%m-%d")?;
+        Ok(Self::for_period(&load_corporate_actions()?, from, to))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn is_affected(&self, ticker: &str) -> bool {
+        self.by_ticker.contains_key(ticker)
+    }
+
+    /// Annotation for a ticker's row, e.g. "2023-08-10 acquisition: Tapestry agrees to acquire Capri"
+    pub fn annotation(&self, ticker: &str) -> Option<String> {
+        self.by_ticker.get(ticker).map(|actions| {
+            actions
+                .iter()
+                .map(|a| a.to_string())
+                .collect::<Vec<_>>()
+                .join("; ")
+        })
+    }
+
+    /// Markdown section listing the events in the period
+    pub fn markdown_section(&self, excluded: bool) -> String {
+        let mut section = String::from("## Corporate Actions\n\n");
+        if excluded {
+            section
+                .push_str("Companies affected by these events are excluded from this report.\n\n");
+        } else {
+            section.push_str(
+                "Market cap changes of these companies are distorted by the events below (marked with †).\n\n",
+            );
+        }
+        for action in &self.events {
+            section.push_str(&format!(
+                "- **{}** ({})\n",
+                action,
+                action.tickers.join(", ")
+            ));
+        }
+        section.push('\n');
+        section
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r#"
+[[actions]]
+date = "2023-08-10"
+tickers = ["TPR", "CPRI"]
+type = "acquisition"
+note = "Tapestry agrees to acquire Capri Holdings"
+
+[[actions]]
+date = "2025-01-15"
+tickers = ["VFC"]
+type = "spinoff"
+"#;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn test_parse_corporate_actions() {
+        let actions = parse_corporate_actions(SAMPLE).unwrap();
+        assert_eq!(actions.len(), 2);
+        assert_eq!(actions[0].action_type, ActionType::Acquisition);
+        assert_eq!(actions[1].note, "");
+        assert!(parse_corporate_actions("").unwrap().is_empty());
+        assert!(parse_corporate_actions("[[actions]]\ndate = \"x\"").is_err());
+    }
+
+    #[test]
+    fn test_index_for_period() {
+        let actions = parse_corporate_actions(SAMPLE).unwrap();
+
+        let index =
+            CorporateActionIndex::for_period(&actions, date("2023-01-01"), date("2023-12-31"));
+        assert!(index.is_affected("CPRI"));
+        assert!(!index.is_affected("VFC"));
+        assert_eq!(
+            index.annotation("TPR").as_deref(),
+            Some("2023-08-10 acquisition: Tapestry agrees to acquire Capri Holdings")
+        );
+        assert!(index.markdown_section(false).contains("TPR, CPRI"));
+
+        // The from date itself is excluded, the to date included
+        let index =
+            CorporateActionIndex::for_period(&actions, date("2023-08-10"), date("2025-01-15"));
+        assert!(!index.is_affected("TPR"));
+        assert_eq!(
+            index.annotation("VFC").as_deref(),
+            Some("2025-01-15 spin-off")
+        );
+    }
+}
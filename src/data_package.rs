@@ -0,0 +1,449 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Frictionless Data packages for exported CSVs (`--data-package`)
+//!
+//! With `--data-package` or `[output] data_package = true`, every snapshot
+//! and comparison CSV gets a `<csv name>.datapackage.json` descriptor: the
+//! column types, the currency or unit of every amount, the missing value
+//! markers, the file hash and where the figures come from. Open-data tools
+//! (`frictionless validate`, pandas loaders) can then check and load the
+//! files without knowing our column names. The descriptor has no generation
+//! time, so it only changes in git when the data or the columns change.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::config;
+use crate::models::DataSource;
+use crate::run_context;
+
+static ENABLED: OnceLock<bool> = OnceLock::new();
+
+/// Write data packages in this run; later calls are ignored
+pub fn init(enabled: bool) {
+    let _ = ENABLED.set(enabled);
+}
+
+fn enabled() -> bool {
+    ENABLED.get().copied().unwrap_or(false)
+}
+
+/// Values the CSV exports write for a missing figure
+const MISSING_VALUES: [&str; 2] = ["", "NA"];
+
+/// What a CSV contains, for the package metadata
+#[derive(Debug, Clone)]
+pub struct Provenance<'a> {
+    /// Output kind, e.g. `marketcaps` or `comparison`
+    pub kind: &'a str,
+    pub title: String,
+    /// Snapshot date, or the from and to date of a comparison
+    pub dates: Vec<String>,
+    /// Date the snapshot was asked for when it was moved to the previous
+    /// trading day (`--align-to-trading-day`)
+    pub requested_date: Option<String>,
+    /// Snapshot CSVs the figures were read from; their `Data Source`
+    /// columns name the providers along with the CSV's own
+    pub inputs: Vec<PathBuf>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Field {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub field_type: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<&'static str>,
+    pub description: String,
+    /// ISO 4217 code of a fixed-currency amount
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+    /// Column holding the currency of an amount in each row's own currency
+    #[serde(rename = "currencyField", skip_serializing_if = "Option::is_none")]
+    pub currency_field: Option<&'static str>,
+    /// `percent` for percentages
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unit: Option<&'static str>,
+}
+
+impl Field {
+    fn new(name: &str, field_type: &'static str, description: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            field_type,
+            format: None,
+            description: description.into(),
+            currency: None,
+            currency_field: None,
+            unit: None,
+        }
+    }
+}
+
+/// Currency code in a column name like `Market Cap (EUR)` or `Market Cap To (JPY)`
+fn column_currency(column: &str) -> Option<&str> {
+    let code = column.strip_suffix(')')?.rsplit_once('(')?.1;
+    (code.len() == 3 && code.bytes().all(|b| b.is_ascii_uppercase())).then_some(code)
+}
+
+/// Schema of one column of the snapshot or comparison CSVs
+pub fn field_schema(column: &str) -> Field {
+    let field = |field_type, description: &str| Field::new(column, field_type, description);
+    match column {
+        "Rank" | "Rank From" | "Rank To" => field("integer", "Position by EUR market cap"),
+        "Rank Change" => field("integer", "Places gained (positive) or lost"),
+        "Ticker" => field("string", "Exchange ticker"),
+        "Name" => field("string", "Company name"),
+        "Original Currency" | "Currency" => field("string", "ISO 4217 code of the listing"),
+        "Market Cap (Original)" | "Market Cap From" | "Market Cap To" | "Absolute Change" => {
+            Field {
+                currency_field: Some(if column == "Market Cap (Original)" {
+                    "Original Currency"
+                } else {
+                    "Currency"
+                }),
+                ..field("number", "Market cap in the listing currency")
+            }
+        }
+        "EUR Rate" | "USD Rate" => field(
+            "number",
+            &format!("Units of {} per unit of the listing currency", &column[..3]),
+        ),
+        "Price" => Field {
+            currency_field: Some("Original Currency"),
+            ..field("number", "Share price in the listing currency")
+        },
+        "Exchange" => field("string", "Listing exchange"),
+        "ISIN" => field("string", "International Securities Identification Number"),
+        "Active" => field("boolean", "Whether the listing is active"),
+        "Employees" => field("integer", "Full-time employees"),
+        "Homepage URL" => Field {
+            format: Some("uri"),
+            ..field("string", "Company website")
+        },
+        "Date" => field("date", "Snapshot date"),
+        "Data Source" => field(
+            "string",
+            "Provider the market cap was fetched from: fmp, polygon or import",
+        ),
+        "Fetched At" => field("datetime", "When the market cap was fetched, in UTC"),
+        _ if column.contains("(%)") => Field {
+            unit: Some("percent"),
+            ..field("number", column.replace(" (%)", "").as_str())
+        },
+        _ => match column_currency(column) {
+            Some(code) => Field {
+                currency: Some(code.to_string()),
+                ..field("number", &format!("Market cap converted to {}", code))
+            },
+            None => field("string", column),
+        },
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Schema {
+    fields: Vec<Field>,
+    #[serde(rename = "missingValues")]
+    missing_values: [&'static str; 2],
+}
+
+#[derive(Debug, Serialize)]
+struct Resource {
+    name: String,
+    path: String,
+    profile: &'static str,
+    format: &'static str,
+    mediatype: &'static str,
+    encoding: &'static str,
+    bytes: u64,
+    hash: String,
+    schema: Schema,
+}
+
+#[derive(Debug, Serialize)]
+struct Source {
+    title: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<&'static str>,
+}
+
+/// Source entry of a provider in the `Data Source` column
+fn source(provider: DataSource) -> Source {
+    match provider {
+        DataSource::Fmp => Source {
+            title: "Financial Modeling Prep",
+            path: Some("https://financialmodelingprep.com"),
+        },
+        DataSource::Polygon => Source {
+            title: "Polygon.io",
+            path: Some("https://polygon.io"),
+        },
+        DataSource::Import => Source {
+            title: "Imported market cap files",
+            path: None,
+        },
+    }
+}
+
+/// Values of the `Data Source` column of a CSV; none without the column
+fn providers_in(csv_path: &Path) -> Result<BTreeSet<String>> {
+    let mut reader = csv::Reader::from_path(csv_path)
+        .with_context(|| format!("Failed to open {}", csv_path.display()))?;
+    let Some(column) = reader.headers()?.iter().position(|h| h == "Data Source") else {
+        return Ok(BTreeSet::new());
+    };
+    let mut providers = BTreeSet::new();
+    for record in reader.records() {
+        if let Some(provider) = record?.get(column).filter(|p| !p.is_empty()) {
+            providers.insert(provider.to_string());
+        }
+    }
+    Ok(providers)
+}
+
+/// How the figures were produced, as `top200` in the descriptor
+#[derive(Debug, Serialize)]
+struct Top200Metadata {
+    version: &'static str,
+    kind: String,
+    dates: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    requested_date: Option<String>,
+    /// Side of the forex quotes amounts were converted with
+    rate_side: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+struct DataPackage {
+    profile: &'static str,
+    name: String,
+    title: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    sources: Vec<Source>,
+    top200: Top200Metadata,
+    resources: Vec<Resource>,
+}
+
+/// Lower-case name with only the characters the spec allows
+fn package_name(value: &str) -> String {
+    value
+        .to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | '0'..='9' | '.' | '_' | '-' => c,
+            _ => '-',
+        })
+        .collect()
+}
+
+/// Descriptor for one CSV, with a schema built from its header row
+fn describe(csv_path: &Path, provenance: &Provenance<'_>) -> Result<DataPackage> {
+    let mut reader = csv::Reader::from_path(csv_path)
+        .with_context(|| format!("Failed to open {}", csv_path.display()))?;
+    let fields = reader.headers()?.iter().map(field_schema).collect();
+    let artifact = run_context::file_artifact(csv_path)
+        .with_context(|| format!("Failed to read {}", csv_path.display()))?;
+    let file_name = csv_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let stem = file_name.trim_end_matches(".csv");
+    let mut providers = providers_in(csv_path)?;
+    for input in &provenance.inputs {
+        providers.extend(providers_in(input)?);
+    }
+    // Rows stored before the column existed have no provider to list
+    let sources = [DataSource::Fmp, DataSource::Polygon, DataSource::Import]
+        .into_iter()
+        .filter(|provider| providers.contains(provider.as_str()))
+        .map(source)
+        .collect();
+
+    Ok(DataPackage {
+        profile: "tabular-data-package",
+        name: package_name(stem),
+        title: provenance.title.clone(),
+        sources,
+        top200: Top200Metadata {
+            version: env!("CARGO_PKG_VERSION"),
+            kind: provenance.kind.to_string(),
+            dates: provenance.dates.clone(),
+            requested_date: provenance.requested_date.clone(),
+            rate_side: config::load_forex_config().rate_side.label(),
+        },
+        resources: vec![Resource {
+            name: package_name(stem),
+            path: file_name.clone(),
+            profile: "tabular-data-resource",
+            format: "csv",
+            mediatype: "text/csv",
+            encoding: "utf-8",
+            bytes: artifact.bytes,
+            hash: format!("sha256:{}", artifact.sha256),
+            schema: Schema {
+                fields,
+                missing_values: MISSING_VALUES,
+            },
+        }],
+    })
+}
+
+/// `<csv name>.datapackage.json` next to the CSV
+fn descriptor_path(csv_path: &Path) -> PathBuf {
+    csv_path.with_extension("datapackage.json")
+}
+
+/// Write the data package of a CSV that was just exported, when enabled
+pub fn write_for(csv_path: &Path, provenance: Provenance<'_>) -> Result<()> {
+    if !enabled() {
+        return Ok(());
+    }
+    let package = describe(csv_path, &provenance)?;
+    let path = descriptor_path(csv_path);
+    run_context::record_output(&path);
+    std::fs::write(&path, serde_json::to_string_pretty(&package)? + "\n")
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    println!("✅ Data package written to {}", path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_schema_units_and_types() {
+        let eur = field_schema("Market Cap (EUR)");
+        assert_eq!(eur.field_type, "number");
+        assert_eq!(eur.currency.as_deref(), Some("EUR"));
+        let jpy = field_schema("Market Cap To (JPY)");
+        assert_eq!(jpy.currency.as_deref(), Some("JPY"));
+
+        let original = field_schema("Market Cap (Original)");
+        assert_eq!(original.currency, None);
+        assert_eq!(original.currency_field, Some("Original Currency"));
+        assert_eq!(
+            field_schema("Absolute Change").currency_field,
+            Some("Currency")
+        );
+
+        let share = field_schema("Market Share From (%)");
+        assert_eq!(share.unit, Some("percent"));
+        assert_eq!(share.description, "Market Share From");
+        assert_eq!(field_schema("Rank Change").field_type, "integer");
+        assert_eq!(field_schema("Date").field_type, "date");
+        assert_eq!(field_schema("Fetched At").field_type, "datetime");
+        assert_eq!(field_schema("Homepage URL").format, Some("uri"));
+        assert_eq!(field_schema("Formerly").field_type, "string");
+    }
+
+    #[test]
+    fn test_describe_csv() {
+        let dir = tempfile::tempdir().unwrap();
+        let csv_path = dir.path().join("comparison_2025-01-01_to_2025-02-01.csv");
+        std::fs::write(
+            &csv_path,
+            "Ticker,Currency,Market Cap From,Percentage Change (%)\nNKE,USD,100.00,NA\n",
+        )
+        .unwrap();
+        let provenance = Provenance {
+            kind: "comparison",
+            title: "Market cap comparison".to_string(),
+            dates: vec!["2025-01-01".to_string(), "2025-02-01".to_string()],
+            requested_date: None,
+            inputs: Vec::new(),
+        };
+        let package = describe(&csv_path, &provenance).unwrap();
+        assert_eq!(package.name, "comparison_2025-01-01_to_2025-02-01");
+        let resource = &package.resources[0];
+        assert_eq!(resource.path, "comparison_2025-01-01_to_2025-02-01.csv");
+        assert_eq!(resource.bytes, 72);
+        assert!(resource.hash.starts_with("sha256:"));
+        let names: Vec<&str> = resource
+            .schema
+            .fields
+            .iter()
+            .map(|f| f.name.as_str())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "Ticker",
+                "Currency",
+                "Market Cap From",
+                "Percentage Change (%)"
+            ]
+        );
+
+        let json = serde_json::to_value(&package).unwrap();
+        assert_eq!(json["profile"], "tabular-data-package");
+        assert_eq!(json["top200"]["dates"][1], "2025-02-01");
+        assert!(json["top200"].get("requested_date").is_none());
+        // No Data Source column and no inputs, so no provider to name
+        assert!(json.get("sources").is_none());
+        assert_eq!(json["resources"][0]["schema"]["missingValues"][1], "NA");
+        assert_eq!(
+            json["resources"][0]["schema"]["fields"][2]["currencyField"],
+            "Currency"
+        );
+        // Unset properties are left out rather than written as null
+        assert!(
+            json["resources"][0]["schema"]["fields"][0]
+                .get("unit")
+                .is_none()
+        );
+        assert_eq!(
+            descriptor_path(&csv_path),
+            dir.path()
+                .join("comparison_2025-01-01_to_2025-02-01.datapackage.json")
+        );
+    }
+
+    #[test]
+    fn test_sources_are_the_providers_of_the_rows() {
+        let dir = tempfile::tempdir().unwrap();
+        let from = dir.path().join("marketcaps_2025-01-01_20250101_000000.csv");
+        std::fs::write(&from, "Ticker,Data Source\nNKE,polygon\nMC.PA,fmp\nOLD,\n").unwrap();
+        let to = dir.path().join("marketcaps_2025-02-01_20250201_000000.csv");
+        std::fs::write(&to, "Ticker,Data Source\nNKE,import\n").unwrap();
+        let comparison = dir.path().join("comparison_2025-01-01_to_2025-02-01.csv");
+        std::fs::write(&comparison, "Ticker\nNKE\n").unwrap();
+
+        let snapshot = Provenance {
+            kind: "marketcaps",
+            title: "Market caps on 2025-01-01".to_string(),
+            dates: vec!["2025-01-01".to_string()],
+            requested_date: None,
+            inputs: Vec::new(),
+        };
+        let package = describe(&from, &snapshot).unwrap();
+        let titles: Vec<&str> = package.sources.iter().map(|s| s.title).collect();
+        assert_eq!(titles, vec!["Financial Modeling Prep", "Polygon.io"]);
+
+        let compared = Provenance {
+            kind: "comparison",
+            title: "Market cap comparison".to_string(),
+            dates: vec!["2025-01-01".to_string(), "2025-02-01".to_string()],
+            requested_date: None,
+            inputs: vec![from, to],
+        };
+        let package = describe(&comparison, &compared).unwrap();
+        let titles: Vec<&str> = package.sources.iter().map(|s| s.title).collect();
+        assert_eq!(
+            titles,
+            vec![
+                "Financial Modeling Prep",
+                "Polygon.io",
+                "Imported market cap files"
+            ]
+        );
+        let json = serde_json::to_value(&package).unwrap();
+        assert!(json["sources"][2].get("path").is_none());
+    }
+}
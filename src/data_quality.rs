@@ -0,0 +1,576 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Data quality checks on freshly fetched market cap snapshots
+//!
+//! Each snapshot (all `market_caps` rows sharing a timestamp) is compared with
+//! the previous snapshot to flag suspicious upstream data before it is published.
+//! Scheduled fetch runs end here, so this is also where the webhook run summary
+//! (total change and top movers since the previous snapshot) is sent.
+
+use anyhow::Result;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime};
+use sqlx::Row;
+use sqlx::sqlite::SqlitePool;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::Write as IoWrite;
+
+use crate::clock;
+use crate::config;
+use crate::notify;
+use crate::run_context;
+
+/// Relative change (in percent) between snapshots that is considered suspicious
+pub const DEFAULT_MAX_CHANGE_PCT: f64 = 50.0;
+
+/// One company's row in a snapshot
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnapshotRow {
+    pub ticker: String,
+    pub name: String,
+    pub market_cap_original: Option<f64>,
+    pub market_cap_usd: Option<f64>,
+    pub original_currency: Option<String>,
+    pub price: Option<f64>,
+}
+
+/// What looks wrong about a ticker
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnomalyKind {
+    /// Market cap moved more than the threshold since the previous snapshot
+    LargeChange {
+        previous: f64,
+        current: f64,
+        pct: f64,
+    },
+    /// Reporting currency differs from the previous snapshot
+    CurrencyChanged { previous: String, current: String },
+    /// Price reported as zero
+    ZeroPrice,
+    /// Ticker had a market cap previously but has none now
+    MissingMarketCap,
+}
+
+impl fmt::Display for AnomalyKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnomalyKind::LargeChange {
+                previous,
+                current,
+                pct,
+            } => write!(
+                f,
+                "Market cap changed {:+.2}% ({:.2}B → {:.2}B)",
+                pct,
+                previous / 1_000_000_000.0,
+                current / 1_000_000_000.0
+            ),
+            AnomalyKind::CurrencyChanged { previous, current } => {
+                write!(f, "Currency changed from {} to {}", previous, current)
+            }
+            AnomalyKind::ZeroPrice => write!(f, "Price is zero"),
+            AnomalyKind::MissingMarketCap => {
+                write!(f, "Market cap missing (present in previous snapshot)")
+            }
+        }
+    }
+}
+
+/// A flagged data issue
+#[derive(Debug, Clone, PartialEq)]
+pub struct Anomaly {
+    pub ticker: String,
+    pub name: String,
+    pub kind: AnomalyKind,
+}
+
+fn has_market_cap(row: &SnapshotRow) -> bool {
+    row.market_cap_original.map(|v| v > 0.0).unwrap_or(false)
+}
+
+/// Compare two snapshots and return all anomalies, ordered by ticker
+pub fn detect_anomalies(
+    previous: &[SnapshotRow],
+    current: &[SnapshotRow],
+    max_change_pct: f64,
+) -> Vec<Anomaly> {
+    let previous_map: HashMap<&str, &SnapshotRow> =
+        previous.iter().map(|r| (r.ticker.as_str(), r)).collect();
+    let current_map: HashMap<&str, &SnapshotRow> =
+        current.iter().map(|r| (r.ticker.as_str(), r)).collect();
+
+    let mut anomalies = Vec::new();
+    let mut push = |row: &SnapshotRow, kind: AnomalyKind| {
+        anomalies.push(Anomaly {
+            ticker: row.ticker.clone(),
+            name: row.name.clone(),
+            kind,
+        })
+    };
+
+    for row in current {
+        if row.price == Some(0.0) {
+            push(row, AnomalyKind::ZeroPrice);
+        }
+
+        let Some(prev) = previous_map.get(row.ticker.as_str()) else {
+            continue;
+        };
+
+        match (&prev.original_currency, &row.original_currency) {
+            (Some(p), Some(c)) if !p.is_empty() && !c.is_empty() && p != c => {
+                push(
+                    row,
+                    AnomalyKind::CurrencyChanged {
+                        previous: p.clone(),
+                        current: c.clone(),
+                    },
+                );
+                // A percentage change across currencies is meaningless
+                continue;
+            }
+            _ => {}
+        }
+
+        if has_market_cap(prev) && !has_market_cap(row) {
+            push(row, AnomalyKind::MissingMarketCap);
+            continue;
+        }
+
+        if let (Some(p), Some(c)) = (prev.market_cap_original, row.market_cap_original)
+            && p > 0.0
+            && c > 0.0
+        {
+            let pct = (c - p) / p * 100.0;
+            if pct.abs() > max_change_pct {
+                push(
+                    row,
+                    AnomalyKind::LargeChange {
+                        previous: p,
+                        current: c,
+                        pct,
+                    },
+                );
+            }
+        }
+    }
+
+    // Tickers that disappeared from the snapshot entirely
+    for prev in previous {
+        if has_market_cap(prev) && !current_map.contains_key(prev.ticker.as_str()) {
+            push(prev, AnomalyKind::MissingMarketCap);
+        }
+    }
+
+    anomalies.sort_by(|a, b| a.ticker.cmp(&b.ticker));
+    anomalies
+}
+
+/// Summarize the moves between two snapshots for webhook notifications. The
+/// total change is computed in USD over companies present in both.
+pub fn run_summary(
+    previous: &[SnapshotRow],
+    current: &[SnapshotRow],
+    from_date: &str,
+    to_date: &str,
+) -> notify::RunSummary {
+    let previous_map: HashMap<&str, &SnapshotRow> =
+        previous.iter().map(|r| (r.ticker.as_str(), r)).collect();
+
+    let mut total_from = 0.0;
+    let mut total_to = 0.0;
+    let mut movers = Vec::new();
+    for row in current {
+        let Some(prev) = previous_map.get(row.ticker.as_str()) else {
+            continue;
+        };
+        let (Some(from), Some(to)) = (prev.market_cap_usd, row.market_cap_usd) else {
+            continue;
+        };
+        if from <= 0.0 {
+            continue;
+        }
+        total_from += from;
+        total_to += to;
+        movers.push(notify::Mover {
+            ticker: row.ticker.clone(),
+            name: row.name.clone(),
+            percentage_change: (to - from) / from * 100.0,
+        });
+    }
+
+    let by_change = |a: &&notify::Mover, b: &&notify::Mover| {
+        a.percentage_change.total_cmp(&b.percentage_change)
+    };
+    let top_gainer = movers
+        .iter()
+        .filter(|m| m.percentage_change > 0.0)
+        .max_by(by_change)
+        .cloned();
+    let top_loser = movers
+        .iter()
+        .filter(|m| m.percentage_change < 0.0)
+        .min_by(by_change)
+        .cloned();
+
+    notify::RunSummary {
+        from_date: from_date.to_string(),
+        to_date: to_date.to_string(),
+        total_change_pct: (total_from > 0.0).then(|| (total_to - total_from) / total_from * 100.0),
+        top_gainer,
+        top_loser,
+        artifacts_url: None,
+    }
+}
+
+/// Load all rows stored for a snapshot timestamp
+pub async fn load_snapshot(pool: &SqlitePool, timestamp: i64) -> Result<Vec<SnapshotRow>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT ticker, name,
+            CAST(market_cap_original AS REAL) as market_cap_original,
+            CAST(market_cap_usd AS REAL) as market_cap_usd,
+            original_currency,
+            CAST(price AS REAL) as price
+        FROM market_caps
+        WHERE timestamp = ?
+        ORDER BY ticker
+        "#,
+    )
+    .bind(timestamp)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .iter()
+        .map(|row| SnapshotRow {
+            ticker: row.get("ticker"),
+            name: row.get("name"),
+            market_cap_original: row.get("market_cap_original"),
+            market_cap_usd: row.get("market_cap_usd"),
+            original_currency: row.get("original_currency"),
+            price: row.get("price"),
+        })
+        .collect())
+}
+
+/// Timestamp of the most recent snapshot strictly before `timestamp`
+pub async fn previous_snapshot_timestamp(pool: &SqlitePool, timestamp: i64) -> Result<Option<i64>> {
+    let previous: Option<i64> =
+        sqlx::query_scalar("SELECT MAX(timestamp) FROM market_caps WHERE timestamp < ?")
+            .bind(timestamp)
+            .fetch_one(pool)
+            .await?;
+    Ok(previous)
+}
+
+/// Write the markdown report and return its path
+fn export_report(
+    anomalies: &[Anomaly],
+    label: &str,
+    previous_label: Option<&str>,
+    rows_checked: usize,
+) -> Result<String> {
+    let output = config::load_output_config();
+    output.ensure_directory()?;
+    let filename = output
+        .file_path("data_quality", label, "md")
+        .display()
+        .to_string();
+
+    let mut file = File::create(&filename)?;
+    writeln!(file, "# Data Quality Report: {}", label)?;
+    writeln!(file)?;
+    writeln!(file, "- Companies checked: {}", rows_checked)?;
+    writeln!(
+        file,
+        "- Compared against snapshot: {}",
+        previous_label.unwrap_or("none (first snapshot)")
+    )?;
+    writeln!(file, "- Issues found: {}", anomalies.len())?;
+    writeln!(file)?;
+
+    if anomalies.is_empty() {
+        writeln!(file, "No issues found.")?;
+    } else {
+        writeln!(file, "| Ticker | Name | Issue |")?;
+        writeln!(file, "|--------|------|-------|")?;
+        for anomaly in anomalies {
+            writeln!(
+                file,
+                "| {} | {} | {} |",
+                anomaly.ticker, anomaly.name, anomaly.kind
+            )?;
+        }
+    }
+    writeln!(file)?;
+    writeln!(file, "---")?;
+    writeln!(
+        file,
+        "*Generated on {}*",
+        clock::now().format("%Y-%m-%d %H:%M:%S")
+    )?;
+
+    Ok(filename)
+}
+
+fn timestamp_label(timestamp: i64) -> String {
+    DateTime::from_timestamp(timestamp, 0)
+        .map(|dt| dt.format("%Y-%m-%d").to_string())
+        .unwrap_or_else(|| timestamp.to_string())
+}
+
+/// Validate the snapshot stored at `timestamp` against the previous one,
+/// write the report, and fail if requested and issues were found
+pub async fn check_snapshot(
+    pool: &SqlitePool,
+    timestamp: i64,
+    fail_on_anomalies: bool,
+) -> Result<Vec<Anomaly>> {
+    let label = timestamp_label(timestamp);
+    let current = load_snapshot(pool, timestamp).await?;
+    if current.is_empty() {
+        println!(
+            "No market cap data stored for {}; skipping data quality checks",
+            label
+        );
+        return Ok(Vec::new());
+    }
+
+    let previous_timestamp = previous_snapshot_timestamp(pool, timestamp).await?;
+    let previous = match previous_timestamp {
+        Some(ts) => load_snapshot(pool, ts).await?,
+        None => Vec::new(),
+    };
+    let previous_label = previous_timestamp.map(timestamp_label);
+
+    let anomalies = detect_anomalies(&previous, &current, DEFAULT_MAX_CHANGE_PCT);
+    let filename = export_report(&anomalies, &label, previous_label.as_deref(), current.len())?;
+
+    if anomalies.is_empty() {
+        println!("✅ Data quality checks passed for {}", label);
+    } else {
+        println!(
+            "⚠️  {} data quality issue(s) found for {}:",
+            anomalies.len(),
+            label
+        );
+        for anomaly in &anomalies {
+            println!("  {} - {}", anomaly.ticker, anomaly.kind);
+            run_context::record_warning(format!(
+                "Data quality: {} - {}",
+                anomaly.ticker, anomaly.kind
+            ));
+        }
+
+        let lines: Vec<String> = anomalies
+            .iter()
+            .map(|a| format!("{} ({}): {}", a.name, a.ticker, a.kind))
+            .collect();
+        if let Err(e) =
+            notify::webhook::notify_message(&format!("Data quality issues: {}", label), &lines)
+                .await
+        {
+            eprintln!("⚠️  Webhook notification failed: {:#}", e);
+        }
+    }
+    println!("✅ Data quality report exported to {}", filename);
+
+    // Ping Slack/Teams when configured and the moves are large enough
+    if let Some(previous_label) = &previous_label {
+        let summary = run_summary(&previous, &current, previous_label, &label);
+        if let Err(e) = notify::notify_run(summary).await {
+            eprintln!("⚠️  Webhook notification failed: {:#}", e);
+        }
+    }
+
+    ensure_no_anomalies(&anomalies, &label, fail_on_anomalies)?;
+    Ok(anomalies)
+}
+
+/// Turn found issues into an error (and a non-zero exit) when requested
+fn ensure_no_anomalies(anomalies: &[Anomaly], label: &str, fail_on_anomalies: bool) -> Result<()> {
+    if fail_on_anomalies && !anomalies.is_empty() {
+        anyhow::bail!(
+            "{} data quality issue(s) found for {}",
+            anomalies.len(),
+            label
+        );
+    }
+    Ok(())
+}
+
+/// Validate the snapshot for a YYYY-MM-DD date
+pub async fn check_date(pool: &SqlitePool, date_str: &str, fail_on_anomalies: bool) -> Result<()> {
+    let date = NaiveDate::parse_from_str(date_str, "%Y-%m-%d")
+        .map_err(|e| anyhow::anyhow!("Invalid date format. Use YYYY-MM-DD: {}", e))?;
+    let timestamp = NaiveDateTime::new(date, NaiveTime::default())
+        .and_utc()
+        .timestamp();
+    check_snapshot(pool, timestamp, fail_on_anomalies).await?;
+    Ok(())
+}
+
+/// Validate the most recently stored snapshot
+pub async fn check_latest(pool: &SqlitePool, fail_on_anomalies: bool) -> Result<()> {
+    let latest: Option<i64> = sqlx::query_scalar("SELECT MAX(timestamp) FROM market_caps")
+        .fetch_one(pool)
+        .await?;
+    if let Some(timestamp) = latest {
+        check_snapshot(pool, timestamp, fail_on_anomalies).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    fn row(ticker: &str, cap: Option<f64>, currency: &str, price: Option<f64>) -> SnapshotRow {
+        SnapshotRow {
+            ticker: ticker.to_string(),
+            name: format!("{} Inc", ticker),
+            market_cap_original: cap,
+            market_cap_usd: cap,
+            original_currency: Some(currency.to_string()),
+            price,
+        }
+    }
+
+    #[test]
+    fn test_no_anomalies_for_normal_moves() {
+        let previous = vec![row("NKE", Some(100.0), "USD", Some(80.0))];
+        let current = vec![row("NKE", Some(120.0), "USD", Some(96.0))];
+        assert!(detect_anomalies(&previous, &current, 50.0).is_empty());
+    }
+
+    #[test]
+    fn test_run_summary_between_snapshots() {
+        let previous = vec![
+            row("NKE", Some(100.0), "USD", None),
+            row("ADS.DE", Some(100.0), "EUR", None),
+            row("GONE", Some(50.0), "USD", None),
+        ];
+        let current = vec![
+            row("NKE", Some(110.0), "USD", None),
+            row("ADS.DE", Some(70.0), "EUR", None),
+            row("NEW", Some(500.0), "USD", None),
+        ];
+        let summary = run_summary(&previous, &current, "2025-01-01", "2025-01-02");
+
+        // Only companies in both snapshots count towards the total
+        assert!((summary.total_change_pct.unwrap() + 10.0).abs() < 1e-9);
+        assert_eq!(summary.top_gainer.unwrap().ticker, "NKE");
+        assert_eq!(summary.top_loser.unwrap().ticker, "ADS.DE");
+        assert_eq!(summary.from_date, "2025-01-01");
+    }
+
+    #[test]
+    fn test_large_change_flagged() {
+        let previous = vec![row("NKE", Some(100.0), "USD", Some(80.0))];
+        let current = vec![row("NKE", Some(40.0), "USD", Some(32.0))];
+        let anomalies = detect_anomalies(&previous, &current, 50.0);
+
+        assert_eq!(anomalies.len(), 1);
+        match &anomalies[0].kind {
+            AnomalyKind::LargeChange { pct, .. } => assert!((pct + 60.0).abs() < 1e-9),
+            other => panic!("unexpected anomaly {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_currency_change_flagged_without_pct_check() {
+        let previous = vec![row("9983.T", Some(10_000.0), "JPY", Some(1.0))];
+        let current = vec![row("9983.T", Some(70.0), "USD", Some(1.0))];
+        let anomalies = detect_anomalies(&previous, &current, 50.0);
+
+        assert_eq!(
+            anomalies,
+            vec![Anomaly {
+                ticker: "9983.T".to_string(),
+                name: "9983.T Inc".to_string(),
+                kind: AnomalyKind::CurrencyChanged {
+                    previous: "JPY".to_string(),
+                    current: "USD".to_string(),
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn test_zero_price_flagged_even_without_history() {
+        let current = vec![row("NKE", Some(100.0), "USD", Some(0.0))];
+        let anomalies = detect_anomalies(&[], &current, 50.0);
+        assert_eq!(anomalies.len(), 1);
+        assert_eq!(anomalies[0].kind, AnomalyKind::ZeroPrice);
+    }
+
+    #[test]
+    fn test_missing_market_cap_flagged() {
+        let previous = vec![
+            row("NKE", Some(100.0), "USD", Some(1.0)),
+            row("TJX", Some(100.0), "USD", Some(1.0)),
+        ];
+        let current = vec![row("NKE", Some(0.0), "USD", Some(1.0))];
+        let anomalies = detect_anomalies(&previous, &current, 50.0);
+
+        assert_eq!(anomalies.len(), 2);
+        assert!(
+            anomalies
+                .iter()
+                .all(|a| a.kind == AnomalyKind::MissingMarketCap)
+        );
+        assert_eq!(anomalies[0].ticker, "NKE");
+        assert_eq!(anomalies[1].ticker, "TJX");
+    }
+
+    #[test]
+    fn test_fail_on_anomalies_errors_only_when_requested() {
+        let anomalies = detect_anomalies(&[], &[row("NKE", Some(100.0), "USD", Some(0.0))], 50.0);
+
+        assert!(ensure_no_anomalies(&anomalies, "2025-01-02", false).is_ok());
+        let err = ensure_no_anomalies(&anomalies, "2025-01-02", true).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "1 data quality issue(s) found for 2025-01-02"
+        );
+        assert!(ensure_no_anomalies(&[], "2025-01-02", true).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_snapshots_from_database() {
+        let pool = db::create_db_pool("sqlite::memory:").await.unwrap();
+        for (ticker, cap, ts) in [("NKE", 100.0, 1_000), ("NKE", 300.0, 2_000)] {
+            sqlx::query(
+                "INSERT INTO market_caps (ticker, name, market_cap_original, original_currency, price, timestamp)
+                 VALUES (?, 'Nike', ?, 'USD', 10.0, ?)",
+            )
+            .bind(ticker)
+            .bind(cap)
+            .bind(ts)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        assert_eq!(
+            previous_snapshot_timestamp(&pool, 2_000).await.unwrap(),
+            Some(1_000)
+        );
+        assert_eq!(
+            previous_snapshot_timestamp(&pool, 1_000).await.unwrap(),
+            None
+        );
+
+        let previous = load_snapshot(&pool, 1_000).await.unwrap();
+        let current = load_snapshot(&pool, 2_000).await.unwrap();
+        assert_eq!(current[0].market_cap_original, Some(300.0));
+
+        let anomalies = detect_anomalies(&previous, &current, DEFAULT_MAX_CHANGE_PCT);
+        assert_eq!(anomalies.len(), 1);
+    }
+}
@@ -5,7 +5,7 @@
 // use crate::api::ExchangeRate;
 // use crate::currencies;
 use anyhow::Result;
-use sqlx::{Sqlite, migrate::MigrateDatabase, sqlite::SqlitePool};
+use sqlx::{Postgres, Sqlite, migrate::MigrateDatabase, postgres::PgPool, sqlite::SqlitePool};
 
 pub async fn create_db_pool(db_url: &str) -> Result<SqlitePool> {
     // Create database if it doesn't exist
@@ -22,6 +22,84 @@ pub async fn create_db_pool(db_url: &str) -> Result<SqlitePool> {
     Ok(pool)
 }
 
+/// Database engine behind a `DATABASE_URL`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Sqlite,
+    Postgres,
+}
+
+impl Backend {
+    pub fn from_url(db_url: &str) -> Self {
+        if db_url.starts_with("postgres://") || db_url.starts_with("postgresql://") {
+            Backend::Postgres
+        } else {
+            Backend::Sqlite
+        }
+    }
+}
+
+/// Pool holding the core tables: currencies, forex_rates,
+/// forex_rate_holidays, market_caps, ticker_details and symbol_changes. Queries on these tables go through
+/// [`core_query!`] and must run unchanged on both engines (`$1` placeholders,
+/// `ON CONFLICT` upserts, `CAST(x AS DOUBLE PRECISION)`).
+#[derive(Debug, Clone)]
+pub enum CorePool {
+    Sqlite(SqlitePool),
+    Postgres(PgPool),
+}
+
+impl CorePool {
+    /// The SQLite pool, when the core tables live in SQLite
+    pub fn as_sqlite(&self) -> Option<&SqlitePool> {
+        match self {
+            CorePool::Sqlite(pool) => Some(pool),
+            CorePool::Postgres(_) => None,
+        }
+    }
+}
+
+impl From<&SqlitePool> for CorePool {
+    fn from(pool: &SqlitePool) -> Self {
+        CorePool::Sqlite(pool.clone())
+    }
+}
+
+impl From<&CorePool> for CorePool {
+    fn from(pool: &CorePool) -> Self {
+        pool.clone()
+    }
+}
+
+/// Run the same query on whichever engine holds the core tables, e.g.
+/// `core_query!(pool, |p| sqlx::query(SQL).bind(x).execute(p).await?)`
+macro_rules! core_query {
+    ($pool:expr, |$p:ident| $body:expr) => {
+        match $pool {
+            $crate::db::CorePool::Sqlite($p) => $body,
+            $crate::db::CorePool::Postgres($p) => $body,
+        }
+    };
+}
+pub(crate) use core_query;
+
+/// Connect to the core tables of `DATABASE_URL`, creating the database and
+/// running the migrations for its engine: `migrations/` for SQLite,
+/// `migrations_postgres/` for PostgreSQL
+pub async fn create_core_pool(db_url: &str) -> Result<CorePool> {
+    match Backend::from_url(db_url) {
+        Backend::Sqlite => Ok(CorePool::Sqlite(create_db_pool(db_url).await?)),
+        Backend::Postgres => {
+            if !Postgres::database_exists(db_url).await.unwrap_or(false) {
+                Postgres::create_database(db_url).await?;
+            }
+            let pool = PgPool::connect(db_url).await?;
+            sqlx::migrate!("./migrations_postgres").run(&pool).await?;
+            Ok(CorePool::Postgres(pool))
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,269 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! `stats`: what data the database actually holds
+//!
+//! Summarises the stored market cap snapshots and their date range, the forex
+//! pairs with the share of business days between their first and last rate
+//! that have one, pending and applied symbol changes, the size of the
+//! database and the row count of every table.
+
+use anyhow::Result;
+use chrono::{DateTime, NaiveDate};
+use sqlx::Row;
+use sqlx::sqlite::SqlitePool;
+
+use crate::api_usage::format_bytes;
+use crate::backup;
+use crate::exchange_rates;
+
+/// Rates stored for one forex pair
+#[derive(Debug, Clone, PartialEq)]
+pub struct PairStats {
+    pub symbol: String,
+    pub rates: i64,
+    pub first: NaiveDate,
+    pub last: NaiveDate,
+    /// Business days from `first` to `last` with at least one rate
+    pub days_with_rate: usize,
+    /// Business days from `first` to `last`
+    pub business_days: usize,
+}
+
+impl PairStats {
+    /// Share of business days with a rate, in percent
+    pub fn coverage(&self) -> f64 {
+        if self.business_days == 0 {
+            return 100.0;
+        }
+        self.days_with_rate as f64 * 100.0 / self.business_days as f64
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DbStats {
+    /// Distinct `market_caps` timestamps
+    pub snapshots: i64,
+    /// Dates of the oldest and newest snapshot
+    pub snapshot_range: Option<(NaiveDate, NaiveDate)>,
+    pub forex_pairs: Vec<PairStats>,
+    pub symbol_changes_pending: i64,
+    pub symbol_changes_applied: i64,
+    /// Pages in use times the page size, without the WAL file
+    pub size_bytes: i64,
+    /// Row count per table, by table name
+    pub tables: Vec<(String, i64)>,
+}
+
+fn timestamp_date(timestamp: i64) -> Option<NaiveDate> {
+    DateTime::from_timestamp(timestamp, 0).map(|datetime| datetime.date_naive())
+}
+
+async fn forex_pair_stats(pool: &SqlitePool) -> Result<Vec<PairStats>> {
+    // strftime('%w') is 0 on Sunday and 6 on Saturday
+    let rows = sqlx::query(
+        r#"
+        SELECT symbol, COUNT(*) AS rates, MIN(timestamp) AS first, MAX(timestamp) AS last,
+            COUNT(DISTINCT CASE WHEN strftime('%w', timestamp, 'unixepoch') NOT IN ('0', '6')
+                THEN date(timestamp, 'unixepoch') END) AS days_with_rate
+        FROM forex_rates
+        GROUP BY symbol
+        ORDER BY symbol
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut pairs = Vec::with_capacity(rows.len());
+    for row in rows {
+        let (Some(first), Some(last)) = (
+            timestamp_date(row.get("first")),
+            timestamp_date(row.get("last")),
+        ) else {
+            continue;
+        };
+        pairs.push(PairStats {
+            symbol: row.get("symbol"),
+            rates: row.get("rates"),
+            first,
+            last,
+            days_with_rate: row.get::<i64, _>("days_with_rate") as usize,
+            business_days: exchange_rates::business_days(first, last).len(),
+        });
+    }
+    Ok(pairs)
+}
+
+/// Row counts of the data tables, in name order
+async fn table_row_counts(pool: &SqlitePool) -> Result<Vec<(String, i64)>> {
+    let mut conn = pool.acquire().await?;
+    let mut counts = Vec::new();
+    for table in backup::list_tables(&mut conn, "main").await? {
+        let count: i64 = sqlx::query_scalar(&format!(
+            "SELECT COUNT(*) FROM {}",
+            backup::quote_ident(&table)
+        ))
+        .fetch_one(&mut *conn)
+        .await?;
+        counts.push((table, count));
+    }
+    Ok(counts)
+}
+
+/// Collect the statistics shown by `stats`
+pub async fn collect(pool: &SqlitePool) -> Result<DbStats> {
+    let (snapshots, first, last): (i64, Option<i64>, Option<i64>) = sqlx::query_as(
+        "SELECT COUNT(DISTINCT timestamp), MIN(timestamp), MAX(timestamp) FROM market_caps",
+    )
+    .fetch_one(pool)
+    .await?;
+    let snapshot_range = match (
+        first.and_then(timestamp_date),
+        last.and_then(timestamp_date),
+    ) {
+        (Some(first), Some(last)) => Some((first, last)),
+        _ => None,
+    };
+
+    let (pending, applied): (Option<i64>, Option<i64>) = sqlx::query_as(
+        "SELECT SUM(COALESCE(applied, 0) = 0), SUM(COALESCE(applied, 0) <> 0) FROM symbol_changes",
+    )
+    .fetch_one(pool)
+    .await?;
+
+    let size_bytes: i64 = sqlx::query_scalar(
+        "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(DbStats {
+        snapshots,
+        snapshot_range,
+        forex_pairs: forex_pair_stats(pool).await?,
+        symbol_changes_pending: pending.unwrap_or(0),
+        symbol_changes_applied: applied.unwrap_or(0),
+        size_bytes,
+        tables: table_row_counts(pool).await?,
+    })
+}
+
+fn print_stats(stats: &DbStats, db_url: &str) {
+    println!("📊 Database {}\n", db_url);
+    println!("Size: {}", format_bytes(stats.size_bytes));
+
+    match stats.snapshot_range {
+        Some((first, last)) => println!(
+            "Market cap snapshots: {} ({} to {})",
+            stats.snapshots, first, last
+        ),
+        None => println!("Market cap snapshots: none"),
+    }
+    println!(
+        "Symbol changes: {} pending, {} applied",
+        stats.symbol_changes_pending, stats.symbol_changes_applied
+    );
+
+    if stats.forex_pairs.is_empty() {
+        println!("\nForex pairs: none");
+    } else {
+        println!("\nForex pairs: {}", stats.forex_pairs.len());
+        println!(
+            "  {:<10} {:>8} {:<10} {:<10} {:>9}",
+            "Pair", "Rates", "First", "Last", "Coverage"
+        );
+        for pair in &stats.forex_pairs {
+            println!(
+                "  {:<10} {:>8} {:<10} {:<10} {:>8.1}%",
+                pair.symbol,
+                pair.rates,
+                pair.first,
+                pair.last,
+                pair.coverage()
+            );
+        }
+    }
+
+    println!("\nRows per table:");
+    for (table, count) in &stats.tables {
+        println!("  {:<30} {:>10}", table, count);
+    }
+}
+
+/// Print the database summary for the `stats` command
+pub async fn show_stats(pool: &SqlitePool, db_url: &str) -> Result<()> {
+    let stats = collect(pool).await?;
+    print_stats(&stats, db_url);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_collect_empty_database() {
+        let pool = db::create_db_pool("sqlite::memory:").await.unwrap();
+        let stats = collect(&pool).await.unwrap();
+        assert_eq!(stats.snapshots, 0);
+        assert_eq!(stats.snapshot_range, None);
+        assert!(stats.forex_pairs.is_empty());
+        assert_eq!(stats.symbol_changes_pending, 0);
+        assert!(stats.size_bytes > 0);
+        assert!(
+            stats
+                .tables
+                .iter()
+                .any(|(table, count)| table == "market_caps" && *count == 0)
+        );
+        assert!(
+            !stats
+                .tables
+                .iter()
+                .any(|(table, _)| table == "_sqlx_migrations")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_collect_counts_snapshots_rates_and_symbol_changes() {
+        let pool = db::create_db_pool("sqlite::memory:").await.unwrap();
+        for sql in [
+            // 2025-01-03 (Friday) and 2025-01-06 (Monday), two tickers each
+            "INSERT INTO market_caps (ticker, name, timestamp) VALUES ('NKE', 'Nike', 1735905600), ('ITX.MC', 'Inditex', 1735905600), ('NKE', 'Nike', 1736164800), ('ITX.MC', 'Inditex', 1736164800)",
+            // Friday, Saturday and Tuesday: Monday is missing, the weekend doesn't count
+            "INSERT INTO forex_rates (symbol, ask, bid, timestamp) VALUES ('EURUSD', 1.03, 1.03, 1735905600), ('EURUSD', 1.03, 1.03, 1735992000), ('EURUSD', 1.04, 1.04, 1736251200)",
+            "INSERT INTO symbol_changes (old_symbol, new_symbol, change_date, applied) VALUES ('FB', 'META', '2022-06-09', 1), ('TWTR', 'X', '2023-07-24', 0), ('SQ', 'XYZ', '2025-01-21', 0)",
+        ] {
+            sqlx::query(sql).execute(&pool).await.unwrap();
+        }
+
+        let stats = collect(&pool).await.unwrap();
+        assert_eq!(stats.snapshots, 2);
+        assert_eq!(
+            stats.snapshot_range,
+            Some((date("2025-01-03"), date("2025-01-06")))
+        );
+        assert_eq!(
+            stats.forex_pairs,
+            vec![PairStats {
+                symbol: "EURUSD".to_string(),
+                rates: 3,
+                first: date("2025-01-03"),
+                last: date("2025-01-07"),
+                days_with_rate: 2,
+                business_days: 3,
+            }]
+        );
+        assert!((stats.forex_pairs[0].coverage() - 200.0 / 3.0).abs() < 1e-9);
+        assert_eq!(stats.symbol_changes_pending, 2);
+        assert_eq!(stats.symbol_changes_applied, 1);
+        assert!(stats.tables.contains(&("market_caps".to_string(), 4)));
+        assert!(stats.tables.contains(&("forex_rates".to_string(), 3)));
+    }
+}
@@ -5,10 +5,8 @@ use crate::api;
 use crate::config;
 use crate::currencies::get_rate_map_from_db;
 use anyhow::Result;
-use chrono::Local;
 use csv::Writer;
 use sqlx::sqlite::SqlitePool;
-use std::path::PathBuf;
 use tokio;
 
 pub async fn export_details_eu_csv(pool: &SqlitePool) -> Result<()> {
@@ -16,12 +14,10 @@ pub async fn export_details_eu_csv(pool: &SqlitePool) -> Result<()> {
     let tickers = config.non_us_tickers;
 
     // Create output directory if it doesn't exist
-    let output_dir = PathBuf::from("output");
-    std::fs::create_dir_all(&output_dir)?;
+    config.output.ensure_directory()?;
 
     // Create CSV file with timestamp
-    let timestamp = Local::now().format("%Y%m%d_%H%M%S");
-    let csv_path = output_dir.join(format!("eu_marketcaps_{}.csv", timestamp));
+    let csv_path = config.output.file_path("eu_marketcaps", "", "csv");
     let mut writer = Writer::from_path(&csv_path)?;
 
     // Write header
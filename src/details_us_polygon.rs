@@ -4,10 +4,10 @@
 use crate::api::PolygonClient;
 use crate::config;
 use anyhow::Result;
-use chrono::{Local, NaiveDate};
+use chrono::NaiveDate;
 use csv::Writer;
 use sqlx::sqlite::SqlitePool;
-use std::{env, path::PathBuf, sync::Arc};
+use std::{env, sync::Arc};
 
 pub async fn export_details_us_csv(_pool: &SqlitePool) -> Result<()> {
     let config = config::load_config()?;
@@ -17,12 +17,10 @@ pub async fn export_details_us_csv(_pool: &SqlitePool) -> Result<()> {
     let date = NaiveDate::from_ymd_opt(2023, 11, 1).unwrap();
 
     // Create output directory if it doesn't exist
-    let output_dir = PathBuf::from("output");
-    std::fs::create_dir_all(&output_dir)?;
+    config.output.ensure_directory()?;
 
     // Create CSV file with timestamp
-    let timestamp = Local::now().format("%Y%m%d_%H%M%S");
-    let csv_path = output_dir.join(format!("us_marketcaps_{}.csv", timestamp));
+    let csv_path = config.output.file_path("us_marketcaps", "", "csv");
     let mut writer = Writer::from_path(&csv_path)?;
 
     // Write header
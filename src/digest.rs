@@ -0,0 +1,488 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Newsletter digest of a comparison (`digest --from --to`)
+//!
+//! The comparison report lists every company; the digest is the short
+//! version for the newsletter: the change of the industry total, the five
+//! largest movers, notable rank changes and a reference to the waterfall
+//! chart. It is written as Markdown and as HTML with inline styles only, so
+//! it survives being pasted into an email editor that strips `<style>` tags.
+
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use sqlx::sqlite::SqlitePool;
+use std::collections::HashMap;
+
+use crate::advanced_comparisons;
+use crate::compare_marketcaps::{
+    self, ChangeAttribution, MarketCapComparison, RowAnnotations, WATERFALL_CONTRIBUTORS,
+};
+use crate::config;
+use crate::corporate_actions::CorporateActionIndex;
+use crate::earnings::EarningsIndex;
+use crate::locale;
+use crate::rankings;
+use crate::ticker_aliases::{AppliedAliases, TickerAliases};
+use crate::utils::escape_markdown;
+use crate::vega;
+use crate::visualizations;
+
+/// Companies listed as top movers
+pub const DIGEST_MOVERS: usize = 5;
+/// Places a company must gain or lose to be a notable rank change
+pub const NOTABLE_RANK_CHANGE: i32 = 3;
+/// Notable rank changes listed at most
+const MAX_RANK_CHANGES: usize = 5;
+
+const GAIN_COLOR: &str = "#15803d";
+const LOSS_COLOR: &str = "#b91c1c";
+
+/// Layout of the digest
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DigestTemplate {
+    /// Short summary for the weekly newsletter
+    Newsletter,
+}
+
+impl DigestTemplate {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.to_lowercase().as_str() {
+            "newsletter" => Ok(Self::Newsletter),
+            other => anyhow::bail!("Unknown digest template: {} (expected newsletter)", other),
+        }
+    }
+}
+
+/// A company in the digest, with its change in its own currency
+#[derive(Debug, Clone, PartialEq)]
+pub struct DigestMover {
+    pub ticker: String,
+    pub name: String,
+    pub percentage_change: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DigestRankChange {
+    pub ticker: String,
+    pub name: String,
+    pub rank_from: usize,
+    pub rank_to: usize,
+    /// Places gained (positive) or lost
+    pub change: i32,
+}
+
+/// Everything the digest shows
+#[derive(Debug, Clone, PartialEq)]
+pub struct Digest {
+    pub from_date: String,
+    pub to_date: String,
+    /// Total USD market cap on both dates
+    pub total_from: f64,
+    pub total_to: f64,
+    pub movers: Vec<DigestMover>,
+    pub rank_changes: Vec<DigestRankChange>,
+    /// File name of the waterfall chart, relative to the digest
+    pub chart: Option<String>,
+}
+
+impl Digest {
+    /// Select the movers and rank changes of a comparison
+    pub fn build(
+        from_date: &str,
+        to_date: &str,
+        comparisons: &[MarketCapComparison],
+        attribution: &ChangeAttribution,
+        chart: Option<String>,
+    ) -> Self {
+        let mut movers: Vec<DigestMover> = comparisons
+            .iter()
+            .filter_map(|c| {
+                Some(DigestMover {
+                    ticker: c.ticker.clone(),
+                    name: c.name.clone(),
+                    percentage_change: c.percentage_change.filter(|p| p.is_finite())?,
+                })
+            })
+            .collect();
+        movers.sort_by(|a, b| {
+            rankings::rank_order(
+                (Some(a.percentage_change.abs()), &a.name, &a.ticker),
+                (Some(b.percentage_change.abs()), &b.name, &b.ticker),
+            )
+        });
+        movers.truncate(DIGEST_MOVERS);
+
+        let mut rank_changes: Vec<DigestRankChange> = comparisons
+            .iter()
+            .filter_map(|c| {
+                let change = c.rank_change?;
+                (change.abs() >= NOTABLE_RANK_CHANGE).then(|| DigestRankChange {
+                    ticker: c.ticker.clone(),
+                    name: c.name.clone(),
+                    rank_from: c.rank_from.unwrap_or_default(),
+                    rank_to: c.rank_to.unwrap_or_default(),
+                    change,
+                })
+            })
+            .collect();
+        rank_changes.sort_by(|a, b| {
+            b.change
+                .abs()
+                .cmp(&a.change.abs())
+                .then(a.rank_to.cmp(&b.rank_to))
+                .then_with(|| a.ticker.cmp(&b.ticker))
+        });
+        rank_changes.truncate(MAX_RANK_CHANGES);
+
+        Self {
+            from_date: from_date.to_string(),
+            to_date: to_date.to_string(),
+            total_from: attribution.total_from,
+            total_to: attribution.total_to,
+            movers,
+            rank_changes,
+            chart,
+        }
+    }
+
+    /// Change of the total USD market cap in percent
+    pub fn total_change_pct(&self) -> Option<f64> {
+        (self.total_from > 0.0).then(|| (self.total_to - self.total_from) / self.total_from * 100.0)
+    }
+
+    pub fn title(&self) -> String {
+        format!(
+            "Fashion market caps: {} to {}",
+            self.from_date, self.to_date
+        )
+    }
+
+    /// Sentence on the industry total
+    fn total_sentence(&self) -> String {
+        let t = locale::current();
+        let total = format!("USD {} bn", t.number(self.total_to / 1_000_000_000.0, 1));
+        match self.total_change_pct() {
+            Some(pct) => format!(
+                "The combined market cap {} {} to {}.",
+                if pct < 0.0 { "fell" } else { "rose" },
+                t.percent(pct.abs(), false),
+                total
+            ),
+            None => format!("The combined market cap is {}.", total),
+        }
+    }
+
+    /// Plain-text lines for chat messages: the total and the top movers
+    pub fn lines(&self) -> Vec<String> {
+        let t = locale::current();
+        let mut lines = vec![self.total_sentence()];
+        lines.extend(self.movers.iter().map(|mover| {
+            format!(
+                "{} ({}) {}",
+                mover.name,
+                mover.ticker,
+                t.percent(mover.percentage_change, true)
+            )
+        }));
+        lines
+    }
+
+    pub fn markdown(&self) -> String {
+        let t = locale::current();
+        let mut md = format!("## {}\n\n{}\n\n", self.title(), self.total_sentence());
+
+        md.push_str("### Top movers\n\n");
+        for mover in &self.movers {
+            md.push_str(&format!(
+                "- **{}** ({}) {}\n",
+                escape_markdown(&mover.name),
+                escape_markdown(&mover.ticker),
+                t.percent(mover.percentage_change, true)
+            ));
+        }
+        if self.movers.is_empty() {
+            md.push_str("- No price changes in this period\n");
+        }
+
+        if !self.rank_changes.is_empty() {
+            md.push_str("\n### Notable rank changes\n\n");
+            for change in &self.rank_changes {
+                md.push_str(&format!(
+                    "- **{}** ({}) #{} → #{}\n",
+                    escape_markdown(&change.name),
+                    escape_markdown(&change.ticker),
+                    change.rank_from,
+                    change.rank_to
+                ));
+            }
+        }
+
+        if let Some(chart) = &self.chart {
+            md.push_str(&format!(
+                "\n![What moved the total]({})\n",
+                chart.replace(' ', "%20")
+            ));
+        }
+        md
+    }
+
+    /// HTML fragment with inline styles, for pasting into an email editor
+    pub fn html(&self) -> String {
+        let t = locale::current();
+        let cell = "padding: 6px 8px; border-bottom: 1px solid #e5e7eb;";
+        let heading = "margin: 20px 0 8px; font-size: 16px; color: #111827;";
+        let mut html = String::from(
+            "<div style=\"font-family: Arial, Helvetica, sans-serif; font-size: 14px; \
+             line-height: 1.5; color: #111827; max-width: 600px;\">\n",
+        );
+        html.push_str(&format!(
+            "<h2 style=\"margin: 0 0 8px; font-size: 20px;\">{}</h2>\n",
+            escape_html(&self.title())
+        ));
+        html.push_str(&format!(
+            "<p style=\"margin: 0 0 16px;\">{}</p>\n",
+            escape_html(&self.total_sentence())
+        ));
+
+        html.push_str(&format!("<h3 style=\"{}\">Top movers</h3>\n", heading));
+        if self.movers.is_empty() {
+            html.push_str("<p style=\"margin: 0;\">No price changes in this period</p>\n");
+        } else {
+            html.push_str(
+                "<table style=\"border-collapse: collapse; width: 100%;\" role=\"presentation\">\n",
+            );
+            for mover in &self.movers {
+                let color = if mover.percentage_change < 0.0 {
+                    LOSS_COLOR
+                } else {
+                    GAIN_COLOR
+                };
+                html.push_str(&format!(
+                    "<tr><td style=\"{cell}\"><strong>{}</strong> \
+                     <span style=\"color: #6b7280;\">{}</span></td>\
+                     <td style=\"{cell} text-align: right; color: {color}; font-weight: bold;\">{}</td></tr>\n",
+                    escape_html(&mover.name),
+                    escape_html(&mover.ticker),
+                    escape_html(&t.percent(mover.percentage_change, true)),
+                ));
+            }
+            html.push_str("</table>\n");
+        }
+
+        if !self.rank_changes.is_empty() {
+            html.push_str(&format!(
+                "<h3 style=\"{}\">Notable rank changes</h3>\n<ul style=\"margin: 0; padding-left: 20px;\">\n",
+                heading
+            ));
+            for change in &self.rank_changes {
+                let color = if change.change < 0 {
+                    LOSS_COLOR
+                } else {
+                    GAIN_COLOR
+                };
+                html.push_str(&format!(
+                    "<li><strong>{}</strong> ({}) \
+                     <span style=\"color: {color};\">#{} &rarr; #{}</span></li>\n",
+                    escape_html(&change.name),
+                    escape_html(&change.ticker),
+                    change.rank_from,
+                    change.rank_to,
+                ));
+            }
+            html.push_str("</ul>\n");
+        }
+
+        if let Some(chart) = &self.chart {
+            html.push_str(&format!(
+                "<p style=\"margin: 20px 0 0;\"><img src=\"{}\" alt=\"What moved the total\" \
+                 style=\"max-width: 100%; height: auto; border: 0;\"></p>\n",
+                escape_html(chart)
+            ));
+        }
+        html.push_str("</div>\n");
+        html
+    }
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Build the digest of the exported snapshots of two dates and write it as
+/// Markdown and HTML next to its waterfall chart
+pub async fn generate_digest(
+    pool: &SqlitePool,
+    from_date: &str,
+    to_date: &str,
+    template: DigestTemplate,
+) -> Result<Digest> {
+    let DigestTemplate::Newsletter = template;
+    let output = config::load_output_config();
+    output.ensure_directory()?;
+
+    let mut from_records = compare_marketcaps::read_market_cap_csv(
+        &advanced_comparisons::find_csv_for_date(from_date, None)?,
+    )?;
+    let mut to_records = compare_marketcaps::read_market_cap_csv(
+        &advanced_comparisons::find_csv_for_date(to_date, None)?,
+    )?;
+    rankings::truncate_to_top(&mut from_records);
+    rankings::truncate_to_top(&mut to_records);
+
+    let ticker_aliases = TickerAliases::load(pool).await?;
+    let mut aliases = AppliedAliases::default();
+    for (date, records) in [(from_date, &mut from_records), (to_date, &mut to_records)] {
+        let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .with_context(|| format!("Invalid date format: {}", date))?;
+        ticker_aliases.apply(date, records, |r| &mut r.ticker, &mut aliases);
+    }
+
+    let comparisons = compare_marketcaps::build_comparisons(
+        &from_records,
+        &to_records,
+        RowAnnotations {
+            corporate_actions: &CorporateActionIndex::default(),
+            aliases: &aliases,
+            earnings: &EarningsIndex::default(),
+        },
+        &[],
+        &HashMap::new(),
+        &HashMap::new(),
+    );
+    let attribution =
+        compare_marketcaps::attribute_change(&from_records, &to_records, WATERFALL_CONTRIBUTORS);
+
+    let chart_name = format!(
+        "digest_{}_to_{}_waterfall.{}",
+        from_date,
+        to_date,
+        vega::extension()
+    );
+    let chart_path = output.named_path(&chart_name);
+    visualizations::create_waterfall_chart(&attribution, from_date, to_date, &chart_path)?;
+
+    let digest = Digest::build(
+        from_date,
+        to_date,
+        &comparisons,
+        &attribution,
+        Some(chart_name),
+    );
+    let range = format!("{}_to_{}", from_date, to_date);
+    let timestamp = config::OutputConfig::timestamp();
+    for (ext, body) in [("md", digest.markdown()), ("html", digest.html())] {
+        let path = output.file_path_at("digest", &range, &timestamp, ext);
+        std::fs::write(&path, body)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        println!("✅ Digest written to {}", path.display());
+    }
+    Ok(digest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn comparison(ticker: &str, pct: Option<f64>, ranks: (usize, usize)) -> MarketCapComparison {
+        MarketCapComparison {
+            ticker: ticker.to_string(),
+            name: format!("{} & Co", ticker),
+            original_currency: Some("USD".to_string()),
+            market_cap_from: Some(100.0),
+            market_cap_to: pct.map(|p| 100.0 + p),
+            absolute_change: pct,
+            percentage_change: pct,
+            rank_from: Some(ranks.0),
+            rank_to: Some(ranks.1),
+            rank_change: Some(ranks.0 as i32 - ranks.1 as i32),
+            market_share_from: None,
+            market_share_to: None,
+            report_values: Vec::new(),
+            corporate_action: None,
+            formerly: None,
+            earnings: None,
+        }
+    }
+
+    fn digest() -> Digest {
+        let comparisons = vec![
+            comparison("A", Some(2.0), (1, 1)),
+            comparison("B", Some(-30.0), (2, 7)),
+            comparison("C", Some(12.0), (9, 3)),
+            comparison("D", Some(1.0), (4, 4)),
+            comparison("E", Some(-5.0), (5, 5)),
+            comparison("F", Some(8.0), (6, 6)),
+            comparison("G", None, (3, 2)),
+        ];
+        let attribution = ChangeAttribution {
+            total_from: 200_000_000_000.0,
+            total_to: 210_000_000_000.0,
+            top: Vec::new(),
+            other: 0.0,
+        };
+        Digest::build(
+            "2025-01-01",
+            "2025-02-01",
+            &comparisons,
+            &attribution,
+            Some("digest_waterfall.svg".to_string()),
+        )
+    }
+
+    #[test]
+    fn test_parse_template() {
+        assert_eq!(
+            DigestTemplate::parse("Newsletter").unwrap(),
+            DigestTemplate::Newsletter
+        );
+        assert!(DigestTemplate::parse("report").is_err());
+    }
+
+    #[test]
+    fn test_build_selects_movers_and_rank_changes() {
+        let digest = digest();
+        let movers: Vec<&str> = digest.movers.iter().map(|m| m.ticker.as_str()).collect();
+        assert_eq!(movers, vec!["B", "C", "F", "E", "A"]);
+        let ranks: Vec<(&str, i32)> = digest
+            .rank_changes
+            .iter()
+            .map(|r| (r.ticker.as_str(), r.change))
+            .collect();
+        assert_eq!(ranks, vec![("C", 6), ("B", -5)]);
+        assert_eq!(digest.total_change_pct(), Some(5.0));
+    }
+
+    #[test]
+    fn test_markdown_and_html() {
+        let digest = digest();
+        let md = digest.markdown();
+        assert!(md.contains("The combined market cap rose 5.00% to USD 210.0 bn."));
+        assert!(md.contains("- **B & Co** (B) -30.00%\n"));
+        assert!(md.contains("- **C & Co** (C) #9 → #3\n"));
+        assert!(md.contains("![What moved the total](digest_waterfall.svg)"));
+
+        let html = digest.html();
+        assert!(html.contains("<strong>B &amp; Co</strong>"));
+        assert!(html.contains("color: #b91c1c; font-weight: bold;\">-30.00%"));
+        assert!(html.contains("<img src=\"digest_waterfall.svg\""));
+        // Inline styles only, nothing an email editor would strip
+        assert!(!html.contains("<style"));
+        assert!(!html.contains("class="));
+    }
+
+    #[test]
+    fn test_markdown_escapes_names() {
+        let mut digest = digest();
+        digest.movers[0].name = "*Star* [Brands]".to_string();
+        digest.rank_changes[0].name = "A_B <Group>".to_string();
+        let md = digest.markdown();
+        assert!(md.contains("- **\\*Star\\* \\[Brands\\]** (B) -30.00%\n"));
+        assert!(md.contains("- **A\\_B \\<Group\\>** (C) #9 → #3\n"));
+    }
+}
@@ -0,0 +1,477 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Earnings reports of the universe (`earnings-calendar`), used to flag
+//! companies in comparisons that reported between the two dates
+//!
+//! The FMP calendar covers all listed companies, so one request per three
+//! months is enough; only the tickers of the config and of the watchlists are
+//! stored. Comparisons read the stored calendar and never call the API, so run
+//! `earnings-calendar` for a period before comparing it.
+
+use anyhow::Result;
+use chrono::{Days, NaiveDate};
+use csv::Writer;
+use sqlx::Row;
+use sqlx::sqlite::SqlitePool;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::Path;
+
+use crate::api::{EarningsCalendarEntry, FMPClient};
+use crate::clock;
+use crate::config::{self, OutputConfig};
+use crate::watchlists;
+
+/// Longest period the FMP earnings calendar returns in one request
+const MAX_DAYS_PER_REQUEST: u64 = 90;
+
+/// Default length of the listed period when `--to` is left out
+pub const DEFAULT_DAYS_AHEAD: u64 = 30;
+
+/// One company's earnings report
+#[derive(Debug, Clone, PartialEq)]
+pub struct EarningsReport {
+    pub ticker: String,
+    pub date: NaiveDate,
+    /// "bmo" (before market open) or "amc" (after market close)
+    pub time: Option<String>,
+    pub eps: Option<f64>,
+    pub eps_estimated: Option<f64>,
+    pub revenue: Option<f64>,
+    pub revenue_estimated: Option<f64>,
+    pub fiscal_date_ending: Option<String>,
+}
+
+impl From<EarningsCalendarEntry> for EarningsReport {
+    fn from(entry: EarningsCalendarEntry) -> Self {
+        Self {
+            ticker: entry.symbol,
+            date: entry.date,
+            time: entry.time.filter(|t| !t.trim().is_empty() && t != "--"),
+            eps: entry.eps,
+            eps_estimated: entry.eps_estimated,
+            revenue: entry.revenue,
+            revenue_estimated: entry.revenue_estimated,
+            fiscal_date_ending: entry.fiscal_date_ending,
+        }
+    }
+}
+
+impl EarningsReport {
+    fn timing(&self) -> Option<&'static str> {
+        match self.time.as_deref() {
+            Some("bmo") => Some("before open"),
+            Some("amc") => Some("after close"),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for EarningsReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.date)?;
+        if let Some(timing) = self.timing() {
+            write!(f, " {}", timing)?;
+        }
+        match (self.eps, self.eps_estimated) {
+            (Some(eps), Some(estimate)) => write!(f, ", EPS {:.2} vs {:.2} est.", eps, estimate),
+            (Some(eps), None) => write!(f, ", EPS {:.2}", eps),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Reports between two dates (after `from`, up to and including `to`), per ticker
+#[derive(Debug, Default, Clone)]
+pub struct EarningsIndex {
+    by_ticker: BTreeMap<String, Vec<EarningsReport>>,
+}
+
+impl EarningsIndex {
+    pub fn for_period(reports: &[EarningsReport], from: NaiveDate, to: NaiveDate) -> Self {
+        let mut index = Self::default();
+        for report in reports.iter().filter(|r| r.date > from && r.date <= to) {
+            index
+                .by_ticker
+                .entry(report.ticker.clone())
+                .or_default()
+                .push(report.clone());
+        }
+        for reports in index.by_ticker.values_mut() {
+            reports.sort_by_key(|r| r.date);
+        }
+        index
+    }
+
+    /// Load the stored reports between two YYYY-MM-DD dates
+    pub async fn load_for_period(pool: &SqlitePool, from: &str, to: &str) -> Result<Self> {
+        let from = NaiveDate::parse_from_str(from, "%Y-%m-%d")?;
+        let to = NaiveDate::parse_from_str(to, "%Y-%m-%d")?;
+        let reports = load_reports(pool, from.succ_opt().unwrap_or(from), to).await?;
+        Ok(Self::for_period(&reports, from, to))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_ticker.is_empty()
+    }
+
+    /// Annotation for a ticker's row, e.g. "2025-03-20 after close, EPS 0.54 vs 0.29 est."
+    pub fn annotation(&self, ticker: &str) -> Option<String> {
+        self.by_ticker.get(ticker).map(|reports| {
+            reports
+                .iter()
+                .map(|r| r.to_string())
+                .collect::<Vec<_>>()
+                .join("; ")
+        })
+    }
+
+    /// Markdown note on the companies that reported in the period
+    pub fn markdown_section(&self) -> String {
+        let mut section = String::from("## Earnings Reports\n\n");
+        section.push_str(
+            "These companies reported earnings between the two dates (marked with ‡); \
+             their reports often explain the largest moves.\n\n",
+        );
+        for (ticker, reports) in &self.by_ticker {
+            for report in reports {
+                section.push_str(&format!("- **{}**: {}\n", ticker, report));
+            }
+        }
+        section.push('\n');
+        section
+    }
+}
+
+/// Tickers whose reports are kept: the config universe and all watchlists
+async fn tracked_tickers(pool: &SqlitePool) -> Result<BTreeSet<String>> {
+    let config = config::load_config()?;
+    let mut tickers: BTreeSet<String> = config
+        .non_us_tickers
+        .into_iter()
+        .chain(config.us_tickers)
+        .collect();
+    for (name, _) in watchlists::list_watchlists(pool).await? {
+        tickers.extend(watchlists::get_tickers(pool, &name).await?);
+    }
+    Ok(tickers)
+}
+
+/// Periods of at most [`MAX_DAYS_PER_REQUEST`] days covering `from..=to`
+fn request_windows(from: NaiveDate, to: NaiveDate) -> Vec<(NaiveDate, NaiveDate)> {
+    let mut windows = Vec::new();
+    let mut start = from;
+    while start <= to {
+        let end = (start + Days::new(MAX_DAYS_PER_REQUEST - 1)).min(to);
+        windows.push((start, end));
+        start = end + Days::new(1);
+    }
+    windows
+}
+
+/// Replace the stored reports of `tickers` between two dates. Reports moved
+/// to another date since the last fetch are dropped from their old one.
+pub async fn store_reports(
+    pool: &SqlitePool,
+    from: NaiveDate,
+    to: NaiveDate,
+    tickers: &BTreeSet<String>,
+    reports: &[EarningsReport],
+) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    for ticker in tickers {
+        sqlx::query("DELETE FROM earnings_calendar WHERE ticker = ? AND date >= ? AND date <= ?")
+            .bind(ticker)
+            .bind(from.to_string())
+            .bind(to.to_string())
+            .execute(&mut *tx)
+            .await?;
+    }
+    for report in reports {
+        sqlx::query(
+            r#"
+            INSERT OR REPLACE INTO earnings_calendar (
+                ticker, date, time, eps, eps_estimated, revenue, revenue_estimated,
+                fiscal_date_ending
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&report.ticker)
+        .bind(report.date.to_string())
+        .bind(&report.time)
+        .bind(report.eps)
+        .bind(report.eps_estimated)
+        .bind(report.revenue)
+        .bind(report.revenue_estimated)
+        .bind(&report.fiscal_date_ending)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Stored reports between two dates (inclusive), by date and ticker
+pub async fn load_reports(
+    pool: &SqlitePool,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<EarningsReport>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT ticker, date, time, eps, eps_estimated, revenue, revenue_estimated,
+            fiscal_date_ending
+        FROM earnings_calendar
+        WHERE date >= ? AND date <= ?
+        ORDER BY date, ticker
+        "#,
+    )
+    .bind(from.to_string())
+    .bind(to.to_string())
+    .fetch_all(pool)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| {
+            Ok(EarningsReport {
+                ticker: row.get("ticker"),
+                date: NaiveDate::parse_from_str(row.get("date"), "%Y-%m-%d")?,
+                time: row.get("time"),
+                eps: row.get("eps"),
+                eps_estimated: row.get("eps_estimated"),
+                revenue: row.get("revenue"),
+                revenue_estimated: row.get("revenue_estimated"),
+                fiscal_date_ending: row.get("fiscal_date_ending"),
+            })
+        })
+        .collect()
+}
+
+/// Fetch the calendar between two dates and store the reports of the tracked tickers
+pub async fn update_calendar(
+    pool: &SqlitePool,
+    fmp_client: &FMPClient,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<EarningsReport>> {
+    let tickers = tracked_tickers(pool).await?;
+    let mut reports = Vec::new();
+    for (start, end) in request_windows(from, to) {
+        let entries = fmp_client.fetch_earnings_calendar(start, end).await?;
+        reports.extend(
+            entries
+                .into_iter()
+                .filter(|e| tickers.contains(&e.symbol) && e.date >= start && e.date <= end)
+                .map(EarningsReport::from),
+        );
+    }
+    reports.sort_by(|a, b| a.date.cmp(&b.date).then_with(|| a.ticker.cmp(&b.ticker)));
+    reports.dedup_by(|a, b| a.ticker == b.ticker && a.date == b.date);
+    store_reports(pool, from, to, &tickers, &reports).await?;
+    Ok(reports)
+}
+
+fn optional(value: Option<f64>) -> String {
+    value.map(|v| format!("{:.2}", v)).unwrap_or_default()
+}
+
+fn write_csv(path: &Path, reports: &[EarningsReport]) -> Result<()> {
+    let mut writer = Writer::from_path(path)?;
+    writer.write_record([
+        "Date",
+        "Ticker",
+        "Time",
+        "EPS",
+        "EPS Estimated",
+        "Revenue",
+        "Revenue Estimated",
+        "Fiscal Date Ending",
+    ])?;
+    for report in reports {
+        writer.write_record([
+            report.date.to_string(),
+            report.ticker.clone(),
+            report.time.clone().unwrap_or_default(),
+            optional(report.eps),
+            optional(report.eps_estimated),
+            optional(report.revenue),
+            optional(report.revenue_estimated),
+            report.fiscal_date_ending.clone().unwrap_or_default(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn parse_date(date: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|e| anyhow::anyhow!("Invalid date format. Use YYYY-MM-DD: {}", e))
+}
+
+/// Fetch, store and list the earnings reports of the universe between two
+/// dates (today and [`DEFAULT_DAYS_AHEAD`] days later by default)
+pub async fn earnings_calendar(
+    pool: &SqlitePool,
+    from: Option<&str>,
+    to: Option<&str>,
+) -> Result<()> {
+    let from = match from {
+        Some(date) => parse_date(date)?,
+        None => clock::now().date(),
+    };
+    let to = match to {
+        Some(date) => parse_date(date)?,
+        None => from + Days::new(DEFAULT_DAYS_AHEAD),
+    };
+    if to < from {
+        anyhow::bail!("--to ({}) is before --from ({})", to, from);
+    }
+
+    let fmp_client = FMPClient::from_env()?;
+    let reports = update_calendar(pool, &fmp_client, from, to).await?;
+
+    println!(
+        "📊 Earnings reports from {} to {} ({} companies):",
+        from,
+        to,
+        reports.len()
+    );
+    let mut current = None;
+    for report in &reports {
+        if current != Some(report.date) {
+            println!("  {}", report.date.format("%a %Y-%m-%d"));
+            current = Some(report.date);
+        }
+        println!(
+            "    {:<10} {}",
+            report.ticker,
+            report.timing().unwrap_or_default()
+        );
+    }
+
+    let output = config::load_output_config();
+    output.ensure_directory()?;
+    let path = output.file_path_at(
+        "earnings_calendar",
+        &format!("{}_to_{}", from, to),
+        &OutputConfig::timestamp(),
+        "csv",
+    );
+    write_csv(&path, &reports)?;
+    println!("✅ Earnings calendar exported to {}", path.display());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn report(ticker: &str, day: &str, eps: Option<(f64, f64)>) -> EarningsReport {
+        EarningsReport {
+            ticker: ticker.to_string(),
+            date: date(day),
+            time: Some("amc".to_string()),
+            eps: eps.map(|e| e.0),
+            eps_estimated: eps.map(|e| e.1),
+            revenue: None,
+            revenue_estimated: None,
+            fiscal_date_ending: None,
+        }
+    }
+
+    #[test]
+    fn test_request_windows() {
+        assert_eq!(
+            request_windows(date("2025-01-01"), date("2025-01-01")),
+            vec![(date("2025-01-01"), date("2025-01-01"))]
+        );
+        assert_eq!(
+            request_windows(date("2025-01-01"), date("2025-06-30")),
+            vec![
+                (date("2025-01-01"), date("2025-03-31")),
+                (date("2025-04-01"), date("2025-06-29")),
+                (date("2025-06-30"), date("2025-06-30")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_index_for_period() {
+        let reports = [
+            report("NKE", "2025-03-20", Some((0.54, 0.29))),
+            report("NKE", "2024-12-19", None),
+            report("TJX", "2025-02-26", None),
+        ];
+        let index = EarningsIndex::for_period(&reports, date("2025-02-26"), date("2025-03-20"));
+
+        assert_eq!(
+            index.annotation("NKE").as_deref(),
+            Some("2025-03-20 after close, EPS 0.54 vs 0.29 est.")
+        );
+        // The from date itself is excluded, the to date included
+        assert_eq!(index.annotation("TJX"), None);
+        assert!(
+            index
+                .markdown_section()
+                .contains("- **NKE**: 2025-03-20 after close")
+        );
+        assert!(
+            EarningsIndex::for_period(&reports, date("2025-04-01"), date("2025-05-01")).is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_store_replaces_moved_reports() {
+        let pool = db::create_db_pool("sqlite::memory:").await.unwrap();
+        let tickers: BTreeSet<String> = ["NKE".to_string(), "TJX".to_string()].into();
+        let (from, to) = (date("2025-03-01"), date("2025-03-31"));
+
+        store_reports(
+            &pool,
+            from,
+            to,
+            &tickers,
+            &[
+                report("NKE", "2025-03-18", None),
+                report("TJX", "2025-02-26", None),
+            ],
+        )
+        .await
+        .unwrap();
+        // The tentative date moved to the 20th
+        store_reports(
+            &pool,
+            from,
+            to,
+            &tickers,
+            &[report("NKE", "2025-03-20", Some((0.54, 0.29)))],
+        )
+        .await
+        .unwrap();
+
+        let stored = load_reports(&pool, date("2025-01-01"), date("2025-12-31"))
+            .await
+            .unwrap();
+        assert_eq!(
+            stored,
+            vec![
+                report("TJX", "2025-02-26", None),
+                report("NKE", "2025-03-20", Some((0.54, 0.29))),
+            ]
+        );
+
+        let index = EarningsIndex::load_for_period(&pool, "2025-02-26", "2025-03-31")
+            .await
+            .unwrap();
+        assert!(index.annotation("NKE").is_some());
+        assert!(index.annotation("TJX").is_none());
+    }
+}
@@ -0,0 +1,733 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Revenue and market cap per employee (`efficiency-report`)
+//!
+//! Market caps come from the snapshot of the requested date. Revenue (the
+//! latest annual figure) and headcount are stored with each `export-combined`
+//! run; a snapshot without them, such as one fetched for a past date, takes
+//! the ticker's figures stored closest to it, preferring earlier ones. The
+//! headcount falls back to `ticker_details`.
+//!
+//! Each company is compared with its FMP industry, or with the whole universe
+//! when the industry has fewer than [`MIN_INDUSTRY_SIZE`] companies. Per-employee
+//! figures span orders of magnitude, so outliers are found on their logarithm:
+//! more than [`IQR_MULTIPLIER`] interquartile ranges outside the middle half.
+
+use anyhow::Result;
+use chrono::{DateTime, NaiveDate, NaiveTime};
+use csv::Writer;
+use sqlx::Row;
+use sqlx::sqlite::SqlitePool;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::Write as IoWrite;
+
+use crate::clock;
+use crate::config::{self, OutputConfig};
+use crate::rankings;
+use crate::utils::{self, median};
+
+/// Companies an industry needs to be its own comparison group
+pub const MIN_INDUSTRY_SIZE: usize = 5;
+
+/// Tukey's fences: interquartile ranges beyond the quartiles that are flagged
+pub const IQR_MULTIPLIER: f64 = 1.5;
+
+/// Group label for companies without a stored industry
+const UNKNOWN_INDUSTRY: &str = "Unknown";
+
+/// One company of a snapshot with its revenue and headcount
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompanyFigures {
+    pub ticker: String,
+    pub name: String,
+    pub industry: Option<String>,
+    pub employees: Option<i64>,
+    pub revenue_usd: Option<f64>,
+    pub market_cap_usd: Option<f64>,
+}
+
+/// Per-employee figures
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    RevenuePerEmployee,
+    MarketCapPerEmployee,
+}
+
+impl Metric {
+    fn label(&self) -> &'static str {
+        match self {
+            Metric::RevenuePerEmployee => "revenue per employee",
+            Metric::MarketCapPerEmployee => "market cap per employee",
+        }
+    }
+}
+
+/// A per-employee figure far from the company's median
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outlier {
+    pub metric: Metric,
+    /// Value divided by the median
+    pub ratio: f64,
+    /// Whether the median is of the industry (else of the universe)
+    pub industry_median: bool,
+}
+
+impl fmt::Display for Outlier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let group = if self.industry_median {
+            "industry"
+        } else {
+            "universe"
+        };
+        if self.ratio >= 1.0 {
+            write!(
+                f,
+                "{} {:.1}x {} median",
+                self.metric.label(),
+                self.ratio,
+                group
+            )
+        } else {
+            write!(
+                f,
+                "{} 1/{:.0} of {} median",
+                self.metric.label(),
+                1.0 / self.ratio,
+                group
+            )
+        }
+    }
+}
+
+/// One company's row in the report
+#[derive(Debug, Clone, PartialEq)]
+pub struct EfficiencyRow {
+    pub ticker: String,
+    pub name: String,
+    pub industry: String,
+    pub employees: i64,
+    pub revenue_usd: Option<f64>,
+    pub market_cap_usd: Option<f64>,
+    pub revenue_per_employee: Option<f64>,
+    pub market_cap_per_employee: Option<f64>,
+    pub revenue_rank: Option<usize>,
+    pub market_cap_rank: Option<usize>,
+    pub outliers: Vec<Outlier>,
+}
+
+impl EfficiencyRow {
+    fn value(&self, metric: Metric) -> Option<f64> {
+        match metric {
+            Metric::RevenuePerEmployee => self.revenue_per_employee,
+            Metric::MarketCapPerEmployee => self.market_cap_per_employee,
+        }
+    }
+}
+
+/// Medians of one industry
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndustryMedian {
+    pub industry: String,
+    pub companies: usize,
+    pub revenue_per_employee: Option<f64>,
+    pub market_cap_per_employee: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EfficiencyReport {
+    /// Companies with a headcount, highest revenue per employee first
+    pub rows: Vec<EfficiencyRow>,
+    /// Industries by number of companies, largest first
+    pub medians: Vec<IndustryMedian>,
+    /// Tickers without a headcount
+    pub missing_employees: Vec<String>,
+}
+
+/// Quantile of sorted values, interpolating between neighbours
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let position = q * (sorted.len() - 1) as f64;
+    let (lower, upper) = (position.floor() as usize, position.ceil() as usize);
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (position - lower as f64)
+}
+
+/// Range of unremarkable logarithms (Tukey's fences)
+fn fences(values: &[f64]) -> Option<(f64, f64)> {
+    let mut logs: Vec<f64> = values.iter().map(|v| v.ln()).collect();
+    if logs.is_empty() {
+        return None;
+    }
+    logs.sort_by(f64::total_cmp);
+    let (q1, q3) = (quantile(&logs, 0.25), quantile(&logs, 0.75));
+    let reach = IQR_MULTIPLIER * (q3 - q1);
+    Some((q1 - reach, q3 + reach))
+}
+
+/// Rank of each row by a metric, highest first; rows without it get none
+fn assign_ranks(rows: &mut [EfficiencyRow], metric: Metric) {
+    let mut order: Vec<usize> = (0..rows.len())
+        .filter(|&i| rows[i].value(metric).is_some())
+        .collect();
+    order.sort_by(|&a, &b| {
+        rows[b]
+            .value(metric)
+            .unwrap()
+            .total_cmp(&rows[a].value(metric).unwrap())
+            .then_with(|| rows[a].ticker.cmp(&rows[b].ticker))
+    });
+    for (rank, i) in order.into_iter().enumerate() {
+        match metric {
+            Metric::RevenuePerEmployee => rows[i].revenue_rank = Some(rank + 1),
+            Metric::MarketCapPerEmployee => rows[i].market_cap_rank = Some(rank + 1),
+        }
+    }
+}
+
+/// Per-employee figures, ranks, industry medians and outliers
+pub fn compute(companies: &[CompanyFigures]) -> EfficiencyReport {
+    let mut missing_employees = Vec::new();
+    let mut rows: Vec<EfficiencyRow> = Vec::new();
+    for company in companies {
+        let Some(employees) = company.employees.filter(|&e| e > 0) else {
+            missing_employees.push(company.ticker.clone());
+            continue;
+        };
+        let per_employee =
+            |value: Option<f64>| value.filter(|v| *v > 0.0).map(|v| v / employees as f64);
+        rows.push(EfficiencyRow {
+            ticker: company.ticker.clone(),
+            name: company.name.clone(),
+            industry: company
+                .industry
+                .clone()
+                .unwrap_or_else(|| UNKNOWN_INDUSTRY.to_string()),
+            employees,
+            revenue_usd: company.revenue_usd,
+            market_cap_usd: company.market_cap_usd,
+            revenue_per_employee: per_employee(company.revenue_usd),
+            market_cap_per_employee: per_employee(company.market_cap_usd),
+            revenue_rank: None,
+            market_cap_rank: None,
+            outliers: Vec::new(),
+        });
+    }
+
+    let metrics = [Metric::RevenuePerEmployee, Metric::MarketCapPerEmployee];
+    for metric in metrics {
+        assign_ranks(&mut rows, metric);
+    }
+
+    let mut by_industry: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (i, row) in rows.iter().enumerate() {
+        by_industry.entry(row.industry.clone()).or_default().push(i);
+    }
+    let values = |indices: &[usize], metric: Metric| -> Vec<f64> {
+        indices
+            .iter()
+            .filter_map(|&i| rows[i].value(metric))
+            .collect()
+    };
+    let mut medians: Vec<IndustryMedian> = by_industry
+        .iter()
+        .map(|(industry, indices)| IndustryMedian {
+            industry: industry.clone(),
+            companies: indices.len(),
+            revenue_per_employee: median(&values(indices, Metric::RevenuePerEmployee)),
+            market_cap_per_employee: median(&values(indices, Metric::MarketCapPerEmployee)),
+        })
+        .collect();
+    medians.sort_by(|a, b| {
+        b.companies
+            .cmp(&a.companies)
+            .then_with(|| a.industry.cmp(&b.industry))
+    });
+
+    // Unknown industries are no peer group, so they are held to the universe
+    let all: Vec<usize> = (0..rows.len()).collect();
+    let mut outliers: Vec<Vec<Outlier>> = vec![Vec::new(); rows.len()];
+    for metric in metrics {
+        let universe_values = values(&all, metric);
+        let universe = (median(&universe_values), fences(&universe_values));
+        for (industry, indices) in &by_industry {
+            let industry_values = values(indices, metric);
+            let own = industry != UNKNOWN_INDUSTRY && industry_values.len() >= MIN_INDUSTRY_SIZE;
+            let (baseline, range) = if own {
+                (median(&industry_values), fences(&industry_values))
+            } else {
+                universe
+            };
+            let (Some(baseline), Some((low, high))) = (baseline, range) else {
+                continue;
+            };
+            for &i in indices {
+                let Some(value) = rows[i].value(metric) else {
+                    continue;
+                };
+                if value.ln() < low || value.ln() > high {
+                    let ratio = value / baseline;
+                    outliers[i].push(Outlier {
+                        metric,
+                        ratio,
+                        industry_median: own,
+                    });
+                }
+            }
+        }
+    }
+    for (row, flags) in rows.iter_mut().zip(outliers) {
+        row.outliers = flags;
+    }
+
+    rows.sort_by(|a, b| {
+        a.revenue_rank
+            .unwrap_or(usize::MAX)
+            .cmp(&b.revenue_rank.unwrap_or(usize::MAX))
+            .then_with(|| {
+                a.market_cap_rank
+                    .unwrap_or(usize::MAX)
+                    .cmp(&b.market_cap_rank.unwrap_or(usize::MAX))
+            })
+            .then_with(|| a.ticker.cmp(&b.ticker))
+    });
+
+    EfficiencyReport {
+        rows,
+        medians,
+        missing_employees,
+    }
+}
+
+/// Timestamp of the latest snapshot stored on a date, or of the latest one
+async fn snapshot_timestamp(pool: &SqlitePool, date: Option<&str>) -> Result<Option<i64>> {
+    match date {
+        Some(date) => {
+            let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map_err(|e| anyhow::anyhow!("Invalid date format. Use YYYY-MM-DD: {}", e))?;
+            let start = date.and_time(NaiveTime::MIN).and_utc().timestamp();
+            Ok(sqlx::query_scalar(
+                "SELECT MAX(timestamp) FROM market_caps WHERE timestamp >= ? AND timestamp < ?",
+            )
+            .bind(start)
+            .bind(start + 86_400)
+            .fetch_one(pool)
+            .await?)
+        }
+        None => Ok(sqlx::query_scalar("SELECT MAX(timestamp) FROM market_caps")
+            .fetch_one(pool)
+            .await?),
+    }
+}
+
+/// Companies of one snapshot with their revenue and headcount, largest first
+pub async fn load_figures(pool: &SqlitePool, timestamp: i64) -> Result<Vec<CompanyFigures>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT m.ticker, m.name, d.industry,
+            CAST(m.market_cap_usd AS REAL) AS market_cap_usd,
+            COALESCE(
+                m.employees,
+                (SELECT e.employees FROM market_caps e
+                 WHERE e.ticker = m.ticker AND e.employees IS NOT NULL
+                 ORDER BY e.timestamp > ?1, ABS(e.timestamp - ?1)
+                 LIMIT 1),
+                CAST(REPLACE(d.employees, ',', '') AS INTEGER)
+            ) AS employees,
+            COALESCE(
+                CAST(m.revenue_usd AS REAL),
+                (SELECT CAST(r.revenue_usd AS REAL) FROM market_caps r
+                 WHERE r.ticker = m.ticker AND r.revenue_usd IS NOT NULL
+                 ORDER BY r.timestamp > ?1, ABS(r.timestamp - ?1)
+                 LIMIT 1)
+            ) AS revenue_usd
+        FROM market_caps m
+        LEFT JOIN ticker_details d ON d.ticker = m.ticker
+        WHERE m.timestamp = ?1
+        ORDER BY m.market_cap_usd DESC, m.ticker
+        "#,
+    )
+    .bind(timestamp)
+    .fetch_all(pool)
+    .await?;
+
+    let mut figures: Vec<CompanyFigures> = rows
+        .into_iter()
+        .map(|row| CompanyFigures {
+            ticker: row.get("ticker"),
+            name: row.get("name"),
+            industry: row
+                .get::<Option<String>, _>("industry")
+                .filter(|i| !i.trim().is_empty()),
+            employees: row.get("employees"),
+            revenue_usd: row.get("revenue_usd"),
+            market_cap_usd: row.get("market_cap_usd"),
+        })
+        .collect();
+    rankings::truncate_to_top(&mut figures);
+    Ok(figures)
+}
+
+fn format_usd(value: Option<f64>) -> String {
+    value.map_or_else(|| "N/A".to_string(), utils::format_usd)
+}
+
+fn optional(value: Option<f64>) -> String {
+    value.map(|v| format!("{:.0}", v)).unwrap_or_default()
+}
+
+fn write_csv(path: &std::path::Path, report: &EfficiencyReport) -> Result<()> {
+    let mut writer = Writer::from_path(path)?;
+    writer.write_record([
+        "Ticker",
+        "Name",
+        "Industry",
+        "Employees",
+        "Revenue (USD)",
+        "Market Cap (USD)",
+        "Revenue per Employee (USD)",
+        "Revenue per Employee Rank",
+        "Market Cap per Employee (USD)",
+        "Market Cap per Employee Rank",
+        "Outliers",
+    ])?;
+    for row in &report.rows {
+        writer.write_record([
+            row.ticker.clone(),
+            row.name.clone(),
+            row.industry.clone(),
+            row.employees.to_string(),
+            optional(row.revenue_usd),
+            optional(row.market_cap_usd),
+            optional(row.revenue_per_employee),
+            row.revenue_rank.map(|r| r.to_string()).unwrap_or_default(),
+            optional(row.market_cap_per_employee),
+            row.market_cap_rank
+                .map(|r| r.to_string())
+                .unwrap_or_default(),
+            row.outliers
+                .iter()
+                .map(Outlier::to_string)
+                .collect::<Vec<_>>()
+                .join("; "),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_ranking(file: &mut File, report: &EfficiencyReport, metric: Metric) -> Result<()> {
+    let mut rows: Vec<&EfficiencyRow> = report
+        .rows
+        .iter()
+        .filter(|r| r.value(metric).is_some())
+        .collect();
+    rows.sort_by(|a, b| {
+        b.value(metric)
+            .unwrap()
+            .total_cmp(&a.value(metric).unwrap())
+    });
+    rows.truncate(rankings::section_size());
+
+    let title = match metric {
+        Metric::RevenuePerEmployee => "Highest Revenue per Employee",
+        Metric::MarketCapPerEmployee => "Highest Market Cap per Employee",
+    };
+    writeln!(file, "## {}", title)?;
+    writeln!(file)?;
+    writeln!(
+        file,
+        "| Rank | Ticker | Name | Industry | Employees | Per Employee |"
+    )?;
+    writeln!(
+        file,
+        "|-----:|--------|------|----------|----------:|-------------:|"
+    )?;
+    for (i, row) in rows.iter().enumerate() {
+        writeln!(
+            file,
+            "| {} | {} | {} | {} | {} | {} |",
+            i + 1,
+            row.ticker,
+            row.name,
+            row.industry,
+            row.employees,
+            format_usd(row.value(metric))
+        )?;
+    }
+    writeln!(file)?;
+    Ok(())
+}
+
+fn write_markdown(path: &std::path::Path, report: &EfficiencyReport, date: &str) -> Result<()> {
+    let mut file = File::create(path)?;
+    writeln!(file, "# Efficiency Report: {}", date)?;
+    writeln!(file)?;
+    writeln!(file, "- Companies with a headcount: {}", report.rows.len())?;
+    writeln!(
+        file,
+        "- With revenue: {}",
+        report
+            .rows
+            .iter()
+            .filter(|r| r.revenue_per_employee.is_some())
+            .count()
+    )?;
+    writeln!(file)?;
+
+    write_ranking(&mut file, report, Metric::RevenuePerEmployee)?;
+    write_ranking(&mut file, report, Metric::MarketCapPerEmployee)?;
+
+    writeln!(file, "## Industry Medians")?;
+    writeln!(file)?;
+    writeln!(
+        file,
+        "| Industry | Companies | Revenue per Employee | Market Cap per Employee |"
+    )?;
+    writeln!(
+        file,
+        "|----------|----------:|---------------------:|------------------------:|"
+    )?;
+    for median in &report.medians {
+        writeln!(
+            file,
+            "| {} | {} | {} | {} |",
+            median.industry,
+            median.companies,
+            format_usd(median.revenue_per_employee),
+            format_usd(median.market_cap_per_employee)
+        )?;
+    }
+    writeln!(file)?;
+    writeln!(
+        file,
+        "Industries with fewer than {} companies are compared with all companies.",
+        MIN_INDUSTRY_SIZE
+    )?;
+    writeln!(file)?;
+
+    let outliers: Vec<&EfficiencyRow> = report
+        .rows
+        .iter()
+        .filter(|r| !r.outliers.is_empty())
+        .collect();
+    writeln!(file, "## Outliers ({})", outliers.len())?;
+    writeln!(file)?;
+    if outliers.is_empty() {
+        writeln!(file, "None.")?;
+    } else {
+        for row in outliers {
+            let flags: Vec<String> = row.outliers.iter().map(Outlier::to_string).collect();
+            writeln!(
+                file,
+                "- **{}** ({}): {}",
+                row.ticker,
+                row.name,
+                flags.join("; ")
+            )?;
+        }
+    }
+    writeln!(file)?;
+
+    if !report.missing_employees.is_empty() {
+        writeln!(file, "## Missing Headcount")?;
+        writeln!(file)?;
+        writeln!(file, "{}", report.missing_employees.join(", "))?;
+        writeln!(file)?;
+    }
+
+    writeln!(file, "---")?;
+    writeln!(
+        file,
+        "*Generated on {}*",
+        clock::now().format("%Y-%m-%d %H:%M:%S")
+    )?;
+    Ok(())
+}
+
+/// Revenue and market cap per employee for a snapshot, as CSV and markdown
+pub async fn efficiency_report(pool: &SqlitePool, date: Option<&str>) -> Result<()> {
+    let Some(timestamp) = snapshot_timestamp(pool, date).await? else {
+        anyhow::bail!(
+            "No market caps stored for {}; fetch them with fetch-specific-date-market-caps",
+            date.unwrap_or("any date")
+        );
+    };
+    let date = DateTime::from_timestamp(timestamp, 0)
+        .map(|dt| dt.format("%Y-%m-%d").to_string())
+        .unwrap_or_default();
+
+    let report = compute(&load_figures(pool, timestamp).await?);
+    println!(
+        "📊 Efficiency on {}: {} companies with a headcount, {} without",
+        date,
+        report.rows.len(),
+        report.missing_employees.len()
+    );
+    for row in report.rows.iter().take(rankings::section_size()) {
+        println!(
+            "  {:<10} {:>12} revenue/employee  {:>12} market cap/employee",
+            row.ticker,
+            format_usd(row.revenue_per_employee),
+            format_usd(row.market_cap_per_employee)
+        );
+    }
+    let flagged = report
+        .rows
+        .iter()
+        .filter(|r| !r.outliers.is_empty())
+        .count();
+    if flagged > 0 {
+        println!("⚠️  {} companies flagged as outliers", flagged);
+    }
+
+    let output = config::load_output_config();
+    output.ensure_directory()?;
+    let stamp = OutputConfig::timestamp();
+    let csv_path = output.file_path_at("efficiency", &date, &stamp, "csv");
+    write_csv(&csv_path, &report)?;
+    println!("✅ Efficiency figures exported to {}", csv_path.display());
+
+    let md_path = output.file_path_at("efficiency", &format!("{}_summary", date), &stamp, "md");
+    write_markdown(&md_path, &report, &date)?;
+    println!("✅ Efficiency report exported to {}", md_path.display());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    fn company(
+        ticker: &str,
+        industry: Option<&str>,
+        employees: Option<i64>,
+        revenue: Option<f64>,
+        market_cap: f64,
+    ) -> CompanyFigures {
+        CompanyFigures {
+            ticker: ticker.to_string(),
+            name: format!("{} Inc", ticker),
+            industry: industry.map(str::to_string),
+            employees,
+            revenue_usd: revenue,
+            market_cap_usd: Some(market_cap),
+        }
+    }
+
+    #[test]
+    fn test_fences() {
+        assert_eq!(fences(&[]), None);
+        // Quartiles of 1, 10, 100, 1000, 10000 (in logs): 10 and 1000
+        let (low, high) = fences(&[1.0, 10.0, 100.0, 1_000.0, 10_000.0]).unwrap();
+        assert!((low.exp() - 0.01).abs() < 1e-9);
+        assert!((high.exp() - 1_000_000.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_compute_ranks_medians_and_outliers() {
+        let luxury = Some("Luxury Goods");
+        let report = compute(&[
+            company("CFR.SW", luxury, Some(100), Some(50_000.0), 100_000.0),
+            company("KER.PA", luxury, Some(100), Some(50_000.0), 110_000.0),
+            company("MC.PA", luxury, Some(100), Some(50_000.0), 120_000.0),
+            company("MONC.MI", luxury, Some(100), Some(50_000.0), 130_000.0),
+            company("RMS.PA", luxury, Some(100), Some(50_000.0), 2_000_000.0),
+            // Its own industry is too small: compared with the universe
+            company(
+                "NKE",
+                Some("Footwear"),
+                Some(1_000),
+                Some(500_000.0),
+                1_000_000.0,
+            ),
+            company("NEW", None, Some(10), None, 1_000.0),
+            company("ANON", luxury, None, Some(1.0), 1.0),
+        ]);
+
+        assert_eq!(report.missing_employees, vec!["ANON"]);
+        let tickers: Vec<&str> = report.rows.iter().map(|r| r.ticker.as_str()).collect();
+        // Equal revenue per employee is ranked by ticker; no revenue comes last
+        assert_eq!(
+            tickers,
+            vec![
+                "CFR.SW", "KER.PA", "MC.PA", "MONC.MI", "NKE", "RMS.PA", "NEW"
+            ]
+        );
+        assert_eq!(report.rows[6].revenue_rank, None);
+
+        let rms = &report.rows[5];
+        assert_eq!(rms.market_cap_per_employee, Some(20_000.0));
+        assert_eq!(rms.market_cap_rank, Some(1));
+        // Luxury market cap per employee: 1000, 1100, 1200, 1300 and 20000
+        assert_eq!(rms.outliers.len(), 1);
+        assert_eq!(
+            rms.outliers[0].to_string(),
+            "market cap per employee 16.7x industry median"
+        );
+        assert!(report.rows[0].outliers.is_empty());
+        assert!(report.rows[4].outliers.is_empty());
+        assert_eq!(
+            report.rows[6].outliers,
+            vec![Outlier {
+                metric: Metric::MarketCapPerEmployee,
+                ratio: 100.0 / 1_100.0,
+                industry_median: false,
+            }]
+        );
+        assert_eq!(
+            report.rows[6].outliers[0].to_string(),
+            "market cap per employee 1/11 of universe median"
+        );
+
+        let luxury_median = &report.medians[0];
+        assert_eq!(luxury_median.industry, "Luxury Goods");
+        assert_eq!(luxury_median.companies, 5);
+        assert_eq!(luxury_median.revenue_per_employee, Some(500.0));
+        assert_eq!(luxury_median.market_cap_per_employee, Some(1_200.0));
+    }
+
+    #[tokio::test]
+    async fn test_load_figures_uses_nearest_stored_revenue() {
+        let pool = db::create_db_pool("sqlite::memory:").await.unwrap();
+        sqlx::query("ALTER TABLE ticker_details ADD COLUMN ceo TEXT")
+            .execute(&pool)
+            .await
+            .unwrap();
+        for sql in [
+            // Snapshot at 200 has no revenue: take 100's, not the later 300's
+            "INSERT INTO market_caps (ticker, name, market_cap_usd, revenue_usd, employees, timestamp) VALUES
+                ('NKE', 'Nike', 90, 40, 70, 100), ('NKE', 'Nike', 100, NULL, NULL, 200), ('NKE', 'Nike', 110, 50, 80, 300),
+                ('MC.PA', 'LVMH', 300, NULL, NULL, 200)",
+            "INSERT INTO ticker_details (ticker, employees, industry) VALUES ('MC.PA', '213,000', 'Luxury Goods')",
+        ] {
+            sqlx::query(sql).execute(&pool).await.unwrap();
+        }
+
+        let figures = load_figures(&pool, 200).await.unwrap();
+        assert_eq!(
+            figures,
+            vec![
+                CompanyFigures {
+                    ticker: "MC.PA".to_string(),
+                    name: "LVMH".to_string(),
+                    industry: Some("Luxury Goods".to_string()),
+                    employees: Some(213_000),
+                    revenue_usd: None,
+                    market_cap_usd: Some(300.0),
+                },
+                CompanyFigures {
+                    ticker: "NKE".to_string(),
+                    name: "Nike".to_string(),
+                    industry: None,
+                    employees: Some(70),
+                    revenue_usd: Some(40.0),
+                    market_cap_usd: Some(100.0),
+                },
+            ]
+        );
+    }
+}
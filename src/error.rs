@@ -0,0 +1,216 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Crate-level `Error` for failures callers handle differently
+//!
+//! Most functions return `anyhow::Result` and add context on the way up. The
+//! failures below get a variant because callers act on them: the web API
+//! answers 404 or 503 instead of 500, and the NATS worker doesn't retry a job
+//! that can't succeed. They travel inside `anyhow::Error` and `code_of()`
+//! finds them again through any added context.
+//!
+//! Jobs run the CLI as a subprocess, so `main` exits with
+//! `ErrorCode::exit_code()` and the worker turns the exit status back into an
+//! `ErrorCode` with `from_exit_code()`.
+
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// FMP still answered "Limit Reach" after every retry
+    #[error("Rate limit reached after {retries} retries")]
+    RateLimited { retries: u32 },
+    /// The API or the database has no data for the ticker
+    #[error("No data found for ticker {0}")]
+    TickerNotFound(String),
+    /// No direct, reverse or cross rate converts between the currencies
+    #[error("No exchange rate found for {from}/{to}")]
+    CurrencyMissing { from: String, to: String },
+    /// No market cap CSV was exported for the date
+    #[error("{}", csv_not_found_message(date, watchlist.as_deref()))]
+    CsvNotFound {
+        date: String,
+        watchlist: Option<String>,
+    },
+    /// config.toml does not parse or fails validation
+    #[error("Invalid configuration: {0}")]
+    ConfigInvalid(String),
+    /// The endpoint failed repeatedly and its circuit breaker is open
+    #[error("Skipped: {endpoint} failed repeatedly, retrying in {retry_in_secs}s")]
+    CircuitOpen {
+        endpoint: String,
+        retry_in_secs: u64,
+    },
+    /// The requests for a ticker took longer than `[api] ticker_deadline_secs`
+    #[error("Skipped: {ticker} took longer than {secs}s")]
+    DeadlineExceeded { ticker: String, secs: u64 },
+}
+
+fn csv_not_found_message(date: &str, watchlist: Option<&str>) -> String {
+    match watchlist {
+        Some(name) => format!(
+            "No CSV file found for watchlist {} on {}. Please run 'watchlist fetch {} --date {}' first.",
+            name, date, name, date
+        ),
+        None => format!(
+            "No CSV file found for date {}. Please run 'fetch-specific-date-market-caps {}' first.",
+            date, date
+        ),
+    }
+}
+
+impl Error {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Error::RateLimited { .. } => ErrorCode::RateLimited,
+            Error::TickerNotFound(_) => ErrorCode::TickerNotFound,
+            Error::CurrencyMissing { .. } => ErrorCode::CurrencyMissing,
+            Error::CsvNotFound { .. } => ErrorCode::CsvNotFound,
+            Error::ConfigInvalid(_) => ErrorCode::ConfigInvalid,
+            Error::CircuitOpen { .. } | Error::DeadlineExceeded { .. } => ErrorCode::ApiUnavailable,
+        }
+    }
+}
+
+/// Kind of an `Error`, as stored with failed jobs and used for exit codes.
+/// It is an error itself so the worker can attach a subprocess's code to
+/// the error it reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, thiserror::Error)]
+#[serde(rename_all = "snake_case")]
+#[error("{}", self.as_str())]
+pub enum ErrorCode {
+    RateLimited,
+    TickerNotFound,
+    CurrencyMissing,
+    CsvNotFound,
+    ConfigInvalid,
+    ApiUnavailable,
+}
+
+impl ErrorCode {
+    pub const ALL: [ErrorCode; 6] = [
+        ErrorCode::RateLimited,
+        ErrorCode::TickerNotFound,
+        ErrorCode::CurrencyMissing,
+        ErrorCode::CsvNotFound,
+        ErrorCode::ConfigInvalid,
+        ErrorCode::ApiUnavailable,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::RateLimited => "rate_limited",
+            ErrorCode::TickerNotFound => "ticker_not_found",
+            ErrorCode::CurrencyMissing => "currency_missing",
+            ErrorCode::CsvNotFound => "csv_not_found",
+            ErrorCode::ConfigInvalid => "config_invalid",
+            ErrorCode::ApiUnavailable => "api_unavailable",
+        }
+    }
+
+    /// Status the web API answers with
+    pub fn http_status(self) -> StatusCode {
+        match self {
+            // Our upstream limit, not the client's: try again later
+            ErrorCode::RateLimited | ErrorCode::ApiUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::TickerNotFound | ErrorCode::CsvNotFound => StatusCode::NOT_FOUND,
+            ErrorCode::CurrencyMissing => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::ConfigInvalid => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Process exit code of the CLI; other errors exit with 1
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorCode::RateLimited => 10,
+            ErrorCode::TickerNotFound => 11,
+            ErrorCode::CurrencyMissing => 12,
+            ErrorCode::CsvNotFound => 13,
+            ErrorCode::ConfigInvalid => 14,
+            ErrorCode::ApiUnavailable => 15,
+        }
+    }
+
+    pub fn from_exit_code(code: i32) -> Option<ErrorCode> {
+        Self::ALL.into_iter().find(|c| c.exit_code() == code)
+    }
+
+    /// Whether running the job again may succeed. Only rate limits and
+    /// unavailable APIs pass; missing data and bad config stay missing and bad.
+    pub fn is_retryable(self) -> bool {
+        matches!(self, ErrorCode::RateLimited | ErrorCode::ApiUnavailable)
+    }
+}
+
+/// Code of the first `Error` (or `ErrorCode`) in the error's chain
+pub fn code_of(err: &anyhow::Error) -> Option<ErrorCode> {
+    err.chain().find_map(|cause| {
+        cause
+            .downcast_ref::<Error>()
+            .map(Error::code)
+            .or_else(|| cause.downcast_ref::<ErrorCode>().copied())
+    })
+}
+
+/// Exit code for an error returned from `main`
+pub fn exit_code(err: &anyhow::Error) -> i32 {
+    code_of(err).map_or(1, ErrorCode::exit_code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_of_finds_error_through_context() {
+        let err: anyhow::Error = Error::CsvNotFound {
+            date: "2025-01-01".to_string(),
+            watchlist: None,
+        }
+        .into();
+        let err = err.context("Comparing 2025-01-01 to 2025-02-01");
+        assert_eq!(code_of(&err), Some(ErrorCode::CsvNotFound));
+        assert_eq!(exit_code(&err), 13);
+        assert_eq!(
+            err.root_cause().to_string(),
+            "No CSV file found for date 2025-01-01. Please run 'fetch-specific-date-market-caps 2025-01-01' first."
+        );
+
+        let err = anyhow::Error::new(ErrorCode::RateLimited).context("Command failed");
+        assert_eq!(code_of(&err), Some(ErrorCode::RateLimited));
+
+        let err = anyhow::anyhow!("something else").context("outer");
+        assert_eq!(code_of(&err), None);
+        assert_eq!(exit_code(&err), 1);
+    }
+
+    #[test]
+    fn test_error_code_round_trips() {
+        for code in ErrorCode::ALL {
+            assert_eq!(ErrorCode::from_exit_code(code.exit_code()), Some(code));
+            assert_eq!(
+                serde_json::to_value(code).unwrap(),
+                serde_json::json!(code.as_str())
+            );
+        }
+        assert_eq!(ErrorCode::from_exit_code(1), None);
+        assert_eq!(
+            Error::TickerNotFound("NKE".to_string())
+                .code()
+                .http_status(),
+            StatusCode::NOT_FOUND
+        );
+        assert!(ErrorCode::RateLimited.is_retryable());
+        assert!(
+            Error::DeadlineExceeded {
+                ticker: "NKE".to_string(),
+                secs: 120
+            }
+            .code()
+            .is_retryable()
+        );
+        assert!(!ErrorCode::ConfigInvalid.is_retryable());
+    }
+}
@@ -2,48 +2,92 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 
 use crate::api::FMPClient;
+use crate::config::{self, ForexConfig};
 use crate::currencies::insert_forex_rate;
+use crate::db::{CorePool, core_query};
+use crate::forex::{self, FmpForexProvider, ForexQuote};
+use crate::progress;
+use crate::run_context;
 use anyhow::Result;
-use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Utc};
-use indicatif::{ProgressBar, ProgressStyle};
-use sqlx::sqlite::SqlitePool;
+use chrono::{Datelike, Days, NaiveDate, NaiveTime, Utc, Weekday};
+use csv::Writer;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Merge quotes from every configured provider; fails only if all providers fail
+fn merge_provider_results(
+    forex_config: &ForexConfig,
+    results: Vec<(config::ForexSource, Result<Vec<ForexQuote>>)>,
+) -> Result<Vec<ForexQuote>> {
+    let mut sources = Vec::new();
+    let mut errors = Vec::new();
+    for (source, result) in results {
+        match result {
+            Ok(quotes) => {
+                println!("✅ {} rates fetched from {:?}", quotes.len(), source);
+                sources.push((source, quotes));
+            }
+            Err(e) => {
+                eprintln!("⚠️  Failed to fetch rates from {:?}: {}", source, e);
+                run_context::record_warning(format!(
+                    "Failed to fetch rates from {:?}: {}",
+                    source, e
+                ));
+                errors.push(e);
+            }
+        }
+    }
+
+    if sources.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Failed to fetch exchange rates: {}",
+            errors
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+                .join("; ")
+        ));
+    }
+
+    Ok(forex::merge_quotes(sources, &forex_config.prefer))
+}
+
+async fn store_quotes(pool: &CorePool, quotes: &[ForexQuote]) -> Result<()> {
+    for quote in quotes {
+        insert_forex_rate(pool, &quote.symbol, quote.rate, quote.rate, quote.timestamp).await?;
+    }
+    Ok(())
+}
 
 /// Update exchange rates in the database
-pub async fn update_exchange_rates(fmp_client: &FMPClient, pool: &SqlitePool) -> Result<()> {
+pub async fn update_exchange_rates(
+    fmp_client: &FMPClient,
+    pool: impl Into<CorePool>,
+) -> Result<()> {
+    let forex_config = config::load_forex_config();
+
     // Fetch exchange rates
     println!("Fetching current exchange rates...");
-    let exchange_rates = match fmp_client.get_exchange_rates().await {
-        Ok(rates) => {
-            println!("✅ Exchange rates fetched");
-            rates
-        }
-        Err(e) => {
-            return Err(anyhow::anyhow!("Failed to fetch exchange rates: {}", e));
-        }
-    };
-
     // Store rates in database (use UTC timestamp for consistency)
     let timestamp = Utc::now().timestamp();
-    for rate in exchange_rates {
-        if let (Some(name), Some(price)) = (rate.name, rate.price) {
-            insert_forex_rate(pool, &name, price, price, timestamp).await?;
-        }
+    let mut results = Vec::new();
+    for &source in &forex_config.providers {
+        results.push((
+            source,
+            forex::fetch_latest(source, fmp_client, timestamp).await,
+        ));
     }
 
+    let quotes = merge_provider_results(&forex_config, results)?;
+    store_quotes(&pool.into(), &quotes).await?;
+
     println!("✅ Exchange rates updated in database");
     Ok(())
 }
 
-/// Currency pairs commonly needed for market cap conversions
-const COMMON_FOREX_PAIRS: &[&str] = &[
-    "EURUSD", "GBPUSD", "JPYUSD", "CHFUSD", "SEKUSD", "DKKUSD", "NOKUSD", "HKDUSD", "CNYUSD",
-    "BRLUSD", "CADUSD", "ILSUSD", "ZARUSD", "INRUSD", "KRWUSD", "TRYUSD", "PLNUSD", "TWDUSD",
-];
-
 /// Fetch and store historical exchange rates for a date range
 pub async fn fetch_historical_exchange_rates(
     fmp_client: &FMPClient,
-    pool: &SqlitePool,
+    pool: impl Into<CorePool>,
     from_date: &str,
     to_date: &str,
 ) -> Result<()> {
@@ -51,129 +95,405 @@ pub async fn fetch_historical_exchange_rates(
         "Fetching historical exchange rates from {} to {}",
         from_date, to_date
     );
+    let from = NaiveDate::parse_from_str(from_date, "%Y-%m-%d")?;
+    let to = NaiveDate::parse_from_str(to_date, "%Y-%m-%d")?;
+    let forex_config = config::load_forex_config();
+
+    let mut results = Vec::new();
+    for &source in &forex_config.providers {
+        results.push((
+            source,
+            forex::fetch_historical(source, fmp_client, from, to).await,
+        ));
+    }
+
+    let quotes = merge_provider_results(&forex_config, results)?;
+    store_quotes(&pool.into(), &quotes).await?;
 
-    // Get available forex pairs to validate
-    println!("Fetching available forex pairs...");
-    let available_pairs = match fmp_client.get_available_forex_pairs().await {
-        Ok(pairs) => {
-            println!("✅ Found {} available forex pairs", pairs.len());
-            pairs
+    // Print summary
+    println!("\n📊 Historical Exchange Rates Summary:");
+    println!("   Date range: {} to {}", from_date, to_date);
+    println!("   Total rates stored: {}", quotes.len());
+
+    println!("\n✅ Historical exchange rates updated in database");
+    Ok(())
+}
+
+/// Monday to Friday dates in the range (inclusive)
+pub(crate) fn business_days(from: NaiveDate, to: NaiveDate) -> Vec<NaiveDate> {
+    from.iter_days()
+        .take_while(|day| *day <= to)
+        .filter(|day| !matches!(day.weekday(), Weekday::Sat | Weekday::Sun))
+        .collect()
+}
+
+/// Group missing business days into date ranges to fetch: days with only
+/// weekend days between them share a range
+fn missing_ranges(missing: &[NaiveDate]) -> Vec<(NaiveDate, NaiveDate)> {
+    let mut ranges: Vec<(NaiveDate, NaiveDate)> = Vec::new();
+    for &day in missing {
+        match ranges.last_mut() {
+            Some((_, end)) if business_days(*end + Days::new(1), day) == [day] => *end = day,
+            _ => ranges.push((day, day)),
         }
-        Err(e) => {
-            eprintln!(
-                "⚠️  Could not fetch available pairs, using common pairs: {}",
-                e
-            );
-            COMMON_FOREX_PAIRS.iter().map(|s| s.to_string()).collect()
+    }
+    ranges
+}
+
+/// Rate coverage of one pair over the business days of a range
+#[derive(Debug, Clone, PartialEq)]
+pub struct PairCoverage {
+    pub symbol: String,
+    pub business_days: usize,
+    /// Days without a rate before fetching
+    pub missing_before: usize,
+    /// Days without a rate after fetching the gaps
+    pub missing: Vec<NaiveDate>,
+}
+
+impl PairCoverage {
+    /// Share of business days with a rate, in percent
+    pub fn coverage(&self) -> f64 {
+        if self.business_days == 0 {
+            return 100.0;
         }
-    };
+        (self.business_days - self.missing.len()) as f64 * 100.0 / self.business_days as f64
+    }
+}
 
-    // Filter to common pairs that are available
-    let pairs_to_fetch: Vec<&str> = COMMON_FOREX_PAIRS
-        .iter()
-        .filter(|p| available_pairs.iter().any(|ap| ap == *p))
-        .copied()
-        .collect();
+/// Days in the range with a stored rate, per symbol
+async fn stored_rate_days(
+    pool: &CorePool,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<BTreeMap<String, BTreeSet<NaiveDate>>> {
+    let start = from.and_time(NaiveTime::MIN).and_utc().timestamp();
+    let end = (to + Days::new(1))
+        .and_time(NaiveTime::MIN)
+        .and_utc()
+        .timestamp();
+    let rows = core_query!(pool, |pool| sqlx::query_as::<_, (String, i64)>(
+        "SELECT symbol, timestamp FROM forex_rates WHERE timestamp >= $1 AND timestamp < $2",
+    )
+    .bind(start)
+    .bind(end)
+    .fetch_all(pool)
+    .await?);
 
-    if pairs_to_fetch.is_empty() {
-        println!("Using all common forex pairs...");
-    } else {
-        println!("Fetching {} currency pairs...", pairs_to_fetch.len());
+    let mut days: BTreeMap<String, BTreeSet<NaiveDate>> = BTreeMap::new();
+    for (symbol, timestamp) in rows {
+        if let Some(datetime) = chrono::DateTime::from_timestamp(timestamp, 0) {
+            days.entry(symbol)
+                .or_default()
+                .insert(datetime.date_naive());
+        }
+    }
+    Ok(days)
+}
+
+/// Days in the range recorded as market holidays of a pair: FMP had no rate
+/// for them when `verify-rates` fetched the range
+async fn known_holidays(
+    pool: &CorePool,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<BTreeMap<String, BTreeSet<NaiveDate>>> {
+    let rows = core_query!(pool, |pool| sqlx::query_as::<_, (String, String)>(
+        "SELECT symbol, date FROM forex_rate_holidays WHERE date >= $1 AND date <= $2",
+    )
+    .bind(from.format("%Y-%m-%d").to_string())
+    .bind(to.format("%Y-%m-%d").to_string())
+    .fetch_all(pool)
+    .await?);
+
+    let mut days: BTreeMap<String, BTreeSet<NaiveDate>> = BTreeMap::new();
+    for (symbol, date) in rows {
+        if let Ok(date) = NaiveDate::parse_from_str(&date, "%Y-%m-%d") {
+            days.entry(symbol).or_default().insert(date);
+        }
+    }
+    Ok(days)
+}
+
+/// Record days without a rate for a pair as market holidays
+async fn record_holidays(pool: &CorePool, symbol: &str, days: &[NaiveDate]) -> Result<()> {
+    for day in days {
+        core_query!(pool, |pool| sqlx::query(
+            "INSERT INTO forex_rate_holidays (symbol, date) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        )
+        .bind(symbol)
+        .bind(day.format("%Y-%m-%d").to_string())
+        .execute(pool)
+        .await?
+        .rows_affected());
+    }
+    Ok(())
+}
+
+/// Days with a stored rate or a recorded holiday, per symbol
+async fn known_rate_days(
+    pool: &CorePool,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<BTreeMap<String, BTreeSet<NaiveDate>>> {
+    let mut days = stored_rate_days(pool, from, to).await?;
+    for (symbol, holidays) in known_holidays(pool, from, to).await? {
+        days.entry(symbol).or_default().extend(holidays);
     }
+    Ok(days)
+}
 
-    let pairs = if pairs_to_fetch.is_empty() {
-        COMMON_FOREX_PAIRS.to_vec()
+/// Business days of `days` without a rate for `symbol`
+fn missing_days(
+    symbol: &str,
+    days: &[NaiveDate],
+    stored: &BTreeMap<String, BTreeSet<NaiveDate>>,
+) -> Vec<NaiveDate> {
+    days.iter()
+        .filter(|day| !stored.get(symbol).is_some_and(|s| s.contains(day)))
+        .copied()
+        .collect()
+}
+
+/// Check which (pair, business day) combinations lack a rate, fetch only
+/// those ranges from FMP, and report the coverage per pair. Without a client
+/// (`--check-only`) the coverage is only reported. `pairs` defaults to the
+/// common FMP pairs. Days that stay without a rate after a successful fetch
+/// are recorded as market holidays and not fetched again.
+pub async fn verify_exchange_rates(
+    fmp_client: Option<&FMPClient>,
+    pool: impl Into<CorePool>,
+    from_date: &str,
+    to_date: &str,
+    pairs: &[String],
+) -> Result<Vec<PairCoverage>> {
+    let pool = &pool.into();
+    let from = NaiveDate::parse_from_str(from_date, "%Y-%m-%d")?;
+    let to = NaiveDate::parse_from_str(to_date, "%Y-%m-%d")?;
+    if from > to {
+        anyhow::bail!("--from {} is after --to {}", from_date, to_date);
+    }
+    let pairs: Vec<String> = if pairs.is_empty() {
+        forex::fmp::common_pairs()
     } else {
-        pairs_to_fetch
+        pairs.iter().map(|p| p.to_uppercase()).collect()
     };
+    let days = business_days(from, to);
 
-    // Set up progress bar
-    let progress = ProgressBar::new(pairs.len() as u64);
-    progress.set_style(
-        ProgressStyle::default_bar()
-            .template("[{elapsed_precise}] {bar:40.cyan/blue} {pos:>3}/{len:3} {msg}")
-            .unwrap()
-            .progress_chars("=>-"),
-    );
+    let stored = known_rate_days(pool, from, to).await?;
+    let gaps: Vec<(String, Vec<NaiveDate>)> = pairs
+        .iter()
+        .map(|pair| (pair.clone(), missing_days(pair, &days, &stored)))
+        .collect();
+    let ranges: Vec<(&str, NaiveDate, NaiveDate)> = gaps
+        .iter()
+        .flat_map(|(pair, missing)| {
+            missing_ranges(missing)
+                .into_iter()
+                .map(move |(start, end)| (pair.as_str(), start, end))
+        })
+        .collect();
 
-    let mut total_rates = 0usize;
-    let mut failed_pairs = Vec::new();
-
-    for pair in &pairs {
-        progress.set_message(format!("Fetching {}...", pair));
-
-        match fmp_client
-            .get_historical_exchange_rates(pair, from_date, to_date)
-            .await
-        {
-            Ok(response) => {
-                let symbol_with_slash = format_pair_with_slash(&response.symbol);
-
-                for data in &response.historical {
-                    // Parse date and convert to Unix timestamp
-                    if let Ok(date) = NaiveDate::parse_from_str(&data.date, "%Y-%m-%d") {
-                        let datetime =
-                            NaiveDateTime::new(date, NaiveTime::from_hms_opt(0, 0, 0).unwrap());
-                        let timestamp = datetime.and_utc().timestamp();
-
-                        // Use close price as the rate (most commonly used)
-                        insert_forex_rate(
-                            pool,
-                            &symbol_with_slash,
-                            data.close,
-                            data.close,
-                            timestamp,
-                        )
-                        .await?;
-                        total_rates += 1;
-                    }
+    let mut stored_after = stored.clone();
+    if let Some(fmp_client) = fmp_client
+        && !ranges.is_empty()
+    {
+        println!(
+            "Fetching {} missing date ranges for {} pairs...",
+            ranges.len(),
+            gaps.iter().filter(|(_, m)| !m.is_empty()).count()
+        );
+        let provider = FmpForexProvider::new(fmp_client);
+        let bar = progress::fetch_bar(ranges.len() as u64, "ranges", 1);
+        let mut fetched = Vec::new();
+        for (pair, start, end) in &ranges {
+            match provider.pair_rates(pair, *start, *end).await {
+                Ok(quotes) => {
+                    store_quotes(pool, &quotes).await?;
+                    fetched.push((*pair, *start, *end));
+                }
+                Err(e) => {
+                    progress::suspend(|| {
+                        eprintln!("⚠️  Failed to fetch {} {} to {}: {}", pair, start, end, e)
+                    });
+                    run_context::record_warning(format!(
+                        "Failed to fetch {} {} to {}: {}",
+                        pair, start, end, e
+                    ));
                 }
             }
-            Err(e) => {
-                failed_pairs.push((pair.to_string(), e.to_string()));
-            }
+            bar.inc(1);
         }
+        bar.finish_and_clear();
+        stored_after = known_rate_days(pool, from, to).await?;
 
-        progress.inc(1);
+        // Today's close may not be published yet, so only earlier days count
+        let today = Utc::now().date_naive();
+        let mut holidays = 0;
+        for (pair, start, end) in fetched {
+            let still_missing: Vec<NaiveDate> = missing_days(pair, &days, &stored_after)
+                .into_iter()
+                .filter(|day| *day >= start && *day <= end && *day < today)
+                .collect();
+            record_holidays(pool, pair, &still_missing).await?;
+            holidays += still_missing.len();
+        }
+        if holidays > 0 {
+            println!(
+                "Recorded {} days without an FMP rate as market holidays; later runs skip them",
+                holidays
+            );
+        }
     }
 
-    progress.finish_with_message("Done");
-
-    // Print summary
-    println!("\n📊 Historical Exchange Rates Summary:");
-    println!("   Date range: {} to {}", from_date, to_date);
-    println!("   Pairs processed: {}", pairs.len() - failed_pairs.len());
-    println!("   Total rates stored: {}", total_rates);
+    let coverage: Vec<PairCoverage> = gaps
+        .into_iter()
+        .map(|(symbol, missing_before)| PairCoverage {
+            missing: missing_days(&symbol, &days, &stored_after),
+            missing_before: missing_before.len(),
+            business_days: days.len(),
+            symbol,
+        })
+        .collect();
 
-    if !failed_pairs.is_empty() {
-        println!("\n⚠️  Failed to fetch {} pairs:", failed_pairs.len());
-        for (pair, error) in &failed_pairs {
-            println!("   {} - {}", pair, error);
-        }
+    print_coverage(&coverage, from_date, to_date);
+    let path = export_coverage(&coverage, from_date, to_date)?;
+    println!("✅ Rate coverage exported to {}", path);
+    for pair in coverage.iter().filter(|c| !c.missing.is_empty()) {
+        run_context::record_warning(format!(
+            "{} has no rate on {} of {} business days",
+            pair.symbol,
+            pair.missing.len(),
+            pair.business_days
+        ));
     }
+    Ok(coverage)
+}
 
-    println!("\n✅ Historical exchange rates updated in database");
-    Ok(())
+fn print_coverage(coverage: &[PairCoverage], from_date: &str, to_date: &str) {
+    println!(
+        "\n📊 Rate coverage {} to {} (business days):",
+        from_date, to_date
+    );
+    println!(
+        "{:<10} {:>6} {:>8} {:>8} {:>9}",
+        "Pair", "Days", "Before", "Missing", "Coverage"
+    );
+    for pair in coverage {
+        println!(
+            "{:<10} {:>6} {:>8} {:>8} {:>8.1}%",
+            pair.symbol,
+            pair.business_days,
+            pair.missing_before,
+            pair.missing.len(),
+            pair.coverage()
+        );
+    }
 }
 
-/// Convert a pair like "EURUSD" to "EUR/USD"
-fn format_pair_with_slash(pair: &str) -> String {
-    if pair.len() == 6 && !pair.contains('/') {
-        format!("{}/{}", &pair[0..3], &pair[3..6])
-    } else {
-        pair.to_string()
+fn export_coverage(coverage: &[PairCoverage], from_date: &str, to_date: &str) -> Result<String> {
+    let output = config::load_output_config();
+    output.ensure_directory()?;
+    let path = output
+        .file_path(
+            "rate_coverage",
+            &format!("{}_{}", from_date, to_date),
+            "csv",
+        )
+        .display()
+        .to_string();
+    let mut writer = Writer::from_path(&path)?;
+    writer.write_record([
+        "Pair",
+        "Business Days",
+        "Missing Before",
+        "Missing",
+        "Coverage (%)",
+        "Missing Days",
+    ])?;
+    for pair in coverage {
+        let missing_days: Vec<String> = pair.missing.iter().map(|d| d.to_string()).collect();
+        writer.write_record([
+            pair.symbol.clone(),
+            pair.business_days.to_string(),
+            pair.missing_before.to_string(),
+            pair.missing.len().to_string(),
+            format!("{:.1}", pair.coverage()),
+            missing_days.join(" "),
+        ])?;
     }
+    writer.flush()?;
+    Ok(path)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::db;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn test_business_days_skip_weekends() {
+        // 2025-01-03 is a Friday
+        assert_eq!(
+            business_days(date("2025-01-03"), date("2025-01-07")),
+            vec![date("2025-01-03"), date("2025-01-06"), date("2025-01-07")]
+        );
+        assert!(business_days(date("2025-01-04"), date("2025-01-05")).is_empty());
+    }
 
     #[test]
-    fn test_format_pair_with_slash() {
-        assert_eq!(format_pair_with_slash("EURUSD"), "EUR/USD");
-        assert_eq!(format_pair_with_slash("GBPUSD"), "GBP/USD");
-        assert_eq!(format_pair_with_slash("EUR/USD"), "EUR/USD");
-        assert_eq!(format_pair_with_slash("JPYUSD"), "JPY/USD");
+    fn test_missing_ranges_span_weekends_only() {
+        let missing = [
+            date("2025-01-02"),
+            date("2025-01-03"),
+            date("2025-01-06"),
+            date("2025-01-08"),
+        ];
+        assert_eq!(
+            missing_ranges(&missing),
+            vec![
+                (date("2025-01-02"), date("2025-01-06")),
+                (date("2025-01-08"), date("2025-01-08")),
+            ]
+        );
+        assert!(missing_ranges(&[]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_stored_days_and_coverage() -> Result<()> {
+        let pool = CorePool::from(&db::create_db_pool("sqlite::memory:").await?);
+        for day in ["2025-01-06", "2025-01-07", "2025-01-09"] {
+            let ts = date(day).and_time(NaiveTime::MIN).and_utc().timestamp();
+            insert_forex_rate(&pool, "EUR/USD", 1.03, 1.03, ts + 3600).await?;
+        }
+        let stored = stored_rate_days(&pool, date("2025-01-06"), date("2025-01-10")).await?;
+        let days = business_days(date("2025-01-06"), date("2025-01-10"));
+
+        let missing = missing_days("EUR/USD", &days, &stored);
+        assert_eq!(missing, vec![date("2025-01-08"), date("2025-01-10")]);
+        assert_eq!(missing_days("GBP/USD", &days, &stored).len(), 5);
+
+        let coverage = PairCoverage {
+            symbol: "EUR/USD".to_string(),
+            business_days: days.len(),
+            missing_before: missing.len(),
+            missing,
+        };
+        assert!((coverage.coverage() - 60.0).abs() < 1e-9);
+
+        // Recorded holidays count as known days, so they are not fetched again
+        record_holidays(&pool, "EUR/USD", &[date("2025-01-08")]).await?;
+        record_holidays(&pool, "EUR/USD", &[date("2025-01-08")]).await?;
+        let known = known_rate_days(&pool, date("2025-01-06"), date("2025-01-10")).await?;
+        assert_eq!(
+            missing_days("EUR/USD", &days, &known),
+            vec![date("2025-01-10")]
+        );
+        assert_eq!(missing_days("GBP/USD", &days, &known).len(), 5);
+        Ok(())
     }
 }
@@ -0,0 +1,529 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Small expression language shared by custom KPIs and the screener
+//!
+//! Expressions combine numbers (`10e9`, `0.5`), quoted text (`'EUR'` or
+//! `"EUR"`) and company fields with `+ - * /`, the comparisons
+//! `== != < <= > >=`, `&&`, `||`, `!` and parentheses. Fields are typed, so
+//! `currency > 5` or `market_cap && pe_ratio` are rejected when parsing
+//! rather than silently matching nothing.
+//!
+//! A missing field makes the result missing: arithmetic and comparisons on it
+//! have no value, `&&` is false when either side is false and `||` is true
+//! when either side is true, otherwise missing too.
+
+use std::collections::HashMap;
+use std::fmt;
+
+/// Type of a field or expression
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Number,
+    Text,
+    Bool,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Kind::Number => "number",
+            Kind::Text => "text",
+            Kind::Bool => "true/false",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(f64),
+    Text(String),
+    Bool(bool),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+impl Op {
+    fn symbol(self) -> &'static str {
+        match self {
+            Op::Add => "+",
+            Op::Sub => "-",
+            Op::Mul => "*",
+            Op::Div => "/",
+            Op::Eq => "==",
+            Op::Ne => "!=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+            Op::And => "&&",
+            Op::Or => "||",
+        }
+    }
+}
+
+/// A parsed, type-checked expression
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    node: Node,
+    kind: Kind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Literal(Value),
+    Field(String),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+}
+
+/// Field values of one company; missing fields are left out
+#[derive(Debug, Clone, Default)]
+pub struct Inputs(HashMap<String, Value>);
+
+impl Inputs {
+    pub fn set_number(&mut self, field: &str, value: Option<f64>) -> &mut Self {
+        if let Some(value) = value.filter(|v| v.is_finite()) {
+            self.0.insert(field.to_string(), Value::Number(value));
+        }
+        self
+    }
+
+    pub fn set_text(&mut self, field: &str, value: Option<&str>) -> &mut Self {
+        if let Some(value) = value.filter(|v| !v.is_empty()) {
+            self.0
+                .insert(field.to_string(), Value::Text(value.to_string()));
+        }
+        self
+    }
+
+    pub fn get(&self, field: &str) -> Option<&Value> {
+        self.0.get(field)
+    }
+}
+
+impl Expr {
+    pub fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    pub fn evaluate(&self, inputs: &Inputs) -> Option<Value> {
+        match &self.node {
+            Node::Literal(value) => Some(value.clone()),
+            Node::Field(name) => inputs.get(name).cloned(),
+            Node::Neg(inner) => Some(Value::Number(-inner.number(inputs)?)),
+            Node::Not(inner) => Some(Value::Bool(!inner.boolean(inputs)?)),
+            Node::Binary(Op::And, left, right) => {
+                match (left.boolean(inputs), right.boolean(inputs)) {
+                    (Some(false), _) | (_, Some(false)) => Some(Value::Bool(false)),
+                    (Some(true), Some(true)) => Some(Value::Bool(true)),
+                    _ => None,
+                }
+            }
+            Node::Binary(Op::Or, left, right) => {
+                match (left.boolean(inputs), right.boolean(inputs)) {
+                    (Some(true), _) | (_, Some(true)) => Some(Value::Bool(true)),
+                    (Some(false), Some(false)) => Some(Value::Bool(false)),
+                    _ => None,
+                }
+            }
+            Node::Binary(op, left, right) => {
+                let (l, r) = (left.evaluate(inputs)?, right.evaluate(inputs)?);
+                let value = match (op, &l, &r) {
+                    (Op::Eq, _, _) => Value::Bool(l == r),
+                    (Op::Ne, _, _) => Value::Bool(l != r),
+                    (_, Value::Number(l), Value::Number(r)) => match op {
+                        Op::Add => Value::Number(l + r),
+                        Op::Sub => Value::Number(l - r),
+                        Op::Mul => Value::Number(l * r),
+                        Op::Div if *r == 0.0 => return None,
+                        Op::Div => Value::Number(l / r),
+                        Op::Lt => Value::Bool(l < r),
+                        Op::Le => Value::Bool(l <= r),
+                        Op::Gt => Value::Bool(l > r),
+                        Op::Ge => Value::Bool(l >= r),
+                        _ => return None,
+                    },
+                    _ => return None,
+                };
+                match value {
+                    Value::Number(n) if !n.is_finite() => None,
+                    value => Some(value),
+                }
+            }
+        }
+    }
+
+    /// Fields the expression reads, in order of first use
+    pub fn fields(&self) -> Vec<&str> {
+        let mut fields = Vec::new();
+        self.collect_fields(&mut fields);
+        fields
+    }
+
+    fn collect_fields<'a>(&'a self, fields: &mut Vec<&'a str>) {
+        match &self.node {
+            Node::Literal(_) => {}
+            Node::Field(name) => {
+                if !fields.contains(&name.as_str()) {
+                    fields.push(name);
+                }
+            }
+            Node::Neg(inner) | Node::Not(inner) => inner.collect_fields(fields),
+            Node::Binary(_, left, right) => {
+                left.collect_fields(fields);
+                right.collect_fields(fields);
+            }
+        }
+    }
+
+    /// Value of a number expression
+    pub fn number(&self, inputs: &Inputs) -> Option<f64> {
+        match self.evaluate(inputs)? {
+            Value::Number(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    /// Value of a condition; missing when a field it needs is missing
+    pub fn boolean(&self, inputs: &Inputs) -> Option<bool> {
+        match self.evaluate(inputs)? {
+            Value::Bool(b) => Some(b),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Text(String),
+    Ident(String),
+    Op(&'static str),
+    Open,
+    Close,
+}
+
+const OPERATORS: [&str; 15] = [
+    "==", "!=", "<=", ">=", "&&", "||", "<", ">", "+", "-", "*", "/", "!", "(", ")",
+];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = source.trim_start();
+    while !rest.is_empty() {
+        let c = rest.chars().next().unwrap_or_default();
+        if c.is_ascii_digit() || c == '.' {
+            // Digits, then an optional exponent like e9 or e-3
+            let mut end = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .unwrap_or(rest.len());
+            if rest[end..].starts_with(['e', 'E']) {
+                let exponent = &rest[end + 1..];
+                let sign = usize::from(exponent.starts_with(['+', '-']));
+                let digits = exponent[sign..]
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(exponent.len() - sign);
+                if digits > 0 {
+                    end += 1 + sign + digits;
+                }
+            }
+            let number = &rest[..end];
+            let value = number
+                .parse()
+                .map_err(|_| format!("invalid number {:?}", number))?;
+            tokens.push(Token::Number(value));
+            rest = &rest[end..];
+        } else if c == '\'' || c == '"' {
+            let close = rest[1..]
+                .find(c)
+                .ok_or_else(|| "missing closing quote".to_string())?;
+            tokens.push(Token::Text(rest[1..=close].to_string()));
+            rest = &rest[close + 2..];
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            tokens.push(match &rest[..end] {
+                "true" => Token::Op("true"),
+                "false" => Token::Op("false"),
+                ident => Token::Ident(ident.to_string()),
+            });
+            rest = &rest[end..];
+        } else {
+            let op = OPERATORS
+                .iter()
+                .find(|op| rest.starts_with(**op))
+                .ok_or_else(|| format!("unexpected character {:?}", c))?;
+            tokens.push(match *op {
+                "(" => Token::Open,
+                ")" => Token::Close,
+                op => Token::Op(op),
+            });
+            rest = &rest[op.len()..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent, loosest binding first: `||`, `&&`, comparisons,
+/// `+ -`, `* /`, then unary `- !`, literals, fields and parentheses
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    position: usize,
+    fields: &'a dyn Fn(&str) -> Option<Kind>,
+}
+
+type Level = [(&'static str, Op)];
+
+const OR: &Level = &[("||", Op::Or)];
+const AND: &Level = &[("&&", Op::And)];
+const COMPARISON: &Level = &[
+    ("==", Op::Eq),
+    ("!=", Op::Ne),
+    ("<", Op::Lt),
+    ("<=", Op::Le),
+    (">", Op::Gt),
+    (">=", Op::Ge),
+];
+const SUM: &Level = &[("+", Op::Add), ("-", Op::Sub)];
+const PRODUCT: &Level = &[("*", Op::Mul), ("/", Op::Div)];
+const LEVELS: [&Level; 5] = [OR, AND, COMPARISON, SUM, PRODUCT];
+
+fn binary(op: Op, left: Expr, right: Expr) -> Result<Expr, String> {
+    let mismatch = || {
+        format!(
+            "{} {} {} is not allowed",
+            left.kind,
+            op.symbol(),
+            right.kind
+        )
+    };
+    let kind = match op {
+        Op::Add | Op::Sub | Op::Mul | Op::Div => {
+            if left.kind != Kind::Number || right.kind != Kind::Number {
+                return Err(mismatch());
+            }
+            Kind::Number
+        }
+        Op::Eq | Op::Ne => {
+            if left.kind != right.kind {
+                return Err(mismatch());
+            }
+            Kind::Bool
+        }
+        Op::Lt | Op::Le | Op::Gt | Op::Ge => {
+            if left.kind != Kind::Number || right.kind != Kind::Number {
+                return Err(mismatch());
+            }
+            Kind::Bool
+        }
+        Op::And | Op::Or => {
+            if left.kind != Kind::Bool || right.kind != Kind::Bool {
+                return Err(mismatch());
+            }
+            Kind::Bool
+        }
+    };
+    Ok(Expr {
+        node: Node::Binary(op, Box::new(left), Box::new(right)),
+        kind,
+    })
+}
+
+impl Parser<'_> {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn level(&mut self, depth: usize) -> Result<Expr, String> {
+        let Some(ops) = LEVELS.get(depth) else {
+            return self.unary();
+        };
+        let mut left = self.level(depth + 1)?;
+        while let Some(Token::Op(symbol)) = self.tokens.get(self.position) {
+            let Some((_, op)) = ops.iter().find(|(s, _)| s == symbol) else {
+                break;
+            };
+            self.position += 1;
+            let right = self.level(depth + 1)?;
+            left = binary(*op, left, right)?;
+            // Comparisons don't chain: `a < b < c` is an error
+            if matches!(op, Op::Eq | Op::Ne | Op::Lt | Op::Le | Op::Gt | Op::Ge) {
+                break;
+            }
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        let literal = |value: Value, kind| Expr {
+            node: Node::Literal(value),
+            kind,
+        };
+        match self.next() {
+            Some(Token::Op("-")) => {
+                let inner = self.unary()?;
+                if inner.kind != Kind::Number {
+                    return Err(format!("- needs a number, not {}", inner.kind));
+                }
+                Ok(Expr {
+                    node: Node::Neg(Box::new(inner)),
+                    kind: Kind::Number,
+                })
+            }
+            Some(Token::Op("!")) => {
+                let inner = self.unary()?;
+                if inner.kind != Kind::Bool {
+                    return Err(format!("! needs a condition, not {}", inner.kind));
+                }
+                Ok(Expr {
+                    node: Node::Not(Box::new(inner)),
+                    kind: Kind::Bool,
+                })
+            }
+            Some(Token::Op("true")) => Ok(literal(Value::Bool(true), Kind::Bool)),
+            Some(Token::Op("false")) => Ok(literal(Value::Bool(false), Kind::Bool)),
+            Some(Token::Number(n)) => Ok(literal(Value::Number(n), Kind::Number)),
+            Some(Token::Text(s)) => Ok(literal(Value::Text(s), Kind::Text)),
+            Some(Token::Ident(name)) => match (self.fields)(&name) {
+                Some(kind) => Ok(Expr {
+                    node: Node::Field(name),
+                    kind,
+                }),
+                None => Err(format!("unknown field {:?}", name)),
+            },
+            Some(Token::Open) => {
+                let inner = self.level(0)?;
+                match self.next() {
+                    Some(Token::Close) => Ok(inner),
+                    _ => Err("missing closing parenthesis".to_string()),
+                }
+            }
+            Some(Token::Op(op)) => Err(format!("unexpected {}", op)),
+            Some(Token::Close) => Err("unexpected )".to_string()),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+}
+
+/// Parse an expression over the fields `fields` knows the type of
+pub fn parse(source: &str, fields: &dyn Fn(&str) -> Option<Kind>) -> Result<Expr, String> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        position: 0,
+        fields,
+    };
+    let expr = parser.level(0)?;
+    match parser.tokens.get(parser.position) {
+        None => Ok(expr),
+        Some(Token::Ident(name)) => Err(format!("unexpected {}", name)),
+        Some(Token::Number(n)) => Err(format!("unexpected {}", n)),
+        Some(Token::Text(s)) => Err(format!("unexpected {:?}", s)),
+        Some(Token::Op(op)) => Err(format!("unexpected {}", op)),
+        Some(Token::Open) => Err("unexpected (".to_string()),
+        Some(Token::Close) => Err("unexpected )".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(name: &str) -> Option<Kind> {
+        match name {
+            "market_cap_usd" | "pe_ratio" | "revenue_usd" => Some(Kind::Number),
+            "currency" | "country" => Some(Kind::Text),
+            _ => None,
+        }
+    }
+
+    fn inputs() -> Inputs {
+        let mut inputs = Inputs::default();
+        inputs
+            .set_number("market_cap_usd", Some(12e9))
+            .set_number("pe_ratio", Some(18.0))
+            .set_number("revenue_usd", None)
+            .set_text("currency", Some("EUR"));
+        inputs
+    }
+
+    fn eval(source: &str) -> Option<Value> {
+        parse(source, &fields).unwrap().evaluate(&inputs())
+    }
+
+    #[test]
+    fn test_arithmetic_and_exponents() {
+        assert_eq!(
+            eval("market_cap_usd / 1e9 - 2 * 3"),
+            Some(Value::Number(6.0))
+        );
+        assert_eq!(eval("-(1.5e-1 + 10E1)"), Some(Value::Number(-100.15)));
+        assert_eq!(eval("10 - 4 - 3"), Some(Value::Number(3.0)));
+        assert_eq!(eval("market_cap_usd / (pe_ratio - 18)"), None);
+    }
+
+    #[test]
+    fn test_conditions() {
+        let yes = Some(Value::Bool(true));
+        let no = Some(Value::Bool(false));
+        assert_eq!(
+            eval("market_cap_usd > 10e9 && pe_ratio < 20 && currency == 'EUR'"),
+            yes
+        );
+        assert_eq!(eval("currency != \"EUR\" || pe_ratio >= 18"), yes);
+        assert_eq!(eval("!(pe_ratio <= 18)"), no);
+        // A missing field is neither true nor false, unless the other side decides
+        assert_eq!(eval("revenue_usd > 0"), None);
+        assert_eq!(eval("revenue_usd > 0 && pe_ratio > 100"), no);
+        assert_eq!(eval("revenue_usd > 0 || pe_ratio > 1"), yes);
+        assert_eq!(eval("revenue_usd > 0 && pe_ratio > 1"), None);
+        assert_eq!(eval("country == 'FR'"), None);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |source: &str| parse(source, &fields).unwrap_err();
+        assert_eq!(error("sales > 1"), "unknown field \"sales\"");
+        assert_eq!(error("currency > 5"), "text > number is not allowed");
+        assert_eq!(
+            error("pe_ratio && true"),
+            "number && true/false is not allowed"
+        );
+        assert_eq!(error("1 < pe_ratio < 3"), "unexpected <");
+        assert_eq!(error("(pe_ratio"), "missing closing parenthesis");
+        assert_eq!(error("currency == 'EUR"), "missing closing quote");
+        assert_eq!(error("pe_ratio % 2"), "unexpected character '%'");
+        assert_eq!(error("pe_ratio 2"), "unexpected 2");
+        assert_eq!(error(""), "unexpected end of expression");
+        assert_eq!(parse("pe_ratio > 1", &fields).unwrap().kind(), Kind::Bool);
+        assert_eq!(
+            parse(
+                "pe_ratio > 1 && (currency == 'EUR' || pe_ratio < 0)",
+                &fields
+            )
+            .unwrap()
+            .fields(),
+            vec!["pe_ratio", "currency"]
+        );
+    }
+}
@@ -0,0 +1,199 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Failover across several FMP API keys
+//!
+//! `FMP_API_KEYS=key1,key2` lists keys with separate quotas. `FMPClient` sends
+//! every request with the active key and, when FMP answers "Limit Reach",
+//! switches to the next key that has not been rejected for that request before
+//! backing off. Requests and rate-limit hits are counted per key (see
+//! `api_usage::record_key_request`), identified by the key's last characters.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Environment variable with the comma-separated FMP keys
+pub const KEYS_ENV: &str = "FMP_API_KEYS";
+
+/// Environment variable with the single FMP key
+pub const PRIMARY_ENV: &str = "FINANCIALMODELINGPREP_API_KEY";
+
+/// Key clients are created with: `FINANCIALMODELINGPREP_API_KEY`, or the
+/// first of `FMP_API_KEYS` when only those are set
+pub fn primary_key() -> Option<String> {
+    primary_from(
+        std::env::var(PRIMARY_ENV).ok().as_deref(),
+        std::env::var(KEYS_ENV).ok().as_deref(),
+    )
+}
+
+fn primary_from(primary: Option<&str>, keys_env: Option<&str>) -> Option<String> {
+    primary
+        .map(str::trim)
+        .filter(|k| !k.is_empty())
+        .or_else(|| keys_env?.split(',').map(str::trim).find(|k| !k.is_empty()))
+        .map(str::to_string)
+}
+
+/// Keys of one client; the active one is shared by its clones
+#[derive(Debug)]
+pub struct FmpKeys {
+    keys: Vec<String>,
+    active: AtomicUsize,
+}
+
+impl FmpKeys {
+    /// The keys of `FMP_API_KEYS` followed by `primary` when it is not among them
+    pub fn new(primary: String, keys_env: Option<&str>) -> Self {
+        let mut keys: Vec<String> = Vec::new();
+        for key in keys_env
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|k| !k.is_empty())
+            .chain(std::iter::once(primary.as_str()))
+        {
+            if !key.is_empty() && !keys.iter().any(|k| k == key) {
+                keys.push(key.to_string());
+            }
+        }
+        if keys.is_empty() {
+            keys.push(primary);
+        }
+        Self {
+            keys,
+            active: AtomicUsize::new(0),
+        }
+    }
+
+    /// Keys from `FMP_API_KEYS`, with `primary` as the last fallback
+    pub fn from_env(primary: String) -> Self {
+        Self::new(primary, std::env::var(KEYS_ENV).ok().as_deref())
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Index of the key to send the next request with
+    pub fn active(&self) -> usize {
+        self.active.load(Ordering::Relaxed) % self.keys.len()
+    }
+
+    pub fn key(&self, index: usize) -> &str {
+        &self.keys[index]
+    }
+
+    /// Switch away from the rejected key to the next one not in `tried`.
+    /// Returns the new active key, or `None` when every key was tried.
+    pub fn rotate(&self, rejected: usize, tried: &[usize]) -> Option<usize> {
+        let next = (1..self.keys.len())
+            .map(|offset| (rejected + offset) % self.keys.len())
+            .find(|index| !tried.contains(index))?;
+        // Another request may have rotated already; only move off the rejected key
+        let _ = self
+            .active
+            .compare_exchange(rejected, next, Ordering::Relaxed, Ordering::Relaxed);
+        Some(next)
+    }
+}
+
+/// Key identifier for logs and usage: its last four characters
+pub fn key_label(key: &str) -> String {
+    let chars: Vec<char> = key.chars().collect();
+    let tail: String = chars[chars.len().saturating_sub(4)..].iter().collect();
+    format!("…{}", tail)
+}
+
+/// `url` with its `apikey` query parameter set to `key`
+pub fn with_api_key(url: &str, key: &str) -> String {
+    let Some((base, query)) = url.split_once('?') else {
+        return format!("{}?apikey={}", url, key);
+    };
+    let mut replaced = false;
+    let params: Vec<String> = query
+        .split('&')
+        .map(|param| {
+            if param.starts_with("apikey=") {
+                replaced = true;
+                format!("apikey={}", key)
+            } else {
+                param.to_string()
+            }
+        })
+        .collect();
+    if replaced {
+        format!("{}?{}", base, params.join("&"))
+    } else {
+        format!("{}?{}&apikey={}", base, query, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_from_env() {
+        let keys = FmpKeys::new("primary".to_string(), Some("one, two,,primary"));
+        assert_eq!(keys.len(), 3);
+        assert_eq!(keys.key(0), "one");
+        assert_eq!(keys.key(1), "two");
+        assert_eq!(keys.key(2), "primary");
+
+        let single = FmpKeys::new("primary".to_string(), None);
+        assert_eq!(single.len(), 1);
+        assert_eq!(single.rotate(0, &[0]), None);
+    }
+
+    #[test]
+    fn test_primary_key_falls_back_to_the_key_list() {
+        assert_eq!(
+            primary_from(Some("primary"), Some("one,two")).as_deref(),
+            Some("primary")
+        );
+        assert_eq!(
+            primary_from(None, Some(" ,one,two")).as_deref(),
+            Some("one")
+        );
+        assert_eq!(primary_from(Some(""), Some("one")).as_deref(), Some("one"));
+        assert_eq!(primary_from(None, Some(",")), None);
+        assert_eq!(primary_from(None, None), None);
+    }
+
+    #[test]
+    fn test_rotate_skips_tried_keys() {
+        let keys = FmpKeys::new("c".to_string(), Some("a,b"));
+        assert_eq!(keys.active(), 0);
+        assert_eq!(keys.rotate(0, &[0]), Some(1));
+        assert_eq!(keys.active(), 1);
+        // A stale rejection of key 0 does not move the active key back
+        assert_eq!(keys.rotate(0, &[0, 1]), Some(2));
+        assert_eq!(keys.active(), 1);
+        assert_eq!(keys.rotate(1, &[0, 1]), Some(2));
+        assert_eq!(keys.active(), 2);
+        assert_eq!(keys.rotate(2, &[0, 1, 2]), None);
+    }
+
+    #[test]
+    fn test_with_api_key() {
+        assert_eq!(
+            with_api_key("https://x/api/v3/quote/NKE?apikey=old", "new"),
+            "https://x/api/v3/quote/NKE?apikey=new"
+        );
+        assert_eq!(
+            with_api_key("https://x/h?from=1&apikey=old&to=2", "new"),
+            "https://x/h?from=1&apikey=new&to=2"
+        );
+        assert_eq!(
+            with_api_key("https://x/a?limit=1", "k"),
+            "https://x/a?limit=1&apikey=k"
+        );
+        assert_eq!(key_label("abcdef123456"), "…3456");
+        assert_eq!(key_label("ab"), "…ab");
+    }
+}
@@ -0,0 +1,161 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! European Central Bank daily euro reference rates (no API key required)
+
+use super::{ForexProvider, ForexQuote};
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use reqwest::Client;
+
+const DAILY_URL: &str = "https://www.ecb.europa.eu/stats/eurofxref/eurofxref-daily.xml";
+const HISTORY_90D_URL: &str = "https://www.ecb.europa.eu/stats/eurofxref/eurofxref-hist-90d.xml";
+const HISTORY_URL: &str = "https://www.ecb.europa.eu/stats/eurofxref/eurofxref-hist.xml";
+
+pub struct EcbProvider {
+    client: Client,
+}
+
+impl Default for EcbProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EcbProvider {
+    pub fn new() -> Self {
+        Self {
+            client: crate::api::http_client(),
+        }
+    }
+
+    async fn fetch(&self, url: &str) -> Result<String> {
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .context("Failed to send request to ECB")?;
+
+        if !response.status().is_success() {
+            anyhow::bail!("ECB request failed with status: {}", response.status());
+        }
+
+        response.text().await.context("Failed to read ECB response")
+    }
+}
+
+/// Value of `name='...'` (or `name="..."`) inside a single XML tag
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    for quote in ['\'', '"'] {
+        let needle = format!("{}={}", name, quote);
+        if let Some(start) = tag.find(&needle) {
+            let rest = &tag[start + needle.len()..];
+            return rest.find(quote).map(|end| &rest[..end]);
+        }
+    }
+    None
+}
+
+/// Parse the ECB `eurofxref` XML into `(date, currency, rate)` entries.
+/// Rates are quoted as 1 EUR = `rate` units of the currency.
+pub fn parse_reference_rates(xml: &str) -> Vec<(NaiveDate, String, f64)> {
+    let mut rates = Vec::new();
+    let mut current_date = None;
+
+    for tag in xml.split("<Cube").skip(1) {
+        let tag = tag.split('>').next().unwrap_or_default();
+        if let Some(time) = attribute(tag, "time") {
+            current_date = NaiveDate::parse_from_str(time, "%Y-%m-%d").ok();
+        } else if let (Some(date), Some(currency), Some(rate)) = (
+            current_date,
+            attribute(tag, "currency"),
+            attribute(tag, "rate").and_then(|r| r.parse::<f64>().ok()),
+        ) {
+            rates.push((date, currency.to_string(), rate));
+        }
+    }
+
+    rates
+}
+
+fn to_quote(currency: &str, rate: f64, timestamp: i64) -> ForexQuote {
+    ForexQuote {
+        symbol: format!("EUR/{}", currency),
+        rate,
+        timestamp,
+    }
+}
+
+impl ForexProvider for EcbProvider {
+    async fn latest_rates(&self, timestamp: i64) -> Result<Vec<ForexQuote>> {
+        let xml = self.fetch(DAILY_URL).await?;
+        Ok(parse_reference_rates(&xml)
+            .into_iter()
+            .map(|(_, currency, rate)| to_quote(&currency, rate, timestamp))
+            .collect())
+    }
+
+    async fn historical_rates(&self, from: NaiveDate, to: NaiveDate) -> Result<Vec<ForexQuote>> {
+        // The 90-day file is much smaller; only fall back to the full history when needed
+        let recent = chrono::Utc::now().date_naive() - chrono::Duration::days(90);
+        let url = if from >= recent {
+            HISTORY_90D_URL
+        } else {
+            HISTORY_URL
+        };
+        let xml = self.fetch(url).await?;
+
+        Ok(parse_reference_rates(&xml)
+            .into_iter()
+            .filter(|(date, _, _)| *date >= from && *date <= to)
+            .map(|(date, currency, rate)| {
+                let timestamp = date.and_time(chrono::NaiveTime::MIN).and_utc().timestamp();
+                to_quote(&currency, rate, timestamp)
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gesmes:Envelope xmlns:gesmes="http://www.gesmes.org/xml/2002-08-01" xmlns="http://www.ecb.int/vocabulary/2002-08-01/eurofxref">
+	<gesmes:subject>Reference rates</gesmes:subject>
+	<Cube>
+		<Cube time='2025-01-09'>
+			<Cube currency='USD' rate='1.0314'/>
+			<Cube currency='KRW' rate='1510.48'/>
+		</Cube>
+		<Cube time="2025-01-08">
+			<Cube currency="USD" rate="1.0321"/>
+		</Cube>
+	</Cube>
+</gesmes:Envelope>"#;
+
+    #[test]
+    fn test_parse_reference_rates() {
+        let rates = parse_reference_rates(SAMPLE);
+        let jan9 = NaiveDate::from_ymd_opt(2025, 1, 9).unwrap();
+        let jan8 = NaiveDate::from_ymd_opt(2025, 1, 8).unwrap();
+
+        assert_eq!(
+            rates,
+            vec![
+                (jan9, "USD".to_string(), 1.0314),
+                (jan9, "KRW".to_string(), 1510.48),
+                (jan8, "USD".to_string(), 1.0321),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_quotes_are_eur_based() {
+        let quote = to_quote("ILS", 3.78, 0);
+        assert_eq!(quote.symbol, "EUR/ILS");
+        assert_eq!(quote.rate, 3.78);
+    }
+}
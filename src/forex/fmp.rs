@@ -0,0 +1,174 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Financial Modeling Prep forex quotes
+
+use super::{ForexProvider, ForexQuote};
+use crate::api::FMPClient;
+use crate::progress;
+use anyhow::Result;
+use chrono::{NaiveDate, NaiveTime};
+
+/// Currency pairs commonly needed for market cap conversions
+const COMMON_FOREX_PAIRS: &[&str] = &[
+    "EURUSD", "GBPUSD", "JPYUSD", "CHFUSD", "SEKUSD", "DKKUSD", "NOKUSD", "HKDUSD", "CNYUSD",
+    "BRLUSD", "CADUSD", "ILSUSD", "ZARUSD", "INRUSD", "KRWUSD", "TRYUSD", "PLNUSD", "TWDUSD",
+];
+
+/// The common pairs as stored in `forex_rates` (`EUR/USD`)
+pub fn common_pairs() -> Vec<String> {
+    COMMON_FOREX_PAIRS
+        .iter()
+        .map(|p| format_pair_with_slash(p))
+        .collect()
+}
+
+pub struct FmpForexProvider<'a> {
+    client: &'a FMPClient,
+}
+
+impl<'a> FmpForexProvider<'a> {
+    pub fn new(client: &'a FMPClient) -> Self {
+        Self { client }
+    }
+
+    /// Daily closes of one pair (`EURUSD` or `EUR/USD`) in the range
+    /// (inclusive), stamped at midnight UTC
+    pub async fn pair_rates(
+        &self,
+        pair: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<ForexQuote>> {
+        let response = self
+            .client
+            .get_historical_exchange_rates(
+                &pair.replace('/', ""),
+                &from.format("%Y-%m-%d").to_string(),
+                &to.format("%Y-%m-%d").to_string(),
+            )
+            .await?;
+        let symbol_with_slash = format_pair_with_slash(&response.symbol);
+        Ok(response
+            .historical
+            .iter()
+            .filter_map(|data| {
+                // Use close price as the rate (most commonly used)
+                let date = NaiveDate::parse_from_str(&data.date, "%Y-%m-%d").ok()?;
+                Some(ForexQuote {
+                    symbol: symbol_with_slash.clone(),
+                    rate: data.close,
+                    timestamp: date.and_time(NaiveTime::MIN).and_utc().timestamp(),
+                })
+            })
+            .collect())
+    }
+}
+
+impl ForexProvider for FmpForexProvider<'_> {
+    async fn latest_rates(&self, timestamp: i64) -> Result<Vec<ForexQuote>> {
+        let exchange_rates = self.client.get_exchange_rates().await?;
+        Ok(exchange_rates
+            .into_iter()
+            .filter_map(|rate| match (rate.name, rate.price) {
+                (Some(symbol), Some(price)) => Some(ForexQuote {
+                    symbol,
+                    rate: price,
+                    timestamp,
+                }),
+                _ => None,
+            })
+            .collect())
+    }
+
+    async fn historical_rates(&self, from: NaiveDate, to: NaiveDate) -> Result<Vec<ForexQuote>> {
+        // Get available forex pairs to validate
+        println!("Fetching available forex pairs...");
+        let available_pairs = match self.client.get_available_forex_pairs().await {
+            Ok(pairs) => {
+                println!("✅ Found {} available forex pairs", pairs.len());
+                pairs
+            }
+            Err(e) => {
+                eprintln!(
+                    "⚠️  Could not fetch available pairs, using common pairs: {}",
+                    e
+                );
+                COMMON_FOREX_PAIRS.iter().map(|s| s.to_string()).collect()
+            }
+        };
+
+        // Filter to common pairs that are available
+        let pairs_to_fetch: Vec<&str> = COMMON_FOREX_PAIRS
+            .iter()
+            .filter(|p| available_pairs.iter().any(|ap| ap == *p))
+            .copied()
+            .collect();
+
+        if pairs_to_fetch.is_empty() {
+            println!("Using all common forex pairs...");
+        } else {
+            println!("Fetching {} currency pairs...", pairs_to_fetch.len());
+        }
+
+        let pairs = if pairs_to_fetch.is_empty() {
+            COMMON_FOREX_PAIRS.to_vec()
+        } else {
+            pairs_to_fetch
+        };
+
+        // Set up progress bar
+        let progress = progress::fetch_bar(pairs.len() as u64, "pairs", 1);
+
+        let mut quotes = Vec::new();
+        let mut failed_pairs = Vec::new();
+
+        for pair in &pairs {
+            progress.set_message(format!("Fetching {}...", pair));
+
+            match self.pair_rates(pair, from, to).await {
+                Ok(pair_quotes) => quotes.extend(pair_quotes),
+                Err(e) => {
+                    failed_pairs.push((pair.to_string(), e.to_string()));
+                }
+            }
+
+            progress.inc(1);
+        }
+
+        progress.finish_with_message("Done");
+
+        println!("   Pairs processed: {}", pairs.len() - failed_pairs.len());
+        if !failed_pairs.is_empty() {
+            println!("\n⚠️  Failed to fetch {} pairs:", failed_pairs.len());
+            for (pair, error) in &failed_pairs {
+                println!("   {} - {}", pair, error);
+            }
+        }
+
+        Ok(quotes)
+    }
+}
+
+/// Convert a pair like "EURUSD" to "EUR/USD"
+fn format_pair_with_slash(pair: &str) -> String {
+    if pair.len() == 6 && !pair.contains('/') {
+        format!("{}/{}", &pair[0..3], &pair[3..6])
+    } else {
+        pair.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_pair_with_slash() {
+        assert_eq!(format_pair_with_slash("EURUSD"), "EUR/USD");
+        assert_eq!(format_pair_with_slash("GBPUSD"), "GBP/USD");
+        assert_eq!(format_pair_with_slash("EUR/USD"), "EUR/USD");
+        assert_eq!(format_pair_with_slash("JPYUSD"), "JPY/USD");
+    }
+}
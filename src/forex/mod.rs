@@ -0,0 +1,185 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Exchange rate providers
+//!
+//! Each provider returns quotes as `FROM/TO` symbols; quotes from several
+//! providers are merged according to the `[forex]` config before storage.
+
+pub mod ecb;
+pub mod fmp;
+
+use crate::api::FMPClient;
+use crate::config::ForexSource;
+use anyhow::Result;
+use chrono::NaiveDate;
+use std::collections::{BTreeMap, HashSet};
+
+pub use ecb::EcbProvider;
+pub use fmp::FmpForexProvider;
+
+/// A single exchange rate: 1 unit of the first currency in `symbol` buys `rate`
+/// units of the second
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForexQuote {
+    /// Pair such as `EUR/USD`
+    pub symbol: String,
+    pub rate: f64,
+    pub timestamp: i64,
+}
+
+impl ForexQuote {
+    fn currencies(&self) -> impl Iterator<Item = &str> {
+        self.symbol.split('/')
+    }
+}
+
+/// A source of exchange rates
+// Only awaited from this crate's own tasks, so no `Send` bound is needed
+#[allow(async_fn_in_trait)]
+pub trait ForexProvider {
+    /// Current rates, stamped with `timestamp`
+    async fn latest_rates(&self, timestamp: i64) -> Result<Vec<ForexQuote>>;
+
+    /// Daily rates for every date in the range (inclusive), stamped at midnight UTC
+    async fn historical_rates(&self, from: NaiveDate, to: NaiveDate) -> Result<Vec<ForexQuote>>;
+}
+
+/// Fetch current rates from a configured source
+pub async fn fetch_latest(
+    source: ForexSource,
+    fmp_client: &FMPClient,
+    timestamp: i64,
+) -> Result<Vec<ForexQuote>> {
+    match source {
+        ForexSource::Fmp => {
+            FmpForexProvider::new(fmp_client)
+                .latest_rates(timestamp)
+                .await
+        }
+        ForexSource::Ecb => EcbProvider::new().latest_rates(timestamp).await,
+    }
+}
+
+/// Fetch historical rates from a configured source
+pub async fn fetch_historical(
+    source: ForexSource,
+    fmp_client: &FMPClient,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<ForexQuote>> {
+    match source {
+        ForexSource::Fmp => {
+            FmpForexProvider::new(fmp_client)
+                .historical_rates(from, to)
+                .await
+        }
+        ForexSource::Ecb => EcbProvider::new().historical_rates(from, to).await,
+    }
+}
+
+/// Merge quotes from several sources, given in priority order.
+///
+/// For a currency listed in `prefer`, quotes involving it are only taken from the
+/// preferred source (as long as that source supplied the currency at all).
+/// Otherwise the first source providing a symbol for a timestamp wins.
+pub fn merge_quotes(
+    sources: Vec<(ForexSource, Vec<ForexQuote>)>,
+    prefer: &BTreeMap<String, ForexSource>,
+) -> Vec<ForexQuote> {
+    // Preferred currencies the preferred source actually covers
+    let covered: HashSet<&str> = prefer
+        .iter()
+        .filter(|(currency, preferred)| {
+            sources.iter().any(|(source, quotes)| {
+                source == *preferred
+                    && quotes
+                        .iter()
+                        .any(|q| q.currencies().any(|c| c == currency.as_str()))
+            })
+        })
+        .map(|(currency, _)| currency.as_str())
+        .collect();
+
+    let mut seen = HashSet::new();
+    let mut merged = Vec::new();
+    for (source, quotes) in &sources {
+        for quote in quotes {
+            let overridden = quote.currencies().any(|c| {
+                covered.contains(c) && prefer.get(c).is_some_and(|preferred| preferred != source)
+            });
+            if overridden {
+                continue;
+            }
+            if seen.insert((quote.symbol.clone(), quote.timestamp)) {
+                merged.push(quote.clone());
+            }
+        }
+    }
+
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(symbol: &str, rate: f64) -> ForexQuote {
+        ForexQuote {
+            symbol: symbol.to_string(),
+            rate,
+            timestamp: 1_736_380_800,
+        }
+    }
+
+    #[test]
+    fn test_merge_first_source_wins() {
+        let merged = merge_quotes(
+            vec![
+                (ForexSource::Fmp, vec![quote("EUR/USD", 1.03)]),
+                (
+                    ForexSource::Ecb,
+                    vec![quote("EUR/USD", 1.04), quote("EUR/KRW", 1500.0)],
+                ),
+            ],
+            &BTreeMap::new(),
+        );
+
+        assert_eq!(
+            merged,
+            vec![quote("EUR/USD", 1.03), quote("EUR/KRW", 1500.0)]
+        );
+    }
+
+    #[test]
+    fn test_merge_prefers_source_per_currency() {
+        let prefer = BTreeMap::from([("ILS".to_string(), ForexSource::Ecb)]);
+        let merged = merge_quotes(
+            vec![
+                (
+                    ForexSource::Fmp,
+                    vec![quote("EUR/USD", 1.03), quote("USD/ILS", 3.7)],
+                ),
+                (ForexSource::Ecb, vec![quote("EUR/ILS", 3.8)]),
+            ],
+            &prefer,
+        );
+
+        assert_eq!(merged, vec![quote("EUR/USD", 1.03), quote("EUR/ILS", 3.8)]);
+    }
+
+    #[test]
+    fn test_merge_keeps_fallback_when_preferred_source_lacks_currency() {
+        let prefer = BTreeMap::from([("ILS".to_string(), ForexSource::Ecb)]);
+        let merged = merge_quotes(
+            vec![
+                (ForexSource::Fmp, vec![quote("USD/ILS", 3.7)]),
+                (ForexSource::Ecb, vec![]),
+            ],
+            &prefer,
+        );
+
+        assert_eq!(merged, vec![quote("USD/ILS", 3.7)]);
+    }
+}
@@ -0,0 +1,331 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Market cap by headquarters country (`geo-report`)
+//!
+//! The country comes from the FMP profile and is stored in `ticker_details`,
+//! so it is filled in by the regular fetches. Companies fetched before that
+//! (or only through Polygon) are counted as `Unknown` until the next refresh.
+//! Unlike `regions`, which follows the listing, this answers where the
+//! companies are based: ADRs of European companies count for their home country.
+
+use anyhow::Result;
+use chrono::{DateTime, NaiveDate, NaiveTime};
+use csv::Writer;
+use sqlx::Row;
+use sqlx::sqlite::SqlitePool;
+use std::path::Path;
+
+use crate::config::{self, OutputConfig};
+use crate::rankings;
+use crate::regions::{self, GroupTotal};
+use crate::vega;
+use crate::visualizations;
+
+/// Group label for companies without a stored country
+pub const UNKNOWN_COUNTRY: &str = "Unknown";
+
+/// One company of a snapshot with its headquarters country
+#[derive(Debug, Clone, PartialEq)]
+pub struct CountryListing {
+    pub ticker: String,
+    pub name: String,
+    pub country: Option<String>,
+    pub market_cap_eur: Option<f64>,
+    pub market_cap_usd: Option<f64>,
+}
+
+/// English name of the countries in the universe; other codes are shown as is
+pub fn country_name(code: &str) -> Option<&'static str> {
+    Some(match code {
+        "AT" => "Austria",
+        "AU" => "Australia",
+        "BE" => "Belgium",
+        "BR" => "Brazil",
+        "CA" => "Canada",
+        "CH" => "Switzerland",
+        "CN" => "China",
+        "DE" => "Germany",
+        "DK" => "Denmark",
+        "ES" => "Spain",
+        "FI" => "Finland",
+        "FR" => "France",
+        "GB" => "United Kingdom",
+        "HK" => "Hong Kong",
+        "IE" => "Ireland",
+        "IN" => "India",
+        "IT" => "Italy",
+        "JP" => "Japan",
+        "KR" => "South Korea",
+        "LU" => "Luxembourg",
+        "NL" => "Netherlands",
+        "NO" => "Norway",
+        "PL" => "Poland",
+        "SE" => "Sweden",
+        "SG" => "Singapore",
+        "TW" => "Taiwan",
+        "US" => "United States",
+        "ZA" => "South Africa",
+        _ => return None,
+    })
+}
+
+/// Chart and table label of a group, e.g. "France (FR)"
+pub fn country_label(group: &str) -> String {
+    country_name(group).map_or_else(|| group.to_string(), |name| format!("{} ({})", name, group))
+}
+
+/// Totals per country, largest first
+pub fn by_country(listings: &[CountryListing]) -> Vec<GroupTotal> {
+    regions::group_totals(listings.iter().map(|l| {
+        (
+            l.country
+                .clone()
+                .unwrap_or_else(|| UNKNOWN_COUNTRY.to_string()),
+            l.market_cap_eur,
+            l.market_cap_usd,
+        )
+    }))
+}
+
+/// Timestamp of the snapshot for a date (midnight, as fetched for specific
+/// dates), or of the latest snapshot
+pub async fn snapshot_timestamp(pool: &SqlitePool, date: Option<&str>) -> Result<Option<i64>> {
+    match date {
+        Some(date) => {
+            let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map_err(|e| anyhow::anyhow!("Invalid date format. Use YYYY-MM-DD: {}", e))?;
+            Ok(Some(date.and_time(NaiveTime::MIN).and_utc().timestamp()))
+        }
+        None => Ok(sqlx::query_scalar("SELECT MAX(timestamp) FROM market_caps")
+            .fetch_one(pool)
+            .await?),
+    }
+}
+
+/// Latest snapshot of an earlier day than the snapshot at `timestamp`; other
+/// runs on the same day are not a previous snapshot
+pub async fn previous_snapshot_timestamp(pool: &SqlitePool, timestamp: i64) -> Result<Option<i64>> {
+    let start_of_day = timestamp - timestamp.rem_euclid(24 * 60 * 60);
+    Ok(
+        sqlx::query_scalar("SELECT MAX(timestamp) FROM market_caps WHERE timestamp < ?")
+            .bind(start_of_day)
+            .fetch_one(pool)
+            .await?,
+    )
+}
+
+/// Companies of one snapshot with their country, largest first
+pub async fn load_listings(pool: &SqlitePool, timestamp: i64) -> Result<Vec<CountryListing>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT m.ticker, m.name, d.country,
+            CAST(m.market_cap_eur AS REAL) AS market_cap_eur,
+            CAST(m.market_cap_usd AS REAL) AS market_cap_usd
+        FROM market_caps m
+        LEFT JOIN ticker_details d ON d.ticker = m.ticker
+        WHERE m.timestamp = ?
+        ORDER BY m.market_cap_usd DESC, m.ticker
+        "#,
+    )
+    .bind(timestamp)
+    .fetch_all(pool)
+    .await?;
+
+    let mut listings: Vec<CountryListing> = rows
+        .into_iter()
+        .map(|row| CountryListing {
+            ticker: row.get("ticker"),
+            name: row.get("name"),
+            country: row
+                .get::<Option<String>, _>("country")
+                .map(|c| c.trim().to_uppercase())
+                .filter(|c| !c.is_empty()),
+            market_cap_eur: row.get("market_cap_eur"),
+            market_cap_usd: row.get("market_cap_usd"),
+        })
+        .collect();
+    rankings::truncate_to_top(&mut listings);
+    Ok(listings)
+}
+
+/// Write the totals per country as CSV
+pub fn export_csv(path: &Path, totals: &[GroupTotal]) -> Result<()> {
+    let mut writer = Writer::from_path(path)?;
+    writer.write_record([
+        "Country Code",
+        "Country",
+        "Companies",
+        "Market Cap (EUR)",
+        "Market Cap (USD)",
+        "Share (%)",
+    ])?;
+    for total in totals {
+        writer.write_record(&[
+            total.group.clone(),
+            country_name(&total.group)
+                .unwrap_or(&total.group)
+                .to_string(),
+            total.companies.to_string(),
+            format!("{:.0}", total.market_cap_eur),
+            format!("{:.0}", total.market_cap_usd),
+            format!("{:.2}", total.share),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Aggregate a snapshot by headquarters country into a CSV and a bar chart
+pub async fn geo_report(pool: &SqlitePool, date: Option<&str>) -> Result<()> {
+    let Some(timestamp) = snapshot_timestamp(pool, date).await? else {
+        println!("No market cap snapshots stored yet.");
+        return Ok(());
+    };
+    let listings = load_listings(pool, timestamp).await?;
+    if listings.is_empty() {
+        anyhow::bail!(
+            "No market caps stored for {}; fetch them with fetch-specific-date-market-caps",
+            date.unwrap_or("the latest snapshot")
+        );
+    }
+    let date = DateTime::from_timestamp(timestamp, 0)
+        .map(|dt| dt.format("%Y-%m-%d").to_string())
+        .unwrap_or_default();
+
+    let totals = by_country(&listings);
+    let unknown = listings.iter().filter(|l| l.country.is_none()).count();
+
+    println!("📊 Market cap by headquarters country on {}:", date);
+    for total in &totals {
+        println!(
+            "  {:<26} {:>3} companies  ${:>8.1}B  {:>5.1}%",
+            country_label(&total.group),
+            total.companies,
+            total.market_cap_usd / 1_000_000_000.0,
+            total.share
+        );
+    }
+    if unknown > 0 {
+        println!(
+            "⚠️  {} companies have no country yet; it is stored with their next fetch",
+            unknown
+        );
+    }
+
+    let output = config::load_output_config();
+    output.ensure_directory()?;
+    let stamp = OutputConfig::timestamp();
+    let csv_path = output.file_path_at("geo_report", &date, &stamp, "csv");
+    export_csv(&csv_path, &totals)?;
+    println!("✅ Country totals exported to {}", csv_path.display());
+
+    let chart_path = output.file_path_at("geo_report", &date, &stamp, vega::extension());
+    visualizations::create_country_chart(&totals, &date, &chart_path)?;
+    println!("✅ Generated country chart: {}", chart_path.display());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    fn listing(ticker: &str, country: Option<&str>, usd: f64) -> CountryListing {
+        CountryListing {
+            ticker: ticker.to_string(),
+            name: ticker.to_string(),
+            country: country.map(str::to_string),
+            market_cap_eur: Some(usd * 0.9),
+            market_cap_usd: Some(usd),
+        }
+    }
+
+    #[test]
+    fn test_by_country() {
+        let totals = by_country(&[
+            listing("MC.PA", Some("FR"), 300.0),
+            listing("RMS.PA", Some("FR"), 200.0),
+            listing("NKE", Some("US"), 400.0),
+            listing("NEW", None, 100.0),
+        ]);
+
+        let groups: Vec<&str> = totals.iter().map(|t| t.group.as_str()).collect();
+        assert_eq!(groups, vec!["FR", "US", UNKNOWN_COUNTRY]);
+        assert_eq!(totals[0].companies, 2);
+        assert!((totals[0].share - 50.0).abs() < 1e-9);
+        assert_eq!(country_label("FR"), "France (FR)");
+        assert_eq!(country_label("XX"), "XX");
+    }
+
+    #[tokio::test]
+    async fn test_load_listings_and_export() {
+        let pool = db::create_db_pool("sqlite::memory:").await.unwrap();
+        sqlx::query("ALTER TABLE ticker_details ADD COLUMN ceo TEXT")
+            .execute(&pool)
+            .await
+            .unwrap();
+        for sql in [
+            "INSERT INTO market_caps (ticker, name, market_cap_eur, market_cap_usd, timestamp) VALUES
+                ('MC.PA', 'LVMH', 270, 300, 100), ('NKE', 'Nike', 90, 100, 100), ('ITX.MC', 'Inditex', 135, 150, 100),
+                ('MC.PA', 'LVMH', 1, 1, 50)",
+            "INSERT INTO ticker_details (ticker, country) VALUES ('MC.PA', 'fr'), ('NKE', 'US')",
+        ] {
+            sqlx::query(sql).execute(&pool).await.unwrap();
+        }
+
+        let listings = load_listings(&pool, 100).await.unwrap();
+        assert_eq!(
+            listings
+                .iter()
+                .map(|l| (l.ticker.as_str(), l.name.as_str(), l.country.as_deref()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("MC.PA", "LVMH", Some("FR")),
+                ("ITX.MC", "Inditex", None),
+                ("NKE", "Nike", Some("US"))
+            ]
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("geo.csv");
+        export_csv(&path, &by_country(&listings)).unwrap();
+        let csv = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            csv,
+            "Country Code,Country,Companies,Market Cap (EUR),Market Cap (USD),Share (%)\n\
+             FR,France,1,270,300,54.55\n\
+             Unknown,Unknown,1,135,150,27.27\n\
+             US,United States,1,90,100,18.18\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_previous_snapshot_skips_runs_of_the_same_day() {
+        let pool = db::create_db_pool("sqlite::memory:").await.unwrap();
+        // 2025-01-01 12:00, 2025-01-02 00:00, 08:00 and 16:00
+        sqlx::query(
+            "INSERT INTO market_caps (ticker, name, timestamp) VALUES
+                ('NKE', 'Nike', 1735732800), ('NKE', 'Nike', 1735776000),
+                ('NKE', 'Nike', 1735804800), ('NKE', 'Nike', 1735833600)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        assert_eq!(
+            previous_snapshot_timestamp(&pool, 1735833600)
+                .await
+                .unwrap(),
+            Some(1735732800)
+        );
+        assert_eq!(
+            previous_snapshot_timestamp(&pool, 1735732800)
+                .await
+                .unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,385 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Golden-file tests for report generation
+//!
+//! Each test runs a report writer against the snapshots in
+//! `tests/golden/fixtures`. The clock is frozen, the exchange rates are fixed,
+//! and output goes to a temporary directory. Every written file must then equal
+//! its copy in `tests/golden/<name>/`, byte for byte.
+//!
+//! After an intentional report change, refresh the expected files with
+//! `UPDATE_GOLDEN=1 cargo test golden` and review the diff.
+
+use chrono::{NaiveDate, NaiveDateTime};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+use crate::advanced_comparisons;
+use crate::clock;
+use crate::compare_marketcaps::{self, ComparisonReport};
+use crate::config::OutputConfig;
+use crate::corporate_actions::{self, CorporateActionIndex};
+use crate::earnings::{EarningsIndex, EarningsReport};
+use crate::ticker_aliases::AppliedAliases;
+
+const DATES: [&str; 3] = ["2025-01-31", "2025-02-28", "2025-03-31"];
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+fn fixture(name: &str) -> String {
+    golden_dir()
+        .join("fixtures")
+        .join(name)
+        .display()
+        .to_string()
+}
+
+fn frozen_time() -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2025, 4, 1)
+        .unwrap()
+        .and_hms_opt(9, 0, 0)
+        .unwrap()
+}
+
+/// Rates used for every conversion, quoted both ways against USD
+fn fixed_rates() -> HashMap<String, f64> {
+    let mut rates = HashMap::new();
+    for (currency, usd) in [("EUR", 1.05), ("GBP", 1.25), ("JPY", 0.0065), ("CHF", 1.1)] {
+        rates.insert(format!("{}/USD", currency), usd);
+        rates.insert(format!("USD/{}", currency), 1.0 / usd);
+    }
+    rates
+}
+
+fn corporate_actions(from: &str, to: &str) -> CorporateActionIndex {
+    let content = fs::read_to_string(fixture("corporate_actions.toml")).unwrap();
+    let actions = corporate_actions::parse_corporate_actions(&content).unwrap();
+    CorporateActionIndex::for_period(
+        &actions,
+        NaiveDate::parse_from_str(from, "%Y-%m-%d").unwrap(),
+        NaiveDate::parse_from_str(to, "%Y-%m-%d").unwrap(),
+    )
+}
+
+fn temp_output(dir: &TempDir) -> OutputConfig {
+    OutputConfig {
+        directory: dir.path().display().to_string(),
+        ..OutputConfig::default()
+    }
+}
+
+fn file_names(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.file_name().to_string_lossy().to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+/// First differing line of two files, for a readable failure message
+fn first_difference(expected: &str, actual: &str) -> String {
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    for line in 1.. {
+        match (expected_lines.next(), actual_lines.next()) {
+            (Some(e), Some(a)) if e == a => continue,
+            (None, None) => break,
+            (e, a) => {
+                return format!(
+                    "line {}:\n  expected: {}\n  actual:   {}",
+                    line,
+                    e.unwrap_or("<end of file>"),
+                    a.unwrap_or("<end of file>")
+                );
+            }
+        }
+    }
+    "line endings differ".to_string()
+}
+
+/// Compare everything written to `written` with `tests/golden/<name>/`, or
+/// replace the golden files when `UPDATE_GOLDEN` is set
+fn assert_matches_golden(written: &Path, name: &str) {
+    let golden = golden_dir().join(name);
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        let _ = fs::remove_dir_all(&golden);
+        fs::create_dir_all(&golden).unwrap();
+        for file in file_names(written) {
+            fs::copy(written.join(&file), golden.join(&file)).unwrap();
+        }
+        return;
+    }
+
+    assert_eq!(
+        file_names(written),
+        file_names(&golden),
+        "{} wrote different files than tests/golden/{} (UPDATE_GOLDEN=1 to accept)",
+        name,
+        name
+    );
+    for file in file_names(&golden) {
+        let expected = fs::read_to_string(golden.join(&file)).unwrap();
+        let actual = fs::read_to_string(written.join(&file)).unwrap();
+        assert!(
+            expected == actual,
+            "{} differs from tests/golden/{}/{} at {} (UPDATE_GOLDEN=1 to accept)",
+            file,
+            name,
+            file,
+            first_difference(&expected, &actual)
+        );
+    }
+}
+
+#[test]
+fn test_compare_market_caps_matches_golden() {
+    let _clock = clock::freeze(frozen_time());
+    let dir = TempDir::new().unwrap();
+    let output = temp_output(&dir);
+    let (from_date, to_date) = (DATES[0], DATES[1]);
+
+    let from_records =
+        compare_marketcaps::read_market_cap_csv(&fixture(&format!("marketcaps_{}.csv", from_date)))
+            .unwrap();
+    let to_records =
+        compare_marketcaps::read_market_cap_csv(&fixture(&format!("marketcaps_{}.csv", to_date)))
+            .unwrap();
+    let corporate_actions = corporate_actions(from_date, to_date);
+    let earnings = EarningsIndex::for_period(
+        &[EarningsReport {
+            ticker: "TJX".to_string(),
+            date: NaiveDate::from_ymd_opt(2025, 2, 26).unwrap(),
+            time: Some("bmo".to_string()),
+            eps: Some(1.23),
+            eps_estimated: Some(1.16),
+            revenue: None,
+            revenue_estimated: None,
+            fiscal_date_ending: None,
+        }],
+        NaiveDate::parse_from_str(from_date, "%Y-%m-%d").unwrap(),
+        NaiveDate::parse_from_str(to_date, "%Y-%m-%d").unwrap(),
+    );
+    let rates = fixed_rates();
+
+    ComparisonReport {
+        from_date,
+        to_date,
+        output: &output,
+        kind: "comparison",
+        corporate_actions: &corporate_actions,
+        aliases: &AppliedAliases::default(),
+        earnings: &earnings,
+        report_currencies: &["CHF".to_string()],
+        from_rates: &rates,
+        to_rates: &rates,
+        notes: &format!(
+            "{}{}",
+            corporate_actions.markdown_section(false),
+            earnings.markdown_section()
+        ),
+        with_charts: false,
+        inputs: &[],
+    }
+    .write(&from_records, &to_records)
+    .unwrap();
+
+    assert_matches_golden(dir.path(), "compare_market_caps");
+}
+
+#[test]
+fn test_trend_analysis_matches_golden() {
+    let _clock = clock::freeze(frozen_time());
+    let dir = TempDir::new().unwrap();
+    let output = temp_output(&dir);
+    let dates: Vec<String> = DATES.iter().map(|d| d.to_string()).collect();
+
+    let all_data: BTreeMap<String, BTreeMap<String, advanced_comparisons::MarketCapRecord>> = dates
+        .iter()
+        .map(|date| {
+            let records = advanced_comparisons::read_market_cap_csv(&fixture(&format!(
+                "marketcaps_{}.csv",
+                date
+            )))
+            .unwrap();
+            let by_ticker = records.into_iter().map(|r| (r.ticker.clone(), r)).collect();
+            (date.clone(), by_ticker)
+        })
+        .collect();
+
+    let (trends, summary) = advanced_comparisons::compute_trends(
+        &dates,
+        &all_data,
+        &fixed_rates(),
+        corporate_actions(DATES[0], DATES[2]),
+        false,
+        None,
+        AppliedAliases::default(),
+    )
+    .unwrap();
+    advanced_comparisons::export_trend_analysis(&trends, &summary, &dates, None, &output).unwrap();
+
+    assert_matches_golden(dir.path(), "trend_analysis");
+}
+
+/// Writes one report into a directory, for the rerun test
+type WriteReport = fn(&Path);
+
+/// Every file in a directory with its contents
+fn read_outputs(dir: &Path) -> Vec<(String, Vec<u8>)> {
+    file_names(dir)
+        .into_iter()
+        .map(|file| {
+            let contents = fs::read(dir.join(&file)).unwrap();
+            (file, contents)
+        })
+        .collect()
+}
+
+/// Write the comparison of the first two fixture dates to `dir`, with the
+/// snapshot rows in the order `arrange` leaves them
+fn write_comparison(dir: &Path, arrange: fn(&mut Vec<compare_marketcaps::MarketCapRecord>)) {
+    let output = OutputConfig {
+        directory: dir.display().to_string(),
+        ..OutputConfig::default()
+    };
+    let (from_date, to_date) = (DATES[0], DATES[1]);
+
+    let mut from_records =
+        compare_marketcaps::read_market_cap_csv(&fixture(&format!("marketcaps_{}.csv", from_date)))
+            .unwrap();
+    let mut to_records =
+        compare_marketcaps::read_market_cap_csv(&fixture(&format!("marketcaps_{}.csv", to_date)))
+            .unwrap();
+    arrange(&mut from_records);
+    arrange(&mut to_records);
+    let corporate_actions = corporate_actions(from_date, to_date);
+    let earnings = EarningsIndex::for_period(
+        &[EarningsReport {
+            ticker: "TJX".to_string(),
+            date: NaiveDate::from_ymd_opt(2025, 2, 26).unwrap(),
+            time: Some("bmo".to_string()),
+            eps: Some(1.23),
+            eps_estimated: Some(1.16),
+            revenue: None,
+            revenue_estimated: None,
+            fiscal_date_ending: None,
+        }],
+        NaiveDate::parse_from_str(from_date, "%Y-%m-%d").unwrap(),
+        NaiveDate::parse_from_str(to_date, "%Y-%m-%d").unwrap(),
+    );
+    let rates = fixed_rates();
+
+    ComparisonReport {
+        from_date,
+        to_date,
+        output: &output,
+        kind: "comparison",
+        corporate_actions: &corporate_actions,
+        aliases: &AppliedAliases::default(),
+        earnings: &earnings,
+        report_currencies: &["CHF".to_string()],
+        from_rates: &rates,
+        to_rates: &rates,
+        notes: &format!(
+            "{}{}",
+            corporate_actions.markdown_section(false),
+            earnings.markdown_section()
+        ),
+        with_charts: false,
+        inputs: &[],
+    }
+    .write(&from_records, &to_records)
+    .unwrap();
+}
+
+/// Write the trend analysis over all fixture dates to `dir`, with the
+/// snapshot rows in the order `arrange` leaves them
+fn write_trends(dir: &Path, arrange: fn(&mut Vec<advanced_comparisons::MarketCapRecord>)) {
+    let output = OutputConfig {
+        directory: dir.display().to_string(),
+        ..OutputConfig::default()
+    };
+    let dates: Vec<String> = DATES.iter().map(|d| d.to_string()).collect();
+
+    let all_data: BTreeMap<String, BTreeMap<String, advanced_comparisons::MarketCapRecord>> = dates
+        .iter()
+        .map(|date| {
+            let mut records = advanced_comparisons::read_market_cap_csv(&fixture(&format!(
+                "marketcaps_{}.csv",
+                date
+            )))
+            .unwrap();
+            arrange(&mut records);
+            let by_ticker = records.into_iter().map(|r| (r.ticker.clone(), r)).collect();
+            (date.clone(), by_ticker)
+        })
+        .collect();
+
+    let (trends, summary) = advanced_comparisons::compute_trends(
+        &dates,
+        &all_data,
+        &fixed_rates(),
+        corporate_actions(DATES[0], DATES[2]),
+        false,
+        None,
+        AppliedAliases::default(),
+    )
+    .unwrap();
+    advanced_comparisons::export_trend_analysis(&trends, &summary, &dates, None, &output).unwrap();
+}
+
+/// Reruns on the same snapshots write the same bytes, also when the rows
+/// come in another order, so output files only change in git when the data
+/// does
+#[test]
+fn test_reports_are_identical_across_reruns() {
+    let _clock = clock::freeze(frozen_time());
+    let writers: [(&str, WriteReport); 4] = [
+        ("comparison", |dir| write_comparison(dir, |_| {})),
+        ("comparison, rows reversed", |dir| {
+            write_comparison(dir, |records| records.reverse())
+        }),
+        ("trend analysis", |dir| write_trends(dir, |_| {})),
+        ("trend analysis, rows reversed", |dir| {
+            write_trends(dir, |records| records.reverse())
+        }),
+    ];
+
+    let mut baseline: BTreeMap<&str, Vec<(String, Vec<u8>)>> = BTreeMap::new();
+    for _ in 0..5 {
+        for (name, write) in writers {
+            let dir = TempDir::new().unwrap();
+            write(dir.path());
+            let outputs = read_outputs(dir.path());
+            // Reversed rows must give the same files as the original order
+            let key = name.trim_end_matches(", rows reversed");
+            let expected = baseline.entry(key).or_insert_with(|| outputs.clone());
+            assert!(!expected.is_empty());
+            for ((file, contents), (_, expected)) in outputs.iter().zip(expected.iter()) {
+                assert!(
+                    contents == expected,
+                    "{}: {} differs between runs at {}",
+                    name,
+                    file,
+                    first_difference(
+                        &String::from_utf8_lossy(expected),
+                        &String::from_utf8_lossy(contents)
+                    )
+                );
+            }
+            assert_eq!(outputs.len(), expected.len(), "{}", name);
+        }
+    }
+}
@@ -5,43 +5,74 @@
 use crate::api;
 use crate::config;
 use crate::currencies::{convert_currency_with_rate, get_rate_map_from_db_for_date};
+use crate::models::{self, DataSource};
+use crate::progress;
+use crate::utils;
 use anyhow::Result;
-use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Utc};
 use sqlx::sqlite::SqlitePool;
 use std::sync::Arc;
 
+/// FMP requests per ticker and date: the historical market cap plus the
+/// profile (or quote) for name, currency and price
+pub const HISTORICAL_REQUESTS_PER_TICKER: u32 = 2;
+
 pub async fn fetch_historical_marketcaps(
     pool: &SqlitePool,
     start_year: i32,
     end_year: i32,
+    concurrency: usize,
 ) -> Result<()> {
     let config = config::load_config()?;
     let tickers = [config.non_us_tickers, config.us_tickers].concat();
 
     // Get FMP client for market data
-    let api_key = std::env::var("FINANCIALMODELINGPREP_API_KEY")
-        .expect("FINANCIALMODELINGPREP_API_KEY must be set");
-    let fmp_client = Arc::new(api::FMPClient::new(api_key));
+    let fmp_client = Arc::new(api::FMPClient::from_env()?);
 
     println!(
         "Fetching historical market caps from {} to {}",
         start_year, end_year
     );
 
+    // Overall bar over every (year, ticker) fetch, with one bar per year below it
+    let years = (end_year - start_year + 1).max(0) as usize;
+    let total = progress::fetch_bar(
+        (years * tickers.len()) as u64,
+        "total",
+        HISTORICAL_REQUESTS_PER_TICKER,
+    );
+
     for year in start_year..=end_year {
         // Get Dec 31st of each year
         let date = NaiveDate::from_ymd_opt(year, 12, 31).unwrap();
         let naive_dt = NaiveDateTime::new(date, NaiveTime::default());
         let datetime_utc = naive_dt.and_utc();
         let timestamp = naive_dt.and_utc().timestamp();
-        println!("Fetching exchange rates for {}", naive_dt);
         let rate_map = get_rate_map_from_db_for_date(pool, Some(timestamp)).await?;
 
-        for ticker in &tickers {
-            match fmp_client
-                .get_historical_market_cap(ticker, &datetime_utc)
-                .await
-            {
+        // Fetch concurrently, then store in ticker order
+        let year_bar = progress::fetch_bar(
+            tickers.len() as u64,
+            &year.to_string(),
+            HISTORICAL_REQUESTS_PER_TICKER,
+        );
+        let fetched = utils::fetch_ordered(&tickers, concurrency, |ticker| {
+            let (year_bar, total) = (year_bar.clone(), total.clone());
+            let fmp_client = fmp_client.clone();
+            async move {
+                let market_cap = fmp_client
+                    .get_historical_market_cap(ticker, &datetime_utc)
+                    .await;
+                year_bar.inc(1);
+                total.inc(1);
+                market_cap
+            }
+        })
+        .await;
+        year_bar.finish_and_clear();
+
+        for (ticker, result) in tickers.iter().zip(fetched) {
+            match result {
                 Ok(market_cap) => {
                     // Convert currencies with rate information
                     let eur_result = convert_currency_with_rate(
@@ -62,14 +93,16 @@ pub async fn fetch_historical_marketcaps(
                     let timestamp = naive_dt.and_utc().timestamp();
 
                     // Insert into database (use OR REPLACE to handle re-runs gracefully)
+                    let data_source = DataSource::Fmp.as_str();
+                    let fetched_at = models::fetched_at(Utc::now());
                     sqlx::query!(
                         r#"
                         INSERT OR REPLACE INTO market_caps (
                             ticker, name, market_cap_original, original_currency,
                             market_cap_eur, market_cap_usd, eur_rate, usd_rate,
-                            exchange, price, active, timestamp
+                            exchange, price, active, timestamp, data_source, fetched_at
                         )
-                        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
                         "#,
                         ticker,
                         market_cap.name,
@@ -83,24 +116,29 @@ pub async fn fetch_historical_marketcaps(
                         market_cap.price,
                         true,
                         timestamp,
+                        data_source,
+                        fetched_at,
                     )
                     .execute(pool)
                     .await?;
 
-                    println!(
+                    progress::println(format!(
                         "✅ Added historical market cap for {} on {}",
                         ticker, naive_dt
-                    );
+                    ));
                 }
                 Err(e) => {
-                    eprintln!(
-                        "❌ Failed to fetch market cap for {} on {}: {}",
-                        ticker, naive_dt, e
-                    );
+                    progress::suspend(|| {
+                        eprintln!(
+                            "❌ Failed to fetch market cap for {} on {}: {}",
+                            ticker, naive_dt, e
+                        )
+                    });
                 }
             }
         }
     }
+    total.finish();
 
     Ok(())
 }
@@ -0,0 +1,269 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Registry identifiers (ISIN, LEI) next to tickers
+//!
+//! External datasets often identify companies by ISIN (ISO 6166) or LEI
+//! (ISO 17442) instead of an exchange ticker. Both are stored in
+//! `ticker_details`: the ISIN comes from the FMP profile, either can come
+//! from `import-marketcaps`. Codes are only stored when their check digits
+//! are valid, so a typo can't join the wrong company. `isin-map` exports the
+//! ticker to identifier table for merging.
+
+use anyhow::Result;
+use csv::Writer;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::config;
+use crate::db::{CorePool, core_query};
+
+/// Letters count as 10 (A) to 35 (Z), digits as themselves
+fn expand_digits(code: &str) -> Option<String> {
+    code.chars()
+        .map(|c| c.to_digit(36).map(|d| d.to_string()))
+        .collect()
+}
+
+/// Upper-case ISIN if `value` is one with a valid check digit: two letters
+/// (country), nine alphanumerics and a Luhn check digit
+pub fn normalize_isin(value: &str) -> Option<String> {
+    let isin = value.trim().to_ascii_uppercase();
+    let bytes = isin.as_bytes();
+    if bytes.len() != 12
+        || !bytes[..2].iter().all(u8::is_ascii_alphabetic)
+        || !bytes[2..11].iter().all(u8::is_ascii_alphanumeric)
+        || !bytes[11].is_ascii_digit()
+    {
+        return None;
+    }
+    let digits = expand_digits(&isin)?;
+    let sum: u32 = digits
+        .chars()
+        .rev()
+        .enumerate()
+        .map(|(i, c)| {
+            let d = c.to_digit(10).unwrap_or(0);
+            match i % 2 {
+                0 => d,
+                _ if d * 2 > 9 => d * 2 - 9,
+                _ => d * 2,
+            }
+        })
+        .sum();
+    sum.is_multiple_of(10).then_some(isin)
+}
+
+/// Upper-case LEI if `value` is one with valid check digits: 20
+/// alphanumerics whose number is 1 modulo 97
+pub fn normalize_lei(value: &str) -> Option<String> {
+    let lei = value.trim().to_ascii_uppercase();
+    if lei.len() != 20 || !lei.bytes().all(|b| b.is_ascii_alphanumeric()) {
+        return None;
+    }
+    let remainder = expand_digits(&lei)?
+        .chars()
+        .fold(0u32, |acc, c| (acc * 10 + c.to_digit(10).unwrap_or(0)) % 97);
+    (remainder == 1).then_some(lei)
+}
+
+/// Store identifiers of a ticker, keeping stored ones where `None` is given
+pub async fn store_identifiers(
+    pool: impl Into<CorePool>,
+    ticker: &str,
+    isin: Option<&str>,
+    lei: Option<&str>,
+) -> Result<()> {
+    core_query!(pool.into(), |pool| sqlx::query(
+        r#"
+        INSERT INTO ticker_details (ticker, isin, lei)
+        VALUES ($1, $2, $3)
+        ON CONFLICT(ticker) DO UPDATE SET
+            isin = COALESCE(excluded.isin, ticker_details.isin),
+            lei = COALESCE(excluded.lei, ticker_details.lei)
+        "#,
+    )
+    .bind(ticker)
+    .bind(isin)
+    .bind(lei)
+    .execute(&pool)
+    .await?
+    .rows_affected());
+    Ok(())
+}
+
+/// Ticker of every stored ISIN. An ISIN stored for several tickers (a
+/// listing that changed symbol) maps to the alphabetically first.
+pub async fn tickers_by_isin(pool: impl Into<CorePool>) -> Result<HashMap<String, String>> {
+    let rows = core_query!(pool.into(), |pool| sqlx::query_as::<_, (String, String)>(
+        "SELECT isin, ticker FROM ticker_details WHERE isin IS NOT NULL ORDER BY ticker DESC",
+    )
+    .fetch_all(&pool)
+    .await?);
+    Ok(rows.into_iter().collect())
+}
+
+/// The ticker for `value` when it is a known ISIN, otherwise `value` itself
+pub async fn resolve_ticker(pool: impl Into<CorePool>, value: &str) -> Result<String> {
+    let Some(isin) = normalize_isin(value) else {
+        return Ok(value.to_string());
+    };
+    match tickers_by_isin(pool).await?.remove(&isin) {
+        Some(ticker) => {
+            println!("ISIN {} is {}", isin, ticker);
+            Ok(ticker)
+        }
+        None => Ok(value.to_string()),
+    }
+}
+
+/// One row of `isin_map_<timestamp>.csv`
+#[derive(Debug, Clone, Default, PartialEq, sqlx::FromRow)]
+pub struct IsinMapRow {
+    pub ticker: String,
+    pub name: Option<String>,
+    pub isin: Option<String>,
+    pub lei: Option<String>,
+    pub exchange: Option<String>,
+    pub country: Option<String>,
+}
+
+/// Identifiers of the configured tickers and of every ticker with a stored
+/// ISIN or LEI, ordered by ticker. Names and exchanges come from the latest
+/// market cap row.
+pub async fn get_isin_map(
+    pool: impl Into<CorePool>,
+    tickers: &[String],
+) -> Result<Vec<IsinMapRow>> {
+    let rows = core_query!(pool.into(), |pool| sqlx::query_as::<_, IsinMapRow>(
+        r#"
+        SELECT td.ticker,
+            COALESCE(td.name, (
+                SELECT m.name FROM market_caps m
+                WHERE m.ticker = td.ticker ORDER BY m.timestamp DESC LIMIT 1
+            )) AS name,
+            td.isin,
+            td.lei,
+            COALESCE(td.exchange, (
+                SELECT m.exchange FROM market_caps m
+                WHERE m.ticker = td.ticker ORDER BY m.timestamp DESC LIMIT 1
+            )) AS exchange,
+            td.country
+        FROM ticker_details td
+        "#,
+    )
+    .fetch_all(&pool)
+    .await?);
+
+    let mut map: BTreeMap<String, IsinMapRow> = rows
+        .into_iter()
+        .filter(|row| row.isin.is_some() || row.lei.is_some() || tickers.contains(&row.ticker))
+        .map(|row| (row.ticker.clone(), row))
+        .collect();
+    for ticker in tickers {
+        map.entry(ticker.clone()).or_insert_with(|| IsinMapRow {
+            ticker: ticker.clone(),
+            ..IsinMapRow::default()
+        });
+    }
+    Ok(map.into_values().collect())
+}
+
+/// Write `isin_map_<timestamp>.csv` for the configured tickers and report
+/// how many lack an ISIN
+pub async fn export_isin_map(pool: impl Into<CorePool>) -> Result<()> {
+    let config = config::load_config()?;
+    let tickers = [config.non_us_tickers, config.us_tickers].concat();
+    let rows = get_isin_map(pool, &tickers).await?;
+
+    let output = config::load_output_config();
+    output.ensure_directory()?;
+    let path = output.file_path("isin_map", "", "csv");
+    let mut writer = Writer::from_path(&path)?;
+    writer.write_record(["Ticker", "Name", "ISIN", "LEI", "Exchange", "Country"])?;
+    for row in &rows {
+        writer.write_record([
+            row.ticker.as_str(),
+            row.name.as_deref().unwrap_or(""),
+            row.isin.as_deref().unwrap_or(""),
+            row.lei.as_deref().unwrap_or(""),
+            row.exchange.as_deref().unwrap_or(""),
+            row.country.as_deref().unwrap_or(""),
+        ])?;
+    }
+    writer.flush()?;
+
+    let without_isin = rows.iter().filter(|r| r.isin.is_none()).count();
+    println!(
+        "✅ ISIN map of {} tickers exported to {}",
+        rows.len(),
+        path.display()
+    );
+    if without_isin > 0 {
+        println!(
+            "⚠️  {} tickers have no ISIN yet (fetched with export-combined --full-details or imported)",
+            without_isin
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    #[test]
+    fn test_normalize_isin() {
+        assert_eq!(
+            normalize_isin(" us6541061031 "),
+            Some("US6541061031".to_string())
+        );
+        assert_eq!(
+            normalize_isin("FR0000121014"),
+            Some("FR0000121014".to_string())
+        );
+        // Wrong check digit, length and country
+        assert_eq!(normalize_isin("US6541061032"), None);
+        assert_eq!(normalize_isin("US654106103"), None);
+        assert_eq!(normalize_isin("126541061031"), None);
+        assert_eq!(normalize_isin("NKE"), None);
+    }
+
+    #[test]
+    fn test_normalize_lei() {
+        assert_eq!(
+            normalize_lei("hwupkr0mpou8fgxbt394"),
+            Some("HWUPKR0MPOU8FGXBT394".to_string())
+        );
+        assert_eq!(normalize_lei("HWUPKR0MPOU8FGXBT395"), None);
+        assert_eq!(normalize_lei("HWUPKR0MPOU8FGXBT39"), None);
+    }
+
+    #[tokio::test]
+    async fn test_store_and_resolve_identifiers() -> Result<()> {
+        let pool = CorePool::from(&db::create_db_pool("sqlite::memory:").await?);
+        store_identifiers(&pool, "NKE", Some("US6541061031"), None).await?;
+        store_identifiers(&pool, "NKE", None, Some("787RXPR0UX0O0XUXPZ81")).await?;
+        store_identifiers(&pool, "MC.PA", None, None).await?;
+
+        assert_eq!(resolve_ticker(&pool, "us6541061031").await?, "NKE");
+        assert_eq!(resolve_ticker(&pool, "FR0000121014").await?, "FR0000121014");
+        assert_eq!(resolve_ticker(&pool, "NKE").await?, "NKE");
+
+        let map = get_isin_map(&pool, &["ITX.MC".to_string()]).await?;
+        let tickers: Vec<(&str, Option<&str>, Option<&str>)> = map
+            .iter()
+            .map(|r| (r.ticker.as_str(), r.isin.as_deref(), r.lei.as_deref()))
+            .collect();
+        // MC.PA has no identifiers and isn't configured; ITX.MC is configured
+        assert_eq!(
+            tickers,
+            vec![
+                ("ITX.MC", None, None),
+                ("NKE", Some("US6541061031"), Some("787RXPR0UX0O0XUXPZ81")),
+            ]
+        );
+        Ok(())
+    }
+}
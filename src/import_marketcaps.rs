@@ -0,0 +1,753 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Bulk import of historical market caps from external CSV files
+//!
+//! A mapping file names the CSV columns that hold each field, plus how dates
+//! and numbers are written. Every row is validated (date, market cap, and a
+//! currency convertible to EUR and USD on that date) before anything is
+//! stored; imported rows then go through the same storage and export path as
+//! `fetch-specific-date-market-caps`, so comparisons can use them. Rows may
+//! identify companies by ISIN instead of ticker; those are matched to the
+//! ISINs stored in `ticker_details`.
+
+use anyhow::{Context, Result};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use serde::Deserialize;
+use sqlx::sqlite::SqlitePool;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use crate::config::{self, OutputConfig};
+use crate::currencies::{
+    convert_currency_with_rate, extra_report_currencies, get_rate_map_with_gaps,
+};
+use crate::identifiers;
+use crate::models::{self, DataSource};
+use crate::rankings;
+use crate::run_context;
+use crate::snapshot_labels::Snapshot;
+use crate::specific_date_marketcaps::{Store, export_specific_date_marketcaps};
+use crate::subunits;
+use crate::universe;
+
+/// How many invalid rows are listed before the rest are only counted
+const MAX_REPORTED_ERRORS: usize = 20;
+
+/// CSV column names for each imported field
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ColumnMapping {
+    /// Needed unless there is an `isin` column
+    pub ticker: Option<String>,
+    pub date: String,
+    pub market_cap: String,
+    pub currency: Option<String>,
+    pub name: Option<String>,
+    pub exchange: Option<String>,
+    pub price: Option<String>,
+    /// ISIN, stored for the ticker and used to find it when the ticker is empty
+    pub isin: Option<String>,
+    /// LEI, stored for the ticker
+    pub lei: Option<String>,
+}
+
+/// Contents of a mapping.toml
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Mapping {
+    pub columns: ColumnMapping,
+    /// chrono format of the date column
+    #[serde(default = "default_date_format")]
+    pub date_format: String,
+    #[serde(default = "default_delimiter")]
+    pub delimiter: char,
+    /// "." or ","; the other one is treated as a thousands separator
+    #[serde(default = "default_decimal_separator")]
+    pub decimal_separator: char,
+    /// Factor applied to market caps and prices, e.g. 1000000 for figures in millions
+    #[serde(default = "default_multiplier")]
+    pub multiplier: f64,
+    /// Currency of rows when there is no currency column
+    pub default_currency: Option<String>,
+}
+
+fn default_date_format() -> String {
+    "%Y-%m-%d".to_string()
+}
+
+fn default_delimiter() -> char {
+    ','
+}
+
+fn default_decimal_separator() -> char {
+    '.'
+}
+
+fn default_multiplier() -> f64 {
+    1.0
+}
+
+impl Mapping {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read mapping file {}", path.display()))?;
+        run_context::record_input(path);
+        let mapping: Mapping = toml::from_str(&content)
+            .with_context(|| format!("Invalid mapping file {}", path.display()))?;
+        mapping.validate()?;
+        Ok(mapping)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.columns.ticker.is_none() && self.columns.isin.is_none() {
+            anyhow::bail!("The mapping needs a `columns.ticker` or a `columns.isin` column");
+        }
+        if self.columns.currency.is_none() && self.default_currency.is_none() {
+            anyhow::bail!("The mapping needs a `columns.currency` column or a `default_currency`");
+        }
+        if !matches!(self.decimal_separator, '.' | ',') {
+            anyhow::bail!("`decimal_separator` must be \".\" or \",\"");
+        }
+        if !self.delimiter.is_ascii() {
+            anyhow::bail!("`delimiter` must be a single ASCII character");
+        }
+        if !(self.multiplier.is_finite() && self.multiplier > 0.0) {
+            anyhow::bail!("`multiplier` must be a positive number");
+        }
+        Ok(())
+    }
+}
+
+/// One validated CSV row
+#[derive(Debug, Clone, PartialEq)]
+struct ImportRow {
+    /// Empty until resolved from the ISIN when the row has no ticker
+    ticker: String,
+    name: String,
+    date: NaiveDate,
+    market_cap: f64,
+    currency: String,
+    exchange: Option<String>,
+    price: Option<f64>,
+    isin: Option<String>,
+    lei: Option<String>,
+}
+
+/// Parse a number written with the mapping's decimal separator, ignoring
+/// thousands separators and surrounding whitespace
+fn parse_number(value: &str, decimal_separator: char) -> Option<f64> {
+    let cleaned: String = value
+        .trim()
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '_')
+        .collect();
+    let cleaned = if decimal_separator == ',' {
+        cleaned.replace('.', "").replace(',', ".")
+    } else {
+        cleaned.replace(',', "")
+    };
+    cleaned.parse::<f64>().ok().filter(|n| n.is_finite())
+}
+
+/// Currency codes are three letters; subunit codes (GBp, ZAc, see
+/// `subunits.rs`) keep their case
+fn normalize_currency(value: &str) -> Option<String> {
+    let value = value.trim();
+    if value.len() != 3 || !value.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    match subunits::lookup(value) {
+        Some(_) => Some(value.to_string()),
+        None => Some(value.to_ascii_uppercase()),
+    }
+}
+
+fn column_index(headers: &csv::StringRecord, column: &str, file: &Path) -> Result<usize> {
+    headers
+        .iter()
+        .position(|h| h.trim() == column)
+        .with_context(|| format!("{} has no column named '{}'", file.display(), column))
+}
+
+/// Parse one CSV file. Missing columns fail the whole file; invalid rows are
+/// returned as `file:line: reason` messages.
+fn parse_file(path: &Path, mapping: &Mapping) -> Result<(Vec<ImportRow>, Vec<String>)> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(mapping.delimiter as u8)
+        .flexible(true)
+        .from_path(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    run_context::record_input(path);
+    let headers = reader.headers()?.clone();
+
+    let columns = &mapping.columns;
+    let date_idx = column_index(&headers, &columns.date, path)?;
+    let market_cap_idx = column_index(&headers, &columns.market_cap, path)?;
+    let optional = |column: &Option<String>| {
+        column
+            .as_deref()
+            .map(|c| column_index(&headers, c, path))
+            .transpose()
+    };
+    let currency_idx = optional(&columns.currency)?;
+    let name_idx = optional(&columns.name)?;
+    let exchange_idx = optional(&columns.exchange)?;
+    let price_idx = optional(&columns.price)?;
+    let ticker_idx = optional(&columns.ticker)?;
+    let isin_idx = optional(&columns.isin)?;
+    let lei_idx = optional(&columns.lei)?;
+
+    let mut rows = Vec::new();
+    let mut errors = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        let field = |idx: usize| record.get(idx).map(str::trim).unwrap_or_default();
+        let optional_field =
+            |idx: Option<usize>| idx.map(field).filter(|v| !v.is_empty()).map(str::to_string);
+        let mut fail = |reason: String| {
+            errors.push(format!("{}:{}: {}", path.display(), line, reason));
+        };
+
+        let ticker = optional_field(ticker_idx).unwrap_or_default();
+        let isin = match optional_field(isin_idx) {
+            None => None,
+            Some(value) => match identifiers::normalize_isin(&value) {
+                Some(isin) => Some(isin),
+                None => {
+                    fail(format!("invalid ISIN '{}'", value));
+                    continue;
+                }
+            },
+        };
+        let lei = match optional_field(lei_idx) {
+            None => None,
+            Some(value) => match identifiers::normalize_lei(&value) {
+                Some(lei) => Some(lei),
+                None => {
+                    fail(format!("invalid LEI '{}'", value));
+                    continue;
+                }
+            },
+        };
+        if ticker.is_empty() && isin.is_none() {
+            fail("empty ticker".to_string());
+            continue;
+        }
+        let date = match NaiveDate::parse_from_str(field(date_idx), &mapping.date_format) {
+            Ok(date) => date,
+            Err(_) => {
+                fail(format!(
+                    "invalid date '{}' (expected format {})",
+                    field(date_idx),
+                    mapping.date_format
+                ));
+                continue;
+            }
+        };
+        let market_cap = match parse_number(field(market_cap_idx), mapping.decimal_separator) {
+            Some(value) if value > 0.0 => value * mapping.multiplier,
+            _ => {
+                fail(format!("invalid market cap '{}'", field(market_cap_idx)));
+                continue;
+            }
+        };
+        let raw_currency = optional_field(currency_idx)
+            .or_else(|| mapping.default_currency.clone())
+            .unwrap_or_default();
+        let Some(currency) = normalize_currency(&raw_currency) else {
+            fail(format!("invalid currency '{}'", raw_currency));
+            continue;
+        };
+        let price = match optional_field(price_idx) {
+            None => None,
+            Some(value) => match parse_number(&value, mapping.decimal_separator) {
+                Some(price) => Some(price * mapping.multiplier),
+                None => {
+                    fail(format!("invalid price '{}'", value));
+                    continue;
+                }
+            },
+        };
+
+        rows.push(ImportRow {
+            name: optional_field(name_idx).unwrap_or_else(|| ticker.clone()),
+            ticker,
+            date,
+            market_cap,
+            currency,
+            exchange: optional_field(exchange_idx),
+            price,
+            isin,
+            lei,
+        });
+    }
+
+    Ok((rows, errors))
+}
+
+/// The CSV files to import: `source` itself, or the `*.csv` files directly in it
+fn collect_files(source: &Path) -> Result<Vec<PathBuf>> {
+    if source.is_file() {
+        return Ok(vec![source.to_path_buf()]);
+    }
+    if !source.is_dir() {
+        anyhow::bail!("{} does not exist", source.display());
+    }
+    let mut files: Vec<PathBuf> = std::fs::read_dir(source)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"))
+        })
+        .collect();
+    files.sort();
+    if files.is_empty() {
+        anyhow::bail!("No CSV files found in {}", source.display());
+    }
+    Ok(files)
+}
+
+fn date_timestamp(date: NaiveDate) -> i64 {
+    NaiveDateTime::new(date, NaiveTime::default())
+        .and_utc()
+        .timestamp()
+}
+
+/// Store validated rows, one snapshot per date, and export each snapshot.
+/// Returns the number of stored rows.
+async fn import_rows(
+    pool: &SqlitePool,
+    rows: Vec<ImportRow>,
+    output: &OutputConfig,
+    report_currencies: &[String],
+    skip_invalid: bool,
+) -> Result<usize> {
+    // Later rows win when a ticker appears twice for the same date
+    let mut by_date: BTreeMap<NaiveDate, BTreeMap<String, ImportRow>> = BTreeMap::new();
+    for row in rows {
+        by_date
+            .entry(row.date)
+            .or_default()
+            .insert(row.ticker.clone(), row);
+    }
+
+    // Check every currency against the rates of its date before storing anything
+    let forex = config::load_forex_config();
+    let mut rate_maps: BTreeMap<NaiveDate, HashMap<String, f64>> = BTreeMap::new();
+    let mut errors = Vec::new();
+    let mut gap_dates = 0;
+    for (date, rows) in by_date.iter_mut() {
+        let (rate_map, gaps) =
+            get_rate_map_with_gaps(pool, Some(date_timestamp(*date)), &forex).await?;
+        if !gaps.is_empty() {
+            gap_dates += 1;
+        }
+        let mut convertible: HashMap<String, bool> = HashMap::new();
+        rows.retain(|ticker, row| {
+            let ok = *convertible.entry(row.currency.clone()).or_insert_with(|| {
+                ["EUR", "USD"].iter().all(|target| {
+                    convert_currency_with_rate(1.0, &row.currency, target, &rate_map).rate_source
+                        != "not_found"
+                })
+            });
+            if !ok {
+                errors.push(format!(
+                    "{} on {}: no EUR/USD exchange rate for {}",
+                    ticker, date, row.currency
+                ));
+            }
+            ok
+        });
+        rate_maps.insert(*date, rate_map);
+    }
+    if gap_dates > 0 {
+        run_context::record_warning(format!(
+            "Exchange rates were stale, interpolated or missing on {} of {} dates",
+            gap_dates,
+            by_date.len()
+        ));
+        println!(
+            "⚠️  Exchange rates were stale, interpolated or missing on {} of {} dates",
+            gap_dates,
+            by_date.len()
+        );
+    }
+    report_errors(&errors, skip_invalid, "without a usable exchange rate")?;
+
+    let imported_at = models::fetched_at(Utc::now());
+    let mut tx = pool.begin().await?;
+    let mut stored = 0;
+    for (date, rows) in &by_date {
+        let rate_map = &rate_maps[date];
+        for row in rows.values() {
+            let eur = convert_currency_with_rate(row.market_cap, &row.currency, "EUR", rate_map);
+            let usd = convert_currency_with_rate(row.market_cap, &row.currency, "USD", rate_map);
+            sqlx::query(
+                r#"
+                INSERT OR REPLACE INTO market_caps (
+                    ticker, name, market_cap_original, original_currency,
+                    market_cap_eur, market_cap_usd, eur_rate, usd_rate,
+                    exchange, price, active, timestamp, data_source, fetched_at
+                )
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(&row.ticker)
+            .bind(&row.name)
+            .bind(row.market_cap)
+            .bind(&row.currency)
+            .bind(eur.amount)
+            .bind(usd.amount)
+            .bind(eur.rate)
+            .bind(usd.rate)
+            .bind(&row.exchange)
+            .bind(row.price)
+            .bind(true)
+            .bind(date_timestamp(*date))
+            .bind(DataSource::Import.as_str())
+            .bind(&imported_at)
+            .execute(&mut *tx)
+            .await?;
+            stored += 1;
+        }
+    }
+    tx.commit().await?;
+
+    for row in by_date.values().flat_map(|rows| rows.values()) {
+        if row.isin.is_some() || row.lei.is_some() {
+            identifiers::store_identifiers(
+                pool,
+                &row.ticker,
+                row.isin.as_deref(),
+                row.lei.as_deref(),
+            )
+            .await?;
+        }
+    }
+
+    let report_currencies = extra_report_currencies(report_currencies);
+    for (date, rows) in &by_date {
+        if rows.is_empty() {
+            continue;
+        }
+        let date_str = date.format("%Y-%m-%d").to_string();
+        let tickers: Vec<String> = rows.keys().cloned().collect();
+        universe::record_universe(pool, &date_str, &tickers).await?;
+        rankings::record_rankings(pool, date_timestamp(*date)).await?;
+        export_specific_date_marketcaps(
+            pool,
+            &Snapshot::daily(*date),
+            output,
+            "marketcaps",
+            &tickers,
+            &report_currencies,
+            &rate_maps[date],
+            None,
+            Store::Universe,
+        )
+        .await?;
+    }
+
+    Ok(stored)
+}
+
+/// Fill in the ticker of rows that only have an ISIN from the ISINs in
+/// `by_isin`; rows with an unknown ISIN are returned as errors
+fn resolve_isins(
+    rows: Vec<ImportRow>,
+    by_isin: &HashMap<String, String>,
+) -> (Vec<ImportRow>, Vec<String>) {
+    let mut errors = Vec::new();
+    let rows = rows
+        .into_iter()
+        .filter_map(|mut row| {
+            if row.ticker.is_empty() {
+                let isin = row.isin.clone().unwrap_or_default();
+                let Some(ticker) = by_isin.get(&isin) else {
+                    errors.push(format!("{} on {}: unknown ISIN", isin, row.date));
+                    return None;
+                };
+                row.ticker = ticker.clone();
+                if row.name.is_empty() {
+                    row.name = ticker.clone();
+                }
+            }
+            Some(row)
+        })
+        .collect();
+    (rows, errors)
+}
+
+/// Print invalid rows; they abort the import unless `skip_invalid` is set
+fn report_errors(errors: &[String], skip_invalid: bool, what: &str) -> Result<()> {
+    if errors.is_empty() {
+        return Ok(());
+    }
+    eprintln!("❌ {} rows {}:", errors.len(), what);
+    for error in errors.iter().take(MAX_REPORTED_ERRORS) {
+        eprintln!("   {}", error);
+    }
+    if errors.len() > MAX_REPORTED_ERRORS {
+        eprintln!("   ... and {} more", errors.len() - MAX_REPORTED_ERRORS);
+    }
+    if !skip_invalid {
+        anyhow::bail!(
+            "Nothing was imported; fix the rows above or pass --skip-invalid to import the rest"
+        );
+    }
+    Ok(())
+}
+
+/// Import market caps from a CSV file, or every CSV file in a directory
+pub async fn import_marketcaps(
+    pool: &SqlitePool,
+    source: &Path,
+    mapping_path: &Path,
+    report_currencies: &[String],
+    skip_invalid: bool,
+) -> Result<()> {
+    let mapping = Mapping::load(mapping_path)?;
+    let files = collect_files(source)?;
+
+    let mut rows = Vec::new();
+    let mut errors = Vec::new();
+    for file in &files {
+        let (file_rows, file_errors) = parse_file(file, &mapping)?;
+        println!("📊 {}: {} valid rows", file.display(), file_rows.len());
+        rows.extend(file_rows);
+        errors.extend(file_errors);
+    }
+    report_errors(&errors, skip_invalid, "are invalid")?;
+    let (rows, errors) = resolve_isins(rows, &identifiers::tickers_by_isin(pool).await?);
+    report_errors(
+        &errors,
+        skip_invalid,
+        "have an ISIN without a stored ticker",
+    )?;
+    if rows.is_empty() {
+        anyhow::bail!("No rows to import");
+    }
+
+    let output = config::load_output_config();
+    let stored = import_rows(pool, rows, &output, report_currencies, skip_invalid).await?;
+    println!(
+        "✅ Imported {} market caps from {} files",
+        stored,
+        files.len()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    const MAPPING: &str = r#"
+date_format = "%d/%m/%Y"
+delimiter = ";"
+decimal_separator = ","
+multiplier = 1000000
+
+[columns]
+ticker = "Symbol"
+date = "As Of"
+market_cap = "Mkt Cap (m)"
+currency = "Ccy"
+name = "Company"
+"#;
+
+    fn write(dir: &Path, name: &str, content: &str) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_parse_number() {
+        assert_eq!(parse_number("1,234.5", '.'), Some(1234.5));
+        assert_eq!(parse_number(" 1.234,5 ", ','), Some(1234.5));
+        assert_eq!(parse_number("12 000", '.'), Some(12000.0));
+        assert_eq!(parse_number("n/a", '.'), None);
+        assert_eq!(parse_number("", '.'), None);
+    }
+
+    #[test]
+    fn test_normalize_currency() {
+        assert_eq!(normalize_currency(" eur "), Some("EUR".to_string()));
+        assert_eq!(normalize_currency("GBp"), Some("GBp".to_string()));
+        assert_eq!(normalize_currency("EURO"), None);
+        assert_eq!(normalize_currency("E1R"), None);
+    }
+
+    #[test]
+    fn test_mapping_requires_a_currency() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write(
+            dir.path(),
+            "mapping.toml",
+            "[columns]\nticker = \"T\"\ndate = \"D\"\nmarket_cap = \"M\"\n",
+        );
+        assert!(Mapping::load(&path).is_err());
+
+        let path = write(dir.path(), "mapping.toml", MAPPING);
+        let mapping = Mapping::load(&path).unwrap();
+        assert_eq!(mapping.delimiter, ';');
+        assert_eq!(mapping.columns.exchange, None);
+    }
+
+    #[test]
+    fn test_parse_file_maps_columns_and_reports_invalid_rows() {
+        let dir = tempfile::tempdir().unwrap();
+        let mapping: Mapping = toml::from_str(MAPPING).unwrap();
+        let csv = write(
+            dir.path(),
+            "legacy.csv",
+            "Symbol;Company;As Of;Mkt Cap (m);Ccy\n\
+             MC.PA;LVMH;31/12/2019;\"210.123,5\";eur\n\
+             NKE;Nike;2019-12-31;150000;USD\n\
+             ITX.MC;Inditex;31/12/2019;-5;EUR\n\
+             HM-B.ST;H&M;31/12/2019;300000;SEKX\n",
+        );
+
+        let (rows, errors) = parse_file(&csv, &mapping).unwrap();
+        assert_eq!(
+            rows,
+            vec![ImportRow {
+                ticker: "MC.PA".to_string(),
+                name: "LVMH".to_string(),
+                date: NaiveDate::from_ymd_opt(2019, 12, 31).unwrap(),
+                market_cap: 210_123_500_000.0,
+                currency: "EUR".to_string(),
+                exchange: None,
+                price: None,
+                isin: None,
+                lei: None,
+            }]
+        );
+        assert_eq!(errors.len(), 3);
+        assert!(errors[0].ends_with(":3: invalid date '2019-12-31' (expected format %d/%m/%Y)"));
+        assert!(errors[1].contains("invalid market cap '-5'"));
+        assert!(errors[2].contains("invalid currency 'SEKX'"));
+
+        // A mapped column missing from the file fails the whole file
+        let csv = write(dir.path(), "other.csv", "Symbol;As Of\nNKE;31/12/2019\n");
+        assert!(parse_file(&csv, &mapping).is_err());
+    }
+
+    #[test]
+    fn test_parse_file_reads_isin_and_lei_without_ticker() {
+        let dir = tempfile::tempdir().unwrap();
+        let mapping: Mapping = toml::from_str(
+            "default_currency = \"USD\"\n[columns]\nisin = \"ISIN\"\nlei = \"LEI\"\ndate = \"D\"\nmarket_cap = \"M\"\n",
+        )
+        .unwrap();
+        mapping.validate().unwrap();
+        let csv = write(
+            dir.path(),
+            "by_isin.csv",
+            "ISIN,LEI,D,M\n\
+             us6541061031,787RXPR0UX0O0XUXPZ81,2019-12-31,150\n\
+             US6541061032,,2019-12-31,150\n\
+             ,,2019-12-31,150\n",
+        );
+
+        let (rows, errors) = parse_file(&csv, &mapping).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].ticker, "");
+        assert_eq!(rows[0].isin.as_deref(), Some("US6541061031"));
+        assert_eq!(rows[0].lei.as_deref(), Some("787RXPR0UX0O0XUXPZ81"));
+        assert!(errors[0].contains("invalid ISIN 'US6541061032'"));
+        assert!(errors[1].contains("empty ticker"));
+
+        let by_isin = HashMap::from([("US6541061031".to_string(), "NKE".to_string())]);
+        let mut unknown = rows[0].clone();
+        unknown.isin = Some("FR0000121014".to_string());
+        let (resolved, errors) = resolve_isins(vec![rows[0].clone(), unknown], &by_isin);
+        assert_eq!(resolved.len(), 1);
+        assert_eq!(
+            (resolved[0].ticker.as_str(), resolved[0].name.as_str()),
+            ("NKE", "NKE")
+        );
+        assert_eq!(errors, vec!["FR0000121014 on 2019-12-31: unknown ISIN"]);
+
+        // Without a ticker or ISIN column rows can't be identified
+        let mapping: Mapping = toml::from_str(
+            "default_currency = \"USD\"\n[columns]\ndate = \"D\"\nmarket_cap = \"M\"\n",
+        )
+        .unwrap();
+        assert!(mapping.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_import_rows_stores_and_exports_snapshots() {
+        let pool = db::create_db_pool("sqlite::memory:").await.unwrap();
+        sqlx::query("ALTER TABLE ticker_details ADD COLUMN ceo TEXT")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let date = NaiveDate::from_ymd_opt(2019, 12, 31).unwrap();
+        crate::currencies::insert_forex_rate(&pool, "EUR/USD", 1.1, 1.1, date_timestamp(date))
+            .await
+            .unwrap();
+
+        let row = |ticker: &str, market_cap: f64, currency: &str| ImportRow {
+            ticker: ticker.to_string(),
+            name: ticker.to_string(),
+            date,
+            market_cap,
+            currency: currency.to_string(),
+            exchange: None,
+            price: None,
+            isin: None,
+            lei: None,
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let output = OutputConfig {
+            directory: dir.path().display().to_string(),
+            ..OutputConfig::default()
+        };
+
+        // A currency without rates aborts the import before anything is stored
+        let rows = vec![row("MC.PA", 200.0, "EUR"), row("HM-B.ST", 300.0, "SEK")];
+        assert!(
+            import_rows(&pool, rows.clone(), &output, &[], false)
+                .await
+                .is_err()
+        );
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM market_caps")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 0);
+
+        let mut rows = rows;
+        rows.push(row("NKE", 110.0, "USD"));
+        let stored = import_rows(&pool, rows, &output, &[], true).await.unwrap();
+        assert_eq!(stored, 2);
+
+        let usd: f64 = sqlx::query_scalar(
+            "SELECT market_cap_usd FROM market_caps WHERE ticker = 'MC.PA' AND timestamp = ?",
+        )
+        .bind(date_timestamp(date))
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert!((usd - 220.0).abs() < 1e-9);
+        let universe = universe::get_universe(&pool, "2019-12-31").await.unwrap();
+        assert_eq!(universe.unwrap().len(), 2);
+
+        let exported: Vec<String> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|name| name.starts_with("marketcaps_2019-12-31_"))
+            .collect();
+        assert_eq!(exported.len(), 2);
+        assert!(exported.contains(&"marketcaps_2019-12-31_by_region.csv".to_string()));
+    }
+}
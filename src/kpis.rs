@@ -0,0 +1,216 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Custom KPIs defined as expressions in config.toml (`[kpis]`)
+//!
+//! ```toml
+//! [kpis]
+//! ev_to_sales = "market_cap / revenue_usd"
+//! ```
+//!
+//! Each KPI is evaluated per company when `export-combined` writes its CSVs
+//! and becomes an extra column named after the KPI; `--rank-by <name>` orders
+//! the export by it. Expressions use numbers, the fields in [`VARIABLES`],
+//! `+ - * /` and parentheses (see [`crate::expression`]). A KPI is empty for a company when a field it
+//! uses is missing or it divides by zero.
+
+use std::collections::BTreeMap;
+
+use crate::error::Error;
+use crate::expression::{self, Expr, Kind};
+
+pub use crate::expression::Inputs;
+
+/// Company fields an expression can use. `market_cap` is the USD market cap.
+pub const VARIABLES: [&str; 14] = [
+    "market_cap",
+    "market_cap_usd",
+    "market_cap_eur",
+    "market_cap_original",
+    "price",
+    "employees",
+    "revenue",
+    "revenue_usd",
+    "eps",
+    "pe_ratio",
+    "de_ratio",
+    "roe",
+    "working_capital_ratio",
+    "quick_ratio",
+];
+
+/// One parsed KPI
+#[derive(Debug, Clone, PartialEq)]
+pub struct Kpi {
+    pub name: String,
+    pub expression: String,
+    expr: Expr,
+}
+
+impl Kpi {
+    pub fn parse(name: &str, expression: &str) -> Result<Self, Error> {
+        let invalid = |reason: String| Error::ConfigInvalid(format!("[kpis] {}: {}", name, reason));
+        let valid_name =
+            name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') && !name.is_empty();
+        if !valid_name {
+            return Err(invalid(
+                "names may only contain letters, digits and underscores".to_string(),
+            ));
+        }
+        if VARIABLES.contains(&name) {
+            return Err(invalid("name is already a company field".to_string()));
+        }
+
+        let fields = |field: &str| VARIABLES.contains(&field).then_some(Kind::Number);
+        let expr = expression::parse(expression, &fields).map_err(invalid)?;
+        if expr.kind() != Kind::Number {
+            return Err(invalid(format!("must be a number, not {}", expr.kind())));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            expression: expression.to_string(),
+            expr,
+        })
+    }
+
+    pub fn evaluate(&self, inputs: &Inputs) -> Option<f64> {
+        self.expr.number(inputs)
+    }
+}
+
+/// The KPIs of a run, in name order, and the one exports are ranked by
+#[derive(Debug, Clone, Default)]
+pub struct Kpis {
+    pub kpis: Vec<Kpi>,
+    rank_by: Option<usize>,
+}
+
+impl Kpis {
+    pub fn from_definitions(definitions: &BTreeMap<String, String>) -> Result<Self, Error> {
+        let kpis = definitions
+            .iter()
+            .map(|(name, expression)| Kpi::parse(name, expression))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            kpis,
+            rank_by: None,
+        })
+    }
+
+    /// KPIs from config.toml; an invalid expression is an error rather than
+    /// a silently missing column
+    pub fn load() -> Result<Self, Error> {
+        Self::from_definitions(&crate::config::load_kpis())
+    }
+
+    /// Rank exports by the named KPI instead of the EUR market cap
+    pub fn rank_by(mut self, name: Option<&str>) -> anyhow::Result<Self> {
+        if let Some(name) = name {
+            let index = self.kpis.iter().position(|k| k.name == name);
+            let Some(index) = index else {
+                anyhow::bail!(
+                    "Unknown KPI {:?} for --rank-by; define it in [kpis] of config.toml",
+                    name
+                );
+            };
+            self.rank_by = Some(index);
+        }
+        Ok(self)
+    }
+
+    /// Column names of the KPIs, after the regular columns
+    pub fn headers(&self) -> impl Iterator<Item = String> + '_ {
+        self.kpis.iter().map(|k| k.name.clone())
+    }
+
+    pub fn evaluate(&self, inputs: &Inputs) -> Vec<Option<f64>> {
+        self.kpis.iter().map(|k| k.evaluate(inputs)).collect()
+    }
+
+    /// Value to rank by among evaluated KPIs, if ranking by a KPI
+    pub fn rank_value(&self, values: &[Option<f64>]) -> Option<Option<f64>> {
+        self.rank_by.map(|i| values.get(i).copied().flatten())
+    }
+}
+
+/// CSV cell of a KPI value: empty when it can't be computed
+pub fn format_value(value: Option<f64>) -> String {
+    value.map(|v| format!("{:.4}", v)).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inputs() -> Inputs {
+        let mut inputs = Inputs::default();
+        inputs
+            .set_number("market_cap", Some(300.0))
+            .set_number("revenue_usd", Some(100.0))
+            .set_number("employees", Some(4.0))
+            .set_number("eps", None);
+        inputs
+    }
+
+    fn eval(expression: &str) -> Option<f64> {
+        Kpi::parse("kpi", expression).unwrap().evaluate(&inputs())
+    }
+
+    #[test]
+    fn test_evaluate_precedence_and_parentheses() {
+        assert_eq!(eval("market_cap / revenue_usd"), Some(3.0));
+        assert_eq!(eval("market_cap - revenue_usd * 2"), Some(100.0));
+        assert_eq!(eval("(market_cap - revenue_usd) / employees"), Some(50.0));
+        assert_eq!(eval("-market_cap / 3 + 1.5"), Some(-98.5));
+        assert_eq!(eval("10 - 4 - 3"), Some(3.0));
+    }
+
+    #[test]
+    fn test_missing_fields_and_division_by_zero_are_empty() {
+        assert_eq!(eval("market_cap / eps"), None);
+        assert_eq!(eval("market_cap / (employees - 4)"), None);
+        assert_eq!(format_value(None), "");
+        assert_eq!(format_value(Some(3.0)), "3.0000");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let message =
+            |name: &str, expression: &str| Kpi::parse(name, expression).unwrap_err().to_string();
+        assert!(message("ev", "market_cap / sales").contains("unknown field \"sales\""));
+        assert!(message("ev", "(market_cap / revenue").contains("missing closing parenthesis"));
+        assert!(message("ev", "market_cap revenue").contains("unexpected"));
+        assert!(message("ev", "market_cap %").contains("unexpected character"));
+        assert!(message("ev", "").contains("unexpected end"));
+        assert!(message("ev", "market_cap > 1").contains("must be a number"));
+        assert!(message("ev-to-sales", "1").contains("names may only contain"));
+        assert!(message("price", "1").contains("already a company field"));
+    }
+
+    #[test]
+    fn test_rank_by() {
+        let definitions = BTreeMap::from([
+            (
+                "per_employee".to_string(),
+                "market_cap / employees".to_string(),
+            ),
+            (
+                "ev_to_sales".to_string(),
+                "market_cap / revenue_usd".to_string(),
+            ),
+        ]);
+        let kpis = Kpis::from_definitions(&definitions).unwrap();
+        assert_eq!(
+            kpis.headers().collect::<Vec<_>>(),
+            vec!["ev_to_sales", "per_employee"]
+        );
+        let values = kpis.evaluate(&inputs());
+        assert_eq!(values, vec![Some(3.0), Some(75.0)]);
+        assert_eq!(kpis.rank_value(&values), None);
+
+        let kpis = kpis.rank_by(Some("per_employee")).unwrap();
+        assert_eq!(kpis.rank_value(&values), Some(Some(75.0)));
+        assert!(Kpis::default().rank_by(Some("ev_to_sales")).is_err());
+    }
+}
@@ -0,0 +1,94 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Library of the `top200-rs` binary
+//!
+//! Every command of the CLI is built from these modules, so other Rust
+//! services can depend on the crate directly. [`client`] wraps the snapshot,
+//! comparison and trend commands in functions that return typed results
+//! instead of printing and writing files.
+
+pub mod advanced_comparisons;
+pub mod aggregates;
+pub mod analyst;
+pub mod api;
+pub mod api_cache;
+pub mod api_keys;
+pub mod api_schema;
+pub mod api_usage;
+pub mod backup;
+pub mod ceo_changes;
+pub mod chart_theme;
+pub mod circuit_breaker;
+pub mod client;
+pub mod clock;
+pub mod company_profile;
+pub mod company_report;
+pub mod compare_marketcaps;
+pub mod concentration;
+pub mod config;
+pub mod corporate_actions;
+pub mod currencies;
+pub mod data_package;
+pub mod data_quality;
+pub mod db;
+pub mod db_stats;
+pub mod details_eu_fmp;
+pub mod details_us_polygon;
+pub mod digest;
+pub mod earnings;
+pub mod efficiency;
+pub mod error;
+pub mod exchange_rates;
+pub mod expression;
+pub mod fmp_keys;
+pub mod forex;
+pub mod geo;
+#[cfg(test)]
+mod golden_tests;
+pub mod historical_marketcaps;
+pub mod identifiers;
+pub mod import_marketcaps;
+pub mod kpis;
+pub mod locale;
+pub mod logos;
+pub mod marketcaps;
+pub mod metrics;
+pub mod models;
+pub mod monthly_historical_marketcaps;
+pub mod nats;
+pub mod notify;
+pub mod outliers;
+pub mod peer_momentum;
+pub mod point_in_time;
+pub mod polygon_snapshot;
+pub mod progress;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod rankings;
+pub mod rate_limit;
+pub mod reconcile;
+pub mod regions;
+pub mod report;
+pub mod retry;
+pub mod run_context;
+pub mod screener;
+pub mod search;
+pub mod shutdown;
+pub mod snapshot_diff;
+pub mod snapshot_labels;
+pub mod specific_date_marketcaps;
+pub mod storage;
+pub mod subunits;
+pub mod symbol_changes;
+pub mod ticker_aliases;
+pub mod ticker_details;
+pub mod trading_calendar;
+pub mod universe;
+pub mod universe_changes;
+pub mod utils;
+pub mod vega;
+pub mod visualizations;
+pub mod watchlists;
+pub mod web;
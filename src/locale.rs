@@ -0,0 +1,255 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Report localization
+//!
+//! Translation tables live in `locales/<code>.toml` and are compiled into the
+//! binary. Each table has the report texts (with `{placeholder}`s) plus number,
+//! percentage and date formats. Missing messages fall back to English. The
+//! locale of a run is set once with `--locale` and read by the report writers.
+
+use anyhow::Result;
+use chrono::{Datelike, NaiveDate, NaiveDateTime};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Languages reports can be written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Locale {
+    #[default]
+    En,
+    De,
+    Fr,
+    Nl,
+}
+
+impl Locale {
+    pub fn parse(code: &str) -> Result<Self> {
+        match code.trim().to_lowercase().as_str() {
+            "en" => Ok(Locale::En),
+            "de" => Ok(Locale::De),
+            "fr" => Ok(Locale::Fr),
+            "nl" => Ok(Locale::Nl),
+            other => anyhow::bail!("Unsupported locale '{}': use en, de, fr or nl", other),
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::De => "de",
+            Locale::Fr => "fr",
+            Locale::Nl => "nl",
+        }
+    }
+
+    fn source(&self) -> &'static str {
+        match self {
+            Locale::En => include_str!("../locales/en.toml"),
+            Locale::De => include_str!("../locales/de.toml"),
+            Locale::Fr => include_str!("../locales/fr.toml"),
+            Locale::Nl => include_str!("../locales/nl.toml"),
+        }
+    }
+}
+
+/// Messages and formats of one locale
+#[derive(Debug, Clone, Deserialize)]
+pub struct Translations {
+    decimal_separator: String,
+    thousands_separator: String,
+    percent: String,
+    date: String,
+    months: Vec<String>,
+    messages: HashMap<String, String>,
+    #[serde(skip)]
+    fallback: HashMap<String, String>,
+}
+
+fn fill(template: &str, args: &[(&str, &str)]) -> String {
+    args.iter()
+        .fold(template.to_string(), |text, (name, value)| {
+            text.replace(&format!("{{{}}}", name), value)
+        })
+}
+
+impl Translations {
+    pub fn load(locale: Locale) -> Result<Self> {
+        let mut translations: Translations = toml::from_str(locale.source())?;
+        if locale != Locale::En {
+            let english: Translations = toml::from_str(Locale::En.source())?;
+            translations.fallback = english.messages;
+        }
+        if translations.months.len() != 12 {
+            anyhow::bail!("Locale '{}' must list 12 month names", locale.code());
+        }
+        Ok(translations)
+    }
+
+    /// Message `key` with its placeholders filled in
+    pub fn t(&self, key: &str, args: &[(&str, &str)]) -> String {
+        let template = self
+            .messages
+            .get(key)
+            .or_else(|| self.fallback.get(key))
+            .map(String::as_str)
+            .unwrap_or(key);
+        fill(template, args)
+    }
+
+    /// Number with `decimals` digits and the locale's separators
+    pub fn number(&self, value: f64, decimals: usize) -> String {
+        let formatted = format!("{:.*}", decimals, value.abs());
+        let (integer, fraction) = formatted
+            .split_once('.')
+            .map_or((formatted.as_str(), None), |(i, f)| (i, Some(f)));
+
+        let mut grouped = String::new();
+        for (i, digit) in integer.chars().enumerate() {
+            if i > 0 && (integer.len() - i) % 3 == 0 {
+                grouped.push_str(&self.thousands_separator);
+            }
+            grouped.push(digit);
+        }
+
+        let sign = if value < 0.0 && formatted.chars().any(|c| c.is_ascii_digit() && c != '0') {
+            "-"
+        } else {
+            ""
+        };
+        match fraction {
+            Some(fraction) => format!("{}{}{}{}", sign, grouped, self.decimal_separator, fraction),
+            None => format!("{}{}", sign, grouped),
+        }
+    }
+
+    /// Number with a `+` in front of positive values that don't round to zero
+    pub fn signed_number(&self, value: f64, decimals: usize) -> String {
+        let number = self.number(value, decimals);
+        if value > 0.0 && number.chars().any(|c| c.is_ascii_digit() && c != '0') {
+            format!("+{}", number)
+        } else {
+            number
+        }
+    }
+
+    /// Percentage with two decimals; `signed` adds a `+` to positive values
+    pub fn percent(&self, value: f64, signed: bool) -> String {
+        let number = self.number(value, 2);
+        let number = if signed && value > 0.0 {
+            format!("+{}", number)
+        } else {
+            number
+        };
+        fill(&self.percent, &[("value", &number)])
+    }
+
+    pub fn date(&self, date: NaiveDate) -> String {
+        fill(
+            &self.date,
+            &[
+                ("yyyy", &date.year().to_string()),
+                ("mm", &format!("{:02}", date.month())),
+                ("dd", &format!("{:02}", date.day())),
+                ("d", &date.day().to_string()),
+                ("month", &self.months[date.month0() as usize]),
+            ],
+        )
+    }
+
+    /// A `YYYY-MM-DD` string as a localized date; other text is kept as is
+    pub fn date_str(&self, value: &str) -> String {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .map(|date| self.date(date))
+            .unwrap_or_else(|_| value.to_string())
+    }
+
+    /// "Generated on" line for the end of a report
+    pub fn generated_on(&self, at: NaiveDateTime) -> String {
+        self.t(
+            "generated_on",
+            &[
+                ("date", &self.date(at.date())),
+                ("time", &at.format("%H:%M:%S").to_string()),
+            ],
+        )
+    }
+}
+
+static CURRENT: OnceLock<Translations> = OnceLock::new();
+
+/// Set the locale for this run; later calls are ignored
+pub fn init(locale: Locale) -> Result<()> {
+    let translations = Translations::load(locale)?;
+    let _ = CURRENT.set(translations);
+    Ok(())
+}
+
+/// Translations of the locale set with `init`, English when none was set
+pub fn current() -> &'static Translations {
+    CURRENT.get_or_init(|| Translations::load(Locale::En).expect("English locale must parse"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_all_locales_load_with_every_message() {
+        let english = Translations::load(Locale::En).unwrap();
+        for locale in [Locale::De, Locale::Fr, Locale::Nl] {
+            let translations = Translations::load(locale).unwrap();
+            for key in english.messages.keys() {
+                assert!(
+                    translations.messages.contains_key(key),
+                    "{} is missing '{}'",
+                    locale.code(),
+                    key
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_english_matches_plain_formatting() {
+        let en = Translations::load(Locale::En).unwrap();
+        assert_eq!(en.number(1234567.891, 2), "1234567.89");
+        assert_eq!(en.percent(12.345, true), "+12.35%");
+        assert_eq!(en.percent(-3.5, true), "-3.50%");
+        assert_eq!(en.signed_number(2.5, 2), "+2.50");
+        assert_eq!(en.signed_number(0.001, 2), "0.00");
+        assert_eq!(
+            en.date(NaiveDate::from_ymd_opt(2025, 3, 7).unwrap()),
+            "2025-03-07"
+        );
+        assert_eq!(
+            en.t("comparison_title", &[("from", "A"), ("to", "B")]),
+            "Market Cap Comparison: A to B"
+        );
+    }
+
+    #[test]
+    fn test_localized_formats() {
+        let de = Translations::load(Locale::De).unwrap();
+        assert_eq!(de.number(1234567.891, 2), "1.234.567,89");
+        assert_eq!(de.number(-0.001, 2), "0,00");
+        assert_eq!(de.percent(-12.5, true), "-12,50 %");
+        assert_eq!(de.date_str("2025-03-07"), "7. März 2025");
+
+        let fr = Translations::load(Locale::Fr).unwrap();
+        assert_eq!(fr.number(-9876.5, 1), "-9\u{202f}876,5");
+        assert_eq!(fr.date_str("2025-12-01"), "1 décembre 2025");
+
+        let nl = Translations::load(Locale::Nl).unwrap();
+        assert_eq!(
+            nl.t("top_gainers", &[("n", "10")]),
+            "Top 10 stijgers (procentueel)"
+        );
+        assert_eq!(nl.date_str("not a date"), "not a date");
+
+        assert!(Locale::parse("es").is_err());
+        assert_eq!(Locale::parse("DE").unwrap(), Locale::De);
+    }
+}
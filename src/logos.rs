@@ -0,0 +1,377 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Company logos (`fetch-logos`)
+//!
+//! Logos are downloaded once into `<output>/assets/logos/<TICKER>.png` and
+//! reused until they are older than `[logos] max_age_days`. Nothing else
+//! downloads them: charts and HTML pages only embed logos that are already in
+//! the cache, so reports look the same as before until `fetch-logos` has run.
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::config::{self, LogoConfig, OutputConfig};
+use crate::run_context;
+
+/// Directory of the logo cache below the output directory
+pub fn logo_dir(output: &OutputConfig) -> PathBuf {
+    output.directory().join("assets").join("logos")
+}
+
+/// File name of a ticker's logo; characters that are not safe in a path
+/// (e.g. `/` in `BRK/B`) become `_`
+pub fn file_name(ticker: &str) -> String {
+    let safe: String = ticker
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{}.png", safe)
+}
+
+/// Download URL of a ticker's logo
+pub fn logo_url(config: &LogoConfig, ticker: &str) -> String {
+    config.url_template.replace("{ticker}", ticker)
+}
+
+/// Path of the cached logo, if it has been downloaded
+pub fn cached_logo(output: &OutputConfig, ticker: &str) -> Option<PathBuf> {
+    let path = logo_dir(output).join(file_name(ticker));
+    let size = fs::metadata(&path).ok()?.len();
+    (size > 0).then_some(path)
+}
+
+/// Whether a cached logo is younger than `max_age_days`
+fn is_fresh(path: &Path, max_age_days: i64, now: SystemTime) -> bool {
+    let Ok(modified) = fs::metadata(path).and_then(|m| m.modified()) else {
+        return false;
+    };
+    let max_age = Duration::from_secs(max_age_days.max(0) as u64 * 24 * 60 * 60);
+    now.duration_since(modified)
+        .map(|age| age < max_age)
+        .unwrap_or(true)
+}
+
+/// Outcome of `fetch-logos` for one run
+#[derive(Debug, Default, PartialEq)]
+pub struct FetchSummary {
+    pub downloaded: usize,
+    pub cached: usize,
+    pub failed: Vec<String>,
+}
+
+/// Accept only PNG responses, since the cache stores every logo as
+/// `<TICKER>.png` and embeds it with the `image/png` type
+fn check_png(content_type: &str) -> Result<()> {
+    let mime = content_type.split(';').next().unwrap_or_default().trim();
+    if !mime.eq_ignore_ascii_case("image/png") {
+        anyhow::bail!(
+            "unexpected content type '{}', expected image/png",
+            content_type
+        );
+    }
+    Ok(())
+}
+
+/// Download one logo, writing through a temporary file so that an
+/// interrupted download never leaves a truncated PNG in the cache
+async fn download_logo(client: &reqwest::Client, url: &str, path: &Path) -> Result<()> {
+    let response = client.get(url).send().await?;
+    if !response.status().is_success() {
+        anyhow::bail!("HTTP {}", response.status());
+    }
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    check_png(&content_type)?;
+    let bytes = response.bytes().await?;
+    if bytes.is_empty() {
+        anyhow::bail!("empty response");
+    }
+    let tmp = path.with_extension("png.part");
+    fs::write(&tmp, &bytes)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Download logos of the given tickers into the cache; fresh logos are
+/// skipped unless `refresh` is set
+pub async fn fetch_logos(
+    tickers: &[String],
+    output: &OutputConfig,
+    config: &LogoConfig,
+    refresh: bool,
+) -> Result<FetchSummary> {
+    let dir = logo_dir(output);
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+
+    let client = crate::api::http_client();
+    let now = SystemTime::now();
+    let mut summary = FetchSummary::default();
+    for ticker in tickers {
+        let path = dir.join(file_name(ticker));
+        if !refresh && is_fresh(&path, config.max_age_days, now) {
+            summary.cached += 1;
+            continue;
+        }
+        match download_logo(&client, &logo_url(config, ticker), &path).await {
+            Ok(()) => {
+                run_context::record_output(&path);
+                summary.downloaded += 1;
+            }
+            Err(e) => {
+                run_context::record_warning(format!("No logo for {}: {}", ticker, e));
+                summary.failed.push(ticker.clone());
+            }
+        }
+    }
+    Ok(summary)
+}
+
+/// `fetch-logos`: download the logos of every configured ticker
+pub async fn fetch_logos_command(refresh: bool) -> Result<()> {
+    let config = config::load_config()?;
+    let tickers: Vec<String> = config
+        .us_tickers
+        .iter()
+        .chain(config.non_us_tickers.iter())
+        .cloned()
+        .collect();
+
+    println!("📥 Fetching logos for {} tickers...", tickers.len());
+    let summary = fetch_logos(&tickers, &config.output, &config.logos, refresh).await?;
+
+    println!(
+        "✅ {} downloaded, {} already cached in {}",
+        summary.downloaded,
+        summary.cached,
+        logo_dir(&config.output).display()
+    );
+    if !summary.failed.is_empty() {
+        println!(
+            "⚠️  No logo for {} tickers: {}",
+            summary.failed.len(),
+            summary.failed.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Image file as a `data:` URI, so that an SVG stays self-contained
+pub fn data_uri(path: &Path) -> Option<String> {
+    let mime = match path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .as_deref()
+    {
+        Some("svg") => "image/svg+xml",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("webp") => "image/webp",
+        _ => "image/png",
+    };
+    let bytes = fs::read(path).ok()?;
+    Some(format!(
+        "data:{};base64,{}",
+        mime,
+        base64::engine::general_purpose::STANDARD.encode(bytes)
+    ))
+}
+
+/// SVG `<image>` element showing a ticker's logo scaled into a `size` square
+/// at (`x`, `y`), or `None` when the logo has not been downloaded
+pub fn svg_image(output: &OutputConfig, ticker: &str, x: i32, y: i32, size: u32) -> Option<String> {
+    let uri = data_uri(&cached_logo(output, ticker)?)?;
+    Some(format!(
+        r#"<image x="{}" y="{}" width="{}" height="{}" preserveAspectRatio="xMidYMid meet" href="{}"/>"#,
+        x, y, size, size, uri
+    ))
+}
+
+/// Append elements to a finished SVG file, just before its closing tag
+pub fn embed_in_svg(svg_path: &Path, elements: &[String]) -> Result<()> {
+    if elements.is_empty() {
+        return Ok(());
+    }
+    let svg = fs::read_to_string(svg_path)?;
+    let Some(end) = svg.rfind("</svg>") else {
+        anyhow::bail!("{} is not an SVG document", svg_path.display());
+    };
+    let mut result =
+        String::with_capacity(svg.len() + elements.iter().map(String::len).sum::<usize>());
+    result.push_str(&svg[..end]);
+    for element in elements {
+        result.push_str(element);
+        result.push('\n');
+    }
+    result.push_str(&svg[end..]);
+    fs::write(svg_path, result)?;
+    Ok(())
+}
+
+/// URLs of cached logos under `/reports`, by ticker, for HTML pages
+pub fn report_hrefs<'a>(
+    output: &OutputConfig,
+    tickers: impl IntoIterator<Item = &'a str>,
+) -> HashMap<String, String> {
+    tickers
+        .into_iter()
+        .filter(|ticker| cached_logo(output, ticker).is_some())
+        .map(|ticker| {
+            (
+                ticker.to_string(),
+                format!("/reports/assets/logos/{}", file_name(ticker)),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn output_in(dir: &TempDir) -> OutputConfig {
+        OutputConfig {
+            directory: dir.path().to_string_lossy().to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn write_logo(output: &OutputConfig, ticker: &str, bytes: &[u8]) -> PathBuf {
+        let dir = logo_dir(output);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(file_name(ticker));
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_file_name_keeps_exchange_suffix_and_replaces_slashes() {
+        assert_eq!(file_name("MC.PA"), "MC.PA.png");
+        assert_eq!(file_name("BRK/B"), "BRK_B.png");
+        assert_eq!(file_name("../x"), ".._x.png");
+    }
+
+    #[test]
+    fn test_check_png_rejects_other_image_types() {
+        assert!(check_png("image/png").is_ok());
+        assert!(check_png("Image/PNG; charset=binary").is_ok());
+        assert!(check_png("image/svg+xml").is_err());
+        assert!(check_png("image/jpeg").is_err());
+        assert!(check_png("").is_err());
+    }
+
+    #[test]
+    fn test_logo_url_fills_in_ticker() {
+        let config = LogoConfig::default();
+        assert_eq!(
+            logo_url(&config, "NKE"),
+            "https://financialmodelingprep.com/image-stock/NKE.png"
+        );
+    }
+
+    #[test]
+    fn test_cached_logo_ignores_missing_and_empty_files() {
+        let dir = TempDir::new().unwrap();
+        let output = output_in(&dir);
+        assert!(cached_logo(&output, "NKE").is_none());
+        write_logo(&output, "NKE", b"");
+        assert!(cached_logo(&output, "NKE").is_none());
+        write_logo(&output, "NKE", b"png");
+        assert!(cached_logo(&output, "NKE").is_some());
+    }
+
+    #[test]
+    fn test_is_fresh_respects_max_age() {
+        let dir = TempDir::new().unwrap();
+        let path = write_logo(&output_in(&dir), "NKE", b"png");
+        let now = SystemTime::now();
+        assert!(is_fresh(&path, 30, now));
+        assert!(!is_fresh(
+            &path,
+            30,
+            now + Duration::from_secs(31 * 24 * 60 * 60)
+        ));
+        assert!(!is_fresh(&path, 0, now));
+        assert!(!is_fresh(&dir.path().join("missing.png"), 30, now));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_logos_skips_fresh_cache() {
+        let dir = TempDir::new().unwrap();
+        let output = output_in(&dir);
+        write_logo(&output, "NKE", b"png");
+        let config = LogoConfig {
+            // Unroutable, so a download attempt would fail instead of hitting the network
+            url_template: "http://127.0.0.1:9/{ticker}.png".to_string(),
+            ..Default::default()
+        };
+
+        let summary = fetch_logos(&["NKE".to_string()], &output, &config, false)
+            .await
+            .unwrap();
+        assert_eq!(summary.cached, 1);
+        assert_eq!(summary.downloaded, 0);
+
+        let summary = fetch_logos(&["NKE".to_string()], &output, &config, true)
+            .await
+            .unwrap();
+        assert_eq!(summary.failed, vec!["NKE".to_string()]);
+        // A failed refresh keeps the logo that was already cached
+        assert!(cached_logo(&output, "NKE").is_some());
+    }
+
+    #[test]
+    fn test_svg_image_embeds_cached_logo_as_data_uri() {
+        let dir = TempDir::new().unwrap();
+        let output = output_in(&dir);
+        assert!(svg_image(&output, "NKE", 10, 20, 18).is_none());
+
+        write_logo(&output, "NKE", b"png");
+        let image = svg_image(&output, "NKE", 10, 20, 18).unwrap();
+        assert!(image.contains(r#"x="10" y="20" width="18" height="18""#));
+        assert!(image.contains("data:image/png;base64,cG5n"));
+    }
+
+    #[test]
+    fn test_embed_in_svg_inserts_before_closing_tag() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("chart.svg");
+        fs::write(&path, "<svg><rect/></svg>\n").unwrap();
+
+        embed_in_svg(&path, &["<image/>".to_string()]).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "<svg><rect/><image/>\n</svg>\n"
+        );
+
+        fs::write(dir.path().join("plain.txt"), "text").unwrap();
+        assert!(embed_in_svg(&dir.path().join("plain.txt"), &["<image/>".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_report_hrefs_only_lists_cached_logos() {
+        let dir = TempDir::new().unwrap();
+        let output = output_in(&dir);
+        write_logo(&output, "MC.PA", b"png");
+
+        let hrefs = report_hrefs(&output, ["MC.PA", "NKE"]);
+        assert_eq!(hrefs.len(), 1);
+        assert_eq!(hrefs["MC.PA"], "/reports/assets/logos/MC.PA.png");
+    }
+}
@@ -2,29 +2,22 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-only
 
-mod advanced_comparisons;
-mod api;
-mod compare_marketcaps;
-mod config;
-mod currencies;
-mod db;
-mod details_eu_fmp;
-mod details_us_polygon;
-mod exchange_rates;
-mod historical_marketcaps;
-mod marketcaps;
-mod models;
-mod monthly_historical_marketcaps;
-mod nats;
-mod specific_date_marketcaps;
-mod symbol_changes;
-mod ticker_details;
-mod utils;
-mod visualizations;
-mod web;
-
-use anyhow::Result;
-use clap::{Parser, Subcommand};
+mod cli_docs;
+
+use top200_rs::{
+    advanced_comparisons, aggregates, analyst, api, api_cache, api_keys, api_schema, api_usage,
+    backup, ceo_changes, chart_theme, clock, company_profile, company_report, compare_marketcaps,
+    config, currencies, data_package, data_quality, db, db_stats, details_eu_fmp,
+    details_us_polygon, digest, earnings, efficiency, error, exchange_rates, fmp_keys, geo,
+    historical_marketcaps, identifiers, import_marketcaps, locale, logos, marketcaps,
+    monthly_historical_marketcaps, nats, notify, outliers, peer_momentum, polygon_snapshot,
+    progress, rankings, rate_limit, reconcile, report, retry, run_context, screener, search,
+    shutdown, snapshot_diff, specific_date_marketcaps, storage, subunits, symbol_changes,
+    universe_changes, utils, vega, visualizations, watchlists, web,
+};
+
+use anyhow::{Context, Result};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
 // use sqlx::sqlite::SqlitePool;
 use std::env;
 use tokio;
@@ -34,6 +27,141 @@ use tokio;
 struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
+
+    /// Upload files generated by this run to object storage (e.g. s3://bucket/prefix, gs://bucket/prefix)
+    #[arg(long, global = true)]
+    upload: Option<String>,
+
+    /// Add market cap columns converted to this currency to exports and comparisons (repeatable)
+    #[arg(long = "report-currency", value_name = "CODE", global = true)]
+    report_currencies: Vec<String>,
+
+    /// Restrict comparison commands to the CSVs of a watchlist instead of the whole universe
+    #[arg(long, value_name = "NAME", global = true)]
+    watchlist: Option<String>,
+
+    /// Restrict comparisons to companies in the ticker universe on every compared date
+    #[arg(long, global = true)]
+    consistent_universe: bool,
+
+    /// Leave out companies affected by events in corporate_actions.toml (M&A, spin-offs, ...)
+    #[arg(long, global = true)]
+    exclude_corporate_actions: bool,
+
+    /// Leave companies below this USD market cap on the start date out of comparisons, trends and peer groups (e.g. 1e9)
+    #[arg(long, value_name = "USD", global = true)]
+    min_market_cap: Option<f64>,
+
+    /// Winsorize percentage changes at this percentile on each tail (e.g. 5) for averages in summaries
+    #[arg(long, value_name = "PCT", global = true)]
+    winsorize: Option<f64>,
+
+    /// Maximum number of per-ticker API requests in flight while fetching
+    #[arg(long, value_name = "N", default_value_t = utils::DEFAULT_CONCURRENCY, global = true)]
+    concurrency: usize,
+
+    /// Always call the APIs instead of reusing today's cached responses
+    #[arg(long, global = true)]
+    no_cache: bool,
+
+    /// Retries of a failed API request (default: `[api] max_retries`, 3)
+    #[arg(long, value_name = "N", global = true)]
+    max_retries: Option<u32>,
+
+    /// Seconds to wait before the first retry, doubled per retry (default: `[api] base_delay_secs`, 5)
+    #[arg(long, value_name = "SECS", global = true)]
+    retry_base_delay: Option<u64>,
+
+    /// Longest wait between retries in seconds (default: `[api] max_delay_secs`, 60)
+    #[arg(long, value_name = "SECS", global = true)]
+    retry_max_delay: Option<u64>,
+
+    /// Random extra wait as a fraction of the delay, 0 to 1 (default: `[api] jitter`, 0.1)
+    #[arg(long, value_name = "FRACTION", global = true)]
+    retry_jitter: Option<f64>,
+
+    /// Language of generated reports: en, de, fr or nl
+    #[arg(long, value_name = "CODE", default_value = "en", global = true)]
+    locale: String,
+
+    /// Limit exports, comparisons and charts to the N largest companies
+    #[arg(long, value_name = "N", global = true)]
+    top: Option<usize>,
+
+    /// Run as if today were this date (YYYY-MM-DD or YYYY-MM-DDTHH:MM:SS)
+    #[arg(long, value_name = "DATE", global = true)]
+    as_of: Option<String>,
+
+    /// Write a JSON manifest of the run: command, inputs and outputs with
+    /// SHA-256, API requests and warnings (default: run_manifest_<timestamp>.json
+    /// in the output directory; give a path as --manifest=PATH)
+    // `require_equals` keeps `--manifest compare-market-caps` from taking the
+    // subcommand as the path. A bare flag parses as `Some(None)`; the default
+    // path depends on the output config, so it has no `default_missing_value`.
+    #[arg(
+        long,
+        value_name = "PATH",
+        global = true,
+        require_equals = true,
+        num_args = 0..=1
+    )]
+    manifest: Option<Option<std::path::PathBuf>>,
+
+    /// Write charts as static SVG (svg) or as interactive Vega-Lite JSON specs (vega)
+    #[arg(long, value_name = "BACKEND", default_value = "svg", global = true)]
+    chart_backend: String,
+
+    /// Hide progress bars and per-ticker lines (for CI logs); errors and summaries are still printed
+    #[arg(long, global = true)]
+    quiet: bool,
+
+    /// Fail the run when an exchange rate is missing instead of reporting
+    /// unconverted amounts (also `[forex] strict = true`)
+    #[arg(long, global = true)]
+    strict_currency: bool,
+
+    /// Write a Frictionless `<csv name>.datapackage.json` next to every
+    /// snapshot and comparison CSV (also `[output] data_package = true`)
+    #[arg(long, global = true)]
+    data_package: bool,
+
+    /// Use the universe, peer groups, output directory and database of
+    /// profiles/NAME.toml, layered over config.toml
+    #[arg(long, value_name = "NAME", global = true)]
+    profile: Option<String>,
+
+    /// Print the man page, or write one page per subcommand into DIR
+    /// (give it as --generate-manpage=DIR)
+    // `require_equals`, like `--manifest`, so a following subcommand isn't
+    // taken as DIR
+    #[arg(long, value_name = "DIR", require_equals = true, num_args = 0..=1)]
+    generate_manpage: Option<Option<std::path::PathBuf>>,
+}
+
+/// Config file holding the tickers the symbol change commands update: the
+/// profile's file under `--profile` unless `--config` points elsewhere
+fn ticker_config_path(config: String) -> String {
+    match config::profile() {
+        Some(name) if config == "config.toml" => {
+            config::profile_path(name).to_string_lossy().into_owned()
+        }
+        _ => config,
+    }
+}
+
+/// Subcommand path of a run, e.g. `db backup`; the default run is `marketcaps`
+fn command_path(matches: &clap::ArgMatches) -> String {
+    let mut names = Vec::new();
+    let mut current = matches;
+    while let Some((name, sub)) = current.subcommand() {
+        names.push(name);
+        current = sub;
+    }
+    if names.is_empty() {
+        "marketcaps".to_string()
+    } else {
+        names.join(" ")
+    }
 }
 
 #[derive(Debug, Subcommand)]
@@ -43,7 +171,34 @@ enum Commands {
     /// Export EU market caps to CSV
     ExportEu,
     /// Export combined market caps to CSV
-    ExportCombined,
+    ExportCombined {
+        /// Also fetch analyst price targets and ratings (two extra FMP requests per ticker)
+        #[arg(long)]
+        with_analyst: bool,
+        /// Fetch profile, ratios, income statement and executives of every
+        /// ticker instead of batch quotes (refreshes the stored details)
+        #[arg(long)]
+        full_details: bool,
+        /// Only fetch tickers whose stored data is older than this (e.g. 6h,
+        /// 30m, 2d); the others are carried forward from their latest row
+        #[arg(long)]
+        max_age: Option<String>,
+        /// Order the exports by this KPI from `[kpis]` in config.toml instead
+        /// of the EUR market cap
+        #[arg(long)]
+        rank_by: Option<String>,
+        /// Also export this run as the intraday snapshot `<date>@<label>`
+        /// (e.g. open, close) that comparisons can select
+        #[arg(long)]
+        label: Option<String>,
+        /// Fetch the US tickers from `polygon` (shares outstanding x close,
+        /// needs POLYGON_API_KEY) instead of `fmp`
+        #[arg(long, default_value = "fmp")]
+        provider: String,
+        /// Exit with an error when data quality checks find issues
+        #[arg(long)]
+        fail_on_anomalies: bool,
+    },
     /// List US market caps
     ListUs,
     /// List EU market caps
@@ -59,22 +214,157 @@ enum Commands {
         #[arg(long)]
         to: String,
     },
+    /// Check which (pair, business day) rates are missing, fetch only those
+    /// gaps and report the coverage per pair
+    VerifyRates {
+        /// Start date (YYYY-MM-DD format)
+        #[arg(long)]
+        from: String,
+        /// End date (YYYY-MM-DD format)
+        #[arg(long)]
+        to: String,
+        /// Pairs to verify, e.g. EUR/USD (default: the common FMP pairs)
+        #[arg(long, value_delimiter = ',')]
+        pairs: Vec<String>,
+        /// Only report the coverage, don't fetch the gaps
+        #[arg(long)]
+        check_only: bool,
+    },
     /// Fetch historical market caps
     FetchHistoricalMarketCaps { start_year: i32, end_year: i32 },
-    /// Fetch monthly historical market caps
-    FetchMonthlyHistoricalMarketCaps { start_year: i32, end_year: i32 },
+    /// Fetch monthly historical market caps; months already stored per ticker are skipped
+    FetchMonthlyHistoricalMarketCaps {
+        start_year: i32,
+        end_year: i32,
+        /// Fetch again even if the month is already stored for a ticker
+        #[arg(long)]
+        refresh: bool,
+    },
     /// Fetch market caps for a specific date
-    FetchSpecificDateMarketCaps { date: String },
+    FetchSpecificDateMarketCaps {
+        date: String,
+        /// Exit with an error when data quality checks find issues
+        #[arg(long)]
+        fail_on_anomalies: bool,
+        /// Resolve universe membership, symbols, names and currencies as of
+        /// the date from stored universe snapshots and symbol changes
+        #[arg(long)]
+        point_in_time: bool,
+        /// Fetch the previous day on which every exchange of the universe
+        /// traded when the date is a weekend or holiday on any of them
+        #[arg(long)]
+        align_to_trading_day: bool,
+    },
+    /// Import historical market caps from external CSV files
+    ImportMarketcaps {
+        /// CSV file, or a directory whose *.csv files are all imported
+        path: std::path::PathBuf,
+        /// TOML file mapping the CSV columns onto the market cap fields
+        #[arg(long)]
+        mapping: std::path::PathBuf,
+        /// Import the valid rows even when others fail validation
+        #[arg(long)]
+        skip_invalid: bool,
+    },
+    /// Print a company profile card (cached details, refreshed from FMP when stale)
+    Show {
+        /// Ticker symbol (e.g. NKE) or a stored ISIN (e.g. US6541061031)
+        ticker: String,
+        /// Ignore the cache and fetch fresh details from FMP
+        #[arg(long)]
+        refresh: bool,
+    },
+    /// Show how a company's rank and market cap evolved across all stored snapshots
+    RankHistory {
+        /// Ticker symbol (e.g. NKE)
+        ticker: String,
+    },
+    /// Write a one-company dossier: rank history chart, fundamentals, rank and
+    /// FX-normalized performance vs its peer groups, and symbol changes
+    CompanyReport {
+        /// Ticker symbol (e.g. NKE) or a stored ISIN (e.g. US6541061031)
+        ticker: String,
+        /// Start date (YYYY-MM-DD); uses the latest exported snapshot on or before it
+        #[arg(long)]
+        from: String,
+        /// End date (YYYY-MM-DD); uses the latest exported snapshot on or before it
+        #[arg(long)]
+        to: String,
+        /// Refresh the company profile from FMP
+        #[arg(long)]
+        refresh: bool,
+    },
+    /// Manage watchlists of tickers tracked separately from the main universe
+    Watchlist {
+        #[command(subcommand)]
+        action: WatchlistCommand,
+    },
+    /// Show API requests per day and endpoint recorded over a recent period
+    ApiUsage {
+        /// Lookback period (e.g. 30d, 12w)
+        #[arg(long, default_value = "30d")]
+        last: String,
+    },
+    /// Fetch one ticker from the FMP endpoints the fetchers use and report
+    /// missing, retyped and new fields; fails when a model no longer parses
+    CheckApiSchema {
+        /// Ticker known to be covered by every endpoint
+        #[arg(long, default_value = api_schema::DEFAULT_TICKER)]
+        ticker: String,
+        /// Post failures to the configured Slack/Teams webhooks
+        #[arg(long)]
+        notify: bool,
+    },
+    /// Summarise the database: snapshots and their date range, forex pairs and
+    /// coverage, symbol changes, file size and rows per table
+    Stats,
+    /// Check a stored snapshot for suspicious data (large moves, currency changes, zero prices, missing values)
+    CheckDataQuality {
+        /// Snapshot date (YYYY-MM-DD format)
+        date: String,
+        /// Exit with an error when issues are found
+        #[arg(long)]
+        fail_on_anomalies: bool,
+    },
     /// Add a currency
     AddCurrency { code: String, name: String },
     /// List currencies
     ListCurrencies,
+    /// List the currency subunits (GBp, ZAc, ...) used in conversions
+    ListSubunits,
+    /// Export the ISIN and LEI of every tracked ticker to CSV
+    IsinMap,
     /// Compare market caps between two dates
     CompareMarketCaps {
+        /// Start date (YYYY-MM-DD), or YYYY-MM-DD@LABEL for a labeled intraday snapshot
         #[arg(long)]
         from: String,
+        /// End date (YYYY-MM-DD), or YYYY-MM-DD@LABEL for a labeled intraday snapshot
         #[arg(long)]
         to: String,
+        /// Also generate the charts and embed them in the summary markdown
+        #[arg(long)]
+        with_charts: bool,
+    },
+    /// Short newsletter summary of a comparison (Markdown and inline-CSS HTML)
+    Digest {
+        #[arg(long)]
+        from: String,
+        #[arg(long)]
+        to: String,
+        /// Digest layout: newsletter
+        #[arg(long, default_value = "newsletter")]
+        template: String,
+    },
+    /// Fetch missing snapshots, compare with charts, write the digest and a report bundle, then publish
+    Report {
+        #[arg(long)]
+        from: String,
+        #[arg(long)]
+        to: String,
+        /// Publish the report: email, slack and/or s3 (comma-separated)
+        #[arg(long, value_delimiter = ',')]
+        publish: Vec<String>,
     },
     /// Generate visualization charts from comparison data
     GenerateCharts {
@@ -137,6 +427,135 @@ enum Commands {
         #[arg(long, value_delimiter = ',')]
         groups: Option<Vec<String>>,
     },
+    /// Leaderboard of peer group returns vs. the universe over consecutive rolling windows
+    PeerMomentum {
+        /// Window length: 30d, 90d, 180d, 1y, or a number of days
+        #[arg(long, default_value = "90d")]
+        window: String,
+        /// Number of windows back from the end date
+        #[arg(long, default_value_t = peer_momentum::DEFAULT_WINDOWS)]
+        windows: usize,
+        /// End date (YYYY-MM-DD format); defaults to the latest snapshot
+        #[arg(long)]
+        date: Option<String>,
+        /// Peer groups to rank (comma-separated). Leave empty for all groups.
+        #[arg(long, value_delimiter = ',')]
+        groups: Option<Vec<String>>,
+    },
+    /// Fetch the earnings calendar of the universe, list the reports and store them for comparisons
+    EarningsCalendar {
+        /// Start date (YYYY-MM-DD format); defaults to today
+        #[arg(long)]
+        from: Option<String>,
+        /// End date (YYYY-MM-DD format); defaults to 30 days after the start
+        #[arg(long)]
+        to: Option<String>,
+    },
+    /// Report new entrants (IPOs, additions) and disappeared companies (delistings, acquisitions) between two dates
+    DetectUniverseChanges {
+        #[arg(long)]
+        from: String,
+        #[arg(long)]
+        to: String,
+    },
+    /// Roll snapshots up into weekly or monthly OHLC market caps and average ranks
+    Aggregate {
+        /// Period length: weekly or monthly
+        #[arg(long, default_value = "monthly")]
+        granularity: String,
+    },
+    /// Compare FMP and Polygon market caps of the US tickers on a date and
+    /// report the discrepancies with their likely cause
+    Reconcile {
+        /// Date (YYYY-MM-DD format)
+        #[arg(long)]
+        date: String,
+        /// Report differences above this many percent (default `[polygon]
+        /// discrepancy_threshold_pct`)
+        #[arg(long)]
+        threshold: Option<f64>,
+    },
+    /// Revenue and market cap per employee with rankings, industry medians and outliers
+    EfficiencyReport {
+        /// Snapshot date (YYYY-MM-DD format); defaults to the latest snapshot
+        #[arg(long)]
+        date: Option<String>,
+    },
+    /// Filter and rank the companies of a stored snapshot by their fundamentals
+    Screen {
+        /// Condition, e.g. "market_cap_usd > 10e9 && pe_ratio < 20 && currency == 'EUR'"
+        #[arg(long = "where")]
+        filter: String,
+        /// Number expression to rank the matches by, largest first (default
+        /// market_cap_usd; "-pe_ratio" ranks the lowest P/E first)
+        #[arg(long)]
+        rank_by: Option<String>,
+        /// Snapshot date (YYYY-MM-DD format); defaults to the latest snapshot
+        #[arg(long)]
+        date: Option<String>,
+        /// Keep only the first N matches
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    /// Consensus price target vs. current price with upside %, per company and peer group
+    AnalystSummary {
+        /// Fetch date (YYYY-MM-DD format); defaults to the latest fetch
+        #[arg(long)]
+        date: Option<String>,
+    },
+    /// Full-text search over company names, descriptions and tickers, with market caps
+    Search {
+        /// Free text, e.g. "luxury handbags"
+        query: String,
+        /// Maximum number of matches
+        #[arg(long, default_value_t = search::DEFAULT_LIMIT)]
+        limit: usize,
+        /// Rebuild the search index from the stored details first
+        #[arg(long)]
+        reindex: bool,
+    },
+    /// Market cap by headquarters country, as CSV and a ranked bar chart
+    GeoReport {
+        /// Snapshot date (YYYY-MM-DD format); defaults to the latest snapshot
+        #[arg(long)]
+        date: Option<String>,
+    },
+    /// Treemap of a stored snapshot by peer group, colored by the change since the previous snapshot
+    Treemap {
+        /// Snapshot date (YYYY-MM-DD format); defaults to the latest snapshot
+        #[arg(long)]
+        date: Option<String>,
+    },
+    /// Download company logos into <output>/assets/logos/ for charts and HTML reports
+    FetchLogos {
+        /// Download again even if the cached logo is younger than `[logos] max_age_days`
+        #[arg(long)]
+        refresh: bool,
+    },
+    /// Show which fields (name, currency, market cap, price, employees, CEO) changed per ticker between two snapshots
+    DiffSnapshots {
+        /// Snapshot CSV file, or a date (latest export CSV for that date)
+        #[arg(long)]
+        from: String,
+        /// Snapshot CSV file, or a date (latest export CSV for that date)
+        #[arg(long)]
+        to: String,
+        /// Read dates from the market_caps table instead of export CSVs
+        #[arg(long)]
+        db: bool,
+        /// Only compare these fields (comma-separated): name, currency, market_cap, price, employees, ceo
+        #[arg(long, value_delimiter = ',')]
+        fields: Vec<String>,
+    },
+    /// List companies whose CEO changed between the snapshots of two dates
+    DetectCeoChanges {
+        /// First date (YYYY-MM-DD)
+        #[arg(long)]
+        from: String,
+        /// Last date (YYYY-MM-DD)
+        #[arg(long)]
+        to: String,
+    },
     /// List available dates for comparison (from output directory)
     ListAvailableDates,
     /// List predefined peer groups
@@ -159,80 +578,519 @@ enum Commands {
         #[arg(long)]
         auto_apply: bool,
     },
+    /// Undo the most recent batch of applied symbol changes
+    UndoSymbolChanges {
+        /// Path to config.toml file
+        #[arg(long, default_value = "config.toml")]
+        config: String,
+        /// Skip the confirmation prompt
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Email the latest comparison report (via Brevo or SMTP)
+    SendReport {
+        /// Start date of the comparison to send (defaults to the latest report)
+        #[arg(long, requires = "to")]
+        from: Option<String>,
+        /// End date of the comparison to send
+        #[arg(long, requires = "from")]
+        to: Option<String>,
+        /// Recipients (comma-separated). Defaults to NOTIFICATION_RECIPIENTS
+        #[arg(long, value_delimiter = ',')]
+        recipients: Option<Vec<String>>,
+        /// Email subject
+        #[arg(long)]
+        subject: Option<String>,
+        /// Transport to use: brevo or smtp (default: whichever is configured)
+        #[arg(long)]
+        via: Option<String>,
+        /// Don't attach the comparison CSV and chart SVGs
+        #[arg(long)]
+        no_attachments: bool,
+    },
+    /// Inspect and requeue background jobs that failed every attempt
+    Jobs {
+        #[command(subcommand)]
+        action: JobsCommand,
+    },
+    /// Create an API key for machine-to-machine access; the key is printed once
+    CreateApiKey {
+        /// Unique name identifying the key's owner (e.g. ci)
+        name: String,
+        /// Scopes (comma-separated): read, compare, fetch
+        #[arg(long, value_delimiter = ',', default_value = "read")]
+        scopes: Vec<String>,
+    },
+    /// Revoke an API key
+    RevokeApiKey { name: String },
+    /// Back up the database or restore it from a backup
+    Db {
+        #[command(subcommand)]
+        action: DbCommand,
+    },
+    /// Print the shell completion script (bash, zsh, fish, powershell or elvish)
+    Completions {
+        /// Shell to complete in
+        shell: clap_complete::Shell,
+    },
     /// Start the web server
     Serve {
         /// Port to bind to
         #[arg(long, default_value = "3000")]
         port: u16,
+        /// Also check FMP reachability in /readyz
+        #[arg(long)]
+        check_fmp: bool,
+    },
+}
+
+impl Commands {
+    /// Whether the command works with the core tables in PostgreSQL. The
+    /// other commands query them through SQLite-only SQL.
+    fn supports_postgres(&self) -> bool {
+        matches!(
+            self,
+            Commands::ExportCombined { .. }
+                | Commands::ExportRates
+                | Commands::FetchHistoricalExchangeRates { .. }
+                | Commands::VerifyRates { .. }
+                | Commands::AddCurrency { .. }
+                | Commands::ListCurrencies
+                | Commands::ListSubunits
+                | Commands::IsinMap
+                | Commands::CheckSymbolChanges { .. }
+                | Commands::ApplySymbolChanges { .. }
+                | Commands::UndoSymbolChanges { .. }
+                | Commands::EarningsCalendar { .. }
+                | Commands::ApiUsage { .. }
+                | Commands::CheckApiSchema { .. }
+                | Commands::Jobs { .. }
+                | Commands::CreateApiKey { .. }
+                | Commands::RevokeApiKey { .. }
+        )
+    }
+}
+
+/// Refuse a command that still reads the core tables with SQLite-only SQL
+/// before it runs against a PostgreSQL `DATABASE_URL`
+fn ensure_postgres_support(command: Option<&Commands>, name: Option<&str>) -> Result<()> {
+    match command {
+        Some(command) if !command.supports_postgres() => anyhow::bail!(
+            "`{}` does not support a PostgreSQL DATABASE_URL yet, it reads the core tables \
+             with SQLite-only SQL. Run it with DATABASE_URL=sqlite:data.db; the \
+             PostgreSQL section of CLAUDE.md lists the supported commands",
+            name.unwrap_or_default()
+        ),
+        _ => Ok(()),
+    }
+}
+
+#[derive(Debug, Subcommand)]
+enum DbCommand {
+    /// Write a consistent snapshot of the database
+    Backup {
+        /// File to write (a directory for --format csv)
+        path: std::path::PathBuf,
+        /// sqlite (copy of the database file), json (one file) or csv (one file per table)
+        #[arg(long, default_value = "sqlite")]
+        format: String,
+    },
+    /// Replace the rows of every table in a backup; other tables are kept
+    Restore {
+        /// Backup file or CSV directory
+        path: std::path::PathBuf,
+        /// sqlite, json or csv (default: from the path)
+        #[arg(long)]
+        format: Option<String>,
+        /// Confirm overwriting the current data
+        #[arg(long)]
+        yes: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum WatchlistCommand {
+    /// Create an empty watchlist
+    Create { name: String },
+    /// Delete a watchlist and its tickers
+    Delete { name: String },
+    /// Add tickers to a watchlist
+    Add {
+        name: String,
+        #[arg(required = true)]
+        tickers: Vec<String>,
+    },
+    /// Remove tickers from a watchlist
+    Remove {
+        name: String,
+        #[arg(required = true)]
+        tickers: Vec<String>,
+    },
+    /// List all watchlists
+    List,
+    /// Show the tickers of a watchlist
+    Show { name: String },
+    /// Fetch market caps of a watchlist's tickers for a specific date
+    Fetch {
+        name: String,
+        /// Date (YYYY-MM-DD format)
+        #[arg(long)]
+        date: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum JobsCommand {
+    /// List jobs in the dead-letter stream
+    ListFailed,
+    /// Requeue a failed job under its original id
+    Retry { job_id: String },
+    /// Show recorded jobs with their duration and output files, newest first
+    History {
+        /// Only jobs with this status: running, completed or failed
+        #[arg(long)]
+        status: Option<String>,
+        /// Only jobs submitted since this date (YYYY-MM-DD)
+        #[arg(long)]
+        since: Option<String>,
+        /// Maximum number of jobs to show
+        #[arg(long, default_value = "20")]
+        limit: usize,
     },
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() {
+    if let Err(e) = run().await {
+        if let Err(manifest_error) = run_context::finish(Some(&e)) {
+            eprintln!("⚠️  {:#}", manifest_error);
+        }
+        // Same output as returning the error from main, with an exit code per
+        // `error::ErrorCode` so the job worker can tell failures apart
+        eprintln!("Error: {:?}", e);
+        std::process::exit(error::exit_code(&e));
+    }
+}
+
+async fn run() -> Result<()> {
     dotenvy::dotenv().ok();
 
-    let cli = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches)?;
 
-    let db_url = env::var("DATABASE_URL").unwrap_or_else(|_| "sqlite:data.db".to_string());
-    let pool = db::create_db_pool(&db_url).await?;
+    // Man pages and completions need neither the database nor the config
+    if let Some(dir) = &cli.generate_manpage {
+        match dir {
+            Some(dir) => {
+                let pages = cli_docs::write_manpages(Cli::command(), dir)?;
+                println!("✅ Wrote {} man pages to {}", pages.len(), dir.display());
+            }
+            None => cli_docs::write_manpage(Cli::command(), &mut std::io::stdout())?,
+        }
+        return Ok(());
+    }
+    if let Some(Commands::Completions { shell }) = cli.command {
+        cli_docs::write_completions(shell, Cli::command(), &mut std::io::stdout());
+        return Ok(());
+    }
+
+    config::init_profile(cli.profile.as_deref())?;
+    let db_url = config::database_url();
+    let core = db::create_core_pool(&db_url).await?;
+    let pool = match &core {
+        db::CorePool::Sqlite(pool) => pool.clone(),
+        // Only the core tables move to PostgreSQL; everything else stays in SQLite
+        db::CorePool::Postgres(_) => {
+            ensure_postgres_support(cli.command.as_ref(), matches.subcommand_name())?;
+            db::create_db_pool(&config::sqlite_database_url()).await?
+        }
+    };
+    locale::init(locale::Locale::parse(&cli.locale)?)?;
+    chart_theme::init(&config::load_chart_config())?;
+    vega::init(vega::ChartBackend::parse(&cli.chart_backend)?);
+    progress::init(cli.quiet);
+    currencies::init_rate_cache();
+    currencies::init_strict(cli.strict_currency || config::load_forex_config().strict);
+    data_package::init(cli.data_package || config::load_output_config().data_package);
+    rankings::init_top(cli.top)?;
+    outliers::init(cli.min_market_cap, cli.winsorize)?;
+    if let Some(as_of) = &cli.as_of {
+        clock::init(Box::new(clock::FixedClock::parse(as_of)?));
+    }
+    if let Some(path) = &cli.manifest {
+        let path = path
+            .clone()
+            .unwrap_or_else(|| config::load_output_config().file_path("run_manifest", "", "json"));
+        run_context::start(
+            path,
+            command_path(&matches),
+            env::args().skip(1).collect(),
+            cli.as_of.clone(),
+        );
+    }
+    retry::init(retry::RetryOverrides {
+        max_retries: cli.max_retries,
+        base_delay_secs: cli.retry_base_delay,
+        max_delay_secs: cli.retry_max_delay,
+        jitter: cli.retry_jitter,
+    })?;
+    if !cli.no_cache {
+        api_cache::init(&pool, config::load_api_config().cache_ttl_hours).await?;
+    }
+
+    // Remember when the run started so new output files can be uploaded afterwards
+    let started_at = std::time::SystemTime::now();
+    let mut upload_url = cli.upload.clone().or_else(|| {
+        config::load_config()
+            .ok()
+            .and_then(|c| c.storage.upload_url)
+    });
+    let report_currencies = cli.report_currencies.clone();
+    let watchlist = cli.watchlist.clone();
+    let watchlist = watchlist.as_deref();
+    let consistent_universe = cli.consistent_universe;
+    let exclude_corporate_actions = cli.exclude_corporate_actions;
+    let concurrency = cli.concurrency;
 
     match cli.command {
         Some(Commands::ExportUs) => details_us_polygon::export_details_us_csv(&pool).await?,
         Some(Commands::ExportEu) => details_eu_fmp::export_details_eu_csv(&pool).await?,
-        Some(Commands::ExportCombined) => {
-            marketcaps::marketcaps(&pool).await?;
+        Some(Commands::ExportCombined {
+            with_analyst,
+            full_details,
+            max_age,
+            rank_by,
+            label,
+            provider,
+            fail_on_anomalies,
+        }) => {
+            let max_age = max_age.as_deref().map(utils::parse_duration).transpose()?;
+            let provider = polygon_snapshot::Provider::parse(&provider)?;
+            marketcaps::marketcaps(
+                &pool,
+                &core,
+                &report_currencies,
+                concurrency,
+                with_analyst,
+                full_details,
+                max_age,
+                rank_by.as_deref(),
+                label.as_deref(),
+                provider,
+            )
+            .await?;
+            if let Some(pool) = core.as_sqlite() {
+                data_quality::check_latest(pool, fail_on_anomalies).await?;
+            }
         }
         Some(Commands::ListUs) => details_us_polygon::list_details_us(&pool).await?,
         Some(Commands::ListEu) => details_eu_fmp::list_details_eu(&pool).await?,
         Some(Commands::ExportRates) => {
-            let api_key = env::var("FINANCIALMODELINGPREP_API_KEY")
-                .expect("FINANCIALMODELINGPREP_API_KEY must be set");
-            let fmp_client = api::FMPClient::new(api_key);
-            exchange_rates::update_exchange_rates(&fmp_client, &pool).await?;
+            let fmp_client = api::FMPClient::from_env()?;
+            exchange_rates::update_exchange_rates(&fmp_client, &core).await?;
         }
         Some(Commands::FetchHistoricalExchangeRates { from, to }) => {
-            let api_key = env::var("FINANCIALMODELINGPREP_API_KEY")
-                .expect("FINANCIALMODELINGPREP_API_KEY must be set");
-            let fmp_client = api::FMPClient::new(api_key);
-            exchange_rates::fetch_historical_exchange_rates(&fmp_client, &pool, &from, &to).await?;
+            let fmp_client = api::FMPClient::from_env()?;
+            exchange_rates::fetch_historical_exchange_rates(&fmp_client, &core, &from, &to).await?;
+        }
+        Some(Commands::VerifyRates {
+            from,
+            to,
+            pairs,
+            check_only,
+        }) => {
+            // Reporting the coverage needs no API key
+            let fmp_client = (!check_only).then(api::FMPClient::from_env).transpose()?;
+            exchange_rates::verify_exchange_rates(fmp_client.as_ref(), &core, &from, &to, &pairs)
+                .await?;
         }
         Some(Commands::FetchHistoricalMarketCaps {
             start_year,
             end_year,
         }) => {
-            historical_marketcaps::fetch_historical_marketcaps(&pool, start_year, end_year).await?;
+            historical_marketcaps::fetch_historical_marketcaps(
+                &pool,
+                start_year,
+                end_year,
+                concurrency,
+            )
+            .await?;
         }
         Some(Commands::FetchMonthlyHistoricalMarketCaps {
             start_year,
             end_year,
+            refresh,
         }) => {
             monthly_historical_marketcaps::fetch_monthly_historical_marketcaps(
-                &pool, start_year, end_year,
+                &pool,
+                start_year,
+                end_year,
+                concurrency,
+                refresh,
+            )
+            .await?;
+        }
+        Some(Commands::FetchSpecificDateMarketCaps {
+            date,
+            fail_on_anomalies,
+            point_in_time,
+            align_to_trading_day,
+        }) => {
+            let date = specific_date_marketcaps::fetch_specific_date_marketcaps(
+                &pool,
+                &date,
+                &report_currencies,
+                concurrency,
+                point_in_time,
+                align_to_trading_day,
+            )
+            .await?;
+            data_quality::check_date(&pool, &date, fail_on_anomalies).await?;
+        }
+        Some(Commands::ImportMarketcaps {
+            path,
+            mapping,
+            skip_invalid,
+        }) => {
+            import_marketcaps::import_marketcaps(
+                &pool,
+                &path,
+                &mapping,
+                &report_currencies,
+                skip_invalid,
             )
             .await?;
         }
-        Some(Commands::FetchSpecificDateMarketCaps { date }) => {
-            specific_date_marketcaps::fetch_specific_date_marketcaps(&pool, &date).await?;
+        Some(Commands::Show { ticker, refresh }) => {
+            let ticker = identifiers::resolve_ticker(&core, &ticker).await?;
+            company_profile::show_company(&pool, &ticker, refresh).await?;
+        }
+        Some(Commands::ApiUsage { last }) => {
+            api_usage::show_usage(&pool, &last).await?;
+        }
+        Some(Commands::CheckApiSchema { ticker, notify }) => {
+            let fmp_client = api::FMPClient::from_env()?;
+            api_schema::check_api_schema(&fmp_client, &ticker, notify).await?;
+        }
+        Some(Commands::Stats) => {
+            db_stats::show_stats(&pool, &config::sqlite_database_url()).await?;
+        }
+        Some(Commands::RankHistory { ticker }) => {
+            rankings::show_rank_history(&pool, &ticker).await?;
+        }
+        Some(Commands::CompanyReport {
+            ticker,
+            from,
+            to,
+            refresh,
+        }) => {
+            let ticker = identifiers::resolve_ticker(&core, &ticker).await?;
+            company_report::company_report(&pool, &ticker, &from, &to, refresh).await?;
+        }
+        Some(Commands::Watchlist { action }) => match action {
+            WatchlistCommand::Create { name } => {
+                watchlists::create_watchlist(&pool, &name).await?;
+                println!("✅ Created watchlist '{}'", name);
+            }
+            WatchlistCommand::Delete { name } => {
+                watchlists::delete_watchlist(&pool, &name).await?;
+                println!("✅ Deleted watchlist '{}'", name);
+            }
+            WatchlistCommand::Add { name, tickers } => {
+                let added = watchlists::add_tickers(&pool, &name, &tickers).await?;
+                println!("✅ Added {} ticker(s) to '{}'", added, name);
+            }
+            WatchlistCommand::Remove { name, tickers } => {
+                let removed = watchlists::remove_tickers(&pool, &name, &tickers).await?;
+                println!("✅ Removed {} ticker(s) from '{}'", removed, name);
+            }
+            WatchlistCommand::List => {
+                let lists = watchlists::list_watchlists(&pool).await?;
+                if lists.is_empty() {
+                    println!("No watchlists yet. Create one with 'watchlist create <name>'.");
+                }
+                for (name, count) in lists {
+                    println!("{} ({} tickers)", name, count);
+                }
+            }
+            WatchlistCommand::Show { name } => {
+                for ticker in watchlists::get_tickers(&pool, &name).await? {
+                    println!("{}", ticker);
+                }
+            }
+            WatchlistCommand::Fetch { name, date } => {
+                watchlists::fetch_watchlist(&pool, &name, &date, &report_currencies, concurrency)
+                    .await?;
+            }
+        },
+        Some(Commands::CheckDataQuality {
+            date,
+            fail_on_anomalies,
+        }) => {
+            data_quality::check_date(&pool, &date, fail_on_anomalies).await?;
         }
         Some(Commands::AddCurrency { code, name }) => {
-            let api_key = env::var("FINANCIALMODELINGPREP_API_KEY")
-                .expect("FINANCIALMODELINGPREP_API_KEY must be set");
-            let fmp_client = api::FMPClient::new(api_key);
-            currencies::update_currencies(&fmp_client, &pool).await?;
+            let fmp_client = api::FMPClient::from_env()?;
+            currencies::update_currencies(&fmp_client, &core).await?;
             println!("✅ Currencies updated from FMP API");
 
             // Also add the manually specified currency
-            currencies::insert_currency(&pool, &code, &name).await?;
+            currencies::insert_currency(&core, &code, &name).await?;
             println!("✅ Added currency: {} ({})", name, code);
         }
         Some(Commands::ListCurrencies) => {
-            let currencies = currencies::list_currencies(&pool).await?;
+            let currencies = currencies::list_currencies(&core).await?;
             for (code, name) in currencies {
                 println!("{}: {}", code, name);
             }
         }
-        Some(Commands::CompareMarketCaps { from, to }) => {
-            compare_marketcaps::compare_market_caps(&from, &to).await?;
+        Some(Commands::ListSubunits) => subunits::list_subunits(&core).await?,
+        Some(Commands::IsinMap) => identifiers::export_isin_map(&core).await?,
+        Some(Commands::CompareMarketCaps {
+            from,
+            to,
+            with_charts,
+        }) => {
+            compare_marketcaps::compare_market_caps(
+                &pool,
+                &from,
+                &to,
+                &report_currencies,
+                watchlist,
+                consistent_universe,
+                exclude_corporate_actions,
+                with_charts,
+            )
+            .await?;
+        }
+        Some(Commands::Digest { from, to, template }) => {
+            let template = digest::DigestTemplate::parse(&template)?;
+            digest::generate_digest(&pool, &from, &to, template).await?;
+        }
+        Some(Commands::Report { from, to, publish }) => {
+            let targets = report::PublishTarget::parse_all(&publish)?;
+            if watchlist.is_some() {
+                anyhow::bail!("report compares the whole universe; drop --watchlist");
+            }
+            if !targets.contains(&report::PublishTarget::S3) {
+                // Publishing is opt-in: a configured destination alone uploads nothing
+                upload_url = None;
+            } else if upload_url.is_none() {
+                anyhow::bail!(
+                    "--publish s3 needs a destination: pass --upload or set [storage] upload_url"
+                );
+            }
+            report::run_report(
+                &pool,
+                &from,
+                &to,
+                &targets,
+                &report_currencies,
+                concurrency,
+                consistent_universe,
+                exclude_corporate_actions,
+            )
+            .await?;
         }
         Some(Commands::GenerateCharts { from, to }) => {
             visualizations::generate_all_charts(&from, &to).await?;
@@ -241,33 +1099,49 @@ async fn main() -> Result<()> {
             if dates.len() < 2 {
                 anyhow::bail!("At least 2 dates are required for trend analysis");
             }
-            advanced_comparisons::multi_date_comparison(&pool, dates).await?;
+            advanced_comparisons::multi_date_comparison(
+                &pool,
+                dates,
+                watchlist,
+                consistent_universe,
+                exclude_corporate_actions,
+            )
+            .await?;
         }
         Some(Commands::CompareYoy { date, years }) => {
-            advanced_comparisons::compare_yoy(&pool, &date, years).await?;
+            advanced_comparisons::compare_yoy(
+                &pool,
+                &date,
+                years,
+                watchlist,
+                consistent_universe,
+                exclude_corporate_actions,
+            )
+            .await?;
         }
         Some(Commands::CompareQoq { date, quarters }) => {
-            advanced_comparisons::compare_qoq(&pool, &date, quarters).await?;
+            advanced_comparisons::compare_qoq(
+                &pool,
+                &date,
+                quarters,
+                watchlist,
+                consistent_universe,
+                exclude_corporate_actions,
+            )
+            .await?;
         }
         Some(Commands::CompareRolling { date, period }) => {
-            let rolling_period = match period.to_lowercase().as_str() {
-                "30d" => advanced_comparisons::RollingPeriod::Days30,
-                "90d" => advanced_comparisons::RollingPeriod::Days90,
-                "180d" => advanced_comparisons::RollingPeriod::Days180,
-                "1y" | "1year" | "365d" => advanced_comparisons::RollingPeriod::Year1,
-                _ => {
-                    // Try to parse as number of days
-                    let days: i64 = period
-                        .trim_end_matches('d')
-                        .parse()
-                        .map_err(|_| anyhow::anyhow!(
-                            "Invalid period '{}'. Use: 30d, 90d, 180d, 1y, or a number of days (e.g., 45d)",
-                            period
-                        ))?;
-                    advanced_comparisons::RollingPeriod::Custom(days)
-                }
-            };
-            advanced_comparisons::compare_rolling(&pool, &date, rolling_period).await?;
+            let rolling_period = advanced_comparisons::RollingPeriod::parse(&period)?;
+            advanced_comparisons::compare_rolling(
+                &pool,
+                &date,
+                rolling_period,
+                &report_currencies,
+                watchlist,
+                consistent_universe,
+                exclude_corporate_actions,
+            )
+            .await?;
         }
         Some(Commands::CompareBenchmark {
             from,
@@ -279,15 +1153,89 @@ async fn main() -> Result<()> {
                 "msci" | "msci_world" | "urth" => advanced_comparisons::Benchmark::MSCI,
                 _ => advanced_comparisons::Benchmark::Custom(benchmark),
             };
-            advanced_comparisons::compare_with_benchmark(&pool, &from, &to, bench).await?;
+            advanced_comparisons::compare_with_benchmark(&pool, &from, &to, bench, watchlist)
+                .await?;
         }
         Some(Commands::ComparePeerGroups { from, to, groups }) => {
-            advanced_comparisons::compare_peer_groups(&pool, &from, &to, groups).await?;
+            advanced_comparisons::compare_peer_groups(&pool, &from, &to, groups, watchlist).await?;
+        }
+        Some(Commands::PeerMomentum {
+            window,
+            windows,
+            date,
+            groups,
+        }) => {
+            let period = advanced_comparisons::RollingPeriod::parse(&window)?;
+            peer_momentum::peer_momentum(
+                &pool,
+                date.as_deref(),
+                period,
+                windows,
+                groups,
+                watchlist,
+            )
+            .await?;
+        }
+        Some(Commands::DetectUniverseChanges { from, to }) => {
+            universe_changes::detect_universe_changes(&pool, &from, &to, watchlist).await?;
+        }
+        Some(Commands::Aggregate { granularity }) => {
+            aggregates::aggregate_marketcaps(&pool, &granularity).await?;
+        }
+        Some(Commands::Reconcile { date, threshold }) => {
+            reconcile::reconcile(&pool, &date, threshold, concurrency).await?;
+        }
+        Some(Commands::EfficiencyReport { date }) => {
+            efficiency::efficiency_report(&pool, date.as_deref()).await?;
+        }
+        Some(Commands::Screen {
+            filter,
+            rank_by,
+            date,
+            limit,
+        }) => {
+            screener::screen(&pool, &filter, rank_by.as_deref(), date.as_deref(), limit).await?;
+        }
+        Some(Commands::EarningsCalendar { from, to }) => {
+            earnings::earnings_calendar(&pool, from.as_deref(), to.as_deref()).await?;
+        }
+        Some(Commands::AnalystSummary { date }) => {
+            analyst::analyst_summary(&pool, date.as_deref()).await?;
+        }
+        Some(Commands::Search {
+            query,
+            limit,
+            reindex,
+        }) => {
+            search::search_command(&pool, &query, limit, reindex).await?;
+        }
+        Some(Commands::GeoReport { date }) => {
+            geo::geo_report(&pool, date.as_deref()).await?;
+        }
+        Some(Commands::Treemap { date }) => {
+            visualizations::snapshot_treemap(&pool, date.as_deref()).await?;
+        }
+        Some(Commands::FetchLogos { refresh }) => {
+            logos::fetch_logos_command(refresh).await?;
+        }
+        Some(Commands::DiffSnapshots {
+            from,
+            to,
+            db,
+            fields,
+        }) => {
+            snapshot_diff::diff_snapshot_sources(&pool, &from, &to, db, &fields, watchlist).await?;
+        }
+        Some(Commands::DetectCeoChanges { from, to }) => {
+            ceo_changes::detect_ceo_changes(&from, &to, watchlist)?;
         }
         Some(Commands::ListAvailableDates) => {
-            let dates = advanced_comparisons::get_available_dates()?;
+            let dates = advanced_comparisons::get_available_dates(watchlist)?;
             if dates.is_empty() {
-                println!("No market cap data files found in output/ directory.");
+                println!(
+                    "No market cap data files found in {}/ directory.",
+                    config::load_output_config().directory
+                );
                 println!("Run 'fetch-specific-date-market-caps YYYY-MM-DD' to fetch data.");
             } else {
                 println!("Available dates for comparison ({} found):", dates.len());
@@ -295,6 +1243,13 @@ async fn main() -> Result<()> {
                     println!("  {}", date);
                 }
             }
+            let labeled = advanced_comparisons::get_labeled_snapshots(watchlist)?;
+            if !labeled.is_empty() {
+                println!("Labeled intraday snapshots ({} found):", labeled.len());
+                for spec in labeled {
+                    println!("  {}", spec);
+                }
+            }
         }
         Some(Commands::ListPeerGroups) => {
             let groups = advanced_comparisons::get_predefined_peer_groups();
@@ -310,16 +1265,19 @@ async fn main() -> Result<()> {
             }
         }
         Some(Commands::CheckSymbolChanges { config }) => {
-            let api_key = env::var("FINANCIALMODELINGPREP_API_KEY")
-                .or_else(|_| env::var("FMP_API_KEY"))
-                .expect("FINANCIALMODELINGPREP_API_KEY or FMP_API_KEY must be set");
+            let config = ticker_config_path(config);
+            let api_key = fmp_keys::primary_key()
+                .or_else(|| env::var("FMP_API_KEY").ok())
+                .context(
+                    "FINANCIALMODELINGPREP_API_KEY, FMP_API_KEYS or FMP_API_KEY must be set",
+                )?;
             let fmp_client = api::FMPClient::new(api_key);
 
             // Fetch and store latest symbol changes
-            symbol_changes::fetch_and_store_symbol_changes(&pool, &fmp_client).await?;
+            symbol_changes::fetch_and_store_symbol_changes(&core, &fmp_client).await?;
 
             // Check which changes apply to our config
-            let report = symbol_changes::check_ticker_updates(&pool, &config).await?;
+            let report = symbol_changes::check_ticker_updates(&core, &pool, &config).await?;
             symbol_changes::print_symbol_change_report(&report);
         }
         Some(Commands::ApplySymbolChanges {
@@ -327,8 +1285,9 @@ async fn main() -> Result<()> {
             dry_run,
             auto_apply,
         }) => {
+            let config = ticker_config_path(config);
             // Check which changes apply to our config
-            let report = symbol_changes::check_ticker_updates(&pool, &config).await?;
+            let report = symbol_changes::check_ticker_updates(&core, &pool, &config).await?;
             symbol_changes::print_symbol_change_report(&report);
 
             if report.applicable_changes.is_empty() {
@@ -336,10 +1295,12 @@ async fn main() -> Result<()> {
             } else if auto_apply || dry_run {
                 // Apply all applicable changes
                 symbol_changes::apply_ticker_updates(
+                    &core,
                     &pool,
                     &config,
                     report.applicable_changes,
                     dry_run,
+                    clock::current(),
                 )
                 .await?;
             } else {
@@ -350,9 +1311,108 @@ async fn main() -> Result<()> {
                 );
             }
         }
-        Some(Commands::Serve { port }) => {
-            // Load configuration
+        Some(Commands::UndoSymbolChanges { config, yes }) => {
+            let config = ticker_config_path(config);
+            symbol_changes::undo_last_batch(&core, &config, yes).await?;
+        }
+        Some(Commands::SendReport {
+            from,
+            to,
+            recipients,
+            subject,
+            via,
+            no_attachments,
+        }) => {
+            notify::send_report(notify::EmailOptions {
+                from_date: from,
+                to_date: to,
+                recipients,
+                subject,
+                via,
+                attach: !no_attachments,
+            })
+            .await?;
+        }
+        Some(Commands::Jobs { action }) => {
+            let nats_url =
+                env::var("NATS_URL").unwrap_or_else(|_| "nats://127.0.0.1:4222".to_string());
+            match action {
+                JobsCommand::ListFailed => {
+                    let nats_client = nats::create_nats_client(&nats_url).await?;
+                    let failed_jobs = nats::list_failed_jobs(&nats_client).await?;
+                    if failed_jobs.is_empty() {
+                        println!("✅ No failed jobs");
+                    }
+                    for job in failed_jobs {
+                        println!(
+                            "{}  {}  {} attempt(s)  failed {}\n    {}",
+                            job.request.job_id,
+                            job.request.job_type.as_str(),
+                            job.attempts,
+                            job.failed_at.format("%Y-%m-%d %H:%M:%S UTC"),
+                            job.error.lines().next().unwrap_or_default()
+                        );
+                    }
+                }
+                JobsCommand::Retry { job_id } => {
+                    let nats_client = nats::create_nats_client(&nats_url).await?;
+                    let job = nats::retry_failed_job(&nats_client, &job_id).await?;
+                    println!(
+                        "✅ Requeued {} job {}",
+                        job.request.job_type.as_str(),
+                        job_id
+                    );
+                }
+                JobsCommand::History {
+                    status,
+                    since,
+                    limit,
+                } => {
+                    nats::history::show_history(&pool, status.as_deref(), since.as_deref(), limit)
+                        .await?;
+                }
+            }
+        }
+        Some(Commands::CreateApiKey { name, scopes }) => {
+            let scopes = scopes
+                .iter()
+                .map(|s| api_keys::Scope::parse(s))
+                .collect::<Result<Vec<_>>>()?;
+            let key = api_keys::create_api_key(&pool, &name, &scopes).await?;
+            println!("✅ Created API key '{}'", name);
+            println!("{}", key);
+            println!("⚠️  Store this key now; it cannot be shown again");
+        }
+        Some(Commands::RevokeApiKey { name }) => {
+            api_keys::revoke_api_key(&pool, &name).await?;
+            println!("✅ Revoked API key '{}'", name);
+        }
+        Some(Commands::Db { action }) => match action {
+            DbCommand::Backup { path, format } => {
+                backup::backup(&pool, &path, backup::DumpFormat::parse(&format)?).await?;
+            }
+            DbCommand::Restore { path, format, yes } => {
+                if !yes {
+                    anyhow::bail!(
+                        "Restoring replaces the current rows of every table in {}; pass --yes to confirm",
+                        path.display()
+                    );
+                }
+                let format = format
+                    .as_deref()
+                    .map(backup::DumpFormat::parse)
+                    .transpose()?;
+                backup::restore(&pool, &path, format).await?;
+            }
+        },
+        // Handled before the database is opened
+        Some(Commands::Completions { .. }) => {}
+        Some(Commands::Serve { port, check_fmp }) => {
+            // Load configuration, reloaded while the server runs
             let config = config::load_config()?;
+            config::validate_config(&config)?;
+            let config = web::config_watch::SharedConfig::new(config::get_config_path(), config);
+            web::config_watch::spawn_watcher(config.clone(), web::config_watch::POLL_INTERVAL);
 
             // Initialize WorkOS client
             let workos_api_key = env::var("WORKOS_API_KEY").expect("WORKOS_API_KEY must be set");
@@ -378,29 +1438,163 @@ async fn main() -> Result<()> {
             // Set up JetStream streams
             nats::setup_streams(&nats_client).await?;
 
+            // SIGTERM/SIGINT stop the server and the worker together
+            let (trigger, shutdown) = shutdown::channel();
+            shutdown::spawn_signal_listener(trigger.clone());
+            let drain_timeout =
+                std::time::Duration::from_secs(config::load_jobs_config().shutdown_timeout_secs);
+
             // Start background worker
             let worker_client = nats_client.clone();
-            tokio::spawn(async move {
-                if let Err(e) = nats::start_worker(worker_client).await {
+            let worker_pool = pool.clone();
+            let worker_shutdown = shutdown.clone();
+            let worker = tokio::spawn(async move {
+                if let Err(e) =
+                    nats::start_worker(worker_client, worker_pool, worker_shutdown).await
+                {
                     eprintln!("Worker error: {}", e);
                 }
             });
 
             // Create app state
-            let state = web::AppState::new(pool, config, workos_client, jwt_secret, nats_client);
+            let state = web::AppState::new(
+                pool.clone(),
+                config,
+                workos_client,
+                jwt_secret,
+                nats_client,
+                check_fmp,
+            );
 
-            // Start the web server
-            web::server::start_server(state, port).await?;
+            // Start the web server, then wait for running jobs before closing SQLite
+            let served = web::server::start_server(state, port, shutdown, drain_timeout).await;
+            // Also stops the worker when the server failed (e.g. port in use)
+            trigger.request();
+            if let Err(e) = worker.await {
+                eprintln!("Worker error: {}", e);
+            }
+            pool.close().await;
+            println!("✓ Shutdown complete");
+            served?;
+            // The pool is closed, so skip the end-of-run bookkeeping below
+            return Ok(());
         }
         None => {
-            marketcaps::marketcaps(&pool).await?;
+            marketcaps::marketcaps(
+                &pool,
+                &core,
+                &report_currencies,
+                concurrency,
+                false,
+                false,
+                None,
+                None,
+                None,
+                polygon_snapshot::Provider::Fmp,
+            )
+            .await?;
+            if let Some(pool) = core.as_sqlite() {
+                data_quality::check_latest(pool, false).await?;
+            }
         }
     }
 
+    api_usage::finish_run(&pool).await?;
+    rate_limit::print_fmp_stats();
+    api_cache::print_stats();
+    // Unconverted amounts must not be uploaded
+    currencies::check_strict()?;
+    // Before uploading, so the manifest is uploaded with the files it lists
+    run_context::finish(None)?;
+
+    if let Some(destination) = upload_url {
+        let output = config::load_output_config();
+        storage::upload_new_files(&destination, output.directory(), started_at).await?;
+    }
+
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    #[test]
+    fn test_export_combined_fail_on_anomalies_flag() {
+        let cli =
+            Cli::try_parse_from(["top200-rs", "export-combined", "--fail-on-anomalies"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::ExportCombined {
+                fail_on_anomalies: true,
+                ..
+            })
+        ));
+
+        let cli = Cli::try_parse_from(["top200-rs", "export-combined"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::ExportCombined {
+                fail_on_anomalies: false,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_unported_commands_are_refused_on_postgres() {
+        for args in [
+            vec!["top200-rs", "fetch-specific-date-market-caps", "2025-01-31"],
+            vec![
+                "top200-rs",
+                "compare-market-caps",
+                "--from",
+                "2025-01-01",
+                "--to",
+                "2025-02-01",
+            ],
+            vec!["top200-rs", "serve"],
+        ] {
+            let matches = Cli::command().try_get_matches_from(&args).unwrap();
+            let cli = Cli::from_arg_matches(&matches).unwrap();
+            let err = ensure_postgres_support(cli.command.as_ref(), matches.subcommand_name())
+                .unwrap_err()
+                .to_string();
+            assert!(
+                err.starts_with(&format!("`{}` does not support", args[1])),
+                "{}",
+                err
+            );
+        }
+
+        let cli = Cli::try_parse_from(["top200-rs", "export-combined"]).unwrap();
+        assert!(ensure_postgres_support(cli.command.as_ref(), Some("export-combined")).is_ok());
+        assert!(ensure_postgres_support(None, None).is_ok());
+    }
+
+    #[test]
+    fn test_manifest_takes_a_path_only_after_equals() {
+        let cli = Cli::try_parse_from(["top200-rs", "--manifest", "export-combined"]).unwrap();
+        assert_eq!(cli.manifest, Some(None));
+        assert!(matches!(cli.command, Some(Commands::ExportCombined { .. })));
+
+        let cli =
+            Cli::try_parse_from(["top200-rs", "export-combined", "--manifest=run.json"]).unwrap();
+        assert_eq!(cli.manifest, Some(Some("run.json".into())));
+
+        let cli = Cli::try_parse_from(["top200-rs", "export-combined"]).unwrap();
+        assert_eq!(cli.manifest, None);
+    }
+
+    #[test]
+    fn test_generate_manpage_takes_a_dir_only_after_equals() {
+        let cli =
+            Cli::try_parse_from(["top200-rs", "--generate-manpage", "list-currencies"]).unwrap();
+        assert_eq!(cli.generate_manpage, Some(None));
+        assert!(matches!(cli.command, Some(Commands::ListCurrencies)));
+
+        let cli = Cli::try_parse_from(["top200-rs", "--generate-manpage=man"]).unwrap();
+        assert_eq!(cli.generate_manpage, Some(Some("man".into())));
+        assert!(cli.command.is_none());
+    }
 }
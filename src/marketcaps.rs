@@ -1,30 +1,98 @@
 // SPDX-FileCopyrightText: 2025 Joost van der Laan
 // SPDX-License-Identifier: AGPL-3.0-only
 
+use crate::analyst;
 use crate::api;
+use crate::circuit_breaker;
+use crate::clock;
 use crate::config;
-use crate::currencies::{convert_currency_with_rate, get_rate_map_from_db, update_currencies};
+use crate::currencies::{
+    convert_currency_with_rate, extra_report_currencies, get_rate_map_from_db,
+    get_rate_map_from_db_for_date, report_currency_values, update_currencies,
+};
+use crate::data_package;
+use crate::db::{CorePool, core_query};
 use crate::exchange_rates;
-use crate::models;
+use crate::kpis::{self, Kpis};
+use crate::models::{self, DataSource, FMPQuote};
+use crate::polygon_snapshot::{self, PolygonMarketCap, Provider};
+use crate::progress;
+use crate::rankings;
+use crate::regions;
+use crate::run_context;
+use crate::search;
+use crate::snapshot_labels::{self, Snapshot};
+use crate::specific_date_marketcaps;
 use crate::ticker_details::{self, TickerDetails};
-use anyhow::Result;
-use chrono::{Local, Utc};
+use crate::universe;
+use crate::utils;
+use anyhow::{Context, Result};
+use chrono::{NaiveDateTime, Utc};
 use csv::Writer;
-use indicatif::{ProgressBar, ProgressStyle};
+use serde_json::Value;
 use sqlx::sqlite::SqlitePool;
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 
 /// Format a conversion rate for display (6 decimal places, or empty if not available)
 fn format_rate(rate: Option<f64>) -> String {
     rate.map(|r| format!("{:.6}", r)).unwrap_or_default()
 }
 
-/// Store market cap data in the database
+/// CSV headers for the combined exports, with one extra column per report currency
+fn export_headers(report_currencies: &[String]) -> Vec<String> {
+    let mut headers: Vec<String> = [
+        "Symbol",
+        "Ticker",
+        "Name",
+        "Market Cap (Original)",
+        "Original Currency",
+        "Market Cap (EUR)",
+        "EUR Rate",
+        "Market Cap (USD)",
+        "USD Rate",
+        "Exchange",
+        "Active",
+        "Description",
+        "Homepage URL",
+        "Employees",
+        "CEO",
+        "ISIN",
+        "Timestamp",
+        "Data Source",
+        "Fetched At",
+    ]
+    .iter()
+    .map(|h| h.to_string())
+    .collect();
+    headers.extend(
+        report_currencies
+            .iter()
+            .map(|c| format!("Market Cap ({})", c)),
+    );
+    headers
+}
+
+/// Sort export rows by EUR market cap, largest first. Equal market caps are
+/// ordered by name (column 2) and ticker (column 1).
+fn sort_by_market_cap(results: &mut [(f64, Vec<String>)]) {
+    results.sort_by(|a, b| {
+        rankings::rank_order(
+            (Some(a.0), a.1[2].as_str(), a.1[1].as_str()),
+            (Some(b.0), b.1[2].as_str(), b.1[1].as_str()),
+        )
+    });
+}
+
+/// Store market cap data in the database, fetched from `source` just now
 async fn store_market_cap(
-    pool: &SqlitePool,
+    pool: &CorePool,
     details: &models::Details,
+    source: DataSource,
     rate_map: &std::collections::HashMap<String, f64>,
     timestamp: i64,
+    update_details: bool,
 ) -> Result<()> {
     let original_market_cap = details.market_cap.unwrap_or(0.0) as i64;
     let currency = details.currency_symbol.clone().unwrap_or_default();
@@ -35,10 +103,17 @@ async fn store_market_cap(
     let usd_result =
         convert_currency_with_rate(original_market_cap as f64, &currency, "USD", rate_map);
 
-    let eur_market_cap = eur_result.amount as i64;
-    let usd_market_cap = usd_result.amount as i64;
-    let eur_rate = eur_result.rate;
-    let usd_rate = usd_result.rate;
+    // Strict mode marks failed conversions NaN: store them as NULL, not 0
+    let eur_market_cap = eur_result
+        .amount
+        .is_finite()
+        .then_some(eur_result.amount as i64);
+    let usd_market_cap = usd_result
+        .amount
+        .is_finite()
+        .then_some(usd_result.amount as i64);
+    let eur_rate = eur_result.rate.is_finite().then_some(eur_result.rate);
+    let usd_rate = usd_result.rate.is_finite().then_some(usd_result.rate);
 
     let name = details.name.as_ref().unwrap_or(&String::new()).to_string();
     let currency_name = details
@@ -46,30 +121,54 @@ async fn store_market_cap(
         .as_ref()
         .unwrap_or(&String::new())
         .to_string();
+    // The listing exchange; details without one keep the currency name as before
+    let exchange = details
+        .extra
+        .get("exchange")
+        .and_then(|e| e.as_str())
+        .filter(|e| !e.is_empty())
+        .map_or(currency_name, str::to_string);
     let active = details.active.unwrap_or(true);
-
-    // Store market cap data with conversion rates
-    sqlx::query!(
+    // FMP reports the headcount as a string, sometimes with separators
+    let employees = details
+        .employees
+        .as_deref()
+        .and_then(|e| e.replace(',', "").trim().parse::<i64>().ok());
+
+    // Store market cap data with conversion rates, and the latest annual
+    // revenue and headcount for `efficiency-report`
+    core_query!(pool, |pool| sqlx::query(
         r#"
         INSERT INTO market_caps (
             ticker, name, market_cap_original, original_currency, market_cap_eur, market_cap_usd,
-            eur_rate, usd_rate, exchange, active, timestamp
-        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            eur_rate, usd_rate, exchange, active, revenue, revenue_usd, employees, timestamp,
+            data_source, fetched_at
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)
         "#,
-        details.ticker,
-        name,
-        original_market_cap,
-        currency,
-        eur_market_cap,
-        usd_market_cap,
-        eur_rate,
-        usd_rate,
-        currency_name,
-        active,
-        timestamp,
     )
+    .bind(&details.ticker)
+    .bind(&name)
+    .bind(original_market_cap)
+    .bind(&currency)
+    .bind(eur_market_cap)
+    .bind(usd_market_cap)
+    .bind(eur_rate)
+    .bind(usd_rate)
+    .bind(&exchange)
+    .bind(active)
+    .bind(details.revenue)
+    .bind(details.revenue_usd)
+    .bind(employees)
+    .bind(timestamp)
+    .bind(source.as_str())
+    .bind(models::fetched_at(Utc::now()))
     .execute(pool)
-    .await?;
+    .await?
+    .rows_affected());
+
+    if !update_details {
+        return Ok(());
+    }
 
     // Store ticker details
     let ticker_details = TickerDetails {
@@ -78,231 +177,770 @@ async fn store_market_cap(
         homepage_url: details.homepage_url.clone(),
         employees: details.employees.clone(),
         ceo: details.ceo.clone(),
+        country: details.country.clone(),
+        industry: details.industry.clone(),
+        isin: details.isin.clone(),
+        lei: details.lei.clone(),
     };
     ticker_details::update_ticker_details(pool, &ticker_details).await?;
 
     Ok(())
 }
 
-/// Fetch market cap data from the database
-async fn get_market_caps(pool: &SqlitePool) -> Result<Vec<(f64, Vec<String>)>> {
-    let records = sqlx::query!(
+/// Latest market cap row joined with its ticker details
+#[derive(sqlx::FromRow)]
+struct LatestMarketCap {
+    ticker: String,
+    name: String,
+    market_cap_original: Option<f64>,
+    original_currency: Option<String>,
+    market_cap_eur: Option<f64>,
+    market_cap_usd: Option<f64>,
+    eur_rate: Option<f64>,
+    usd_rate: Option<f64>,
+    exchange: Option<String>,
+    active: Option<bool>,
+    timestamp: i64,
+    data_source: Option<String>,
+    fetched_at: Option<String>,
+    description: Option<String>,
+    homepage_url: Option<String>,
+    employees: Option<String>,
+    ceo: Option<String>,
+    isin: Option<String>,
+    price: Option<f64>,
+    revenue: Option<f64>,
+    revenue_usd: Option<f64>,
+    eps: Option<f64>,
+    pe_ratio: Option<f64>,
+    de_ratio: Option<f64>,
+    roe: Option<f64>,
+    working_capital_ratio: Option<f64>,
+    quick_ratio: Option<f64>,
+}
+
+impl LatestMarketCap {
+    /// Fields the custom KPIs can use
+    fn kpi_inputs(&self) -> kpis::Inputs {
+        let employees = self
+            .employees
+            .as_deref()
+            .and_then(|e| e.trim().parse().ok());
+        let mut inputs = kpis::Inputs::default();
+        inputs
+            .set_number("market_cap", self.market_cap_usd)
+            .set_number("market_cap_usd", self.market_cap_usd)
+            .set_number("market_cap_eur", self.market_cap_eur)
+            .set_number("market_cap_original", self.market_cap_original)
+            .set_number("price", self.price)
+            .set_number("employees", employees)
+            .set_number("revenue", self.revenue)
+            .set_number("revenue_usd", self.revenue_usd)
+            .set_number("eps", self.eps)
+            .set_number("pe_ratio", self.pe_ratio)
+            .set_number("de_ratio", self.de_ratio)
+            .set_number("roe", self.roe)
+            .set_number("working_capital_ratio", self.working_capital_ratio)
+            .set_number("quick_ratio", self.quick_ratio);
+        inputs
+    }
+}
+
+/// Fetch market cap data from the database. Rows are keyed by EUR market
+/// cap, or by the KPI exports are ranked by.
+async fn get_market_caps(
+    pool: &CorePool,
+    report_currencies: &[String],
+    kpis: &Kpis,
+) -> Result<Vec<(f64, Vec<String>)>> {
+    let records = core_query!(pool, |pool| sqlx::query_as::<_, LatestMarketCap>(
         r#"
         SELECT
-            m.ticker as "ticker!",
-            m.name as "name!",
-            CAST(m.market_cap_original AS REAL) as market_cap_original,
+            m.ticker,
+            m.name,
+            CAST(m.market_cap_original AS DOUBLE PRECISION) as market_cap_original,
             m.original_currency,
-            CAST(m.market_cap_eur AS REAL) as market_cap_eur,
-            CAST(m.market_cap_usd AS REAL) as market_cap_usd,
-            CAST(m.eur_rate AS REAL) as eur_rate,
-            CAST(m.usd_rate AS REAL) as usd_rate,
+            CAST(m.market_cap_eur AS DOUBLE PRECISION) as market_cap_eur,
+            CAST(m.market_cap_usd AS DOUBLE PRECISION) as market_cap_usd,
+            CAST(m.eur_rate AS DOUBLE PRECISION) as eur_rate,
+            CAST(m.usd_rate AS DOUBLE PRECISION) as usd_rate,
             m.exchange,
             m.active,
-            strftime('%s', m.timestamp) as timestamp,
+            m.timestamp,
+            m.data_source,
+            m.fetched_at,
             td.description,
             td.homepage_url,
-            td.employees,
-            td.ceo
+            CAST(td.employees AS TEXT) as employees,
+            td.ceo,
+            td.isin,
+            CAST(m.price AS DOUBLE PRECISION) as price,
+            CAST(m.revenue AS DOUBLE PRECISION) as revenue,
+            CAST(m.revenue_usd AS DOUBLE PRECISION) as revenue_usd,
+            CAST(COALESCE(m.eps, td.eps) AS DOUBLE PRECISION) as eps,
+            CAST(COALESCE(m.pe_ratio, td.pe_ratio) AS DOUBLE PRECISION) as pe_ratio,
+            CAST(COALESCE(m.de_ratio, td.debt_equity_ratio) AS DOUBLE PRECISION) as de_ratio,
+            CAST(COALESCE(m.roe, td.roe) AS DOUBLE PRECISION) as roe,
+            CAST(COALESCE(m.working_capital_ratio, td.working_capital_ratio) AS DOUBLE PRECISION)
+                as working_capital_ratio,
+            CAST(COALESCE(m.quick_ratio, td.quick_ratio) AS DOUBLE PRECISION) as quick_ratio
         FROM market_caps m
         LEFT JOIN ticker_details td ON m.ticker = td.ticker
         WHERE m.timestamp = (SELECT MAX(timestamp) FROM market_caps)
         "#
     )
     .fetch_all(pool)
-    .await?;
+    .await?);
+
+    let rate_map = if report_currencies.is_empty() {
+        Arc::default()
+    } else {
+        get_rate_map_from_db(pool).await?
+    };
 
     let results = records
         .into_iter()
         .map(|r| {
-            let market_cap_eur = r.market_cap_eur.unwrap_or(0.0) as f64;
-            (
-                market_cap_eur,
-                vec![
-                    r.ticker.clone(),
-                    r.ticker,
-                    r.name,
-                    format!("{:.0}", r.market_cap_original.unwrap_or(0.0)),
-                    r.original_currency.unwrap_or_default(),
-                    format!("{:.0}", r.market_cap_eur.unwrap_or(0.0)),
-                    format_rate(r.eur_rate),
-                    format!("{:.0}", r.market_cap_usd.unwrap_or(0.0)),
-                    format_rate(r.usd_rate),
-                    r.exchange.unwrap_or_default(),
-                    if r.active.unwrap_or(true) {
-                        "true".to_string()
-                    } else {
-                        "false".to_string()
-                    },
-                    r.description.unwrap_or_default(),
-                    r.homepage_url.unwrap_or_default(),
-                    r.employees.map(|e| e.to_string()).unwrap_or_default(),
-                    r.ceo.unwrap_or_default(),
-                    r.timestamp.unwrap_or_default().to_string(),
-                ],
-            )
+            let kpi_values = kpis.evaluate(&r.kpi_inputs());
+            let sort_key = match kpis.rank_value(&kpi_values) {
+                Some(value) => value.unwrap_or(f64::NAN),
+                None => r.market_cap_eur.unwrap_or(0.0),
+            };
+            let extra = report_currency_values(
+                r.market_cap_original,
+                r.original_currency.as_deref().unwrap_or_default(),
+                report_currencies,
+                &rate_map,
+            );
+            let mut row = vec![
+                r.ticker.clone(),
+                r.ticker,
+                r.name,
+                format!("{:.0}", r.market_cap_original.unwrap_or(0.0)),
+                r.original_currency.unwrap_or_default(),
+                format!("{:.0}", r.market_cap_eur.unwrap_or(0.0)),
+                format_rate(r.eur_rate),
+                format!("{:.0}", r.market_cap_usd.unwrap_or(0.0)),
+                format_rate(r.usd_rate),
+                r.exchange.unwrap_or_default(),
+                if r.active.unwrap_or(true) {
+                    "true".to_string()
+                } else {
+                    "false".to_string()
+                },
+                r.description.unwrap_or_default(),
+                r.homepage_url.unwrap_or_default(),
+                r.employees.unwrap_or_default(),
+                r.ceo.unwrap_or_default(),
+                r.isin.unwrap_or_default(),
+                r.timestamp.to_string(),
+                r.data_source.unwrap_or_default(),
+                r.fetched_at.unwrap_or_default(),
+            ];
+            row.extend(extra);
+            row.extend(kpi_values.into_iter().map(kpis::format_value));
+            (sort_key, row)
         })
         .collect();
 
     Ok(results)
 }
 
-/// Update market cap data in the database
-async fn update_market_caps(pool: &SqlitePool) -> Result<()> {
+/// What the latest stored snapshot knows about a ticker: the fields a batch
+/// quote lacks
+#[derive(sqlx::FromRow, Debug, Clone)]
+struct StoredDetails {
+    ticker: String,
+    name: String,
+    original_currency: Option<String>,
+    exchange: Option<String>,
+    active: Option<bool>,
+    revenue: Option<f64>,
+    revenue_usd: Option<f64>,
+    employees: Option<i64>,
+    timestamp: i64,
+    /// When the row was fetched; rows carried forward by `--max-age` keep
+    /// the time of the fetch they were copied from
+    fetched_at: Option<String>,
+    /// When the ticker's `ticker_details` (CEO, country, industry, ISIN, ...)
+    /// were last written
+    details_updated_at: Option<String>,
+}
+
+/// Database timestamp as stored by SQLite and cast to text by PostgreSQL
+fn parse_db_time(value: Option<&str>) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value?, "%Y-%m-%d %H:%M:%S%.f").ok()
+}
+
+impl StoredDetails {
+    /// Fetch time of the row, UTC
+    fn fetched_at(&self) -> Option<NaiveDateTime> {
+        parse_db_time(self.fetched_at.as_deref())
+    }
+
+    /// Whether the ticker details were written at or after `cutoff`
+    fn details_fresh(&self, cutoff: NaiveDateTime) -> bool {
+        parse_db_time(self.details_updated_at.as_deref()).is_some_and(|updated| updated >= cutoff)
+    }
+}
+
+/// Stored details of every ticker whose latest row has a currency
+async fn get_stored_details(pool: &CorePool) -> Result<HashMap<String, StoredDetails>> {
+    let records = core_query!(pool, |pool| sqlx::query_as::<_, StoredDetails>(
+        r#"
+        SELECT
+            m.ticker,
+            m.name,
+            m.original_currency,
+            m.exchange,
+            m.active,
+            CAST(m.revenue AS DOUBLE PRECISION) as revenue,
+            CAST(m.revenue_usd AS DOUBLE PRECISION) as revenue_usd,
+            CAST(m.employees AS BIGINT) as employees,
+            m.timestamp,
+            CAST(m.created_at AS TEXT) as fetched_at,
+            CAST(td.updated_at AS TEXT) as details_updated_at
+        FROM market_caps m
+        JOIN (SELECT ticker, MAX(timestamp) AS ts FROM market_caps GROUP BY ticker) latest
+            ON m.ticker = latest.ticker AND m.timestamp = latest.ts
+        LEFT JOIN ticker_details td ON td.ticker = m.ticker
+        WHERE m.original_currency IS NOT NULL AND m.original_currency <> ''
+        "#
+    )
+    .fetch_all(pool)
+    .await?);
+    Ok(records.into_iter().map(|r| (r.ticker.clone(), r)).collect())
+}
+
+/// Split tickers into those fetched within `max_age` before `now`, which
+/// are carried forward, and the stale ones that are fetched again
+fn split_by_age(
+    tickers: &[String],
+    stored: &HashMap<String, StoredDetails>,
+    now: NaiveDateTime,
+    max_age: Duration,
+) -> (Vec<String>, Vec<String>) {
+    let cutoff = chrono::Duration::from_std(max_age)
+        .ok()
+        .and_then(|max_age| now.checked_sub_signed(max_age))
+        .unwrap_or(NaiveDateTime::MIN);
+    tickers.iter().cloned().partition(|ticker| {
+        stored
+            .get(ticker)
+            .and_then(StoredDetails::fetched_at)
+            .is_some_and(|fetched_at| fetched_at >= cutoff)
+    })
+}
+
+/// Copy the latest stored row of a fresh ticker to the snapshot at `timestamp`
+async fn carry_forward(pool: &CorePool, stored: &StoredDetails, timestamp: i64) -> Result<()> {
+    core_query!(pool, |pool| sqlx::query(
+        r#"
+        INSERT INTO market_caps (
+            ticker, name, market_cap_original, original_currency, market_cap_eur, market_cap_usd,
+            eur_rate, usd_rate, exchange, price, active, revenue, revenue_usd, employees,
+            created_at, data_source, fetched_at, timestamp
+        )
+        SELECT
+            ticker, name, market_cap_original, original_currency, market_cap_eur, market_cap_usd,
+            eur_rate, usd_rate, exchange, price, active, revenue, revenue_usd, employees,
+            created_at, data_source, fetched_at, $1
+        FROM market_caps
+        WHERE ticker = $2 AND timestamp = $3
+        "#,
+    )
+    .bind(timestamp)
+    .bind(&stored.ticker)
+    .bind(stored.timestamp)
+    .execute(pool)
+    .await?
+    .rows_affected());
+    Ok(())
+}
+
+/// Split tickers into those whose snapshot is built from their batch quote
+/// and stored details, and those that need the per-ticker detail requests:
+/// not quoted, never stored with a currency, ticker details written before
+/// `details_cutoff`, or all of them with `full_details`
+fn plan_details(
+    tickers: &[String],
+    quotes: &HashMap<String, FMPQuote>,
+    stored: &HashMap<String, StoredDetails>,
+    details_cutoff: NaiveDateTime,
+    full_details: bool,
+) -> (Vec<String>, Vec<String>) {
+    tickers.iter().cloned().partition(|ticker| {
+        !full_details
+            && stored
+                .get(ticker)
+                .is_some_and(|s| s.details_fresh(details_cutoff))
+            && quotes.get(ticker).is_some_and(|q| q.market_cap.is_some())
+    })
+}
+
+/// Details of a ticker from its batch quote, with the currency, name,
+/// exchange, revenue and headcount of its latest stored row
+fn details_from_quote(ticker: &str, quote: &FMPQuote, stored: &StoredDetails) -> models::Details {
+    let mut extra = HashMap::new();
+    let exchange = stored
+        .exchange
+        .clone()
+        .filter(|e| !e.is_empty())
+        .or_else(|| quote.exchange.clone())
+        .unwrap_or_default();
+    extra.insert("exchange".to_string(), Value::String(exchange));
+    if let Some(price) = quote.price.and_then(serde_json::Number::from_f64) {
+        extra.insert("price".to_string(), Value::Number(price));
+    }
+    models::Details {
+        ticker: ticker.to_string(),
+        market_cap: quote.market_cap,
+        name: Some(stored.name.clone()),
+        currency_name: stored.original_currency.clone(),
+        currency_symbol: stored.original_currency.clone(),
+        active: stored.active,
+        description: None,
+        homepage_url: None,
+        weighted_shares_outstanding: None,
+        employees: stored.employees.map(|e| e.to_string()),
+        revenue: stored.revenue,
+        revenue_usd: stored.revenue_usd,
+        timestamp: Some(Utc::now().to_rfc3339()),
+        ceo: None,
+        country: None,
+        industry: None,
+        isin: None,
+        lei: None,
+        working_capital_ratio: None,
+        quick_ratio: None,
+        eps: None,
+        pe_ratio: None,
+        debt_equity_ratio: None,
+        roe: None,
+        extra,
+    }
+}
+
+/// Details of a ticker from its Polygon market cap, with the name, exchange,
+/// revenue and headcount of its latest stored row so the two providers'
+/// rows stay comparable
+fn details_from_polygon(
+    snapshot: &PolygonMarketCap,
+    stored: Option<&StoredDetails>,
+) -> models::Details {
+    let mut details = snapshot.to_details();
+    if let Some(stored) = stored {
+        details.name = Some(stored.name.clone());
+        if let Some(exchange) = stored.exchange.clone().filter(|e| !e.is_empty()) {
+            details
+                .extra
+                .insert("exchange".to_string(), Value::String(exchange));
+        }
+        details.revenue = stored.revenue;
+        details.revenue_usd = stored.revenue_usd;
+        details.employees = stored.employees.map(|e| e.to_string());
+    }
+    details
+}
+
+/// Fetch the Polygon market caps of `tickers`; the ones that fail are
+/// fetched from FMP instead
+async fn fetch_polygon_market_caps(
+    tickers: &[String],
+    concurrency: usize,
+) -> Result<HashMap<String, PolygonMarketCap>> {
+    let api_key = std::env::var("POLYGON_API_KEY")
+        .map_err(|_| anyhow::anyhow!("POLYGON_API_KEY must be set to fetch from Polygon"))?;
+    let client = Arc::new(api::PolygonClient::new(api_key));
+    let today = Utc::now().date_naive();
+
+    println!("Fetching {} tickers from Polygon...", tickers.len());
+    let progress = progress::fetch_bar(tickers.len() as u64, "polygon", 2);
+    let fetched = utils::fetch_ordered(tickers, concurrency, |ticker| {
+        let client = client.clone();
+        let progress = progress.clone();
+        async move {
+            let market_cap = polygon_snapshot::fetch_market_cap(&client, ticker, today).await;
+            progress.inc(1);
+            market_cap
+        }
+    })
+    .await;
+    progress.finish();
+
+    let mut market_caps = HashMap::new();
+    for (ticker, result) in tickers.iter().zip(fetched) {
+        match result {
+            Ok(market_cap) => {
+                market_caps.insert(ticker.clone(), market_cap);
+            }
+            Err(e) => eprintln!("Polygon failed for {}, falling back to FMP: {}", ticker, e),
+        }
+    }
+    Ok(market_caps)
+}
+
+/// Report Polygon market caps that differ from the FMP quote by more than
+/// `[polygon] discrepancy_threshold_pct`
+fn report_discrepancies(
+    polygon: &HashMap<String, PolygonMarketCap>,
+    quotes: &HashMap<String, FMPQuote>,
+) {
+    let threshold = config::load_polygon_config().discrepancy_threshold_pct;
+    let mut pairs: Vec<(String, f64, f64)> = polygon
+        .values()
+        .filter_map(|p| {
+            let fmp = quotes.get(&p.ticker)?.market_cap?;
+            Some((p.ticker.clone(), p.market_cap, fmp))
+        })
+        .collect();
+    pairs.sort_by(|a, b| a.0.cmp(&b.0));
+    let discrepancies = polygon_snapshot::find_discrepancies(&pairs, threshold);
+    println!(
+        "🔍 Cross-validated {} Polygon market caps against FMP quotes: {} differ by more than {}%",
+        pairs.len(),
+        discrepancies.len(),
+        threshold
+    );
+    for d in &discrepancies {
+        let message = format!(
+            "{}: Polygon market cap {:.0} differs {:+.1}% from FMP's {:.0}",
+            d.ticker, d.polygon, d.diff_pct, d.fmp
+        );
+        println!("  ⚠️  {}", message);
+        run_context::record_warning(message);
+    }
+}
+
+/// Update market cap data in the database, and the analyst consensus of the
+/// fetched companies when `with_analyst` is set.
+///
+/// Prices and market caps come from batch quotes of `QUOTE_BATCH_SIZE`
+/// tickers per request; only tickers without a usable quote or stored
+/// details (or all of them with `full_details`) get the four per-ticker
+/// detail requests. With `max_age`, tickers fetched more recently than that
+/// are not fetched at all: their latest row is carried forward. US tickers
+/// selected by `provider` or `[polygon] tickers` are fetched from Polygon
+/// and cross-validated against their FMP quote.
+async fn update_market_caps(
+    pool: &SqlitePool,
+    core: &CorePool,
+    concurrency: usize,
+    with_analyst: bool,
+    full_details: bool,
+    max_age: Option<Duration>,
+    provider: Provider,
+) -> Result<()> {
     let config = config::load_config()?;
-    let tickers = [config.non_us_tickers, config.us_tickers].concat();
+    let tickers = [config.non_us_tickers, config.us_tickers.clone()].concat();
+    let today = Utc::now().format("%Y-%m-%d").to_string();
+    universe::record_universe(pool, &today, &tickers).await?;
 
     // Get latest exchange rates from database
     println!("Fetching current exchange rates from database...");
-    let rate_map = get_rate_map_from_db(pool).await?;
+    let rate_map = get_rate_map_from_db(core).await?;
     println!("✅ Exchange rates fetched from database");
 
     // Get FMP client for market data
-    let api_key = std::env::var("FINANCIALMODELINGPREP_API_KEY")
-        .expect("FINANCIALMODELINGPREP_API_KEY must be set");
-    let fmp_client = Arc::new(api::FMPClient::new(api_key));
-
-    // Create a rate_map Arc for sharing between tasks
-    let rate_map = Arc::new(rate_map);
-    let total_tickers = tickers.len();
+    let fmp_client = Arc::new(api::FMPClient::from_env()?);
 
     // Use a single UTC timestamp for all records (consistent with other modules)
-    let timestamp = Utc::now().timestamp();
+    let now = Utc::now();
+    let timestamp = now.timestamp();
 
-    // Process tickers with progress tracking
-    let progress = ProgressBar::new(total_tickers as u64);
-    progress.set_style(
-        ProgressStyle::default_bar()
-            .template("[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}")
-            .unwrap()
-            .progress_chars("=>-"),
+    let stored = get_stored_details(core).await?;
+    let (fresh, tickers) = match max_age {
+        Some(max_age) => split_by_age(&tickers, &stored, now.naive_utc(), max_age),
+        None => (Vec::new(), tickers),
+    };
+    if !fresh.is_empty() {
+        println!(
+            "Keeping {} tickers fetched within --max-age, fetching {} stale ones",
+            fresh.len(),
+            tickers.len()
+        );
+    }
+    let total_tickers = tickers.len();
+
+    let polygon_tickers = polygon_snapshot::polygon_tickers(
+        provider,
+        &tickers,
+        &config.us_tickers,
+        &config.polygon.tickers,
     );
+    let polygon = if polygon_tickers.is_empty() {
+        HashMap::new()
+    } else {
+        fetch_polygon_market_caps(&polygon_tickers, concurrency).await?
+    };
+    let polygon_count = polygon.len();
 
-    // Update market cap data in database
-    println!("Updating market cap data in database...");
     let mut failed_tickers = Vec::new();
-    for ticker in &tickers {
+    let mut skipped_tickers = Vec::new();
+    let mut quotes = HashMap::new();
+    if !full_details {
+        let batches: Vec<Vec<String>> = tickers
+            .chunks(api::QUOTE_BATCH_SIZE)
+            .map(<[String]>::to_vec)
+            .collect();
+        println!(
+            "Fetching quotes for {} tickers in {} batch requests...",
+            total_tickers,
+            batches.len()
+        );
+        let progress = progress::fetch_bar(batches.len() as u64, "quotes", 1);
+        let fetched = utils::fetch_ordered(&batches, concurrency, |batch| {
+            let fmp_client = fmp_client.clone();
+            let progress = progress.clone();
+            async move {
+                let quotes = fmp_client.get_batch_quotes(batch).await;
+                progress.inc(1);
+                quotes
+            }
+        })
+        .await;
+        progress.finish();
+        for result in fetched {
+            match result {
+                Ok(batch) => quotes.extend(batch.into_iter().map(|q| (q.symbol.clone(), q))),
+                // The tickers of a failed batch fall back to the detail requests
+                Err(e) => eprintln!("Failed to fetch batch quotes: {}", e),
+            }
+        }
+    }
+
+    if !polygon.is_empty() && !quotes.is_empty() {
+        report_discrepancies(&polygon, &quotes);
+    }
+
+    // The batch quotes of Polygon tickers only serve the cross-validation
+    let fmp_tickers: Vec<String> = tickers
+        .iter()
+        .filter(|ticker| !polygon.contains_key(*ticker))
+        .cloned()
+        .collect();
+    let details_max_age_days = config.company_profiles.details_max_age_days;
+    let details_cutoff = now.naive_utc() - chrono::Duration::days(details_max_age_days.max(0));
+    let (quoted, detailed) =
+        plan_details(&fmp_tickers, &quotes, &stored, details_cutoff, full_details);
+    if !detailed.is_empty() && !full_details {
+        println!(
+            "Fetching full details for {} tickers without a quote, stored details or details from the last {} days",
+            detailed.len(),
+            details_max_age_days
+        );
+    }
+
+    // Profile, ratios, income statement and executives per ticker
+    let progress = progress::fetch_bar(detailed.len() as u64, "tickers", 4);
+
+    // Fetch details concurrently, then store them in ticker order
+    println!(
+        "Updating market cap data in database ({} concurrent requests)...",
+        concurrency
+    );
+    let fetched = utils::fetch_ordered(&detailed, concurrency, |ticker| {
         let rate_map = rate_map.clone();
         let fmp_client = fmp_client.clone();
+        let progress = progress.clone();
+        async move {
+            let details =
+                circuit_breaker::within_deadline(ticker, fmp_client.get_details(ticker, &rate_map))
+                    .await;
+            progress.inc(1);
+            details
+        }
+    })
+    .await;
+    progress.finish();
+    let mut fetched: HashMap<&String, Result<models::Details>> =
+        detailed.iter().zip(fetched).collect();
 
-        match fmp_client.get_details(ticker, &rate_map).await {
+    let mut analyst_quotes = Vec::new();
+    for ticker in &tickers {
+        // Quoted and Polygon tickers keep their stored ticker details
+        let (result, source, update_details) = if let Some(snapshot) = polygon.get(ticker) {
+            (
+                Ok(details_from_polygon(snapshot, stored.get(ticker))),
+                DataSource::Polygon,
+                false,
+            )
+        } else {
+            match fetched.remove(ticker) {
+                Some(result) => (result, DataSource::Fmp, true),
+                None => {
+                    let details = details_from_quote(ticker, &quotes[ticker], &stored[ticker]);
+                    (Ok(details), DataSource::Fmp, false)
+                }
+            }
+        };
+        match result {
             Ok(details) => {
-                if let Err(e) = store_market_cap(pool, &details, &rate_map, timestamp).await {
+                if let Err(e) =
+                    store_market_cap(core, &details, source, &rate_map, timestamp, update_details)
+                        .await
+                {
                     eprintln!("Failed to store market cap for {}: {}", ticker, e);
                     failed_tickers.push((ticker, format!("Failed to store market cap: {}", e)));
+                    continue;
                 }
+                analyst_quotes.push(analyst::Quote {
+                    ticker: details.ticker.clone(),
+                    name: details.name.clone().unwrap_or_default(),
+                    currency: details.currency_symbol.clone(),
+                    price: details.extra.get("price").and_then(|p| p.as_f64()),
+                });
+            }
+            Err(e) if circuit_breaker::is_skipped(&e) => {
+                skipped_tickers.push((ticker.clone(), e.to_string()));
             }
             Err(e) => {
                 eprintln!("Failed to fetch details for {}: {}", ticker, e);
                 failed_tickers.push((ticker, format!("Failed to fetch details: {}", e)));
             }
         }
-        progress.inc(1);
     }
-    progress.finish();
+
+    for ticker in &fresh {
+        carry_forward(core, &stored[ticker], timestamp).await?;
+    }
 
     // Print summary of failed tickers
     if !failed_tickers.is_empty() {
         println!("\nFailed to process {} tickers:", failed_tickers.len());
         for (ticker, error) in &failed_tickers {
             println!("  {} - {}", ticker, error);
+            run_context::record_warning(format!("{}: {}", ticker, error));
         }
     }
+    circuit_breaker::report_skipped(&skipped_tickers);
 
     println!(
-        "✅ Market cap data updated in database ({} successful, {} failed)",
-        total_tickers - failed_tickers.len(),
-        failed_tickers.len()
+        "✅ Market cap data updated in database ({} successful, {} failed, {} skipped; {} from batch quotes, {} from Polygon)",
+        total_tickers - failed_tickers.len() - skipped_tickers.len(),
+        failed_tickers.len(),
+        skipped_tickers.len(),
+        quoted.len(),
+        polygon_count
     );
 
+    if !fresh.is_empty() {
+        println!("✅ Carried forward {} fresh tickers", fresh.len());
+    }
+
+    if with_analyst {
+        analyst::update_targets(pool, &fmp_client, &analyst_quotes, &today, concurrency).await?;
+    }
+
     Ok(())
 }
 
 /// Export market cap data to CSV
-pub async fn export_market_caps(pool: &SqlitePool) -> Result<()> {
+pub async fn export_market_caps(
+    pool: &CorePool,
+    report_currencies: &[String],
+    kpis: &Kpis,
+) -> Result<()> {
     // Get market cap data from database
     println!("Fetching market cap data from database...");
-    let mut results = get_market_caps(pool).await?;
+    let mut results = get_market_caps(pool, report_currencies, kpis).await?;
     println!("✅ Market cap data fetched from database");
 
-    // Sort by EUR market cap
-    results.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+    sort_by_market_cap(&mut results);
+    rankings::truncate_to_top(&mut results);
 
     // Export to CSV
-    let timestamp = Local::now().format("%Y%m%d_%H%M%S");
-    let filename = format!("output/combined_marketcaps_{}.csv", timestamp);
-    let file = std::fs::File::create(&filename)?;
+    let output = config::load_output_config();
+    output.ensure_directory()?;
+    let timestamp = config::OutputConfig::timestamp();
+    let path = output.file_path_at("combined_marketcaps", "", &timestamp, "csv");
+    let filename = path.display().to_string();
+    let file = std::fs::File::create(&path)?;
     let mut writer = Writer::from_writer(file);
 
     // Write headers
-    writer.write_record(&[
-        "Symbol",
-        "Ticker",
-        "Name",
-        "Market Cap (Original)",
-        "Original Currency",
-        "Market Cap (EUR)",
-        "EUR Rate",
-        "Market Cap (USD)",
-        "USD Rate",
-        "Exchange",
-        "Active",
-        "Description",
-        "Homepage URL",
-        "Employees",
-        "CEO",
-        "Timestamp",
-    ])?;
+    let mut headers = export_headers(report_currencies);
+    headers.extend(kpis.headers());
+    writer.write_record(&headers)?;
 
     // Write data
     for (_, record) in &results {
         writer.write_record(record)?;
     }
-
+    writer.flush()?;
     println!("✅ Market cap data exported to {}", filename);
+    data_package::write_for(
+        &path,
+        data_package::Provenance {
+            kind: "combined_marketcaps",
+            title: "Latest market caps".to_string(),
+            dates: Vec::new(),
+            requested_date: None,
+            inputs: Vec::new(),
+        },
+    )?;
+
+    // Totals per region and exchange of today's snapshot
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|h| h == name)
+            .with_context(|| format!("Export has no {} column", name))
+    };
+    let (exchange, currency, eur, usd) = (
+        column("Exchange")?,
+        column("Original Currency")?,
+        column("Market Cap (EUR)")?,
+        column("Market Cap (USD)")?,
+    );
+    let listings: Vec<regions::Listing> = results
+        .iter()
+        .map(|(_, record)| regions::Listing {
+            exchange: &record[exchange],
+            currency: &record[currency],
+            market_cap_eur: record[eur].parse().ok(),
+            market_cap_usd: record[usd].parse().ok(),
+        })
+        .collect();
+    let path = output.named_path(&format!(
+        "marketcaps_{}_by_region.csv",
+        clock::now().format("%Y-%m-%d")
+    ));
+    regions::export_breakdown_csv(&path, &listings)?;
+    println!("✅ Regional breakdown exported to {}", path.display());
     Ok(())
 }
 
 /// Export top 100 active companies to CSV
-pub async fn export_top_100_active(pool: &SqlitePool) -> Result<()> {
+pub async fn export_top_100_active(
+    pool: &CorePool,
+    report_currencies: &[String],
+    kpis: &Kpis,
+) -> Result<()> {
     // Get market cap data from database
-    let mut results = get_market_caps(pool).await?;
+    let mut results = get_market_caps(pool, report_currencies, kpis).await?;
 
-    // Sort by EUR market cap
-    results.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+    sort_by_market_cap(&mut results);
 
-    // Filter for active companies first, then take top 100
+    // Filter for active companies first, then take top 100 (or fewer under --top)
     let active_results: Vec<_> = results
         .iter()
         .filter(|(_, record)| record[10] == "true") // Active column (index shifted due to rate columns)
-        .take(100)
+        .take(rankings::top().map_or(100, |top| top.min(100)))
         .collect();
 
     // Export to CSV
-    let timestamp = Local::now().format("%Y%m%d_%H%M%S");
-    let filename = format!("output/top_100_active_{}.csv", timestamp);
-    let file = std::fs::File::create(&filename)?;
+    let output = config::load_output_config();
+    output.ensure_directory()?;
+    let path = output.file_path("top_100_active", "", "csv");
+    let filename = path.display().to_string();
+    let file = std::fs::File::create(&path)?;
     let mut writer = Writer::from_writer(file);
 
     // Write headers
-    writer.write_record(&[
-        "Symbol",
-        "Ticker",
-        "Name",
-        "Market Cap (Original)",
-        "Original Currency",
-        "Market Cap (EUR)",
-        "EUR Rate",
-        "Market Cap (USD)",
-        "USD Rate",
-        "Exchange",
-        "Active",
-        "Description",
-        "Homepage URL",
-        "Employees",
-        "CEO",
-        "Timestamp",
-    ])?;
+    let mut headers = export_headers(report_currencies);
+    headers.extend(kpis.headers());
+    writer.write_record(headers)?;
 
     // Write data
     for (_, record) in active_results {
@@ -313,27 +951,109 @@ pub async fn export_top_100_active(pool: &SqlitePool) -> Result<()> {
     Ok(())
 }
 
-/// Main entry point for market cap functionality
-pub async fn marketcaps(pool: &SqlitePool) -> Result<()> {
+/// Main entry point for market cap functionality. The core tables are
+/// written to `core`; the universe, rankings and analyst data go to the
+/// SQLite `pool`. Exports are ranked by the `rank_by` KPI when given. With
+/// a `label` the snapshot is also exported as the intraday snapshot
+/// `<date>@<label>` for comparisons. `provider` selects where the US
+/// tickers are fetched from.
+#[allow(clippy::too_many_arguments)]
+pub async fn marketcaps(
+    pool: &SqlitePool,
+    core: &CorePool,
+    report_currencies: &[String],
+    concurrency: usize,
+    with_analyst: bool,
+    full_details: bool,
+    max_age: Option<Duration>,
+    rank_by: Option<&str>,
+    label: Option<&str>,
+    provider: Provider,
+) -> Result<()> {
+    // Check the KPI definitions and label before spending API requests
+    let kpis = Kpis::load()?.rank_by(rank_by)?;
+    if let Some(label) = label {
+        snapshot_labels::validate_label(label)?;
+    }
+
     // First update currencies and exchange rates
-    let api_key = std::env::var("FINANCIALMODELINGPREP_API_KEY")
-        .expect("FINANCIALMODELINGPREP_API_KEY must be set");
-    let fmp_client = api::FMPClient::new(api_key);
+    let fmp_client = api::FMPClient::from_env()?;
 
     println!("Updating currencies and exchange rates...");
-    update_currencies(&fmp_client, pool).await?;
-    exchange_rates::update_exchange_rates(&fmp_client, pool).await?;
+    update_currencies(&fmp_client, core).await?;
+    exchange_rates::update_exchange_rates(&fmp_client, core).await?;
 
     // Then update market caps
-    update_market_caps(pool).await?;
+    update_market_caps(
+        pool,
+        core,
+        concurrency,
+        with_analyst,
+        full_details,
+        max_age,
+        provider,
+    )
+    .await?;
+    match core.as_sqlite() {
+        Some(pool) => {
+            rankings::record_latest_rankings(pool).await?;
+            search::rebuild_index(pool).await?;
+        }
+        None => println!(
+            "⚠️  Rankings and the search index are only updated when market caps are stored in SQLite"
+        ),
+    }
 
     // Export both the full list and top 100 active
-    export_market_caps(pool).await?;
-    export_top_100_active(pool).await?;
+    let report_currencies = extra_report_currencies(report_currencies);
+    export_market_caps(core, &report_currencies, &kpis).await?;
+    export_top_100_active(core, &report_currencies, &kpis).await?;
+
+    if let Some(label) = label {
+        match core.as_sqlite() {
+            Some(pool) => export_labeled_snapshot(pool, label, &report_currencies).await?,
+            None => println!(
+                "⚠️  Labeled snapshots are only exported when market caps are stored in SQLite"
+            ),
+        }
+    }
 
     Ok(())
 }
 
+/// Label the snapshot just stored and export it like a specific-date
+/// snapshot, as `marketcaps_<date>@<label>_<timestamp>.csv`
+async fn export_labeled_snapshot(
+    pool: &SqlitePool,
+    label: &str,
+    report_currencies: &[String],
+) -> Result<()> {
+    let latest: Option<i64> = sqlx::query_scalar("SELECT MAX(timestamp) FROM market_caps")
+        .fetch_one(pool)
+        .await?;
+    let Some(timestamp) = latest else {
+        return Ok(());
+    };
+    let snapshot = Snapshot::labeled(timestamp, label)?;
+    snapshot_labels::record_label(pool, &snapshot).await?;
+
+    let config = config::load_config()?;
+    let tickers = [config.non_us_tickers, config.us_tickers].concat();
+    let rate_map = get_rate_map_from_db_for_date(pool, Some(timestamp)).await?;
+    specific_date_marketcaps::export_specific_date_marketcaps(
+        pool,
+        &snapshot,
+        &config.output,
+        "marketcaps",
+        &tickers,
+        report_currencies,
+        &rate_map,
+        None,
+        specific_date_marketcaps::Store::Universe,
+    )
+    .await
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -402,11 +1122,23 @@ mod tests {
             "Homepage URL",
             "Employees",
             "CEO",
+            "ISIN",
             "Timestamp",
+            "Data Source",
+            "Fetched At",
         ];
 
         // Just verify our expected headers count
-        assert_eq!(expected_headers.len(), 16);
+        assert_eq!(expected_headers.len(), 19);
+        assert_eq!(export_headers(&[]), expected_headers);
+    }
+
+    #[test]
+    fn test_csv_headers_with_report_currencies() {
+        let headers = export_headers(&["GBP".to_string(), "JPY".to_string()]);
+        assert_eq!(headers.len(), 21);
+        assert_eq!(headers[19], "Market Cap (GBP)");
+        assert_eq!(headers[20], "Market Cap (JPY)");
     }
 
     // Tests for sorting behavior
@@ -441,6 +1173,24 @@ mod tests {
         assert_eq!(data.len(), 3);
     }
 
+    #[test]
+    fn test_sort_by_market_cap_breaks_ties_by_name() {
+        let row = |ticker: &str, name: &str| {
+            vec![ticker.to_string(), ticker.to_string(), name.to_string()]
+        };
+        let mut data = vec![
+            (100.0, row("ZZ", "Alpha")),
+            (100.0, row("BB", "Beta")),
+            (200.0, row("CC", "Gamma")),
+            (100.0, row("AA", "Beta")),
+        ];
+
+        sort_by_market_cap(&mut data);
+
+        let tickers: Vec<&str> = data.iter().map(|(_, r)| r[1].as_str()).collect();
+        assert_eq!(tickers, vec!["CC", "ZZ", "AA", "BB"]);
+    }
+
     #[test]
     fn test_market_cap_sorting_with_nan() {
         let mut data = vec![
@@ -559,4 +1309,297 @@ mod tests {
         assert_eq!(original_market_cap, 1_000_000_000);
         assert_eq!(currency, "USD");
     }
+
+    /// Store two companies at one timestamp and read them back as export rows
+    async fn assert_store_and_export_round_trip(pool: &CorePool) {
+        crate::currencies::insert_forex_rate(pool, "EUR/USD", 1.25, 1.25, 1_700_000_000)
+            .await
+            .unwrap();
+        let rate_map = get_rate_map_from_db(pool).await.unwrap();
+
+        for (ticker, market_cap, currency, exchange, revenue_usd, isin) in [
+            ("MC.PA", 300.0, "EUR", "PAR", 150.0, "FR0000121014"),
+            ("NKE", 100.0, "USD", "NYSE", 20.0, "US6541061031"),
+        ] {
+            let details: models::Details = serde_json::from_value(serde_json::json!({
+                "ticker": ticker,
+                "market_cap": market_cap,
+                "name": format!("{} Inc", ticker),
+                "currency_symbol": currency,
+                "employees": "1000",
+                "exchange": exchange,
+                "revenue_usd": revenue_usd,
+                "isin": isin,
+            }))
+            .unwrap();
+            store_market_cap(
+                pool,
+                &details,
+                DataSource::Fmp,
+                &rate_map,
+                1_700_000_000,
+                true,
+            )
+            .await
+            .unwrap();
+        }
+
+        let mut rows = get_market_caps(pool, &[], &Kpis::default()).await.unwrap();
+        sort_by_market_cap(&mut rows);
+        let summary: Vec<(f64, &str, &str, &str, &str, &str, &str)> = rows
+            .iter()
+            .map(|(eur, row)| {
+                (
+                    *eur,
+                    row[1].as_str(),
+                    row[7].as_str(),
+                    row[9].as_str(),
+                    row[13].as_str(),
+                    row[15].as_str(),
+                    row[16].as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    300.0,
+                    "MC.PA",
+                    "375",
+                    "PAR",
+                    "1000",
+                    "FR0000121014",
+                    "1700000000"
+                ),
+                (
+                    80.0,
+                    "NKE",
+                    "100",
+                    "NYSE",
+                    "1000",
+                    "US6541061031",
+                    "1700000000"
+                ),
+            ]
+        );
+        // Every row names its provider and when it was fetched
+        for (_, row) in &rows {
+            assert_eq!(row[17], "fmp");
+            assert!(row[18].ends_with('Z'), "{}", row[18]);
+        }
+
+        // KPIs become extra columns and can replace the EUR market cap as the ranking
+        let definitions = std::collections::BTreeMap::from([(
+            "ev_to_sales".to_string(),
+            "market_cap / revenue_usd".to_string(),
+        )]);
+        let kpis = Kpis::from_definitions(&definitions)
+            .unwrap()
+            .rank_by(Some("ev_to_sales"))
+            .unwrap();
+        let mut rows = get_market_caps(pool, &[], &kpis).await.unwrap();
+        sort_by_market_cap(&mut rows);
+        let ranked: Vec<(f64, &str, &str)> = rows
+            .iter()
+            .map(|(key, row)| (*key, row[1].as_str(), row[19].as_str()))
+            .collect();
+        assert_eq!(
+            ranked,
+            vec![(5.0, "NKE", "5.0000"), (2.5, "MC.PA", "2.5000")]
+        );
+    }
+
+    fn quote(symbol: &str, market_cap: Option<f64>) -> FMPQuote {
+        serde_json::from_value(serde_json::json!({
+            "symbol": symbol,
+            "name": format!("{} quote name", symbol),
+            "price": 12.5,
+            "marketCap": market_cap,
+            "exchange": "NYSE",
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_quoted_tickers_reuse_stored_details() {
+        let pool = crate::db::create_db_pool("sqlite::memory:").await.unwrap();
+        sqlx::query("ALTER TABLE ticker_details ADD COLUMN ceo TEXT")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let core: CorePool = (&pool).into();
+        let rate_map = std::collections::HashMap::new();
+        let details: models::Details = serde_json::from_value(serde_json::json!({
+            "ticker": "NKE",
+            "market_cap": 100.0,
+            "name": "Nike Inc",
+            "currency_symbol": "USD",
+            "employees": "79,400",
+            "revenue": 51.0,
+            "exchange": "NYSE",
+            "description": "Athletic footwear",
+        }))
+        .unwrap();
+        store_market_cap(
+            &core,
+            &details,
+            DataSource::Fmp,
+            &rate_map,
+            1_700_000_000,
+            true,
+        )
+        .await
+        .unwrap();
+
+        let stored = get_stored_details(&core).await.unwrap();
+        let tickers = vec!["NKE".to_string(), "MC.PA".to_string(), "TPR".to_string()];
+        let quotes: HashMap<String, FMPQuote> = [
+            ("NKE".to_string(), quote("NKE", Some(120.0))),
+            ("MC.PA".to_string(), quote("MC.PA", Some(300.0))),
+        ]
+        .into_iter()
+        .collect();
+        let cutoff = Utc::now().naive_utc() - chrono::Duration::days(30);
+        // MC.PA was never stored with a currency, TPR is not quoted
+        assert_eq!(
+            plan_details(&tickers, &quotes, &stored, cutoff, false),
+            (
+                vec!["NKE".to_string()],
+                vec!["MC.PA".to_string(), "TPR".to_string()]
+            )
+        );
+        assert_eq!(
+            plan_details(&tickers, &quotes, &stored, cutoff, true)
+                .0
+                .len(),
+            0
+        );
+        // Details written before the cutoff are fetched again
+        let later = Utc::now().naive_utc() + chrono::Duration::days(1);
+        assert!(
+            plan_details(&tickers, &quotes, &stored, later, false)
+                .0
+                .is_empty()
+        );
+        let no_market_cap: HashMap<String, FMPQuote> = [("NKE".to_string(), quote("NKE", None))]
+            .into_iter()
+            .collect();
+        assert!(
+            plan_details(&tickers, &no_market_cap, &stored, cutoff, false)
+                .0
+                .is_empty()
+        );
+
+        let from_quote = details_from_quote("NKE", &quotes["NKE"], &stored["NKE"]);
+        assert_eq!(from_quote.market_cap, Some(120.0));
+        assert_eq!(from_quote.name.as_deref(), Some("Nike Inc"));
+        assert_eq!(from_quote.currency_symbol.as_deref(), Some("USD"));
+        assert_eq!(from_quote.employees.as_deref(), Some("79400"));
+        assert_eq!(from_quote.revenue, Some(51.0));
+        assert_eq!(from_quote.extra["price"].as_f64(), Some(12.5));
+
+        // Storing a quote snapshot leaves the ticker details alone
+        store_market_cap(
+            &core,
+            &from_quote,
+            DataSource::Fmp,
+            &rate_map,
+            1_700_086_400,
+            false,
+        )
+        .await
+        .unwrap();
+        let description: Option<String> =
+            sqlx::query_scalar("SELECT description FROM ticker_details WHERE ticker = 'NKE'")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(description.as_deref(), Some("Athletic footwear"));
+        let stored = get_stored_details(&core).await.unwrap();
+        assert_eq!(stored["NKE"].revenue, Some(51.0));
+    }
+
+    #[tokio::test]
+    async fn test_max_age_carries_fresh_tickers_forward() {
+        let pool = crate::db::create_db_pool("sqlite::memory:").await.unwrap();
+        sqlx::query("ALTER TABLE ticker_details ADD COLUMN ceo TEXT")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let core: CorePool = (&pool).into();
+        for (ticker, created_at) in [
+            ("NKE", "2025-06-30 10:00:00"),
+            ("MC.PA", "2025-06-30 02:00:00"),
+        ] {
+            sqlx::query(
+                "INSERT INTO market_caps (ticker, name, original_currency, market_cap_eur, created_at, data_source, fetched_at, timestamp)
+                 VALUES (?, ?, 'USD', 100, ?, 'polygon', ?, 1000)",
+            )
+            .bind(ticker)
+            .bind(ticker)
+            .bind(created_at)
+            .bind(created_at.replace(' ', "T") + "Z")
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+        let stored = get_stored_details(&core).await.unwrap();
+        let tickers = vec!["NKE".to_string(), "MC.PA".to_string(), "TPR".to_string()];
+        let now =
+            NaiveDateTime::parse_from_str("2025-06-30 12:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        // Only NKE was fetched in the last 6 hours; TPR was never stored
+        let (fresh, stale) = split_by_age(
+            &tickers,
+            &stored,
+            now,
+            std::time::Duration::from_secs(6 * 3600),
+        );
+        assert_eq!(fresh, vec!["NKE"]);
+        assert_eq!(stale, vec!["MC.PA", "TPR"]);
+        // A max age beyond the calendar keeps every stored ticker
+        let (fresh, _) = split_by_age(&tickers, &stored, now, std::time::Duration::MAX);
+        assert_eq!(fresh.len(), 2);
+
+        carry_forward(&core, &stored["NKE"], 2000).await.unwrap();
+        let rows = get_market_caps(&core, &[], &Kpis::default()).await.unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].0, 100.0);
+        assert_eq!(rows[0].1[16], "2000");
+        assert_eq!(rows[0].1[17..19], ["polygon", "2025-06-30T10:00:00Z"]);
+        // The copy keeps the fetch time, so it turns stale like the original
+        let stored = get_stored_details(&core).await.unwrap();
+        assert_eq!(stored["NKE"].timestamp, 2000);
+        assert_eq!(
+            stored["NKE"].fetched_at(),
+            NaiveDateTime::parse_from_str("2025-06-30 10:00:00", "%Y-%m-%d %H:%M:%S").ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_store_and_export_round_trip_sqlite() {
+        let pool = crate::db::create_db_pool("sqlite::memory:").await.unwrap();
+        // The ceo column is not created by the SQLite migrations
+        sqlx::query("ALTER TABLE ticker_details ADD COLUMN ceo TEXT")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert_store_and_export_round_trip(&(&pool).into()).await;
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires PostgreSQL running, see TEST_POSTGRES_URL
+    async fn test_store_and_export_round_trip_postgres() {
+        let url = std::env::var("TEST_POSTGRES_URL")
+            .unwrap_or_else(|_| "postgres://postgres@127.0.0.1:5432/top200_test".to_string());
+        let pool = crate::db::create_core_pool(&url).await.unwrap();
+        for table in ["market_caps", "ticker_details", "forex_rates"] {
+            core_query!(&pool, |p| sqlx::query(&format!("DELETE FROM {}", table))
+                .execute(p)
+                .await
+                .unwrap()
+                .rows_affected());
+        }
+        assert_store_and_export_round_trip(&pool).await;
+    }
 }
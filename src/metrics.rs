@@ -0,0 +1,166 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Prometheus metrics for the web server and job worker
+//!
+//! Metrics live in a process-wide registry and are exposed by `serve` on
+//! `/metrics` in the Prometheus text format. CLI runs record into the same
+//! registry, which is simply never scraped.
+
+use anyhow::Result;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use std::sync::OnceLock;
+
+use crate::api_usage;
+
+/// Metric handles registered in one registry
+pub struct Metrics {
+    registry: Registry,
+    /// HTTP requests by method, matched route and status code
+    pub http_requests: IntCounterVec,
+    /// Jobs received by the worker that have not finished yet
+    pub jobs_in_progress: IntGauge,
+    /// Messages waiting in the JOBS_SUBMIT stream, sampled on scrape
+    pub nats_consumer_lag: IntGauge,
+    /// Job durations by job type and outcome
+    pub job_duration: HistogramVec,
+    /// Upstream API errors by endpoint and kind
+    pub api_errors: IntCounterVec,
+    /// FMP values that were missing or unreadable and replaced by 0, by field
+    pub schema_fallbacks: IntCounterVec,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self> {
+        let registry = Registry::new();
+
+        let http_requests = IntCounterVec::new(
+            Opts::new("top200_http_requests_total", "HTTP requests handled"),
+            &["method", "route", "status"],
+        )?;
+        let jobs_in_progress = IntGauge::new(
+            "top200_jobs_in_progress",
+            "Jobs received by the worker that have not finished",
+        )?;
+        let nats_consumer_lag = IntGauge::new(
+            "top200_nats_consumer_lag",
+            "Messages waiting in the JOBS_SUBMIT stream",
+        )?;
+        let job_duration = HistogramVec::new(
+            HistogramOpts::new("top200_job_duration_seconds", "Job fetch durations").buckets(vec![
+                1.0, 5.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1800.0,
+            ]),
+            &["job_type", "outcome"],
+        )?;
+        let api_errors = IntCounterVec::new(
+            Opts::new(
+                "top200_api_errors_total",
+                "Errors returned by upstream APIs",
+            ),
+            &["endpoint", "kind"],
+        )?;
+        let schema_fallbacks = IntCounterVec::new(
+            Opts::new(
+                "top200_schema_fallbacks_total",
+                "FMP values missing or unreadable and replaced by 0",
+            ),
+            &["field"],
+        )?;
+
+        registry.register(Box::new(http_requests.clone()))?;
+        registry.register(Box::new(jobs_in_progress.clone()))?;
+        registry.register(Box::new(nats_consumer_lag.clone()))?;
+        registry.register(Box::new(job_duration.clone()))?;
+        registry.register(Box::new(api_errors.clone()))?;
+        registry.register(Box::new(schema_fallbacks.clone()))?;
+
+        Ok(Self {
+            registry,
+            http_requests,
+            jobs_in_progress,
+            nats_consumer_lag,
+            job_duration,
+            api_errors,
+            schema_fallbacks,
+        })
+    }
+
+    /// All metrics in the Prometheus text exposition format
+    pub fn render(&self) -> Result<String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+}
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+/// The process-wide metrics registry
+pub fn metrics() -> &'static Metrics {
+    METRICS.get_or_init(|| Metrics::new().expect("Failed to register metrics"))
+}
+
+/// Count an upstream API error for `url`, e.g. kind `rate_limit`, `status` or `parse`
+pub fn record_api_error(url: &str, kind: &str) {
+    metrics()
+        .api_errors
+        .with_label_values(&[&api_usage::endpoint_name(url), kind])
+        .inc();
+}
+
+/// Count an FMP `field` replaced by 0; returns how often that happened so far
+pub fn record_schema_fallback(field: &str) -> u64 {
+    let counter = metrics().schema_fallbacks.with_label_values(&[field]);
+    counter.inc();
+    counter.get()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_includes_recorded_metrics() {
+        let metrics = Metrics::new().unwrap();
+        metrics
+            .http_requests
+            .with_label_values(&["GET", "/api/market-caps", "200"])
+            .inc();
+        metrics.jobs_in_progress.set(2);
+        metrics
+            .job_duration
+            .with_label_values(&["fetch-market-caps", "success"])
+            .observe(12.0);
+        metrics
+            .api_errors
+            .with_label_values(&["fmp /api/v3/profile/{symbol}", "rate_limit"])
+            .inc();
+
+        let text = metrics.render().unwrap();
+        assert!(text.contains(
+            "top200_http_requests_total{method=\"GET\",route=\"/api/market-caps\",status=\"200\"} 1"
+        ));
+        assert!(text.contains("top200_jobs_in_progress 2"));
+        assert!(text.contains("top200_nats_consumer_lag 0"));
+        assert!(text.contains(
+            "top200_job_duration_seconds_bucket{job_type=\"fetch-market-caps\",outcome=\"success\",le=\"15\"} 1"
+        ));
+        assert!(text.contains("kind=\"rate_limit\"} 1"));
+    }
+
+    #[test]
+    fn test_record_api_error_uses_endpoint_name() {
+        record_api_error(
+            "https://financialmodelingprep.com/api/v3/profile/NKE?apikey=secret",
+            "parse",
+        );
+        let count = metrics()
+            .api_errors
+            .with_label_values(&["fmp /api/v3/profile/{symbol}", "parse"])
+            .get();
+        assert!(count >= 1);
+    }
+}
@@ -2,6 +2,14 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-only
 
+//! API response and export models
+//!
+//! The FMP models tolerate schema drift: numbers may arrive as strings,
+//! fields renamed between the v3 and the `stable` API are accepted under
+//! either name (`alias`), optional fields may be missing or null, and fields
+//! we don't know yet are kept in `extra`. `check-api-schema` reports the
+//! fields a live response lacks (`src/api_schema.rs`).
+
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
@@ -26,6 +34,17 @@ pub struct Details {
     pub revenue_usd: Option<f64>,
     pub timestamp: Option<String>,
     pub ceo: Option<String>,
+    /// Headquarters country (ISO 3166 code)
+    #[serde(default)]
+    pub country: Option<String>,
+    #[serde(default)]
+    pub industry: Option<String>,
+    /// ISO 6166 security identifier, e.g. "US6541061031"
+    #[serde(default)]
+    pub isin: Option<String>,
+    /// ISO 17442 legal entity identifier
+    #[serde(default)]
+    pub lei: Option<String>,
     // Financial ratios
     pub working_capital_ratio: Option<f64>,
     pub quick_ratio: Option<f64>,
@@ -53,33 +72,101 @@ pub struct FMPCompanyProfile {
     pub symbol: String,
     #[serde(rename = "companyName")]
     pub company_name: String,
-    #[serde(rename = "mktCap", default)]
+    /// `mktCap` in v3, `marketCap` in the stable API
+    #[serde(
+        rename = "mktCap",
+        alias = "marketCap",
+        default = "lenient::missing_market_cap",
+        deserialize_with = "lenient::market_cap_or_zero"
+    )]
     pub market_cap: f64,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "lenient::string_or_empty")]
     pub description: String,
-    #[serde(rename = "website", default)]
+    #[serde(
+        rename = "website",
+        default,
+        deserialize_with = "lenient::string_or_empty"
+    )]
     pub website: String,
-    #[serde(rename = "fullTimeEmployees")]
+    /// A string in v3 ("79400"), sometimes a number
+    #[serde(
+        rename = "fullTimeEmployees",
+        default,
+        deserialize_with = "lenient::opt_string"
+    )]
     pub employees: Option<String>,
-    #[serde(rename = "price", default)]
+    #[serde(
+        rename = "price",
+        default = "lenient::missing_price",
+        deserialize_with = "lenient::price_or_zero"
+    )]
     pub price: f64,
     pub currency: String,
-    #[serde(rename = "exchangeShortName")]
+    /// Short exchange name. The stable API has it as `exchange`, which v3
+    /// uses for the full name, so it is not an alias: see `exchange_code()`.
+    #[serde(
+        rename = "exchangeShortName",
+        default,
+        deserialize_with = "lenient::string_or_empty"
+    )]
     pub exchange: String,
-    #[serde(rename = "isActivelyTrading", default)]
+    #[serde(
+        rename = "isActivelyTrading",
+        default,
+        deserialize_with = "lenient::bool_or_false"
+    )]
     pub is_active: bool,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "lenient::opt_string")]
     pub ceo: Option<String>,
+    /// Headquarters country as an ISO 3166 code, e.g. "FR"
+    #[serde(default)]
+    pub country: Option<String>,
+    /// e.g. "Luxury Goods" or "Apparel - Retail"
+    #[serde(default)]
+    pub industry: Option<String>,
+    #[serde(default)]
+    pub isin: Option<String>,
     // Add any other fields you need from the FMP API
     #[serde(flatten)]
     pub extra: std::collections::HashMap<String, Value>,
 }
 
+impl FMPCompanyProfile {
+    /// Short exchange name: `exchangeShortName` (v3), or `exchange` when
+    /// only the stable API's fields are present
+    pub fn exchange_code(&self) -> String {
+        if !self.exchange.is_empty() {
+            return self.exchange.clone();
+        }
+        self.extra
+            .get("exchange")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string()
+    }
+}
+
+/// Entry of an FMP (batch) quote: price and market cap, but no currency
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FMPQuote {
+    pub symbol: String,
+    #[serde(default, deserialize_with = "lenient::opt_string")]
+    pub name: Option<String>,
+    #[serde(default, deserialize_with = "lenient::opt_f64")]
+    pub price: Option<f64>,
+    #[serde(rename = "marketCap", default, deserialize_with = "lenient::opt_f64")]
+    pub market_cap: Option<f64>,
+    #[serde(default, deserialize_with = "lenient::opt_string")]
+    pub exchange: Option<String>,
+    #[serde(flatten)]
+    pub extra: std::collections::HashMap<String, Value>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct FMPExecutive {
     pub title: String,
     pub name: String,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "lenient::opt_f64")]
     pub pay: Option<f64>,
     #[serde(rename = "currencyPay", default)]
     pub currency_pay: Option<String>,
@@ -93,11 +180,36 @@ pub struct FMPExecutive {
 #[allow(dead_code)]
 pub struct FMPRatios {
     pub symbol: String,
+    #[serde(alias = "currentRatio", default, deserialize_with = "lenient::opt_f64")]
     pub current_ratio: Option<f64>,
+    #[serde(alias = "quickRatio", default, deserialize_with = "lenient::opt_f64")]
     pub quick_ratio: Option<f64>,
+    #[serde(
+        alias = "netIncomePerShare",
+        default,
+        deserialize_with = "lenient::opt_f64"
+    )]
     pub eps: Option<f64>,
+    /// `priceEarningsRatio` in v3, `priceToEarningsRatio` in the stable API
+    #[serde(
+        alias = "priceEarningsRatio",
+        alias = "priceToEarningsRatio",
+        default,
+        deserialize_with = "lenient::opt_f64"
+    )]
     pub price_earnings_ratio: Option<f64>,
+    #[serde(
+        alias = "debtEquityRatio",
+        alias = "debtToEquityRatio",
+        default,
+        deserialize_with = "lenient::opt_f64"
+    )]
     pub debt_equity_ratio: Option<f64>,
+    #[serde(
+        alias = "returnOnEquity",
+        default,
+        deserialize_with = "lenient::opt_f64"
+    )]
     pub return_on_equity: Option<f64>,
     // Add catch-all for other fields
     #[serde(flatten)]
@@ -109,6 +221,7 @@ pub struct FMPRatios {
 pub struct FMPIncomeStatement {
     pub date: String,
     pub symbol: String,
+    #[serde(default, deserialize_with = "lenient::opt_f64")]
     pub revenue: Option<f64>,
     // Add catch-all for other fields
     #[serde(flatten)]
@@ -137,6 +250,101 @@ pub struct Stock {
     pub ceo: String,
 }
 
+/// Provider a stored market cap was fetched from, kept with every
+/// `market_caps` row and exported as the `Data Source` column
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataSource {
+    Fmp,
+    Polygon,
+    /// Loaded from a file with `import-marketcaps`
+    Import,
+}
+
+impl DataSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DataSource::Fmp => "fmp",
+            DataSource::Polygon => "polygon",
+            DataSource::Import => "import",
+        }
+    }
+}
+
+/// `Fetched At` value of a row retrieved at `time`: ISO 8601 in UTC
+pub fn fetched_at(time: chrono::DateTime<chrono::Utc>) -> String {
+    time.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+/// Deserializers that accept the shapes FMP has been seen to send for a field
+mod lenient {
+    use serde::{Deserialize, Deserializer};
+    use serde_json::Value;
+
+    /// A number, a numeric string ("1.5e9", "1,234"), or nothing
+    pub fn opt_f64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+        Ok(match Value::deserialize(deserializer)? {
+            Value::Number(n) => n.as_f64(),
+            Value::String(s) => s.trim().replace(',', "").parse().ok(),
+            _ => None,
+        })
+    }
+
+    /// 0 for a value FMP left out or sent in a shape `opt_f64` can't read,
+    /// counted in `top200_schema_fallbacks_total`; warns on the first one of
+    /// each field in a run
+    fn fallback(field: &str) -> f64 {
+        if crate::metrics::record_schema_fallback(field) == 1 {
+            eprintln!(
+                "⚠️  FMP sent no usable {}; using 0 (see top200_schema_fallbacks_total)",
+                field
+            );
+        }
+        0.0
+    }
+
+    pub fn market_cap_or_zero<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        Ok(opt_f64(deserializer)?.unwrap_or_else(|| fallback("mktCap")))
+    }
+
+    pub fn missing_market_cap() -> f64 {
+        fallback("mktCap")
+    }
+
+    pub fn price_or_zero<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        Ok(opt_f64(deserializer)?.unwrap_or_else(|| fallback("price")))
+    }
+
+    pub fn missing_price() -> f64 {
+        fallback("price")
+    }
+
+    /// A string, or a number or boolean as text; null and "" are nothing
+    pub fn opt_string<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<String>, D::Error> {
+        Ok(match Value::deserialize(deserializer)? {
+            Value::String(s) if !s.is_empty() => Some(s),
+            Value::Number(n) => Some(n.to_string()),
+            Value::Bool(b) => Some(b.to_string()),
+            _ => None,
+        })
+    }
+
+    pub fn string_or_empty<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+        Ok(opt_string(deserializer)?.unwrap_or_default())
+    }
+
+    /// A boolean, "true"/"false", or 1/0; anything else is false
+    pub fn bool_or_false<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+        Ok(match Value::deserialize(deserializer)? {
+            Value::Bool(b) => b,
+            Value::String(s) => s.eq_ignore_ascii_case("true"),
+            Value::Number(n) => n.as_f64().is_some_and(|n| n != 0.0),
+            _ => false,
+        })
+    }
+}
+
 #[allow(dead_code)]
 fn default_string() -> String {
     "0".to_string()
@@ -164,6 +372,10 @@ mod tests {
             revenue_usd: Some(365000000000.0),
             timestamp: Some("2024-01-01".to_string()),
             ceo: Some("Tim Cook".to_string()),
+            country: Some("US".to_string()),
+            industry: Some("Consumer Electronics".to_string()),
+            isin: Some("US0378331005".to_string()),
+            lei: None,
             working_capital_ratio: Some(1.2),
             quick_ratio: Some(0.9),
             eps: Some(6.05),
@@ -231,6 +443,29 @@ mod tests {
         assert_eq!(profile.ceo, Some("Tim Cook".to_string()));
     }
 
+    #[test]
+    fn test_fmp_profile_counts_values_replaced_by_zero() {
+        let fallbacks = |field: &str| {
+            crate::metrics::metrics()
+                .schema_fallbacks
+                .with_label_values(&[field])
+                .get()
+        };
+        let (market_caps, prices) = (fallbacks("mktCap"), fallbacks("price"));
+        let json = json!({
+            "symbol": "AAPL",
+            "companyName": "Apple Inc.",
+            "price": "n/a",
+            "currency": "USD"
+        });
+
+        let profile: FMPCompanyProfile = serde_json::from_value(json).unwrap();
+        assert_eq!(profile.market_cap, 0.0);
+        assert_eq!(profile.price, 0.0);
+        assert!(fallbacks("mktCap") > market_caps);
+        assert!(fallbacks("price") > prices);
+    }
+
     #[test]
     fn test_fmp_ratios_deserialization() {
         let json = json!({
@@ -253,6 +488,65 @@ mod tests {
         assert_eq!(ratios.return_on_equity, Some(0.15));
     }
 
+    #[test]
+    fn test_fmp_models_tolerate_schema_drift() {
+        // Stable API names, numbers as strings, an employee count as a number
+        let profile: FMPCompanyProfile = serde_json::from_value(json!({
+            "symbol": "NKE",
+            "companyName": "NIKE, Inc.",
+            "marketCap": "110000000000",
+            "fullTimeEmployees": 79400,
+            "price": null,
+            "currency": "USD",
+            "exchange": "NYSE",
+            "exchangeFullName": "New York Stock Exchange",
+            "isActivelyTrading": "true",
+            "website": null
+        }))
+        .unwrap();
+        assert_eq!(profile.market_cap, 110e9);
+        assert_eq!(profile.employees.as_deref(), Some("79400"));
+        assert_eq!(profile.price, 0.0);
+        assert!(profile.is_active);
+        assert_eq!(profile.website, "");
+        assert_eq!(profile.exchange_code(), "NYSE");
+        assert!(profile.extra.contains_key("exchangeFullName"));
+
+        // v3 has the full name under `exchange`
+        let profile: FMPCompanyProfile = serde_json::from_value(json!({
+            "symbol": "NKE",
+            "companyName": "NIKE, Inc.",
+            "mktCap": 110e9,
+            "currency": "USD",
+            "exchange": "New York Stock Exchange",
+            "exchangeShortName": "NYSE"
+        }))
+        .unwrap();
+        assert_eq!(profile.exchange_code(), "NYSE");
+
+        let ratios: FMPRatios = serde_json::from_value(json!({
+            "symbol": "NKE",
+            "currentRatio": 2.4,
+            "priceToEarningsRatio": "28.1",
+            "debtEquityRatio": 0.8,
+            "returnOnEquity": null
+        }))
+        .unwrap();
+        assert_eq!(ratios.current_ratio, Some(2.4));
+        assert_eq!(ratios.price_earnings_ratio, Some(28.1));
+        assert_eq!(ratios.debt_equity_ratio, Some(0.8));
+        assert_eq!(ratios.return_on_equity, None);
+
+        let quote: FMPQuote = serde_json::from_value(json!({
+            "symbol": "NKE",
+            "marketCap": "1,234",
+            "changesPercentage": 1.2
+        }))
+        .unwrap();
+        assert_eq!(quote.market_cap, Some(1234.0));
+        assert_eq!(quote.extra["changesPercentage"], json!(1.2));
+    }
+
     #[test]
     fn test_fmp_income_statement_deserialization() {
         let json = json!({
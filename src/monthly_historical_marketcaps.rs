@@ -3,116 +3,230 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 
 use crate::api;
+use crate::clock::{self, Clock};
 use crate::config;
 use crate::currencies::{convert_currency_with_rate, get_rate_map_from_db_for_date};
+use crate::historical_marketcaps::HISTORICAL_REQUESTS_PER_TICKER;
+use crate::models::{self, DataSource};
+use crate::progress;
+use crate::utils;
 use anyhow::Result;
-use chrono::{Datelike, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Utc};
 use sqlx::sqlite::SqlitePool;
+use std::collections::HashSet;
 use std::sync::Arc;
 
-/// Fetches historical market caps for the last day of each month within the specified year range
+/// Tickers fetched, skipped because they were already stored, and failed
+#[derive(Debug, Default, PartialEq)]
+pub struct FetchSummary {
+    pub fetched: usize,
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+/// Date and time under which a month's market caps are stored: the last day
+/// of the month at 23:59 UTC
+fn month_end(year: i32, month: u32) -> NaiveDateTime {
+    let time = NaiveTime::from_hms_opt(23, 59, 0).unwrap();
+    NaiveDateTime::new(get_last_day_of_month(year, month), time)
+}
+
+/// Tickers of a month that still need fetching: all of them with `refresh`,
+/// otherwise those without a `market_caps` row for the month
+fn tickers_to_fetch(tickers: &[String], stored: &HashSet<String>, refresh: bool) -> Vec<String> {
+    tickers
+        .iter()
+        .filter(|t| refresh || !stored.contains(*t))
+        .cloned()
+        .collect()
+}
+
+/// Tickers with a `market_caps` row at `timestamp`
+async fn stored_tickers(pool: &SqlitePool, timestamp: i64) -> Result<HashSet<String>> {
+    let stored: Vec<String> =
+        sqlx::query_scalar("SELECT ticker FROM market_caps WHERE timestamp = ?")
+            .bind(timestamp)
+            .fetch_all(pool)
+            .await?;
+    Ok(stored.into_iter().collect())
+}
+
+/// Completed months with the tickers still to fetch for each; months already
+/// stored are left out and their tickers counted as skipped. The current month
+/// is left out until it has ended, since its month-end date lies in the future
+/// and a stored row would keep it from being fetched once the month is over.
+async fn plan_months(
+    pool: &SqlitePool,
+    tickers: &[String],
+    start_year: i32,
+    end_year: i32,
+    refresh: bool,
+    summary: &mut FetchSummary,
+) -> Result<Vec<(i32, u32, Vec<String>)>> {
+    let mut plan = Vec::new();
+    for year in start_year..=end_year {
+        for month in 1..=12 {
+            if !is_completed_month(year, month, clock::current()) {
+                break;
+            }
+            let timestamp = month_end(year, month).and_utc().timestamp();
+            let stored = stored_tickers(pool, timestamp).await?;
+            let to_fetch = tickers_to_fetch(tickers, &stored, refresh);
+            summary.skipped += tickers.len() - to_fetch.len();
+            if !to_fetch.is_empty() {
+                plan.push((year, month, to_fetch));
+            }
+        }
+    }
+    Ok(plan)
+}
+
+/// Fetches historical market caps for the last day of each month within the
+/// specified year range. (month, ticker) pairs already in the database are
+/// skipped unless `refresh` is set, so an interrupted run can be resumed.
 pub async fn fetch_monthly_historical_marketcaps(
     pool: &SqlitePool,
     start_year: i32,
     end_year: i32,
-) -> Result<()> {
+    concurrency: usize,
+    refresh: bool,
+) -> Result<FetchSummary> {
     let config = config::load_config()?;
     let tickers = [config.non_us_tickers, config.us_tickers].concat();
 
     // Get FMP client for market data
-    let api_key = std::env::var("FINANCIALMODELINGPREP_API_KEY")
-        .expect("FINANCIALMODELINGPREP_API_KEY must be set");
-    let fmp_client = Arc::new(api::FMPClient::new(api_key));
+    let fmp_client = Arc::new(api::FMPClient::from_env()?);
 
     println!(
         "Fetching monthly historical market caps from {} to {}",
         start_year, end_year
     );
 
-    for year in start_year..=end_year {
-        for month in 1..=12 {
-            // Skip future months in the current year
-            if year == Utc::now().year() && month > Utc::now().month() {
-                break;
-            }
+    let mut summary = FetchSummary::default();
+    let months = plan_months(pool, &tickers, start_year, end_year, refresh, &mut summary).await?;
+    if summary.skipped > 0 {
+        println!(
+            "Skipping {} (month, ticker) pairs already in the database (use --refresh to fetch them again)",
+            summary.skipped
+        );
+    }
+
+    // Overall bar over every (month, ticker) fetch, with one bar per month below it
+    let total = progress::fetch_bar(
+        months.iter().map(|(_, _, t)| t.len() as u64).sum(),
+        "total",
+        HISTORICAL_REQUESTS_PER_TICKER,
+    );
 
-            // Get the last day of the month at 23:59
-            let last_day = get_last_day_of_month(year, month);
-            let time = NaiveTime::from_hms_opt(23, 59, 0).unwrap();
-            let naive_dt = NaiveDateTime::new(last_day, time);
-            let datetime_utc = naive_dt.and_utc();
-            let timestamp = naive_dt.and_utc().timestamp();
+    for (year, month, tickers) in months {
+        let naive_dt = month_end(year, month);
+        let datetime_utc = naive_dt.and_utc();
+        let timestamp = naive_dt.and_utc().timestamp();
 
-            println!("Fetching exchange rates for {}", naive_dt);
-            let rate_map = get_rate_map_from_db_for_date(pool, Some(timestamp)).await?;
+        let rate_map = get_rate_map_from_db_for_date(pool, Some(timestamp)).await?;
 
-            for ticker in &tickers {
-                match fmp_client
+        // Fetch concurrently, then store in ticker order
+        let month_bar = progress::fetch_bar(
+            tickers.len() as u64,
+            &format!("{}-{:02}", year, month),
+            HISTORICAL_REQUESTS_PER_TICKER,
+        );
+        let fetched = utils::fetch_ordered(&tickers, concurrency, |ticker| {
+            let (month_bar, total) = (month_bar.clone(), total.clone());
+            let fmp_client = fmp_client.clone();
+            async move {
+                let market_cap = fmp_client
                     .get_historical_market_cap(ticker, &datetime_utc)
-                    .await
-                {
-                    Ok(market_cap) => {
-                        // Convert currencies with rate information
-                        let eur_result = convert_currency_with_rate(
-                            market_cap.market_cap_original,
-                            &market_cap.original_currency,
-                            "EUR",
-                            &rate_map,
-                        );
-
-                        let usd_result = convert_currency_with_rate(
-                            market_cap.market_cap_original,
-                            &market_cap.original_currency,
-                            "USD",
-                            &rate_map,
-                        );
-
-                        // Store the Unix timestamp of the historical date
-                        let timestamp = naive_dt.and_utc().timestamp();
-
-                        // Insert into database (use OR REPLACE to handle re-runs gracefully)
-                        sqlx::query!(
-                            r#"
+                    .await;
+                month_bar.inc(1);
+                total.inc(1);
+                market_cap
+            }
+        })
+        .await;
+        month_bar.finish_and_clear();
+
+        for (ticker, result) in tickers.iter().zip(fetched) {
+            match result {
+                Ok(market_cap) => {
+                    // Convert currencies with rate information
+                    let eur_result = convert_currency_with_rate(
+                        market_cap.market_cap_original,
+                        &market_cap.original_currency,
+                        "EUR",
+                        &rate_map,
+                    );
+
+                    let usd_result = convert_currency_with_rate(
+                        market_cap.market_cap_original,
+                        &market_cap.original_currency,
+                        "USD",
+                        &rate_map,
+                    );
+
+                    // Store the Unix timestamp of the historical date
+                    let timestamp = naive_dt.and_utc().timestamp();
+
+                    // Insert into database (use OR REPLACE to handle re-runs gracefully)
+                    let data_source = DataSource::Fmp.as_str();
+                    let fetched_at = models::fetched_at(Utc::now());
+                    sqlx::query!(
+                        r#"
                             INSERT OR REPLACE INTO market_caps (
                                 ticker, name, market_cap_original, original_currency,
                                 market_cap_eur, market_cap_usd, eur_rate, usd_rate,
-                                exchange, price, active, timestamp
+                                exchange, price, active, timestamp, data_source, fetched_at
                             )
-                            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
                             "#,
-                            ticker,
-                            market_cap.name,
-                            market_cap.market_cap_original,
-                            market_cap.original_currency,
-                            eur_result.amount,
-                            usd_result.amount,
-                            eur_result.rate,
-                            usd_result.rate,
-                            market_cap.exchange,
-                            market_cap.price,
-                            true,
-                            timestamp,
-                        )
-                        .execute(pool)
-                        .await?;
-
-                        println!(
-                            "✅ Added historical market cap for {} on {}",
-                            ticker, naive_dt
-                        );
-                    }
-                    Err(e) => {
+                        ticker,
+                        market_cap.name,
+                        market_cap.market_cap_original,
+                        market_cap.original_currency,
+                        eur_result.amount,
+                        usd_result.amount,
+                        eur_result.rate,
+                        usd_result.rate,
+                        market_cap.exchange,
+                        market_cap.price,
+                        true,
+                        timestamp,
+                        data_source,
+                        fetched_at,
+                    )
+                    .execute(pool)
+                    .await?;
+
+                    progress::println(format!(
+                        "✅ Added historical market cap for {} on {}",
+                        ticker, naive_dt
+                    ));
+                    summary.fetched += 1;
+                }
+                Err(e) => {
+                    summary.failed += 1;
+                    progress::suspend(|| {
                         eprintln!(
                             "❌ Failed to fetch market cap for {} on {}: {}",
                             ticker, naive_dt, e
-                        );
-                    }
+                        )
+                    });
                 }
             }
         }
     }
+    total.finish();
 
-    Ok(())
+    println!(
+        "📊 Monthly historical market caps: {} fetched, {} skipped (already stored), {} failed",
+        summary.fetched, summary.skipped, summary.failed
+    );
+    Ok(summary)
+}
+
+/// Whether the last day of `month` of `year` lies before today on `clock`
+fn is_completed_month(year: i32, month: u32, clock: &dyn Clock) -> bool {
+    get_last_day_of_month(year, month) < clock.today()
 }
 
 /// Helper function to get the last day of a given month
@@ -152,4 +266,57 @@ mod tests {
             NaiveDate::from_ymd_opt(2025, 12, 31).unwrap()
         );
     }
+
+    #[test]
+    fn test_tickers_to_fetch_skips_stored_unless_refresh() {
+        let tickers = vec!["MC.PA".to_string(), "NKE".to_string()];
+        let stored: HashSet<String> = ["NKE".to_string()].into_iter().collect();
+        assert_eq!(tickers_to_fetch(&tickers, &stored, false), vec!["MC.PA"]);
+        assert_eq!(tickers_to_fetch(&tickers, &stored, true), tickers);
+    }
+
+    #[tokio::test]
+    async fn test_plan_months_leaves_out_complete_months() {
+        let pool = crate::db::create_db_pool("sqlite::memory:").await.unwrap();
+        let _clock = clock::freeze(month_end(2025, 3).date().and_hms_opt(12, 0, 0).unwrap());
+        for (ticker, month) in [("MC.PA", 1), ("NKE", 1), ("NKE", 2)] {
+            sqlx::query("INSERT INTO market_caps (ticker, name, timestamp) VALUES (?, ?, ?)")
+                .bind(ticker)
+                .bind(ticker)
+                .bind(month_end(2025, month).and_utc().timestamp())
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        let tickers = vec!["MC.PA".to_string(), "NKE".to_string()];
+
+        let mut summary = FetchSummary::default();
+        let plan = plan_months(&pool, &tickers, 2025, 2025, false, &mut summary)
+            .await
+            .unwrap();
+        // January is stored, February lacks MC.PA, March has not ended yet
+        assert_eq!(plan, vec![(2025, 2, vec!["MC.PA".to_string()])]);
+        assert_eq!(summary.skipped, 3);
+
+        let mut summary = FetchSummary::default();
+        let plan = plan_months(&pool, &tickers, 2025, 2025, true, &mut summary)
+            .await
+            .unwrap();
+        assert_eq!(plan.len(), 2);
+        assert_eq!(summary.skipped, 0);
+    }
+
+    #[test]
+    fn test_is_completed_month() {
+        let clock = clock::FixedClock::parse("2025-03-15").unwrap();
+        assert!(is_completed_month(2025, 2, &clock));
+        assert!(is_completed_month(2024, 12, &clock));
+        assert!(!is_completed_month(2025, 3, &clock));
+        assert!(!is_completed_month(2025, 4, &clock));
+
+        let clock = clock::FixedClock::parse("2025-03-31").unwrap();
+        assert!(!is_completed_month(2025, 3, &clock));
+        let clock = clock::FixedClock::parse("2025-04-01").unwrap();
+        assert!(is_completed_month(2025, 3, &clock));
+    }
 }
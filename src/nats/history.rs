@@ -0,0 +1,348 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Job history in SQLite
+//!
+//! The worker records every job it runs in the `jobs` table: its request,
+//! attempts, timings, output files and final error. Unlike the NATS tracking
+//! streams, the table keeps jobs after the stream retention has expired.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use serde::Serialize;
+use sqlx::Row;
+use sqlx::sqlite::SqlitePool;
+use std::time::Duration;
+
+use super::JobRequest;
+use crate::error::ErrorCode;
+
+const STATUSES: [&str; 3] = ["running", "completed", "failed"];
+
+/// One job as stored in the `jobs` table
+#[derive(Debug, Clone, Serialize)]
+pub struct JobRecord {
+    pub job_id: String,
+    pub job_type: String,
+    pub parameters: serde_json::Value,
+    pub status: String,
+    pub attempts: i64,
+    pub submitted_at: String,
+    pub started_at: String,
+    pub completed_at: Option<String>,
+    pub duration_secs: Option<f64>,
+    pub output_files: Vec<String>,
+    pub error: Option<String>,
+    /// `ErrorCode` of the failure, when it has one
+    pub error_code: Option<String>,
+}
+
+/// Mark a job as running. The first attempt (re)starts the record, so a job
+/// requeued from the dead-letter stream gets fresh timings.
+pub async fn record_attempt(pool: &SqlitePool, request: &JobRequest, attempt: u32) -> Result<()> {
+    let parameters = serde_json::to_string(&request.parameters)?;
+    let now = Utc::now().to_rfc3339();
+    sqlx::query(
+        "INSERT INTO jobs (job_id, job_type, parameters, status, attempts, submitted_at, started_at)
+         VALUES (?, ?, ?, 'running', ?, ?, ?)
+         ON CONFLICT(job_id) DO UPDATE SET
+             status = 'running',
+             attempts = excluded.attempts,
+             submitted_at = CASE WHEN excluded.attempts = 1
+                 THEN excluded.submitted_at ELSE jobs.submitted_at END,
+             started_at = CASE WHEN excluded.attempts = 1
+                 THEN excluded.started_at ELSE jobs.started_at END,
+             completed_at = NULL,
+             duration_secs = NULL,
+             output_files = NULL,
+             error = NULL,
+             error_code = NULL",
+    )
+    .bind(&request.job_id)
+    .bind(request.job_type.as_str())
+    .bind(parameters)
+    .bind(attempt as i64)
+    .bind(request.submitted_at.to_rfc3339())
+    .bind(now)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+async fn record_finished(
+    pool: &SqlitePool,
+    job_id: &str,
+    status: &str,
+    output_files: &[String],
+    error: Option<(&str, Option<ErrorCode>)>,
+    duration: Duration,
+) -> Result<()> {
+    sqlx::query(
+        "UPDATE jobs SET status = ?, completed_at = ?, duration_secs = ?, output_files = ?,
+             error = ?, error_code = ?
+         WHERE job_id = ?",
+    )
+    .bind(status)
+    .bind(Utc::now().to_rfc3339())
+    .bind(duration.as_secs_f64())
+    .bind(serde_json::to_string(output_files)?)
+    .bind(error.map(|(message, _)| message))
+    .bind(error.and_then(|(_, code)| code).map(ErrorCode::as_str))
+    .bind(job_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Mark a job as completed; `duration` covers all attempts
+pub async fn record_completed(
+    pool: &SqlitePool,
+    job_id: &str,
+    output_files: &[String],
+    duration: Duration,
+) -> Result<()> {
+    record_finished(pool, job_id, "completed", output_files, None, duration).await
+}
+
+/// Mark a job as failed after its last attempt
+pub async fn record_failed(
+    pool: &SqlitePool,
+    job_id: &str,
+    error: &str,
+    error_code: Option<ErrorCode>,
+    duration: Duration,
+) -> Result<()> {
+    record_finished(
+        pool,
+        job_id,
+        "failed",
+        &[],
+        Some((error, error_code)),
+        duration,
+    )
+    .await
+}
+
+/// Normalise a `since` filter (YYYY-MM-DD or RFC 3339) to a UTC timestamp
+/// comparable with the stored ones
+pub fn parse_since(value: &str) -> Result<String> {
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(date.and_time(NaiveTime::MIN).and_utc().to_rfc3339());
+    }
+    let timestamp = DateTime::parse_from_rfc3339(value)
+        .with_context(|| format!("Invalid since '{}': use YYYY-MM-DD or RFC 3339", value))?;
+    Ok(timestamp.with_timezone(&Utc).to_rfc3339())
+}
+
+/// Check a `status` filter against the recorded statuses
+pub fn validate_status(status: &str) -> Result<()> {
+    if !STATUSES.contains(&status) {
+        anyhow::bail!(
+            "Unknown job status '{}': use {}",
+            status,
+            STATUSES.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Jobs submitted at or after `since`, newest first, optionally by status
+pub async fn list_jobs(
+    pool: &SqlitePool,
+    status: Option<&str>,
+    since: Option<&str>,
+) -> Result<Vec<JobRecord>> {
+    if let Some(status) = status {
+        validate_status(status)?;
+    }
+    let since = since.map(parse_since).transpose()?;
+
+    let rows = sqlx::query(
+        "SELECT job_id, job_type, parameters, status, attempts, submitted_at, started_at,
+                completed_at, duration_secs, output_files, error, error_code
+         FROM jobs
+         WHERE (?1 IS NULL OR status = ?1) AND (?2 IS NULL OR submitted_at >= ?2)
+         ORDER BY submitted_at DESC",
+    )
+    .bind(status)
+    .bind(since)
+    .fetch_all(pool)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| {
+            let parameters: String = row.get("parameters");
+            let output_files: Option<String> = row.get("output_files");
+            Ok(JobRecord {
+                job_id: row.get("job_id"),
+                job_type: row.get("job_type"),
+                parameters: serde_json::from_str(&parameters)?,
+                status: row.get("status"),
+                attempts: row.get("attempts"),
+                submitted_at: row.get("submitted_at"),
+                started_at: row.get("started_at"),
+                completed_at: row.get("completed_at"),
+                duration_secs: row.get("duration_secs"),
+                output_files: output_files
+                    .map(|files| serde_json::from_str(&files))
+                    .transpose()?
+                    .unwrap_or_default(),
+                error: row.get("error"),
+                error_code: row.get("error_code"),
+            })
+        })
+        .collect()
+}
+
+/// Print recent jobs for `jobs history`
+pub async fn show_history(
+    pool: &SqlitePool,
+    status: Option<&str>,
+    since: Option<&str>,
+    limit: usize,
+) -> Result<()> {
+    let jobs = list_jobs(pool, status, since).await?;
+    if jobs.is_empty() {
+        println!("No jobs recorded");
+        return Ok(());
+    }
+
+    println!(
+        "{:<36}  {:<17}  {:<9}  {:>8}  {:>9}  {:<25}",
+        "Job", "Type", "Status", "Attempts", "Duration", "Submitted"
+    );
+    for job in jobs.iter().take(limit) {
+        let duration = job
+            .duration_secs
+            .map(|secs| format!("{:.0}s", secs))
+            .unwrap_or_else(|| "-".to_string());
+        println!(
+            "{:<36}  {:<17}  {:<9}  {:>8}  {:>9}  {:<25}",
+            job.job_id, job.job_type, job.status, job.attempts, duration, job.submitted_at
+        );
+        for file in &job.output_files {
+            println!("    📄 {}", file);
+        }
+        if let Some(error) = &job.error {
+            let code = job
+                .error_code
+                .as_deref()
+                .map(|code| format!("[{}] ", code))
+                .unwrap_or_default();
+            println!(
+                "    ❌ {}{}",
+                code,
+                error.lines().next().unwrap_or_default()
+            );
+        }
+    }
+    if jobs.len() > limit {
+        println!(
+            "... {} more (raise --limit to see them)",
+            jobs.len() - limit
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::nats::{JobParameters, JobType};
+
+    fn request(job_id: &str, submitted_at: &str) -> JobRequest {
+        JobRequest {
+            job_id: job_id.to_string(),
+            job_type: JobType::FetchMarketCaps,
+            parameters: JobParameters::FetchMarketCaps {
+                date: "2025-01-01".to_string(),
+            },
+            submitted_at: DateTime::parse_from_rfc3339(submitted_at)
+                .unwrap()
+                .with_timezone(&Utc),
+        }
+    }
+
+    #[test]
+    fn test_parse_since() {
+        assert_eq!(
+            parse_since("2025-03-01").unwrap(),
+            "2025-03-01T00:00:00+00:00"
+        );
+        assert_eq!(
+            parse_since("2025-03-01T12:00:00+01:00").unwrap(),
+            "2025-03-01T11:00:00+00:00"
+        );
+        assert!(parse_since("March").is_err());
+    }
+
+    #[test]
+    fn test_validate_status() {
+        for status in STATUSES {
+            assert!(validate_status(status).is_ok());
+        }
+        assert!(validate_status("lost").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_record_and_list_jobs() {
+        let pool = db::create_db_pool("sqlite::memory:").await.unwrap();
+
+        let old = request("job-old", "2025-01-01T08:00:00+00:00");
+        record_attempt(&pool, &old, 1).await.unwrap();
+        record_completed(
+            &pool,
+            "job-old",
+            &["output/marketcaps_2025-01-01.csv".to_string()],
+            Duration::from_secs(42),
+        )
+        .await
+        .unwrap();
+
+        let new = request("job-new", "2025-02-01T08:00:00+00:00");
+        record_attempt(&pool, &new, 1).await.unwrap();
+        record_attempt(&pool, &new, 2).await.unwrap();
+        record_failed(
+            &pool,
+            "job-new",
+            "Rate limit reached after 3 retries",
+            Some(ErrorCode::RateLimited),
+            Duration::from_secs(90),
+        )
+        .await
+        .unwrap();
+
+        let all = list_jobs(&pool, None, None).await.unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].job_id, "job-new");
+        assert_eq!(all[0].attempts, 2);
+        assert_eq!(
+            all[0].error.as_deref(),
+            Some("Rate limit reached after 3 retries")
+        );
+        assert_eq!(all[0].error_code.as_deref(), Some("rate_limited"));
+        assert!(all[1].error_code.is_none());
+        assert_eq!(
+            all[1].output_files,
+            vec!["output/marketcaps_2025-01-01.csv"]
+        );
+        assert_eq!(all[1].duration_secs, Some(42.0));
+        assert_eq!(all[1].parameters["date"], "2025-01-01");
+
+        let failed = list_jobs(&pool, Some("failed"), None).await.unwrap();
+        assert_eq!(failed.len(), 1);
+        let recent = list_jobs(&pool, None, Some("2025-01-15")).await.unwrap();
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].job_id, "job-new");
+        assert!(list_jobs(&pool, Some("lost"), None).await.is_err());
+
+        // A requeued job starts over
+        record_attempt(&pool, &new, 1).await.unwrap();
+        let requeued = list_jobs(&pool, Some("running"), None).await.unwrap();
+        assert_eq!(requeued[0].attempts, 1);
+        assert!(requeued[0].error.is_none());
+        assert!(requeued[0].error_code.is_none());
+    }
+}
@@ -18,28 +18,32 @@ pub async fn submit_job(
 
     let job_request = JobRequest {
         job_id: job_id.clone(),
-        job_type: job_type.clone(),
+        job_type,
         parameters,
         submitted_at: Utc::now(),
     };
+    publish_job_request(nats_client, &job_request).await?;
 
-    let subject = match job_type {
-        JobType::FetchMarketCaps => "jobs.submit.fetch-market-caps",
-        JobType::GenerateComparison => "jobs.submit.comparison",
-    };
+    Ok(job_id)
+}
 
-    let payload = serde_json::to_vec(&job_request).context("Failed to serialize job request")?;
+/// Queue a job request for the worker and mark it as queued
+pub async fn publish_job_request(nats_client: &NatsClient, job_request: &JobRequest) -> Result<()> {
+    let subject = format!("jobs.submit.{}", job_request.job_type.as_str());
+    let payload = serde_json::to_vec(job_request).context("Failed to serialize job request")?;
 
     nats_client
         .inner()
-        .publish(subject.to_string(), payload.into())
+        .publish(subject, payload.into())
         .await
         .context("Failed to publish job to NATS")?;
 
     // Publish initial status
-    publish_job_status(nats_client, JobStatus::new_queued(job_id.clone())).await?;
-
-    Ok(job_id)
+    publish_job_status(
+        nats_client,
+        JobStatus::new_queued(job_request.job_id.clone()),
+    )
+    .await
 }
 
 /// Publish job status update
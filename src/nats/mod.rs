@@ -3,13 +3,21 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 
 pub mod client;
+pub mod history;
 pub mod jobs;
 pub mod models;
 pub mod streams;
 pub mod worker;
 
 pub use client::{NatsClient, create_nats_client};
-pub use jobs::{publish_job_progress, publish_job_result, publish_job_status, submit_job};
-pub use models::{JobParameters, JobProgress, JobRequest, JobResult, JobStatus, JobType};
-pub use streams::setup_streams;
+pub use jobs::{
+    publish_job_progress, publish_job_request, publish_job_result, publish_job_status, submit_job,
+};
+pub use models::{
+    FailedJob, JobParameters, JobProgress, JobRequest, JobResult, JobStatus, JobType,
+};
+pub use streams::{
+    JobEventKind, dead_letter_job, job_event_messages, list_failed_jobs, pending_job_count,
+    retry_failed_job, setup_streams, validate_job_id,
+};
 pub use worker::start_worker;
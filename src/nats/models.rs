@@ -5,6 +5,8 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::error::ErrorCode;
+
 /// Job request that gets published to NATS
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JobRequest {
@@ -21,6 +23,16 @@ pub enum JobType {
     GenerateComparison,
 }
 
+impl JobType {
+    /// Name used in the job's submit subject and in metrics
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobType::FetchMarketCaps => "fetch-market-caps",
+            JobType::GenerateComparison => "comparison",
+        }
+    }
+}
+
 /// Parameters for different job types
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
@@ -43,6 +55,9 @@ pub struct JobStatus {
     pub current_step: Option<u8>,
     pub current_step_message: Option<String>,
     pub error: Option<String>,
+    /// Kind of failure, when the error has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ErrorCode>,
     pub updated_at: DateTime<Utc>,
 }
 
@@ -73,9 +88,24 @@ pub struct JobResult {
     pub status: JobResultStatus,
     pub output_files: Vec<String>,
     pub error: Option<String>,
+    /// Kind of failure, when the error has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ErrorCode>,
     pub completed_at: DateTime<Utc>,
 }
 
+/// A job that failed every attempt, kept in the dead-letter stream
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailedJob {
+    pub request: JobRequest,
+    pub attempts: u32,
+    pub error: String,
+    /// Kind of failure, when the error has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ErrorCode>,
+    pub failed_at: DateTime<Utc>,
+}
+
 /// Result status (success or failure)
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum JobResultStatus {
@@ -91,6 +121,7 @@ impl JobStatus {
             current_step: None,
             current_step_message: None,
             error: None,
+            error_code: None,
             updated_at: Utc::now(),
         }
     }
@@ -102,6 +133,7 @@ impl JobStatus {
             current_step: Some(step),
             current_step_message: Some(message),
             error: None,
+            error_code: None,
             updated_at: Utc::now(),
         }
     }
@@ -113,17 +145,32 @@ impl JobStatus {
             current_step: None,
             current_step_message: None,
             error: None,
+            error_code: None,
             updated_at: Utc::now(),
         }
     }
 
-    pub fn new_failed(job_id: String, error: String) -> Self {
+    /// Back in the queue after a failed attempt, waiting for the next one
+    pub fn new_retrying(job_id: String, message: String, error: String) -> Self {
+        Self {
+            job_id,
+            status: JobStatusType::Queued,
+            current_step: None,
+            current_step_message: Some(message),
+            error: Some(error),
+            error_code: None,
+            updated_at: Utc::now(),
+        }
+    }
+
+    pub fn new_failed(job_id: String, error: String, error_code: Option<ErrorCode>) -> Self {
         Self {
             job_id,
             status: JobStatusType::Failed,
             current_step: None,
             current_step_message: None,
             error: Some(error),
+            error_code,
             updated_at: Utc::now(),
         }
     }
@@ -141,6 +188,23 @@ impl JobProgress {
     }
 }
 
+impl FailedJob {
+    pub fn new(
+        request: JobRequest,
+        attempts: u32,
+        error: String,
+        error_code: Option<ErrorCode>,
+    ) -> Self {
+        Self {
+            request,
+            attempts,
+            error,
+            error_code,
+            failed_at: Utc::now(),
+        }
+    }
+}
+
 impl JobResult {
     pub fn success(job_id: String, output_files: Vec<String>) -> Self {
         Self {
@@ -148,16 +212,18 @@ impl JobResult {
             status: JobResultStatus::Success,
             output_files,
             error: None,
+            error_code: None,
             completed_at: Utc::now(),
         }
     }
 
-    pub fn failed(job_id: String, error: String) -> Self {
+    pub fn failed(job_id: String, error: String, error_code: Option<ErrorCode>) -> Self {
         Self {
             job_id,
             status: JobResultStatus::Failed,
             output_files: Vec::new(),
             error: Some(error),
+            error_code,
             completed_at: Utc::now(),
         }
     }
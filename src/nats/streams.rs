@@ -2,14 +2,17 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-only
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use async_nats::jetstream::consumer::{AckPolicy, DeliverPolicy, pull};
 use async_nats::jetstream::stream::{Config, DiscardPolicy, RetentionPolicy};
+use futures::{Stream, StreamExt};
 use std::time::Duration;
 
-use super::NatsClient;
+use super::{FailedJob, NatsClient, publish_job_request};
 
 const JOBS_SUBMIT_STREAM: &str = "JOBS_SUBMIT";
 const JOBS_TRACKING_STREAM: &str = "JOBS_TRACKING";
+const JOBS_DEAD_LETTER_STREAM: &str = "JOBS_DEAD_LETTER";
 
 /// Set up JetStream streams for job submission and tracking
 pub async fn setup_streams(nats_client: &NatsClient) -> Result<()> {
@@ -63,14 +66,260 @@ pub async fn setup_streams(nats_client: &NatsClient) -> Result<()> {
         }
     }
 
+    // Create JOBS_DEAD_LETTER stream (Limits retention, one message per job)
+    let dead_letter_config = Config {
+        name: JOBS_DEAD_LETTER_STREAM.to_string(),
+        description: Some("Jobs that failed every attempt".to_string()),
+        subjects: vec!["jobs.dead.*".to_string()],
+        retention: RetentionPolicy::Limits,
+        max_age: Duration::from_secs(30 * 24 * 60 * 60), // 30 days
+        max_messages_per_subject: 1,
+        discard: DiscardPolicy::Old,
+        ..Default::default()
+    };
+
+    match jetstream.get_or_create_stream(dead_letter_config).await {
+        Ok(_) => println!("✓ JetStream stream '{}' ready", JOBS_DEAD_LETTER_STREAM),
+        Err(e) => {
+            eprintln!(
+                "Warning: Failed to create stream {}: {}",
+                JOBS_DEAD_LETTER_STREAM, e
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn dead_letter_subject(job_id: &str) -> String {
+    format!("jobs.dead.{}", job_id)
+}
+
+/// Store a permanently failed job in the dead-letter stream
+pub async fn dead_letter_job(nats_client: &NatsClient, failed_job: &FailedJob) -> Result<()> {
+    let jetstream = async_nats::jetstream::new(nats_client.inner().clone());
+    let payload = serde_json::to_vec(failed_job).context("Failed to serialize failed job")?;
+    jetstream
+        .publish(
+            dead_letter_subject(&failed_job.request.job_id),
+            payload.into(),
+        )
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to publish failed job: {}", e))?
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed job was not stored: {}", e))?;
     Ok(())
 }
 
+/// All jobs in the dead-letter stream, oldest first
+pub async fn list_failed_jobs(nats_client: &NatsClient) -> Result<Vec<FailedJob>> {
+    let jetstream = async_nats::jetstream::new(nats_client.inner().clone());
+    let stream = jetstream
+        .get_stream(JOBS_DEAD_LETTER_STREAM)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to get stream {}: {}", JOBS_DEAD_LETTER_STREAM, e))?;
+    let count = stream.cached_info().state.messages as usize;
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+
+    let consumer = stream
+        .create_consumer(pull::Config {
+            deliver_policy: DeliverPolicy::All,
+            ack_policy: AckPolicy::None,
+            inactive_threshold: Duration::from_secs(60),
+            ..Default::default()
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to create dead-letter consumer: {}", e))?;
+    let mut messages = consumer
+        .fetch()
+        .max_messages(count)
+        .messages()
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to read failed jobs: {}", e))?;
+
+    let mut failed_jobs = Vec::with_capacity(count);
+    while let Some(message) = messages.next().await {
+        let message = message.map_err(|e| anyhow::anyhow!("Failed to read failed job: {}", e))?;
+        match serde_json::from_slice::<FailedJob>(&message.payload) {
+            Ok(failed_job) => failed_jobs.push(failed_job),
+            Err(e) => eprintln!(
+                "⚠️  Skipping invalid failed job on {}: {}",
+                message.subject, e
+            ),
+        }
+    }
+    Ok(failed_jobs)
+}
+
+/// Requeue a dead-lettered job under its original id and remove it from the
+/// dead-letter stream
+pub async fn retry_failed_job(nats_client: &NatsClient, job_id: &str) -> Result<FailedJob> {
+    let jetstream = async_nats::jetstream::new(nats_client.inner().clone());
+    let stream = jetstream
+        .get_stream(JOBS_DEAD_LETTER_STREAM)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to get stream {}: {}", JOBS_DEAD_LETTER_STREAM, e))?;
+    let raw = stream
+        .get_last_raw_message_by_subject(&dead_letter_subject(job_id))
+        .await
+        .map_err(|e| anyhow::anyhow!("No failed job with id {}: {}", job_id, e))?;
+    let sequence = raw.sequence;
+    let message = async_nats::Message::try_from(raw)
+        .map_err(|e| anyhow::anyhow!("Invalid failed job {}: {}", job_id, e))?;
+    let failed_job: FailedJob =
+        serde_json::from_slice(&message.payload).context("Failed to parse failed job")?;
+
+    publish_job_request(nats_client, &failed_job.request).await?;
+    stream
+        .delete_message(sequence)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to remove job {} from dead letters: {}", job_id, e))?;
+    Ok(failed_job)
+}
+
+/// Number of job submissions waiting in the JOBS_SUBMIT stream
+pub async fn pending_job_count(nats_client: &NatsClient) -> Result<u64> {
+    let jetstream = async_nats::jetstream::new(nats_client.inner().clone());
+    let stream = jetstream
+        .get_stream(JOBS_SUBMIT_STREAM)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to get stream {}: {}", JOBS_SUBMIT_STREAM, e))?;
+    Ok(stream.cached_info().state.messages)
+}
+
+/// Kind of message published on a job's tracking subjects
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobEventKind {
+    Status,
+    Progress,
+    Result,
+}
+
+impl JobEventKind {
+    /// Kind of a `jobs.{job_id}.{kind}` subject
+    pub fn from_subject(subject: &str) -> Option<Self> {
+        match subject.rsplit('.').next()? {
+            "status" => Some(Self::Status),
+            "progress" => Some(Self::Progress),
+            "result" => Some(Self::Result),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Status => "status",
+            Self::Progress => "progress",
+            Self::Result => "result",
+        }
+    }
+}
+
+/// A job tracking message with its JOBS_TRACKING stream sequence
+#[derive(Debug, Clone)]
+pub struct JobEventMessage {
+    pub sequence: u64,
+    pub kind: JobEventKind,
+    pub payload: Vec<u8>,
+}
+
+/// Check that a job id can be put into a subject: `.`, `*`, `>` and
+/// whitespace would change which subjects a filter matches, so only letters,
+/// digits, `-` and `_` are accepted (generated ids are UUIDs)
+pub fn validate_job_id(job_id: &str) -> Result<()> {
+    if job_id.is_empty()
+        || !job_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        anyhow::bail!("Invalid job id '{}'", job_id);
+    }
+    Ok(())
+}
+
+/// Replay and follow the tracking messages of one job. Resumes after
+/// `after_sequence` when given, otherwise starts at the oldest retained message.
+pub async fn job_event_messages(
+    nats_client: &NatsClient,
+    job_id: &str,
+    after_sequence: Option<u64>,
+) -> Result<impl Stream<Item = Result<JobEventMessage>> + use<>> {
+    validate_job_id(job_id)?;
+    let jetstream = async_nats::jetstream::new(nats_client.inner().clone());
+    let stream = jetstream
+        .get_stream(JOBS_TRACKING_STREAM)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to get stream {}: {}", JOBS_TRACKING_STREAM, e))?;
+
+    let deliver_policy = match after_sequence {
+        Some(sequence) => DeliverPolicy::ByStartSequence {
+            start_sequence: sequence + 1,
+        },
+        None => DeliverPolicy::All,
+    };
+    // Ephemeral consumer, removed by the server once the client goes away
+    let consumer = stream
+        .create_consumer(pull::Config {
+            filter_subject: format!("jobs.{}.*", job_id),
+            deliver_policy,
+            ack_policy: AckPolicy::None,
+            inactive_threshold: Duration::from_secs(60),
+            ..Default::default()
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to create job event consumer: {}", e))?;
+    let messages = consumer
+        .messages()
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to read job events: {}", e))?;
+
+    Ok(messages.filter_map(|message| async move {
+        let message = match message {
+            Ok(message) => message,
+            Err(e) => return Some(Err(anyhow::anyhow!("Job event stream failed: {}", e))),
+        };
+        let sequence = match message.info() {
+            Ok(info) => info.stream_sequence,
+            Err(e) => return Some(Err(anyhow::anyhow!("Invalid job event: {}", e))),
+        };
+        let kind = JobEventKind::from_subject(&message.subject)?;
+        Some(Ok(JobEventMessage {
+            sequence,
+            kind,
+            payload: message.payload.to_vec(),
+        }))
+    }))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::nats::create_nats_client;
 
+    #[test]
+    fn test_job_event_kind_from_subject() {
+        assert_eq!(
+            JobEventKind::from_subject("jobs.abc-123.progress"),
+            Some(JobEventKind::Progress)
+        );
+        assert_eq!(
+            JobEventKind::from_subject("jobs.abc-123.result"),
+            Some(JobEventKind::Result)
+        );
+        assert_eq!(JobEventKind::from_subject("jobs.submit.comparison"), None);
+    }
+
+    #[test]
+    fn test_validate_job_id() {
+        assert!(validate_job_id("6f1c2a8e-4b7d-4e0a-9c3f-2d5b8a7e1f00").is_ok());
+        assert!(validate_job_id("job_42").is_ok());
+        for job_id in ["", "*", ">", "abc.progress", "a b", "abc.>"] {
+            assert!(validate_job_id(job_id).is_err(), "{:?}", job_id);
+        }
+    }
+
     #[tokio::test]
     #[ignore] // Requires NATS server running
     async fn test_setup_streams() {
@@ -4,16 +4,41 @@
 
 use anyhow::{Context, Result};
 use futures::StreamExt;
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant};
 use tokio::process::Command;
+use tokio::task::{self, JoinSet};
 
+use crate::config::{self, JobsConfig};
+use crate::error::{self, ErrorCode};
+use crate::metrics;
+use crate::shutdown::Shutdown;
+
+use super::history;
 use super::{
-    JobParameters, JobProgress, JobRequest, JobResult, JobStatus, JobType, NatsClient,
-    publish_job_progress, publish_job_result, publish_job_status,
+    FailedJob, JobParameters, JobProgress, JobRequest, JobResult, JobStatus, JobType, NatsClient,
+    dead_letter_job, publish_job_progress, publish_job_result, publish_job_status,
 };
 
-/// Start the background worker that processes jobs from NATS queue
-pub async fn start_worker(nats_client: NatsClient) -> Result<()> {
+/// Start the background worker that processes jobs from NATS queue.
+/// Failed jobs are retried with exponential backoff; after the last attempt
+/// they are moved to the dead-letter stream. Every job is recorded in the
+/// `jobs` table.
+///
+/// When `shutdown` is requested the worker stops taking jobs and waits up to
+/// `[jobs] shutdown_timeout_secs` for running ones. Jobs still running then are
+/// dead-lettered, so `jobs retry` can requeue them, and NATS is flushed.
+pub async fn start_worker(
+    nats_client: NatsClient,
+    pool: SqlitePool,
+    shutdown: Shutdown,
+) -> Result<()> {
     println!("🚀 Starting NATS worker...");
+    let jobs_config = config::load_jobs_config();
 
     // Subscribe to job submissions
     let mut sub = nats_client
@@ -24,8 +49,18 @@ pub async fn start_worker(nats_client: NatsClient) -> Result<()> {
 
     println!("✓ Worker subscribed to jobs.submit.>");
 
-    // Process messages in a loop
-    while let Some(msg) = sub.next().await {
+    // Process messages until the subscription ends or shutdown is requested
+    let mut running = RunningJobs::default();
+    loop {
+        let msg = tokio::select! {
+            msg = sub.next() => match msg {
+                Some(msg) => msg,
+                None => break,
+            },
+            _ = shutdown.wait() => break,
+            Some(()) = running.next_finished() => continue,
+        };
+
         // Deserialize job request
         let job_request: JobRequest = match serde_json::from_slice(&msg.payload) {
             Ok(req) => req,
@@ -38,37 +73,266 @@ pub async fn start_worker(nats_client: NatsClient) -> Result<()> {
         println!(
             "📋 Received job: {} ({})",
             job_request.job_id,
-            match &job_request.job_type {
-                JobType::FetchMarketCaps => "fetch-market-caps",
-                JobType::GenerateComparison => "comparison",
-            }
+            job_request.job_type.as_str()
         );
-
-        // Clone for async task
-        let client = nats_client.clone();
-        let job_id = job_request.job_id.clone();
-
-        // Spawn task to process job
-        tokio::spawn(async move {
-            if let Err(e) = process_job(&client, job_request).await {
-                eprintln!("❌ Job {} failed: {}", job_id, e);
-
-                // Publish failure status and result
-                let _ = publish_job_status(
-                    &client,
-                    JobStatus::new_failed(job_id.clone(), e.to_string()),
-                )
-                .await;
-                let _ = publish_job_result(&client, JobResult::failed(job_id, e.to_string())).await;
-            }
+        let (client, pool, jobs_config) = (nats_client.clone(), pool.clone(), jobs_config.clone());
+        running.spawn(job_request.clone(), |attempt| {
+            run_job(client, pool, jobs_config, job_request, attempt)
         });
     }
 
+    // Stop taking jobs, then give running ones time to finish
+    if let Err(e) = sub.unsubscribe().await {
+        eprintln!("⚠️  Failed to unsubscribe from job queue: {}", e);
+    }
+    let timeout = Duration::from_secs(jobs_config.shutdown_timeout_secs);
+    if !running.is_empty() {
+        println!(
+            "⏳ Waiting up to {}s for {} running job(s)...",
+            timeout.as_secs(),
+            running.len()
+        );
+    }
+    for job in running.drain(timeout).await {
+        let job_id = job.request.job_id.clone();
+        let error = "Interrupted by server shutdown".to_string();
+        eprintln!("❌ Job {} did not finish before shutdown", job_id);
+        if let Err(e) =
+            history::record_failed(&pool, &job_id, &error, None, job.received.elapsed()).await
+        {
+            eprintln!("⚠️  Failed to record job {}: {}", job_id, e);
+        }
+        let attempt = job.attempt();
+        let failed_job = FailedJob::new(job.request, attempt, error.clone(), None);
+        if let Err(e) = dead_letter_job(&nats_client, &failed_job).await {
+            eprintln!("❌ Failed to dead-letter job {}: {}", job_id, e);
+        }
+        let _ = publish_job_status(
+            &nats_client,
+            JobStatus::new_failed(job_id.clone(), error.clone(), None),
+        )
+        .await;
+        let _ = publish_job_result(&nats_client, JobResult::failed(job_id, error, None)).await;
+    }
+
+    // Make sure statuses, results and dead-letter entries reach the server
+    nats_client
+        .inner()
+        .flush()
+        .await
+        .context("Failed to flush NATS")?;
+    println!("✓ Worker stopped");
+
     Ok(())
 }
 
-/// Process a single job
-async fn process_job(nats_client: &NatsClient, job_request: JobRequest) -> Result<()> {
+/// A job the worker has started
+struct RunningJob {
+    request: JobRequest,
+    received: Instant,
+    /// Attempt in progress, updated by the job
+    current_attempt: Arc<AtomicU32>,
+}
+
+impl RunningJob {
+    fn attempt(&self) -> u32 {
+        self.current_attempt.load(Ordering::Relaxed)
+    }
+}
+
+/// Jobs the worker has started and not yet seen finish
+#[derive(Default)]
+struct RunningJobs {
+    tasks: JoinSet<()>,
+    jobs: HashMap<task::Id, RunningJob>,
+}
+
+impl RunningJobs {
+    /// Start `job`, handing it the counter of the attempt it is on
+    fn spawn<F>(&mut self, request: JobRequest, job: impl FnOnce(Arc<AtomicU32>) -> F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let current_attempt = Arc::new(AtomicU32::new(1));
+        let handle = self.tasks.spawn(job(Arc::clone(&current_attempt)));
+        self.jobs.insert(
+            handle.id(),
+            RunningJob {
+                request,
+                received: Instant::now(),
+                current_attempt,
+            },
+        );
+    }
+
+    fn len(&self) -> usize {
+        self.jobs.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    /// Wait for the next job to finish; `None` when none are running
+    async fn next_finished(&mut self) -> Option<()> {
+        let id = match self.tasks.join_next_with_id().await? {
+            Ok((id, ())) => id,
+            Err(e) => {
+                eprintln!("❌ Job task failed: {}", e);
+                e.id()
+            }
+        };
+        self.jobs.remove(&id);
+        Some(())
+    }
+
+    /// Wait up to `timeout` for all jobs; abort the rest (killing their child
+    /// processes) and return them in the order they were received
+    async fn drain(mut self, timeout: Duration) -> Vec<RunningJob> {
+        let _ = tokio::time::timeout(timeout, async {
+            while self.next_finished().await.is_some() {}
+        })
+        .await;
+        self.tasks.abort_all();
+        // Aborted tasks are dropped once joined
+        while self.tasks.join_next().await.is_some() {}
+        let mut unfinished: Vec<_> = self.jobs.into_values().collect();
+        unfinished.sort_by_key(|job| job.received);
+        unfinished
+    }
+}
+
+/// Counts a job in `jobs_in_progress` until dropped, so a job aborted at
+/// shutdown or a panicking one is decremented exactly once
+struct InProgress;
+
+impl InProgress {
+    fn start() -> Self {
+        metrics::metrics().jobs_in_progress.inc();
+        Self
+    }
+}
+
+impl Drop for InProgress {
+    fn drop(&mut self) {
+        metrics::metrics().jobs_in_progress.dec();
+    }
+}
+
+/// Run one job with retries, then record and report the outcome
+async fn run_job(
+    client: NatsClient,
+    pool: SqlitePool,
+    jobs_config: JobsConfig,
+    job_request: JobRequest,
+    current_attempt: Arc<AtomicU32>,
+) {
+    let job_id = job_request.job_id.clone();
+    let job_type = job_request.job_type.as_str();
+    let metrics = metrics::metrics();
+    let in_progress = InProgress::start();
+    let received = Instant::now();
+
+    let mut attempt = 1;
+    let result = loop {
+        current_attempt.store(attempt, Ordering::Relaxed);
+        if let Err(e) = history::record_attempt(&pool, &job_request, attempt).await {
+            eprintln!("⚠️  Failed to record job {}: {}", job_id, e);
+        }
+        let started = Instant::now();
+        let result = process_job(&client, job_request.clone()).await;
+        let outcome = if result.is_ok() { "success" } else { "failure" };
+        metrics
+            .job_duration
+            .with_label_values(&[job_type, outcome])
+            .observe(started.elapsed().as_secs_f64());
+
+        let e = match result {
+            Ok(output_files) => break Ok(output_files),
+            Err(e) => e,
+        };
+        // Missing data or bad config won't fix itself; only retry unknown
+        // failures and rate limits
+        let retryable = error::code_of(&e).is_none_or(ErrorCode::is_retryable);
+        if attempt >= jobs_config.max_attempts || !retryable {
+            break Err(e);
+        }
+
+        let delay = jobs_config.backoff(attempt);
+        eprintln!(
+            "⚠️  Job {} failed (attempt {}/{}), retrying in {}s: {}",
+            job_id,
+            attempt,
+            jobs_config.max_attempts,
+            delay.as_secs(),
+            e
+        );
+        let _ = publish_job_status(
+            &client,
+            JobStatus::new_retrying(
+                job_id.clone(),
+                format!(
+                    "Attempt {} of {} failed, retrying in {}s",
+                    attempt,
+                    jobs_config.max_attempts,
+                    delay.as_secs()
+                ),
+                e.to_string(),
+            ),
+        )
+        .await;
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    };
+    drop(in_progress);
+
+    let recorded = match &result {
+        Ok(output_files) => {
+            history::record_completed(&pool, &job_id, output_files, received.elapsed()).await
+        }
+        Err(e) => {
+            history::record_failed(
+                &pool,
+                &job_id,
+                &e.to_string(),
+                error::code_of(e),
+                received.elapsed(),
+            )
+            .await
+        }
+    };
+    if let Err(e) = recorded {
+        eprintln!("⚠️  Failed to record job {}: {}", job_id, e);
+    }
+
+    if let Err(e) = result {
+        eprintln!(
+            "❌ Job {} failed after {} attempt(s): {}",
+            job_id, attempt, e
+        );
+
+        let error_code = error::code_of(&e);
+        let failed_job = FailedJob::new(job_request, attempt, e.to_string(), error_code);
+        if let Err(dlq_error) = dead_letter_job(&client, &failed_job).await {
+            eprintln!("❌ Failed to dead-letter job {}: {}", job_id, dlq_error);
+        }
+
+        // Publish failure status and result
+        let _ = publish_job_status(
+            &client,
+            JobStatus::new_failed(job_id.clone(), e.to_string(), error_code),
+        )
+        .await;
+        let _ = publish_job_result(
+            &client,
+            JobResult::failed(job_id, e.to_string(), error_code),
+        )
+        .await;
+    }
+}
+
+/// Process a single job and return its output files
+async fn process_job(nats_client: &NatsClient, job_request: JobRequest) -> Result<Vec<String>> {
     let job_id = job_request.job_id.clone();
 
     match job_request.job_type {
@@ -86,7 +350,7 @@ async fn execute_fetch_market_caps(
     nats_client: &NatsClient,
     job_id: String,
     parameters: JobParameters,
-) -> Result<()> {
+) -> Result<Vec<String>> {
     let date = match parameters {
         JobParameters::FetchMarketCaps { date } => date,
         _ => anyhow::bail!("Invalid parameters for FetchMarketCaps job"),
@@ -118,24 +382,28 @@ async fn execute_fetch_market_caps(
     let output = Command::new("cargo")
         .args(&["run", "--", "fetch-specific-date-market-caps", &date])
         .envs(std::env::vars())
+        .kill_on_drop(true)
         .output()
         .await
         .context("Failed to execute cargo command")?;
 
     if !output.status.success() {
-        let error_msg = String::from_utf8_lossy(&output.stderr).to_string();
-        anyhow::bail!("Command failed: {}", error_msg);
+        return Err(command_failed("Command failed", &output));
     }
 
     // Parse output to find generated files
     let stdout = String::from_utf8_lossy(&output.stdout);
-    let output_files = extract_output_files(&stdout);
+    let output_files = extract_output_files(&stdout, &config::load_output_config().directory);
 
     // Publish success
     publish_job_status(nats_client, JobStatus::new_completed(job_id.clone())).await?;
-    publish_job_result(nats_client, JobResult::success(job_id, output_files)).await?;
+    publish_job_result(
+        nats_client,
+        JobResult::success(job_id, output_files.clone()),
+    )
+    .await?;
 
-    Ok(())
+    Ok(output_files)
 }
 
 /// Execute generate comparison job
@@ -143,7 +411,7 @@ async fn execute_generate_comparison(
     nats_client: &NatsClient,
     job_id: String,
     parameters: JobParameters,
-) -> Result<()> {
+) -> Result<Vec<String>> {
     let (from_date, to_date, generate_charts) = match parameters {
         JobParameters::GenerateComparison {
             from_date,
@@ -178,13 +446,13 @@ async fn execute_generate_comparison(
     let output = Command::new("cargo")
         .args(&["run", "--", "fetch-specific-date-market-caps", &from_date])
         .envs(std::env::vars())
+        .kill_on_drop(true)
         .output()
         .await
         .context("Failed to fetch from date market caps")?;
 
     if !output.status.success() {
-        let error_msg = String::from_utf8_lossy(&output.stderr).to_string();
-        anyhow::bail!("Failed to fetch from date: {}", error_msg);
+        return Err(command_failed("Failed to fetch from date", &output));
     }
 
     // Step 2: Fetch market caps for to_date
@@ -212,13 +480,13 @@ async fn execute_generate_comparison(
     let output = Command::new("cargo")
         .args(&["run", "--", "fetch-specific-date-market-caps", &to_date])
         .envs(std::env::vars())
+        .kill_on_drop(true)
         .output()
         .await
         .context("Failed to fetch to date market caps")?;
 
     if !output.status.success() {
-        let error_msg = String::from_utf8_lossy(&output.stderr).to_string();
-        anyhow::bail!("Failed to fetch to date: {}", error_msg);
+        return Err(command_failed("Failed to fetch to date", &output));
     }
 
     // Step 3: Generate comparison
@@ -250,16 +518,19 @@ async fn execute_generate_comparison(
             &to_date,
         ])
         .envs(std::env::vars())
+        .kill_on_drop(true)
         .output()
         .await
         .context("Failed to generate comparison")?;
 
     if !output.status.success() {
-        let error_msg = String::from_utf8_lossy(&output.stderr).to_string();
-        anyhow::bail!("Failed to generate comparison: {}", error_msg);
+        return Err(command_failed("Failed to generate comparison", &output));
     }
 
-    let mut output_files = extract_output_files(&String::from_utf8_lossy(&output.stdout));
+    let mut output_files = extract_output_files(
+        &String::from_utf8_lossy(&output.stdout),
+        &config::load_output_config().directory,
+    );
 
     // Step 4: Generate charts (if requested)
     if generate_charts {
@@ -291,35 +562,53 @@ async fn execute_generate_comparison(
                 &to_date,
             ])
             .envs(std::env::vars())
+            .kill_on_drop(true)
             .output()
             .await
             .context("Failed to generate charts")?;
 
         if !output.status.success() {
-            let error_msg = String::from_utf8_lossy(&output.stderr).to_string();
-            anyhow::bail!("Failed to generate charts: {}", error_msg);
+            return Err(command_failed("Failed to generate charts", &output));
         }
 
-        let chart_files = extract_output_files(&String::from_utf8_lossy(&output.stdout));
+        let chart_files = extract_output_files(
+            &String::from_utf8_lossy(&output.stdout),
+            &config::load_output_config().directory,
+        );
         output_files.extend(chart_files);
     }
 
     // Publish success
     publish_job_status(nats_client, JobStatus::new_completed(job_id.clone())).await?;
-    publish_job_result(nats_client, JobResult::success(job_id, output_files)).await?;
+    publish_job_result(
+        nats_client,
+        JobResult::success(job_id, output_files.clone()),
+    )
+    .await?;
 
-    Ok(())
+    Ok(output_files)
+}
+
+/// Error for a failed CLI subprocess, with the `ErrorCode` its exit status
+/// stands for (see `main`) so the job is recorded and retried accordingly
+fn command_failed(what: &str, output: &std::process::Output) -> anyhow::Error {
+    let message = format!("{}: {}", what, String::from_utf8_lossy(&output.stderr));
+    match output.status.code().and_then(ErrorCode::from_exit_code) {
+        Some(code) => anyhow::Error::new(code).context(message),
+        None => anyhow::anyhow!(message),
+    }
 }
 
 /// Extract output file paths from command stdout
-fn extract_output_files(stdout: &str) -> Vec<String> {
+fn extract_output_files(stdout: &str, output_dir: &str) -> Vec<String> {
     let mut files = Vec::new();
+    let prefix = format!("{}/", output_dir.trim_end_matches('/'));
 
     // Look for patterns like "output/..." or "Generated: ..."
     for line in stdout.lines() {
-        if line.contains("output/") {
+        if line.contains(&prefix) {
             // Extract file path
-            if let Some(start) = line.find("output/") {
+            if let Some(start) = line.find(&prefix) {
                 let rest = &line[start..];
                 if let Some(end) = rest.find(|c: char| c.is_whitespace() || c == ',' || c == ')') {
                     files.push(rest[..end].to_string());
@@ -337,13 +626,105 @@ fn extract_output_files(stdout: &str) -> Vec<String> {
 mod tests {
     use super::*;
 
+    fn request(job_id: &str) -> JobRequest {
+        JobRequest {
+            job_id: job_id.to_string(),
+            job_type: JobType::FetchMarketCaps,
+            parameters: JobParameters::FetchMarketCaps {
+                date: "2025-01-31".to_string(),
+            },
+            submitted_at: chrono::Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_drain_waits_for_jobs_and_returns_unfinished() {
+        let mut running = RunningJobs::default();
+        let (done_tx, done_rx) = tokio::sync::oneshot::channel();
+        running.spawn(request("quick"), |_| async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            let _ = done_tx.send(());
+        });
+        // A job on its third attempt when the worker shuts down
+        running.spawn(request("stuck"), |attempt| async move {
+            attempt.store(3, Ordering::Relaxed);
+            std::future::pending().await
+        });
+        assert_eq!(running.len(), 2);
+
+        let unfinished = running.drain(Duration::from_millis(200)).await;
+        let ids: Vec<&str> = unfinished
+            .iter()
+            .map(|job| job.request.job_id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["stuck"]);
+        assert_eq!(unfinished[0].attempt(), 3);
+        assert!(done_rx.await.is_ok(), "quick job should have finished");
+
+        let empty = RunningJobs::default();
+        assert!(empty.drain(Duration::from_secs(5)).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_aborted_job_leaves_in_progress_once() {
+        let gauge = &metrics::metrics().jobs_in_progress;
+        let before = gauge.get();
+        let mut running = RunningJobs::default();
+        let (started_tx, started_rx) = tokio::sync::oneshot::channel();
+        running.spawn(request("stuck"), |_| async move {
+            let _in_progress = InProgress::start();
+            let _ = started_tx.send(());
+            std::future::pending::<()>().await;
+        });
+        started_rx.await.unwrap();
+        assert_eq!(gauge.get(), before + 1);
+
+        let unfinished = running.drain(Duration::from_millis(20)).await;
+        assert_eq!(unfinished.len(), 1);
+        assert_eq!(gauge.get(), before);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_failed_keeps_error_code() {
+        use std::os::unix::process::ExitStatusExt;
+        let output = |code: i32| std::process::Output {
+            status: std::process::ExitStatus::from_raw(code << 8),
+            stdout: Vec::new(),
+            stderr: b"Error: No CSV file found for date 2025-01-01".to_vec(),
+        };
+
+        let err = command_failed("Failed to generate comparison", &output(13));
+        assert_eq!(error::code_of(&err), Some(ErrorCode::CsvNotFound));
+        assert_eq!(
+            err.to_string(),
+            "Failed to generate comparison: Error: No CSV file found for date 2025-01-01"
+        );
+        assert_eq!(
+            error::code_of(&command_failed("Command failed", &output(1))),
+            None
+        );
+    }
+
     #[test]
     fn test_extract_output_files() {
         let stdout = "Generated comparison at output/comparison_2025-01-01_to_2025-02-01.csv\n\
                       Summary written to output/comparison_2025-01-01_to_2025-02-01_summary.md";
 
-        let files = extract_output_files(stdout);
+        let files = extract_output_files(stdout, "output");
         assert_eq!(files.len(), 2);
         assert!(files[0].starts_with("output/comparison"));
     }
+
+    #[test]
+    fn test_extract_output_files_custom_directory() {
+        let stdout = "✅ Comparison data exported to /mnt/lake/comparison_2025-01-01_to_2025-02-01.csv\n\
+                      Reading data from: output/comparison.csv";
+
+        let files = extract_output_files(stdout, "/mnt/lake/");
+        assert_eq!(
+            files,
+            vec!["/mnt/lake/comparison_2025-01-01_to_2025-02-01.csv".to_string()]
+        );
+    }
 }
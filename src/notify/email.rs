@@ -0,0 +1,593 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Email delivery of comparison reports via the Brevo API or SMTP
+//!
+//! Configuration is read from the environment:
+//! - `BREVO_API_KEY` selects the Brevo transport
+//! - `SMTP_HOST`, `SMTP_PORT`, `SMTP_USERNAME`, `SMTP_PASSWORD` select SMTP
+//! - `EMAIL_SENDER` / `EMAIL_SENDER_NAME` (falling back to `BREVO_SENDER_EMAIL` /
+//!   `BREVO_SENDER_NAME`) set the sender
+//! - `NOTIFICATION_RECIPIENTS` is a comma-separated list of recipients
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use lettre::message::header::ContentType;
+use lettre::message::{Attachment as MailAttachment, Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use serde_json::{Value, json};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::{self, OutputConfig};
+
+const BREVO_API_URL: &str = "https://api.brevo.com/v3/smtp/email";
+
+/// A file attached to the email
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub filename: String,
+    pub content_type: String,
+    pub content: Vec<u8>,
+}
+
+impl Attachment {
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let filename = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .with_context(|| format!("Invalid attachment name: {}", path.display()))?
+            .to_string();
+        let content = fs::read(path)
+            .with_context(|| format!("Failed to read attachment: {}", path.display()))?;
+
+        Ok(Self {
+            content_type: content_type_for(&filename).to_string(),
+            filename,
+            content,
+        })
+    }
+}
+
+/// A rendered email ready to send
+#[derive(Debug, Clone)]
+pub struct EmailMessage {
+    pub subject: String,
+    pub text_body: String,
+    pub html_body: String,
+    pub attachments: Vec<Attachment>,
+}
+
+/// How the email leaves the machine
+#[derive(Debug, Clone, PartialEq)]
+pub enum EmailTransport {
+    Brevo {
+        api_key: String,
+    },
+    Smtp {
+        host: String,
+        port: u16,
+        username: Option<String>,
+        password: Option<String>,
+    },
+}
+
+/// Sender, recipients and transport for a delivery
+#[derive(Debug, Clone)]
+pub struct EmailSettings {
+    pub transport: EmailTransport,
+    pub sender_email: String,
+    pub sender_name: Option<String>,
+    pub recipients: Vec<String>,
+}
+
+impl EmailSettings {
+    /// Build settings from environment variables. `via` forces a transport
+    /// ("brevo" or "smtp"); otherwise Brevo is preferred when configured.
+    pub fn from_env(via: Option<&str>, recipients: Option<Vec<String>>) -> Result<Self> {
+        let brevo_key = env::var("BREVO_API_KEY").ok();
+        let smtp_host = env::var("SMTP_HOST").ok();
+
+        let transport = match (via.map(|v| v.to_lowercase()), brevo_key, smtp_host) {
+            (Some(v), Some(api_key), _) if v == "brevo" => EmailTransport::Brevo { api_key },
+            (Some(v), _, Some(host)) if v == "smtp" => smtp_transport(host)?,
+            (Some(v), _, _) if v == "brevo" => anyhow::bail!("BREVO_API_KEY must be set"),
+            (Some(v), _, _) if v == "smtp" => anyhow::bail!("SMTP_HOST must be set"),
+            (Some(v), _, _) => anyhow::bail!("Unknown email transport '{}'. Use: brevo, smtp", v),
+            (None, Some(api_key), _) => EmailTransport::Brevo { api_key },
+            (None, None, Some(host)) => smtp_transport(host)?,
+            (None, None, None) => {
+                anyhow::bail!("No email transport configured. Set BREVO_API_KEY or SMTP_HOST")
+            }
+        };
+
+        let sender_email = env::var("EMAIL_SENDER")
+            .or_else(|_| env::var("BREVO_SENDER_EMAIL"))
+            .context("EMAIL_SENDER or BREVO_SENDER_EMAIL must be set")?;
+        let sender_name = env::var("EMAIL_SENDER_NAME")
+            .or_else(|_| env::var("BREVO_SENDER_NAME"))
+            .ok();
+
+        let recipients = match recipients {
+            Some(r) => r,
+            None => parse_recipients(
+                &env::var("NOTIFICATION_RECIPIENTS")
+                    .context("NOTIFICATION_RECIPIENTS must be set")?,
+            ),
+        };
+        if recipients.is_empty() {
+            anyhow::bail!("No email recipients configured");
+        }
+
+        Ok(Self {
+            transport,
+            sender_email,
+            sender_name,
+            recipients,
+        })
+    }
+}
+
+fn smtp_transport(host: String) -> Result<EmailTransport> {
+    let port = match env::var("SMTP_PORT") {
+        Ok(p) => p.parse().context("SMTP_PORT must be a number")?,
+        Err(_) => 587,
+    };
+    Ok(EmailTransport::Smtp {
+        host,
+        port,
+        username: env::var("SMTP_USERNAME").ok(),
+        password: env::var("SMTP_PASSWORD").ok(),
+    })
+}
+
+/// Options for the `send-report` command
+#[derive(Debug, Clone, Default)]
+pub struct EmailOptions {
+    pub from_date: Option<String>,
+    pub to_date: Option<String>,
+    pub recipients: Option<Vec<String>>,
+    pub subject: Option<String>,
+    pub via: Option<String>,
+    pub attach: bool,
+}
+
+/// Split a comma-separated recipient list, dropping blanks
+pub fn parse_recipients(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|r| r.trim())
+        .filter(|r| !r.is_empty())
+        .map(|r| r.to_string())
+        .collect()
+}
+
+/// Render a markdown report as a standalone HTML document
+pub fn markdown_to_html(markdown: &str) -> String {
+    let parser = pulldown_cmark::Parser::new_ext(markdown, pulldown_cmark::Options::all());
+    let mut body = String::new();
+    pulldown_cmark::html::push_html(&mut body, parser);
+    format!(
+        "<html><body style=\"font-family: sans-serif; color: #111827;\">\n{}</body></html>",
+        body
+    )
+}
+
+fn content_type_for(filename: &str) -> &'static str {
+    match Path::new(filename)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .as_deref()
+    {
+        Some("csv") => "text/csv",
+        Some("svg") => "image/svg+xml",
+        Some("md") => "text/markdown",
+        Some("html") => "text/html",
+        Some("json") => "application/json",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Request body for Brevo's transactional email endpoint
+fn brevo_payload(settings: &EmailSettings, message: &EmailMessage) -> Value {
+    let mut sender = json!({ "email": settings.sender_email });
+    if let Some(name) = &settings.sender_name {
+        sender["name"] = json!(name);
+    }
+
+    let mut payload = json!({
+        "sender": sender,
+        "to": settings
+            .recipients
+            .iter()
+            .map(|r| json!({ "email": r }))
+            .collect::<Vec<_>>(),
+        "subject": message.subject,
+        "htmlContent": message.html_body,
+        "textContent": message.text_body,
+    });
+
+    if !message.attachments.is_empty() {
+        payload["attachment"] = json!(
+            message
+                .attachments
+                .iter()
+                .map(|a| json!({ "name": a.filename, "content": BASE64.encode(&a.content) }))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    payload
+}
+
+async fn send_via_brevo(
+    api_key: &str,
+    settings: &EmailSettings,
+    message: &EmailMessage,
+) -> Result<()> {
+    let response = reqwest::Client::new()
+        .post(BREVO_API_URL)
+        .header("api-key", api_key)
+        .json(&brevo_payload(settings, message))
+        .send()
+        .await
+        .context("Failed to send request to Brevo")?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        anyhow::bail!("Brevo API error: {} - {}", status, body);
+    }
+
+    Ok(())
+}
+
+fn build_mime_message(settings: &EmailSettings, message: &EmailMessage) -> Result<Message> {
+    let sender = Mailbox::new(
+        settings.sender_name.clone(),
+        settings
+            .sender_email
+            .parse()
+            .with_context(|| format!("Invalid sender address: {}", settings.sender_email))?,
+    );
+
+    let mut builder = Message::builder().from(sender).subject(&message.subject);
+    for recipient in &settings.recipients {
+        builder = builder.to(recipient
+            .parse()
+            .with_context(|| format!("Invalid recipient address: {}", recipient))?);
+    }
+
+    let mut body = MultiPart::mixed().multipart(MultiPart::alternative_plain_html(
+        message.text_body.clone(),
+        message.html_body.clone(),
+    ));
+    for attachment in &message.attachments {
+        body = body.singlepart(MailAttachment::new(attachment.filename.clone()).body(
+            attachment.content.clone(),
+            ContentType::parse(&attachment.content_type)?,
+        ));
+    }
+
+    Ok(builder.multipart(body)?)
+}
+
+async fn send_via_smtp(
+    host: &str,
+    port: u16,
+    credentials: Option<Credentials>,
+    settings: &EmailSettings,
+    message: &EmailMessage,
+) -> Result<()> {
+    let email = build_mime_message(settings, message)?;
+
+    let mut transport = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)
+        .with_context(|| format!("Failed to configure SMTP relay {}", host))?
+        .port(port);
+    if let Some(credentials) = credentials {
+        transport = transport.credentials(credentials);
+    }
+
+    transport
+        .build()
+        .send(email)
+        .await
+        .with_context(|| format!("SMTP delivery via {}:{} failed", host, port))?;
+
+    Ok(())
+}
+
+/// Deliver a message with the configured transport
+pub async fn send_email(settings: &EmailSettings, message: &EmailMessage) -> Result<()> {
+    match &settings.transport {
+        EmailTransport::Brevo { api_key } => send_via_brevo(api_key, settings, message).await,
+        EmailTransport::Smtp {
+            host,
+            port,
+            username,
+            password,
+        } => {
+            let credentials = match (username, password) {
+                (Some(u), Some(p)) => Some(Credentials::new(u.clone(), p.clone())),
+                _ => None,
+            };
+            send_via_smtp(host, *port, credentials, settings, message).await
+        }
+    }
+}
+
+/// Find the first two YYYY-MM-DD dates in a comparison file name
+fn parse_date_range(file_name: &str) -> Option<(String, String)> {
+    let mut dates = (0..file_name.len().saturating_sub(9)).filter_map(|start| {
+        let candidate = file_name.get(start..start + 10)?;
+        chrono::NaiveDate::parse_from_str(candidate, "%Y-%m-%d")
+            .ok()
+            .map(|_| candidate.to_string())
+    });
+    Some((dates.next()?, dates.next()?))
+}
+
+/// Locate the comparison summary to send, either for the given dates or the
+/// most recently written one
+fn find_summary(
+    output: &OutputConfig,
+    from_date: Option<&str>,
+    to_date: Option<&str>,
+) -> Result<(PathBuf, String, String)> {
+    if let (Some(from), Some(to)) = (from_date, to_date) {
+        let summary = output
+            .find_latest("comparison", &format!("{}_to_{}_summary", from, to), "md")?
+            .with_context(|| {
+                format!(
+                    "No comparison summary found for {} to {}. Run 'compare-market-caps' first.",
+                    from, to
+                )
+            })?;
+        return Ok((summary, from.to_string(), to.to_string()));
+    }
+
+    let mut latest: Option<(std::time::SystemTime, PathBuf)> = None;
+    for path in output.list("comparison", "md")? {
+        let modified = fs::metadata(&path)?.modified()?;
+        if latest.as_ref().is_none_or(|(t, _)| modified > *t) {
+            latest = Some((modified, path));
+        }
+    }
+    let (_, summary) =
+        latest.context("No comparison summary found. Run 'compare-market-caps' first.")?;
+
+    let file_name = summary
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let (from, to) = parse_date_range(&file_name)
+        .with_context(|| format!("Cannot determine dates from {}", file_name))?;
+
+    Ok((summary, from, to))
+}
+
+/// Comparison CSV and chart SVGs that belong to a comparison
+fn find_attachments(output: &OutputConfig, from_date: &str, to_date: &str) -> Result<Vec<PathBuf>> {
+    let range = format!("{}_to_{}", from_date, to_date);
+    let mut files = Vec::new();
+
+    if let Some(csv) = output.find_latest("comparison", &range, "csv")? {
+        files.push(csv);
+    }
+
+    for chart in [
+        "gainers_losers",
+        "market_distribution",
+        "rank_movements",
+        "summary_dashboard",
+    ] {
+        let path = output.named_path(&format!("comparison_{}_{}.svg", range, chart));
+        if path.exists() {
+            files.push(path);
+        }
+    }
+
+    Ok(files)
+}
+
+/// Comparison CSV and charts of a range, read for attaching
+fn load_attachments(
+    output: &OutputConfig,
+    from_date: &str,
+    to_date: &str,
+) -> Result<Vec<Attachment>> {
+    find_attachments(output, from_date, to_date)?
+        .iter()
+        .map(|p| Attachment::from_path(p))
+        .collect()
+}
+
+/// Render the latest comparison report and email it
+pub async fn send_report(options: EmailOptions) -> Result<()> {
+    let settings = EmailSettings::from_env(options.via.as_deref(), options.recipients.clone())?;
+    let output = config::load_output_config();
+
+    let (summary_path, from_date, to_date) = find_summary(
+        &output,
+        options.from_date.as_deref(),
+        options.to_date.as_deref(),
+    )?;
+    println!("Rendering report from {}", summary_path.display());
+
+    let markdown = fs::read_to_string(&summary_path)
+        .with_context(|| format!("Failed to read {}", summary_path.display()))?;
+
+    let attachments = if options.attach {
+        load_attachments(&output, &from_date, &to_date)?
+    } else {
+        Vec::new()
+    };
+
+    let message = EmailMessage {
+        subject: options.subject.clone().unwrap_or_else(|| {
+            format!(
+                "Top 200 Market Cap Comparison: {} to {}",
+                from_date, to_date
+            )
+        }),
+        html_body: markdown_to_html(&markdown),
+        text_body: markdown,
+        attachments,
+    };
+
+    send_email(&settings, &message).await?;
+
+    println!(
+        "✅ Report sent to {} recipient(s) with {} attachment(s)",
+        settings.recipients.len(),
+        message.attachments.len()
+    );
+
+    Ok(())
+}
+
+/// Email the `report` bundle (digest above the comparison summary) with the
+/// comparison CSV and charts attached, to the default recipients
+pub async fn send_bundle(from_date: &str, to_date: &str, markdown: &str) -> Result<()> {
+    let settings = EmailSettings::from_env(None, None)?;
+    let output = config::load_output_config();
+    let message = EmailMessage {
+        subject: format!("Top 200 Market Cap Report: {} to {}", from_date, to_date),
+        html_body: markdown_to_html(markdown),
+        text_body: markdown.to_string(),
+        attachments: load_attachments(&output, from_date, to_date)?,
+    };
+
+    send_email(&settings, &message).await?;
+
+    println!(
+        "✅ Report bundle sent to {} recipient(s) with {} attachment(s)",
+        settings.recipients.len(),
+        message.attachments.len()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> EmailSettings {
+        EmailSettings {
+            transport: EmailTransport::Brevo {
+                api_key: "test".to_string(),
+            },
+            sender_email: "reports@example.com".to_string(),
+            sender_name: Some("Top200".to_string()),
+            recipients: vec!["a@example.com".to_string(), "b@example.com".to_string()],
+        }
+    }
+
+    fn message() -> EmailMessage {
+        EmailMessage {
+            subject: "Report".to_string(),
+            text_body: "# Title".to_string(),
+            html_body: markdown_to_html("# Title"),
+            attachments: vec![Attachment {
+                filename: "comparison.csv".to_string(),
+                content_type: "text/csv".to_string(),
+                content: b"Ticker\nNKE\n".to_vec(),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_parse_recipients() {
+        assert_eq!(
+            parse_recipients(" a@example.com, ,b@example.com ,"),
+            vec!["a@example.com".to_string(), "b@example.com".to_string()]
+        );
+        assert!(parse_recipients("").is_empty());
+    }
+
+    #[test]
+    fn test_markdown_to_html() {
+        let html = markdown_to_html("# Market Cap\n\n- **LVMH**: +2.00%\n");
+        assert!(html.contains("<h1>Market Cap</h1>"));
+        assert!(html.contains("<strong>LVMH</strong>"));
+        assert!(html.starts_with("<html>"));
+    }
+
+    #[test]
+    fn test_content_type_for() {
+        assert_eq!(content_type_for("a.csv"), "text/csv");
+        assert_eq!(content_type_for("chart.SVG"), "image/svg+xml");
+        assert_eq!(content_type_for("blob"), "application/octet-stream");
+    }
+
+    #[test]
+    fn test_brevo_payload() {
+        let payload = brevo_payload(&settings(), &message());
+
+        assert_eq!(payload["sender"]["email"], "reports@example.com");
+        assert_eq!(payload["sender"]["name"], "Top200");
+        assert_eq!(payload["to"].as_array().unwrap().len(), 2);
+        assert_eq!(payload["attachment"][0]["name"], "comparison.csv");
+        assert_eq!(
+            payload["attachment"][0]["content"],
+            BASE64.encode(b"Ticker\nNKE\n")
+        );
+    }
+
+    #[test]
+    fn test_build_mime_message_with_attachment() {
+        let email = build_mime_message(&settings(), &message()).unwrap();
+        let raw = String::from_utf8(email.formatted()).unwrap();
+
+        assert!(raw.contains("Subject: Report"));
+        assert!(raw.contains("filename=\"comparison.csv\""));
+    }
+
+    #[test]
+    fn test_build_mime_message_rejects_bad_recipient() {
+        let mut bad = settings();
+        bad.recipients = vec!["not-an-address".to_string()];
+        assert!(build_mime_message(&bad, &message()).is_err());
+    }
+
+    #[test]
+    fn test_parse_date_range() {
+        assert_eq!(
+            parse_date_range("comparison_2025-01-01_to_2025-02-01_summary_20250201_120000.md"),
+            Some(("2025-01-01".to_string(), "2025-02-01".to_string()))
+        );
+        assert_eq!(parse_date_range("comparison_summary.md"), None);
+    }
+
+    #[test]
+    fn test_find_summary_and_attachments() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = OutputConfig {
+            directory: dir.path().to_string_lossy().to_string(),
+            ..OutputConfig::default()
+        };
+        for name in [
+            "comparison_2025-01-01_to_2025-02-01_summary_20250201_120000.md",
+            "comparison_2025-01-01_to_2025-02-01_20250201_120000.csv",
+            "comparison_2025-01-01_to_2025-02-01_gainers_losers.svg",
+        ] {
+            fs::write(dir.path().join(name), "x").unwrap();
+        }
+
+        let (summary, from, to) = find_summary(&output, None, None).unwrap();
+        assert!(
+            summary.ends_with("comparison_2025-01-01_to_2025-02-01_summary_20250201_120000.md")
+        );
+        assert_eq!((from.as_str(), to.as_str()), ("2025-01-01", "2025-02-01"));
+
+        let attachments = find_attachments(&output, &from, &to).unwrap();
+        assert_eq!(attachments.len(), 2);
+
+        assert!(find_summary(&output, Some("2024-01-01"), Some("2024-02-01")).is_err());
+    }
+}
@@ -0,0 +1,9 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+pub mod email;
+pub mod webhook;
+
+pub use email::{EmailOptions, send_report};
+pub use webhook::{Mover, RunSummary, notify_run};
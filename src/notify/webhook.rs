@@ -0,0 +1,284 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Slack and Microsoft Teams incoming-webhook notifications
+//!
+//! Webhook URLs come from the `[notifications]` section of config.toml or the
+//! `SLACK_WEBHOOK_URL` / `TEAMS_WEBHOOK_URL` environment variables (which win).
+
+use anyhow::{Context, Result};
+use serde_json::{Value, json};
+use std::env;
+
+use crate::config::{self, NotificationConfig};
+
+/// A company's move used in notifications
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mover {
+    pub ticker: String,
+    pub name: String,
+    pub percentage_change: f64,
+}
+
+/// Concise summary of a finished comparison run
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunSummary {
+    pub from_date: String,
+    pub to_date: String,
+    pub total_change_pct: Option<f64>,
+    pub top_gainer: Option<Mover>,
+    pub top_loser: Option<Mover>,
+    pub artifacts_url: Option<String>,
+}
+
+impl RunSummary {
+    /// Largest absolute move in the summary (total or single company)
+    pub fn largest_move(&self) -> f64 {
+        [
+            self.total_change_pct,
+            self.top_gainer.as_ref().map(|m| m.percentage_change),
+            self.top_loser.as_ref().map(|m| m.percentage_change),
+        ]
+        .into_iter()
+        .flatten()
+        .map(f64::abs)
+        .fold(0.0, f64::max)
+    }
+
+    /// Whether any move reaches the configured threshold (in percent)
+    pub fn exceeds_threshold(&self, threshold_pct: f64) -> bool {
+        self.largest_move() >= threshold_pct
+    }
+
+    fn title(&self) -> String {
+        format!(
+            "Top 200 market caps: {} to {}",
+            self.from_date, self.to_date
+        )
+    }
+
+    /// Body lines shared by the Slack and Teams payloads
+    fn lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        if let Some(total) = self.total_change_pct {
+            lines.push(format!("Total market cap change: {:+.2}%", total));
+        }
+        if let Some(m) = &self.top_gainer {
+            lines.push(format!(
+                "Top gainer: {} ({}) {:+.2}%",
+                m.name, m.ticker, m.percentage_change
+            ));
+        }
+        if let Some(m) = &self.top_loser {
+            lines.push(format!(
+                "Top loser: {} ({}) {:+.2}%",
+                m.name, m.ticker, m.percentage_change
+            ));
+        }
+        if let Some(url) = &self.artifacts_url {
+            lines.push(format!("Artifacts: {}", url));
+        }
+        lines
+    }
+}
+
+/// Slack incoming-webhook payload
+pub fn slack_payload(title: &str, lines: &[String]) -> Value {
+    json!({
+        "text": format!("*{}*\n{}", title, lines.join("\n")),
+    })
+}
+
+/// Microsoft Teams incoming-webhook payload (legacy MessageCard format)
+pub fn teams_payload(title: &str, lines: &[String]) -> Value {
+    json!({
+        "@type": "MessageCard",
+        "@context": "http://schema.org/extensions",
+        "summary": title,
+        "title": title,
+        "text": lines.join("<br/>"),
+    })
+}
+
+async fn post_json(url: &str, payload: &Value) -> Result<()> {
+    let response = reqwest::Client::new()
+        .post(url)
+        .json(payload)
+        .send()
+        .await
+        .context("Failed to send webhook request")?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        anyhow::bail!("Webhook returned {} - {}", status, body);
+    }
+    Ok(())
+}
+
+/// Webhook settings from config.toml with environment overrides applied
+fn resolve_settings() -> NotificationConfig {
+    let mut settings = config::load_config()
+        .map(|c| c.notifications)
+        .unwrap_or_default();
+    if let Ok(url) = env::var("SLACK_WEBHOOK_URL") {
+        settings.slack_webhook_url = Some(url);
+    }
+    if let Ok(url) = env::var("TEAMS_WEBHOOK_URL") {
+        settings.teams_webhook_url = Some(url);
+    }
+    if let Ok(url) = env::var("ARTIFACTS_URL") {
+        settings.artifacts_url = Some(url);
+    }
+    settings
+}
+
+/// Post a message to every configured webhook. Returns the number of hooks
+/// that were notified.
+pub async fn post_message(
+    settings: &NotificationConfig,
+    title: &str,
+    lines: &[String],
+) -> Result<usize> {
+    let mut sent = 0;
+    if let Some(url) = &settings.slack_webhook_url {
+        post_json(url, &slack_payload(title, lines))
+            .await
+            .context("Slack notification failed")?;
+        sent += 1;
+    }
+    if let Some(url) = &settings.teams_webhook_url {
+        post_json(url, &teams_payload(title, lines))
+            .await
+            .context("Teams notification failed")?;
+        sent += 1;
+    }
+    Ok(sent)
+}
+
+/// Post a message to the configured webhooks regardless of thresholds.
+/// Does nothing when no webhook is configured.
+pub async fn notify_message(title: &str, lines: &[String]) -> Result<()> {
+    let settings = resolve_settings();
+    if settings.is_configured() {
+        post_message(&settings, title, lines).await?;
+    }
+    Ok(())
+}
+
+/// Post a message that was explicitly asked for (`report --publish slack`);
+/// fails when no webhook is configured
+pub async fn publish_message(title: &str, lines: &[String]) -> Result<usize> {
+    let settings = resolve_settings();
+    if !settings.is_configured() {
+        anyhow::bail!(
+            "No webhook configured: set SLACK_WEBHOOK_URL or TEAMS_WEBHOOK_URL, or [notifications] in config.toml"
+        );
+    }
+    post_message(&settings, title, lines).await
+}
+
+/// Notify the configured webhooks about a finished comparison, but only when
+/// a move exceeds the configured threshold
+pub async fn notify_run(mut summary: RunSummary) -> Result<()> {
+    let settings = resolve_settings();
+    if !settings.is_configured() {
+        return Ok(());
+    }
+
+    if !summary.exceeds_threshold(settings.threshold_pct) {
+        println!(
+            "No webhook notification sent: largest move {:.2}% is below the {:.2}% threshold",
+            summary.largest_move(),
+            settings.threshold_pct
+        );
+        return Ok(());
+    }
+
+    if summary.artifacts_url.is_none() {
+        summary.artifacts_url = settings.artifacts_url.clone();
+    }
+
+    let sent = post_message(&settings, &summary.title(), &summary.lines()).await?;
+    println!("✅ Posted run summary to {} webhook(s)", sent);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary() -> RunSummary {
+        RunSummary {
+            from_date: "2025-01-01".to_string(),
+            to_date: "2025-02-01".to_string(),
+            total_change_pct: Some(1.25),
+            top_gainer: Some(Mover {
+                ticker: "RMS.PA".to_string(),
+                name: "Hermès".to_string(),
+                percentage_change: 4.5,
+            }),
+            top_loser: Some(Mover {
+                ticker: "NKE".to_string(),
+                name: "Nike".to_string(),
+                percentage_change: -2.0,
+            }),
+            artifacts_url: None,
+        }
+    }
+
+    #[test]
+    fn test_threshold() {
+        let s = summary();
+        assert_eq!(s.largest_move(), 4.5);
+        assert!(s.exceeds_threshold(3.0));
+        assert!(!s.exceeds_threshold(5.0));
+    }
+
+    #[test]
+    fn test_threshold_counts_losses() {
+        let mut s = summary();
+        s.top_gainer = None;
+        s.top_loser.as_mut().unwrap().percentage_change = -7.0;
+        assert!(s.exceeds_threshold(3.0));
+    }
+
+    #[test]
+    fn test_empty_summary_never_exceeds() {
+        let s = RunSummary {
+            from_date: "2025-01-01".to_string(),
+            to_date: "2025-02-01".to_string(),
+            total_change_pct: None,
+            top_gainer: None,
+            top_loser: None,
+            artifacts_url: None,
+        };
+        assert!(!s.exceeds_threshold(0.1));
+    }
+
+    #[test]
+    fn test_lines() {
+        let mut s = summary();
+        s.artifacts_url = Some("https://example.com/run/1".to_string());
+        let lines = s.lines();
+
+        assert_eq!(lines[0], "Total market cap change: +1.25%");
+        assert_eq!(lines[1], "Top gainer: Hermès (RMS.PA) +4.50%");
+        assert_eq!(lines[2], "Top loser: Nike (NKE) -2.00%");
+        assert_eq!(lines[3], "Artifacts: https://example.com/run/1");
+    }
+
+    #[test]
+    fn test_slack_and_teams_payloads() {
+        let lines = vec!["a".to_string(), "b".to_string()];
+
+        let slack = slack_payload("Title", &lines);
+        assert_eq!(slack["text"], "*Title*\na\nb");
+
+        let teams = teams_payload("Title", &lines);
+        assert_eq!(teams["@type"], "MessageCard");
+        assert_eq!(teams["title"], "Title");
+        assert_eq!(teams["text"], "a<br/>b");
+    }
+}
@@ -0,0 +1,216 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! `--min-market-cap` and `--winsorize`: keep tiny companies from dominating
+//! summaries
+//!
+//! A company with a small market cap can move hundreds of percent and top the
+//! gainers tables and averages. `--min-market-cap 1e9` leaves companies below
+//! the USD floor out of comparisons, trends and peer groups; a company is
+//! sized by its market cap on the start date, or on the end date when it has
+//! none then. `--winsorize 5` clamps percentage changes to their 5th and 95th
+//! percentile before they are averaged; the tables still show the actual
+//! changes. Reports note the filters applied at the top.
+
+use anyhow::Result;
+use std::collections::BTreeSet;
+use std::sync::OnceLock;
+
+use crate::utils::format_usd;
+
+/// Outlier handling of this run
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct OutlierOptions {
+    /// Smallest USD market cap a company needs to be included
+    pub min_market_cap: Option<f64>,
+    /// Percentile clamped on each tail of the percentage changes for averages
+    pub winsorize_pct: Option<f64>,
+}
+
+static OPTIONS: OnceLock<OutlierOptions> = OnceLock::new();
+
+/// Set the outlier handling for this run; later calls are ignored
+pub fn init(min_market_cap: Option<f64>, winsorize_pct: Option<f64>) -> Result<()> {
+    if min_market_cap.is_some_and(|floor| !floor.is_finite() || floor <= 0.0) {
+        anyhow::bail!("--min-market-cap must be a positive amount in USD, e.g. 1e9");
+    }
+    if winsorize_pct.is_some_and(|pct| !(pct > 0.0 && pct < 50.0)) {
+        anyhow::bail!("--winsorize takes a percentile between 0 and 50, e.g. 5");
+    }
+    let _ = OPTIONS.set(OutlierOptions {
+        min_market_cap,
+        winsorize_pct,
+    });
+    Ok(())
+}
+
+/// The outlier handling of this run (none unless `init` was called)
+pub fn current() -> OutlierOptions {
+    OPTIONS.get().copied().unwrap_or_default()
+}
+
+impl OutlierOptions {
+    pub fn is_active(&self) -> bool {
+        self.min_market_cap.is_some() || self.winsorize_pct.is_some()
+    }
+
+    /// Whether a company of this USD market cap falls below the floor. A
+    /// missing market cap is never below it.
+    pub fn below_floor(&self, market_cap_usd: Option<f64>) -> bool {
+        match (self.min_market_cap, market_cap_usd) {
+            (Some(floor), Some(value)) => value < floor,
+            _ => false,
+        }
+    }
+
+    /// Tickers below the floor, sized by their start-date market cap or, for
+    /// companies without one, their end-date one
+    pub fn below_floor_tickers<'a>(
+        &self,
+        from: impl IntoIterator<Item = (&'a str, Option<f64>)>,
+        to: impl IntoIterator<Item = (&'a str, Option<f64>)>,
+    ) -> BTreeSet<String> {
+        if self.min_market_cap.is_none() {
+            return BTreeSet::new();
+        }
+        let mut seen = BTreeSet::new();
+        let mut below = BTreeSet::new();
+        for (ticker, value) in from.into_iter().chain(to) {
+            if value.is_some() && seen.insert(ticker) && self.below_floor(value) {
+                below.insert(ticker.to_string());
+            }
+        }
+        below
+    }
+
+    /// Mean of the percentage changes, winsorized under `--winsorize`
+    pub fn average(&self, changes: &[f64]) -> Option<f64> {
+        if changes.is_empty() {
+            return None;
+        }
+        let values = match self.winsorize_pct {
+            Some(pct) => winsorize(changes, pct),
+            None => changes.to_vec(),
+        };
+        Some(values.iter().sum::<f64>() / values.len() as f64)
+    }
+
+    /// "Outlier Handling" section for the top of a report. `excluded` are the
+    /// tickers left out by the floor; the winsorizing line is only added when
+    /// the report has averages it applies to.
+    pub fn markdown_note(&self, excluded: &BTreeSet<String>, with_averages: bool) -> String {
+        let mut note = String::from("## Outlier Handling\n\n");
+        if let Some(floor) = self.min_market_cap {
+            note.push_str(&format!(
+                "- **Market cap floor:** companies below {} on the start date are excluded ({})",
+                format_usd(floor),
+                excluded.len()
+            ));
+            if !excluded.is_empty() {
+                note.push_str(&format!(
+                    ": {}",
+                    excluded.iter().cloned().collect::<Vec<_>>().join(", ")
+                ));
+            }
+            note.push('\n');
+        }
+        if let (Some(pct), true) = (self.winsorize_pct, with_averages) {
+            note.push_str(&format!(
+                "- **Winsorized averages:** percentage changes are clamped to their {}th and {}th percentile before averaging; tables show the actual changes\n",
+                pct,
+                100.0 - pct
+            ));
+        }
+        note.push('\n');
+        note
+    }
+}
+
+/// Percentile of sorted values, interpolated between the closest ranks
+fn percentile(sorted: &[f64], pct: f64) -> f64 {
+    let position = pct / 100.0 * (sorted.len() - 1) as f64;
+    let (lower, upper) = (position.floor() as usize, position.ceil() as usize);
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (position - lower as f64)
+}
+
+/// The values clamped to their `pct`th and `100 - pct`th percentile
+pub fn winsorize(values: &[f64], pct: f64) -> Vec<f64> {
+    if values.is_empty() {
+        return Vec::new();
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let (low, high) = (percentile(&sorted, pct), percentile(&sorted, 100.0 - pct));
+    values.iter().map(|v| v.clamp(low, high)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_winsorize_clamps_both_tails() {
+        let values = [0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 500.0];
+        // 10th percentile is 1.0, 90th is 9.0
+        let clamped = winsorize(&values, 10.0);
+        assert_eq!(clamped[0], 1.0);
+        assert_eq!(clamped[10], 9.0);
+        assert_eq!(clamped[5], 5.0);
+
+        let options = OutlierOptions {
+            min_market_cap: None,
+            winsorize_pct: Some(10.0),
+        };
+        assert_eq!(options.average(&values), Some(55.0 / 11.0));
+        assert_eq!(
+            OutlierOptions::default().average(&values),
+            Some(545.0 / 11.0)
+        );
+        assert_eq!(options.average(&[]), None);
+    }
+
+    #[test]
+    fn test_below_floor_sizes_by_start_date() {
+        let options = OutlierOptions {
+            min_market_cap: Some(1e9),
+            winsorize_pct: None,
+        };
+        let below = options.below_floor_tickers(
+            [("TINY", Some(2e8)), ("BIG", Some(5e9)), ("NODATA", None)],
+            // TINY grew past the floor, NEW entered below it
+            [
+                ("TINY", Some(1.5e9)),
+                ("BIG", Some(5e9)),
+                ("NODATA", Some(4e8)),
+                ("NEW", Some(3e8)),
+            ],
+        );
+        assert_eq!(
+            below,
+            BTreeSet::from(["NEW", "NODATA", "TINY"].map(String::from))
+        );
+        assert!(
+            OutlierOptions::default()
+                .below_floor_tickers([("TINY", Some(1.0))], [])
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_markdown_note_lists_filters() {
+        let options = OutlierOptions {
+            min_market_cap: Some(1e9),
+            winsorize_pct: Some(5.0),
+        };
+        let note = options.markdown_note(&BTreeSet::from(["TINY".to_string()]), true);
+        assert!(note.starts_with("## Outlier Handling\n\n"));
+        assert!(note.contains("below $1.0B on the start date are excluded (1): TINY\n"));
+        assert!(note.contains("5th and 95th percentile"));
+        assert!(
+            !options
+                .markdown_note(&BTreeSet::new(), false)
+                .contains("Winsorized")
+        );
+    }
+}
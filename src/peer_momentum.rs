@@ -0,0 +1,607 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! `peer-momentum --window 90d`: relative strength of the peer groups
+//!
+//! Splits the history before `--date` into consecutive windows of the given
+//! length and computes, per window, the return of each peer group's combined
+//! market cap and of the whole universe. A group's relative strength is the
+//! difference in percentage points. Only companies listed at both ends of a
+//! window count, so additions to the universe don't show up as growth. The
+//! leaderboard ranks the groups by their relative strength in the latest
+//! window and counts how many windows in a row they beat (or trailed) the
+//! universe; the sparkline chart shows the relative strength per window.
+
+use anyhow::{Context, Result};
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
+use csv::Writer;
+use sqlx::sqlite::SqlitePool;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Write as IoWrite;
+
+use crate::advanced_comparisons::{self, PeerGroup, RollingPeriod};
+use crate::clock;
+use crate::config::{self, OutputConfig};
+use crate::currencies::{convert_currency, get_rate_map_from_db_for_date};
+use crate::rankings;
+use crate::ticker_aliases::{AppliedAliases, TickerAliases};
+use crate::vega;
+use crate::visualizations;
+use crate::watchlists;
+
+/// Windows compared by default
+pub const DEFAULT_WINDOWS: usize = 4;
+
+/// Returns of one peer group per window, oldest window first
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupMomentum {
+    pub name: String,
+    /// Members listed at both ends of the latest window
+    pub members: usize,
+    /// Return of the group's combined market cap, in percent
+    pub returns: Vec<Option<f64>>,
+    /// Group return minus universe return, in percentage points
+    pub relative: Vec<Option<f64>>,
+}
+
+impl GroupMomentum {
+    pub fn latest_return(&self) -> Option<f64> {
+        self.returns.last().copied().flatten()
+    }
+
+    pub fn latest_relative(&self) -> Option<f64> {
+        self.relative.last().copied().flatten()
+    }
+
+    /// Windows in a row, up to the latest, in which the group beat the
+    /// universe (positive) or trailed it (negative)
+    pub fn streak(&self) -> i32 {
+        let sign = |v: f64| {
+            if v > 0.0 {
+                1
+            } else if v < 0.0 {
+                -1
+            } else {
+                0
+            }
+        };
+        let Some(direction) = self.latest_relative().map(sign).filter(|s| *s != 0) else {
+            return 0;
+        };
+        let count = self
+            .relative
+            .iter()
+            .rev()
+            .take_while(|v| v.map(sign) == Some(direction))
+            .count() as i32;
+        direction * count
+    }
+}
+
+/// Snapshot dates bounding `windows` consecutive windows of `window_days`
+/// ending on `end`, oldest first. Each boundary is the latest snapshot on or
+/// before its target date; the history stops early when that snapshot is
+/// more than half a window older than the target.
+pub fn window_dates(
+    available: &[NaiveDate],
+    end: NaiveDate,
+    window_days: i64,
+    windows: usize,
+) -> Vec<NaiveDate> {
+    let mut boundaries: Vec<NaiveDate> = Vec::new();
+    for k in 0..=windows as i64 {
+        let target = end - Duration::days(window_days * k);
+        let Some(date) = available.iter().filter(|d| **d <= target).max().copied() else {
+            break;
+        };
+        if (target - date).num_days() * 2 > window_days
+            || boundaries.last().is_some_and(|last| *last <= date)
+        {
+            break;
+        }
+        boundaries.push(date);
+    }
+    boundaries.reverse();
+    boundaries
+}
+
+/// Return of the combined market cap of the companies listed in both
+/// snapshots, restricted to `tickers` when given
+pub(crate) fn aggregate_return(
+    start: &HashMap<String, f64>,
+    end: &HashMap<String, f64>,
+    tickers: Option<&HashSet<String>>,
+) -> (Option<f64>, usize) {
+    let (mut total_start, mut total_end, mut count) = (0.0, 0.0, 0);
+    for (ticker, start_value) in start {
+        if tickers.is_some_and(|tickers| !tickers.contains(ticker)) {
+            continue;
+        }
+        if let Some(end_value) = end.get(ticker) {
+            total_start += start_value;
+            total_end += end_value;
+            count += 1;
+        }
+    }
+    let pct = (total_start > 0.0).then(|| (total_end - total_start) / total_start * 100.0);
+    (pct, count)
+}
+
+/// Momentum of each group over USD market caps per snapshot (oldest first),
+/// sorted by relative strength in the latest window
+pub fn compute_momentum(
+    groups: &[(String, HashSet<String>)],
+    snapshots: &[HashMap<String, f64>],
+) -> Vec<GroupMomentum> {
+    let universe: Vec<Option<f64>> = snapshots
+        .windows(2)
+        .map(|pair| aggregate_return(&pair[0], &pair[1], None).0)
+        .collect();
+
+    let mut results: Vec<GroupMomentum> = groups
+        .iter()
+        .map(|(name, tickers)| {
+            let mut members = 0;
+            let returns: Vec<Option<f64>> = snapshots
+                .windows(2)
+                .map(|pair| {
+                    let (pct, count) = aggregate_return(&pair[0], &pair[1], Some(tickers));
+                    members = count;
+                    pct
+                })
+                .collect();
+            let relative = returns
+                .iter()
+                .zip(&universe)
+                .map(|(group, universe)| Some(group.as_ref()? - universe.as_ref()?))
+                .collect();
+            GroupMomentum {
+                name: name.clone(),
+                members,
+                returns,
+                relative,
+            }
+        })
+        .collect();
+
+    results.sort_by(|a, b| {
+        rankings::rank_order(
+            (a.latest_relative(), &a.name, ""),
+            (b.latest_relative(), &b.name, ""),
+        )
+    });
+    results
+}
+
+/// Text sparkline of the values, blank where a value is missing
+fn sparkline(values: &[Option<f64>]) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let present: Vec<f64> = values.iter().flatten().copied().collect();
+    let low = present.iter().copied().fold(f64::INFINITY, f64::min);
+    let high = present.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    values
+        .iter()
+        .map(|value| match value {
+            Some(v) if high > low => {
+                BARS[(((v - low) / (high - low)) * (BARS.len() - 1) as f64).round() as usize]
+            }
+            Some(_) => BARS[BARS.len() / 2],
+            None => ' ',
+        })
+        .collect()
+}
+
+fn format_pct(value: Option<f64>) -> String {
+    value
+        .map(|v| format!("{:+.2}", v))
+        .unwrap_or_else(|| "N/A".to_string())
+}
+
+/// Sentence for a streak of two or more windows, e.g. "Sportswear
+/// outperformed the universe for 3 straight 90-day windows"
+fn streak_sentence(group: &GroupMomentum, window_days: i64) -> Option<String> {
+    let streak = group.streak();
+    if streak.abs() < 2 {
+        return None;
+    }
+    Some(format!(
+        "{} {} the universe for {} straight {}-day windows",
+        group.name,
+        if streak > 0 {
+            "outperformed"
+        } else {
+            "underperformed"
+        },
+        streak.abs(),
+        window_days
+    ))
+}
+
+/// USD market caps of a snapshot, converted with `rates`
+fn snapshot_usd(
+    date: &str,
+    watchlist: Option<&str>,
+    aliases: &TickerAliases,
+    applied: &mut AppliedAliases,
+    rates: &HashMap<String, f64>,
+) -> Result<HashMap<String, f64>> {
+    let records = advanced_comparisons::load_trend_snapshot(date, watchlist, aliases, applied)?;
+    Ok(records
+        .into_iter()
+        .filter_map(|r| {
+            let orig = r.market_cap_original?;
+            let currency = r.original_currency.as_deref().unwrap_or("USD");
+            let usd = if rates.is_empty() {
+                r.market_cap_usd.unwrap_or(orig)
+            } else {
+                convert_currency(orig, currency, "USD", rates)
+            };
+            Some((r.ticker, usd))
+        })
+        .collect())
+}
+
+/// Compute the peer group leaderboard and write its CSV, summary and chart
+pub async fn peer_momentum(
+    pool: &SqlitePool,
+    end_date: Option<&str>,
+    period: RollingPeriod,
+    windows: usize,
+    groups: Option<Vec<String>>,
+    watchlist: Option<&str>,
+) -> Result<()> {
+    if windows == 0 {
+        anyhow::bail!("--windows must be at least 1");
+    }
+    if period.days() <= 0 {
+        anyhow::bail!("The window must be at least one day");
+    }
+    let selected_groups: Vec<PeerGroup> = advanced_comparisons::select_peer_groups(groups)?;
+
+    let available: Vec<NaiveDate> = advanced_comparisons::get_available_dates(watchlist)?
+        .iter()
+        .filter_map(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+        .collect();
+    let end = match end_date {
+        Some(date) => NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .context("Invalid date format. Use YYYY-MM-DD")?,
+        None => *available
+            .iter()
+            .max()
+            .context("No market cap snapshots found in the output directory")?,
+    };
+    let dates = window_dates(&available, end, period.days(), windows);
+    if dates.len() < 2 {
+        anyhow::bail!(
+            "Not enough snapshots for a {}-day window ending {}: export one about {} days earlier",
+            period.days(),
+            end,
+            period.days()
+        );
+    }
+    if dates.len() - 1 < windows {
+        println!(
+            "⚠️  Snapshots cover only {} of {} windows",
+            dates.len() - 1,
+            windows
+        );
+    }
+    let dates: Vec<String> = dates.iter().map(|d| d.to_string()).collect();
+    println!(
+        "Computing {}-day peer group momentum over {} windows: {}",
+        period.days(),
+        dates.len() - 1,
+        dates.join(", ")
+    );
+
+    // Constant rates of the last date, so currency moves don't count as growth
+    let last = dates.last().unwrap();
+    let last_timestamp = NaiveDateTime::new(
+        NaiveDate::parse_from_str(last, "%Y-%m-%d")?,
+        NaiveTime::default(),
+    )
+    .and_utc()
+    .timestamp();
+    let rates = get_rate_map_from_db_for_date(pool, Some(last_timestamp)).await?;
+
+    let aliases = TickerAliases::load(pool).await?;
+    let mut applied = AppliedAliases::default();
+    let snapshots = dates
+        .iter()
+        .map(|date| snapshot_usd(date, watchlist, &aliases, &mut applied, &rates))
+        .collect::<Result<Vec<_>>>()?;
+
+    // Group tickers under their current symbol, like the snapshot records
+    let first = NaiveDate::parse_from_str(&dates[0], "%Y-%m-%d")?;
+    let groups: Vec<(String, HashSet<String>)> = selected_groups
+        .iter()
+        .map(|group| {
+            let tickers = group
+                .tickers
+                .iter()
+                .map(|ticker| aliases.current_symbol(ticker, first).to_string())
+                .collect();
+            (group.name.clone(), tickers)
+        })
+        .collect();
+    let universe: Vec<Option<f64>> = snapshots
+        .windows(2)
+        .map(|pair| aggregate_return(&pair[0], &pair[1], None).0)
+        .collect();
+    let results = compute_momentum(&groups, &snapshots);
+
+    for group in &results {
+        println!(
+            "  {:<20} {:>9}% vs universe {:>9} pp  {}",
+            group.name,
+            format_pct(group.latest_return()),
+            format_pct(group.latest_relative()),
+            sparkline(&group.relative)
+        );
+    }
+
+    export_peer_momentum(&results, &universe, &dates, period.days(), watchlist)
+}
+
+fn export_peer_momentum(
+    results: &[GroupMomentum],
+    universe: &[Option<f64>],
+    dates: &[String],
+    window_days: i64,
+    watchlist: Option<&str>,
+) -> Result<()> {
+    let output = config::load_output_config();
+    output.ensure_directory()?;
+    let timestamp = OutputConfig::timestamp();
+    let kind = watchlists::scoped_kind(watchlist, "peer_momentum");
+    let end = dates.last().map(String::as_str).unwrap_or_default();
+    let range = format!("{}_{}d", end, window_days);
+    let csv_path = output.file_path_at(&kind, &range, &timestamp, "csv");
+    let md_path = output.file_path_at(&kind, &format!("{}_summary", range), &timestamp, "md");
+    let chart_path = output.named_path(&format!(
+        "{}_{}_sparklines.{}",
+        kind,
+        range,
+        vega::extension()
+    ));
+
+    let mut writer = Writer::from_writer(File::create(&csv_path)?);
+    writer.write_record([
+        "Group",
+        "Window Start",
+        "Window End",
+        "Group Return (%)",
+        "Universe Return (%)",
+        "Relative Strength (pp)",
+    ])?;
+    for group in results {
+        for (i, window) in dates.windows(2).enumerate() {
+            let cell = |v: Option<f64>| v.map(|v| format!("{:.2}", v)).unwrap_or_default();
+            writer.write_record([
+                group.name.clone(),
+                window[0].clone(),
+                window[1].clone(),
+                cell(group.returns[i]),
+                cell(universe[i]),
+                cell(group.relative[i]),
+            ])?;
+        }
+    }
+    writer.flush()?;
+    println!("Peer momentum data exported to {}", csv_path.display());
+
+    let labels: Vec<String> = dates[1..].to_vec();
+    let rows: Vec<(String, Vec<Option<f64>>)> = results
+        .iter()
+        .map(|group| (group.name.clone(), group.relative.clone()))
+        .collect();
+    visualizations::create_sparkline_chart(
+        &format!("Peer Group Relative Strength: {}-day windows", window_days),
+        &format!(
+            "Group return minus universe return (pp) per window, {} to {}",
+            dates[0], end
+        ),
+        &labels,
+        &rows,
+        &chart_path,
+    )?;
+    println!("✅ Generated sparkline chart: {}", chart_path.display());
+
+    let mut file = File::create(&md_path)?;
+    writeln!(
+        file,
+        "# Peer Group Momentum: {}-day windows to {}",
+        window_days, end
+    )?;
+    writeln!(file)?;
+    writeln!(
+        file,
+        "Windows: {}. Relative strength is the group's market cap return minus the universe's, in percentage points; only companies listed at both ends of a window count.",
+        dates.join(" → ")
+    )?;
+    writeln!(file)?;
+
+    let highlights: Vec<String> = results
+        .iter()
+        .filter_map(|group| streak_sentence(group, window_days))
+        .collect();
+    if !highlights.is_empty() {
+        writeln!(file, "## Highlights")?;
+        writeln!(file)?;
+        for sentence in &highlights {
+            writeln!(file, "- {}", sentence)?;
+        }
+        writeln!(file)?;
+    }
+
+    writeln!(file, "## Leaderboard")?;
+    writeln!(file)?;
+    writeln!(
+        file,
+        "| # | Group | Members | Return (%) | vs Universe (pp) | Streak | Trend |"
+    )?;
+    writeln!(
+        file,
+        "|---|-------|---------|------------|------------------|--------|-------|"
+    )?;
+    for (rank, group) in results.iter().enumerate() {
+        let streak = match group.streak() {
+            0 => "-".to_string(),
+            s if s > 0 => format!("{} up", s),
+            s => format!("{} down", -s),
+        };
+        writeln!(
+            file,
+            "| {} | {} | {} | {} | {} | {} | {} |",
+            rank + 1,
+            group.name,
+            group.members,
+            format_pct(group.latest_return()),
+            format_pct(group.latest_relative()),
+            streak,
+            sparkline(&group.relative)
+        )?;
+    }
+    writeln!(file)?;
+    let chart_name = chart_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    if vega::enabled() {
+        writeln!(file, "Chart: [{}]({})", chart_name, chart_name)?;
+    } else {
+        writeln!(file, "![Relative strength per window]({})", chart_name)?;
+    }
+    writeln!(file)?;
+
+    writeln!(file, "## Relative Strength per Window (pp)")?;
+    writeln!(file)?;
+    write!(file, "| Group |")?;
+    for date in &labels {
+        write!(file, " {} |", date)?;
+    }
+    writeln!(file)?;
+    write!(file, "|-------|")?;
+    for _ in &labels {
+        write!(file, "------|")?;
+    }
+    writeln!(file)?;
+    write!(file, "| Universe return (%) |")?;
+    for value in universe {
+        write!(file, " {} |", format_pct(*value))?;
+    }
+    writeln!(file)?;
+    for group in results {
+        write!(file, "| {} |", group.name)?;
+        for value in &group.relative {
+            write!(file, " {} |", format_pct(*value))?;
+        }
+        writeln!(file)?;
+    }
+    writeln!(file)?;
+
+    writeln!(file, "---")?;
+    writeln!(
+        file,
+        "*Generated on {}*",
+        clock::now().format("%Y-%m-%d %H:%M:%S")
+    )?;
+    println!("Summary report exported to {}", md_path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn caps(values: &[(&str, f64)]) -> HashMap<String, f64> {
+        values.iter().map(|(t, v)| (t.to_string(), *v)).collect()
+    }
+
+    #[test]
+    fn test_window_dates_pick_latest_snapshot_before_each_boundary() {
+        let available = [
+            "2024-09-30",
+            "2024-12-31",
+            "2025-01-15",
+            "2025-03-28",
+            "2025-06-30",
+        ]
+        .map(date);
+        assert_eq!(
+            window_dates(&available, date("2025-06-30"), 90, 2),
+            ["2024-12-31", "2025-03-28", "2025-06-30"].map(date)
+        );
+        // Nothing on or before the fifth boundary (2024-07-05)
+        assert_eq!(
+            window_dates(&available, date("2025-06-30"), 90, 4),
+            ["2024-09-30", "2024-12-31", "2025-03-28", "2025-06-30"].map(date)
+        );
+        // Without 2024-12-31 the next snapshot back is half a window too old
+        let sparse = ["2024-09-30", "2025-03-28", "2025-06-30"].map(date);
+        assert_eq!(
+            window_dates(&sparse, date("2025-06-30"), 90, 4),
+            ["2025-03-28", "2025-06-30"].map(date)
+        );
+        assert!(window_dates(&available, date("2024-01-01"), 90, 3).is_empty());
+    }
+
+    #[test]
+    fn test_momentum_ranks_by_latest_relative_strength() {
+        let snapshots = vec![
+            caps(&[("NKE", 100.0), ("MC.PA", 300.0), ("GAP", 100.0)]),
+            caps(&[("NKE", 110.0), ("MC.PA", 300.0), ("GAP", 100.0)]),
+            // GAP leaves the universe: not counted as a loss
+            caps(&[("NKE", 121.0), ("MC.PA", 270.0), ("ONON", 50.0)]),
+        ];
+        let groups = vec![
+            ("Luxury".to_string(), HashSet::from(["MC.PA".to_string()])),
+            (
+                "Sportswear".to_string(),
+                HashSet::from(["NKE".to_string(), "ONON".to_string()]),
+            ),
+        ];
+        let results = compute_momentum(&groups, &snapshots);
+        assert_eq!(results[0].name, "Sportswear");
+        assert_eq!(results[0].members, 1);
+        assert!((results[0].returns[0].unwrap() - 10.0).abs() < 1e-9);
+        // Universe: 500 -> 510 (+2%), then 410 -> 391 (-4.63%)
+        assert!((results[0].relative[0].unwrap() - 8.0).abs() < 1e-9);
+        assert!((results[0].latest_relative().unwrap() - (10.0 + 1900.0 / 410.0)).abs() < 1e-9);
+        assert_eq!(results[0].streak(), 2);
+        assert_eq!(results[1].name, "Luxury");
+        assert_eq!(results[1].streak(), -2);
+        assert_eq!(
+            streak_sentence(&results[0], 90).unwrap(),
+            "Sportswear outperformed the universe for 2 straight 90-day windows"
+        );
+    }
+
+    #[test]
+    fn test_streak_stops_at_gap_or_sign_change() {
+        let group = |relative: Vec<Option<f64>>| GroupMomentum {
+            name: "Luxury".to_string(),
+            members: 1,
+            returns: relative.clone(),
+            relative,
+        };
+        assert_eq!(
+            group(vec![Some(1.0), None, Some(2.0), Some(0.5)]).streak(),
+            2
+        );
+        assert_eq!(group(vec![Some(-1.0), Some(2.0), Some(-0.5)]).streak(), -1);
+        assert_eq!(group(vec![Some(1.0), None]).streak(), 0);
+    }
+
+    #[test]
+    fn test_sparkline() {
+        assert_eq!(sparkline(&[Some(0.0), Some(7.0), None, Some(3.5)]), "▁█ ▅");
+        assert_eq!(sparkline(&[Some(2.0)]), "▅");
+    }
+}
@@ -0,0 +1,377 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Point-in-time resolution of historical snapshots (`--point-in-time`)
+//!
+//! FMP's historical market cap is point-in-time, but the name and currency
+//! stored with it come from today's profile, and the ticker list is today's
+//! config.toml. In point-in-time mode each ticker is resolved as of the
+//! requested date instead:
+//!
+//! - universe membership from the latest `universe_snapshots` entry on or
+//!   before the date (tickers that joined later are not fetched),
+//! - the symbol it traded under from `symbol_changes` made after the date,
+//! - name and currency from the latest stored `market_caps` row before the
+//!   date, under the current or the older symbol.
+//!
+//! Whatever cannot be resolved this way keeps today's value and is listed
+//! as unresolved in `point_in_time_<date>_<timestamp>.csv`.
+
+use anyhow::Result;
+use chrono::{NaiveDate, NaiveTime};
+use csv::Writer;
+use sqlx::sqlite::SqlitePool;
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+use crate::api::HistoricalMarketCap;
+
+/// Where a resolved value came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// A stored snapshot before the requested date
+    History,
+    /// Today's FMP profile: could not be resolved as of the date
+    Current,
+}
+
+impl Source {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Source::History => "history",
+            Source::Current => "current",
+        }
+    }
+}
+
+/// One ticker resolved as of the requested date
+#[derive(Debug, Clone, PartialEq)]
+pub struct Resolution {
+    pub ticker: String,
+    /// Symbol the company traded under on the date
+    pub symbol_as_of: String,
+    /// Whether the ticker was in the universe; `None` when no universe
+    /// snapshot precedes the date
+    pub member: Option<bool>,
+    pub name: String,
+    pub name_source: Source,
+    pub currency: String,
+    pub currency_source: Source,
+}
+
+impl Resolution {
+    /// Fields that keep today's value
+    pub fn unresolved(&self) -> Vec<&'static str> {
+        let mut fields = Vec::new();
+        if self.member.is_none() {
+            fields.push("universe");
+        }
+        if self.name_source == Source::Current {
+            fields.push("name");
+        }
+        if self.currency_source == Source::Current {
+            fields.push("currency");
+        }
+        fields
+    }
+}
+
+/// What the database knows about the universe before a date
+#[derive(Debug, Default, Clone)]
+pub struct PointInTime {
+    /// Date and tickers of the latest universe snapshot on or before the date
+    pub universe: Option<(String, BTreeSet<String>)>,
+    /// Symbol changes after the date, as (old symbol, new symbol)
+    renames_after: Vec<(String, String)>,
+    /// Name and currency of each ticker's latest snapshot before the date
+    history: HashMap<String, (String, Option<String>)>,
+}
+
+impl PointInTime {
+    /// Load universe snapshots, symbol changes and stored snapshots as of `date`
+    pub async fn load(pool: &SqlitePool, date: NaiveDate) -> Result<Self> {
+        let date_str = date.format("%Y-%m-%d").to_string();
+        let universe_date: Option<String> =
+            sqlx::query_scalar("SELECT MAX(date) FROM universe_snapshots WHERE date <= ?")
+                .bind(&date_str)
+                .fetch_one(pool)
+                .await?;
+        let universe = match universe_date {
+            Some(universe_date) => {
+                let tickers: Vec<String> =
+                    sqlx::query_scalar("SELECT ticker FROM universe_snapshots WHERE date = ?")
+                        .bind(&universe_date)
+                        .fetch_all(pool)
+                        .await?;
+                Some((universe_date, tickers.into_iter().collect()))
+            }
+            None => None,
+        };
+
+        let renames_after: Vec<(String, String)> = sqlx::query_as(
+            "SELECT old_symbol, new_symbol FROM symbol_changes
+             WHERE change_date > ? ORDER BY change_date DESC, id DESC",
+        )
+        .bind(&date_str)
+        .fetch_all(pool)
+        .await?;
+
+        // Rows at the date itself may come from an earlier run with today's profile
+        let start = date.and_time(NaiveTime::MIN).and_utc().timestamp();
+        let rows: Vec<(String, String, Option<String>)> = sqlx::query_as(
+            "SELECT m.ticker, m.name, m.original_currency
+             FROM market_caps m
+             JOIN (SELECT ticker, MAX(timestamp) AS ts FROM market_caps
+                   WHERE timestamp < ? GROUP BY ticker) latest
+               ON m.ticker = latest.ticker AND m.timestamp = latest.ts",
+        )
+        .bind(start)
+        .fetch_all(pool)
+        .await?;
+        let history = rows
+            .into_iter()
+            .map(|(ticker, name, currency)| (ticker, (name, currency)))
+            .collect();
+
+        Ok(Self {
+            universe,
+            renames_after,
+            history,
+        })
+    }
+
+    /// Symbol `ticker` traded under on the date, following later renames back
+    pub fn symbol_as_of(&self, ticker: &str) -> String {
+        let mut symbol = ticker.to_string();
+        // Renames are newest first, so a chain A -> B -> C resolves C to A
+        for (old, new) in &self.renames_after {
+            if *new == symbol {
+                symbol = old.clone();
+            }
+        }
+        symbol
+    }
+
+    /// Whether `ticker` (or the symbol it had) was in the universe on the date
+    pub fn is_member(&self, ticker: &str) -> Option<bool> {
+        let (_, tickers) = self.universe.as_ref()?;
+        Some(tickers.contains(ticker) || tickers.contains(&self.symbol_as_of(ticker)))
+    }
+
+    /// Resolve a fetched market cap as of the date, replacing today's name and
+    /// currency with the stored historical ones where available
+    pub fn resolve(&self, market_cap: &mut HistoricalMarketCap) -> Resolution {
+        let symbol_as_of = self.symbol_as_of(&market_cap.ticker);
+        let stored = self
+            .history
+            .get(&market_cap.ticker)
+            .or_else(|| self.history.get(&symbol_as_of));
+
+        let name_source = match stored {
+            Some((name, _)) if !name.is_empty() => {
+                market_cap.name = name.clone();
+                Source::History
+            }
+            _ => Source::Current,
+        };
+        let currency_source = match stored.and_then(|(_, c)| c.as_ref()) {
+            Some(currency) if !currency.is_empty() => {
+                market_cap.original_currency = currency.clone();
+                Source::History
+            }
+            _ => Source::Current,
+        };
+
+        Resolution {
+            ticker: market_cap.ticker.clone(),
+            member: self.is_member(&market_cap.ticker),
+            symbol_as_of,
+            name: market_cap.name.clone(),
+            name_source,
+            currency: market_cap.original_currency.clone(),
+            currency_source,
+        }
+    }
+
+    /// Split `tickers` into those in the universe on the date (or all of
+    /// them when membership is unknown) and those that joined later
+    pub fn members(&self, tickers: &[String]) -> (Vec<String>, Vec<String>) {
+        tickers
+            .iter()
+            .cloned()
+            .partition(|t| self.is_member(t) != Some(false))
+    }
+}
+
+/// Write the resolution of every fetched ticker
+pub fn export_resolutions(path: &Path, resolutions: &[Resolution]) -> Result<()> {
+    let mut writer = Writer::from_path(path)?;
+    writer.write_record([
+        "Ticker",
+        "Symbol As Of",
+        "In Universe",
+        "Name",
+        "Name Source",
+        "Currency",
+        "Currency Source",
+        "Unresolved",
+    ])?;
+    for r in resolutions {
+        writer.write_record([
+            r.ticker.as_str(),
+            r.symbol_as_of.as_str(),
+            match r.member {
+                Some(true) => "yes",
+                Some(false) => "no",
+                None => "unknown",
+            },
+            r.name.as_str(),
+            r.name_source.label(),
+            r.currency.as_str(),
+            r.currency_source.label(),
+            &r.unresolved().join(";"),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    fn market_cap(ticker: &str, name: &str, currency: &str) -> HistoricalMarketCap {
+        HistoricalMarketCap {
+            ticker: ticker.to_string(),
+            name: name.to_string(),
+            market_cap_original: 1.0,
+            original_currency: currency.to_string(),
+            exchange: "NYSE".to_string(),
+            price: 1.0,
+        }
+    }
+
+    async fn insert_snapshot(
+        pool: &SqlitePool,
+        ticker: &str,
+        name: &str,
+        currency: &str,
+        date: &str,
+    ) {
+        let ts = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .unwrap()
+            .and_time(NaiveTime::MIN)
+            .and_utc()
+            .timestamp();
+        sqlx::query(
+            "INSERT INTO market_caps (ticker, name, original_currency, timestamp) VALUES (?, ?, ?, ?)",
+        )
+        .bind(ticker)
+        .bind(name)
+        .bind(currency)
+        .bind(ts)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_resolves_as_of_date() {
+        let pool = db::create_db_pool("sqlite::memory:").await.unwrap();
+        for (date, tickers) in [
+            ("2024-01-31", vec!["FB", "NKE"]),
+            ("2025-06-30", vec!["META", "NKE", "NEW"]),
+        ] {
+            let tickers: Vec<String> = tickers.into_iter().map(String::from).collect();
+            crate::universe::record_universe(&pool, date, &tickers)
+                .await
+                .unwrap();
+        }
+        sqlx::query(
+            "INSERT INTO symbol_changes (old_symbol, new_symbol, change_date) VALUES ('FB', 'META', '2024-06-09')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        insert_snapshot(&pool, "FB", "Facebook Inc", "USD", "2024-01-31").await;
+        insert_snapshot(&pool, "NKE", "Nike Inc", "USD", "2024-01-31").await;
+        // Stored on the requested date itself, e.g. by a run with today's profile
+        insert_snapshot(&pool, "NKE", "Nike (today)", "EUR", "2024-03-01").await;
+
+        let date = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let pit = PointInTime::load(&pool, date).await.unwrap();
+        assert_eq!(pit.universe.as_ref().unwrap().0, "2024-01-31");
+
+        let tickers: Vec<String> = ["META", "NKE", "NEW"].map(String::from).to_vec();
+        let (members, later) = pit.members(&tickers);
+        assert_eq!(members, vec!["META", "NKE"]);
+        assert_eq!(later, vec!["NEW"]);
+
+        let mut meta = market_cap("META", "Meta Platforms", "USD");
+        let resolution = pit.resolve(&mut meta);
+        assert_eq!(resolution.symbol_as_of, "FB");
+        assert_eq!(resolution.member, Some(true));
+        assert_eq!(meta.name, "Facebook Inc");
+        assert_eq!(resolution.name_source, Source::History);
+        assert!(resolution.unresolved().is_empty());
+
+        let mut nike = market_cap("NKE", "NIKE, Inc.", "USD");
+        pit.resolve(&mut nike);
+        assert_eq!(nike.name, "Nike Inc");
+    }
+
+    #[tokio::test]
+    async fn test_unresolved_fields_keep_current_values() {
+        let pool = db::create_db_pool("sqlite::memory:").await.unwrap();
+        let pit = PointInTime::load(&pool, NaiveDate::from_ymd_opt(2020, 1, 31).unwrap())
+            .await
+            .unwrap();
+
+        let mut lvmh = market_cap("MC.PA", "LVMH", "EUR");
+        let resolution = pit.resolve(&mut lvmh);
+        assert_eq!(lvmh.name, "LVMH");
+        assert_eq!(resolution.member, None);
+        assert_eq!(
+            resolution.unresolved(),
+            vec!["universe", "name", "currency"]
+        );
+        // Without a universe snapshot nobody is left out
+        assert_eq!(pit.members(&["MC.PA".to_string()]).0, vec!["MC.PA"]);
+    }
+
+    #[test]
+    fn test_symbol_as_of_follows_chains() {
+        let pit = PointInTime {
+            renames_after: vec![
+                ("B".to_string(), "C".to_string()),
+                ("A".to_string(), "B".to_string()),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(pit.symbol_as_of("C"), "A");
+        assert_eq!(pit.symbol_as_of("B"), "A");
+        assert_eq!(pit.symbol_as_of("X"), "X");
+    }
+
+    #[test]
+    fn test_export_resolutions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pit.csv");
+        let resolution = Resolution {
+            ticker: "META".to_string(),
+            symbol_as_of: "FB".to_string(),
+            member: None,
+            name: "Facebook Inc".to_string(),
+            name_source: Source::History,
+            currency: "USD".to_string(),
+            currency_source: Source::Current,
+        };
+        export_resolutions(&path, &[resolution]).unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert!(
+            written.contains("META,FB,unknown,Facebook Inc,history,USD,current,universe;currency")
+        );
+    }
+}
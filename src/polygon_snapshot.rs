@@ -0,0 +1,290 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! US snapshots from Polygon instead of FMP
+//!
+//! The market cap is Polygon's weighted shares outstanding times the latest
+//! split-adjusted daily close from its aggregates endpoint. `export-combined
+//! --provider polygon` fetches every US ticker this way, `[polygon] tickers`
+//! selects single tickers on every run. The FMP quote of the same ticker is
+//! still fetched, so the two providers can be cross-validated: market caps
+//! further apart than `[polygon] discrepancy_threshold_pct` are reported.
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, NaiveDate};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::api::PolygonClient;
+use crate::models::Details;
+
+/// Days before the snapshot date searched for the latest close
+const CLOSE_LOOKBACK_DAYS: i64 = 7;
+
+/// Provider of a run's snapshot (`export-combined --provider`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Provider {
+    #[default]
+    Fmp,
+    Polygon,
+}
+
+impl Provider {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "fmp" => Ok(Provider::Fmp),
+            "polygon" => Ok(Provider::Polygon),
+            other => anyhow::bail!("Unknown provider '{}': use fmp or polygon", other),
+        }
+    }
+}
+
+/// Tickers of `tickers` to fetch from Polygon: all US tickers with the
+/// Polygon provider, plus the ones listed in `[polygon] tickers`
+pub fn polygon_tickers(
+    provider: Provider,
+    tickers: &[String],
+    us_tickers: &[String],
+    configured: &[String],
+) -> Vec<String> {
+    tickers
+        .iter()
+        .filter(|ticker| {
+            (provider == Provider::Polygon && us_tickers.contains(ticker))
+                || configured.contains(ticker)
+        })
+        .cloned()
+        .collect()
+}
+
+/// Market cap of a ticker computed from Polygon data
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolygonMarketCap {
+    pub ticker: String,
+    pub name: Option<String>,
+    /// ISO code, upper case
+    pub currency: String,
+    /// FMP-style exchange name when the MIC is known (`NYSE`, `NASDAQ`)
+    pub exchange: Option<String>,
+    pub weighted_shares_outstanding: f64,
+    /// Shares of this share class only, when Polygon reports them
+    pub share_class_shares_outstanding: Option<f64>,
+    pub close: f64,
+    pub close_date: NaiveDate,
+    /// `weighted_shares_outstanding * close`
+    pub market_cap: f64,
+}
+
+impl PolygonMarketCap {
+    /// Combine the ticker details with the latest close on or before the snapshot date
+    pub fn from_parts(details: &Details, close: f64, close_date: NaiveDate) -> Result<Self> {
+        let shares = details
+            .weighted_shares_outstanding
+            .filter(|shares| *shares > 0.0)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Polygon has no weighted shares outstanding for {}",
+                    details.ticker
+                )
+            })?;
+        let exchange = details
+            .extra
+            .get("primary_exchange")
+            .and_then(Value::as_str)
+            .map(exchange_name);
+        Ok(Self {
+            ticker: details.ticker.clone(),
+            name: details.name.clone(),
+            currency: details
+                .currency_name
+                .as_deref()
+                .unwrap_or("usd")
+                .to_uppercase(),
+            exchange,
+            weighted_shares_outstanding: shares,
+            share_class_shares_outstanding: details
+                .extra
+                .get("share_class_shares_outstanding")
+                .and_then(Value::as_f64),
+            close,
+            close_date,
+            market_cap: shares * close,
+        })
+    }
+
+    /// Details to store as a `market_caps` row
+    pub fn to_details(&self) -> Details {
+        let mut extra = HashMap::new();
+        if let Some(exchange) = &self.exchange {
+            extra.insert("exchange".to_string(), Value::String(exchange.clone()));
+        }
+        if let Some(price) = serde_json::Number::from_f64(self.close) {
+            extra.insert("price".to_string(), Value::Number(price));
+        }
+        Details {
+            ticker: self.ticker.clone(),
+            market_cap: Some(self.market_cap),
+            name: self.name.clone(),
+            currency_name: Some(self.currency.clone()),
+            currency_symbol: Some(self.currency.clone()),
+            active: Some(true),
+            description: None,
+            homepage_url: None,
+            weighted_shares_outstanding: Some(self.weighted_shares_outstanding),
+            employees: None,
+            revenue: None,
+            revenue_usd: None,
+            timestamp: Some(self.close_date.to_string()),
+            ceo: None,
+            country: None,
+            industry: None,
+            isin: None,
+            lei: None,
+            working_capital_ratio: None,
+            quick_ratio: None,
+            eps: None,
+            pe_ratio: None,
+            debt_equity_ratio: None,
+            roe: None,
+            extra,
+        }
+    }
+}
+
+/// FMP's name of the exchange with this MIC, or the MIC itself
+fn exchange_name(mic: &str) -> String {
+    match mic {
+        "XNYS" => "NYSE",
+        "XNAS" => "NASDAQ",
+        "XASE" => "AMEX",
+        "ARCX" => "NYSE Arca",
+        other => other,
+    }
+    .to_string()
+}
+
+/// Market cap of `ticker` at `date` from the Polygon ticker details and
+/// the latest daily close within a week before it
+pub async fn fetch_market_cap(
+    client: &PolygonClient,
+    ticker: &str,
+    date: NaiveDate,
+) -> Result<PolygonMarketCap> {
+    let details = client.get_details(ticker, date).await?;
+    let bars = client
+        .get_daily_bars(ticker, date - Duration::days(CLOSE_LOOKBACK_DAYS), date)
+        .await?;
+    let bar = bars
+        .iter()
+        .max_by_key(|bar| bar.timestamp_ms)
+        .ok_or_else(|| {
+            anyhow::anyhow!("Polygon has no daily close for {} before {}", ticker, date)
+        })?;
+    let close_date = DateTime::from_timestamp_millis(bar.timestamp_ms)
+        .ok_or_else(|| anyhow::anyhow!("Invalid bar timestamp {}", bar.timestamp_ms))?
+        .date_naive();
+    PolygonMarketCap::from_parts(&details, bar.close, close_date)
+}
+
+/// Polygon and FMP market caps of a ticker that disagree
+#[derive(Debug, Clone, PartialEq)]
+pub struct Discrepancy {
+    pub ticker: String,
+    pub polygon: f64,
+    pub fmp: f64,
+    /// Polygon relative to FMP, in percent
+    pub diff_pct: f64,
+}
+
+/// Difference of the Polygon market cap relative to FMP's, in percent
+pub fn diff_pct(polygon: f64, fmp: f64) -> Option<f64> {
+    (fmp > 0.0).then(|| (polygon - fmp) / fmp * 100.0)
+}
+
+/// Tickers whose market caps differ by more than `threshold_pct`, largest difference first
+pub fn find_discrepancies(pairs: &[(String, f64, f64)], threshold_pct: f64) -> Vec<Discrepancy> {
+    let mut discrepancies: Vec<Discrepancy> = pairs
+        .iter()
+        .filter_map(|(ticker, polygon, fmp)| {
+            let diff_pct = diff_pct(*polygon, *fmp)?;
+            (diff_pct.abs() > threshold_pct).then(|| Discrepancy {
+                ticker: ticker.clone(),
+                polygon: *polygon,
+                fmp: *fmp,
+                diff_pct,
+            })
+        })
+        .collect();
+    discrepancies.sort_by(|a, b| b.diff_pct.abs().total_cmp(&a.diff_pct.abs()));
+    discrepancies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn details(json: Value) -> Details {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn test_market_cap_from_shares_and_close() {
+        let details = details(serde_json::json!({
+            "ticker": "NKE",
+            "name": "Nike Inc.",
+            "currency_name": "usd",
+            "primary_exchange": "XNYS",
+            "weighted_shares_outstanding": 1_500_000_000.0,
+            "market_cap": 1.0
+        }));
+        let date = NaiveDate::from_ymd_opt(2025, 8, 1).unwrap();
+        let snapshot = PolygonMarketCap::from_parts(&details, 70.0, date).unwrap();
+        assert_eq!(snapshot.market_cap, 105_000_000_000.0);
+        assert_eq!(snapshot.currency, "USD");
+        assert_eq!(snapshot.exchange.as_deref(), Some("NYSE"));
+
+        let stored = snapshot.to_details();
+        assert_eq!(stored.market_cap, Some(105_000_000_000.0));
+        assert_eq!(stored.extra["exchange"], "NYSE");
+        assert_eq!(stored.extra["price"], 70.0);
+
+        let without_shares = details_without_shares();
+        assert!(PolygonMarketCap::from_parts(&without_shares, 70.0, date).is_err());
+    }
+
+    fn details_without_shares() -> Details {
+        details(serde_json::json!({ "ticker": "XYZ", "market_cap": 1.0 }))
+    }
+
+    #[test]
+    fn test_polygon_tickers_per_run_and_per_ticker() {
+        let tickers: Vec<String> = ["MC.PA", "NKE", "TJX"].map(String::from).to_vec();
+        let us: Vec<String> = ["NKE", "TJX"].map(String::from).to_vec();
+        let configured = vec!["TJX".to_string()];
+        assert_eq!(
+            polygon_tickers(Provider::Fmp, &tickers, &us, &configured),
+            vec!["TJX"]
+        );
+        assert_eq!(
+            polygon_tickers(Provider::Polygon, &tickers, &us, &[]),
+            vec!["NKE", "TJX"]
+        );
+        assert_eq!(Provider::parse("Polygon").unwrap(), Provider::Polygon);
+        assert!(Provider::parse("yahoo").is_err());
+    }
+
+    #[test]
+    fn test_find_discrepancies() {
+        let pairs = vec![
+            ("NKE".to_string(), 104.0, 100.0),
+            ("TJX".to_string(), 80.0, 100.0),
+            ("VFC".to_string(), 10.0, 0.0),
+            ("GPS".to_string(), 110.0, 100.0),
+        ];
+        let found = find_discrepancies(&pairs, 5.0);
+        let tickers: Vec<&str> = found.iter().map(|d| d.ticker.as_str()).collect();
+        assert_eq!(tickers, vec!["TJX", "GPS"]);
+        assert_eq!(found[0].diff_pct, -20.0);
+    }
+}
@@ -0,0 +1,153 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Progress bars shared by the long-running commands (`--quiet` hides them)
+//!
+//! All bars of a run are drawn by one `MultiProgress`, so a per-ticker bar
+//! can run below an overall bar (e.g. months of a historical fetch) without
+//! the two overwriting each other. Fetch bars estimate the time left from
+//! the FMP quota in `[api]` as well as from the observed pace: early in a
+//! run, or when the limiter is about to start throttling, the observed pace
+//! alone is far too optimistic.
+
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressState, ProgressStyle};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::config;
+
+static QUIET: OnceLock<bool> = OnceLock::new();
+static MULTI: OnceLock<MultiProgress> = OnceLock::new();
+
+/// Hide progress output for this run (`--quiet`); later calls are ignored
+pub fn init(quiet: bool) {
+    let _ = QUIET.set(quiet);
+}
+
+/// Whether progress output is hidden
+pub fn quiet() -> bool {
+    QUIET.get().copied().unwrap_or(false)
+}
+
+fn multi() -> &'static MultiProgress {
+    MULTI.get_or_init(|| {
+        if quiet() {
+            MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+        } else {
+            MultiProgress::new()
+        }
+    })
+}
+
+/// Time left for `remaining` items: the observed estimate, but never less
+/// than the quota allows for `requests_per_item` API requests per item
+pub fn eta(
+    remaining: u64,
+    requests_per_item: u32,
+    requests_per_minute: u32,
+    observed: Duration,
+) -> Duration {
+    let requests = remaining * requests_per_item as u64;
+    let quota = Duration::from_secs_f64(requests as f64 * 60.0 / requests_per_minute.max(1) as f64);
+    observed.max(quota)
+}
+
+/// Format a duration as `1h02m`, `3m05s` or `12s`
+fn format_eta(eta: Duration) -> String {
+    let secs = eta.as_secs();
+    match (secs / 3600, secs % 3600 / 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m{:02}s", m, s),
+        (h, m, _) => format!("{}h{:02}m", h, m),
+    }
+}
+
+fn add(bar: ProgressBar, label: &str) -> ProgressBar {
+    let bar = multi().add(bar);
+    bar.set_prefix(label.to_string());
+    bar
+}
+
+/// Bar over API fetches of `len` items, each costing `requests_per_item`
+/// FMP requests, with an ETA that respects the FMP quota
+pub fn fetch_bar(len: u64, label: &str, requests_per_item: u32) -> ProgressBar {
+    let per_minute = config::load_api_config().fmp_requests_per_minute;
+    let style = ProgressStyle::default_bar()
+        .template(
+            "{prefix:>12} [{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} ETA {quota_eta} {msg}",
+        )
+        .unwrap()
+        .with_key(
+            "quota_eta",
+            move |state: &ProgressState, w: &mut dyn std::fmt::Write| {
+                let remaining = state.len().unwrap_or(0).saturating_sub(state.pos());
+                let eta = eta(remaining, requests_per_item, per_minute, state.eta());
+                let _ = w.write_str(&format_eta(eta));
+            },
+        )
+        .progress_chars("=>-");
+    add(ProgressBar::new(len).with_style(style), label)
+}
+
+/// Bar over `len` local steps (reading files, computing a report)
+pub fn step_bar(len: u64, label: &str) -> ProgressBar {
+    let style = ProgressStyle::default_bar()
+        .template("{prefix:>12} [{elapsed_precise}] {bar:40.cyan/blue} {msg}")
+        .unwrap()
+        .progress_chars("=>-");
+    add(ProgressBar::new(len).with_style(style), label)
+}
+
+/// Print a line above the bars (or nothing under `--quiet`); use this
+/// instead of `println!` for per-item messages while a bar is shown
+pub fn println(message: impl AsRef<str>) {
+    if quiet() {
+        return;
+    }
+    let _ = multi().println(message);
+}
+
+/// Run `f` with the bars cleared, for output that must also appear under
+/// `--quiet` (errors) without being overwritten by a bar
+pub fn suspend<R>(f: impl FnOnce() -> R) -> R {
+    multi().suspend(f)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eta_respects_quota() {
+        // 100 tickers x 4 requests at 300 per minute take at least 80s
+        assert_eq!(
+            eta(100, 4, 300, Duration::from_secs(10)),
+            Duration::from_secs(80)
+        );
+        // A slower observed pace wins
+        assert_eq!(
+            eta(100, 4, 300, Duration::from_secs(200)),
+            Duration::from_secs(200)
+        );
+        assert_eq!(eta(0, 4, 300, Duration::ZERO), Duration::ZERO);
+        // A zero quota is treated as one request per minute instead of dividing by zero
+        assert_eq!(eta(2, 1, 0, Duration::ZERO), Duration::from_secs(120));
+    }
+
+    #[test]
+    fn test_format_eta() {
+        assert_eq!(format_eta(Duration::from_secs(12)), "12s");
+        assert_eq!(format_eta(Duration::from_secs(185)), "3m05s");
+        assert_eq!(format_eta(Duration::from_secs(3720)), "1h02m");
+    }
+
+    #[test]
+    fn test_bars_count_and_finish() {
+        let bar = step_bar(3, "test");
+        bar.inc(2);
+        assert_eq!(bar.position(), 2);
+        bar.finish_and_clear();
+        assert!(bar.is_finished());
+    }
+}
@@ -0,0 +1,128 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Python bindings (`top200` module, `--features python`)
+//!
+//! Wraps the comparison and trend analysis of [`crate::client`] so they can be
+//! called from Python without running the binary and parsing its CSVs. Rows
+//! come back as lists of dicts, ready for `pandas.DataFrame(rows)`; comparison
+//! rows use the column names of the comparison CSV. Build with
+//! `maturin develop --release` (see `pyproject.toml`).
+
+use anyhow::Result;
+use chrono::NaiveDate;
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyDict, PyList};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{client, db};
+
+fn parse_date(value: &str) -> PyResult<NaiveDate> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|e| PyValueError::new_err(format!("Invalid date {}: {}", value, e)))
+}
+
+/// Run `f` against the database on a fresh runtime, with the GIL released
+fn with_pool<T, F, Fut>(py: Python<'_>, database_url: Option<String>, f: F) -> PyResult<T>
+where
+    T: Send,
+    F: FnOnce(sqlx::SqlitePool) -> Fut + Send,
+    Fut: Future<Output = Result<T>>,
+{
+    py.allow_threads(|| {
+        let database_url = database_url
+            .or_else(|| std::env::var("DATABASE_URL").ok())
+            .unwrap_or_else(|| "sqlite:data.db".to_string());
+        let runtime = tokio::runtime::Runtime::new()?;
+        runtime.block_on(async {
+            let pool = db::create_db_pool(&database_url).await?;
+            f(pool).await
+        })
+    })
+    .map_err(|e| PyRuntimeError::new_err(format!("{:#}", e)))
+}
+
+/// Python value of a JSON value: objects become dicts, arrays lists
+fn to_py<'py>(py: Python<'py>, value: &Value) -> PyResult<Bound<'py, PyAny>> {
+    Ok(match value {
+        Value::Null => py.None().into_bound(py),
+        Value::Bool(b) => PyBool::new(py, *b).to_owned().into_any(),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => i.into_pyobject(py)?.into_any(),
+            None => n.as_f64().unwrap_or(f64::NAN).into_pyobject(py)?.into_any(),
+        },
+        Value::String(s) => s.into_pyobject(py)?.into_any(),
+        Value::Array(items) => {
+            let list = PyList::empty(py);
+            for item in items {
+                list.append(to_py(py, item)?)?;
+            }
+            list.into_any()
+        }
+        Value::Object(fields) => {
+            let dict = PyDict::new(py);
+            for (key, item) in fields {
+                dict.set_item(key, to_py(py, item)?)?;
+            }
+            dict.into_any()
+        }
+    })
+}
+
+/// Python value of anything that serializes, through its JSON form
+fn serialize_to_py<'py>(py: Python<'py>, value: &impl Serialize) -> PyResult<Bound<'py, PyAny>> {
+    let json = serde_json::to_value(value).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    to_py(py, &json)
+}
+
+/// compare(from_date, to_date, database_url=None) -> list[dict]
+///
+/// One dict per company, largest percentage gain first
+#[pyfunction]
+#[pyo3(signature = (from_date, to_date, database_url=None))]
+fn compare<'py>(
+    py: Python<'py>,
+    from_date: &str,
+    to_date: &str,
+    database_url: Option<String>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let (from, to) = (parse_date(from_date)?, parse_date(to_date)?);
+    let comparison = with_pool(py, database_url, |pool| async move {
+        client::compare_snapshots(&pool, from, to).await
+    })?;
+    serialize_to_py(py, &comparison.rows)
+}
+
+/// trends(dates, database_url=None) -> dict
+///
+/// `{"trends": [...], "summary": {...}}`, one trend per company with its
+/// data points per date
+#[pyfunction]
+#[pyo3(signature = (dates, database_url=None))]
+fn trends<'py>(
+    py: Python<'py>,
+    dates: Vec<String>,
+    database_url: Option<String>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let dates = dates
+        .iter()
+        .map(|date| parse_date(date))
+        .collect::<PyResult<Vec<_>>>()?;
+    let (trends, summary) = with_pool(py, database_url, |pool| async move {
+        client::trends(&pool, &dates).await
+    })?;
+    let result = PyDict::new(py);
+    result.set_item("trends", serialize_to_py(py, &trends)?)?;
+    result.set_item("summary", serialize_to_py(py, &summary)?)?;
+    Ok(result.into_any())
+}
+
+#[pymodule]
+fn top200(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(compare, m)?)?;
+    m.add_function(wrap_pyfunction!(trends, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,471 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Rank history per company across all stored market cap snapshots, and the
+//! ranking rules shared by exports and comparisons
+
+use crate::chart_theme;
+use crate::config::{self, OutputConfig};
+use crate::vega;
+use anyhow::Result;
+use chrono::DateTime;
+use csv::Writer;
+use plotters::prelude::*;
+use sqlx::Row;
+use sqlx::sqlite::SqlitePool;
+use std::cmp::Ordering;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// A company's position in one snapshot
+#[derive(Debug, Clone, PartialEq)]
+pub struct RankPoint {
+    pub date: String,
+    pub timestamp: i64,
+    pub rank: i64,
+    pub market_cap_eur: Option<f64>,
+    pub market_cap_usd: Option<f64>,
+}
+
+/// Number of entries in the "Top N" sections of reports and charts
+pub const SECTION_SIZE: usize = 10;
+
+static TOP: OnceLock<Option<usize>> = OnceLock::new();
+
+/// Limit exports and comparisons to the `top` largest companies for this
+/// run; later calls are ignored
+pub fn init_top(top: Option<usize>) -> Result<()> {
+    if top == Some(0) {
+        anyhow::bail!("--top must be at least 1");
+    }
+    let _ = TOP.set(top);
+    Ok(())
+}
+
+/// The `--top` limit of this run, if any
+pub fn top() -> Option<usize> {
+    TOP.get().copied().flatten()
+}
+
+/// Keep the first `--top` entries of a list that is already in rank order
+pub fn truncate_to_top<T>(items: &mut Vec<T>) {
+    if let Some(top) = top() {
+        items.truncate(top);
+    }
+}
+
+/// Length of the "Top N" report sections: 10, or fewer under a smaller `--top`
+pub fn section_size() -> usize {
+    top().map_or(SECTION_SIZE, |top| top.min(SECTION_SIZE))
+}
+
+/// Ranking order: larger values first, missing ones last. Ties are broken by
+/// name and then ticker so ranks do not change between runs.
+pub fn rank_order(a: (Option<f64>, &str, &str), b: (Option<f64>, &str, &str)) -> Ordering {
+    let value = |v: Option<f64>| v.filter(|v| !v.is_nan()).unwrap_or(f64::NEG_INFINITY);
+    value(b.0)
+        .partial_cmp(&value(a.0))
+        .unwrap_or(Ordering::Equal)
+        .then_with(|| a.1.cmp(b.1))
+        .then_with(|| a.2.cmp(b.2))
+}
+
+fn date_label(timestamp: i64) -> String {
+    DateTime::from_timestamp(timestamp, 0)
+        .map(|dt| dt.format("%Y-%m-%d").to_string())
+        .unwrap_or_else(|| timestamp.to_string())
+}
+
+/// Compute and store the ranks for the snapshot at `timestamp`.
+/// Companies are ranked by EUR market cap, like the CSV exports.
+pub async fn record_rankings(pool: &SqlitePool, timestamp: i64) -> Result<usize> {
+    let rows = sqlx::query(
+        r#"
+        SELECT ticker,
+            CAST(market_cap_eur AS REAL) as market_cap_eur,
+            CAST(market_cap_usd AS REAL) as market_cap_usd
+        FROM market_caps
+        WHERE timestamp = ?
+        ORDER BY market_cap_eur DESC, name, ticker
+        "#,
+    )
+    .bind(timestamp)
+    .fetch_all(pool)
+    .await?;
+
+    let date = date_label(timestamp);
+    let mut tx = pool.begin().await?;
+    for (index, row) in rows.iter().enumerate() {
+        sqlx::query(
+            r#"
+            INSERT OR REPLACE INTO rankings (ticker, date, timestamp, rank, market_cap_eur, market_cap_usd)
+            VALUES (?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(row.get::<String, _>("ticker"))
+        .bind(&date)
+        .bind(timestamp)
+        .bind(index as i64 + 1)
+        .bind(row.get::<Option<f64>, _>("market_cap_eur"))
+        .bind(row.get::<Option<f64>, _>("market_cap_usd"))
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok(rows.len())
+}
+
+/// Record the ranks for the most recent snapshot
+pub async fn record_latest_rankings(pool: &SqlitePool) -> Result<()> {
+    let latest: Option<i64> = sqlx::query_scalar("SELECT MAX(timestamp) FROM market_caps")
+        .fetch_one(pool)
+        .await?;
+    if let Some(timestamp) = latest {
+        let count = record_rankings(pool, timestamp).await?;
+        println!("✅ Rankings recorded for {} companies", count);
+    }
+    Ok(())
+}
+
+/// Record ranks for snapshots stored before rankings were tracked
+pub async fn backfill_rankings(pool: &SqlitePool) -> Result<usize> {
+    let missing: Vec<i64> = sqlx::query_scalar(
+        r#"
+        SELECT DISTINCT timestamp FROM market_caps
+        WHERE timestamp NOT IN (SELECT DISTINCT timestamp FROM rankings)
+        ORDER BY timestamp
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for timestamp in &missing {
+        record_rankings(pool, *timestamp).await?;
+    }
+    Ok(missing.len())
+}
+
+/// Rank and market cap trajectory of a ticker, oldest first
+pub async fn get_rank_history(pool: &SqlitePool, ticker: &str) -> Result<Vec<RankPoint>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT date, timestamp, rank, market_cap_eur, market_cap_usd
+        FROM rankings
+        WHERE ticker = ?
+        ORDER BY timestamp
+        "#,
+    )
+    .bind(ticker)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .iter()
+        .map(|row| RankPoint {
+            date: row.get("date"),
+            timestamp: row.get("timestamp"),
+            rank: row.get("rank"),
+            market_cap_eur: row.get("market_cap_eur"),
+            market_cap_usd: row.get("market_cap_usd"),
+        })
+        .collect())
+}
+
+fn format_billions(value: Option<f64>) -> String {
+    value
+        .map(|v| format!("{:.2}B", v / 1_000_000_000.0))
+        .unwrap_or_else(|| "NA".to_string())
+}
+
+fn export_rank_history_csv(
+    ticker: &str,
+    history: &[RankPoint],
+    output: &OutputConfig,
+) -> Result<String> {
+    let filename = output
+        .file_path("rank_history", ticker, "csv")
+        .display()
+        .to_string();
+    let mut writer = Writer::from_path(&filename)?;
+
+    writer.write_record([
+        "Date",
+        "Rank",
+        "Rank Change",
+        "Market Cap (EUR)",
+        "Market Cap (USD)",
+    ])?;
+    let mut previous_rank = None;
+    for point in history {
+        let rank_change = previous_rank
+            .map(|prev: i64| {
+                let change = prev - point.rank;
+                if change > 0 {
+                    format!("+{}", change)
+                } else {
+                    change.to_string()
+                }
+            })
+            .unwrap_or_else(|| "NA".to_string());
+        writer.write_record(&[
+            point.date.clone(),
+            point.rank.to_string(),
+            rank_change,
+            point
+                .market_cap_eur
+                .map(|v| format!("{:.0}", v))
+                .unwrap_or_default(),
+            point
+                .market_cap_usd
+                .map(|v| format!("{:.0}", v))
+                .unwrap_or_default(),
+        ])?;
+        previous_rank = Some(point.rank);
+    }
+    writer.flush()?;
+
+    Ok(filename)
+}
+
+/// Plot the rank (top) and EUR market cap (bottom) of a ticker's history
+pub(crate) fn create_rank_history_chart(
+    ticker: &str,
+    history: &[RankPoint],
+    path: &Path,
+) -> Result<()> {
+    let filename = path.display().to_string();
+    if vega::enabled() {
+        let points: Vec<(String, i64, Option<f64>)> = history
+            .iter()
+            .map(|p| {
+                let cap = p.market_cap_eur.map(|v| v / 1_000_000_000.0);
+                (p.date.clone(), p.rank, cap)
+            })
+            .collect();
+        let spec = vega::rank_history(&format!("{}: rank history", ticker), &points);
+        vega::write_spec(Path::new(&filename), &spec)?;
+        println!("✅ Generated rank history chart: {}", filename);
+        return Ok(());
+    }
+    let theme = chart_theme::current();
+    let root = SVGBackend::new(&filename, (1200, 800)).into_drawing_area();
+    root.fill(&theme.background)?;
+    let (upper, lower) = root.split_vertically(400);
+
+    let x_max = history.len().max(2) as i32 - 1;
+    let label = |x: &i32| {
+        history
+            .get(*x as usize)
+            .map(|p| p.date.clone())
+            .unwrap_or_default()
+    };
+
+    // Ranks are plotted negated so that #1 is at the top
+    let worst_rank = history.iter().map(|p| p.rank).max().unwrap_or(1);
+    let mut rank_chart = ChartBuilder::on(&upper)
+        .caption(format!("{}: rank history", ticker), theme.text_style(28))
+        .margin(20)
+        .x_label_area_size(40)
+        .y_label_area_size(60)
+        .build_cartesian_2d(0..x_max, -(worst_rank + 1)..0i64)?;
+    rank_chart
+        .configure_mesh()
+        .x_label_formatter(&label)
+        .y_label_formatter(&|v| format!("#{}", -v))
+        .label_style(theme.text_style(12))
+        .axis_style(theme.muted)
+        .bold_line_style(theme.subtle)
+        .light_line_style(theme.subtle.mix(0.5))
+        .draw()?;
+    rank_chart.draw_series(LineSeries::new(
+        history.iter().enumerate().map(|(i, p)| (i as i32, -p.rank)),
+        theme.primary.stroke_width(3),
+    ))?;
+    rank_chart.draw_series(
+        history
+            .iter()
+            .enumerate()
+            .map(|(i, p)| Circle::new((i as i32, -p.rank), 4, theme.primary.filled())),
+    )?;
+
+    let caps: Vec<f64> = history
+        .iter()
+        .map(|p| p.market_cap_eur.unwrap_or(0.0) / 1_000_000_000.0)
+        .collect();
+    let cap_max = caps.iter().cloned().fold(0.0, f64::max).max(1.0) * 1.1;
+    let mut cap_chart = ChartBuilder::on(&lower)
+        .caption("Market cap (EUR billions)", theme.text_style(22))
+        .margin(20)
+        .x_label_area_size(40)
+        .y_label_area_size(60)
+        .build_cartesian_2d(0..x_max, 0.0..cap_max)?;
+    cap_chart
+        .configure_mesh()
+        .x_label_formatter(&label)
+        .label_style(theme.text_style(12))
+        .axis_style(theme.muted)
+        .bold_line_style(theme.subtle)
+        .light_line_style(theme.subtle.mix(0.5))
+        .draw()?;
+    cap_chart.draw_series(LineSeries::new(
+        caps.iter().enumerate().map(|(i, v)| (i as i32, *v)),
+        theme.positive.stroke_width(3),
+    ))?;
+
+    root.present()?;
+    theme.apply_watermark(Path::new(&filename), root.dim_in_pixel())?;
+    println!("✅ Generated rank history chart: {}", filename);
+
+    Ok(())
+}
+
+/// Print, export and plot how a ticker's rank evolved
+pub async fn show_rank_history(pool: &SqlitePool, ticker: &str) -> Result<()> {
+    let backfilled = backfill_rankings(pool).await?;
+    if backfilled > 0 {
+        println!("Computed rankings for {} earlier snapshots", backfilled);
+    }
+
+    let history = get_rank_history(pool, ticker).await?;
+    if history.is_empty() {
+        anyhow::bail!("No stored market caps found for {}", ticker);
+    }
+
+    println!("\n📈 Rank history for {}:", ticker);
+    println!("{:<12} {:>6} {:>12} {:>12}", "Date", "Rank", "EUR", "USD");
+    for point in &history {
+        println!(
+            "{:<12} {:>6} {:>12} {:>12}",
+            point.date,
+            format!("#{}", point.rank),
+            format_billions(point.market_cap_eur),
+            format_billions(point.market_cap_usd)
+        );
+    }
+
+    let output = config::load_output_config();
+    output.ensure_directory()?;
+    let csv_file = export_rank_history_csv(ticker, &history, &output)?;
+    println!("✅ Rank history exported to {}", csv_file);
+    create_rank_history_chart(
+        ticker,
+        &history,
+        &output.named_path(&format!("rank_history_{}.{}", ticker, vega::extension())),
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    async fn insert_market_cap(pool: &SqlitePool, ticker: &str, eur: f64, timestamp: i64) {
+        sqlx::query(
+            "INSERT INTO market_caps (ticker, name, market_cap_eur, market_cap_usd, timestamp)
+             VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(ticker)
+        .bind(ticker)
+        .bind(eur)
+        .bind(eur * 1.1)
+        .bind(timestamp)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rank_history_across_snapshots() {
+        let pool = db::create_db_pool("sqlite::memory:").await.unwrap();
+        let day1 = 1_735_689_600; // 2025-01-01
+        let day2 = day1 + 86_400;
+
+        insert_market_cap(&pool, "NKE", 100.0, day1).await;
+        insert_market_cap(&pool, "TJX", 200.0, day1).await;
+        insert_market_cap(&pool, "NKE", 300.0, day2).await;
+        insert_market_cap(&pool, "TJX", 200.0, day2).await;
+
+        assert_eq!(record_rankings(&pool, day1).await.unwrap(), 2);
+        // Only the second snapshot is still missing
+        assert_eq!(backfill_rankings(&pool).await.unwrap(), 1);
+
+        let history = get_rank_history(&pool, "NKE").await.unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].date, "2025-01-01");
+        assert_eq!(history[0].rank, 2);
+        assert_eq!(history[1].rank, 1);
+        assert_eq!(history[1].market_cap_eur, Some(300.0));
+    }
+
+    #[test]
+    fn test_export_rank_history_csv() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = OutputConfig {
+            directory: dir.path().display().to_string(),
+            ..OutputConfig::default()
+        };
+        let point = |date: &str, rank| RankPoint {
+            date: date.to_string(),
+            timestamp: 0,
+            rank,
+            market_cap_eur: Some(1e9),
+            market_cap_usd: None,
+        };
+
+        let file = export_rank_history_csv(
+            "NKE",
+            &[point("2025-01-01", 5), point("2025-02-01", 3)],
+            &output,
+        )
+        .unwrap();
+        let contents = std::fs::read_to_string(file).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+
+        assert_eq!(lines[1], "2025-01-01,5,NA,1000000000,");
+        assert_eq!(lines[2], "2025-02-01,3,+2,1000000000,");
+    }
+
+    #[test]
+    fn test_rank_order_breaks_ties_by_name_then_ticker() {
+        let mut companies = [
+            (Some(100.0), "Zara", "ITX.MC"),
+            (None, "Missing", "MISS"),
+            (Some(300.0), "Nike", "NKE"),
+            (Some(100.0), "Adidas", "ADS.DE"),
+            (Some(100.0), "Adidas", "ADDYY"),
+            (Some(f64::NAN), "Broken", "NAN"),
+        ];
+        companies.sort_by(|a, b| rank_order(*a, *b));
+        let tickers: Vec<&str> = companies.iter().map(|c| c.2).collect();
+        assert_eq!(
+            tickers,
+            vec!["NKE", "ADDYY", "ADS.DE", "ITX.MC", "NAN", "MISS"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_record_rankings_is_stable_for_equal_market_caps() {
+        let pool = db::create_db_pool("sqlite::memory:").await.unwrap();
+        let day = 1_735_689_600;
+        for (ticker, name) in [("ZZZ", "Alpha"), ("AAA", "Beta")] {
+            sqlx::query(
+                "INSERT INTO market_caps (ticker, name, market_cap_eur, market_cap_usd, timestamp)
+                 VALUES (?, ?, 100.0, 110.0, ?)",
+            )
+            .bind(ticker)
+            .bind(name)
+            .bind(day)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        record_rankings(&pool, day).await.unwrap();
+        assert_eq!(get_rank_history(&pool, "ZZZ").await.unwrap()[0].rank, 1);
+        assert_eq!(get_rank_history(&pool, "AAA").await.unwrap()[0].rank, 2);
+    }
+}
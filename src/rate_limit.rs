@@ -0,0 +1,233 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Token bucket rate limiting for API clients
+//!
+//! The bucket holds up to `burst` tokens and refills at the per-minute quota.
+//! Every request takes one token; when the bucket is empty the caller sleeps
+//! until the next token is due, so the long-run rate never exceeds the quota.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+
+use crate::config;
+
+/// Token bucket state, driven by explicit instants so the pacing is testable
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    refill_per_sec: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket allowing `per_minute` requests per minute and bursts of `burst`
+    pub fn new(per_minute: u32, burst: u32, now: Instant) -> Self {
+        let capacity = burst.max(1) as f64;
+        Self {
+            capacity,
+            tokens: capacity,
+            refill_per_sec: per_minute.max(1) as f64 / 60.0,
+            last_refill: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    /// Take a token, or return how long to wait until one is available
+    pub fn try_acquire(&mut self, now: Instant) -> Result<(), Duration> {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            let missing = 1.0 - self.tokens;
+            Err(Duration::from_secs_f64(missing / self.refill_per_sec))
+        }
+    }
+}
+
+/// Counters describing how much a limiter has throttled
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RateLimiterStats {
+    /// Requests that passed the limiter
+    pub requests: u64,
+    /// Requests that had to wait for a token
+    pub throttled: u64,
+    /// Total time spent waiting for tokens
+    pub waited: Duration,
+    /// Rate limit errors still returned by the API
+    pub rejected: u64,
+}
+
+/// Async token bucket limiter, shareable between clients
+#[derive(Debug)]
+pub struct RateLimiter {
+    bucket: Mutex<TokenBucket>,
+    requests: AtomicU64,
+    throttled: AtomicU64,
+    waited_micros: AtomicU64,
+    rejected: AtomicU64,
+}
+
+impl RateLimiter {
+    pub fn new(per_minute: u32, burst: u32) -> Self {
+        Self {
+            bucket: Mutex::new(TokenBucket::new(per_minute, burst, Instant::now())),
+            requests: AtomicU64::new(0),
+            throttled: AtomicU64::new(0),
+            waited_micros: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+        }
+    }
+
+    /// Wait until a request may be sent
+    pub async fn acquire(&self) {
+        let mut throttled = false;
+        loop {
+            let wait = match self.bucket.lock().unwrap().try_acquire(Instant::now()) {
+                Ok(()) => break,
+                Err(wait) => wait,
+            };
+            throttled = true;
+            self.waited_micros
+                .fetch_add(wait.as_micros() as u64, Ordering::Relaxed);
+            sleep(wait).await;
+        }
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        if throttled {
+            self.throttled.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Record a rate limit error returned by the API despite the pacing
+    pub fn record_rejection(&self) {
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> RateLimiterStats {
+        RateLimiterStats {
+            requests: self.requests.load(Ordering::Relaxed),
+            throttled: self.throttled.load(Ordering::Relaxed),
+            waited: Duration::from_micros(self.waited_micros.load(Ordering::Relaxed)),
+            rejected: self.rejected.load(Ordering::Relaxed),
+        }
+    }
+}
+
+static FMP_LIMITER: OnceLock<Arc<RateLimiter>> = OnceLock::new();
+
+/// The process-wide FMP limiter, configured from `[api]` in config.toml
+pub fn fmp_limiter() -> Arc<RateLimiter> {
+    FMP_LIMITER
+        .get_or_init(|| {
+            let api = config::load_api_config();
+            Arc::new(RateLimiter::new(api.fmp_requests_per_minute, api.fmp_burst))
+        })
+        .clone()
+}
+
+/// Print FMP throttling metrics if any requests were made this run
+pub fn print_fmp_stats() {
+    let Some(limiter) = FMP_LIMITER.get() else {
+        return;
+    };
+    let stats = limiter.stats();
+    if stats.requests == 0 {
+        return;
+    }
+    println!(
+        "📊 FMP requests: {} ({} throttled, {:.1}s waiting, {} rate limit errors)",
+        stats.requests,
+        stats.throttled,
+        stats.waited.as_secs_f64(),
+        stats.rejected
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_allows_burst_then_paces() {
+        let start = Instant::now();
+        // 60 per minute = one token per second
+        let mut bucket = TokenBucket::new(60, 3, start);
+
+        for _ in 0..3 {
+            assert!(bucket.try_acquire(start).is_ok());
+        }
+        let wait = bucket.try_acquire(start).unwrap_err();
+        assert!((wait.as_secs_f64() - 1.0).abs() < 1e-6);
+
+        // Half a token later the wait halves
+        let wait = bucket
+            .try_acquire(start + Duration::from_millis(500))
+            .unwrap_err();
+        assert!((wait.as_secs_f64() - 0.5).abs() < 1e-6);
+
+        assert!(bucket.try_acquire(start + Duration::from_secs(1)).is_ok());
+    }
+
+    #[test]
+    fn test_bucket_never_exceeds_capacity() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(600, 2, start);
+        let later = start + Duration::from_secs(3600);
+
+        assert!(bucket.try_acquire(later).is_ok());
+        assert!(bucket.try_acquire(later).is_ok());
+        assert!(bucket.try_acquire(later).is_err());
+    }
+
+    #[test]
+    fn test_bucket_rate_over_a_minute() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(300, 10, start);
+        let mut granted = 0;
+        // Try every 10ms for one minute
+        for step in 0..6000 {
+            if bucket
+                .try_acquire(start + Duration::from_millis(step * 10))
+                .is_ok()
+            {
+                granted += 1;
+            }
+        }
+        // The quota plus the initial burst
+        assert!((300..=310).contains(&granted), "granted {}", granted);
+    }
+
+    #[tokio::test]
+    async fn test_limiter_paces_requests() {
+        // 1200 per minute = one token every 50ms
+        let limiter = RateLimiter::new(1200, 2);
+        let started = Instant::now();
+        for _ in 0..5 {
+            limiter.acquire().await;
+        }
+        let elapsed = started.elapsed();
+
+        // Two burst tokens, then three paced ones
+        assert!(
+            elapsed >= Duration::from_millis(140),
+            "elapsed {:?}",
+            elapsed
+        );
+        let stats = limiter.stats();
+        assert_eq!(stats.requests, 5);
+        assert_eq!(stats.throttled, 3);
+        assert!(stats.waited >= Duration::from_millis(140));
+    }
+}
@@ -0,0 +1,605 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Cross-provider reconciliation (`reconcile --date`)
+//!
+//! Market caps of the tickers both providers cover (the US tickers and
+//! `[polygon] tickers`) are taken from FMP and Polygon for one date and
+//! compared. The FMP figure is the stored snapshot row of the date when
+//! `fetch-specific-date-market-caps` ran for it, otherwise the historical
+//! market cap endpoint; Polygon's is computed as in `polygon_snapshot`. Both
+//! go through the API cache. Every difference beyond `[polygon]
+//! discrepancy_threshold_pct` gets a likely cause and the source to trust.
+
+use anyhow::Result;
+use chrono::{NaiveDate, NaiveTime, TimeZone, Utc};
+use csv::Writer;
+use sqlx::Row;
+use sqlx::sqlite::SqlitePool;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::Write as IoWrite;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::api::{FMPClient, PolygonClient};
+use crate::clock;
+use crate::config::{self, OutputConfig};
+use crate::polygon_snapshot::{self, PolygonMarketCap};
+use crate::utils;
+
+/// Closes further apart than this (in percent) point at the price, not the shares
+const PRICE_TOLERANCE_PCT: f64 = 1.0;
+
+/// FMP's market cap of a ticker on the date
+#[derive(Debug, Clone, PartialEq)]
+pub struct FmpFigures {
+    pub name: String,
+    pub market_cap: f64,
+    pub currency: String,
+    pub price: Option<f64>,
+    /// Read from the stored snapshot rather than fetched
+    pub stored: bool,
+}
+
+/// Most likely reason two market caps differ
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Cause {
+    Agree,
+    MissingFmp,
+    MissingPolygon,
+    /// Neither provider has a market cap for the ticker
+    MissingBoth,
+    /// The providers report the market cap in different currencies
+    Currency,
+    /// FMP counts one share class, Polygon's weighted shares all of them
+    ShareClass,
+    /// Share counts differ, e.g. buybacks or issuance reported at different times
+    SharesOutstanding,
+    /// Closes differ, e.g. a stale or differently dated close
+    PriceOrDate,
+    Unexplained,
+}
+
+impl fmt::Display for Cause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Cause::Agree => "agree",
+            Cause::MissingFmp => "missing in FMP",
+            Cause::MissingPolygon => "missing in Polygon",
+            Cause::MissingBoth => "missing in both",
+            Cause::Currency => "currency",
+            Cause::ShareClass => "share class",
+            Cause::SharesOutstanding => "shares outstanding",
+            Cause::PriceOrDate => "price or close date",
+            Cause::Unexplained => "unexplained",
+        })
+    }
+}
+
+/// Provider to trust for a ticker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trust {
+    Either,
+    Fmp,
+    Polygon,
+    Review,
+}
+
+impl fmt::Display for Trust {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Trust::Either => "either",
+            Trust::Fmp => "fmp",
+            Trust::Polygon => "polygon",
+            Trust::Review => "review",
+        })
+    }
+}
+
+/// Comparison of one ticker's market caps
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reconciliation {
+    pub ticker: String,
+    pub fmp: Option<FmpFigures>,
+    pub polygon: Option<PolygonMarketCap>,
+    pub cause: Cause,
+    pub trust: Trust,
+}
+
+impl Reconciliation {
+    pub fn name(&self) -> &str {
+        self.fmp
+            .as_ref()
+            .map(|f| f.name.as_str())
+            .or_else(|| self.polygon.as_ref().and_then(|p| p.name.as_deref()))
+            .unwrap_or_default()
+    }
+
+    /// Polygon minus FMP
+    pub fn difference(&self) -> Option<f64> {
+        Some(self.polygon.as_ref()?.market_cap - self.fmp.as_ref()?.market_cap)
+    }
+
+    /// Polygon relative to FMP, in percent
+    pub fn diff_pct(&self) -> Option<f64> {
+        polygon_snapshot::diff_pct(
+            self.polygon.as_ref()?.market_cap,
+            self.fmp.as_ref()?.market_cap,
+        )
+    }
+}
+
+fn pct(value: f64, reference: f64) -> f64 {
+    (value - reference) / reference * 100.0
+}
+
+/// Likely cause of the difference and the source to trust
+pub fn classify(
+    fmp: Option<&FmpFigures>,
+    polygon: Option<&PolygonMarketCap>,
+    date: NaiveDate,
+    threshold_pct: f64,
+) -> (Cause, Trust) {
+    let (fmp, polygon) = match (fmp, polygon) {
+        (None, None) => return (Cause::MissingBoth, Trust::Review),
+        (None, Some(_)) => return (Cause::MissingFmp, Trust::Polygon),
+        (Some(_), None) => return (Cause::MissingPolygon, Trust::Fmp),
+        (Some(fmp), Some(polygon)) => (fmp, polygon),
+    };
+    // Polygon assumes USD when the ticker has no currency; FMP has the listing's
+    if !fmp.currency.eq_ignore_ascii_case(&polygon.currency) {
+        return (Cause::Currency, Trust::Fmp);
+    }
+    match polygon_snapshot::diff_pct(polygon.market_cap, fmp.market_cap) {
+        Some(diff) if diff.abs() <= threshold_pct => return (Cause::Agree, Trust::Either),
+        Some(_) => {}
+        None => return (Cause::MissingFmp, Trust::Polygon),
+    }
+    let Some(price) = fmp.price.filter(|p| *p > 0.0) else {
+        return (Cause::Unexplained, Trust::Review);
+    };
+
+    // Compare the two factors of the market cap separately
+    let fmp_shares = fmp.market_cap / price;
+    let shares_diff = pct(polygon.weighted_shares_outstanding, fmp_shares);
+    let price_diff = pct(polygon.close, price);
+    if shares_diff.abs() <= threshold_pct && price_diff.abs() > PRICE_TOLERANCE_PCT {
+        // FMP's historical figure is for the date itself
+        let trust = if polygon.close_date == date {
+            Trust::Review
+        } else {
+            Trust::Fmp
+        };
+        return (Cause::PriceOrDate, trust);
+    }
+    if shares_diff.abs() > threshold_pct {
+        let one_class = polygon
+            .share_class_shares_outstanding
+            .is_some_and(|class| pct(class, fmp_shares).abs() <= threshold_pct);
+        return if one_class {
+            (Cause::ShareClass, Trust::Polygon)
+        } else {
+            (Cause::SharesOutstanding, Trust::Review)
+        };
+    }
+    (Cause::Unexplained, Trust::Review)
+}
+
+/// Tickers both providers cover: the US tickers and `[polygon] tickers`
+fn overlapping_tickers(config: &config::Config) -> Vec<String> {
+    let mut tickers = config.us_tickers.clone();
+    for ticker in &config.polygon.tickers {
+        if !tickers.contains(ticker) {
+            tickers.push(ticker.clone());
+        }
+    }
+    tickers
+}
+
+/// FMP figures of the stored snapshot row of `date`
+async fn stored_fmp_figures(
+    pool: &SqlitePool,
+    date: NaiveDate,
+) -> Result<BTreeMap<String, FmpFigures>> {
+    let timestamp = Utc
+        .from_utc_datetime(&date.and_time(NaiveTime::MIN))
+        .timestamp();
+    let rows = sqlx::query(
+        r#"
+        SELECT ticker, name, CAST(market_cap_original AS REAL) AS market_cap,
+               original_currency, CAST(price AS REAL) AS price
+        FROM market_caps
+        WHERE timestamp = ? AND market_cap_original > 0
+        "#,
+    )
+    .bind(timestamp)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let figures = FmpFigures {
+                name: row.get("name"),
+                market_cap: row.get("market_cap"),
+                currency: row
+                    .get::<Option<String>, _>("original_currency")
+                    .unwrap_or_default(),
+                price: row.get("price"),
+                stored: true,
+            };
+            (row.get("ticker"), figures)
+        })
+        .collect())
+}
+
+/// Compare FMP and Polygon market caps of the overlapping tickers on `date`
+/// and write the discrepancy report
+pub async fn reconcile(
+    pool: &SqlitePool,
+    date: &str,
+    threshold_pct: Option<f64>,
+    concurrency: usize,
+) -> Result<Vec<Reconciliation>> {
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|e| anyhow::anyhow!("Invalid date {:?}: use YYYY-MM-DD: {}", date, e))?;
+    let config = config::load_config()?;
+    let threshold_pct = threshold_pct.unwrap_or(config.polygon.discrepancy_threshold_pct);
+    let tickers = overlapping_tickers(&config);
+    let polygon_key = std::env::var("POLYGON_API_KEY")
+        .map_err(|_| anyhow::anyhow!("POLYGON_API_KEY must be set to reconcile with Polygon"))?;
+    let polygon_client = Arc::new(PolygonClient::new(polygon_key));
+
+    let mut stored = stored_fmp_figures(pool, date).await?;
+    let missing: Vec<String> = tickers
+        .iter()
+        .filter(|t| !stored.contains_key(*t))
+        .cloned()
+        .collect();
+    println!(
+        "🔍 Reconciling {} tickers on {}: {} FMP figures from the stored snapshot, {} from the API",
+        tickers.len(),
+        date,
+        tickers.len() - missing.len(),
+        missing.len()
+    );
+    if !missing.is_empty() {
+        let fmp_client = Arc::new(FMPClient::from_env()?);
+        let at = Utc.from_utc_datetime(&date.and_time(NaiveTime::MIN));
+        let fetched = utils::fetch_ordered(&missing, concurrency, |ticker| {
+            let fmp_client = fmp_client.clone();
+            async move { fmp_client.get_historical_market_cap(ticker, &at).await }
+        })
+        .await;
+        for (ticker, result) in missing.iter().zip(fetched) {
+            match result {
+                Ok(h) if h.market_cap_original > 0.0 => {
+                    stored.insert(
+                        ticker.clone(),
+                        FmpFigures {
+                            name: h.name,
+                            market_cap: h.market_cap_original,
+                            currency: h.original_currency,
+                            price: Some(h.price),
+                            stored: false,
+                        },
+                    );
+                }
+                Ok(_) => eprintln!("FMP has no market cap for {} on {}", ticker, date),
+                Err(e) => eprintln!("FMP failed for {}: {}", ticker, e),
+            }
+        }
+    }
+
+    let polygon = utils::fetch_ordered(&tickers, concurrency, |ticker| {
+        let client = polygon_client.clone();
+        async move { polygon_snapshot::fetch_market_cap(&client, ticker, date).await }
+    })
+    .await;
+
+    let mut rows = Vec::new();
+    for (ticker, polygon) in tickers.iter().zip(polygon) {
+        let polygon = polygon
+            .inspect_err(|e| eprintln!("Polygon failed for {}: {}", ticker, e))
+            .ok();
+        let fmp = stored.remove(ticker);
+        let (cause, trust) = classify(fmp.as_ref(), polygon.as_ref(), date, threshold_pct);
+        rows.push(Reconciliation {
+            ticker: ticker.clone(),
+            fmp,
+            polygon,
+            cause,
+            trust,
+        });
+    }
+    // Largest differences first, then the tickers missing on one side
+    rows.sort_by(|a, b| {
+        let key = |r: &Reconciliation| r.diff_pct().map(f64::abs);
+        key(b)
+            .is_some()
+            .cmp(&key(a).is_some())
+            .then_with(|| key(b).unwrap_or(0.0).total_cmp(&key(a).unwrap_or(0.0)))
+            .then_with(|| a.ticker.cmp(&b.ticker))
+    });
+
+    let counts = cause_counts(&rows);
+    for (cause, count) in &counts {
+        println!("  {:<22} {:>4}", cause.to_string(), count);
+    }
+
+    let output = config::load_output_config();
+    output.ensure_directory()?;
+    let stamp = OutputConfig::timestamp();
+    let day = date.to_string();
+    let csv_path = output.file_path_at("reconciliation", &day, &stamp, "csv");
+    write_csv(&csv_path, &rows)?;
+    println!("✅ Reconciliation exported to {}", csv_path.display());
+    let md_path = output.file_path_at("reconciliation", &format!("{}_summary", day), &stamp, "md");
+    write_markdown(&md_path, &rows, &day, threshold_pct)?;
+    println!("✅ Reconciliation report exported to {}", md_path.display());
+
+    Ok(rows)
+}
+
+fn cause_counts(rows: &[Reconciliation]) -> BTreeMap<Cause, usize> {
+    let mut counts = BTreeMap::new();
+    for row in rows {
+        *counts.entry(row.cause).or_default() += 1;
+    }
+    counts
+}
+
+fn number(value: Option<f64>) -> String {
+    value.map(|v| format!("{:.0}", v)).unwrap_or_default()
+}
+
+fn write_csv(path: &Path, rows: &[Reconciliation]) -> Result<()> {
+    let mut writer = Writer::from_path(path)?;
+    writer.write_record([
+        "Ticker",
+        "Name",
+        "FMP Market Cap",
+        "FMP Currency",
+        "FMP Price",
+        "FMP Source",
+        "Polygon Market Cap",
+        "Polygon Currency",
+        "Polygon Close",
+        "Polygon Close Date",
+        "Polygon Weighted Shares",
+        "Polygon Share Class Shares",
+        "Difference",
+        "Difference (%)",
+        "Likely Cause",
+        "Trust",
+    ])?;
+    for row in rows {
+        let fmp = row.fmp.as_ref();
+        let polygon = row.polygon.as_ref();
+        writer.write_record([
+            row.ticker.clone(),
+            row.name().to_string(),
+            number(fmp.map(|f| f.market_cap)),
+            fmp.map(|f| f.currency.clone()).unwrap_or_default(),
+            fmp.and_then(|f| f.price)
+                .map(|p| p.to_string())
+                .unwrap_or_default(),
+            fmp.map(|f| if f.stored { "stored" } else { "api" })
+                .unwrap_or_default()
+                .to_string(),
+            number(polygon.map(|p| p.market_cap)),
+            polygon.map(|p| p.currency.clone()).unwrap_or_default(),
+            polygon.map(|p| p.close.to_string()).unwrap_or_default(),
+            polygon
+                .map(|p| p.close_date.to_string())
+                .unwrap_or_default(),
+            number(polygon.map(|p| p.weighted_shares_outstanding)),
+            number(polygon.and_then(|p| p.share_class_shares_outstanding)),
+            number(row.difference()),
+            row.diff_pct()
+                .map(|d| format!("{:.2}", d))
+                .unwrap_or_default(),
+            row.cause.to_string(),
+            row.trust.to_string(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_markdown(
+    path: &Path,
+    rows: &[Reconciliation],
+    date: &str,
+    threshold_pct: f64,
+) -> Result<()> {
+    let mut file = File::create(path)?;
+    writeln!(file, "# FMP / Polygon Reconciliation: {}", date)?;
+    writeln!(file)?;
+    writeln!(
+        file,
+        "{} tickers compared; differences above {}% get a likely cause.",
+        rows.len(),
+        threshold_pct
+    )?;
+    writeln!(file)?;
+    writeln!(file, "| Likely Cause | Tickers |")?;
+    writeln!(file, "|--------------|--------:|")?;
+    for (cause, count) in cause_counts(rows) {
+        writeln!(file, "| {} | {} |", cause, count)?;
+    }
+    writeln!(file)?;
+
+    let discrepancies: Vec<&Reconciliation> =
+        rows.iter().filter(|r| r.cause != Cause::Agree).collect();
+    writeln!(file, "## Discrepancies ({})", discrepancies.len())?;
+    writeln!(file)?;
+    if discrepancies.is_empty() {
+        writeln!(file, "None.")?;
+    } else {
+        writeln!(
+            file,
+            "| Ticker | Name | FMP | Polygon | Difference | Likely Cause | Trust |"
+        )?;
+        writeln!(
+            file,
+            "|--------|------|----:|--------:|-----------:|--------------|-------|"
+        )?;
+        for row in discrepancies {
+            writeln!(
+                file,
+                "| {} | {} | {} | {} | {} | {} | {} |",
+                utils::escape_markdown(&row.ticker),
+                utils::escape_markdown(row.name()),
+                row.fmp
+                    .as_ref()
+                    .map(|f| format!("{:.2}B {}", f.market_cap / 1e9, f.currency))
+                    .unwrap_or_else(|| "-".to_string()),
+                row.polygon
+                    .as_ref()
+                    .map(|p| format!("{:.2}B {}", p.market_cap / 1e9, p.currency))
+                    .unwrap_or_else(|| "-".to_string()),
+                row.diff_pct()
+                    .map(|d| format!("{:+.1}%", d))
+                    .unwrap_or_else(|| "-".to_string()),
+                row.cause,
+                row.trust
+            )?;
+        }
+    }
+    writeln!(file)?;
+    writeln!(file, "---")?;
+    writeln!(
+        file,
+        "*Generated on {}*",
+        clock::now().format("%Y-%m-%d %H:%M:%S")
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 8, 1).unwrap()
+    }
+
+    fn fmp(market_cap: f64, price: f64) -> FmpFigures {
+        FmpFigures {
+            name: "Nike".to_string(),
+            market_cap,
+            currency: "USD".to_string(),
+            price: Some(price),
+            stored: true,
+        }
+    }
+
+    fn polygon(
+        shares: f64,
+        close: f64,
+        class: Option<f64>,
+        close_date: NaiveDate,
+    ) -> PolygonMarketCap {
+        PolygonMarketCap {
+            ticker: "NKE".to_string(),
+            name: Some("Nike Inc.".to_string()),
+            currency: "USD".to_string(),
+            exchange: Some("NYSE".to_string()),
+            weighted_shares_outstanding: shares,
+            share_class_shares_outstanding: class,
+            close,
+            close_date,
+            market_cap: shares * close,
+        }
+    }
+
+    #[test]
+    fn test_classify_causes() {
+        let d = date();
+        // 100 shares at 10 per FMP
+        let f = fmp(1_000.0, 10.0);
+        let classify = |p: &PolygonMarketCap| classify(Some(&f), Some(p), d, 5.0);
+
+        assert_eq!(
+            classify(&polygon(102.0, 10.0, None, d)),
+            (Cause::Agree, Trust::Either)
+        );
+        // Same shares, older close
+        let stale = d.pred_opt().unwrap();
+        assert_eq!(
+            classify(&polygon(100.0, 11.0, None, stale)),
+            (Cause::PriceOrDate, Trust::Fmp)
+        );
+        assert_eq!(
+            classify(&polygon(100.0, 11.0, None, d)),
+            (Cause::PriceOrDate, Trust::Review)
+        );
+        // FMP counts the 100 class A shares, Polygon all 150
+        assert_eq!(
+            classify(&polygon(150.0, 10.0, Some(100.0), d)),
+            (Cause::ShareClass, Trust::Polygon)
+        );
+        assert_eq!(
+            classify(&polygon(150.0, 10.0, None, d)),
+            (Cause::SharesOutstanding, Trust::Review)
+        );
+
+        let mut eur = polygon(100.0, 10.0, None, d);
+        eur.currency = "EUR".to_string();
+        assert_eq!(classify(&eur), (Cause::Currency, Trust::Fmp));
+
+        let p = polygon(100.0, 10.0, None, d);
+        assert_eq!(
+            super::classify(None, Some(&p), d, 5.0),
+            (Cause::MissingFmp, Trust::Polygon)
+        );
+        assert_eq!(
+            super::classify(Some(&f), None, d, 5.0),
+            (Cause::MissingPolygon, Trust::Fmp)
+        );
+        assert_eq!(
+            super::classify(None, None, d, 5.0),
+            (Cause::MissingBoth, Trust::Review)
+        );
+    }
+
+    #[test]
+    fn test_reconciliation_difference() {
+        let row = Reconciliation {
+            ticker: "NKE".to_string(),
+            fmp: Some(fmp(1_000.0, 10.0)),
+            polygon: Some(polygon(110.0, 10.0, None, date())),
+            cause: Cause::SharesOutstanding,
+            trust: Trust::Review,
+        };
+        assert_eq!(row.name(), "Nike");
+        assert_eq!(row.difference(), Some(100.0));
+        assert_eq!(row.diff_pct(), Some(10.0));
+        assert_eq!(Cause::PriceOrDate.to_string(), "price or close date");
+    }
+
+    #[test]
+    fn test_markdown_escapes_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("reconciliation.md");
+        let mut f = fmp(1_000.0, 10.0);
+        f.name = "A|B *Group*".to_string();
+        let rows = vec![Reconciliation {
+            ticker: "AB".to_string(),
+            fmp: Some(f),
+            polygon: None,
+            cause: Cause::MissingPolygon,
+            trust: Trust::Fmp,
+        }];
+        write_markdown(&path, &rows, "2025-08-01", 5.0).unwrap();
+        let md = std::fs::read_to_string(&path).unwrap();
+        assert!(
+            md.contains("| AB | A\\|B \\*Group\\* | 0.00B USD | - |"),
+            "{}",
+            md
+        );
+    }
+}
@@ -0,0 +1,330 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Market cap per exchange and per region
+//!
+//! Companies are mapped to a region by their listing exchange, falling back to
+//! their reporting currency when the exchange is unknown (older snapshots
+//! stored the currency name in the exchange column). Exports write the totals
+//! to a `_by_region` CSV, and summaries show the regional share per date.
+
+use anyhow::Result;
+use csv::Writer;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as FmtWrite;
+use std::path::Path;
+
+use crate::locale::Translations;
+use crate::subunits;
+
+/// Region a company is listed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Region {
+    /// Europe, including the UK, Switzerland and the Nordics
+    Eu,
+    Us,
+    Asia,
+    Other,
+}
+
+impl Region {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Region::Eu => "EU",
+            Region::Us => "US",
+            Region::Asia => "Asia",
+            Region::Other => "Other",
+        }
+    }
+}
+
+fn region_of_exchange(exchange: &str) -> Option<Region> {
+    match exchange.trim().to_uppercase().as_str() {
+        "NYSE" | "NASDAQ" | "AMEX" | "NYSEARCA" | "NYSEAMERICAN" | "OTC" | "PNK" | "CBOE" => {
+            Some(Region::Us)
+        }
+        "LSE" | "XETRA" | "FRA" | "GER" | "MIL" | "PAR" | "EURONEXT" | "AMS" | "BRU" | "LIS"
+        | "STO" | "HEL" | "CPH" | "OSL" | "SIX" | "BME" | "MCE" | "VIE" | "DUB" | "ISE" | "WSE"
+        | "ATH" | "ICE" => Some(Region::Eu),
+        "HKSE" | "JPX" | "TSE" | "SHZ" | "SHH" | "NSE" | "BSE" | "KSC" | "KOE" | "TAI" | "TWO"
+        | "SES" | "SET" | "KLS" | "JKT" => Some(Region::Asia),
+        _ => None,
+    }
+}
+
+fn region_of_currency(currency: &str) -> Option<Region> {
+    // Subunits (GBp) count towards their parent currency
+    match subunits::resolve(currency.trim()).0 {
+        "USD" => Some(Region::Us),
+        "EUR" | "GBP" | "GBX" | "CHF" | "SEK" | "DKK" | "NOK" | "PLN" | "CZK" | "HUF" | "ISK" => {
+            Some(Region::Eu)
+        }
+        "JPY" | "HKD" | "CNY" | "INR" | "KRW" | "TWD" | "SGD" | "THB" | "MYR" | "IDR" => {
+            Some(Region::Asia)
+        }
+        _ => None,
+    }
+}
+
+/// Region of a company from its exchange, else its currency
+pub fn region_for(exchange: &str, currency: &str) -> Region {
+    region_of_exchange(exchange)
+        .or_else(|| region_of_currency(exchange))
+        .or_else(|| region_of_currency(currency))
+        .unwrap_or(Region::Other)
+}
+
+/// Exchange group of a company; currency codes from older snapshots are `Unknown`
+pub fn exchange_label(exchange: &str) -> String {
+    let exchange = exchange.trim();
+    if exchange.is_empty()
+        || (region_of_exchange(exchange).is_none() && region_of_currency(exchange).is_some())
+    {
+        "Unknown".to_string()
+    } else {
+        exchange.to_string()
+    }
+}
+
+/// One company of a snapshot, as far as grouping is concerned
+#[derive(Debug, Clone, Copy)]
+pub struct Listing<'a> {
+    pub exchange: &'a str,
+    pub currency: &'a str,
+    pub market_cap_eur: Option<f64>,
+    pub market_cap_usd: Option<f64>,
+}
+
+/// Totals per region, largest first
+pub fn by_region(listings: &[Listing]) -> Vec<GroupTotal> {
+    group_totals(listings.iter().map(|l| {
+        (
+            region_for(l.exchange, l.currency).label().to_string(),
+            l.market_cap_eur,
+            l.market_cap_usd,
+        )
+    }))
+}
+
+/// Totals per exchange, largest first
+pub fn by_exchange(listings: &[Listing]) -> Vec<GroupTotal> {
+    group_totals(listings.iter().map(|l| {
+        (
+            exchange_label(l.exchange),
+            l.market_cap_eur,
+            l.market_cap_usd,
+        )
+    }))
+}
+
+/// Companies and market cap of one exchange or region
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GroupTotal {
+    pub group: String,
+    pub companies: usize,
+    pub market_cap_eur: f64,
+    pub market_cap_usd: f64,
+    /// Percentage of the total USD market cap
+    pub share: f64,
+}
+
+/// Totals per group from `(group, eur, usd)` rows, largest first
+pub fn group_totals(
+    rows: impl IntoIterator<Item = (String, Option<f64>, Option<f64>)>,
+) -> Vec<GroupTotal> {
+    let mut groups: BTreeMap<String, (usize, f64, f64)> = BTreeMap::new();
+    for (group, eur, usd) in rows {
+        let entry = groups.entry(group).or_default();
+        entry.0 += 1;
+        entry.1 += eur.unwrap_or(0.0);
+        entry.2 += usd.unwrap_or(0.0);
+    }
+
+    let total_usd: f64 = groups.values().map(|g| g.2).sum();
+    let mut totals: Vec<GroupTotal> = groups
+        .into_iter()
+        .map(|(group, (companies, eur, usd))| GroupTotal {
+            group,
+            companies,
+            market_cap_eur: eur,
+            market_cap_usd: usd,
+            share: if total_usd > 0.0 {
+                usd / total_usd * 100.0
+            } else {
+                0.0
+            },
+        })
+        .collect();
+    totals.sort_by(|a, b| {
+        b.market_cap_usd
+            .total_cmp(&a.market_cap_usd)
+            .then_with(|| a.group.cmp(&b.group))
+    });
+    totals
+}
+
+/// Write region and exchange totals of a snapshot as one tidy CSV
+pub fn export_breakdown_csv(path: &Path, listings: &[Listing]) -> Result<()> {
+    let by_region = by_region(listings);
+    let by_exchange = by_exchange(listings);
+    let mut writer = Writer::from_path(path)?;
+    writer.write_record([
+        "Grouping",
+        "Group",
+        "Companies",
+        "Market Cap (EUR)",
+        "Market Cap (USD)",
+        "Share (%)",
+    ])?;
+    for (grouping, totals) in [("region", &by_region), ("exchange", &by_exchange)] {
+        for total in totals {
+            writer.write_record(&[
+                grouping.to_string(),
+                total.group.clone(),
+                total.companies.to_string(),
+                format!("{:.0}", total.market_cap_eur),
+                format!("{:.0}", total.market_cap_usd),
+                format!("{:.2}", total.share),
+            ])?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Markdown table of the regional split on two dates, largest region first
+pub fn markdown_table(
+    from_label: &str,
+    to_label: &str,
+    from: &[GroupTotal],
+    to: &[GroupTotal],
+    tr: &Translations,
+) -> String {
+    let from_map: HashMap<&str, &GroupTotal> = from.iter().map(|g| (g.group.as_str(), g)).collect();
+    let to_map: HashMap<&str, &GroupTotal> = to.iter().map(|g| (g.group.as_str(), g)).collect();
+    let mut groups: Vec<&str> = to.iter().map(|g| g.group.as_str()).collect();
+    groups.extend(
+        from.iter()
+            .map(|g| g.group.as_str())
+            .filter(|g| !to_map.contains_key(g)),
+    );
+
+    let mut table = String::new();
+    let _ = writeln!(
+        table,
+        "| {} | {} | {} | {} | {} | {} |",
+        tr.t("region", &[]),
+        tr.t("companies_on", &[("date", from_label)]),
+        tr.t("companies_on", &[("date", to_label)]),
+        tr.t("share_on", &[("date", from_label)]),
+        tr.t("share_on", &[("date", to_label)]),
+        tr.t("concentration_change", &[])
+    );
+    let _ = writeln!(table, "|---|---:|---:|---:|---:|---:|");
+    for group in groups {
+        let (before, after) = (from_map.get(group), to_map.get(group));
+        let share = |g: Option<&&GroupTotal>| g.map_or(0.0, |g| g.share);
+        let delta = share(after) - share(before);
+        let change = tr.signed_number(delta, 2);
+        let _ = writeln!(
+            table,
+            "| {} | {} | {} | {} | {} | {} |",
+            group,
+            before.map_or(0, |g| g.companies),
+            after.map_or(0, |g| g.companies),
+            tr.percent(share(before), false),
+            tr.percent(share(after), false),
+            tr.t("percentage_points", &[("value", &change)])
+        );
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locale::Locale;
+
+    #[test]
+    fn test_region_for() {
+        assert_eq!(region_for("NYSE", "USD"), Region::Us);
+        assert_eq!(region_for("XETRA", "EUR"), Region::Eu);
+        // Exchange wins over a USD reporting currency
+        assert_eq!(region_for("HKSE", "USD"), Region::Asia);
+        // Older snapshots stored the currency in the exchange column
+        assert_eq!(region_for("GBp", ""), Region::Eu);
+        assert_eq!(region_for("", "JPY"), Region::Asia);
+        assert_eq!(region_for("SAO", "BRL"), Region::Other);
+
+        assert_eq!(exchange_label("NYSE"), "NYSE");
+        assert_eq!(exchange_label("SAO"), "SAO");
+        assert_eq!(exchange_label("USD"), "Unknown");
+        assert_eq!(exchange_label(""), "Unknown");
+    }
+
+    #[test]
+    fn test_group_totals() {
+        let totals = group_totals(vec![
+            ("EU".to_string(), Some(90.0), Some(100.0)),
+            ("US".to_string(), Some(270.0), Some(300.0)),
+            ("EU".to_string(), Some(90.0), Some(100.0)),
+            ("Other".to_string(), None, None),
+        ]);
+        let summary: Vec<(&str, usize, f64)> = totals
+            .iter()
+            .map(|t| (t.group.as_str(), t.companies, t.share))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![("US", 1, 60.0), ("EU", 2, 40.0), ("Other", 1, 0.0)]
+        );
+        assert_eq!(totals[1].market_cap_eur, 180.0);
+    }
+
+    #[test]
+    fn test_breakdown_csv_and_markdown() {
+        let from = group_totals(vec![
+            ("EU".to_string(), Some(1.0), Some(50.0)),
+            ("US".to_string(), Some(1.0), Some(50.0)),
+        ]);
+        let to = group_totals(vec![
+            ("US".to_string(), Some(1.0), Some(75.0)),
+            ("EU".to_string(), Some(1.0), Some(25.0)),
+        ]);
+
+        let listing = |exchange, currency, usd| Listing {
+            exchange,
+            currency,
+            market_cap_eur: Some(1.0),
+            market_cap_usd: Some(usd),
+        };
+        let listings = [
+            listing("NASDAQ", "USD", 60.0),
+            listing("NYSE", "USD", 15.0),
+            listing("EUR", "EUR", 25.0),
+        ];
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("by_region.csv");
+        export_breakdown_csv(&path, &listings).unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "Grouping,Group,Companies,Market Cap (EUR),Market Cap (USD),Share (%)",
+                "region,US,2,2,75,75.00",
+                "region,EU,1,1,25,25.00",
+                "exchange,NASDAQ,1,1,60,60.00",
+                "exchange,Unknown,1,1,25,25.00",
+                "exchange,NYSE,1,1,15,15.00",
+            ]
+        );
+
+        let tr = Translations::load(Locale::En).unwrap();
+        let table = markdown_table("2025-01-31", "2025-02-28", &from, &to, &tr);
+        assert!(table.contains("| US | 1 | 1 | 50.00% | 75.00% | +25.00 pp |"));
+        assert!(table.contains("| EU | 1 | 1 | 50.00% | 25.00% | -25.00 pp |"));
+    }
+}
@@ -0,0 +1,204 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! `report --from --to [--publish email,slack,s3]`: the comparison pipeline in one run
+//!
+//! Fetches the snapshot of each date that has no exported CSV yet, compares
+//! the two with the charts embedded in the summary, writes the newsletter
+//! digest and bundles digest and summary into one `report_<from>_to_<to>`
+//! Markdown and HTML file next to the charts. Publishing is opt-in: `email`
+//! sends the bundle with the comparison CSV and charts attached, `slack`
+//! posts the digest headline to the configured webhooks and `s3` uploads the
+//! files of the run to the `--upload` / `[storage] upload_url` destination at
+//! the end of the run. Without `--publish s3` nothing is uploaded, even when
+//! a destination is configured.
+
+use anyhow::{Context, Result};
+use sqlx::sqlite::SqlitePool;
+use std::fs;
+
+use crate::advanced_comparisons;
+use crate::compare_marketcaps;
+use crate::config;
+use crate::data_quality;
+use crate::digest::{self, Digest, DigestTemplate};
+use crate::notify::{email, webhook};
+use crate::snapshot_labels;
+use crate::specific_date_marketcaps;
+
+/// Where `report --publish` sends the bundle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PublishTarget {
+    /// Email the bundle with the comparison CSV and charts attached
+    Email,
+    /// Post the digest headline to the Slack/Teams webhooks
+    Slack,
+    /// Upload the files written by the run to object storage
+    S3,
+}
+
+impl PublishTarget {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "email" => Ok(Self::Email),
+            "slack" => Ok(Self::Slack),
+            "s3" => Ok(Self::S3),
+            other => anyhow::bail!("Unknown publish target '{}': use email, slack or s3", other),
+        }
+    }
+
+    /// Targets of a `--publish` list, without duplicates
+    pub fn parse_all(values: &[String]) -> Result<Vec<Self>> {
+        let mut targets = Vec::new();
+        for value in values {
+            let target = Self::parse(value)?;
+            if !targets.contains(&target) {
+                targets.push(target);
+            }
+        }
+        Ok(targets)
+    }
+}
+
+/// Fetch the snapshot of `date` unless an exported CSV already exists
+async fn ensure_snapshot(
+    pool: &SqlitePool,
+    date: &str,
+    report_currencies: &[String],
+    concurrency: usize,
+) -> Result<()> {
+    if advanced_comparisons::find_csv_for_date(date, None).is_ok() {
+        println!("✓ Snapshot for {} already exported", date);
+        return Ok(());
+    }
+    println!("📥 No snapshot for {}, fetching it", date);
+    let date = specific_date_marketcaps::fetch_specific_date_marketcaps(
+        pool,
+        date,
+        report_currencies,
+        concurrency,
+        false,
+        false,
+    )
+    .await?;
+    data_quality::check_date(pool, &date, false).await
+}
+
+/// Digest on top of the full comparison summary
+fn bundle_markdown(digest: &Digest, summary: &str) -> String {
+    format!("{}\n---\n\n{}", digest.markdown(), summary)
+}
+
+/// Run the whole pipeline for `from` to `to` and publish to `targets`
+/// (except `s3`, which the end-of-run upload takes care of)
+#[allow(clippy::too_many_arguments)]
+pub async fn run_report(
+    pool: &SqlitePool,
+    from_date: &str,
+    to_date: &str,
+    targets: &[PublishTarget],
+    report_currencies: &[String],
+    concurrency: usize,
+    consistent_universe: bool,
+    exclude_corporate_actions: bool,
+) -> Result<()> {
+    // Labeled intraday snapshots can't be fetched after the fact, and the
+    // digest compares daily snapshots
+    for date in [from_date, to_date] {
+        if snapshot_labels::split_spec(date)?.1.is_some() {
+            anyhow::bail!(
+                "report takes plain dates (YYYY-MM-DD); compare labeled snapshots with compare-market-caps"
+            );
+        }
+    }
+    println!("📊 Report {} to {}\n", from_date, to_date);
+    let output = config::load_output_config();
+    output.ensure_directory()?;
+
+    for date in [from_date, to_date] {
+        ensure_snapshot(pool, date, report_currencies, concurrency).await?;
+    }
+
+    println!();
+    compare_marketcaps::compare_market_caps(
+        pool,
+        from_date,
+        to_date,
+        report_currencies,
+        None,
+        consistent_universe,
+        exclude_corporate_actions,
+        true,
+    )
+    .await?;
+
+    println!();
+    let digest =
+        digest::generate_digest(pool, from_date, to_date, DigestTemplate::Newsletter).await?;
+
+    let range = format!("{}_to_{}", from_date, to_date);
+    let summary_path = output
+        .find_latest("comparison", &format!("{}_summary", range), "md")?
+        .context("The comparison summary was not written")?;
+    let summary = fs::read_to_string(&summary_path)
+        .with_context(|| format!("Failed to read {}", summary_path.display()))?;
+    let markdown = bundle_markdown(&digest, &summary);
+    let timestamp = config::OutputConfig::timestamp();
+    for (ext, body) in [
+        ("md", markdown.clone()),
+        ("html", email::markdown_to_html(&markdown)),
+    ] {
+        let path = output.file_path_at("report", &range, &timestamp, ext);
+        fs::write(&path, body).with_context(|| format!("Failed to write {}", path.display()))?;
+        println!("✅ Report written to {}", path.display());
+    }
+
+    for target in targets {
+        match target {
+            PublishTarget::Email => email::send_bundle(from_date, to_date, &markdown).await?,
+            PublishTarget::Slack => {
+                let sent = webhook::publish_message(&digest.title(), &digest.lines()).await?;
+                println!("✅ Posted the digest to {} webhook(s)", sent);
+            }
+            PublishTarget::S3 => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_publish_targets() {
+        let values = ["email", " Slack", "s3", "email"].map(String::from);
+        assert_eq!(
+            PublishTarget::parse_all(&values).unwrap(),
+            vec![
+                PublishTarget::Email,
+                PublishTarget::Slack,
+                PublishTarget::S3
+            ]
+        );
+        assert!(PublishTarget::parse("teams").is_err());
+        assert!(PublishTarget::parse_all(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_bundle_puts_digest_above_summary() {
+        let digest = Digest {
+            from_date: "2025-01-01".to_string(),
+            to_date: "2025-02-01".to_string(),
+            total_from: 100e9,
+            total_to: 110e9,
+            movers: Vec::new(),
+            rank_changes: Vec::new(),
+            chart: None,
+        };
+        let bundle = bundle_markdown(&digest, "# Market Cap Comparison\n");
+        assert!(bundle.starts_with("## Fashion market caps: 2025-01-01 to 2025-02-01"));
+        assert!(bundle.ends_with("\n---\n\n# Market Cap Comparison\n"));
+    }
+}
@@ -0,0 +1,320 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Retry policy of the API clients
+//!
+//! A failed request is retried up to `max_retries` times. The wait before
+//! retry `n` is `base_delay × 2ⁿ`, capped at `max_delay`, plus a random share
+//! of up to `jitter` of it so parallel requests don't retry in lockstep. The
+//! policy comes from `[api]` in config.toml; `--max-retries`,
+//! `--retry-base-delay`, `--retry-max-delay` and `--retry-jitter` override it
+//! for a run. Both clients count their retries of a request with one
+//! `Attempts` and retry failed connections, `429 Too Many Requests` and server
+//! errors; FMP also retries "Limit Reach" answers once every key was
+//! rejected. A `Retry-After` header replaces the backoff.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use reqwest::StatusCode;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::api_usage;
+use crate::circuit_breaker;
+use crate::config::{self, ApiConfig};
+
+/// How often and how long to wait before retrying a failed request
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt
+    pub max_retries: u32,
+    /// Wait before the first retry; doubled for every further retry
+    pub base_delay: Duration,
+    /// Upper bound for the wait, before jitter
+    pub max_delay: Duration,
+    /// Random extra wait as a fraction of the delay (0 to 1)
+    pub jitter: f64,
+}
+
+impl From<&ApiConfig> for RetryPolicy {
+    fn from(api: &ApiConfig) -> Self {
+        Self {
+            max_retries: api.max_retries,
+            base_delay: Duration::from_secs(api.base_delay_secs),
+            max_delay: Duration::from_secs(api.max_delay_secs),
+            jitter: api.jitter,
+        }
+    }
+}
+
+/// Per-run overrides of the configured policy (the CLI flags)
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RetryOverrides {
+    pub max_retries: Option<u32>,
+    pub base_delay_secs: Option<u64>,
+    pub max_delay_secs: Option<u64>,
+    pub jitter: Option<f64>,
+}
+
+impl RetryPolicy {
+    /// Policy with the overrides that are set applied
+    pub fn with_overrides(self, overrides: RetryOverrides) -> Result<Self> {
+        let policy = Self {
+            max_retries: overrides.max_retries.unwrap_or(self.max_retries),
+            base_delay: overrides
+                .base_delay_secs
+                .map_or(self.base_delay, Duration::from_secs),
+            max_delay: overrides
+                .max_delay_secs
+                .map_or(self.max_delay, Duration::from_secs),
+            jitter: overrides.jitter.unwrap_or(self.jitter),
+        };
+        policy.validate()?;
+        Ok(policy)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if !(0.0..=1.0).contains(&self.jitter) {
+            anyhow::bail!("retry jitter must be between 0 and 1, e.g. 0.1");
+        }
+        if self.max_delay < self.base_delay {
+            anyhow::bail!("retry max delay must not be shorter than the base delay");
+        }
+        Ok(())
+    }
+
+    /// Wait before retry `retry` (0 for the first retry), without jitter
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry);
+        self.base_delay.saturating_mul(factor).min(self.max_delay)
+    }
+
+    /// Wait before retry `retry`, with `random` (0 to 1) picking the jitter
+    pub fn delay_with(&self, retry: u32, random: f64) -> Duration {
+        let backoff = self.backoff(retry);
+        backoff + backoff.mul_f64(self.jitter * random.clamp(0.0, 1.0))
+    }
+
+    /// Wait before retry `retry`, with random jitter
+    pub fn delay(&self, retry: u32) -> Duration {
+        self.delay_with(retry, random_fraction())
+    }
+}
+
+/// Uniform-ish number in [0, 1) from the process's hash seed
+fn random_fraction() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64,
+    );
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Whether a response status is worth retrying: rate limits and server errors
+pub fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Wait a `Retry-After` header asks for, in seconds or until an HTTP date
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let until = DateTime::parse_from_rfc2822(value)
+        .ok()?
+        .with_timezone(&Utc);
+    Some((until - Utc::now()).to_std().unwrap_or_default())
+}
+
+/// Retries of one request under a policy
+#[derive(Debug)]
+pub struct Attempts {
+    policy: RetryPolicy,
+    retries: u32,
+}
+
+impl Default for Attempts {
+    fn default() -> Self {
+        Self::new(policy())
+    }
+}
+
+impl Attempts {
+    pub fn new(policy: RetryPolicy) -> Self {
+        Self { policy, retries: 0 }
+    }
+
+    /// Retries made so far
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    /// Whether another retry is allowed
+    pub fn can_retry(&self) -> bool {
+        self.retries < self.policy.max_retries
+    }
+
+    /// Wait before the next retry, `retry_after` when the server sent one;
+    /// None once the retries are used up
+    pub fn next_delay(&mut self, retry_after: Option<Duration>) -> Option<Duration> {
+        if !self.can_retry() {
+            return None;
+        }
+        let delay = retry_after.unwrap_or_else(|| self.policy.delay(self.retries));
+        self.retries += 1;
+        Some(delay)
+    }
+
+    /// Count a retry of `url` after `failure` and wait for it; false once the
+    /// retries are used up
+    pub async fn retry(&mut self, url: &str, failure: &str) -> bool {
+        let Some(delay) = self.next_delay(None) else {
+            return false;
+        };
+        api_usage::record_retry(url);
+        eprintln!(
+            "Request to {} failed ({}). Retrying in {:.1} seconds...",
+            api_usage::endpoint_name(url),
+            failure,
+            delay.as_secs_f64()
+        );
+        tokio::time::sleep(delay).await;
+        true
+    }
+
+    /// Like `retry`, after a rate limit answer: the wait is `retry_after`
+    /// when given and doesn't count against the ticker's deadline
+    pub async fn retry_rate_limited(&mut self, url: &str, retry_after: Option<Duration>) -> bool {
+        let Some(delay) = self.next_delay(retry_after) else {
+            return false;
+        };
+        api_usage::record_retry(url);
+        eprintln!(
+            "Rate limit hit for {}. Retrying in {:.1} seconds...",
+            api_usage::endpoint_name(url),
+            delay.as_secs_f64()
+        );
+        circuit_breaker::excluded_from_deadline(tokio::time::sleep(delay)).await;
+        true
+    }
+}
+
+static POLICY: OnceLock<RetryPolicy> = OnceLock::new();
+
+/// Set this run's policy: `[api]` with the CLI overrides applied. Later
+/// calls are ignored.
+pub fn init(overrides: RetryOverrides) -> Result<()> {
+    let policy = RetryPolicy::from(&config::load_api_config()).with_overrides(overrides)?;
+    let _ = POLICY.set(policy);
+    Ok(())
+}
+
+/// This run's policy, or the configured one when `init` wasn't called
+pub fn policy() -> RetryPolicy {
+    *POLICY.get_or_init(|| RetryPolicy::from(&config::load_api_config()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy::from(&ApiConfig::default())
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_max_delay() {
+        let policy = RetryPolicy {
+            max_delay: Duration::from_secs(15),
+            ..policy()
+        };
+        assert_eq!(policy.backoff(0), Duration::from_secs(5));
+        assert_eq!(policy.backoff(1), Duration::from_secs(10));
+        assert_eq!(policy.backoff(2), Duration::from_secs(15));
+        assert_eq!(policy.backoff(40), Duration::from_secs(15));
+
+        // Jitter only ever adds to the wait
+        let jittered = RetryPolicy {
+            jitter: 0.5,
+            ..policy
+        };
+        assert_eq!(jittered.delay_with(1, 0.0), Duration::from_secs(10));
+        assert_eq!(jittered.delay_with(1, 1.0), Duration::from_secs(15));
+        let delay = jittered.delay(1);
+        assert!(delay >= Duration::from_secs(10) && delay <= Duration::from_secs(15));
+    }
+
+    #[test]
+    fn test_overrides_replace_configured_values() {
+        let policy = policy()
+            .with_overrides(RetryOverrides {
+                max_retries: Some(0),
+                max_delay_secs: Some(30),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(policy.max_retries, 0);
+        assert_eq!(policy.base_delay, Duration::from_secs(5));
+        assert_eq!(policy.max_delay, Duration::from_secs(30));
+
+        let too_much_jitter = RetryOverrides {
+            jitter: Some(1.5),
+            ..Default::default()
+        };
+        assert!(policy.with_overrides(too_much_jitter).is_err());
+        let inverted = RetryOverrides {
+            base_delay_secs: Some(60),
+            ..Default::default()
+        };
+        assert!(policy.with_overrides(inverted).is_err());
+    }
+
+    #[test]
+    fn test_retryable_statuses() {
+        assert!(is_retryable(StatusCode::TOO_MANY_REQUESTS));
+        assert!(is_retryable(StatusCode::BAD_GATEWAY));
+        assert!(!is_retryable(StatusCode::NOT_FOUND));
+        assert!(!is_retryable(StatusCode::UNAUTHORIZED));
+    }
+
+    #[test]
+    fn test_attempts_prefer_retry_after_and_stop_at_max_retries() {
+        let mut attempts = Attempts::new(RetryPolicy {
+            max_retries: 2,
+            jitter: 0.0,
+            ..policy()
+        });
+        assert_eq!(attempts.next_delay(None), Some(Duration::from_secs(5)));
+        assert_eq!(
+            attempts.next_delay(Some(Duration::from_secs(42))),
+            Some(Duration::from_secs(42))
+        );
+        assert_eq!(attempts.retries(), 2);
+        assert!(!attempts.can_retry());
+        assert_eq!(attempts.next_delay(None), None);
+    }
+
+    #[test]
+    fn test_retry_after_reads_seconds_and_dates() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+        headers.insert(RETRY_AFTER, "120".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(120)));
+        // A date in the past means retry now
+        headers.insert(
+            RETRY_AFTER,
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(retry_after(&headers), Some(Duration::ZERO));
+        headers.insert(RETRY_AFTER, "soon".parse().unwrap());
+        assert_eq!(retry_after(&headers), None);
+    }
+}
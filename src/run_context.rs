@@ -0,0 +1,310 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Provenance of a CLI run (`--manifest`)
+//!
+//! With `--manifest`, code that reads or writes files registers them here:
+//! CSV and config readers call `record_input()` and `OutputConfig` paths are
+//! recorded as outputs when they are built. Warnings that affect the figures
+//! (missing or stale rates, tickers that failed) are recorded next to where
+//! they are printed. At the end of the run `finish()` writes a JSON manifest
+//! with the command, timings, SHA-256 of every input and output, the API
+//! requests made and the warnings. Outputs only count when the file was
+//! written during the run. Without `--manifest` nothing is recorded.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::SystemTime;
+
+use crate::api_usage::{self, EndpointUsage};
+use crate::error;
+
+/// A file read or written by the run
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FileArtifact {
+    pub path: String,
+    pub bytes: u64,
+    pub sha256: String,
+}
+
+/// Contents of `run_manifest_<timestamp>.json`
+#[derive(Debug, Serialize)]
+pub struct RunManifest {
+    pub version: &'static str,
+    /// Subcommand path, e.g. `compare-market-caps` or `db backup`
+    pub command: String,
+    /// Arguments as given, without the program name
+    pub args: Vec<String>,
+    /// `--as-of` date the run pretended to be on
+    pub as_of: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub duration_secs: f64,
+    /// `success` or `failed`
+    pub status: &'static str,
+    pub error: Option<String>,
+    pub error_code: Option<error::ErrorCode>,
+    pub inputs: Vec<FileArtifact>,
+    pub outputs: Vec<FileArtifact>,
+    pub api_requests_total: i64,
+    /// Requests per endpoint, as in `api-usage`
+    pub api_requests: BTreeMap<String, EndpointUsage>,
+    /// Side of the forex quotes conversions used (`ask`, `bid` or `mid`),
+    /// when the run converted currencies
+    pub rate_side: Option<&'static str>,
+    /// Date a snapshot was asked for and the trading day it was fetched for
+    pub trading_day: Option<TradingDayRecord>,
+    pub warnings: Vec<String>,
+}
+
+/// Requested and effective date of a snapshot, with the exchanges closed on
+/// the requested date
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TradingDayRecord {
+    pub requested_date: String,
+    pub effective_date: String,
+    pub closed_exchanges: Vec<String>,
+}
+
+/// Everything registered so far
+struct Run {
+    manifest_path: PathBuf,
+    command: String,
+    args: Vec<String>,
+    as_of: Option<String>,
+    started_at: DateTime<Utc>,
+    inputs: BTreeMap<PathBuf, FileArtifact>,
+    outputs: BTreeSet<PathBuf>,
+    warnings: Vec<String>,
+    api_usage: Option<BTreeMap<String, EndpointUsage>>,
+    rate_side: Option<&'static str>,
+    trading_day: Option<TradingDayRecord>,
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static RUN: Mutex<Option<Run>> = Mutex::new(None);
+
+/// Start recording; the manifest is written to `manifest_path` by `finish()`
+pub fn start(manifest_path: PathBuf, command: String, args: Vec<String>, as_of: Option<String>) {
+    *RUN.lock().unwrap() = Some(Run {
+        manifest_path,
+        command,
+        args,
+        as_of,
+        started_at: Utc::now(),
+        inputs: BTreeMap::new(),
+        outputs: BTreeSet::new(),
+        warnings: Vec::new(),
+        api_usage: None,
+        rate_side: None,
+        trading_day: None,
+    });
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+fn with_run(apply: impl FnOnce(&mut Run)) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    if let Some(run) = RUN.lock().unwrap().as_mut() {
+        apply(run);
+    }
+}
+
+/// Size and SHA-256 of a file, `None` when it can't be read
+pub fn file_artifact(path: &Path) -> Option<FileArtifact> {
+    let bytes = std::fs::read(path).ok()?;
+    Some(FileArtifact {
+        path: path.display().to_string(),
+        bytes: bytes.len() as u64,
+        sha256: hex::encode(Sha256::digest(&bytes)),
+    })
+}
+
+/// Register a file the run reads. It is hashed now, so later changes to it
+/// don't alter what the manifest says was used.
+pub fn record_input(path: impl AsRef<Path>) {
+    let path = path.as_ref();
+    if !ENABLED.load(Ordering::Relaxed) || !path.is_file() {
+        return;
+    }
+    let Some(artifact) = file_artifact(path) else {
+        return;
+    };
+    with_run(|run| {
+        run.inputs.insert(path.to_path_buf(), artifact);
+    });
+}
+
+/// Register a file the run may write; it is listed if it was written
+pub fn record_output(path: impl AsRef<Path>) {
+    let path = path.as_ref().to_path_buf();
+    with_run(|run| {
+        run.outputs.insert(path);
+    });
+}
+
+/// Register a warning about the figures the run produces
+pub fn record_warning(message: impl Into<String>) {
+    let message = message.into();
+    with_run(|run| run.warnings.push(message));
+}
+
+/// Register the side of the forex quotes the run's conversions use
+pub fn record_rate_side(side: &'static str) {
+    with_run(|run| run.rate_side = Some(side));
+}
+
+/// Register the date a snapshot was asked for and the date it was fetched for
+pub fn record_trading_day(record: TradingDayRecord) {
+    with_run(|run| run.trading_day = Some(record));
+}
+
+/// Register the API usage of the run, before `api_usage::finish_run()`
+/// clears it
+pub fn record_api_usage(usage: &BTreeMap<String, EndpointUsage>) {
+    with_run(|run| run.api_usage = Some(usage.clone()));
+}
+
+/// Outputs modified at or after `since`, hashed
+fn written_outputs(outputs: &BTreeSet<PathBuf>, since: SystemTime) -> Vec<FileArtifact> {
+    outputs
+        .iter()
+        .filter(|path| {
+            std::fs::metadata(path)
+                .and_then(|m| m.modified())
+                .is_ok_and(|modified| path.is_file() && modified >= since)
+        })
+        .filter_map(|path| file_artifact(path))
+        .collect()
+}
+
+fn build_manifest(run: Run, error: Option<&anyhow::Error>) -> RunManifest {
+    let finished_at = Utc::now();
+    let api_requests = run.api_usage.unwrap_or_else(api_usage::run_usage);
+    let outputs = written_outputs(&run.outputs, SystemTime::from(run.started_at));
+    RunManifest {
+        version: env!("CARGO_PKG_VERSION"),
+        command: run.command,
+        args: run.args,
+        as_of: run.as_of,
+        started_at: run.started_at,
+        finished_at,
+        duration_secs: (finished_at - run.started_at).num_milliseconds() as f64 / 1000.0,
+        status: if error.is_some() { "failed" } else { "success" },
+        error: error.map(|e| format!("{:#}", e)),
+        error_code: error.and_then(error::code_of),
+        inputs: run.inputs.into_values().collect(),
+        outputs,
+        api_requests_total: api_requests.values().map(|u| u.count).sum(),
+        api_requests,
+        rate_side: run.rate_side,
+        trading_day: run.trading_day,
+        warnings: run.warnings,
+    }
+}
+
+/// Stop recording and write the manifest. Returns its path, or `None` when
+/// recording wasn't started (or the manifest was already written).
+pub fn finish(error: Option<&anyhow::Error>) -> Result<Option<PathBuf>> {
+    ENABLED.store(false, Ordering::Relaxed);
+    let Some(run) = RUN.lock().unwrap().take() else {
+        return Ok(None);
+    };
+    let path = run.manifest_path.clone();
+    let manifest = build_manifest(run, error);
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, serde_json::to_string_pretty(&manifest)?)
+        .with_context(|| format!("Failed to write run manifest {}", path.display()))?;
+    println!("📄 Run manifest written to {}", path.display());
+    Ok(Some(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_manifest_hashes_inputs_and_written_outputs() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("marketcaps_2025-01-01.csv");
+        std::fs::write(&input, "abc").unwrap();
+        let stale = dir.path().join("old_report.csv");
+        std::fs::write(&stale, "old").unwrap();
+        let started_at = Utc::now() + chrono::Duration::seconds(1);
+
+        let output = dir.path().join("comparison.csv");
+        let mut run = Run {
+            manifest_path: dir.path().join("run_manifest.json"),
+            command: "compare-market-caps".to_string(),
+            args: vec!["compare-market-caps".to_string()],
+            as_of: None,
+            // Files written before this time are not outputs of the run
+            started_at,
+            inputs: BTreeMap::new(),
+            outputs: BTreeSet::from([output.clone(), stale, dir.path().join("never-written.md")]),
+            warnings: vec!["No exchange rate found for EUR/CHF".to_string()],
+            api_usage: Some(BTreeMap::from([(
+                "fmp /api/v3/profile/{symbol}".to_string(),
+                EndpointUsage {
+                    count: 3,
+                    retries: 1,
+                    rate_limit_hits: 1,
+                    bytes: 1024,
+                    not_modified: 0,
+                },
+            )])),
+            rate_side: Some("mid"),
+            trading_day: None,
+        };
+        run.inputs
+            .insert(input.clone(), file_artifact(&input).unwrap());
+        std::fs::write(&output, "").unwrap();
+        let written = SystemTime::from(started_at);
+        std::fs::File::options()
+            .write(true)
+            .open(&output)
+            .unwrap()
+            .set_modified(written)
+            .unwrap();
+
+        let err = anyhow::Error::new(error::Error::TickerNotFound("NKE".to_string()));
+        let manifest = build_manifest(run, Some(&err));
+        assert_eq!(manifest.status, "failed");
+        assert_eq!(manifest.error_code, Some(error::ErrorCode::TickerNotFound));
+        assert_eq!(manifest.api_requests_total, 3);
+        assert_eq!(manifest.rate_side, Some("mid"));
+        assert_eq!(manifest.inputs.len(), 1);
+        assert_eq!(manifest.inputs[0].bytes, 3);
+        // SHA-256 of "abc"
+        assert_eq!(
+            manifest.inputs[0].sha256,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        let outputs: Vec<&str> = manifest.outputs.iter().map(|o| o.path.as_str()).collect();
+        assert_eq!(outputs, vec![output.display().to_string()]);
+
+        let json = serde_json::to_value(&manifest).unwrap();
+        assert_eq!(json["error_code"], "ticker_not_found");
+        assert_eq!(
+            json["api_requests"]["fmp /api/v3/profile/{symbol}"]["retries"],
+            1
+        );
+    }
+
+    #[test]
+    fn test_nothing_recorded_without_start() {
+        record_warning("ignored");
+        record_output("ignored.csv");
+        assert!(finish(None).unwrap().is_none());
+    }
+}
@@ -0,0 +1,493 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Company screener (`screen --where "..."`)
+//!
+//! Filters the companies of a stored snapshot (the latest by default) with a
+//! condition such as `market_cap_usd > 10e9 && pe_ratio < 20 && currency ==
+//! 'EUR'` and ranks the matches, largest first, by the USD market cap or by
+//! `--rank-by`, which takes any number expression (`-pe_ratio` ranks the
+//! cheapest first). Conditions can use the number fields of
+//! [`kpis::VARIABLES`], the custom KPIs from `[kpis]` and the text fields in
+//! [`TEXT_FIELDS`]; see [`crate::expression`] for the syntax. Ratios come from
+//! the snapshot row, or else from the profile cached in `ticker_details`.
+//! A company missing a field the condition needs does not match.
+
+use anyhow::Result;
+use chrono::DateTime;
+use csv::Writer;
+use sqlx::Row;
+use sqlx::sqlite::SqlitePool;
+use std::fs::File;
+use std::io::Write as IoWrite;
+use std::path::Path;
+
+use crate::clock;
+use crate::config::{self, OutputConfig};
+use crate::expression::{self, Expr, Inputs, Kind, Value};
+use crate::geo;
+use crate::kpis::{self, Kpis};
+use crate::rankings;
+
+/// Text fields a condition can compare with `==` and `!=`
+pub const TEXT_FIELDS: [&str; 6] = [
+    "ticker", "name", "currency", "exchange", "country", "industry",
+];
+
+/// Ranking used without `--rank-by`
+const DEFAULT_RANK_BY: &str = "market_cap_usd";
+
+/// One company of the screened snapshot with its field values
+#[derive(Debug, Clone)]
+pub struct Company {
+    pub ticker: String,
+    pub name: String,
+    pub inputs: Inputs,
+}
+
+/// A company that passed the filter
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScreenMatch {
+    pub rank: usize,
+    pub ticker: String,
+    pub name: String,
+    pub market_cap_usd: Option<f64>,
+    /// Value of the `--rank-by` expression
+    pub rank_value: Option<f64>,
+    /// Values of [`Screen::columns`], empty when missing
+    pub values: Vec<String>,
+}
+
+/// A parsed filter and ranking
+#[derive(Debug, Clone)]
+pub struct Screen {
+    pub filter_source: String,
+    pub rank_source: String,
+    filter: Expr,
+    rank_by: Expr,
+    kpis: Kpis,
+}
+
+impl Screen {
+    pub fn parse(filter: &str, rank_by: Option<&str>, kpis: Kpis) -> Result<Self> {
+        let fields = |name: &str| {
+            if TEXT_FIELDS.contains(&name) {
+                Some(Kind::Text)
+            } else if kpis::VARIABLES.contains(&name) || kpis.kpis.iter().any(|k| k.name == name) {
+                Some(Kind::Number)
+            } else {
+                None
+            }
+        };
+        let parsed = expression::parse(filter, &fields)
+            .map_err(|e| anyhow::anyhow!("Invalid --where {:?}: {}", filter, e))?;
+        if parsed.kind() != Kind::Bool {
+            anyhow::bail!(
+                "Invalid --where {:?}: must be a condition, not a {} expression",
+                filter,
+                parsed.kind()
+            );
+        }
+        let rank_source = rank_by.unwrap_or(DEFAULT_RANK_BY);
+        let rank_expr = expression::parse(rank_source, &fields)
+            .map_err(|e| anyhow::anyhow!("Invalid --rank-by {:?}: {}", rank_source, e))?;
+        if rank_expr.kind() != Kind::Number {
+            anyhow::bail!(
+                "Invalid --rank-by {:?}: must be a number, not a {} expression",
+                rank_source,
+                rank_expr.kind()
+            );
+        }
+        Ok(Self {
+            filter_source: filter.to_string(),
+            rank_source: rank_source.to_string(),
+            filter: parsed,
+            rank_by: rank_expr,
+            kpis,
+        })
+    }
+
+    /// Fields shown next to the name and market cap: those the filter and the
+    /// ranking use
+    pub fn columns(&self) -> Vec<&str> {
+        let mut columns: Vec<&str> = Vec::new();
+        for field in self
+            .filter
+            .fields()
+            .into_iter()
+            .chain(self.rank_by.fields())
+        {
+            if !["ticker", "name", "market_cap_usd"].contains(&field) && !columns.contains(&field) {
+                columns.push(field);
+            }
+        }
+        columns
+    }
+
+    /// Companies passing the filter, best ranked first; equal values are
+    /// ordered by name and ticker
+    pub fn apply(&self, companies: &[Company]) -> Vec<ScreenMatch> {
+        let columns = self.columns();
+        let mut matches: Vec<ScreenMatch> = companies
+            .iter()
+            .filter_map(|company| {
+                let mut inputs = company.inputs.clone();
+                for (kpi, value) in self.kpis.kpis.iter().zip(self.kpis.evaluate(&inputs)) {
+                    inputs.set_number(&kpi.name, value);
+                }
+                if self.filter.boolean(&inputs) != Some(true) {
+                    return None;
+                }
+                Some(ScreenMatch {
+                    rank: 0,
+                    ticker: company.ticker.clone(),
+                    name: company.name.clone(),
+                    market_cap_usd: match inputs.get("market_cap_usd") {
+                        Some(Value::Number(n)) => Some(*n),
+                        _ => None,
+                    },
+                    rank_value: self.rank_by.number(&inputs),
+                    values: columns
+                        .iter()
+                        .map(|field| format_value(inputs.get(field)))
+                        .collect(),
+                })
+            })
+            .collect();
+        matches.sort_by(|a, b| {
+            rankings::rank_order(
+                (a.rank_value, &a.name, &a.ticker),
+                (b.rank_value, &b.name, &b.ticker),
+            )
+        });
+        for (i, m) in matches.iter_mut().enumerate() {
+            m.rank = i + 1;
+        }
+        matches
+    }
+}
+
+fn format_number(value: f64) -> String {
+    if value.fract() == 0.0 {
+        format!("{:.0}", value)
+    } else {
+        format!("{:.4}", value)
+    }
+}
+
+fn format_value(value: Option<&Value>) -> String {
+    match value {
+        Some(Value::Number(n)) => format_number(*n),
+        Some(Value::Text(s)) => s.clone(),
+        Some(Value::Bool(b)) => b.to_string(),
+        None => String::new(),
+    }
+}
+
+/// Companies of one snapshot with every screenable field, largest first
+pub async fn load_companies(pool: &SqlitePool, timestamp: i64) -> Result<Vec<Company>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT m.ticker, m.name, m.original_currency AS currency, m.exchange,
+            d.country, d.industry,
+            CAST(m.market_cap_original AS REAL) AS market_cap_original,
+            CAST(m.market_cap_eur AS REAL) AS market_cap_eur,
+            CAST(m.market_cap_usd AS REAL) AS market_cap_usd,
+            CAST(m.price AS REAL) AS price,
+            CAST(m.revenue AS REAL) AS revenue,
+            CAST(m.revenue_usd AS REAL) AS revenue_usd,
+            CAST(COALESCE(m.employees, CAST(REPLACE(d.employees, ',', '') AS INTEGER)) AS REAL)
+                AS employees,
+            CAST(COALESCE(m.eps, d.eps) AS REAL) AS eps,
+            CAST(COALESCE(m.pe_ratio, d.pe_ratio) AS REAL) AS pe_ratio,
+            CAST(COALESCE(m.de_ratio, d.debt_equity_ratio) AS REAL) AS de_ratio,
+            CAST(COALESCE(m.roe, d.roe) AS REAL) AS roe,
+            CAST(COALESCE(m.working_capital_ratio, d.working_capital_ratio) AS REAL)
+                AS working_capital_ratio,
+            CAST(COALESCE(m.quick_ratio, d.quick_ratio) AS REAL) AS quick_ratio
+        FROM market_caps m
+        LEFT JOIN ticker_details d ON d.ticker = m.ticker
+        WHERE m.timestamp = ?1
+        ORDER BY m.market_cap_usd DESC, m.ticker
+        "#,
+    )
+    .bind(timestamp)
+    .fetch_all(pool)
+    .await?;
+
+    let mut companies: Vec<Company> = rows
+        .into_iter()
+        .map(|row| {
+            let mut inputs = Inputs::default();
+            for field in TEXT_FIELDS {
+                inputs.set_text(field, row.get::<Option<String>, _>(field).as_deref());
+            }
+            for field in kpis::VARIABLES {
+                let column = if field == "market_cap" {
+                    "market_cap_usd"
+                } else {
+                    field
+                };
+                inputs.set_number(field, row.get::<Option<f64>, _>(column));
+            }
+            Company {
+                ticker: row.get("ticker"),
+                name: row.get("name"),
+                inputs,
+            }
+        })
+        .collect();
+    rankings::truncate_to_top(&mut companies);
+    Ok(companies)
+}
+
+fn write_csv(path: &Path, screen: &Screen, matches: &[ScreenMatch]) -> Result<()> {
+    let mut writer = Writer::from_path(path)?;
+    let mut headers = vec!["Rank", "Ticker", "Name", "Market Cap (USD)"];
+    headers.extend(screen.columns());
+    if screen.rank_source != DEFAULT_RANK_BY {
+        headers.push(&screen.rank_source);
+    }
+    writer.write_record(&headers)?;
+    for m in matches {
+        let mut record = vec![
+            m.rank.to_string(),
+            m.ticker.clone(),
+            m.name.clone(),
+            m.market_cap_usd
+                .map(|v| format!("{:.0}", v))
+                .unwrap_or_default(),
+        ];
+        record.extend(m.values.iter().cloned());
+        if screen.rank_source != DEFAULT_RANK_BY {
+            record.push(m.rank_value.map(format_number).unwrap_or_default());
+        }
+        writer.write_record(&record)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_markdown(
+    path: &Path,
+    screen: &Screen,
+    matches: &[ScreenMatch],
+    total: usize,
+    date: &str,
+) -> Result<()> {
+    let mut file = File::create(path)?;
+    writeln!(file, "# Company Screen")?;
+    writeln!(file)?;
+    writeln!(file, "- Snapshot: {}", date)?;
+    writeln!(file, "- Filter: `{}`", screen.filter_source)?;
+    writeln!(file, "- Ranked by: `{}`", screen.rank_source)?;
+    writeln!(file, "- Matches: {} of {} companies", matches.len(), total)?;
+    writeln!(file)?;
+
+    if matches.is_empty() {
+        writeln!(file, "No company matches the filter.")?;
+    } else {
+        let columns = screen.columns();
+        let mut header = String::from("| Rank | Ticker | Name | Market Cap (USD) |");
+        let mut rule = String::from("|-----:|--------|------|-----------------:|");
+        for column in &columns {
+            header.push_str(&format!(" {} |", column));
+            rule.push_str("------|");
+        }
+        writeln!(file, "{}", header)?;
+        writeln!(file, "{}", rule)?;
+        for m in matches {
+            let market_cap = m
+                .market_cap_usd
+                .map(|v| format!("${:.2}B", v / 1_000_000_000.0))
+                .unwrap_or_else(|| "N/A".to_string());
+            write!(
+                file,
+                "| {} | {} | {} | {} |",
+                m.rank, m.ticker, m.name, market_cap
+            )?;
+            for value in &m.values {
+                write!(file, " {} |", value)?;
+            }
+            writeln!(file)?;
+        }
+    }
+    writeln!(file)?;
+    writeln!(file, "---")?;
+    writeln!(
+        file,
+        "*Generated on {}*",
+        clock::now().format("%Y-%m-%d %H:%M:%S")
+    )?;
+    Ok(())
+}
+
+/// Screen a stored snapshot and write the ranked matches as CSV and markdown
+pub async fn screen(
+    pool: &SqlitePool,
+    filter: &str,
+    rank_by: Option<&str>,
+    date: Option<&str>,
+    limit: Option<usize>,
+) -> Result<()> {
+    let screen = Screen::parse(filter, rank_by, Kpis::load()?)?;
+    let Some(timestamp) = geo::snapshot_timestamp(pool, date).await? else {
+        anyhow::bail!(
+            "No market caps stored for {}; fetch them with fetch-specific-date-market-caps",
+            date.unwrap_or("any date")
+        );
+    };
+    let date = DateTime::from_timestamp(timestamp, 0)
+        .map(|dt| dt.format("%Y-%m-%d").to_string())
+        .unwrap_or_default();
+
+    let companies = load_companies(pool, timestamp).await?;
+    let mut matches = screen.apply(&companies);
+    if let Some(limit) = limit {
+        matches.truncate(limit);
+    }
+    println!(
+        "🔎 {} of {} companies on {} match {}",
+        matches.len(),
+        companies.len(),
+        date,
+        screen.filter_source
+    );
+    for m in matches.iter().take(rankings::section_size()) {
+        println!("  {:>3}. {:<10} {}", m.rank, m.ticker, m.name);
+    }
+
+    let output = config::load_output_config();
+    output.ensure_directory()?;
+    let stamp = OutputConfig::timestamp();
+    let csv_path = output.file_path_at("screen", &date, &stamp, "csv");
+    write_csv(&csv_path, &screen, &matches)?;
+    println!("✅ Screen results exported to {}", csv_path.display());
+
+    let md_path = output.file_path_at("screen", &format!("{}_summary", date), &stamp, "md");
+    write_markdown(&md_path, &screen, &matches, companies.len(), &date)?;
+    println!("✅ Screen report exported to {}", md_path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use std::collections::BTreeMap;
+
+    fn company(ticker: &str, currency: &str, market_cap: f64, pe_ratio: Option<f64>) -> Company {
+        let mut inputs = Inputs::default();
+        inputs
+            .set_text("ticker", Some(ticker))
+            .set_text("currency", Some(currency))
+            .set_number("market_cap_usd", Some(market_cap))
+            .set_number("market_cap", Some(market_cap))
+            .set_number("revenue_usd", Some(market_cap / 4.0))
+            .set_number("pe_ratio", pe_ratio);
+        Company {
+            ticker: ticker.to_string(),
+            name: format!("{} Group", ticker),
+            inputs,
+        }
+    }
+
+    fn companies() -> Vec<Company> {
+        vec![
+            company("MC.PA", "EUR", 300e9, Some(25.0)),
+            company("ITX.MC", "EUR", 150e9, Some(19.5)),
+            company("KER.PA", "EUR", 20e9, Some(12.0)),
+            company("ADS.DE", "EUR", 40e9, None),
+            company("NKE", "USD", 120e9, Some(18.0)),
+            company("BOSS.DE", "EUR", 3e9, Some(10.0)),
+        ]
+    }
+
+    fn tickers(matches: &[ScreenMatch]) -> Vec<&str> {
+        matches.iter().map(|m| m.ticker.as_str()).collect()
+    }
+
+    #[test]
+    fn test_filter_and_rank() {
+        let screen = Screen::parse(
+            "market_cap_usd > 10e9 && pe_ratio < 20 && currency == 'EUR'",
+            None,
+            Kpis::default(),
+        )
+        .unwrap();
+        let matches = screen.apply(&companies());
+        // ADS.DE has no P/E, so it can't pass pe_ratio < 20
+        assert_eq!(tickers(&matches), vec!["ITX.MC", "KER.PA"]);
+        assert_eq!(matches[1].rank, 2);
+        assert_eq!(screen.columns(), vec!["pe_ratio", "currency"]);
+        assert_eq!(matches[0].values, vec!["19.5000", "EUR"]);
+
+        let cheapest = Screen::parse("pe_ratio < 20", Some("-pe_ratio"), Kpis::default()).unwrap();
+        assert_eq!(
+            tickers(&cheapest.apply(&companies())),
+            vec!["BOSS.DE", "KER.PA", "NKE", "ITX.MC"]
+        );
+    }
+
+    #[test]
+    fn test_custom_kpis_are_fields() {
+        let kpis = Kpis::from_definitions(&BTreeMap::from([(
+            "ev_to_sales".to_string(),
+            "market_cap / revenue_usd".to_string(),
+        )]))
+        .unwrap();
+        let screen = Screen::parse("ev_to_sales >= 4", Some("ev_to_sales"), kpis).unwrap();
+        let matches = screen.apply(&companies());
+        assert_eq!(matches.len(), 6);
+        assert_eq!(matches[0].rank_value, Some(4.0));
+        assert_eq!(screen.columns(), vec!["ev_to_sales"]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |filter: &str, rank_by: Option<&str>| {
+            Screen::parse(filter, rank_by, Kpis::default())
+                .unwrap_err()
+                .to_string()
+        };
+        assert!(error("sales > 1", None).contains("unknown field \"sales\""));
+        assert!(error("market_cap_usd", None).contains("must be a condition"));
+        assert!(error("pe_ratio > 1", Some("currency")).contains("must be a number"));
+    }
+
+    #[tokio::test]
+    async fn test_load_companies_falls_back_to_profile_ratios() -> Result<()> {
+        let pool = db::create_db_pool("sqlite::memory:").await?;
+        sqlx::query(
+            "INSERT INTO market_caps (ticker, name, original_currency, market_cap_usd, exchange, timestamp)
+             VALUES ('MC.PA', 'LVMH', 'EUR', 300.0, 'PAR', 100), ('NKE', 'Nike', 'USD', 120.0, 'NYSE', 100),
+                    ('NKE', 'Nike', 'USD', 110.0, 'NYSE', 50)",
+        )
+        .execute(&pool)
+        .await?;
+        sqlx::query(
+            "INSERT INTO ticker_details (ticker, pe_ratio, country, employees)
+             VALUES ('MC.PA', 24.5, 'FR', '213,000')",
+        )
+        .execute(&pool)
+        .await?;
+
+        let companies = load_companies(&pool, 100).await?;
+        assert_eq!(companies.len(), 2);
+        let lvmh = &companies[0].inputs;
+        assert_eq!(lvmh.get("pe_ratio"), Some(&Value::Number(24.5)));
+        assert_eq!(lvmh.get("employees"), Some(&Value::Number(213_000.0)));
+        assert_eq!(lvmh.get("market_cap"), Some(&Value::Number(300.0)));
+        assert_eq!(lvmh.get("country"), Some(&Value::Text("FR".to_string())));
+        assert_eq!(companies[1].inputs.get("pe_ratio"), None);
+
+        let screen = Screen::parse(
+            "country == 'FR' || currency == 'USD'",
+            None,
+            Kpis::default(),
+        )?;
+        assert_eq!(tickers(&screen.apply(&companies)), vec!["MC.PA", "NKE"]);
+        Ok(())
+    }
+}
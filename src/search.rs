@@ -0,0 +1,236 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Full-text search over the stored companies (`search`, `/api/search`)
+//!
+//! The FTS5 table `company_search` holds each ticker with its latest name and
+//! the description from `ticker_details`. It is rebuilt after every
+//! `export-combined` run (and on `search --reindex`), which takes milliseconds
+//! for a few hundred companies. Words are stemmed, so "handbags" finds
+//! "handbag", and accents are ignored ("hermes" finds "Hermès").
+
+use anyhow::Result;
+use serde::Serialize;
+use sqlx::Row;
+use sqlx::sqlite::SqlitePool;
+
+use crate::utils;
+
+/// Matches returned when no limit is given
+pub const DEFAULT_LIMIT: usize = 20;
+
+/// Largest number of matches returned
+pub const MAX_LIMIT: usize = 100;
+
+/// One company matching a query, best match first
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SearchResult {
+    pub ticker: String,
+    pub name: String,
+    /// Matching part of the description, matched words in `**`
+    pub snippet: Option<String>,
+    /// BM25 relevance, higher is better
+    pub score: f64,
+    pub market_cap_eur: Option<f64>,
+    pub market_cap_usd: Option<f64>,
+    /// Date of the snapshot the market caps are from
+    pub date: Option<String>,
+}
+
+/// Refill the index from the latest name of every stored ticker and its
+/// description. Returns the number of indexed companies.
+pub async fn rebuild_index(pool: &SqlitePool) -> Result<u64> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM company_search")
+        .execute(&mut *tx)
+        .await?;
+    let indexed = sqlx::query(
+        r#"
+        INSERT INTO company_search (ticker, name, description)
+        SELECT m.ticker, m.name, COALESCE(d.description, '')
+        FROM market_caps m
+        LEFT JOIN ticker_details d ON d.ticker = m.ticker
+        WHERE m.timestamp = (SELECT MAX(x.timestamp) FROM market_caps x WHERE x.ticker = m.ticker)
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    tx.commit().await?;
+    Ok(indexed)
+}
+
+/// FTS5 query for free text: every word as a quoted prefix, any of them
+/// matching. `None` when the text has no words.
+pub fn fts_query(text: &str) -> Option<String> {
+    let terms: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| format!("\"{}\"*", word.to_lowercase()))
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" OR "))
+}
+
+/// Companies matching free text, ranked by relevance (tickers weigh most,
+/// then names, then descriptions), with their latest market caps
+pub async fn search(pool: &SqlitePool, text: &str, limit: usize) -> Result<Vec<SearchResult>> {
+    let Some(query) = fts_query(text) else {
+        anyhow::bail!("Search query has no words: {:?}", text);
+    };
+    let limit = limit.clamp(1, MAX_LIMIT) as i64;
+
+    let rows = sqlx::query(
+        r#"
+        SELECT s.ticker, s.name,
+            NULLIF(snippet(company_search, 2, '**', '**', '…', 16), '') AS snippet,
+            -bm25(company_search, 10.0, 5.0, 1.0) AS score,
+            CAST(m.market_cap_eur AS REAL) AS market_cap_eur,
+            CAST(m.market_cap_usd AS REAL) AS market_cap_usd,
+            m.timestamp
+        FROM company_search s
+        LEFT JOIN market_caps m ON m.ticker = s.ticker
+            AND m.timestamp = (SELECT MAX(x.timestamp) FROM market_caps x WHERE x.ticker = s.ticker)
+        WHERE company_search MATCH ?
+        ORDER BY score DESC, m.market_cap_usd DESC, s.ticker
+        LIMIT ?
+        "#,
+    )
+    .bind(query)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| SearchResult {
+            ticker: row.get("ticker"),
+            name: row.get("name"),
+            snippet: row.get("snippet"),
+            score: row.get("score"),
+            market_cap_eur: row.get("market_cap_eur"),
+            market_cap_usd: row.get("market_cap_usd"),
+            date: row
+                .get::<Option<i64>, _>("timestamp")
+                .and_then(|t| chrono::DateTime::from_timestamp(t, 0))
+                .map(|dt| dt.format("%Y-%m-%d").to_string()),
+        })
+        .collect())
+}
+
+/// Build the index when it is still empty (a database that predates it).
+/// Returns the number of indexed companies when it was built.
+pub async fn ensure_index(pool: &SqlitePool) -> Result<Option<u64>> {
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM company_search")
+        .fetch_one(pool)
+        .await?;
+    if count > 0 {
+        return Ok(None);
+    }
+    Ok(Some(rebuild_index(pool).await?))
+}
+
+/// Print the companies matching a query; the index is built first when it
+/// is empty or `reindex` is set
+pub async fn search_command(
+    pool: &SqlitePool,
+    text: &str,
+    limit: usize,
+    reindex: bool,
+) -> Result<()> {
+    let indexed = if reindex {
+        Some(rebuild_index(pool).await?)
+    } else {
+        ensure_index(pool).await?
+    };
+    if let Some(indexed) = indexed {
+        println!("✅ Search index rebuilt ({} companies)", indexed);
+    }
+
+    let results = search(pool, text, limit).await?;
+    if results.is_empty() {
+        println!("No companies match {:?}", text);
+        return Ok(());
+    }
+    println!("🔍 {} companies match {:?}:", results.len(), text);
+    for (i, result) in results.iter().enumerate() {
+        println!(
+            "{:>3}. {:<10} {:<40} {:>10}",
+            i + 1,
+            result.ticker,
+            result.name,
+            result
+                .market_cap_usd
+                .map_or_else(|| "N/A".to_string(), utils::format_usd)
+        );
+        if let Some(snippet) = &result.snippet {
+            println!("     {}", snippet);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    #[test]
+    fn test_fts_query() {
+        assert_eq!(
+            fts_query("Luxury handbags").as_deref(),
+            Some("\"luxury\"* OR \"handbags\"*")
+        );
+        // Operators and quotes are not passed through to FTS5
+        assert_eq!(
+            fts_query("H&M \"NEAR\" -x").as_deref(),
+            Some("\"h\"* OR \"m\"* OR \"near\"* OR \"x\"*")
+        );
+        assert_eq!(fts_query(" .-* "), None);
+    }
+
+    #[tokio::test]
+    async fn test_search_ranks_matches_with_latest_market_caps() {
+        let pool = db::create_db_pool("sqlite::memory:").await.unwrap();
+        sqlx::query("ALTER TABLE ticker_details ADD COLUMN ceo TEXT")
+            .execute(&pool)
+            .await
+            .unwrap();
+        for sql in [
+            "INSERT INTO market_caps (ticker, name, market_cap_eur, market_cap_usd, timestamp) VALUES
+                ('RMS.PA', 'Hermès International', 200, 220, 100),
+                ('RMS.PA', 'Hermes International', 250, 270, 200),
+                ('KER.PA', 'Kering', 20, 22, 200),
+                ('NKE', 'Nike', 90, 100, 200)",
+            "INSERT INTO ticker_details (ticker, description) VALUES
+                ('RMS.PA', 'Designs leather goods, handbags and silk scarves.'),
+                ('KER.PA', 'Luxury group owning Gucci; sells a handbag line.'),
+                ('NKE', 'Athletic footwear and apparel.')",
+        ] {
+            sqlx::query(sql).execute(&pool).await.unwrap();
+        }
+        assert_eq!(rebuild_index(&pool).await.unwrap(), 3);
+
+        let results = search(&pool, "luxury handbags", 10).await.unwrap();
+        let tickers: Vec<&str> = results.iter().map(|r| r.ticker.as_str()).collect();
+        // Kering matches both words, Hermès one
+        assert_eq!(tickers, vec!["KER.PA", "RMS.PA"]);
+        assert_eq!(results[1].name, "Hermes International");
+        assert_eq!(results[1].market_cap_usd, Some(270.0));
+        assert!(
+            results[1]
+                .snippet
+                .as_deref()
+                .unwrap()
+                .contains("**handbags**")
+        );
+
+        // Ticker and accent-insensitive name matches
+        assert_eq!(search(&pool, "nke", 10).await.unwrap()[0].ticker, "NKE");
+        assert_eq!(
+            search(&pool, "hermès", 10).await.unwrap()[0].ticker,
+            "RMS.PA"
+        );
+        assert!(search(&pool, "--", 10).await.is_err());
+    }
+}
@@ -0,0 +1,108 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Coordinated shutdown of `serve`
+//!
+//! SIGTERM or SIGINT flips one shared flag. The HTTP server stops accepting
+//! connections, and the NATS worker stops taking jobs and lets running ones
+//! finish. Both are bounded by `[jobs] shutdown_timeout_secs`, then the
+//! SQLite pool is closed. This is what Kubernetes needs for rolling deploys.
+
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// Resolves once shutdown has been requested; cheap to clone
+#[derive(Clone)]
+pub struct Shutdown(watch::Receiver<bool>);
+
+impl Shutdown {
+    /// Wait until shutdown is requested (returns at once if it already was)
+    pub async fn wait(&self) {
+        let mut receiver = self.0.clone();
+        // An error means the trigger was dropped, which also ends the run
+        let _ = receiver.wait_for(|requested| *requested).await;
+    }
+}
+
+/// Requests shutdown for every [`Shutdown`] made from the same channel
+#[derive(Clone)]
+pub struct Trigger(Arc<watch::Sender<bool>>);
+
+impl Trigger {
+    pub fn request(&self) {
+        self.0.send_replace(true);
+    }
+}
+
+pub fn channel() -> (Trigger, Shutdown) {
+    let (sender, receiver) = watch::channel(false);
+    (Trigger(Arc::new(sender)), Shutdown(receiver))
+}
+
+/// Wait for SIGINT (Ctrl-C) or, on Unix, SIGTERM
+pub async fn os_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            eprintln!("⚠️  Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                eprintln!("⚠️  Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+/// Request shutdown on the first SIGINT/SIGTERM
+pub fn spawn_signal_listener(trigger: Trigger) {
+    tokio::spawn(async move {
+        os_signal().await;
+        println!("🛑 Shutdown requested, draining requests and jobs...");
+        trigger.request();
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_wait_resolves_after_request() {
+        let (trigger, shutdown) = channel();
+
+        let waiter = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.wait().await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!waiter.is_finished());
+
+        trigger.request();
+        tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .expect("waiter did not finish")
+            .unwrap();
+        // Late waiters return at once
+        tokio::time::timeout(Duration::from_secs(1), shutdown.wait())
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,548 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Field-level diffs between two snapshots
+//!
+//! Unlike `compare-market-caps`, which is about market cap deltas, this lists
+//! every field that changed per ticker (name, currency, market cap, price,
+//! employees, CEO), plus tickers that appeared or disappeared. It is meant to
+//! catch silent data changes, such as FMP switching a company's reporting
+//! currency. A snapshot is a CSV file, the latest CSV for a date, or the
+//! `market_caps` rows of a date in the database. Only fields present on both
+//! sides are compared.
+
+use anyhow::{Context, Result};
+use chrono::{NaiveDate, NaiveTime};
+use csv::{Reader, Writer};
+use sqlx::Row;
+use sqlx::sqlite::SqlitePool;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::path::Path;
+
+use crate::advanced_comparisons::find_csv_for_date;
+use crate::config::{self, OutputConfig};
+use crate::run_context;
+
+/// A field compared between snapshots
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Field {
+    Name,
+    Currency,
+    MarketCap,
+    Price,
+    Employees,
+    Ceo,
+}
+
+impl Field {
+    pub const ALL: [Field; 6] = [
+        Field::Name,
+        Field::Currency,
+        Field::MarketCap,
+        Field::Price,
+        Field::Employees,
+        Field::Ceo,
+    ];
+
+    pub fn parse(value: &str) -> Result<Self> {
+        Field::ALL
+            .into_iter()
+            .find(|field| field.key() == value.trim().to_lowercase())
+            .with_context(|| {
+                format!(
+                    "Unknown field '{}': use {}",
+                    value,
+                    Field::ALL.map(|f| f.key()).join(", ")
+                )
+            })
+    }
+
+    /// Name used in `--fields` and the diff CSV
+    pub fn key(&self) -> &'static str {
+        match self {
+            Field::Name => "name",
+            Field::Currency => "currency",
+            Field::MarketCap => "market_cap",
+            Field::Price => "price",
+            Field::Employees => "employees",
+            Field::Ceo => "ceo",
+        }
+    }
+
+    /// Column holding the field in export CSVs
+    fn header(&self) -> &'static str {
+        match self {
+            Field::Name => "Name",
+            Field::Currency => "Original Currency",
+            Field::MarketCap => "Market Cap (Original)",
+            Field::Price => "Price",
+            Field::Employees => "Employees",
+            Field::Ceo => "CEO",
+        }
+    }
+
+    fn is_numeric(&self) -> bool {
+        matches!(self, Field::MarketCap | Field::Price | Field::Employees)
+    }
+}
+
+/// Field values per ticker of one snapshot
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    pub label: String,
+    /// Fields this snapshot carries
+    pub fields: Vec<Field>,
+    pub rows: BTreeMap<String, HashMap<Field, String>>,
+}
+
+impl Snapshot {
+    fn name(&self, ticker: &str) -> String {
+        self.rows
+            .get(ticker)
+            .and_then(|values| values.get(&Field::Name))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+/// Read a snapshot from an export CSV; columns that are missing are not compared
+pub fn read_snapshot_csv(path: &str) -> Result<Snapshot> {
+    let file = File::open(path).with_context(|| format!("Failed to open CSV file: {}", path))?;
+    run_context::record_input(path);
+    let mut reader = Reader::from_reader(file);
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| headers.iter().position(|h| h == name);
+
+    let ticker_column =
+        column("Ticker").with_context(|| format!("{} has no Ticker column", path))?;
+    let columns: Vec<(Field, usize)> = Field::ALL
+        .into_iter()
+        .filter_map(|field| column(field.header()).map(|index| (field, index)))
+        .collect();
+
+    let mut rows = BTreeMap::new();
+    for record in reader.records() {
+        let record = record?;
+        let values = columns
+            .iter()
+            .map(|(field, index)| (*field, record.get(*index).unwrap_or("").trim().to_string()))
+            .collect();
+        rows.insert(record.get(ticker_column).unwrap_or("").to_string(), values);
+    }
+
+    Ok(Snapshot {
+        label: Path::new(path)
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| path.to_string()),
+        fields: columns.into_iter().map(|(field, _)| field).collect(),
+        rows,
+    })
+}
+
+/// Read the `market_caps` rows of a date. The database keeps no CEO history,
+/// so the CEO is not compared.
+pub async fn load_snapshot_db(pool: &SqlitePool, date: &str) -> Result<Snapshot> {
+    let timestamp = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map(|d| d.and_time(NaiveTime::MIN).and_utc().timestamp())
+        .with_context(|| format!("Invalid date format: {}", date))?;
+
+    let rows = sqlx::query(
+        r#"
+        SELECT
+            ticker,
+            name,
+            original_currency,
+            CAST(market_cap_original AS REAL) as market_cap_original,
+            CAST(price AS REAL) as price,
+            employees
+        FROM market_caps
+        WHERE timestamp = ?
+        "#,
+    )
+    .bind(timestamp)
+    .fetch_all(pool)
+    .await?;
+
+    if rows.is_empty() {
+        anyhow::bail!("No market caps stored for {}", date);
+    }
+
+    let rows = rows
+        .into_iter()
+        .map(|row| {
+            let market_cap: Option<f64> = row.get("market_cap_original");
+            let price: Option<f64> = row.get("price");
+            let employees: Option<i64> = row.get("employees");
+            let values = HashMap::from([
+                (Field::Name, row.get::<String, _>("name")),
+                (
+                    Field::Currency,
+                    row.get::<Option<String>, _>("original_currency")
+                        .unwrap_or_default(),
+                ),
+                (
+                    Field::MarketCap,
+                    market_cap.map(|v| format!("{:.0}", v)).unwrap_or_default(),
+                ),
+                (
+                    Field::Price,
+                    price.map(|v| v.to_string()).unwrap_or_default(),
+                ),
+                (
+                    Field::Employees,
+                    employees.map(|v| v.to_string()).unwrap_or_default(),
+                ),
+            ]);
+            (row.get::<String, _>("ticker"), values)
+        })
+        .collect();
+
+    Ok(Snapshot {
+        label: date.to_string(),
+        fields: Field::ALL
+            .into_iter()
+            .filter(|f| *f != Field::Ceo)
+            .collect(),
+        rows,
+    })
+}
+
+/// What happened to a ticker between the snapshots
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+impl ChangeKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Removed => "removed",
+            ChangeKind::Changed => "changed",
+        }
+    }
+}
+
+/// One line of the diff: a changed field, or an added or removed ticker
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffRow {
+    pub ticker: String,
+    pub name: String,
+    pub change: ChangeKind,
+    pub field: Option<Field>,
+    pub from: String,
+    pub to: String,
+}
+
+fn values_equal(field: Field, from: &str, to: &str) -> bool {
+    if field.is_numeric()
+        && let (Ok(a), Ok(b)) = (from.parse::<f64>(), to.parse::<f64>())
+    {
+        // Tolerate rounding between CSV exports and database values
+        return (a - b).abs() <= 1e-9 * a.abs().max(b.abs());
+    }
+    from == to
+}
+
+/// Fields out of `requested` that both snapshots carry
+pub fn comparable_fields(from: &Snapshot, to: &Snapshot, requested: &[Field]) -> Vec<Field> {
+    requested
+        .iter()
+        .copied()
+        .filter(|field| from.fields.contains(field) && to.fields.contains(field))
+        .collect()
+}
+
+/// Changed fields and added/removed tickers, by ticker
+pub fn diff_snapshots(from: &Snapshot, to: &Snapshot, fields: &[Field]) -> Vec<DiffRow> {
+    let mut tickers: Vec<&String> = from.rows.keys().chain(to.rows.keys()).collect();
+    tickers.sort();
+    tickers.dedup();
+
+    let mut diff = Vec::new();
+    for ticker in tickers {
+        match (from.rows.get(ticker), to.rows.get(ticker)) {
+            (Some(_), None) => diff.push(DiffRow {
+                ticker: ticker.clone(),
+                name: from.name(ticker),
+                change: ChangeKind::Removed,
+                field: None,
+                from: from.label.clone(),
+                to: String::new(),
+            }),
+            (None, Some(_)) => diff.push(DiffRow {
+                ticker: ticker.clone(),
+                name: to.name(ticker),
+                change: ChangeKind::Added,
+                field: None,
+                from: String::new(),
+                to: to.label.clone(),
+            }),
+            (Some(old), Some(new)) => {
+                for field in fields {
+                    let before = old.get(field).map(String::as_str).unwrap_or("");
+                    let after = new.get(field).map(String::as_str).unwrap_or("");
+                    if !values_equal(*field, before, after) {
+                        diff.push(DiffRow {
+                            ticker: ticker.clone(),
+                            name: to.name(ticker),
+                            change: ChangeKind::Changed,
+                            field: Some(*field),
+                            from: before.to_string(),
+                            to: after.to_string(),
+                        });
+                    }
+                }
+            }
+            (None, None) => {}
+        }
+    }
+    diff
+}
+
+fn export_diff_csv(
+    diff: &[DiffRow],
+    from: &Snapshot,
+    to: &Snapshot,
+    output: &OutputConfig,
+) -> Result<String> {
+    output.ensure_directory()?;
+    let path = output.file_path(
+        "snapshot_diff",
+        &format!("{}_to_{}", from.label, to.label),
+        "csv",
+    );
+    let mut writer = Writer::from_path(&path)?;
+    writer.write_record(["Ticker", "Name", "Change", "Field", "From", "To"])?;
+    for row in diff {
+        writer.write_record([
+            row.ticker.as_str(),
+            row.name.as_str(),
+            row.change.as_str(),
+            row.field.map(|f| f.key()).unwrap_or(""),
+            row.from.as_str(),
+            row.to.as_str(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(path.display().to_string())
+}
+
+async fn load_snapshot(
+    pool: &SqlitePool,
+    source: &str,
+    from_db: bool,
+    watchlist: Option<&str>,
+) -> Result<Snapshot> {
+    if Path::new(source).is_file() {
+        return read_snapshot_csv(source);
+    }
+    if from_db {
+        return load_snapshot_db(pool, source).await;
+    }
+    let mut snapshot = read_snapshot_csv(&find_csv_for_date(source, watchlist)?)?;
+    snapshot.label = source.to_string();
+    Ok(snapshot)
+}
+
+/// Diff two snapshots (CSV paths or dates) and export the changes
+pub async fn diff_snapshot_sources(
+    pool: &SqlitePool,
+    from: &str,
+    to: &str,
+    from_db: bool,
+    fields: &[String],
+    watchlist: Option<&str>,
+) -> Result<()> {
+    let requested = if fields.is_empty() {
+        Field::ALL.to_vec()
+    } else {
+        fields
+            .iter()
+            .map(|f| Field::parse(f))
+            .collect::<Result<Vec<_>>>()?
+    };
+
+    let from = load_snapshot(pool, from, from_db, watchlist).await?;
+    let to = load_snapshot(pool, to, from_db, watchlist).await?;
+    println!("Diffing snapshots {} and {}", from.label, to.label);
+
+    let fields = comparable_fields(&from, &to, &requested);
+    for field in requested.iter().filter(|f| !fields.contains(f)) {
+        println!(
+            "⚠️  Skipping {}: not available in both snapshots",
+            field.key()
+        );
+    }
+
+    let diff = diff_snapshots(&from, &to, &fields);
+    if diff.is_empty() {
+        println!("✅ No differences in {} tickers", to.rows.len());
+        return Ok(());
+    }
+
+    let mut current = "";
+    for row in &diff {
+        if row.ticker != current {
+            println!("\n{} ({})", row.ticker, row.name);
+            current = &row.ticker;
+        }
+        match row.field {
+            Some(field) => println!(
+                "  {}: {} -> {}",
+                field.key(),
+                if row.from.is_empty() { "-" } else { &row.from },
+                if row.to.is_empty() { "-" } else { &row.to }
+            ),
+            None => println!("  {}", row.change.as_str()),
+        }
+    }
+
+    let changed = diff
+        .iter()
+        .filter(|r| r.change == ChangeKind::Changed)
+        .map(|r| &r.ticker)
+        .collect::<std::collections::HashSet<_>>()
+        .len();
+    let added = diff
+        .iter()
+        .filter(|r| r.change == ChangeKind::Added)
+        .count();
+    let removed = diff
+        .iter()
+        .filter(|r| r.change == ChangeKind::Removed)
+        .count();
+    println!(
+        "\n📊 {} tickers changed, {} added, {} removed",
+        changed, added, removed
+    );
+
+    let path = export_diff_csv(&diff, &from, &to, &config::load_output_config())?;
+    println!("✅ Snapshot diff exported to {}", path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn write_csv(dir: &TempDir, name: &str, content: &str) -> String {
+        let path = dir.path().join(name);
+        fs::write(&path, content).unwrap();
+        path.display().to_string()
+    }
+
+    #[test]
+    fn test_field_parse() {
+        assert_eq!(Field::parse("Market_Cap").unwrap(), Field::MarketCap);
+        assert_eq!(Field::parse("ceo").unwrap(), Field::Ceo);
+        assert!(Field::parse("revenue").is_err());
+    }
+
+    #[test]
+    fn test_diff_lists_changed_fields_added_and_removed() {
+        let dir = TempDir::new().unwrap();
+        let from = read_snapshot_csv(&write_csv(
+            &dir,
+            "from.csv",
+            "Symbol,Ticker,Name,Market Cap (Original),Original Currency,Employees,CEO\n\
+             NKE,NKE,Nike,100000000000,USD,79400,John Donahoe\n\
+             ITX.MC,ITX.MC,Inditex,150000000000,EUR,161000,Oscar Garcia\n\
+             GPS,GPS,Gap,8000000000,USD,85000,Richard Dickson\n",
+        ))
+        .unwrap();
+        let to = read_snapshot_csv(&write_csv(
+            &dir,
+            "to.csv",
+            "Rank,Ticker,Name,Market Cap (Original),Original Currency,Market Cap (EUR)\n\
+             1,ITX.MC,Inditex,150000000000.0000001,USD,150000000000\n\
+             2,NKE,Nike Inc,100000000000,USD,92000000000\n\
+             3,HM-B.ST,H&M,250000000000,SEK,21000000000\n",
+        ))
+        .unwrap();
+
+        // The second export has no employee or CEO columns
+        let fields = comparable_fields(&from, &to, &Field::ALL);
+        assert_eq!(fields, vec![Field::Name, Field::Currency, Field::MarketCap]);
+
+        let diff = diff_snapshots(&from, &to, &fields);
+        let summary: Vec<(&str, ChangeKind, Option<Field>, &str, &str)> = diff
+            .iter()
+            .map(|r| {
+                (
+                    r.ticker.as_str(),
+                    r.change,
+                    r.field,
+                    r.from.as_str(),
+                    r.to.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("GPS", ChangeKind::Removed, None, "from", ""),
+                ("HM-B.ST", ChangeKind::Added, None, "", "to"),
+                (
+                    "ITX.MC",
+                    ChangeKind::Changed,
+                    Some(Field::Currency),
+                    "EUR",
+                    "USD"
+                ),
+                (
+                    "NKE",
+                    ChangeKind::Changed,
+                    Some(Field::Name),
+                    "Nike",
+                    "Nike Inc"
+                ),
+            ]
+        );
+        assert_eq!(diff[0].name, "Gap");
+        assert_eq!(diff[1].name, "H&M");
+    }
+
+    #[tokio::test]
+    async fn test_load_snapshot_db() {
+        let pool = db::create_db_pool("sqlite::memory:").await.unwrap();
+        for (ticker, name, currency, price, timestamp) in [
+            ("NKE", "Nike", "USD", 70.5, 1735689600),
+            ("NKE", "Nike", "EUR", 65.0, 1738368000),
+        ] {
+            sqlx::query(
+                "INSERT INTO market_caps (ticker, name, market_cap_original, original_currency, price, timestamp)
+                 VALUES (?, ?, 100000000000, ?, ?, ?)",
+            )
+            .bind(ticker)
+            .bind(name)
+            .bind(currency)
+            .bind(price)
+            .bind(timestamp)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        let from = load_snapshot_db(&pool, "2025-01-01").await.unwrap();
+        let to = load_snapshot_db(&pool, "2025-02-01").await.unwrap();
+        assert!(!from.fields.contains(&Field::Ceo));
+        assert_eq!(from.rows["NKE"][&Field::MarketCap], "100000000000");
+
+        let diff = diff_snapshots(&from, &to, &comparable_fields(&from, &to, &Field::ALL));
+        let fields: Vec<Option<Field>> = diff.iter().map(|r| r.field).collect();
+        assert_eq!(fields, vec![Some(Field::Currency), Some(Field::Price)]);
+        assert_eq!(diff[1].from, "70.5");
+        assert_eq!(diff[1].to, "65");
+
+        assert!(load_snapshot_db(&pool, "2025-03-01").await.is_err());
+    }
+}
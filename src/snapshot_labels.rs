@@ -0,0 +1,164 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Labeled intraday snapshots (`export-combined --label close`)
+//!
+//! A day can have several snapshots, e.g. one at market open and one at the
+//! close. `export-combined --label <label>` records the `market_caps`
+//! timestamp of its run under the label in `snapshot_labels` and exports it
+//! like a specific-date snapshot as `marketcaps_<date>@<label>_<timestamp>.csv`.
+//! Comparisons select it with the date spec `2025-08-01@close`; a plain date
+//! keeps selecting the unlabeled daily snapshot.
+
+use anyhow::Result;
+use chrono::{DateTime, NaiveDate, NaiveTime};
+use sqlx::sqlite::SqlitePool;
+
+/// Separates the date from the label in a date spec and in file names
+pub const SEPARATOR: char = '@';
+
+/// Labels are lower-case letters, digits and `-`, e.g. `open`, `close` or `pre-market`
+pub fn validate_label(label: &str) -> Result<()> {
+    let valid = !label.is_empty()
+        && label
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+    if !valid {
+        anyhow::bail!(
+            "Invalid snapshot label {:?}: use lower-case letters, digits and '-' (e.g. open, close)",
+            label
+        );
+    }
+    Ok(())
+}
+
+/// Split `2025-08-01@close` into the date and the label
+pub fn split_spec(spec: &str) -> Result<(&str, Option<&str>)> {
+    let (date, label) = match spec.split_once(SEPARATOR) {
+        Some((date, label)) => {
+            validate_label(label)?;
+            (date, Some(label))
+        }
+        None => (spec, None),
+    };
+    NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|e| {
+        anyhow::anyhow!(
+            "Invalid date {:?}: use YYYY-MM-DD or YYYY-MM-DD@LABEL: {}",
+            spec,
+            e
+        )
+    })?;
+    Ok((date, label))
+}
+
+/// A stored snapshot to export: the day it is filed under, its
+/// `market_caps` timestamp and its label when it is an intraday one
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub date: NaiveDate,
+    pub timestamp: i64,
+    pub label: Option<String>,
+}
+
+impl Snapshot {
+    /// The daily snapshot of a date, stored at midnight UTC
+    pub fn daily(date: NaiveDate) -> Self {
+        Self {
+            date,
+            timestamp: date.and_time(NaiveTime::MIN).and_utc().timestamp(),
+            label: None,
+        }
+    }
+
+    /// Snapshot stored at `timestamp`, filed under its UTC date
+    pub fn labeled(timestamp: i64, label: &str) -> Result<Self> {
+        validate_label(label)?;
+        let date = DateTime::from_timestamp(timestamp, 0)
+            .ok_or_else(|| anyhow::anyhow!("Invalid snapshot timestamp {}", timestamp))?
+            .date_naive();
+        Ok(Self {
+            date,
+            timestamp,
+            label: Some(label.to_string()),
+        })
+    }
+
+    /// Date spec of the snapshot, also the date part of its file names
+    pub fn spec(&self) -> String {
+        let date = self.date.format("%Y-%m-%d");
+        match &self.label {
+            Some(label) => format!("{}{}{}", date, SEPARATOR, label),
+            None => date.to_string(),
+        }
+    }
+}
+
+/// Remember which stored snapshot carries the label on its day; a second
+/// run with the same label on the same day replaces the first
+pub async fn record_label(pool: &SqlitePool, snapshot: &Snapshot) -> Result<()> {
+    let Some(label) = &snapshot.label else {
+        return Ok(());
+    };
+    sqlx::query("INSERT OR REPLACE INTO snapshot_labels (date, label, timestamp) VALUES (?, ?, ?)")
+        .bind(snapshot.date.format("%Y-%m-%d").to_string())
+        .bind(label)
+        .bind(snapshot.timestamp)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Stored snapshot of a date spec: the labeled one, or the daily one
+pub async fn resolve(pool: &SqlitePool, spec: &str) -> Result<Option<Snapshot>> {
+    let (date, label) = split_spec(spec)?;
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")?;
+    let Some(label) = label else {
+        return Ok(Some(Snapshot::daily(date)));
+    };
+    let timestamp: Option<i64> =
+        sqlx::query_scalar("SELECT timestamp FROM snapshot_labels WHERE date = ? AND label = ?")
+            .bind(date.format("%Y-%m-%d").to_string())
+            .bind(label)
+            .fetch_optional(pool)
+            .await?;
+    timestamp
+        .map(|timestamp| Snapshot::labeled(timestamp, label))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    #[test]
+    fn test_split_spec() {
+        assert_eq!(split_spec("2025-08-01").unwrap(), ("2025-08-01", None));
+        assert_eq!(
+            split_spec("2025-08-01@close").unwrap(),
+            ("2025-08-01", Some("close"))
+        );
+        assert!(split_spec("2025-08-01@Close").is_err());
+        assert!(split_spec("2025-08-01@").is_err());
+        assert!(split_spec("2025-08-01@close_2").is_err());
+        assert!(split_spec("08/01/2025@close").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_record_and_resolve_labels() {
+        let pool = db::create_db_pool("sqlite::memory:").await.unwrap();
+        // 2025-08-01 15:30 UTC
+        let snapshot = Snapshot::labeled(1754062200, "close").unwrap();
+        assert_eq!(snapshot.spec(), "2025-08-01@close");
+        record_label(&pool, &snapshot).await.unwrap();
+
+        let resolved = resolve(&pool, "2025-08-01@close").await.unwrap();
+        assert_eq!(resolved, Some(snapshot));
+        assert_eq!(resolve(&pool, "2025-08-01@open").await.unwrap(), None);
+
+        let daily = resolve(&pool, "2025-08-01").await.unwrap().unwrap();
+        assert_eq!(daily.timestamp, 1754006400);
+        assert_eq!(daily.spec(), "2025-08-01");
+    }
+}
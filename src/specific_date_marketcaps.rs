@@ -3,23 +3,162 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 
 use crate::api;
-use crate::config;
-use crate::currencies::{convert_currency_with_rate, get_rate_map_from_db_for_date};
+use crate::circuit_breaker;
+use crate::config::{self, OutputConfig};
+use crate::currencies::{
+    convert_currency_with_rate, extra_report_currencies, get_rate_map_from_db_for_date,
+    report_currency_values,
+};
+use crate::data_package;
+use crate::historical_marketcaps::HISTORICAL_REQUESTS_PER_TICKER;
+use crate::models::{self, DataSource};
+use crate::point_in_time::{self, PointInTime};
+use crate::progress;
+use crate::rankings;
+use crate::regions;
+use crate::run_context;
+use crate::snapshot_labels::Snapshot;
+use crate::trading_calendar::{self, TradingDay};
+use crate::universe;
+use crate::utils;
 use anyhow::Result;
-use chrono::{Local, NaiveDate, NaiveDateTime, NaiveTime};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Utc};
 use csv::Writer;
-use indicatif::{ProgressBar, ProgressStyle};
 use sqlx::sqlite::SqlitePool;
+use std::collections::HashMap;
 use std::sync::Arc;
 
+/// Table a fetch stores its market caps in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Store {
+    /// The universe snapshot in `market_caps`
+    Universe,
+    /// `watchlist_market_caps`, which universe readers (rankings, aggregates,
+    /// data quality, ...) never see. Tickers the universe snapshot already
+    /// has for the date are reused instead of fetched.
+    Watchlist,
+}
+
 /// Format a conversion rate for display (6 decimal places, or empty if not available)
 fn format_rate(rate: Option<f64>) -> String {
     rate.map(|r| format!("{:.6}", r)).unwrap_or_default()
 }
 
-pub async fn fetch_specific_date_marketcaps(pool: &SqlitePool, date_str: &str) -> Result<()> {
+/// Fetch a snapshot of the configured universe for a date; returns the date
+/// it was fetched for, which `align_to_trading_day` may have moved back
+pub async fn fetch_specific_date_marketcaps(
+    pool: &SqlitePool,
+    date_str: &str,
+    report_currencies: &[String],
+    concurrency: usize,
+    point_in_time: bool,
+    align_to_trading_day: bool,
+) -> Result<String> {
     let config = config::load_config()?;
-    let tickers = [config.non_us_tickers, config.us_tickers].concat();
+    let mut tickers = [config.non_us_tickers, config.us_tickers].concat();
+
+    let requested = NaiveDate::parse_from_str(date_str, "%Y-%m-%d")
+        .map_err(|e| anyhow::anyhow!("Invalid date format. Use YYYY-MM-DD: {}", e))?;
+    let trading_day = check_trading_day(requested, &tickers, align_to_trading_day);
+    let effective = trading_day.effective.format("%Y-%m-%d").to_string();
+    let date_str = effective.as_str();
+
+    // In point-in-time mode only the tickers in the universe on the date are fetched
+    let pit = if point_in_time {
+        let date = trading_day.effective;
+        let pit = PointInTime::load(pool, date).await?;
+        match &pit.universe {
+            Some((universe_date, _)) => {
+                let (members, later) = pit.members(&tickers);
+                println!(
+                    "Point-in-time: {} of {} tickers were in the universe of {}",
+                    members.len(),
+                    tickers.len(),
+                    universe_date
+                );
+                if !later.is_empty() {
+                    println!("  Skipping tickers added later: {}", later.join(", "));
+                }
+                tickers = members;
+            }
+            None => run_context::record_warning(format!(
+                "No universe snapshot on or before {}; membership not resolved historically",
+                date_str
+            )),
+        }
+        Some(pit)
+    } else {
+        None
+    };
+    universe::record_universe(pool, date_str, &tickers).await?;
+
+    let timestamp = fetch_marketcaps_for_tickers(
+        pool,
+        date_str,
+        &tickers,
+        "marketcaps",
+        report_currencies,
+        Store::Universe,
+        concurrency,
+        pit.as_ref(),
+        Some(&trading_day),
+    )
+    .await?;
+    rankings::record_rankings(pool, timestamp).await?;
+
+    Ok(effective)
+}
+
+/// Warn when an exchange of the universe is closed on the requested date,
+/// or move to the previous trading day with `align`
+fn check_trading_day(requested: NaiveDate, tickers: &[String], align: bool) -> TradingDay {
+    let calendars = trading_calendar::calendars_of(tickers);
+    let trading_day = TradingDay::resolve(requested, &calendars, align);
+    if trading_day.is_shifted() {
+        println!(
+            "📅 {} is not a trading day on {}; fetching {} instead",
+            requested,
+            trading_day.closed_summary(),
+            trading_day.effective
+        );
+    } else if !trading_day.closed.is_empty() {
+        let message = format!(
+            "{} is not a trading day on {}; those prices are from the previous close (use --align-to-trading-day to fetch the previous trading day)",
+            requested,
+            trading_day.closed_summary()
+        );
+        eprintln!("⚠️  {}", message);
+        run_context::record_warning(message);
+    }
+    run_context::record_trading_day(run_context::TradingDayRecord {
+        requested_date: requested.to_string(),
+        effective_date: trading_day.effective.to_string(),
+        closed_exchanges: trading_day
+            .closed
+            .iter()
+            .map(|(calendar, _)| calendar.label().to_string())
+            .collect(),
+    });
+    trading_day
+}
+
+/// Fetch and store market caps of `tickers` for a date in `store`, then export
+/// them as a `kind` CSV ranked among themselves. `trading_day` tells the data package
+/// which date was asked for when it was moved to a trading day. Returns the
+/// snapshot timestamp.
+#[allow(clippy::too_many_arguments)]
+pub async fn fetch_marketcaps_for_tickers(
+    pool: &SqlitePool,
+    date_str: &str,
+    tickers: &[String],
+    kind: &str,
+    report_currencies: &[String],
+    store: Store,
+    concurrency: usize,
+    point_in_time: Option<&PointInTime>,
+    trading_day: Option<&TradingDay>,
+) -> Result<i64> {
+    let output = config::load_output_config();
 
     // Parse the date string
     let date = NaiveDate::parse_from_str(date_str, "%Y-%m-%d")
@@ -29,10 +168,29 @@ pub async fn fetch_specific_date_marketcaps(pool: &SqlitePool, date_str: &str) -
     let datetime_utc = naive_dt.and_utc();
     let timestamp = naive_dt.and_utc().timestamp();
 
+    let to_fetch: Vec<String> = if store == Store::Watchlist {
+        let stored: Vec<String> = sqlx::query_scalar(
+            r#"
+            SELECT ticker FROM market_caps WHERE timestamp = ?
+            UNION
+            SELECT ticker FROM watchlist_market_caps WHERE timestamp = ?
+            "#,
+        )
+        .bind(timestamp)
+        .bind(timestamp)
+        .fetch_all(pool)
+        .await?;
+        tickers
+            .iter()
+            .filter(|t| !stored.contains(t))
+            .cloned()
+            .collect()
+    } else {
+        tickers.to_vec()
+    };
+
     // Get FMP client for market data
-    let api_key = std::env::var("FINANCIALMODELINGPREP_API_KEY")
-        .expect("FINANCIALMODELINGPREP_API_KEY must be set");
-    let fmp_client = Arc::new(api::FMPClient::new(api_key));
+    let fmp_client = Arc::new(api::FMPClient::from_env()?);
 
     println!("Fetching market caps for date: {}", date);
 
@@ -41,6 +199,10 @@ pub async fn fetch_specific_date_marketcaps(pool: &SqlitePool, date_str: &str) -
     let rate_map = get_rate_map_from_db_for_date(pool, Some(timestamp)).await?;
 
     if rate_map.is_empty() {
+        run_context::record_warning(format!(
+            "No exchange rates found for date {} or earlier; currency conversions are inaccurate",
+            date
+        ));
         eprintln!(
             "⚠️  WARNING: No exchange rates found for date {} or earlier!",
             date
@@ -51,26 +213,42 @@ pub async fn fetch_specific_date_marketcaps(pool: &SqlitePool, date_str: &str) -
         println!("✅ Exchange rates fetched for {}", date);
     }
 
-    let total_tickers = tickers.len();
-    let progress = ProgressBar::new(total_tickers as u64);
-    progress.set_style(
-        ProgressStyle::default_bar()
-            .template("[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}")
-            .unwrap()
-            .progress_chars("=>-"),
+    let total_tickers = to_fetch.len();
+    let progress = progress::fetch_bar(
+        total_tickers as u64,
+        "tickers",
+        HISTORICAL_REQUESTS_PER_TICKER,
     );
 
     let mut successful_tickers = Vec::new();
     let mut failed_tickers = Vec::new();
+    let mut skipped_tickers = Vec::new();
+    let mut resolutions = Vec::new();
+
+    // Fetch concurrently, then store in ticker order
+    progress.set_message(format!("{} concurrent requests", concurrency));
+    let fetched = utils::fetch_ordered(&to_fetch, concurrency, |ticker| {
+        let progress = progress.clone();
+        let fmp_client = fmp_client.clone();
+        async move {
+            let market_cap = circuit_breaker::within_deadline(
+                ticker,
+                fmp_client.get_historical_market_cap(ticker, &datetime_utc),
+            )
+            .await;
+            progress.inc(1);
+            market_cap
+        }
+    })
+    .await;
 
-    for ticker in &tickers {
-        progress.set_message(format!("Processing {}", ticker));
+    for (ticker, result) in to_fetch.iter().zip(fetched) {
+        match result {
+            Ok(mut market_cap) => {
+                if let Some(pit) = point_in_time {
+                    resolutions.push(pit.resolve(&mut market_cap));
+                }
 
-        match fmp_client
-            .get_historical_market_cap(ticker, &datetime_utc)
-            .await
-        {
-            Ok(market_cap) => {
                 // Convert currencies with rate information
                 let eur_result = convert_currency_with_rate(
                     market_cap.market_cap_original,
@@ -90,33 +268,65 @@ pub async fn fetch_specific_date_marketcaps(pool: &SqlitePool, date_str: &str) -
                 let timestamp = naive_dt.and_utc().timestamp();
 
                 // Insert into database with conversion rates
-                sqlx::query!(
-                    r#"
-                    INSERT OR REPLACE INTO market_caps (
-                        ticker, name, market_cap_original, original_currency,
-                        market_cap_eur, market_cap_usd, eur_rate, usd_rate,
-                        exchange, price, active, timestamp
-                    )
-                    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
-                    "#,
-                    ticker,
-                    market_cap.name,
-                    market_cap.market_cap_original,
-                    market_cap.original_currency,
-                    eur_result.amount,
-                    usd_result.amount,
-                    eur_result.rate,
-                    usd_result.rate,
-                    market_cap.exchange,
-                    market_cap.price,
-                    true,
-                    timestamp,
-                )
-                .execute(pool)
-                .await?;
+                let data_source = DataSource::Fmp.as_str();
+                let fetched_at = models::fetched_at(Utc::now());
+                let query = match store {
+                    Store::Universe => sqlx::query!(
+                        r#"
+                        INSERT OR REPLACE INTO market_caps (
+                            ticker, name, market_cap_original, original_currency,
+                            market_cap_eur, market_cap_usd, eur_rate, usd_rate,
+                            exchange, price, active, timestamp, data_source, fetched_at
+                        )
+                        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                        "#,
+                        ticker,
+                        market_cap.name,
+                        market_cap.market_cap_original,
+                        market_cap.original_currency,
+                        eur_result.amount,
+                        usd_result.amount,
+                        eur_result.rate,
+                        usd_result.rate,
+                        market_cap.exchange,
+                        market_cap.price,
+                        true,
+                        timestamp,
+                        data_source,
+                        fetched_at,
+                    ),
+                    Store::Watchlist => sqlx::query!(
+                        r#"
+                        INSERT OR REPLACE INTO watchlist_market_caps (
+                            ticker, name, market_cap_original, original_currency,
+                            market_cap_eur, market_cap_usd, eur_rate, usd_rate,
+                            exchange, price, active, timestamp, data_source, fetched_at
+                        )
+                        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                        "#,
+                        ticker,
+                        market_cap.name,
+                        market_cap.market_cap_original,
+                        market_cap.original_currency,
+                        eur_result.amount,
+                        usd_result.amount,
+                        eur_result.rate,
+                        usd_result.rate,
+                        market_cap.exchange,
+                        market_cap.price,
+                        true,
+                        timestamp,
+                        data_source,
+                        fetched_at,
+                    ),
+                };
+                query.execute(pool).await?;
 
                 successful_tickers.push(ticker.clone());
             }
+            Err(e) if circuit_breaker::is_skipped(&e) => {
+                skipped_tickers.push((ticker.clone(), e.to_string()));
+            }
             Err(e) => {
                 eprintln!(
                     "❌ Failed to fetch market cap for {} on {}: {}",
@@ -125,7 +335,6 @@ pub async fn fetch_specific_date_marketcaps(pool: &SqlitePool, date_str: &str) -
                 failed_tickers.push((ticker.clone(), e.to_string()));
             }
         }
-        progress.inc(1);
     }
     progress.finish_with_message("Processing complete");
 
@@ -139,18 +348,72 @@ pub async fn fetch_specific_date_marketcaps(pool: &SqlitePool, date_str: &str) -
         println!("\n❌ Failed to fetch {} tickers:", failed_tickers.len());
         for (ticker, error) in &failed_tickers {
             println!("  {} - {}", ticker, error);
+            run_context::record_warning(format!("{}: {}", ticker, error));
+        }
+    }
+    circuit_breaker::report_skipped(&skipped_tickers);
+
+    if point_in_time.is_some() {
+        let unresolved: Vec<String> = resolutions
+            .iter()
+            .filter(|r| !r.unresolved().is_empty())
+            .map(|r| format!("{} ({})", r.ticker, r.unresolved().join(", ")))
+            .collect();
+        if !unresolved.is_empty() {
+            println!(
+                "⚠️  {} tickers keep today's values for fields not resolvable as of {}",
+                unresolved.len(),
+                date
+            );
+            run_context::record_warning(format!(
+                "Not resolved as of {}: {}",
+                date,
+                unresolved.join("; ")
+            ));
         }
+        output.ensure_directory()?;
+        let path = output.file_path("point_in_time", date_str, "csv");
+        point_in_time::export_resolutions(&path, &resolutions)?;
+        println!("✅ Point-in-time resolution exported to {}", path.display());
     }
 
     // Export to CSV
-    export_specific_date_marketcaps(pool, date).await?;
+    let report_currencies = extra_report_currencies(report_currencies);
+    export_specific_date_marketcaps(
+        pool,
+        &Snapshot::daily(date),
+        &output,
+        kind,
+        tickers,
+        &report_currencies,
+        &rate_map,
+        trading_day,
+        store,
+    )
+    .await?;
 
-    Ok(())
+    Ok(timestamp)
 }
 
-async fn export_specific_date_marketcaps(pool: &SqlitePool, date: NaiveDate) -> Result<()> {
-    let naive_dt = NaiveDateTime::new(date, NaiveTime::default());
-    let timestamp = naive_dt.and_utc().timestamp();
+/// Export a stored snapshot of `tickers` as a `kind` CSV, ranked among
+/// themselves, plus its regional breakdown. Labeled snapshots are filed
+/// under `<date>@<label>`. For `Store::Watchlist` the rows of
+/// `watchlist_market_caps` fill in the tickers the universe snapshot lacks.
+#[allow(clippy::too_many_arguments)]
+pub async fn export_specific_date_marketcaps(
+    pool: &SqlitePool,
+    snapshot: &Snapshot,
+    output: &OutputConfig,
+    kind: &str,
+    tickers: &[String],
+    report_currencies: &[String],
+    rate_map: &HashMap<String, f64>,
+    trading_day: Option<&TradingDay>,
+    store: Store,
+) -> Result<()> {
+    let date = snapshot.date;
+    let timestamp = snapshot.timestamp;
+    let with_watchlist = store == Store::Watchlist;
 
     // Fetch market caps for the specific date
     let records = sqlx::query!(
@@ -167,38 +430,73 @@ async fn export_specific_date_marketcaps(pool: &SqlitePool, date: NaiveDate) ->
             m.exchange,
             m.active,
             CAST(m.price AS REAL) as price,
+            m.data_source,
+            m.fetched_at,
             td.description,
             td.homepage_url,
             td.employees,
-            td.ceo
-        FROM market_caps m
+            td.ceo,
+            td.isin
+        FROM (
+            SELECT ticker, name, market_cap_original, original_currency,
+                market_cap_eur, market_cap_usd, eur_rate, usd_rate,
+                exchange, active, price, data_source, fetched_at
+            FROM market_caps
+            WHERE timestamp = ?
+            UNION ALL
+            SELECT ticker, name, market_cap_original, original_currency,
+                market_cap_eur, market_cap_usd, eur_rate, usd_rate,
+                exchange, active, price, data_source, fetched_at
+            FROM watchlist_market_caps w
+            WHERE ? AND w.timestamp = ?
+                AND NOT EXISTS (
+                    SELECT 1 FROM market_caps u
+                    WHERE u.ticker = w.ticker AND u.timestamp = w.timestamp
+                )
+        ) m
         LEFT JOIN ticker_details td ON m.ticker = td.ticker
-        WHERE m.timestamp = ?
         ORDER BY m.market_cap_eur DESC
         "#,
+        timestamp,
+        with_watchlist,
         timestamp
     )
     .fetch_all(pool)
     .await?;
 
+    // The snapshot may hold more tickers than asked for
+    let mut records: Vec<_> = records
+        .into_iter()
+        .filter(|r| tickers.contains(&r.ticker))
+        .collect();
+    records.sort_by(|a, b| {
+        rankings::rank_order(
+            (a.market_cap_eur, &a.name, &a.ticker),
+            (b.market_cap_eur, &b.name, &b.ticker),
+        )
+    });
+    rankings::truncate_to_top(&mut records);
+
     if records.is_empty() {
         println!("No market cap data found for date: {}", date);
         return Ok(());
     }
 
     // Create output directory if it doesn't exist
-    std::fs::create_dir_all("output")?;
+    output.ensure_directory()?;
 
     // Generate filename with date
-    let timestamp_str = Local::now().format("%Y%m%d_%H%M%S");
     let date_str = date.format("%Y-%m-%d");
-    let filename = format!("output/marketcaps_{}_{}.csv", date_str, timestamp_str);
+    let spec = snapshot.spec();
+    let stamp = OutputConfig::timestamp();
+    let path = output.file_path_at(kind, &spec, &stamp, "csv");
+    let filename = path.display().to_string();
 
-    let file = std::fs::File::create(&filename)?;
+    let file = std::fs::File::create(&path)?;
     let mut writer = Writer::from_writer(file);
 
-    // Write headers
-    writer.write_record(&[
+    // Write headers, with one extra column per report currency
+    let mut headers: Vec<String> = [
         "Rank",
         "Ticker",
         "Name",
@@ -215,12 +513,24 @@ async fn export_specific_date_marketcaps(pool: &SqlitePool, date: NaiveDate) ->
         "Homepage URL",
         "Employees",
         "CEO",
+        "ISIN",
         "Date",
-    ])?;
+        "Data Source",
+        "Fetched At",
+    ]
+    .iter()
+    .map(|h| h.to_string())
+    .collect();
+    headers.extend(
+        report_currencies
+            .iter()
+            .map(|c| format!("Market Cap ({})", c)),
+    );
+    writer.write_record(&headers)?;
 
     // Write data with rank
     for (index, record) in records.iter().enumerate() {
-        writer.write_record(&[
+        let mut row = vec![
             (index + 1).to_string(),
             record.ticker.clone(),
             record.name.clone(),
@@ -241,14 +551,50 @@ async fn export_specific_date_marketcaps(pool: &SqlitePool, date: NaiveDate) ->
             record.homepage_url.clone().unwrap_or_default(),
             record.employees.map(|e| e.to_string()).unwrap_or_default(),
             record.ceo.clone().unwrap_or_default(),
+            record.isin.clone().unwrap_or_default(),
             date_str.to_string(),
-        ])?;
+            record.data_source.clone().unwrap_or_default(),
+            record.fetched_at.clone().unwrap_or_default(),
+        ];
+        row.extend(report_currency_values(
+            record.market_cap_original,
+            record.original_currency.as_deref().unwrap_or_default(),
+            report_currencies,
+            rate_map,
+        ));
+        writer.write_record(&row)?;
     }
 
     writer.flush()?;
-    println!("✅ Market caps for {} exported to {}", date, filename);
+    println!("✅ Market caps for {} exported to {}", spec, filename);
+    data_package::write_for(
+        &path,
+        data_package::Provenance {
+            kind,
+            title: format!("Market caps on {}", spec),
+            dates: vec![spec.clone()],
+            requested_date: trading_day
+                .filter(|day| day.is_shifted())
+                .map(|day| day.requested.to_string()),
+            inputs: Vec::new(),
+        },
+    )?;
     println!("   Total companies: {}", records.len());
 
+    // Totals per region and exchange
+    let listings: Vec<regions::Listing> = records
+        .iter()
+        .map(|record| regions::Listing {
+            exchange: record.exchange.as_deref().unwrap_or_default(),
+            currency: record.original_currency.as_deref().unwrap_or_default(),
+            market_cap_eur: record.market_cap_eur,
+            market_cap_usd: record.market_cap_usd,
+        })
+        .collect();
+    let path = output.named_path(&format!("{}_{}_by_region.csv", kind, spec));
+    regions::export_breakdown_csv(&path, &listings)?;
+    println!("✅ Regional breakdown exported to {}", path.display());
+
     Ok(())
 }
 
@@ -388,4 +734,87 @@ mod tests {
         assert!(filename.starts_with("output/marketcaps_2025-03-15_"));
         assert!(filename.ends_with(".csv"));
     }
+
+    #[tokio::test]
+    async fn test_watchlist_rows_stay_out_of_universe_exports() {
+        let pool = crate::db::create_db_pool("sqlite::memory:").await.unwrap();
+        sqlx::query("ALTER TABLE ticker_details ADD COLUMN ceo TEXT")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let date = NaiveDate::from_ymd_opt(2025, 3, 14).unwrap();
+        let timestamp = NaiveDateTime::new(date, NaiveTime::default())
+            .and_utc()
+            .timestamp();
+        for (table, ticker, cap) in [
+            ("market_caps", "NKE", 100.0),
+            ("watchlist_market_caps", "NKE", 1.0),
+            ("watchlist_market_caps", "SHEIN", 50.0),
+        ] {
+            sqlx::query(&format!(
+                "INSERT INTO {table} (ticker, name, market_cap_original, original_currency, market_cap_eur, market_cap_usd, timestamp)
+                 VALUES (?, ?, ?, 'USD', ?, ?, ?)"
+            ))
+            .bind(ticker)
+            .bind(ticker)
+            .bind(cap)
+            .bind(cap)
+            .bind(cap)
+            .bind(timestamp)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let output = OutputConfig {
+            directory: dir.path().display().to_string(),
+            ..OutputConfig::default()
+        };
+        let tickers = vec!["NKE".to_string(), "SHEIN".to_string()];
+        let export = |kind: &'static str, store: Store| {
+            let (pool, output, tickers) = (&pool, &output, &tickers);
+            async move {
+                export_specific_date_marketcaps(
+                    pool,
+                    &Snapshot::daily(date),
+                    output,
+                    kind,
+                    tickers,
+                    &[],
+                    &HashMap::new(),
+                    None,
+                    store,
+                )
+                .await
+                .unwrap();
+                let path = output
+                    .find_latest(kind, "2025-03-14", "csv")
+                    .unwrap()
+                    .unwrap();
+                let mut reader = csv::Reader::from_path(path).unwrap();
+                reader
+                    .records()
+                    .map(|r| {
+                        let r = r.unwrap();
+                        (r[1].to_string(), r[3].to_string())
+                    })
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        // The universe export never sees watchlist rows
+        assert_eq!(
+            export("marketcaps", Store::Universe).await,
+            vec![("NKE".to_string(), "100".to_string())]
+        );
+        // A watchlist reuses the universe row and adds its own
+        assert_eq!(
+            export("watchlist-ipo_marketcaps", Store::Watchlist).await,
+            vec![
+                ("NKE".to_string(), "100".to_string()),
+                ("SHEIN".to_string(), "50".to_string()),
+            ]
+        );
+    }
 }
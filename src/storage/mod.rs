@@ -0,0 +1,7 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+pub mod uploader;
+
+pub use uploader::upload_new_files;
@@ -0,0 +1,164 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Upload generated reports to object storage (S3, GCS or a local `file://` URL)
+
+use anyhow::{Context, Result};
+use object_store::path::Path as ObjectPath;
+use object_store::{ObjectStore, PutPayload};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use url::Url;
+
+/// Pushes local files to an object store under a fixed prefix
+pub struct Uploader {
+    store: Box<dyn ObjectStore>,
+    prefix: ObjectPath,
+    url: String,
+}
+
+impl Uploader {
+    /// Create an uploader from a destination URL such as `s3://bucket/prefix`
+    /// or `gs://bucket/prefix`.
+    ///
+    /// Credentials and region are read from the usual environment variables
+    /// (`AWS_ACCESS_KEY_ID`, `AWS_REGION`, `GOOGLE_SERVICE_ACCOUNT`, ...).
+    pub fn from_url(destination: &str) -> Result<Self> {
+        let url = Url::parse(destination)
+            .with_context(|| format!("Invalid upload destination: {}", destination))?;
+        let options = std::env::vars().map(|(k, v)| (k.to_ascii_lowercase(), v));
+        let (store, prefix) = object_store::parse_url_opts(&url, options)
+            .with_context(|| format!("Failed to configure object store for {}", destination))?;
+
+        Ok(Self {
+            store,
+            prefix,
+            url: destination.trim_end_matches('/').to_string(),
+        })
+    }
+
+    /// Upload a single file, keyed by its file name under the prefix
+    pub async fn upload_file(&self, local_path: &Path) -> Result<String> {
+        let file_name = local_path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .with_context(|| format!("Invalid file name: {}", local_path.display()))?;
+        let key = self.prefix.child(file_name);
+
+        let bytes = fs::read(local_path)
+            .with_context(|| format!("Failed to read {}", local_path.display()))?;
+        self.store
+            .put(&key, PutPayload::from(bytes))
+            .await
+            .with_context(|| format!("Failed to upload {}", local_path.display()))?;
+
+        Ok(format!("{}/{}", self.url, file_name))
+    }
+
+    /// Upload all files, stopping at the first failure
+    pub async fn upload_files(&self, files: &[PathBuf]) -> Result<Vec<String>> {
+        let mut uploaded = Vec::new();
+        for file in files {
+            uploaded.push(self.upload_file(file).await?);
+        }
+        Ok(uploaded)
+    }
+}
+
+/// Files in `dir` that were created or modified at or after `since`
+pub fn files_modified_since(dir: &Path, since: SystemTime) -> Result<Vec<PathBuf>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_file() && metadata.modified()? >= since {
+            files.push(entry.path());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Upload everything written to `dir` since `since` to `destination`
+pub async fn upload_new_files(destination: &str, dir: &Path, since: SystemTime) -> Result<()> {
+    let files = files_modified_since(dir, since)?;
+    if files.is_empty() {
+        println!("No new files to upload to {}", destination);
+        return Ok(());
+    }
+
+    let uploader = Uploader::from_url(destination)?;
+    for url in uploader.upload_files(&files).await? {
+        println!("☁️  Uploaded {}", url);
+    }
+    println!("✅ Uploaded {} files to {}", files.len(), destination);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_upload_to_file_url() {
+        let source = tempfile::tempdir().unwrap();
+        let target = tempfile::tempdir().unwrap();
+        let report = source
+            .path()
+            .join("comparison_2025-01-01_to_2025-02-01.csv");
+        fs::write(&report, "Ticker,Name\nNKE,Nike\n").unwrap();
+
+        let destination = format!("file://{}/reports", target.path().display());
+        let uploader = Uploader::from_url(&destination).unwrap();
+        let uploaded = uploader.upload_files(&[report]).await.unwrap();
+
+        assert_eq!(
+            uploaded,
+            vec![format!(
+                "{}/comparison_2025-01-01_to_2025-02-01.csv",
+                destination
+            )]
+        );
+        let copied = target
+            .path()
+            .join("reports/comparison_2025-01-01_to_2025-02-01.csv");
+        assert_eq!(
+            fs::read_to_string(copied).unwrap(),
+            "Ticker,Name\nNKE,Nike\n"
+        );
+    }
+
+    #[test]
+    fn test_invalid_destination() {
+        assert!(Uploader::from_url("not a url").is_err());
+        assert!(Uploader::from_url("ftp://bucket/prefix").is_err());
+    }
+
+    #[test]
+    fn test_files_modified_since() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("old.csv"), "").unwrap();
+        let since = SystemTime::now() + Duration::from_secs(3600);
+
+        assert!(files_modified_since(dir.path(), since).unwrap().is_empty());
+        assert_eq!(
+            files_modified_since(dir.path(), SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(
+            files_modified_since(&dir.path().join("missing"), since)
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
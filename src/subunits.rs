@@ -0,0 +1,158 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Currency subunits (`GBp`, `ZAc`, ...) recognised by the conversions
+//!
+//! Some exchanges quote prices and market caps in a fraction of a currency,
+//! while exchange rates are only stored for the parent currency. The table
+//! maps each subunit code to its parent and the number of subunits per
+//! parent unit. GBp, ZAc and ILA are built in; `[[forex.subunits]]` in
+//! config.toml adds codes or overrides built-in ones. The table is read once
+//! per run; `list-subunits` prints it.
+
+use anyhow::Result;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::OnceLock;
+
+use crate::config::{self, CurrencySubunit};
+use crate::currencies::{get_rate_map_from_db, try_convert_currency};
+use crate::db::CorePool;
+use top200_core::conversion::BUILT_IN_SUBUNITS;
+
+static TABLE: OnceLock<Vec<Subunit>> = OnceLock::new();
+
+/// One row of the subunit table
+#[derive(Debug, Clone, PartialEq)]
+pub struct Subunit {
+    pub code: String,
+    pub parent: String,
+    pub divisor: f64,
+    /// `built-in` or `config`
+    pub source: &'static str,
+}
+
+/// The built-in subunits with `configured` ones added or replacing them,
+/// ordered by code
+pub fn build(configured: &[CurrencySubunit]) -> Vec<Subunit> {
+    let mut table: BTreeMap<String, Subunit> = BUILT_IN_SUBUNITS
+        .iter()
+        .map(|&(code, parent, divisor)| {
+            let subunit = Subunit {
+                code: code.to_string(),
+                parent: parent.to_string(),
+                divisor,
+                source: "built-in",
+            };
+            (code.to_string(), subunit)
+        })
+        .collect();
+    for subunit in configured {
+        table.insert(
+            subunit.code.clone(),
+            Subunit {
+                code: subunit.code.clone(),
+                parent: subunit.parent.clone(),
+                divisor: subunit.divisor,
+                source: "config",
+            },
+        );
+    }
+    table.into_values().collect()
+}
+
+/// The subunit table of this run
+pub fn table() -> &'static [Subunit] {
+    TABLE.get_or_init(|| build(&config::load_forex_config().subunits))
+}
+
+/// The subunit with this (case-sensitive) code
+pub fn lookup(code: &str) -> Option<&'static Subunit> {
+    table().iter().find(|s| s.code == code)
+}
+
+/// Currency rates are quoted in for `code`, and how many units of `code`
+/// make one of it (`("GBP", 100.0)` for `GBp`, `(code, 1.0)` otherwise)
+pub fn resolve(code: &str) -> (&str, f64) {
+    match lookup(code) {
+        Some(subunit) => (&subunit.parent, subunit.divisor),
+        None => (code, 1.0),
+    }
+}
+
+/// EUR value of `amount` in the subunit with the given rates, if convertible
+fn eur_value(subunit: &Subunit, amount: f64, rate_map: &HashMap<String, f64>) -> Option<f64> {
+    try_convert_currency(amount, &subunit.code, "EUR", rate_map)
+        .ok()
+        .map(|c| c.amount)
+}
+
+/// Print the subunit table with the EUR value of 100 subunits at the latest
+/// stored rates, so a wrong parent or divisor stands out
+pub async fn list_subunits(pool: &CorePool) -> Result<()> {
+    let rate_map = get_rate_map_from_db(pool).await?;
+    println!(
+        "{:<6} {:<6} {:>10} {:<9} {:>16}",
+        "Code", "Parent", "Divisor", "Source", "100 in EUR"
+    );
+    for subunit in table() {
+        let eur = eur_value(subunit, 100.0, &rate_map)
+            .map_or_else(|| "no rate".to_string(), |v| format!("{:.4}", v));
+        println!(
+            "{:<6} {:<6} {:>10} {:<9} {:>16}",
+            subunit.code, subunit.parent, subunit.divisor, subunit.source, eur
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn configured(code: &str, parent: &str, divisor: f64) -> CurrencySubunit {
+        CurrencySubunit {
+            code: code.to_string(),
+            parent: parent.to_string(),
+            divisor,
+        }
+    }
+
+    #[test]
+    fn test_build_adds_and_overrides() {
+        let table = build(&[
+            configured("KWf", "KWD", 1000.0),
+            configured("ILA", "ILS", 100.0),
+        ]);
+        let codes: Vec<(&str, f64, &str)> = table
+            .iter()
+            .map(|s| (s.code.as_str(), s.divisor, s.source))
+            .collect();
+        assert_eq!(
+            codes,
+            vec![
+                ("GBp", 100.0, "built-in"),
+                ("ILA", 100.0, "config"),
+                ("KWf", 1000.0, "config"),
+                ("ZAc", 100.0, "built-in"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolve_built_in() {
+        assert_eq!(resolve("GBp"), ("GBP", 100.0));
+        assert_eq!(resolve("ZAc"), ("ZAR", 100.0));
+        // Codes are case-sensitive: GBP is the parent, not a subunit
+        assert_eq!(resolve("GBP"), ("GBP", 1.0));
+        assert_eq!(resolve("USD"), ("USD", 1.0));
+    }
+
+    #[test]
+    fn test_eur_value() {
+        let rate_map: HashMap<String, f64> = [("GBP/EUR".to_string(), 1.2)].into_iter().collect();
+        let pence = lookup("GBp").unwrap();
+        assert!((eur_value(pence, 100.0, &rate_map).unwrap() - 1.2).abs() < 1e-9);
+        assert_eq!(eur_value(lookup("ZAc").unwrap(), 100.0, &rate_map), None);
+    }
+}
@@ -3,16 +3,20 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 
 use anyhow::{Context, Result};
-use chrono::Utc;
+use chrono::{NaiveDate, NaiveTime};
 use serde::{Deserialize, Serialize};
 use sqlx::sqlite::SqlitePool;
 use std::collections::HashSet;
 use std::fs;
+use std::io::Write;
 use toml::Value;
 
+use crate::advanced_comparisons::get_predefined_peer_groups;
 use crate::api::FMPClient;
+use crate::clock::Clock;
+use crate::db::{CorePool, core_query};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, sqlx::FromRow)]
 pub struct StoredSymbolChange {
     pub id: Option<i64>,
     pub old_symbol: String,
@@ -33,31 +37,33 @@ pub struct SymbolChangeReport {
 
 /// Fetch symbol changes from FMP API and store in database
 pub async fn fetch_and_store_symbol_changes(
-    pool: &SqlitePool,
+    pool: impl Into<CorePool>,
     fmp_client: &FMPClient,
 ) -> Result<usize> {
+    let pool = pool.into();
     println!("Fetching symbol changes from FMP API...");
     let changes = fmp_client.fetch_symbol_changes().await?;
 
     let mut stored_count = 0;
     for change in changes {
-        let result = sqlx::query!(
+        let rows_affected = core_query!(&pool, |pool| sqlx::query(
             r#"
             INSERT INTO symbol_changes (old_symbol, new_symbol, change_date, company_name, reason)
-            VALUES (?, ?, ?, ?, ?)
+            VALUES ($1, $2, $3, $4, $5)
             ON CONFLICT(old_symbol, new_symbol, change_date) DO NOTHING
             "#,
-            change.old_symbol,
-            change.new_symbol,
-            change.date,
-            change.name,
-            None::<String>, // Reason will be added later if available
         )
+        .bind(&change.old_symbol)
+        .bind(&change.new_symbol)
+        .bind(&change.date)
+        .bind(&change.name)
+        .bind(None::<String>) // Reason will be added later if available
         .execute(pool)
-        .await;
+        .await
+        .map(|result| result.rows_affected()));
 
-        if let Ok(result) = result {
-            if result.rows_affected() > 0 {
+        if let Ok(rows_affected) = rows_affected {
+            if rows_affected > 0 {
                 stored_count += 1;
             }
         }
@@ -68,35 +74,41 @@ pub async fn fetch_and_store_symbol_changes(
 }
 
 /// Get all pending (unapplied) symbol changes from database
-pub async fn get_pending_changes(pool: &SqlitePool) -> Result<Vec<StoredSymbolChange>> {
-    let changes = sqlx::query_as!(
-        StoredSymbolChange,
+pub async fn get_pending_changes(pool: impl Into<CorePool>) -> Result<Vec<StoredSymbolChange>> {
+    let changes = core_query!(pool.into(), |pool| sqlx::query_as::<_, StoredSymbolChange>(
         r#"
-        SELECT 
-            id as "id?",
+        SELECT
+            id,
             old_symbol,
             new_symbol,
             change_date,
             company_name,
             reason,
-            applied as "applied!"
+            COALESCE(applied, 0) AS applied
         FROM symbol_changes
         WHERE applied = 0
         ORDER BY change_date DESC, old_symbol
         "#
     )
-    .fetch_all(pool)
-    .await?;
+    .fetch_all(&pool)
+    .await?);
 
     Ok(changes)
 }
 
-/// Check which symbol changes apply to our current configuration
+/// Check which symbol changes apply to our current configuration or to a watchlist
 pub async fn check_ticker_updates(
-    pool: &SqlitePool,
+    pool: impl Into<CorePool>,
+    local: &SqlitePool,
     config_path: &str,
 ) -> Result<SymbolChangeReport> {
     let pending_changes = get_pending_changes(pool).await?;
+    let watchlist_tickers: HashSet<String> =
+        sqlx::query_scalar("SELECT DISTINCT ticker FROM watchlist_tickers")
+            .fetch_all(local)
+            .await?
+            .into_iter()
+            .collect();
 
     // Read current config
     let config_content = fs::read_to_string(config_path).context("Failed to read config.toml")?;
@@ -141,6 +153,8 @@ pub async fn check_ticker_updates(
             } else {
                 applicable_changes.push(change.clone());
             }
+        } else if watchlist_tickers.contains(&change.old_symbol) {
+            applicable_changes.push(change.clone());
         } else {
             non_applicable_changes.push(change.clone());
         }
@@ -166,12 +180,124 @@ fn is_valid_ticker_symbol(symbol: &str) -> bool {
             .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
 }
 
-/// Apply ticker updates to the configuration file
+/// Config entry replacing the old ticker, annotated with the change date
+/// (today on `clock` when the change has none)
+fn replacement_for(change: &StoredSymbolChange, clock: &dyn Clock) -> String {
+    let change_date = change
+        .change_date
+        .clone()
+        .unwrap_or_else(|| clock.today().format("%Y-%m-%d").to_string());
+    format!(
+        "\"{}\" # Changed from {} on {}",
+        change.new_symbol, change.old_symbol, change_date
+    )
+}
+
+/// Column with the date of a row, so that only rows from before a change
+/// move; later rows under the old symbol belong to whoever uses it next
+#[derive(Debug, Clone, Copy)]
+enum RowDate {
+    /// Undated rows always move
+    None,
+    /// Unix timestamp
+    Timestamp(&'static str),
+    /// `YYYY-MM-DD`
+    Text(&'static str),
+}
+
+/// Tables in the core database that store a ticker, with the other columns
+/// of their primary key and their date column
+const CORE_TICKER_TABLES: &[(&str, &[&str], RowDate)] = &[
+    (
+        "market_caps",
+        &["timestamp"],
+        RowDate::Timestamp("timestamp"),
+    ),
+    ("ticker_details", &[], RowDate::None),
+];
+
+/// Tables that always live in SQLite and store a ticker, with the other
+/// columns of their primary key and their date column
+const LOCAL_TICKER_TABLES: &[(&str, &[&str], RowDate)] = &[
+    ("watchlist_tickers", &["watchlist"], RowDate::None),
+    (
+        "watchlist_market_caps",
+        &["timestamp"],
+        RowDate::Timestamp("timestamp"),
+    ),
+    ("rankings", &["timestamp"], RowDate::Timestamp("timestamp")),
+    ("universe_snapshots", &["date"], RowDate::Text("date")),
+    (
+        "marketcap_aggregates",
+        &["granularity", "period"],
+        RowDate::Text("period_start"),
+    ),
+];
+
+/// Change date of a symbol change, `None` when it has none
+fn change_date(change: &StoredSymbolChange) -> Result<Option<NaiveDate>> {
+    change
+        .change_date
+        .as_deref()
+        .map(|date| {
+            NaiveDate::parse_from_str(date, "%Y-%m-%d").with_context(|| {
+                format!(
+                    "Invalid change date '{}' for {} -> {}",
+                    date, change.old_symbol, change.new_symbol
+                )
+            })
+        })
+        .transpose()
+}
+
+/// Move the rows of `$2` to `$1`, only those dated before `before` when
+/// given. Rows whose key the new symbol already has stay under the old
+/// symbol, so nothing is overwritten.
+fn move_ticker_sql(table: &str, keys: &[&str], date: RowDate, before: Option<NaiveDate>) -> String {
+    let same_key: String = keys
+        .iter()
+        .map(|key| format!(" AND n.{key} = {table}.{key}"))
+        .collect();
+    // A parsed date and its timestamp are safe to inline
+    let dated = match (date, before) {
+        (RowDate::Timestamp(column), Some(before)) => format!(
+            " AND {column} < {}",
+            before.and_time(NaiveTime::MIN).and_utc().timestamp()
+        ),
+        (RowDate::Text(column), Some(before)) => {
+            format!(" AND {column} < '{}'", before.format("%Y-%m-%d"))
+        }
+        _ => String::new(),
+    };
+    format!(
+        "UPDATE {table} SET ticker = $1 WHERE ticker = $2{dated} \
+         AND NOT EXISTS (SELECT 1 FROM {table} AS n WHERE n.ticker = $1{same_key})"
+    )
+}
+
+/// Run a statement in the open transaction of the database holding the core
+/// tables: PostgreSQL when `$pg` is set, otherwise the SQLite transaction
+macro_rules! execute_core {
+    ($local:expr, $pg:expr, $query:expr) => {
+        match $pg.as_mut() {
+            Some(tx) => $query.execute(&mut **tx).await?.rows_affected(),
+            None => $query.execute(&mut *$local).await?.rows_affected(),
+        }
+    };
+}
+
+/// Apply ticker updates to the configuration file, watchlists and every
+/// stored table keyed by ticker. The database updates run in one transaction
+/// (one per database when the core tables are in PostgreSQL); a dry run
+/// rolls them back after printing what they would change. `local` is the
+/// SQLite database, which also holds the core tables unless `pool` is PostgreSQL.
 pub async fn apply_ticker_updates(
-    pool: &SqlitePool,
+    pool: impl Into<CorePool>,
+    local: &SqlitePool,
     config_path: &str,
     changes_to_apply: Vec<StoredSymbolChange>,
     dry_run: bool,
+    clock: &dyn Clock,
 ) -> Result<()> {
     if changes_to_apply.is_empty() {
         println!("No changes to apply.");
@@ -192,24 +318,31 @@ pub async fn apply_ticker_updates(
                 change.new_symbol
             );
         }
+        change_date(change)?;
     }
 
+    let pool = pool.into();
+
     // Read current config
     let config_content = fs::read_to_string(config_path).context("Failed to read config.toml")?;
-
-    if !dry_run {
-        // Create backup
-        let backup_path = format!(
-            "{}.backup.{}",
-            config_path,
-            Utc::now().format("%Y%m%d_%H%M%S")
-        );
-        fs::copy(config_path, &backup_path).context("Failed to create config backup")?;
-        println!("✅ Created backup at: {}", backup_path);
-    }
-
     let mut updated_content = config_content.clone();
 
+    let mut local_tx = local.begin().await?;
+    let mut pg_tx = match &pool {
+        CorePool::Postgres(pg) => Some(pg.begin().await?),
+        CorePool::Sqlite(_) => None,
+    };
+    let peer_groups = get_predefined_peer_groups();
+    let mut applied = 0;
+    // The backup also names the batch, for undo-symbol-changes, which undoes
+    // the batch with the highest sequence number
+    let backup_path = free_backup_path(config_path);
+    let batch_seq: i64 = core_query!(&pool, |pool| sqlx::query_scalar(
+        "SELECT CAST(COALESCE(MAX(applied_seq), 0) + 1 AS BIGINT) FROM symbol_changes"
+    )
+    .fetch_one(pool)
+    .await?);
+
     for change in &changes_to_apply {
         println!(
             "Applying change: {} -> {}",
@@ -219,49 +352,269 @@ pub async fn apply_ticker_updates(
         // Replace the ticker in the config content
         // Handle both quoted and potential comment scenarios
         let old_pattern = format!("\"{}\"", change.old_symbol);
-        let new_replacement = format!(
-            "\"{}\" # Changed from {} on {}",
-            change.new_symbol,
-            change.old_symbol,
-            change
-                .change_date
-                .as_ref()
-                .unwrap_or(&Utc::now().format("%Y-%m-%d").to_string())
-        );
-
-        if updated_content.contains(&old_pattern) {
+        let new_replacement = replacement_for(change, clock);
+        let in_config = updated_content.contains(&old_pattern);
+        if in_config {
             updated_content = updated_content.replace(&old_pattern, &new_replacement);
+        } else {
+            println!("   config.toml: {} not listed", change.old_symbol);
+        }
 
-            if !dry_run {
-                // Mark as applied in database
-                sqlx::query!(
-                    "UPDATE symbol_changes SET applied = 1, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
-                    change.id
-                )
-                .execute(pool)
-                .await?;
+        let before = change_date(change)?;
+        let mut moved_rows = 0;
+        for (table, keys, date) in CORE_TICKER_TABLES {
+            let sql = move_ticker_sql(table, keys, *date, before);
+            let moved = execute_core!(
+                local_tx,
+                pg_tx,
+                sqlx::query(&sql)
+                    .bind(&change.new_symbol)
+                    .bind(&change.old_symbol)
+            );
+            moved_rows += moved;
+            if moved > 0 {
+                println!("   {}: {} rows", table, moved);
+            }
+        }
+        for (table, keys, date) in LOCAL_TICKER_TABLES {
+            let moved = sqlx::query(&move_ticker_sql(table, keys, *date, before))
+                .bind(&change.new_symbol)
+                .bind(&change.old_symbol)
+                .execute(&mut *local_tx)
+                .await?
+                .rows_affected();
+            moved_rows += moved;
+            if moved > 0 {
+                println!("   {}: {} rows", table, moved);
             }
+        }
+
+        // Peer groups are defined in code; comparisons look their members up
+        // under the current symbol (see ticker_aliases)
+        for group in peer_groups
+            .iter()
+            .filter(|g| g.tickers.contains(&change.old_symbol))
+        {
+            println!(
+                "   peer group {}: {} is looked up as {}",
+                group.name, change.old_symbol, change.new_symbol
+            );
+        }
+
+        if in_config || moved_rows > 0 {
+            execute_core!(
+                local_tx,
+                pg_tx,
+                sqlx::query(
+                    "UPDATE symbol_changes SET applied = 1, applied_batch = $2, applied_seq = $3, updated_at = CURRENT_TIMESTAMP WHERE id = $1",
+                )
+                .bind(change.id)
+                .bind(&backup_path)
+                .bind(batch_seq)
+            );
+            applied += 1;
         } else {
             println!(
-                "⚠️  Warning: Could not find {} in config",
+                "⚠️  Warning: Could not find {} in config or the database",
                 change.old_symbol
             );
         }
     }
 
     if dry_run {
+        // Dropping the transactions rolls them back
         println!("\n=== DRY RUN - Changes that would be made: ===");
         println!("{}", updated_content);
         println!("=== END DRY RUN ===");
-    } else {
-        // Write updated config
-        fs::write(config_path, updated_content).context("Failed to write updated config")?;
-        println!(
-            "✅ Updated config.toml with {} changes",
-            changes_to_apply.len()
-        );
+        return Ok(());
+    }
+
+    // Create backup, never over an earlier one
+    fs::File::create_new(&backup_path)
+        .and_then(|mut file| file.write_all(config_content.as_bytes()))
+        .with_context(|| format!("Failed to create config backup {}", backup_path))?;
+    println!("✅ Created backup at: {}", backup_path);
+
+    // Write updated config, and put the old one back if the database can't commit
+    fs::write(config_path, &updated_content).context("Failed to write updated config")?;
+    let committed = async {
+        if let Some(tx) = pg_tx {
+            tx.commit().await?;
+        }
+        local_tx.commit().await
+    }
+    .await;
+    if let Err(e) = committed {
+        fs::write(config_path, &config_content).context("Failed to restore config.toml")?;
+        return Err(e).context("Failed to commit the symbol changes; config.toml was restored");
+    }
+
+    println!(
+        "✅ Applied {} of {} changes to config.toml and the database",
+        applied,
+        changes_to_apply.len()
+    );
+
+    Ok(())
+}
+
+/// Path for the config backup of a new batch: `<config>.backup.<timestamp>`,
+/// with `_2`, `_3`, ... when a batch was applied in the same second. The
+/// timestamp is from the wall clock, not `--as-of`, so it is a real record of
+/// when the batch was applied.
+fn free_backup_path(config_path: &str) -> String {
+    let base = format!(
+        "{}.backup.{}",
+        config_path,
+        chrono::Local::now().format("%Y%m%d_%H%M%S")
+    );
+    (1..)
+        .map(|n| match n {
+            1 => base.clone(),
+            n => format!("{}_{}", base, n),
+        })
+        .find(|path| !std::path::Path::new(path).exists())
+        .expect("some backup name is free")
+}
+
+/// Undo the config entry written by `replacement_for`, whatever date it carries
+fn revert_replacement(content: &str, change: &StoredSymbolChange) -> Option<String> {
+    let marker = format!(
+        "\"{}\" # Changed from {} on ",
+        change.new_symbol, change.old_symbol
+    );
+    let start = content.find(&marker)?;
+    let date_start = start + marker.len();
+    let end = content[date_start..]
+        .find(|c: char| !c.is_ascii_digit() && c != '-')
+        .map_or(content.len(), |offset| date_start + offset);
+    Some(format!(
+        "{}\"{}\"{}",
+        &content[..start],
+        change.old_symbol,
+        &content[end..]
+    ))
+}
+
+/// Ask on stdin; anything but y/yes (including no terminal) declines
+fn confirm(question: &str) -> Result<bool> {
+    print!("{} [y/N] ", question);
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Escape `\`, `%` and `_` for a `LIKE ... ESCAPE '\'` pattern
+fn escape_like(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Revert the most recent batch applied by `apply_ticker_updates`: restore
+/// config.toml and mark the changes pending again. The config is restored
+/// from the batch's backup when nothing else changed since; otherwise only
+/// the entries the batch rewrote are reverted. Rows moved to the new symbols
+/// stay there, since those symbols are still the companies' real ones.
+pub async fn undo_last_batch(
+    pool: impl Into<CorePool>,
+    config_path: &str,
+    assume_yes: bool,
+) -> Result<()> {
+    let pool = pool.into();
+    let batch: Option<String> = core_query!(&pool, |pool| sqlx::query_scalar(
+        r#"
+        SELECT applied_batch FROM symbol_changes
+        WHERE applied = 1 AND applied_batch LIKE $1 ESCAPE '\'
+        ORDER BY applied_seq DESC
+        LIMIT 1
+        "#
+    )
+    .bind(format!("{}.backup.%", escape_like(config_path)))
+    .fetch_optional(pool)
+    .await?);
+    let Some(batch) = batch else {
+        println!("No applied batch of symbol changes to undo.");
+        return Ok(());
+    };
+
+    let changes = core_query!(&pool, |pool| sqlx::query_as::<_, StoredSymbolChange>(
+        r#"
+        SELECT id, old_symbol, new_symbol, change_date, company_name, reason,
+            COALESCE(applied, 0) AS applied
+        FROM symbol_changes
+        WHERE applied_batch = $1
+        ORDER BY old_symbol
+        "#
+    )
+    .bind(&batch)
+    .fetch_all(pool)
+    .await?);
+
+    println!("Most recent batch ({}):", batch);
+    for change in &changes {
+        println!("  {} -> {}", change.old_symbol, change.new_symbol);
     }
 
+    let current = fs::read_to_string(config_path).context("Failed to read config.toml")?;
+    let backup = fs::read_to_string(&batch).ok();
+    let mut reverted = current.clone();
+    let mut missing = Vec::new();
+    for change in &changes {
+        match revert_replacement(&reverted, change) {
+            Some(content) => reverted = content,
+            None => missing.push(change.new_symbol.as_str()),
+        }
+    }
+    let reverted = match backup {
+        _ if missing.is_empty() => {
+            if backup.as_deref() == Some(reverted.as_str()) {
+                println!("config.toml: back to {}", batch);
+            } else {
+                println!("config.toml: reverting the rewritten entries, keeping later edits");
+            }
+            reverted
+        }
+        Some(backup) => {
+            println!(
+                "⚠️  config.toml no longer has the entries written for {}; restoring {} (edits made since are lost)",
+                missing.join(", "),
+                batch
+            );
+            backup
+        }
+        None => {
+            println!(
+                "⚠️  config.toml no longer has the entries written for {} and {} is gone; reverting the rest",
+                missing.join(", "),
+                batch
+            );
+            reverted
+        }
+    };
+
+    if !assume_yes && !confirm(&format!("Undo these {} changes?", changes.len()))? {
+        println!("Cancelled.");
+        return Ok(());
+    }
+
+    let reset = core_query!(&pool, |pool| {
+        sqlx::query(
+        "UPDATE symbol_changes SET applied = 0, applied_batch = NULL, applied_seq = NULL, updated_at = CURRENT_TIMESTAMP WHERE applied_batch = $1",
+    )
+    .bind(&batch)
+    .execute(pool)
+    .await?
+    .rows_affected()
+    });
+    fs::write(config_path, reverted).context("Failed to write config.toml")?;
+    println!("✅ Undid {} symbol changes; they are pending again", reset);
     Ok(())
 }
 
@@ -505,6 +858,29 @@ mod tests {
         assert!(new_replacement.contains("Changed from"));
     }
 
+    #[test]
+    fn test_replacement_defaults_to_clock_date() {
+        let clock = crate::clock::FixedClock::parse("2025-06-30").unwrap();
+        let mut change = StoredSymbolChange {
+            id: None,
+            old_symbol: "OLD".to_string(),
+            new_symbol: "NEW".to_string(),
+            change_date: None,
+            company_name: None,
+            reason: None,
+            applied: 0,
+        };
+        assert_eq!(
+            replacement_for(&change, &clock),
+            "\"NEW\" # Changed from OLD on 2025-06-30"
+        );
+        change.change_date = Some("2025-01-15".to_string());
+        assert_eq!(
+            replacement_for(&change, &clock),
+            "\"NEW\" # Changed from OLD on 2025-01-15"
+        );
+    }
+
     // Tests for HashSet operations (used in check_ticker_updates)
     #[test]
     fn test_ticker_hashset_operations() {
@@ -597,6 +973,339 @@ mod tests {
         assert_eq!(change.new_symbol, "META");
         assert_eq!(change.applied, 0);
     }
+
+    #[tokio::test]
+    async fn test_apply_updates_config_watchlists_and_tables() {
+        let pool = crate::db::create_db_pool("sqlite::memory:").await.unwrap();
+        for sql in [
+            "INSERT INTO symbol_changes (old_symbol, new_symbol, change_date) VALUES ('FB', 'META', '2022-06-09')",
+            "INSERT INTO market_caps (ticker, name, timestamp) VALUES ('FB', 'Facebook', 100), ('FB', 'Facebook', 200), ('META', 'Meta', 200)",
+            "INSERT INTO rankings (ticker, date, timestamp, rank) VALUES ('FB', '1970-01-01', 100, 1)",
+            "INSERT INTO watchlists (name) VALUES ('social')",
+            "INSERT INTO watchlist_tickers (watchlist, ticker) VALUES ('social', 'FB')",
+        ] {
+            sqlx::query(sql).execute(&pool).await.unwrap();
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config.toml");
+        std::fs::write(&config_path, "us_tickers = [\"FB\", \"NKE\"]\n").unwrap();
+        let config_path = config_path.to_str().unwrap();
+        let clock = crate::clock::FixedClock::parse("2025-01-01").unwrap();
+
+        let count = |sql: &'static str| {
+            let pool = pool.clone();
+            async move {
+                sqlx::query_scalar::<_, i64>(sql)
+                    .fetch_one(&pool)
+                    .await
+                    .unwrap()
+            }
+        };
+
+        let report = check_ticker_updates(&pool, &pool, config_path)
+            .await
+            .unwrap();
+        assert_eq!(report.applicable_changes.len(), 1);
+
+        // A dry run changes nothing
+        apply_ticker_updates(
+            &pool,
+            &pool,
+            config_path,
+            report.applicable_changes.clone(),
+            true,
+            &clock,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            count("SELECT COUNT(*) FROM market_caps WHERE ticker = 'FB'").await,
+            2
+        );
+        assert!(
+            std::fs::read_to_string(config_path)
+                .unwrap()
+                .contains("\"FB\",")
+        );
+
+        apply_ticker_updates(
+            &pool,
+            &pool,
+            config_path,
+            report.applicable_changes,
+            false,
+            &clock,
+        )
+        .await
+        .unwrap();
+        assert!(
+            std::fs::read_to_string(config_path)
+                .unwrap()
+                .contains("\"META\" # Changed from FB on 2022-06-09")
+        );
+        // The snapshot META already has stays, FB's row for it is kept apart
+        assert_eq!(
+            count("SELECT COUNT(*) FROM market_caps WHERE ticker = 'META'").await,
+            2
+        );
+        assert_eq!(
+            count("SELECT COUNT(*) FROM market_caps WHERE ticker = 'FB'").await,
+            1
+        );
+        assert_eq!(
+            count("SELECT COUNT(*) FROM rankings WHERE ticker = 'META'").await,
+            1
+        );
+        assert_eq!(
+            count("SELECT COUNT(*) FROM watchlist_tickers WHERE ticker = 'META'").await,
+            1
+        );
+        assert_eq!(count("SELECT applied FROM symbol_changes").await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_apply_moves_only_rows_before_the_change_date() {
+        let pool = crate::db::create_db_pool("sqlite::memory:").await.unwrap();
+        // 2022-06-08 and 2022-06-10 around the change on 2022-06-09; the
+        // later FB rows belong to whoever uses the symbol next
+        for sql in [
+            "INSERT INTO symbol_changes (old_symbol, new_symbol, change_date) VALUES ('FB', 'META', '2022-06-09')",
+            "INSERT INTO market_caps (ticker, name, timestamp) VALUES ('FB', 'Facebook', 1654646400), ('FB', 'Other', 1654819200)",
+            "INSERT INTO rankings (ticker, date, timestamp, rank) VALUES ('FB', '2022-06-08', 1654646400, 1), ('FB', '2022-06-10', 1654819200, 2)",
+            "INSERT INTO universe_snapshots (date, ticker) VALUES ('2022-06-08', 'FB'), ('2022-06-09', 'FB')",
+        ] {
+            sqlx::query(sql).execute(&pool).await.unwrap();
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config.toml");
+        std::fs::write(&config_path, "us_tickers = [\"FB\"]\n").unwrap();
+        let config_path = config_path.to_str().unwrap();
+        let clock = crate::clock::FixedClock::parse("2025-01-01").unwrap();
+
+        let report = check_ticker_updates(&pool, &pool, config_path)
+            .await
+            .unwrap();
+        apply_ticker_updates(
+            &pool,
+            &pool,
+            config_path,
+            report.applicable_changes,
+            false,
+            &clock,
+        )
+        .await
+        .unwrap();
+
+        for (table, column) in [
+            ("market_caps", "timestamp"),
+            ("rankings", "timestamp"),
+            ("universe_snapshots", "date"),
+        ] {
+            let rows: Vec<(String, String)> = sqlx::query_as(&format!(
+                "SELECT ticker, CAST({column} AS TEXT) FROM {table} ORDER BY {column}"
+            ))
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+            let tickers: Vec<&str> = rows.iter().map(|(t, _)| t.as_str()).collect();
+            assert_eq!(tickers, vec!["META", "FB"], "{}", table);
+        }
+    }
+
+    #[test]
+    fn test_move_ticker_sql_without_change_date_moves_every_row() {
+        let sql = move_ticker_sql(
+            "rankings",
+            &["timestamp"],
+            RowDate::Timestamp("timestamp"),
+            None,
+        );
+        assert!(!sql.contains("timestamp <"));
+        let date = NaiveDate::from_ymd_opt(2022, 6, 9);
+        let sql = move_ticker_sql(
+            "rankings",
+            &["timestamp"],
+            RowDate::Timestamp("timestamp"),
+            date,
+        );
+        assert!(sql.contains("AND timestamp < 1654732800"));
+        let sql = move_ticker_sql("ticker_details", &[], RowDate::None, date);
+        assert!(!sql.contains(" < "));
+    }
+
+    #[tokio::test]
+    async fn test_undo_last_batch_reverts_config_and_marks_pending() {
+        let pool = crate::db::create_db_pool("sqlite::memory:").await.unwrap();
+        sqlx::query(
+            "INSERT INTO symbol_changes (old_symbol, new_symbol, change_date) VALUES ('FB', 'META', '2022-06-09'), ('TWTR', 'X', '2023-07-24')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config.toml");
+        let original = "us_tickers = [\n    \"FB\", # Facebook\n    \"TWTR\", # Twitter\n    \"NKE\", # Nike\n]\n";
+        std::fs::write(&config_path, original).unwrap();
+        let config_path = config_path.to_str().unwrap();
+
+        // Nothing applied yet
+        undo_last_batch(&pool, config_path, true).await.unwrap();
+        assert_eq!(std::fs::read_to_string(config_path).unwrap(), original);
+
+        // Two batches: FB first, TWTR a day later
+        let report = check_ticker_updates(&pool, &pool, config_path)
+            .await
+            .unwrap();
+        let (fb, twtr): (Vec<_>, Vec<_>) = report
+            .applicable_changes
+            .into_iter()
+            .partition(|c| c.old_symbol == "FB");
+        let day_one = crate::clock::FixedClock::parse("2025-01-01").unwrap();
+        let day_two = crate::clock::FixedClock::parse("2025-01-02").unwrap();
+        apply_ticker_updates(&pool, &pool, config_path, fb, false, &day_one)
+            .await
+            .unwrap();
+        apply_ticker_updates(&pool, &pool, config_path, twtr, false, &day_two)
+            .await
+            .unwrap();
+        let after_fb = std::fs::read_to_string(batch_of(&pool, "TWTR").await).unwrap();
+
+        // Untouched since: back to the backup
+        undo_last_batch(&pool, config_path, true).await.unwrap();
+        assert_eq!(std::fs::read_to_string(config_path).unwrap(), after_fb);
+        let pending: Vec<String> = sqlx::query_scalar(
+            "SELECT old_symbol FROM symbol_changes WHERE applied = 0 AND applied_batch IS NULL",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(pending, vec!["TWTR"]);
+
+        // Edited since: only the rewritten entry is reverted
+        let edited = format!("{}eu_tickers = [\"MC.PA\"]\n", after_fb);
+        std::fs::write(config_path, &edited).unwrap();
+        undo_last_batch(&pool, config_path, true).await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(config_path).unwrap(),
+            format!("{}eu_tickers = [\"MC.PA\"]\n", original)
+        );
+        assert_eq!(
+            count_applied(&pool).await,
+            0,
+            "both batches are pending again"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_undo_matches_the_config_path_literally() {
+        let pool = crate::db::create_db_pool("sqlite::memory:").await.unwrap();
+        sqlx::query(
+            "INSERT INTO symbol_changes (old_symbol, new_symbol, change_date) VALUES ('FB', 'META', '2022-06-09')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let applied_path = dir.path().join("prodXconfig.toml");
+        let other_path = dir.path().join("prod_config.toml");
+        for path in [&applied_path, &other_path] {
+            std::fs::write(path, "us_tickers = [\"FB\"]\n").unwrap();
+        }
+        let (applied_path, other_path) =
+            (applied_path.to_str().unwrap(), other_path.to_str().unwrap());
+        let clock = crate::clock::FixedClock::parse("2025-01-01").unwrap();
+
+        let report = check_ticker_updates(&pool, &pool, applied_path)
+            .await
+            .unwrap();
+        apply_ticker_updates(
+            &pool,
+            &pool,
+            applied_path,
+            report.applicable_changes,
+            false,
+            &clock,
+        )
+        .await
+        .unwrap();
+
+        // `_` in the other path must not match the `X` of the applied one
+        undo_last_batch(&pool, other_path, true).await.unwrap();
+        assert_eq!(count_applied(&pool).await, 1);
+        assert_eq!(escape_like("a_b%c\\d"), "a\\_b\\%c\\\\d");
+    }
+
+    #[tokio::test]
+    async fn test_batches_with_the_same_or_an_earlier_as_of_stay_apart() {
+        let pool = crate::db::create_db_pool("sqlite::memory:").await.unwrap();
+        sqlx::query(
+            "INSERT INTO symbol_changes (old_symbol, new_symbol, change_date) VALUES ('FB', 'META', '2022-06-09'), ('TWTR', 'X', '2023-07-24')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config.toml");
+        let original = "us_tickers = [\n    \"FB\",\n    \"TWTR\",\n]\n";
+        std::fs::write(&config_path, original).unwrap();
+        let config_path = config_path.to_str().unwrap();
+        let later = crate::clock::FixedClock::parse("2025-01-02").unwrap();
+        let earlier = crate::clock::FixedClock::parse("2024-01-01").unwrap();
+
+        let report = check_ticker_updates(&pool, &pool, config_path)
+            .await
+            .unwrap();
+        let change = |symbol: &str| {
+            report
+                .applicable_changes
+                .iter()
+                .filter(|c| c.old_symbol == symbol)
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        let apply = |changes, clock| {
+            let pool = pool.clone();
+            async move {
+                apply_ticker_updates(&pool, &pool, config_path, changes, false, &clock)
+                    .await
+                    .unwrap();
+            }
+        };
+
+        // Same --as-of, usually within the same second: two backups
+        apply(change("FB"), later).await;
+        apply(change("TWTR"), later).await;
+        let (fb_batch, twtr_batch) = (batch_of(&pool, "FB").await, batch_of(&pool, "TWTR").await);
+        assert_ne!(fb_batch, twtr_batch);
+        assert_eq!(std::fs::read_to_string(&fb_batch).unwrap(), original);
+        let after_fb = std::fs::read_to_string(&twtr_batch).unwrap();
+        assert!(after_fb.contains("\"META\""));
+
+        undo_last_batch(&pool, config_path, true).await.unwrap();
+        assert_eq!(std::fs::read_to_string(config_path).unwrap(), after_fb);
+        assert_eq!(count_applied(&pool).await, 1);
+
+        // An --as-of before FB's batch is still the latest batch
+        apply(change("TWTR"), earlier).await;
+        undo_last_batch(&pool, config_path, true).await.unwrap();
+        assert_eq!(std::fs::read_to_string(config_path).unwrap(), after_fb);
+        assert_eq!(batch_of(&pool, "FB").await, fb_batch);
+    }
+
+    async fn batch_of(pool: &SqlitePool, old_symbol: &str) -> String {
+        sqlx::query_scalar("SELECT applied_batch FROM symbol_changes WHERE old_symbol = ?")
+            .bind(old_symbol)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    async fn count_applied(pool: &SqlitePool) -> i64 {
+        sqlx::query_scalar("SELECT COUNT(*) FROM symbol_changes WHERE applied = 1")
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
 }
 
 // Required for serialization tests
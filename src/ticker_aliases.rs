@@ -0,0 +1,247 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Continuous histories for renamed symbols (FB → META)
+//!
+//! Snapshots taken before a rename list the company under its old symbol, so
+//! comparisons would report it as delisted and the new symbol as new. The
+//! `symbol_changes` table is turned into an alias map that re-keys records to
+//! the current symbol, following chains of renames. A rename only applies to
+//! snapshots taken before it, so a symbol later reused by another company
+//! keeps its own history.
+
+use anyhow::Result;
+use chrono::NaiveDate;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::db::{CorePool, core_query};
+
+/// One stored rename
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rename {
+    pub old_symbol: String,
+    pub new_symbol: String,
+    /// Renames without a date apply to every snapshot
+    pub date: Option<NaiveDate>,
+}
+
+/// Renames per old symbol, earliest first
+#[derive(Debug, Default, Clone)]
+pub struct TickerAliases {
+    by_old: HashMap<String, Vec<Rename>>,
+}
+
+impl TickerAliases {
+    pub fn new(renames: Vec<Rename>) -> Self {
+        let mut by_old: HashMap<String, Vec<Rename>> = HashMap::new();
+        for rename in renames {
+            if rename.old_symbol != rename.new_symbol {
+                by_old
+                    .entry(rename.old_symbol.clone())
+                    .or_default()
+                    .push(rename);
+            }
+        }
+        for renames in by_old.values_mut() {
+            renames.sort_by_key(|r| r.date);
+        }
+        Self { by_old }
+    }
+
+    /// Load every rename from the `symbol_changes` table, applied or not
+    pub async fn load(pool: impl Into<CorePool>) -> Result<Self> {
+        let rows: Vec<(String, String, Option<String>)> =
+            core_query!(pool.into(), |pool| sqlx::query_as(
+                "SELECT old_symbol, new_symbol, change_date FROM symbol_changes"
+            )
+            .fetch_all(&pool)
+            .await?);
+        let renames = rows
+            .into_iter()
+            .map(|(old_symbol, new_symbol, date)| Rename {
+                old_symbol,
+                new_symbol,
+                date: date.and_then(|d| NaiveDate::parse_from_str(&d, "%Y-%m-%d").ok()),
+            })
+            .collect();
+        Ok(Self::new(renames))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_old.is_empty()
+    }
+
+    /// The current symbol of a ticker listed in a snapshot taken on `date`
+    pub fn current_symbol<'a>(&'a self, ticker: &'a str, date: NaiveDate) -> &'a str {
+        let mut current = ticker;
+        let mut after = date;
+        let mut seen = BTreeSet::from([ticker]);
+        // Each step takes the first rename after the previous one, so dated
+        // round trips (A → B → A) end where they should. Undated loops stop.
+        while let Some(rename) = self
+            .by_old
+            .get(current)
+            .and_then(|renames| renames.iter().find(|r| r.date.is_none_or(|d| d > after)))
+        {
+            if !seen.insert(rename.new_symbol.as_str()) && rename.date.is_none() {
+                break;
+            }
+            current = &rename.new_symbol;
+            if let Some(date) = rename.date {
+                after = date;
+            }
+        }
+        current
+    }
+
+    /// Re-key the records of one snapshot to their current symbols. A record
+    /// keeps its symbol when the snapshot already lists the current one.
+    pub fn apply<T>(
+        &self,
+        date: NaiveDate,
+        records: &mut [T],
+        ticker: impl Fn(&mut T) -> &mut String,
+        applied: &mut AppliedAliases,
+    ) {
+        if self.is_empty() {
+            return;
+        }
+        let mut listed: BTreeSet<String> = records.iter_mut().map(|r| ticker(r).clone()).collect();
+        for record in records.iter_mut() {
+            let symbol = ticker(record);
+            let current = self.current_symbol(symbol, date).to_string();
+            // `insert` also keeps two old symbols from merging into one row
+            if current != *symbol && listed.insert(current.clone()) {
+                applied.record(symbol, &current);
+                *symbol = current;
+            }
+        }
+    }
+}
+
+/// Renames applied while loading snapshots: old symbols per current symbol
+#[derive(Debug, Default, Clone)]
+pub struct AppliedAliases {
+    formerly: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl AppliedAliases {
+    fn record(&mut self, old_symbol: &str, current: &str) {
+        self.formerly
+            .entry(current.to_string())
+            .or_default()
+            .insert(old_symbol.to_string());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.formerly.is_empty()
+    }
+
+    /// Earlier symbols of a row's company, e.g. "FB"
+    pub fn annotation(&self, ticker: &str) -> Option<String> {
+        self.formerly
+            .get(ticker)
+            .map(|old| old.iter().cloned().collect::<Vec<_>>().join(", "))
+    }
+
+    /// Markdown section listing the renamed symbols
+    pub fn markdown_section(&self) -> String {
+        let mut section = String::from("## Symbol Changes\n\n");
+        section.push_str(
+            "Earlier snapshots of these companies are listed under their current symbol.\n\n",
+        );
+        for (current, old) in &self.formerly {
+            let old: Vec<&str> = old.iter().map(String::as_str).collect();
+            section.push_str(&format!("- {} → **{}**\n", old.join(", "), current));
+        }
+        section.push('\n');
+        section
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn rename(old: &str, new: &str, on: &str) -> Rename {
+        Rename {
+            old_symbol: old.to_string(),
+            new_symbol: new.to_string(),
+            date: Some(date(on)),
+        }
+    }
+
+    #[test]
+    fn test_current_symbol_follows_dated_renames() {
+        let aliases = TickerAliases::new(vec![
+            rename("FB", "META", "2022-06-09"),
+            rename("META", "MTA", "2030-01-01"),
+            // Round trip: renamed and later renamed back
+            rename("AA", "BB", "2020-01-01"),
+            rename("BB", "AA", "2021-01-01"),
+        ]);
+
+        assert_eq!(aliases.current_symbol("FB", date("2021-12-31")), "MTA");
+        assert_eq!(aliases.current_symbol("META", date("2025-01-01")), "MTA");
+        // FB was reused after the rename; that listing is another company
+        assert_eq!(aliases.current_symbol("FB", date("2023-01-01")), "FB");
+        assert_eq!(aliases.current_symbol("AA", date("2019-06-01")), "AA");
+        assert_eq!(aliases.current_symbol("BB", date("2020-06-01")), "AA");
+        assert_eq!(aliases.current_symbol("NKE", date("2020-06-01")), "NKE");
+
+        // Undated loops stop
+        let looping = TickerAliases::new(vec![
+            Rename {
+                old_symbol: "X".to_string(),
+                new_symbol: "Y".to_string(),
+                date: None,
+            },
+            Rename {
+                old_symbol: "Y".to_string(),
+                new_symbol: "X".to_string(),
+                date: None,
+            },
+        ]);
+        assert_eq!(looping.current_symbol("X", date("2020-01-01")), "Y");
+    }
+
+    #[test]
+    fn test_apply_rekeys_records_and_records_annotations() {
+        let aliases = TickerAliases::new(vec![
+            rename("FB", "META", "2022-06-09"),
+            rename("OLD", "NEW", "2022-01-01"),
+        ]);
+        let mut applied = AppliedAliases::default();
+
+        // Both the old and the current symbol are listed: leave them apart
+        let mut records = vec!["FB".to_string(), "OLD".to_string(), "NEW".to_string()];
+        aliases.apply(date("2021-12-31"), &mut records, |r| r, &mut applied);
+        assert_eq!(records, vec!["META", "OLD", "NEW"]);
+
+        assert_eq!(applied.annotation("META").as_deref(), Some("FB"));
+        assert_eq!(applied.annotation("NEW"), None);
+        assert_eq!(
+            applied.markdown_section(),
+            "## Symbol Changes\n\nEarlier snapshots of these companies are listed under their current symbol.\n\n- FB → **META**\n\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_load_from_symbol_changes() {
+        let pool = crate::db::create_db_pool("sqlite::memory:").await.unwrap();
+        sqlx::query(
+            "INSERT INTO symbol_changes (old_symbol, new_symbol, change_date, applied) VALUES ('FB', 'META', '2022-06-09', 1)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let aliases = TickerAliases::load(&pool).await.unwrap();
+        assert_eq!(aliases.current_symbol("FB", date("2022-01-01")), "META");
+    }
+}
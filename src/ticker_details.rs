@@ -2,8 +2,8 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-only
 
+use crate::db::{CorePool, core_query};
 use anyhow::Result;
-use sqlx::sqlite::SqlitePool;
 
 #[derive(Debug)]
 pub struct TickerDetails {
@@ -12,29 +12,52 @@ pub struct TickerDetails {
     pub homepage_url: Option<String>,
     pub employees: Option<String>,
     pub ceo: Option<String>,
+    /// Headquarters country (ISO 3166 code)
+    pub country: Option<String>,
+    pub industry: Option<String>,
+    /// ISO 6166 security identifier
+    pub isin: Option<String>,
+    /// ISO 17442 legal entity identifier
+    pub lei: Option<String>,
 }
 
 /// Update ticker details in the database
-pub async fn update_ticker_details(pool: &SqlitePool, details: &TickerDetails) -> Result<()> {
-    sqlx::query!(
+pub async fn update_ticker_details(
+    pool: impl Into<CorePool>,
+    details: &TickerDetails,
+) -> Result<()> {
+    core_query!(pool.into(), |pool| sqlx::query(
         r#"
-        INSERT INTO ticker_details (ticker, description, homepage_url, employees, ceo)
-        VALUES (?, ?, ?, ?, ?)
+        INSERT INTO ticker_details (
+            ticker, description, homepage_url, employees, ceo, country, industry, isin, lei
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
         ON CONFLICT(ticker) DO UPDATE SET
             description = excluded.description,
             homepage_url = excluded.homepage_url,
             employees = excluded.employees,
             ceo = excluded.ceo,
+            -- Sources without a country or industry (Polygon) keep the ones from FMP
+            country = COALESCE(excluded.country, ticker_details.country),
+            industry = COALESCE(excluded.industry, ticker_details.industry),
+            -- Identifiers only come from some sources (FMP profiles, imports)
+            isin = COALESCE(excluded.isin, ticker_details.isin),
+            lei = COALESCE(excluded.lei, ticker_details.lei),
             updated_at = CURRENT_TIMESTAMP
         "#,
-        details.ticker,
-        details.description,
-        details.homepage_url,
-        details.employees,
-        details.ceo,
     )
-    .execute(pool)
-    .await?;
+    .bind(&details.ticker)
+    .bind(&details.description)
+    .bind(&details.homepage_url)
+    .bind(&details.employees)
+    .bind(&details.ceo)
+    .bind(&details.country)
+    .bind(&details.industry)
+    .bind(&details.isin)
+    .bind(&details.lei)
+    .execute(&pool)
+    .await?
+    .rows_affected());
 
     Ok(())
 }
@@ -56,6 +79,10 @@ mod tests {
             homepage_url: Some("https://apple.com".to_string()),
             employees: Some("164000".to_string()),
             ceo: Some("Tim Cook".to_string()),
+            country: None,
+            industry: None,
+            isin: None,
+            lei: None,
         };
 
         assert_eq!(details.ticker, "AAPL");
@@ -73,6 +100,10 @@ mod tests {
             homepage_url: None,
             employees: None,
             ceo: None,
+            country: None,
+            industry: None,
+            isin: None,
+            lei: None,
         };
 
         assert_eq!(details.ticker, "XYZ");
@@ -90,6 +121,10 @@ mod tests {
             homepage_url: None,
             employees: Some("164000".to_string()),
             ceo: Some("Tim Cook".to_string()),
+            country: None,
+            industry: None,
+            isin: None,
+            lei: None,
         };
 
         let debug_str = format!("{:?}", details);
@@ -105,6 +140,10 @@ mod tests {
             homepage_url: Some("https://hm.com/en_gb/".to_string()),
             employees: Some("100000".to_string()),
             ceo: Some("Helena Helmersson".to_string()),
+            country: None,
+            industry: None,
+            isin: None,
+            lei: None,
         };
 
         assert_eq!(details.ticker, "HM-B.ST");
@@ -120,6 +159,10 @@ mod tests {
             homepage_url: Some("https://microsoft.com".to_string()),
             employees: Some("200000".to_string()),
             ceo: Some("Satya Nadella".to_string()),
+            country: None,
+            industry: None,
+            isin: None,
+            lei: None,
         };
 
         // Test that we can create another struct with same values
@@ -129,6 +172,10 @@ mod tests {
             homepage_url: details1.homepage_url.clone(),
             employees: details1.employees.clone(),
             ceo: details1.ceo.clone(),
+            country: details1.country.clone(),
+            industry: details1.industry.clone(),
+            isin: details1.isin.clone(),
+            lei: details1.lei.clone(),
         };
 
         assert_eq!(details1.ticker, details2.ticker);
@@ -0,0 +1,476 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Trading calendars of the exchanges in the universe
+//!
+//! A snapshot fetched for a weekend or an exchange holiday gets the previous
+//! close of the companies listed there, labeled with a date on which nothing
+//! traded. `fetch-specific-date-market-caps` warns about such dates and, with
+//! `--align-to-trading-day`, fetches the previous day on which every exchange
+//! of the universe traded instead.
+//!
+//! Holidays are computed from each exchange's rules (fixed dates, weekday
+//! rules, Easter, weekend substitution) plus a list of one-off closures.
+//! Exchanges without a calendar here only close on weekends.
+
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+
+/// How far back `--align-to-trading-day` looks for a day all exchanges traded
+const MAX_ALIGN_DAYS: i64 = 31;
+
+/// Trading calendar of an exchange (or a group with the same holidays)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Calendar {
+    /// NYSE and Nasdaq
+    Nyse,
+    /// Euronext (Paris, Amsterdam, Brussels, Lisbon, Dublin); Madrid and
+    /// Milan close on the same core holidays
+    Euronext,
+    /// Xetra / Frankfurt
+    Xetra,
+    /// London Stock Exchange
+    Lse,
+    /// SIX Swiss Exchange
+    Six,
+    /// Japan Exchange Group (Tokyo)
+    Jpx,
+    /// Any other exchange: weekends only
+    Weekdays,
+}
+
+/// One-off closures outside the regular rules
+const SPECIAL_CLOSURES: [(Calendar, i32, u32, u32, &str); 7] = [
+    (Calendar::Nyse, 2012, 10, 29, "Hurricane Sandy"),
+    (Calendar::Nyse, 2012, 10, 30, "Hurricane Sandy"),
+    (Calendar::Nyse, 2018, 12, 5, "National Day of Mourning"),
+    (Calendar::Nyse, 2025, 1, 9, "National Day of Mourning"),
+    (Calendar::Lse, 2022, 6, 3, "Platinum Jubilee"),
+    (Calendar::Lse, 2022, 9, 19, "State Funeral"),
+    (Calendar::Lse, 2023, 5, 8, "Coronation"),
+];
+
+impl Calendar {
+    /// Calendar of a ticker from its exchange suffix; tickers without one
+    /// (or with a share class such as `BF.B`) are US listings
+    pub fn for_ticker(ticker: &str) -> Self {
+        let Some((_, suffix)) = ticker.rsplit_once('.') else {
+            return Calendar::Nyse;
+        };
+        match suffix {
+            "PA" | "AS" | "BR" | "LS" | "IR" | "MC" | "MI" => Calendar::Euronext,
+            "DE" | "F" => Calendar::Xetra,
+            "L" => Calendar::Lse,
+            "SW" => Calendar::Six,
+            "T" => Calendar::Jpx,
+            _ if suffix.len() == 1 => Calendar::Nyse,
+            _ => Calendar::Weekdays,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Calendar::Nyse => "NYSE",
+            Calendar::Euronext => "Euronext",
+            Calendar::Xetra => "Xetra",
+            Calendar::Lse => "LSE",
+            Calendar::Six => "SIX",
+            Calendar::Jpx => "JPX",
+            Calendar::Weekdays => "other exchanges",
+        }
+    }
+
+    /// Why the exchange is closed on `date`, `None` on a trading day
+    pub fn closure(self, date: NaiveDate) -> Option<&'static str> {
+        if matches!(date.weekday(), Weekday::Sat | Weekday::Sun) {
+            return Some("weekend");
+        }
+        let special = SPECIAL_CLOSURES
+            .iter()
+            .find(|(calendar, y, m, d, _)| {
+                *calendar == self && NaiveDate::from_ymd_opt(*y, *m, *d) == Some(date)
+            })
+            .map(|(.., name)| *name);
+        special.or_else(|| {
+            self.holidays(date.year())
+                .into_iter()
+                .find(|(day, _)| *day == date)
+                .map(|(_, name)| name)
+        })
+    }
+
+    pub fn is_trading_day(self, date: NaiveDate) -> bool {
+        self.closure(date).is_none()
+    }
+
+    /// Regular holidays of a year that fall on weekdays
+    fn holidays(self, year: i32) -> Vec<(NaiveDate, &'static str)> {
+        let ymd = |m, d| ymd(year, m, d);
+        let easter = easter_sunday(year);
+        let good_friday = (easter - Duration::days(2), "Good Friday");
+        let easter_monday = (easter + Duration::days(1), "Easter Monday");
+        match self {
+            Calendar::Nyse => {
+                let mut days = vec![
+                    (
+                        nth_weekday(year, 1, Weekday::Mon, 3),
+                        "Martin Luther King Jr. Day",
+                    ),
+                    (
+                        nth_weekday(year, 2, Weekday::Mon, 3),
+                        "Washington's Birthday",
+                    ),
+                    good_friday,
+                    (last_weekday(year, 5, Weekday::Mon), "Memorial Day"),
+                    (us_observed(ymd(7, 4)), "Independence Day"),
+                    (nth_weekday(year, 9, Weekday::Mon, 1), "Labor Day"),
+                    (nth_weekday(year, 11, Weekday::Thu, 4), "Thanksgiving Day"),
+                    (us_observed(ymd(12, 25)), "Christmas Day"),
+                ];
+                // A Saturday New Year's Day is not made up on the Friday before
+                let new_year = ymd(1, 1);
+                if new_year.weekday() == Weekday::Sun {
+                    days.push((new_year + Duration::days(1), "New Year's Day"));
+                } else {
+                    days.push((new_year, "New Year's Day"));
+                }
+                if year >= 2022 {
+                    days.push((us_observed(ymd(6, 19)), "Juneteenth"));
+                }
+                days
+            }
+            Calendar::Euronext => vec![
+                (ymd(1, 1), "New Year's Day"),
+                good_friday,
+                easter_monday,
+                (ymd(5, 1), "Labour Day"),
+                (ymd(12, 25), "Christmas Day"),
+                (ymd(12, 26), "Boxing Day"),
+            ],
+            Calendar::Xetra => vec![
+                (ymd(1, 1), "New Year's Day"),
+                good_friday,
+                easter_monday,
+                (ymd(5, 1), "Labour Day"),
+                (ymd(12, 24), "Christmas Eve"),
+                (ymd(12, 25), "Christmas Day"),
+                (ymd(12, 26), "Boxing Day"),
+                (ymd(12, 31), "New Year's Eve"),
+            ],
+            Calendar::Lse => {
+                let mut new_year = ymd(1, 1);
+                while matches!(new_year.weekday(), Weekday::Sat | Weekday::Sun) {
+                    new_year += Duration::days(1);
+                }
+                // Christmas and Boxing Day move to the first weekdays from the 25th
+                let mut christmas = weekdays_from(ymd(12, 25)).take(2);
+                vec![
+                    (new_year, "New Year's Day"),
+                    good_friday,
+                    easter_monday,
+                    (
+                        nth_weekday(year, 5, Weekday::Mon, 1),
+                        "Early May Bank Holiday",
+                    ),
+                    (last_weekday(year, 5, Weekday::Mon), "Spring Bank Holiday"),
+                    (last_weekday(year, 8, Weekday::Mon), "Summer Bank Holiday"),
+                    (christmas.next().unwrap_or(ymd(12, 25)), "Christmas Day"),
+                    (christmas.next().unwrap_or(ymd(12, 26)), "Boxing Day"),
+                ]
+            }
+            Calendar::Six => vec![
+                (ymd(1, 1), "New Year's Day"),
+                (ymd(1, 2), "Berchtold's Day"),
+                good_friday,
+                easter_monday,
+                (easter + Duration::days(39), "Ascension Day"),
+                (easter + Duration::days(50), "Whit Monday"),
+                (ymd(5, 1), "Labour Day"),
+                (ymd(8, 1), "Swiss National Day"),
+                (ymd(12, 24), "Christmas Eve"),
+                (ymd(12, 25), "Christmas Day"),
+                (ymd(12, 26), "St. Stephen's Day"),
+                (ymd(12, 31), "New Year's Eve"),
+            ],
+            Calendar::Jpx => japan_holidays(year),
+            Calendar::Weekdays => Vec::new(),
+        }
+    }
+}
+
+fn ymd(year: i32, month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, day).expect("valid calendar date")
+}
+
+/// Easter Sunday (anonymous Gregorian algorithm)
+fn easter_sunday(year: i32) -> NaiveDate {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    ymd(year, month as u32, day as u32)
+}
+
+/// The `n`th given weekday of a month
+fn nth_weekday(year: i32, month: u32, weekday: Weekday, n: u8) -> NaiveDate {
+    NaiveDate::from_weekday_of_month_opt(year, month, weekday, n).expect("valid weekday rule")
+}
+
+fn last_weekday(year: i32, month: u32, weekday: Weekday) -> NaiveDate {
+    (1..=5)
+        .rev()
+        .find_map(|n| NaiveDate::from_weekday_of_month_opt(year, month, weekday, n))
+        .expect("every month has four of each weekday")
+}
+
+/// US rule: a Saturday holiday is observed on Friday, a Sunday one on Monday
+fn us_observed(date: NaiveDate) -> NaiveDate {
+    match date.weekday() {
+        Weekday::Sat => date - Duration::days(1),
+        Weekday::Sun => date + Duration::days(1),
+        _ => date,
+    }
+}
+
+fn weekdays_from(date: NaiveDate) -> impl Iterator<Item = NaiveDate> {
+    date.iter_days()
+        .filter(|d| !matches!(d.weekday(), Weekday::Sat | Weekday::Sun))
+}
+
+/// Japanese national holidays plus the exchange's year-end closure. A
+/// holiday on a Sunday moves to the next non-holiday, and a day between two
+/// holidays is a holiday too.
+fn japan_holidays(year: i32) -> Vec<(NaiveDate, &'static str)> {
+    let ymd = |m, d| ymd(year, m, d);
+    // Equinox days, valid for 1980-2099
+    let offset = 0.242194 * f64::from(year - 1980) - f64::from((year - 1980) / 4);
+    let vernal = ymd(3, (20.8431 + offset).floor() as u32);
+    let autumnal = ymd(9, (23.2488 + offset).floor() as u32);
+    let mut national = vec![
+        (ymd(1, 1), "New Year's Day"),
+        (nth_weekday(year, 1, Weekday::Mon, 2), "Coming of Age Day"),
+        (ymd(2, 11), "National Foundation Day"),
+        (vernal, "Vernal Equinox Day"),
+        (ymd(4, 29), "Showa Day"),
+        (ymd(5, 3), "Constitution Memorial Day"),
+        (ymd(5, 4), "Greenery Day"),
+        (ymd(5, 5), "Children's Day"),
+        (nth_weekday(year, 7, Weekday::Mon, 3), "Marine Day"),
+        (
+            nth_weekday(year, 9, Weekday::Mon, 3),
+            "Respect for the Aged Day",
+        ),
+        (autumnal, "Autumnal Equinox Day"),
+        (nth_weekday(year, 10, Weekday::Mon, 2), "Sports Day"),
+        (ymd(11, 3), "Culture Day"),
+        (ymd(11, 23), "Labor Thanksgiving Day"),
+    ];
+    if year >= 2020 {
+        national.push((ymd(2, 23), "Emperor's Birthday"));
+    }
+    if year >= 2016 {
+        national.push((ymd(8, 11), "Mountain Day"));
+    }
+    national.sort();
+
+    let is_national = |date: NaiveDate| national.iter().any(|(d, _)| *d == date);
+    let mut days = national.clone();
+    for (date, _) in &national {
+        if date.weekday() == Weekday::Sun {
+            let mut substitute = *date + Duration::days(1);
+            while is_national(substitute) {
+                substitute += Duration::days(1);
+            }
+            days.push((substitute, "Substitute Holiday"));
+        }
+    }
+    for pair in national.windows(2) {
+        let between = pair[0].0 + Duration::days(1);
+        if pair[1].0 - pair[0].0 == Duration::days(2) && !is_national(between) {
+            days.push((between, "Citizens' Holiday"));
+        }
+    }
+    days.extend([
+        (ymd(1, 2), "Exchange Holiday"),
+        (ymd(1, 3), "Exchange Holiday"),
+        (ymd(12, 31), "Exchange Holiday"),
+    ]);
+    days
+}
+
+/// Calendars of the exchanges the tickers are listed on, sorted
+pub fn calendars_of(tickers: &[String]) -> Vec<Calendar> {
+    let mut calendars: Vec<Calendar> = tickers.iter().map(|t| Calendar::for_ticker(t)).collect();
+    calendars.sort();
+    calendars.dedup();
+    calendars
+}
+
+/// Exchanges among `calendars` closed on `date`, with the reason
+pub fn closed_on(date: NaiveDate, calendars: &[Calendar]) -> Vec<(Calendar, &'static str)> {
+    calendars
+        .iter()
+        .filter_map(|c| c.closure(date).map(|reason| (*c, reason)))
+        .collect()
+}
+
+/// Latest day before `date` on which every exchange of `calendars` traded,
+/// or the latest weekday when there is none within a month
+pub fn previous_trading_day(date: NaiveDate, calendars: &[Calendar]) -> NaiveDate {
+    (1..=MAX_ALIGN_DAYS)
+        .map(|days| date - Duration::days(days))
+        .find(|d| closed_on(*d, calendars).is_empty())
+        .unwrap_or_else(|| {
+            (1..=7)
+                .map(|days| date - Duration::days(days))
+                .find(|d| Calendar::Weekdays.is_trading_day(*d))
+                .expect("a week has weekdays")
+        })
+}
+
+/// Date a snapshot was asked for and the date it was fetched for
+#[derive(Debug, Clone, PartialEq)]
+pub struct TradingDay {
+    pub requested: NaiveDate,
+    pub effective: NaiveDate,
+    /// Exchanges closed on the requested date
+    pub closed: Vec<(Calendar, &'static str)>,
+}
+
+impl TradingDay {
+    /// Check `requested` against the calendars of the universe; with
+    /// `align`, move a date on which any of them is closed to the previous
+    /// day all of them traded
+    pub fn resolve(requested: NaiveDate, calendars: &[Calendar], align: bool) -> Self {
+        let closed = closed_on(requested, calendars);
+        let effective = if align && !closed.is_empty() {
+            previous_trading_day(requested, calendars)
+        } else {
+            requested
+        };
+        Self {
+            requested,
+            effective,
+            closed,
+        }
+    }
+
+    pub fn is_shifted(&self) -> bool {
+        self.requested != self.effective
+    }
+
+    /// `Xetra (Christmas Eve), SIX (Christmas Eve)`
+    pub fn closed_summary(&self) -> String {
+        self.closed
+            .iter()
+            .map(|(calendar, reason)| format!("{} ({})", calendar.label(), reason))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn test_easter_and_calendar_mapping() {
+        assert_eq!(easter_sunday(2024), date("2024-03-31"));
+        assert_eq!(easter_sunday(2025), date("2025-04-20"));
+        assert_eq!(easter_sunday(2026), date("2026-04-05"));
+        assert_eq!(Calendar::for_ticker("NKE"), Calendar::Nyse);
+        assert_eq!(Calendar::for_ticker("BF.B"), Calendar::Nyse);
+        assert_eq!(Calendar::for_ticker("MC.PA"), Calendar::Euronext);
+        assert_eq!(Calendar::for_ticker("ADS.DE"), Calendar::Xetra);
+        assert_eq!(Calendar::for_ticker("9983.T"), Calendar::Jpx);
+        assert_eq!(Calendar::for_ticker("HM-B.ST"), Calendar::Weekdays);
+    }
+
+    #[test]
+    fn test_exchange_holidays() {
+        let closed = |calendar: Calendar, s: &str| calendar.closure(date(s));
+        assert_eq!(
+            closed(Calendar::Nyse, "2025-07-04"),
+            Some("Independence Day")
+        );
+        assert_eq!(
+            closed(Calendar::Nyse, "2026-07-03"),
+            Some("Independence Day")
+        );
+        assert_eq!(
+            closed(Calendar::Nyse, "2025-11-27"),
+            Some("Thanksgiving Day")
+        );
+        assert_eq!(
+            closed(Calendar::Nyse, "2025-01-09"),
+            Some("National Day of Mourning")
+        );
+        // New Year's Day 2022 was a Saturday and not made up
+        assert_eq!(closed(Calendar::Nyse, "2021-12-31"), None);
+        assert_eq!(closed(Calendar::Nyse, "2025-04-18"), Some("Good Friday"));
+        assert_eq!(
+            closed(Calendar::Euronext, "2025-04-21"),
+            Some("Easter Monday")
+        );
+        assert_eq!(closed(Calendar::Nyse, "2025-04-21"), None);
+        assert_eq!(closed(Calendar::Xetra, "2025-12-24"), Some("Christmas Eve"));
+        assert_eq!(closed(Calendar::Euronext, "2025-12-24"), None);
+        // Christmas 2021 fell on a Saturday: Monday and Tuesday instead
+        assert_eq!(closed(Calendar::Lse, "2021-12-27"), Some("Christmas Day"));
+        assert_eq!(closed(Calendar::Lse, "2021-12-28"), Some("Boxing Day"));
+        assert_eq!(closed(Calendar::Six, "2025-05-29"), Some("Ascension Day"));
+        assert_eq!(
+            closed(Calendar::Jpx, "2025-03-20"),
+            Some("Vernal Equinox Day")
+        );
+        assert_eq!(
+            closed(Calendar::Jpx, "2025-05-06"),
+            Some("Substitute Holiday")
+        );
+        assert_eq!(
+            closed(Calendar::Jpx, "2026-09-22"),
+            Some("Citizens' Holiday")
+        );
+        assert_eq!(closed(Calendar::Weekdays, "2025-12-25"), None);
+        assert_eq!(closed(Calendar::Weekdays, "2025-12-27"), Some("weekend"));
+    }
+
+    #[test]
+    fn test_resolve_trading_day() {
+        let calendars = calendars_of(&["NKE".to_string(), "ADS.DE".to_string()]);
+        assert_eq!(calendars, vec![Calendar::Nyse, Calendar::Xetra]);
+
+        // Saturday: the Friday before
+        let day = TradingDay::resolve(date("2025-06-07"), &calendars, true);
+        assert_eq!(day.effective, date("2025-06-06"));
+        assert!(day.is_shifted());
+        assert_eq!(day.closed_summary(), "NYSE (weekend), Xetra (weekend)");
+
+        // Christmas Eve closes Xetra, the 25th and 26th both; the 23rd traded
+        let day = TradingDay::resolve(date("2025-12-26"), &calendars, true);
+        assert_eq!(day.effective, date("2025-12-23"));
+        assert_eq!(day.closed_summary(), "Xetra (Boxing Day)");
+
+        let day = TradingDay::resolve(date("2025-12-26"), &calendars, false);
+        assert_eq!(day.effective, date("2025-12-26"));
+        assert!(!day.is_shifted());
+
+        let day = TradingDay::resolve(date("2025-06-06"), &calendars, true);
+        assert!(day.closed.is_empty());
+        assert_eq!(day.effective, date("2025-06-06"));
+    }
+}
@@ -0,0 +1,217 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Snapshots of the config.toml ticker universe per fetched date, so comparisons
+//! can be restricted to the companies tracked on every compared date
+
+use anyhow::Result;
+use chrono::NaiveDate;
+use sqlx::sqlite::SqlitePool;
+use std::collections::BTreeSet;
+
+use crate::run_context;
+use crate::ticker_aliases::TickerAliases;
+
+/// Store the tickers that made up the universe on `date`, replacing any earlier snapshot
+pub async fn record_universe(pool: &SqlitePool, date: &str, tickers: &[String]) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM universe_snapshots WHERE date = ?")
+        .bind(date)
+        .execute(&mut *tx)
+        .await?;
+    for ticker in tickers {
+        sqlx::query("INSERT OR IGNORE INTO universe_snapshots (date, ticker) VALUES (?, ?)")
+            .bind(date)
+            .bind(ticker)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// The universe snapshot for `date`, if one was recorded
+pub async fn get_universe(pool: &SqlitePool, date: &str) -> Result<Option<BTreeSet<String>>> {
+    let tickers: Vec<String> =
+        sqlx::query_scalar("SELECT ticker FROM universe_snapshots WHERE date = ?")
+            .bind(date)
+            .fetch_all(pool)
+            .await?;
+    if tickers.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(tickers.into_iter().collect()))
+    }
+}
+
+/// The universe for `date`, falling back to the tickers found in that date's
+/// export for snapshots fetched before universes were recorded
+pub async fn universe_for_date(
+    pool: &SqlitePool,
+    date: &str,
+    exported: &BTreeSet<String>,
+) -> Result<BTreeSet<String>> {
+    match get_universe(pool, date).await? {
+        Some(universe) => Ok(universe),
+        None => {
+            run_context::record_warning(format!(
+                "No universe snapshot for {}; using the {} tickers in its export",
+                date,
+                exported.len()
+            ));
+            println!(
+                "⚠️  No universe snapshot for {}; using the {} tickers in its export",
+                date,
+                exported.len()
+            );
+            Ok(exported.clone())
+        }
+    }
+}
+
+/// How the universe changed across a series of dates
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct UniverseDiff {
+    /// Tickers present on every date
+    pub common: BTreeSet<String>,
+    /// Tickers missing on the first date but added later
+    pub added: Vec<String>,
+    /// Tickers present on the first date but dropped later
+    pub removed: Vec<String>,
+}
+
+impl UniverseDiff {
+    /// Compare the universes of consecutive dates (first date first)
+    pub fn across(universes: &[BTreeSet<String>]) -> Self {
+        let Some(first) = universes.first() else {
+            return Self::default();
+        };
+        let common: BTreeSet<String> = universes.iter().skip(1).fold(first.clone(), |acc, u| {
+            acc.intersection(u).cloned().collect()
+        });
+        let all: BTreeSet<String> = universes.iter().flatten().cloned().collect();
+        let added = all.difference(first).cloned().collect();
+        let removed = first.difference(&common).cloned().collect();
+        Self {
+            common,
+            added,
+            removed,
+        }
+    }
+
+    pub fn contains(&self, ticker: &str) -> bool {
+        self.common.contains(ticker)
+    }
+
+    /// Markdown section explaining which names were left out of the comparison
+    pub fn markdown_note(&self) -> String {
+        let mut note = String::from("## Consistent Universe\n\n");
+        note.push_str(&format!(
+            "Restricted to the {} companies tracked on every compared date.\n\n",
+            self.common.len()
+        ));
+        if self.added.is_empty() && self.removed.is_empty() {
+            note.push_str("The universe did not change between these dates.\n\n");
+            return note;
+        }
+        if !self.added.is_empty() {
+            note.push_str(&format!(
+                "- **Added to the universe (excluded):** {}\n",
+                self.added.join(", ")
+            ));
+        }
+        if !self.removed.is_empty() {
+            note.push_str(&format!(
+                "- **Removed from the universe (excluded):** {}\n",
+                self.removed.join(", ")
+            ));
+        }
+        note.push('\n');
+        note
+    }
+}
+
+/// Universe diff across `dates`, given the tickers found in each date's export.
+/// Recorded universes are re-keyed to current symbols like the exports.
+pub async fn consistent_universe(
+    pool: &SqlitePool,
+    dates: &[(String, BTreeSet<String>)],
+    aliases: &TickerAliases,
+) -> Result<UniverseDiff> {
+    let mut universes = Vec::with_capacity(dates.len());
+    for (date, exported) in dates {
+        let universe = universe_for_date(pool, date, exported).await?;
+        let day = NaiveDate::parse_from_str(date, "%Y-%m-%d")?;
+        universes.push(
+            universe
+                .iter()
+                .map(|ticker| aliases.current_symbol(ticker, day).to_string())
+                .collect(),
+        );
+    }
+    let diff = UniverseDiff::across(&universes);
+    println!(
+        "📊 Consistent universe: {} companies ({} added, {} removed excluded)",
+        diff.common.len(),
+        diff.added.len(),
+        diff.removed.len()
+    );
+    Ok(diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    fn set(tickers: &[&str]) -> BTreeSet<String> {
+        tickers.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn test_universe_diff_two_dates() {
+        let diff =
+            UniverseDiff::across(&[set(&["NKE", "CPRI", "TPR"]), set(&["NKE", "TPR", "BIRK"])]);
+        assert_eq!(diff.common, set(&["NKE", "TPR"]));
+        assert_eq!(diff.added, ["BIRK"]);
+        assert_eq!(diff.removed, ["CPRI"]);
+        assert!(diff.markdown_note().contains("BIRK"));
+    }
+
+    #[test]
+    fn test_universe_diff_multiple_dates() {
+        let diff = UniverseDiff::across(&[
+            set(&["NKE", "CPRI"]),
+            set(&["NKE", "CPRI", "BIRK"]),
+            set(&["NKE", "BIRK"]),
+        ]);
+        assert_eq!(diff.common, set(&["NKE"]));
+        assert_eq!(diff.added, ["BIRK"]);
+        assert_eq!(diff.removed, ["CPRI"]);
+        assert!(UniverseDiff::across(&[]).common.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_record_and_fallback() {
+        let pool = db::create_db_pool("sqlite::memory:").await.unwrap();
+        let tickers = vec!["NKE".to_string(), "TPR".to_string()];
+        record_universe(&pool, "2025-01-01", &tickers)
+            .await
+            .unwrap();
+        record_universe(&pool, "2025-01-01", &tickers[..1])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            get_universe(&pool, "2025-01-01").await.unwrap(),
+            Some(set(&["NKE"]))
+        );
+        assert_eq!(
+            universe_for_date(&pool, "2025-02-01", &set(&["ADS"]))
+                .await
+                .unwrap(),
+            set(&["ADS"])
+        );
+    }
+}
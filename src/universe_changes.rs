@@ -0,0 +1,413 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! New entrants and disappeared companies between two snapshots
+//!
+//! Tickers are compared using the market cap exports of both dates and the
+//! recorded universe snapshots, so a company dropped from config.toml is told
+//! apart from one that stopped reporting (delisting, acquisition). The latter are
+//! enriched with FMP's delisted-companies list when an API key is available.
+
+use anyhow::Result;
+use csv::Writer;
+use sqlx::sqlite::SqlitePool;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::fs::File;
+use std::io::Write as IoWrite;
+
+use crate::advanced_comparisons::{MarketCapRecord, find_csv_for_date, read_market_cap_csv};
+use crate::api::{DelistedCompany, FMPClient};
+use crate::clock;
+use crate::config::{self, OutputConfig};
+use crate::rankings;
+use crate::universe;
+use crate::watchlists;
+
+/// Pages of the delisted-companies list to scan at most (100 entries each)
+const MAX_DELISTED_PAGES: u32 = 10;
+
+/// Why a ticker appeared or disappeared between the two snapshots
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChangeReason {
+    /// Ticker was added to the tracked universe
+    AddedToUniverse,
+    /// Ticker was already tracked but had no data before (e.g. an IPO)
+    NewListing,
+    /// Ticker was removed from the tracked universe
+    RemovedFromUniverse,
+    /// Ticker is still tracked but no longer returns data
+    NoLongerReported,
+    /// Ticker appears in FMP's delisted-companies list
+    Delisted(DelistedCompany),
+}
+
+impl fmt::Display for ChangeReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChangeReason::AddedToUniverse => write!(f, "Added to universe"),
+            ChangeReason::NewListing => write!(f, "New listing / first data"),
+            ChangeReason::RemovedFromUniverse => write!(f, "Removed from universe"),
+            ChangeReason::NoLongerReported => {
+                write!(f, "No longer reported (possible delisting or acquisition)")
+            }
+            ChangeReason::Delisted(company) => {
+                write!(
+                    f,
+                    "Delisted {}",
+                    company.delisted_date.as_deref().unwrap_or("(date unknown)")
+                )?;
+                if let Some(exchange) = &company.exchange {
+                    write!(f, " from {}", exchange)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A company that entered or left between the two snapshots
+#[derive(Debug, Clone, PartialEq)]
+pub struct UniverseChange {
+    pub ticker: String,
+    pub name: String,
+    pub rank: Option<usize>,
+    pub market_cap_eur: Option<f64>,
+    pub reason: ChangeReason,
+}
+
+/// Split the tickers of two exports into new entrants and disappeared companies
+pub fn find_changes(
+    from_records: &[MarketCapRecord],
+    to_records: &[MarketCapRecord],
+    from_universe: &BTreeSet<String>,
+    to_universe: &BTreeSet<String>,
+) -> (Vec<UniverseChange>, Vec<UniverseChange>) {
+    let from_tickers: BTreeSet<&str> = from_records.iter().map(|r| r.ticker.as_str()).collect();
+    let to_tickers: BTreeSet<&str> = to_records.iter().map(|r| r.ticker.as_str()).collect();
+
+    let change = |record: &MarketCapRecord, reason| UniverseChange {
+        ticker: record.ticker.clone(),
+        name: record.name.clone(),
+        rank: record.rank,
+        market_cap_eur: record.market_cap_eur,
+        reason,
+    };
+
+    let mut entrants: Vec<UniverseChange> = to_records
+        .iter()
+        .filter(|r| !from_tickers.contains(r.ticker.as_str()))
+        .map(|r| {
+            if from_universe.contains(&r.ticker) {
+                change(r, ChangeReason::NewListing)
+            } else {
+                change(r, ChangeReason::AddedToUniverse)
+            }
+        })
+        .collect();
+
+    let mut disappeared: Vec<UniverseChange> = from_records
+        .iter()
+        .filter(|r| !to_tickers.contains(r.ticker.as_str()))
+        .map(|r| {
+            if to_universe.contains(&r.ticker) {
+                change(r, ChangeReason::NoLongerReported)
+            } else {
+                change(r, ChangeReason::RemovedFromUniverse)
+            }
+        })
+        .collect();
+
+    // Largest companies first, ties by name and ticker
+    let by_market_cap = |a: &UniverseChange, b: &UniverseChange| {
+        rankings::rank_order(
+            (a.market_cap_eur, &a.name, &a.ticker),
+            (b.market_cap_eur, &b.name, &b.ticker),
+        )
+    };
+    entrants.sort_by(by_market_cap);
+    disappeared.sort_by(by_market_cap);
+
+    (entrants, disappeared)
+}
+
+/// Mark disappeared companies found in the delisted-companies list
+pub fn apply_delistings(disappeared: &mut [UniverseChange], delisted: &[DelistedCompany]) {
+    let by_symbol: HashMap<&str, &DelistedCompany> =
+        delisted.iter().map(|d| (d.symbol.as_str(), d)).collect();
+    for change in disappeared.iter_mut() {
+        if let Some(company) = by_symbol.get(change.ticker.as_str()) {
+            change.reason = ChangeReason::Delisted((*company).clone());
+        }
+    }
+}
+
+/// Scan FMP's delisted-companies list (most recent first) back to `since`
+async fn fetch_delistings_since(
+    fmp_client: &FMPClient,
+    since: &str,
+) -> Result<Vec<DelistedCompany>> {
+    let mut delisted = Vec::new();
+    for page in 0..MAX_DELISTED_PAGES {
+        let batch = fmp_client.fetch_delisted_companies(page).await?;
+        if batch.is_empty() {
+            break;
+        }
+        let reached_since = batch
+            .iter()
+            .filter_map(|d| d.delisted_date.as_deref())
+            .any(|date| date < since);
+        delisted.extend(batch);
+        if reached_since {
+            break;
+        }
+    }
+    Ok(delisted)
+}
+
+fn format_market_cap(value: Option<f64>) -> String {
+    value
+        .map(|v| format!("€{:.2}B", v / 1_000_000_000.0))
+        .unwrap_or_else(|| "N/A".to_string())
+}
+
+fn write_section(file: &mut File, title: &str, changes: &[UniverseChange]) -> Result<()> {
+    writeln!(file, "## {} ({})", title, changes.len())?;
+    writeln!(file)?;
+    if changes.is_empty() {
+        writeln!(file, "None.")?;
+    } else {
+        writeln!(file, "| Ticker | Name | Rank | Market Cap (EUR) | Reason |")?;
+        writeln!(file, "|--------|------|------|------------------|--------|")?;
+        for change in changes {
+            writeln!(
+                file,
+                "| {} | {} | {} | {} | {} |",
+                change.ticker,
+                change.name,
+                change
+                    .rank
+                    .map(|r| r.to_string())
+                    .unwrap_or_else(|| "N/A".to_string()),
+                format_market_cap(change.market_cap_eur),
+                change.reason
+            )?;
+        }
+    }
+    writeln!(file)?;
+    Ok(())
+}
+
+fn export_report(
+    entrants: &[UniverseChange],
+    disappeared: &[UniverseChange],
+    from_date: &str,
+    to_date: &str,
+    kind: &str,
+) -> Result<()> {
+    let output = config::load_output_config();
+    output.ensure_directory()?;
+    let timestamp = OutputConfig::timestamp();
+    let range = format!("{}_to_{}", from_date, to_date);
+
+    let csv_path = output.file_path_at(kind, &range, &timestamp, "csv");
+    let mut writer = Writer::from_writer(File::create(&csv_path)?);
+    writer.write_record([
+        "Change",
+        "Ticker",
+        "Name",
+        "Rank",
+        "Market Cap (EUR)",
+        "Reason",
+    ])?;
+    for (label, changes) in [("Entrant", entrants), ("Disappeared", disappeared)] {
+        for change in changes {
+            writer.write_record([
+                label.to_string(),
+                change.ticker.clone(),
+                change.name.clone(),
+                change.rank.map(|r| r.to_string()).unwrap_or_default(),
+                change
+                    .market_cap_eur
+                    .map(|v| format!("{:.0}", v))
+                    .unwrap_or_default(),
+                change.reason.to_string(),
+            ])?;
+        }
+    }
+    writer.flush()?;
+    println!("✅ Universe changes exported to {}", csv_path.display());
+
+    let md_path = output.file_path_at(kind, &format!("{}_summary", range), &timestamp, "md");
+    let mut file = File::create(&md_path)?;
+    writeln!(file, "# Universe Changes: {} to {}", from_date, to_date)?;
+    writeln!(file)?;
+    writeln!(file, "- New entrants: {}", entrants.len())?;
+    writeln!(file, "- Disappeared companies: {}", disappeared.len())?;
+    writeln!(file)?;
+    write_section(&mut file, "New Entrants", entrants)?;
+    write_section(&mut file, "Disappeared Companies", disappeared)?;
+    writeln!(file, "---")?;
+    writeln!(
+        file,
+        "*Generated on {}*",
+        clock::now().format("%Y-%m-%d %H:%M:%S")
+    )?;
+    println!(
+        "✅ Universe changes report exported to {}",
+        md_path.display()
+    );
+
+    Ok(())
+}
+
+/// Report companies that entered or left between two snapshots
+pub async fn detect_universe_changes(
+    pool: &SqlitePool,
+    from_date: &str,
+    to_date: &str,
+    watchlist: Option<&str>,
+) -> Result<()> {
+    println!(
+        "Detecting universe changes from {} to {}",
+        from_date, to_date
+    );
+
+    let from_records = read_market_cap_csv(&find_csv_for_date(from_date, watchlist)?)?;
+    let to_records = read_market_cap_csv(&find_csv_for_date(to_date, watchlist)?)?;
+
+    let exported = |records: &[MarketCapRecord]| -> BTreeSet<String> {
+        records.iter().map(|r| r.ticker.clone()).collect()
+    };
+    let (from_universe, to_universe) = match watchlist {
+        // Watchlists are not snapshotted, so only the exports are compared
+        Some(_) => (exported(&from_records), exported(&to_records)),
+        None => (
+            universe::universe_for_date(pool, from_date, &exported(&from_records)).await?,
+            universe::universe_for_date(pool, to_date, &exported(&to_records)).await?,
+        ),
+    };
+
+    let (entrants, mut disappeared) =
+        find_changes(&from_records, &to_records, &from_universe, &to_universe);
+
+    let to_check = disappeared
+        .iter()
+        .filter(|c| c.reason == ChangeReason::NoLongerReported)
+        .count();
+    if to_check > 0 {
+        match FMPClient::from_env() {
+            Ok(fmp_client) => {
+                println!("Checking {} companies against FMP delistings...", to_check);
+                match fetch_delistings_since(&fmp_client, from_date).await {
+                    Ok(delisted) => apply_delistings(&mut disappeared, &delisted),
+                    Err(e) => eprintln!("⚠️  Could not fetch delisted companies: {:#}", e),
+                }
+            }
+            Err(e) => println!("⚠️  {}; skipping delisting lookup", e),
+        }
+    }
+
+    println!("\n📈 New entrants: {}", entrants.len());
+    for change in &entrants {
+        println!(
+            "  + {} ({}) - {}",
+            change.ticker, change.name, change.reason
+        );
+    }
+    println!("📉 Disappeared: {}", disappeared.len());
+    for change in &disappeared {
+        println!(
+            "  - {} ({}) - {}",
+            change.ticker, change.name, change.reason
+        );
+    }
+
+    let kind = watchlists::scoped_kind(watchlist, "universe_changes");
+    export_report(&entrants, &disappeared, from_date, to_date, &kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(ticker: &str, market_cap_eur: f64) -> MarketCapRecord {
+        MarketCapRecord {
+            rank: None,
+            ticker: ticker.to_string(),
+            name: format!("{} Inc", ticker),
+            market_cap_original: Some(market_cap_eur),
+            original_currency: Some("EUR".to_string()),
+            market_cap_eur: Some(market_cap_eur),
+            market_cap_usd: None,
+            exchange: None,
+        }
+    }
+
+    fn set(tickers: &[&str]) -> BTreeSet<String> {
+        tickers.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn test_find_changes_classifies_reasons() {
+        let from = vec![
+            record("NKE", 100.0),
+            record("CPRI", 5.0),
+            record("OLD", 1.0),
+        ];
+        let to = vec![
+            record("NKE", 110.0),
+            record("BIRK", 8.0),
+            record("SHEIN", 50.0),
+        ];
+        let from_universe = set(&["NKE", "CPRI", "OLD", "SHEIN"]);
+        let to_universe = set(&["NKE", "CPRI", "BIRK", "SHEIN"]);
+
+        let (entrants, disappeared) = find_changes(&from, &to, &from_universe, &to_universe);
+
+        assert_eq!(entrants.len(), 2);
+        assert_eq!(entrants[0].ticker, "SHEIN");
+        assert_eq!(entrants[0].reason, ChangeReason::NewListing);
+        assert_eq!(entrants[1].ticker, "BIRK");
+        assert_eq!(entrants[1].reason, ChangeReason::AddedToUniverse);
+
+        assert_eq!(disappeared.len(), 2);
+        assert_eq!(disappeared[0].ticker, "CPRI");
+        assert_eq!(disappeared[0].reason, ChangeReason::NoLongerReported);
+        assert_eq!(disappeared[1].ticker, "OLD");
+        assert_eq!(disappeared[1].reason, ChangeReason::RemovedFromUniverse);
+    }
+
+    #[test]
+    fn test_apply_delistings() {
+        let (_, mut disappeared) = find_changes(
+            &[record("CPRI", 5.0)],
+            &[],
+            &set(&["CPRI"]),
+            &set(&["CPRI"]),
+        );
+        let delisted = DelistedCompany {
+            symbol: "CPRI".to_string(),
+            company_name: Some("Capri Holdings".to_string()),
+            exchange: Some("NYSE".to_string()),
+            ipo_date: None,
+            delisted_date: Some("2025-03-01".to_string()),
+        };
+        apply_delistings(&mut disappeared, std::slice::from_ref(&delisted));
+
+        assert_eq!(disappeared[0].reason, ChangeReason::Delisted(delisted));
+        assert_eq!(
+            disappeared[0].reason.to_string(),
+            "Delisted 2025-03-01 from NYSE"
+        );
+    }
+
+    #[test]
+    fn test_parse_delisted_company() {
+        let json = r#"[{"symbol":"CPRI","companyName":"Capri Holdings","exchange":"NYSE","ipoDate":"2011-12-15","delistedDate":"2025-03-01"}]"#;
+        let parsed: Vec<DelistedCompany> = serde_json::from_str(json).unwrap();
+        assert_eq!(parsed[0].symbol, "CPRI");
+        assert_eq!(parsed[0].delisted_date.as_deref(), Some("2025-03-01"));
+    }
+}
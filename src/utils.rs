@@ -3,3 +3,192 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 
 // This module is reserved for utility functions that don't fit elsewhere
+
+use anyhow::{Result, anyhow};
+use futures::stream::{self, StreamExt};
+use std::future::Future;
+use std::time::Duration;
+
+/// Default number of per-ticker requests kept in flight
+pub const DEFAULT_CONCURRENCY: usize = 8;
+
+/// Run `fetch` for every item with at most `concurrency` futures in flight and
+/// return the results in the order of `items`, however they complete
+pub async fn fetch_ordered<'a, T, R, F, Fut>(items: &'a [T], concurrency: usize, fetch: F) -> Vec<R>
+where
+    F: Fn(&'a T) -> Fut,
+    Fut: Future<Output = R>,
+{
+    let mut results: Vec<(usize, R)> = stream::iter(items.iter().enumerate())
+        .map(|(index, item)| {
+            let future = fetch(item);
+            async move { (index, future.await) }
+        })
+        .buffer_unordered(concurrency.max(1))
+        .collect()
+        .await;
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Parse a duration such as `90s`, `30m`, `6h` or `2d`
+pub fn parse_duration(value: &str) -> Result<Duration> {
+    let invalid = || anyhow!("Invalid duration '{}': use e.g. 30m, 6h or 2d", value);
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return Err(invalid()),
+    };
+    let seconds = number
+        .checked_mul(seconds)
+        .ok_or_else(|| anyhow!("Duration '{}' is too long", value))?;
+    Ok(Duration::from_secs(seconds))
+}
+
+/// Median of the values, the mean of the middle two for an even count
+pub fn median(values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let mid = sorted.len() / 2;
+    Some(if sorted.len().is_multiple_of(2) {
+        (sorted[mid - 1] + sorted[mid]) / 2.0
+    } else {
+        sorted[mid]
+    })
+}
+
+/// Compact USD amount for reports and chart labels ($1.23T, $45.6B,
+/// $789.0M, $450K)
+pub fn format_usd(value: f64) -> String {
+    let sign = if value < 0.0 { "-" } else { "" };
+    let abs = value.abs();
+    if abs >= 1e12 {
+        format!("{}${:.2}T", sign, abs / 1e12)
+    } else if abs >= 1e9 {
+        format!("{}${:.1}B", sign, abs / 1e9)
+    } else if abs >= 1e6 {
+        format!("{}${:.1}M", sign, abs / 1e6)
+    } else {
+        format!("{}${:.0}K", sign, abs / 1e3)
+    }
+}
+
+/// Text with the characters Markdown would read as formatting (emphasis,
+/// links, code, HTML, table cells) backslash-escaped, for company names and
+/// other data in Markdown reports
+pub fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(
+            c,
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '|' | '~' | '#' | '!'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_fetch_ordered_keeps_input_order() {
+        let items = vec![30u64, 10, 20, 0];
+        let results = fetch_ordered(&items, 4, |delay| {
+            let delay = *delay;
+            async move {
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+                delay
+            }
+        })
+        .await;
+        assert_eq!(results, items);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_ordered_bounds_concurrency() {
+        let in_flight = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        let items: Vec<usize> = (0..20).collect();
+
+        let results = fetch_ordered(&items, 3, |item| {
+            let item = *item;
+            let in_flight = &in_flight;
+            let peak = &peak;
+            async move {
+                let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                item * 2
+            }
+        })
+        .await;
+
+        assert_eq!(results, items.iter().map(|i| i * 2).collect::<Vec<_>>());
+        assert!(peak.load(Ordering::SeqCst) <= 3);
+        assert!(peak.load(Ordering::SeqCst) > 1);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_ordered_zero_concurrency_runs_sequentially() {
+        let results = fetch_ordered(&[1, 2, 3], 0, |i| {
+            let i = *i;
+            async move { i + 1 }
+        })
+        .await;
+        assert_eq!(results, vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("30m").unwrap(), Duration::from_secs(1800));
+        assert_eq!(parse_duration("6h").unwrap(), Duration::from_secs(21600));
+        assert_eq!(parse_duration(" 2d ").unwrap(), Duration::from_secs(172800));
+        for invalid in ["", "6", "h", "6 h", "-1h", "1.5h", "6w"] {
+            assert!(parse_duration(invalid).is_err(), "{invalid}");
+        }
+        assert!(parse_duration(&format!("{}d", u64::MAX / 86400 + 1)).is_err());
+    }
+
+    #[test]
+    fn test_median() {
+        assert_eq!(median(&[]), None);
+        assert_eq!(median(&[3.0, 1.0, 2.0]), Some(2.0));
+        assert_eq!(median(&[4.0, 1.0, 2.0, 3.0]), Some(2.5));
+    }
+
+    #[test]
+    fn test_format_usd() {
+        assert_eq!(format_usd(2_345_000_000_000.0), "$2.35T");
+        assert_eq!(format_usd(-45_600_000_000.0), "-$45.6B");
+        assert_eq!(format_usd(789_000_000.0), "$789.0M");
+        assert_eq!(format_usd(450_300.0), "$450K");
+    }
+
+    #[test]
+    fn test_escape_markdown() {
+        assert_eq!(escape_markdown("H&M"), "H&M");
+        assert_eq!(
+            escape_markdown("*Star* [Brands] <Inc> | A_B"),
+            "\\*Star\\* \\[Brands\\] \\<Inc\\> \\| A\\_B"
+        );
+        assert_eq!(escape_markdown("a\\b"), "a\\\\b");
+    }
+}
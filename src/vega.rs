@@ -0,0 +1,638 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Vega-Lite chart specs (`--chart-backend vega`)
+//!
+//! With the Vega backend every chart is written as a Vega-Lite v5 JSON spec
+//! (`*.vl.json`) next to where the SVG would go, with the data inlined, so
+//! the CMS can render interactive versions. The chart functions in
+//! `visualizations` prepare the same rows for both backends; this module only
+//! turns them into specs. Colors and font come from the chart theme.
+
+use anyhow::Result;
+use plotters::style::RGBColor;
+use serde_json::{Value, json};
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::chart_theme::{self, ChartTheme};
+
+pub const SCHEMA: &str = "https://vega.github.io/schema/vega-lite/v5.json";
+
+/// How charts are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChartBackend {
+    /// Static SVG drawn with plotters
+    #[default]
+    Svg,
+    /// Vega-Lite JSON spec with inline data
+    Vega,
+}
+
+impl ChartBackend {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "svg" => Ok(ChartBackend::Svg),
+            "vega" | "vega-lite" => Ok(ChartBackend::Vega),
+            other => anyhow::bail!("Unknown chart backend '{}': use svg or vega", other),
+        }
+    }
+
+    /// Extension of chart files written with this backend
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ChartBackend::Svg => "svg",
+            ChartBackend::Vega => "vl.json",
+        }
+    }
+}
+
+static BACKEND: OnceLock<ChartBackend> = OnceLock::new();
+
+/// Set the chart backend for this run; later calls are ignored
+pub fn init(backend: ChartBackend) {
+    let _ = BACKEND.set(backend);
+}
+
+/// Backend set with `init`, SVG when none was set
+pub fn current() -> ChartBackend {
+    BACKEND.get().copied().unwrap_or_default()
+}
+
+/// Whether charts are written as Vega-Lite specs in this run
+pub fn enabled() -> bool {
+    current() == ChartBackend::Vega
+}
+
+/// Extension of chart files in this run (`svg` or `vl.json`)
+pub fn extension() -> &'static str {
+    current().extension()
+}
+
+/// CSS hex notation of a color
+pub fn hex(color: RGBColor) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.0, color.1, color.2)
+}
+
+/// Top-level spec with the theme's background, font and axis colors
+fn base(theme: &ChartTheme, title: &str, width: u32, height: u32) -> Value {
+    json!({
+        "$schema": SCHEMA,
+        "title": title,
+        "width": width,
+        "height": height,
+        "background": hex(theme.background),
+        "config": {
+            "font": theme.font,
+            "title": {"color": hex(theme.text), "fontSize": 20},
+            "axis": {
+                "labelColor": hex(theme.text),
+                "titleColor": hex(theme.text),
+                "gridColor": hex(theme.subtle),
+                "domainColor": hex(theme.muted),
+                "tickColor": hex(theme.muted)
+            },
+            "legend": {"labelColor": hex(theme.text), "titleColor": hex(theme.text)},
+            "view": {"stroke": null},
+            "range": {"category": theme.palette.iter().map(|c| hex(*c)).collect::<Vec<_>>()}
+        }
+    })
+}
+
+/// `base` with the chart-specific top-level keys added
+fn spec(title: &str, width: u32, height: u32, body: Value) -> Value {
+    let mut spec = base(chart_theme::current(), title, width, height);
+    if let (Some(spec), Value::Object(body)) = (spec.as_object_mut(), body) {
+        spec.extend(body);
+    }
+    spec
+}
+
+/// Write a spec as pretty-printed JSON
+pub fn write_spec(path: &Path, spec: &Value) -> Result<()> {
+    fs::write(path, format!("{}\n", serde_json::to_string_pretty(spec)?))?;
+    Ok(())
+}
+
+/// Horizontal bars of signed values, colored by sign (gainers/losers, rank moves)
+pub fn signed_bars(
+    title: &str,
+    x_title: &str,
+    rows: &[(String, f64)],
+    (positive, negative): (RGBColor, RGBColor),
+) -> Value {
+    let values: Vec<Value> = rows
+        .iter()
+        .map(|(label, value)| {
+            json!({
+                "label": label,
+                "value": value,
+                "direction": if *value >= 0.0 { "up" } else { "down" }
+            })
+        })
+        .collect();
+    spec(
+        title,
+        900,
+        (rows.len().max(1) * 24) as u32,
+        json!({
+            "data": {"values": values},
+            "mark": {"type": "bar", "tooltip": true},
+            "encoding": {
+                "y": {"field": "label", "type": "nominal", "sort": "-x", "title": null},
+                "x": {"field": "value", "type": "quantitative", "title": x_title},
+                "color": {
+                    "field": "direction",
+                    "type": "nominal",
+                    "scale": {"domain": ["up", "down"], "range": [hex(positive), hex(negative)]},
+                    "legend": null
+                }
+            }
+        }),
+    )
+}
+
+/// Pie or donut of shares, one color per segment in the given order
+pub fn share_arcs(title: &str, segments: &[(String, f64, RGBColor)], donut: bool) -> Value {
+    let values: Vec<Value> = segments
+        .iter()
+        .enumerate()
+        .map(|(order, (label, value, _))| json!({"label": label, "value": value, "order": order}))
+        .collect();
+    let domain: Vec<&str> = segments.iter().map(|(l, _, _)| l.as_str()).collect();
+    let range: Vec<String> = segments.iter().map(|(_, _, c)| hex(*c)).collect();
+    spec(
+        title,
+        500,
+        500,
+        json!({
+            "data": {"values": values},
+            "mark": {"type": "arc", "innerRadius": if donut { 120 } else { 0 }, "tooltip": true},
+            "encoding": {
+                "theta": {"field": "value", "type": "quantitative", "stack": true},
+                "order": {"field": "order", "type": "ordinal"},
+                "color": {
+                    "field": "label",
+                    "type": "nominal",
+                    "sort": null,
+                    "scale": {"domain": domain, "range": range},
+                    "title": null
+                }
+            }
+        }),
+    )
+}
+
+/// Ranked bars of shares with the number of companies in the tooltip
+pub fn share_bars(title: &str, rows: &[(String, f64, usize)], color: RGBColor) -> Value {
+    let values: Vec<Value> = rows
+        .iter()
+        .map(|(label, share, companies)| {
+            json!({"label": label, "share": share, "companies": companies})
+        })
+        .collect();
+    spec(
+        title,
+        700,
+        (rows.len().max(1) * 28) as u32,
+        json!({
+            "data": {"values": values},
+            "mark": {"type": "bar", "color": hex(color), "tooltip": true},
+            "encoding": {
+                "y": {"field": "label", "type": "nominal", "sort": "-x", "title": null},
+                "x": {"field": "share", "type": "quantitative", "title": "Share (%)"},
+                "tooltip": [
+                    {"field": "label", "type": "nominal"},
+                    {"field": "share", "type": "quantitative", "format": ".1f"},
+                    {"field": "companies", "type": "quantitative"}
+                ]
+            }
+        }),
+    )
+}
+
+/// One tile of a treemap with its layout in pixels
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tile {
+    pub group: String,
+    pub ticker: String,
+    pub name: String,
+    pub market_cap: f64,
+    pub change_pct: Option<f64>,
+    pub x: f64,
+    pub y: f64,
+    pub w: f64,
+    pub h: f64,
+}
+
+/// Treemap from tiles laid out by the caller (Vega-Lite has no treemap
+/// layout), colored by change on the theme's diverging scale
+pub fn treemap(title: &str, tiles: &[Tile], (width, height): (u32, u32)) -> Value {
+    let theme = chart_theme::current();
+    let values: Vec<Value> = tiles
+        .iter()
+        .map(|t| {
+            json!({
+                "group": t.group,
+                "ticker": t.ticker,
+                "name": t.name,
+                "market_cap": t.market_cap,
+                "change": t.change_pct,
+                "x": t.x,
+                "x2": t.x + t.w,
+                "y": t.y,
+                "y2": t.y + t.h,
+                "label": t.w >= 50.0 && t.h >= 30.0
+            })
+        })
+        .collect();
+    let position = |field: &str, max: u32| json!({"field": field, "type": "quantitative", "scale": {"domain": [0, max]}, "axis": null});
+    spec(
+        title,
+        width,
+        height,
+        json!({
+            "data": {"values": values},
+            "encoding": {
+                "x": position("x", width),
+                "x2": {"field": "x2"},
+                "y": {
+                    "field": "y", "type": "quantitative",
+                    "scale": {"domain": [0, height], "reverse": true}, "axis": null
+                },
+                "y2": {"field": "y2"}
+            },
+            "layer": [
+                {
+                    "mark": {"type": "rect", "stroke": hex(theme.background), "tooltip": true},
+                    "encoding": {
+                        "color": {
+                            "field": "change",
+                            "type": "quantitative",
+                            "scale": {
+                                "domain": [-10, 0, 10],
+                                "range": [hex(theme.negative), hex(theme.muted), hex(theme.positive)],
+                                "clamp": true
+                            },
+                            "title": "Change (%)"
+                        }
+                    }
+                },
+                {
+                    "transform": [{"filter": "datum.label"}],
+                    "mark": {"type": "text", "align": "left", "baseline": "top", "dx": 4, "dy": 4,
+                             "color": hex(theme.on_accent)},
+                    "encoding": {"text": {"field": "name"}}
+                }
+            ]
+        }),
+    )
+}
+
+/// Floating bars from `start` to `end` in the given order; totals start at
+/// the axis floor like in the SVG
+pub fn waterfall(title: &str, steps: &[(String, f64, f64, RGBColor)], floor: f64) -> Value {
+    let values: Vec<Value> = steps
+        .iter()
+        .enumerate()
+        .map(|(order, (label, start, end, color))| {
+            json!({
+                "step": label,
+                "order": order,
+                "start": if start.is_nan() { floor } else { *start },
+                "end": end,
+                "change": if start.is_nan() { None } else { Some(end - start) },
+                "color": hex(*color)
+            })
+        })
+        .collect();
+    spec(
+        title,
+        900,
+        450,
+        json!({
+            "data": {"values": values},
+            "mark": {"type": "bar", "tooltip": true},
+            "encoding": {
+                "x": {"field": "step", "type": "ordinal", "sort": {"field": "order"}, "title": null},
+                "y": {"field": "start", "type": "quantitative", "scale": {"zero": false}, "title": "Market cap (USD)"},
+                "y2": {"field": "end"},
+                "color": {"field": "color", "type": "nominal", "scale": null}
+            }
+        }),
+    )
+}
+
+/// Bump chart: rank per date, one line per company, rank 1 at the top
+pub fn bump(title: &str, points: &[(String, String, String, usize)]) -> Value {
+    let values: Vec<Value> = points
+        .iter()
+        .map(|(ticker, name, date, rank)| {
+            json!({"ticker": ticker, "name": name, "date": date, "rank": rank})
+        })
+        .collect();
+    spec(
+        title,
+        900,
+        600,
+        json!({
+            "data": {"values": values},
+            "mark": {"type": "line", "point": true, "strokeWidth": 3, "tooltip": true},
+            "encoding": {
+                "x": {"field": "date", "type": "ordinal", "title": null},
+                "y": {"field": "rank", "type": "quantitative", "scale": {"reverse": true, "zero": false}, "title": "Rank"},
+                "color": {"field": "ticker", "type": "nominal", "title": null},
+                "detail": {"field": "name"}
+            }
+        }),
+    )
+}
+
+/// Small multiples: one line of values per series, series stacked in rows
+/// in the given order, with a zero rule
+pub fn sparklines(title: &str, points: &[(String, String, f64)]) -> Value {
+    let theme = chart_theme::current();
+    let series: Vec<&str> = points.iter().fold(Vec::new(), |mut series, (name, _, _)| {
+        if !series.contains(&name.as_str()) {
+            series.push(name);
+        }
+        series
+    });
+    let values: Vec<Value> = points
+        .iter()
+        .map(|(name, x, y)| json!({"series": name, "x": x, "value": y}))
+        .collect();
+    let mut spec = spec(
+        title,
+        600,
+        60,
+        json!({
+            "data": {"values": values},
+            "facet": {"row": {"field": "series", "type": "nominal", "title": null, "sort": series}},
+            "spec": {
+                "width": 600,
+                "height": 60,
+                "layer": [
+                    {
+                        "mark": {"type": "rule", "color": hex(theme.subtle)},
+                        "encoding": {"y": {"datum": 0}}
+                    },
+                    {
+                        "mark": {"type": "line", "point": true, "color": hex(theme.primary), "tooltip": true},
+                        "encoding": {
+                            "x": {"field": "x", "type": "ordinal", "title": null},
+                            "y": {"field": "value", "type": "quantitative", "title": null}
+                        }
+                    }
+                ]
+            },
+            "resolve": {"scale": {"y": "independent"}}
+        }),
+    );
+    // Sizes belong to the faceted views
+    if let Some(spec) = spec.as_object_mut() {
+        spec.remove("width");
+        spec.remove("height");
+    }
+    spec
+}
+
+/// Rank and EUR market cap over time for one company
+pub fn rank_history(title: &str, points: &[(String, i64, Option<f64>)]) -> Value {
+    let theme = chart_theme::current();
+    let values: Vec<Value> = points
+        .iter()
+        .map(|(date, rank, cap)| json!({"date": date, "rank": rank, "market_cap_eur_bn": cap}))
+        .collect();
+    let mut spec = spec(
+        title,
+        900,
+        250,
+        json!({
+            "data": {"values": values},
+            "vconcat": [
+                {
+                    "mark": {"type": "line", "point": true, "color": hex(theme.primary), "tooltip": true},
+                    "encoding": {
+                        "x": {"field": "date", "type": "ordinal", "title": null},
+                        "y": {"field": "rank", "type": "quantitative", "scale": {"reverse": true, "zero": false}, "title": "Rank"}
+                    }
+                },
+                {
+                    "mark": {"type": "line", "point": true, "color": hex(theme.positive), "tooltip": true},
+                    "encoding": {
+                        "x": {"field": "date", "type": "ordinal", "title": null},
+                        "y": {"field": "market_cap_eur_bn", "type": "quantitative", "title": "Market cap (EUR billions)"}
+                    }
+                }
+            ]
+        }),
+    );
+    // Sizes belong to the concatenated views
+    if let Some(spec) = spec.as_object_mut() {
+        spec.remove("width");
+        spec.remove("height");
+    }
+    spec
+}
+
+/// Dashboard: total market cap on both dates next to the gainer/loser split
+pub fn dashboard(
+    title: &str,
+    totals: &[(String, f64)],
+    movement: &[(String, usize, RGBColor)],
+) -> Value {
+    let theme = chart_theme::current();
+    let total_values: Vec<Value> = totals
+        .iter()
+        .map(|(date, total)| json!({"date": date, "total_usd": total}))
+        .collect();
+    let movement_values: Vec<Value> = movement
+        .iter()
+        .map(|(label, count, _)| json!({"movement": label, "companies": count}))
+        .collect();
+    let domain: Vec<&str> = movement.iter().map(|(l, _, _)| l.as_str()).collect();
+    let range: Vec<String> = movement.iter().map(|(_, _, c)| hex(*c)).collect();
+    let mut spec = spec(
+        title,
+        0,
+        0,
+        json!({
+            "hconcat": [
+                {
+                    "width": 300, "height": 300,
+                    "data": {"values": total_values},
+                    "mark": {"type": "bar", "color": hex(theme.primary), "tooltip": true},
+                    "encoding": {
+                        "x": {"field": "date", "type": "ordinal", "title": null},
+                        "y": {"field": "total_usd", "type": "quantitative", "title": "Total market cap (USD)"}
+                    }
+                },
+                {
+                    "width": 300, "height": 300,
+                    "data": {"values": movement_values},
+                    "mark": {"type": "arc", "tooltip": true},
+                    "encoding": {
+                        "theta": {"field": "companies", "type": "quantitative"},
+                        "color": {
+                            "field": "movement", "type": "nominal", "sort": null,
+                            "scale": {"domain": domain, "range": range}, "title": null
+                        }
+                    }
+                }
+            ]
+        }),
+    );
+    if let Some(spec) = spec.as_object_mut() {
+        spec.remove("width");
+        spec.remove("height");
+    }
+    spec
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart_theme::{COLOR_EMERALD, COLOR_ROSE};
+
+    #[test]
+    fn test_backend_parse_and_extension() {
+        assert_eq!(ChartBackend::parse("svg").unwrap(), ChartBackend::Svg);
+        assert_eq!(ChartBackend::parse("Vega").unwrap(), ChartBackend::Vega);
+        assert_eq!(
+            ChartBackend::parse("vega-lite").unwrap(),
+            ChartBackend::Vega
+        );
+        assert!(ChartBackend::parse("plotly").is_err());
+        assert_eq!(ChartBackend::Svg.extension(), "svg");
+        assert_eq!(ChartBackend::Vega.extension(), "vl.json");
+    }
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(hex(COLOR_EMERALD), "#10b981");
+        assert_eq!(hex(RGBColor(0, 0, 0)), "#000000");
+    }
+
+    #[test]
+    fn test_signed_bars_spec() {
+        let spec = signed_bars(
+            "Top Gainers and Losers",
+            "Percentage Change (%)",
+            &[("LVMH".to_string(), 12.5), ("Nike".to_string(), -4.0)],
+            (COLOR_EMERALD, COLOR_ROSE),
+        );
+        assert_eq!(spec["$schema"], SCHEMA);
+        assert_eq!(spec["mark"]["type"], "bar");
+        assert_eq!(spec["data"]["values"][0]["label"], "LVMH");
+        assert_eq!(spec["data"]["values"][1]["direction"], "down");
+        assert_eq!(
+            spec["encoding"]["color"]["scale"]["range"],
+            json!(["#10b981", "#f43f5e"])
+        );
+        // Theme colors reach the config
+        assert_eq!(spec["background"], "#ffffff");
+        assert_eq!(spec["config"]["font"], "sans-serif");
+    }
+
+    #[test]
+    fn test_share_arcs_keep_segment_order_and_colors() {
+        let spec = share_arcs(
+            "Market Cap Distribution",
+            &[
+                ("LVMH (MC.PA)".to_string(), 300.0, COLOR_EMERALD),
+                ("Others".to_string(), 100.0, COLOR_ROSE),
+            ],
+            true,
+        );
+        assert_eq!(spec["mark"]["innerRadius"], 120);
+        assert_eq!(
+            spec["encoding"]["color"]["scale"]["domain"],
+            json!(["LVMH (MC.PA)", "Others"])
+        );
+        assert_eq!(spec["data"]["values"][1]["order"], 1);
+    }
+
+    #[test]
+    fn test_sparklines_keep_series_order() {
+        let spec = sparklines(
+            "Relative Strength",
+            &[
+                ("Sportswear".to_string(), "2025-03-31".to_string(), 2.5),
+                ("Luxury".to_string(), "2025-03-31".to_string(), -1.0),
+                ("Sportswear".to_string(), "2025-06-30".to_string(), 1.5),
+            ],
+        );
+        assert_eq!(
+            spec["facet"]["row"]["sort"],
+            json!(["Sportswear", "Luxury"])
+        );
+        assert_eq!(spec["data"]["values"][1]["value"], -1.0);
+        assert!(spec.get("width").is_none());
+    }
+
+    #[test]
+    fn test_waterfall_totals_start_at_floor() {
+        let spec = waterfall(
+            "Market Cap Change",
+            &[
+                ("2025-01-31".to_string(), f64::NAN, 100.0, COLOR_EMERALD),
+                ("NKE".to_string(), 100.0, 90.0, COLOR_ROSE),
+            ],
+            80.0,
+        );
+        let values = &spec["data"]["values"];
+        assert_eq!(values[0]["start"], 80.0);
+        assert_eq!(values[0]["change"], Value::Null);
+        assert_eq!(values[1]["change"], -10.0);
+        assert_eq!(values[1]["color"], "#f43f5e");
+    }
+
+    #[test]
+    fn test_treemap_spec_positions_and_labels() {
+        let tiles = [Tile {
+            group: "Luxury".to_string(),
+            ticker: "MC.PA".to_string(),
+            name: "LVMH".to_string(),
+            market_cap: 300.0,
+            change_pct: Some(4.0),
+            x: 10.0,
+            y: 20.0,
+            w: 100.0,
+            h: 60.0,
+        }];
+        let spec = treemap("Market Map", &tiles, (1400, 900));
+        let tile = &spec["data"]["values"][0];
+        assert_eq!(tile["x2"], 110.0);
+        assert_eq!(tile["y2"], 80.0);
+        assert_eq!(tile["label"], true);
+        assert_eq!(spec["layer"].as_array().unwrap().len(), 2);
+        assert_eq!(spec["layer"][1]["encoding"]["text"]["field"], "name");
+    }
+
+    #[test]
+    fn test_concat_specs_have_no_top_level_size() {
+        let spec = rank_history("NKE", &[("2025-01-31".to_string(), 3, Some(100.0))]);
+        assert!(spec.get("width").is_none());
+        assert_eq!(spec["vconcat"].as_array().unwrap().len(), 2);
+
+        let spec = dashboard(
+            "Market Summary",
+            &[("2025-01-31".to_string(), 1.0)],
+            &[("Gainers".to_string(), 3, COLOR_EMERALD)],
+        );
+        assert!(spec.get("height").is_none());
+        assert_eq!(spec["hconcat"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_write_spec() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("chart.vl.json");
+        write_spec(&path, &bump("Ranks", &[])).unwrap();
+        let written: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written["title"], "Ranks");
+    }
+}
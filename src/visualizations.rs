@@ -2,12 +2,27 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-only
 
+use crate::advanced_comparisons::{TickerTrend, get_predefined_peer_groups};
+use crate::chart_theme::{self, ChartTheme};
+use crate::clock;
+use crate::compare_marketcaps::ChangeAttribution;
+use crate::config::{self, OutputConfig};
+use crate::geo;
+use crate::logos;
+use crate::rankings;
+use crate::regions::{self, GroupTotal};
+use crate::run_context;
+use crate::utils;
+use crate::vega;
 use anyhow::{Context, Result};
+use chrono::DateTime;
 use csv::Reader;
 use plotters::prelude::*;
 use serde::Deserialize;
+use sqlx::sqlite::SqlitePool;
+use std::collections::HashMap;
 use std::fs::File;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Deserialize)]
 struct ComparisonRecord {
@@ -15,6 +30,8 @@ struct ComparisonRecord {
     ticker: String,
     #[serde(rename = "Name")]
     name: String,
+    #[serde(rename = "Currency", default)]
+    currency: Option<String>,
     #[serde(rename = "Market Cap From (USD)")]
     market_cap_from: Option<String>,
     #[serde(rename = "Market Cap To (USD)")]
@@ -32,70 +49,27 @@ struct ComparisonRecord {
     #[serde(rename = "Market Share From (%)")]
     _market_share_from: Option<String>,
     #[serde(rename = "Market Share To (%)")]
-    _market_share_to: Option<String>,
+    market_share_to: Option<String>,
 }
 
-// Professional color palette
-const COLOR_EMERALD: RGBColor = RGBColor(16, 185, 129);
-const COLOR_ROSE: RGBColor = RGBColor(244, 63, 94);
-const COLOR_BLUE: RGBColor = RGBColor(59, 130, 246);
-const COLOR_AMBER: RGBColor = RGBColor(245, 158, 11);
-const COLOR_TEAL: RGBColor = RGBColor(20, 184, 166);
-const COLOR_CORAL: RGBColor = RGBColor(251, 113, 133);
-const COLOR_PURPLE: RGBColor = RGBColor(139, 92, 246);
-const COLOR_PINK: RGBColor = RGBColor(236, 72, 153);
-const COLOR_LIME: RGBColor = RGBColor(132, 204, 22);
-const COLOR_ORANGE: RGBColor = RGBColor(249, 115, 22);
-const COLOR_SLATE: RGBColor = RGBColor(100, 116, 139);
-const COLOR_GRAY_LIGHT: RGBColor = RGBColor(243, 244, 246);
-
-const CHART_COLORS: [RGBColor; 10] = [
-    COLOR_BLUE,
-    COLOR_EMERALD,
-    COLOR_AMBER,
-    COLOR_ROSE,
-    COLOR_PURPLE,
-    COLOR_PINK,
-    COLOR_TEAL,
-    COLOR_ORANGE,
-    COLOR_LIME,
-    COLOR_SLATE,
-];
-
 /// Find the comparison CSV file for the given dates
-fn find_comparison_csv(from_date: &str, to_date: &str) -> Result<String> {
-    let output_dir = Path::new("output");
-    let pattern = format!("comparison_{}_to_{}_", from_date, to_date);
-
-    let mut matching_files = Vec::new();
-    for entry in std::fs::read_dir(output_dir)? {
-        let entry = entry?;
-        let file_name = entry.file_name();
-        let file_name_str = file_name.to_string_lossy();
-
-        if file_name_str.starts_with(&pattern) && file_name_str.ends_with(".csv") {
-            matching_files.push(file_name_str.to_string());
-        }
-    }
-
-    if matching_files.is_empty() {
-        anyhow::bail!(
+fn find_comparison_csv(from_date: &str, to_date: &str, output: &OutputConfig) -> Result<String> {
+    let range = format!("{}_to_{}", from_date, to_date);
+    match output.find_latest("comparison", &range, "csv")? {
+        Some(path) => Ok(path.display().to_string()),
+        None => anyhow::bail!(
             "No comparison CSV found for {} to {}. Please run 'compare-market-caps' first.",
             from_date,
             to_date
-        );
+        ),
     }
-
-    matching_files.sort();
-    let selected_file = matching_files.last().unwrap();
-
-    Ok(format!("output/{}", selected_file))
 }
 
 /// Read comparison data from CSV
 fn read_comparison_data(csv_path: &str) -> Result<Vec<ComparisonRecord>> {
     let file =
         File::open(csv_path).with_context(|| format!("Failed to open CSV file: {}", csv_path))?;
+    run_context::record_input(csv_path);
 
     let mut reader = Reader::from_reader(file);
     let mut records = Vec::new();
@@ -128,12 +102,28 @@ fn parse_usd_amount(s: &Option<String>) -> Option<f64> {
     s.as_ref()?.parse::<f64>().ok()
 }
 
+/// Write a chart as `<stem>.vl.json` in the output directory
+/// (`--chart-backend vega`)
+fn write_vega_chart(
+    output: &OutputConfig,
+    stem: &str,
+    spec: &serde_json::Value,
+    label: &str,
+) -> Result<PathBuf> {
+    let path = output.named_path(&format!("{}.{}", stem, vega::extension()));
+    vega::write_spec(&path, spec)?;
+    println!("✅ Generated {}: {}", label, path.display());
+    Ok(path)
+}
+
 /// Create top gainers and losers bar chart
 fn create_gainers_losers_chart(
     records: &[ComparisonRecord],
     from_date: &str,
     to_date: &str,
-) -> Result<()> {
+    output: &OutputConfig,
+) -> Result<PathBuf> {
+    let theme = chart_theme::current();
     // Filter and sort for top gainers
     let mut gainers: Vec<_> = records
         .iter()
@@ -146,8 +136,8 @@ fn create_gainers_losers_chart(
             }
         })
         .collect();
-    gainers.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
-    gainers.truncate(10);
+    gainers.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap().then_with(|| a.0.cmp(&b.0)));
+    gainers.truncate(rankings::section_size());
 
     // Filter and sort for top losers
     let mut losers: Vec<_> = records
@@ -161,21 +151,36 @@ fn create_gainers_losers_chart(
             }
         })
         .collect();
-    losers.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
-    losers.truncate(10);
+    losers.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap().then_with(|| a.0.cmp(&b.0)));
+    losers.truncate(rankings::section_size());
+
+    if vega::enabled() {
+        let rows: Vec<(String, f64)> = gainers.iter().chain(losers.iter()).cloned().collect();
+        let spec = vega::signed_bars(
+            &format!("Top Gainers and Losers: {} to {}", from_date, to_date),
+            "Percentage Change (%)",
+            &rows,
+            (theme.positive, theme.negative),
+        );
+        let stem = format!("comparison_{}_to_{}_gainers_losers", from_date, to_date);
+        return write_vega_chart(output, &stem, &spec, "gainers/losers chart");
+    }
 
     // Create the chart
-    let filename = format!(
-        "output/comparison_{}_to_{}_gainers_losers.svg",
-        from_date, to_date
-    );
+    let filename = output
+        .named_path(&format!(
+            "comparison_{}_to_{}_gainers_losers.svg",
+            from_date, to_date
+        ))
+        .display()
+        .to_string();
     let root = SVGBackend::new(&filename, (1200, 800)).into_drawing_area();
-    root.fill(&WHITE)?;
+    root.fill(&theme.background)?;
 
     let mut chart = ChartBuilder::on(&root)
         .caption(
             format!("Top Gainers and Losers: {} to {}", from_date, to_date),
-            ("sans-serif", 32).into_font().color(&BLACK),
+            theme.text_style(32),
         )
         .margin(20)
         .x_label_area_size(150)
@@ -188,18 +193,19 @@ fn create_gainers_losers_chart(
         .y_desc("")
         .x_label_formatter(&|x| format!("{:.0}%", x))
         .y_label_formatter(&|_| "".to_string())
-        .axis_desc_style(("sans-serif", 16))
+        .axis_desc_style(theme.text_style(16))
+        .label_style(theme.text_style(12))
+        .axis_style(theme.muted)
+        .bold_line_style(theme.subtle)
+        .light_line_style(theme.subtle.mix(0.5))
         .draw()?;
 
     // Draw gainers (green gradient)
     for (i, (name, pct)) in gainers.iter().enumerate() {
         let y = 19 - i;
         let y_coord = y as i32;
-        let color = RGBColor(
-            16 + (i * 10) as u8,
-            185 - (i * 5) as u8,
-            129 - (i * 5) as u8,
-        );
+        // Fade from the full color for the largest gain
+        let color = theme.positive.mix(1.0 - i as f64 * 0.05);
 
         chart.draw_series(std::iter::once(Rectangle::new(
             [(0.0, y), (*pct, y.saturating_sub(1))],
@@ -209,16 +215,12 @@ fn create_gainers_losers_chart(
         // Add label
         let label_name = truncate_string(name, 30);
 
-        root.draw_text(
-            &label_name,
-            &TextStyle::from(("sans-serif", 14).into_font()),
-            (50, 80 + y_coord * 35),
-        )?;
+        root.draw_text(&label_name, &theme.text_style(14), (50, 80 + y_coord * 35))?;
 
         // Add value label
         root.draw_text(
             &format!("+{:.1}%", pct),
-            &TextStyle::from(("sans-serif", 12).into_font()).color(&COLOR_EMERALD),
+            &theme.text_style(12).color(&theme.positive),
             (1050, 80 + y_coord * 35),
         )?;
     }
@@ -227,12 +229,7 @@ fn create_gainers_losers_chart(
     for (i, (name, pct)) in losers.iter().enumerate() {
         let y = 9 - i;
         let y_coord = y as i32;
-        // Use saturating arithmetic to prevent u8 underflow
-        let color = RGBColor(
-            244u8.saturating_sub((i * 5) as u8),
-            63u8.saturating_add((i * 5) as u8),
-            94u8.saturating_add((i * 5) as u8),
-        );
+        let color = theme.negative.mix(1.0 - i as f64 * 0.05);
 
         chart.draw_series(std::iter::once(Rectangle::new(
             [(0.0, y), (*pct, y.saturating_sub(1))],
@@ -244,14 +241,14 @@ fn create_gainers_losers_chart(
 
         root.draw_text(
             &label_name,
-            &TextStyle::from(("sans-serif", 14).into_font()),
+            &theme.text_style(14),
             (50, 440 + (9 - y_coord) * 35),
         )?;
 
         // Add value label
         root.draw_text(
             &format!("{:.1}%", pct),
-            &TextStyle::from(("sans-serif", 12).into_font()).color(&COLOR_ROSE),
+            &theme.text_style(12).color(&theme.negative),
             (1050, 440 + (9 - y_coord) * 35),
         )?;
     }
@@ -259,13 +256,14 @@ fn create_gainers_losers_chart(
     // Add dividing line
     chart.draw_series(std::iter::once(PathElement::new(
         vec![(0.0, 10), (0.0, 10)],
-        BLACK.stroke_width(2),
+        theme.text.stroke_width(2),
     )))?;
 
     root.present()?;
+    theme.apply_watermark(Path::new(&filename), root.dim_in_pixel())?;
     println!("✅ Generated gainers/losers chart: {}", filename);
 
-    Ok(())
+    Ok(PathBuf::from(&filename))
 }
 
 /// Create market cap distribution donut chart
@@ -273,8 +271,10 @@ fn create_market_distribution_chart(
     records: &[ComparisonRecord],
     from_date: &str,
     to_date: &str,
-) -> Result<()> {
-    // Get top 10 companies by market cap
+    output: &OutputConfig,
+) -> Result<PathBuf> {
+    let theme = chart_theme::current();
+    // Get the largest companies by market cap (10, or fewer under --top)
     let mut companies: Vec<_> = records
         .iter()
         .filter_map(|r| {
@@ -282,25 +282,60 @@ fn create_market_distribution_chart(
             Some((r.ticker.clone(), r.name.clone(), market_cap))
         })
         .collect();
-    companies.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap());
+    companies
+        .sort_by(|a, b| rankings::rank_order((Some(a.2), &a.1, &a.0), (Some(b.2), &b.1, &b.0)));
 
     let total_market_cap: f64 = companies.iter().map(|c| c.2).sum();
-    let top_10 = companies.iter().take(10).cloned().collect::<Vec<_>>();
+    let top_10 = companies
+        .iter()
+        .take(rankings::section_size())
+        .cloned()
+        .collect::<Vec<_>>();
     let top_10_sum: f64 = top_10.iter().map(|c| c.2).sum();
     let others = total_market_cap - top_10_sum;
 
+    if vega::enabled() {
+        let mut segments: Vec<(String, f64, RGBColor)> = top_10
+            .iter()
+            .enumerate()
+            .map(|(i, (ticker, name, market_cap))| {
+                (
+                    format!("{} ({})", name, ticker),
+                    *market_cap,
+                    theme.palette[i],
+                )
+            })
+            .collect();
+        if others > 0.0 {
+            segments.push(("Others".to_string(), others, theme.subtle));
+        }
+        let spec = vega::share_arcs(
+            &format!("Market Cap Distribution: {}", to_date),
+            &segments,
+            true,
+        );
+        let stem = format!(
+            "comparison_{}_to_{}_market_distribution",
+            from_date, to_date
+        );
+        return write_vega_chart(output, &stem, &spec, "market distribution chart");
+    }
+
     // Create the chart
-    let filename = format!(
-        "output/comparison_{}_to_{}_market_distribution.svg",
-        from_date, to_date
-    );
+    let filename = output
+        .named_path(&format!(
+            "comparison_{}_to_{}_market_distribution.svg",
+            from_date, to_date
+        ))
+        .display()
+        .to_string();
     let root = SVGBackend::new(&filename, (1200, 800)).into_drawing_area();
-    root.fill(&WHITE)?;
+    root.fill(&theme.background)?;
 
     // Title
     root.draw_text(
         &format!("Market Cap Distribution: {}", to_date),
-        &TextStyle::from(("sans-serif", 32).into_font()).color(&BLACK),
+        &theme.text_style(32),
         (400, 30),
     )?;
 
@@ -323,7 +358,7 @@ fn create_market_distribution_chart(
             inner_radius,
             start_angle,
             sweep_angle,
-            CHART_COLORS[i],
+            theme.palette[i],
         )?;
 
         start_angle += sweep_angle;
@@ -341,7 +376,7 @@ fn create_market_distribution_chart(
             inner_radius,
             start_angle,
             sweep_angle,
-            COLOR_GRAY_LIGHT,
+            theme.subtle,
         )?;
     }
 
@@ -355,7 +390,7 @@ fn create_market_distribution_chart(
         // Color box
         root.draw(&Rectangle::new(
             [(legend_x, y), (legend_x + 20, y + 20)],
-            CHART_COLORS[i].filled(),
+            theme.palette[i].filled(),
         ))?;
 
         // Company name
@@ -363,7 +398,7 @@ fn create_market_distribution_chart(
 
         root.draw_text(
             &format!("{} ({})", display_name, ticker),
-            &TextStyle::from(("sans-serif", 14).into_font()),
+            &theme.text_style(14),
             (legend_x + 30, y + 5),
         )?;
 
@@ -371,7 +406,7 @@ fn create_market_distribution_chart(
         let percentage = (market_cap / total_market_cap) * 100.0;
         root.draw_text(
             &format!("{:.1}%", percentage),
-            &TextStyle::from(("sans-serif", 12).into_font()).color(&COLOR_SLATE),
+            &theme.text_style(12).color(&theme.muted),
             (legend_x + 30, y + 20),
         )?;
     }
@@ -381,19 +416,15 @@ fn create_market_distribution_chart(
         let y = legend_y_start + 10 * 35;
         root.draw(&Rectangle::new(
             [(legend_x, y), (legend_x + 20, y + 20)],
-            COLOR_GRAY_LIGHT.filled(),
+            theme.subtle.filled(),
         ))?;
 
-        root.draw_text(
-            "Others",
-            &TextStyle::from(("sans-serif", 14).into_font()),
-            (legend_x + 30, y + 5),
-        )?;
+        root.draw_text("Others", &theme.text_style(14), (legend_x + 30, y + 5))?;
 
         let percentage = (others / total_market_cap) * 100.0;
         root.draw_text(
             &format!("{:.1}%", percentage),
-            &TextStyle::from(("sans-serif", 12).into_font()).color(&COLOR_SLATE),
+            &theme.text_style(12).color(&theme.muted),
             (legend_x + 30, y + 20),
         )?;
     }
@@ -401,19 +432,32 @@ fn create_market_distribution_chart(
     // Add center text with total
     root.draw_text(
         "Total Market Cap",
-        &TextStyle::from(("sans-serif", 16).into_font()).color(&COLOR_SLATE),
+        &theme.text_style(16).color(&theme.muted),
         (center.0 - 60, center.1 - 10),
     )?;
     root.draw_text(
         &format!("${:.1}T", total_market_cap / 1_000_000_000_000.0),
-        &TextStyle::from(("sans-serif", 24).into_font()).color(&BLACK),
+        &theme.text_style(24),
         (center.0 - 40, center.1 + 10),
     )?;
 
     root.present()?;
+    theme.apply_watermark(Path::new(&filename), root.dim_in_pixel())?;
+
+    // Scaled logos to the left of the legend, for companies whose logo is cached
+    let logos: Vec<String> = top_10
+        .iter()
+        .enumerate()
+        .filter_map(|(i, (ticker, _, _))| {
+            let y = legend_y_start + (i as i32) * 35;
+            logos::svg_image(output, ticker, legend_x - 26, y, 20)
+        })
+        .collect();
+    logos::embed_in_svg(Path::new(&filename), &logos)?;
+
     println!("✅ Generated market distribution chart: {}", filename);
 
-    Ok(())
+    Ok(PathBuf::from(&filename))
 }
 
 /// Draw a donut segment
@@ -457,7 +501,9 @@ fn create_rank_movement_chart(
     records: &[ComparisonRecord],
     from_date: &str,
     to_date: &str,
-) -> Result<()> {
+    output: &OutputConfig,
+) -> Result<PathBuf> {
+    let theme = chart_theme::current();
     // Parse rank changes
     let mut rank_changes: Vec<_> = records
         .iter()
@@ -483,42 +529,61 @@ fn create_rank_movement_chart(
         })
         .collect();
 
-    // Get top 10 improvements and declines
-    rank_changes.sort_by(|a, b| b.1.cmp(&a.1));
+    // Get top improvements and declines
+    rank_changes.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
     let improvements = rank_changes
         .iter()
         .filter(|r| r.1 > 0)
-        .take(10)
+        .take(rankings::section_size())
         .cloned()
         .collect::<Vec<_>>();
 
-    rank_changes.sort_by(|a, b| a.1.cmp(&b.1));
+    rank_changes.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
     let declines = rank_changes
         .iter()
         .filter(|r| r.1 < 0)
-        .take(10)
+        .take(rankings::section_size())
         .cloned()
         .collect::<Vec<_>>();
 
+    if vega::enabled() {
+        let rows: Vec<(String, f64)> = improvements
+            .iter()
+            .chain(declines.iter())
+            .map(|(name, change, _, _)| (name.clone(), *change as f64))
+            .collect();
+        let spec = vega::signed_bars(
+            &format!("Rank Movements: {} to {}", from_date, to_date),
+            "Rank change (positions)",
+            &rows,
+            (theme.positive_alt, theme.negative_alt),
+        );
+        let stem = format!("comparison_{}_to_{}_rank_movements", from_date, to_date);
+        return write_vega_chart(output, &stem, &spec, "rank movements chart");
+    }
+
     // Create the chart
-    let filename = format!(
-        "output/comparison_{}_to_{}_rank_movements.svg",
-        from_date, to_date
-    );
+    let filename = output
+        .named_path(&format!(
+            "comparison_{}_to_{}_rank_movements.svg",
+            from_date, to_date
+        ))
+        .display()
+        .to_string();
     let root = SVGBackend::new(&filename, (1200, 800)).into_drawing_area();
-    root.fill(&WHITE)?;
+    root.fill(&theme.background)?;
 
     // Title
     root.draw_text(
         &format!("Rank Movements: {} to {}", from_date, to_date),
-        &TextStyle::from(("sans-serif", 32).into_font()).color(&BLACK),
+        &theme.text_style(32),
         (350, 30),
     )?;
 
     // Draw improvements
     root.draw_text(
         "Biggest Rank Improvements",
-        &TextStyle::from(("sans-serif", 20).into_font()).color(&COLOR_TEAL),
+        &theme.text_style(20).color(&theme.positive_alt),
         (150, 100),
     )?;
 
@@ -529,17 +594,13 @@ fn create_rank_movement_chart(
         // Draw bar
         root.draw(&Rectangle::new(
             [(200, y as i32), (200 + bar_width, (y + 20) as i32)],
-            COLOR_TEAL.filled(),
+            theme.positive_alt.filled(),
         ))?;
 
         // Company name
         let display_name = truncate_string(name, 25);
 
-        root.draw_text(
-            &display_name,
-            &TextStyle::from(("sans-serif", 12).into_font()),
-            (10, y as i32),
-        )?;
+        root.draw_text(&display_name, &theme.text_style(12), (10, y as i32))?;
 
         // Change value
         root.draw_text(
@@ -549,7 +610,7 @@ fn create_rank_movement_chart(
                 from.as_ref().unwrap_or(&"NA".to_string()),
                 to.as_ref().unwrap_or(&"NA".to_string())
             ),
-            &TextStyle::from(("sans-serif", 11).into_font()).color(&COLOR_TEAL),
+            &theme.text_style(11).color(&theme.positive_alt),
             (210 + bar_width, y as i32 + 5),
         )?;
     }
@@ -557,7 +618,7 @@ fn create_rank_movement_chart(
     // Draw declines
     root.draw_text(
         "Biggest Rank Declines",
-        &TextStyle::from(("sans-serif", 20).into_font()).color(&COLOR_CORAL),
+        &theme.text_style(20).color(&theme.negative_alt),
         (150, 450),
     )?;
 
@@ -568,17 +629,13 @@ fn create_rank_movement_chart(
         // Draw bar
         root.draw(&Rectangle::new(
             [(200, y as i32), (200 + bar_width, (y + 20) as i32)],
-            COLOR_CORAL.filled(),
+            theme.negative_alt.filled(),
         ))?;
 
         // Company name
         let display_name = truncate_string(name, 25);
 
-        root.draw_text(
-            &display_name,
-            &TextStyle::from(("sans-serif", 12).into_font()),
-            (10, y as i32),
-        )?;
+        root.draw_text(&display_name, &theme.text_style(12), (10, y as i32))?;
 
         // Change value
         root.draw_text(
@@ -588,15 +645,16 @@ fn create_rank_movement_chart(
                 from.as_ref().unwrap_or(&"NA".to_string()),
                 to.as_ref().unwrap_or(&"NA".to_string())
             ),
-            &TextStyle::from(("sans-serif", 11).into_font()).color(&COLOR_CORAL),
+            &theme.text_style(11).color(&theme.negative_alt),
             (210 + bar_width, y as i32 + 5),
         )?;
     }
 
     root.present()?;
+    theme.apply_watermark(Path::new(&filename), root.dim_in_pixel())?;
     println!("✅ Generated rank movements chart: {}", filename);
 
-    Ok(())
+    Ok(PathBuf::from(&filename))
 }
 
 /// Create market summary dashboard
@@ -604,7 +662,9 @@ fn create_summary_dashboard(
     records: &[ComparisonRecord],
     from_date: &str,
     to_date: &str,
-) -> Result<()> {
+    output: &OutputConfig,
+) -> Result<PathBuf> {
+    let theme = chart_theme::current();
     // Calculate metrics
     let total_from: f64 = records
         .iter()
@@ -635,74 +695,94 @@ fn create_summary_dashboard(
 
     let unchanged = records.len() - gainers - losers;
 
+    if vega::enabled() {
+        let spec = vega::dashboard(
+            &format!("Market Summary: {} to {}", from_date, to_date),
+            &[
+                (from_date.to_string(), total_from),
+                (to_date.to_string(), total_to),
+            ],
+            &[
+                ("Gainers".to_string(), gainers, theme.positive),
+                ("Losers".to_string(), losers, theme.negative),
+                ("Unchanged".to_string(), unchanged, theme.muted),
+            ],
+        );
+        let stem = format!("comparison_{}_to_{}_summary_dashboard", from_date, to_date);
+        return write_vega_chart(output, &stem, &spec, "summary dashboard");
+    }
+
     // Create the dashboard
-    let filename = format!(
-        "output/comparison_{}_to_{}_summary_dashboard.svg",
-        from_date, to_date
-    );
+    let filename = output
+        .named_path(&format!(
+            "comparison_{}_to_{}_summary_dashboard.svg",
+            from_date, to_date
+        ))
+        .display()
+        .to_string();
     let root = SVGBackend::new(&filename, (1200, 800)).into_drawing_area();
-    root.fill(&WHITE)?;
+    root.fill(&theme.background)?;
 
     // Title
     root.draw_text(
         &format!("Market Summary: {} to {}", from_date, to_date),
-        &TextStyle::from(("sans-serif", 36).into_font()).color(&BLACK),
+        &theme.text_style(36),
         (300, 40),
     )?;
 
     // Main metric box
     let metric_color = if total_change >= 0.0 {
-        COLOR_EMERALD
+        theme.positive
     } else {
-        COLOR_ROSE
+        theme.negative
     };
     let arrow = if total_change >= 0.0 { "↑" } else { "↓" };
 
     // Background box
     root.draw(&Rectangle::new(
         [(100, 120), (500, 280)],
-        COLOR_GRAY_LIGHT.filled(),
+        theme.subtle.filled(),
     ))?;
 
     root.draw_text(
         "Total Market Cap Change",
-        &TextStyle::from(("sans-serif", 18).into_font()).color(&COLOR_SLATE),
+        &theme.text_style(18).color(&theme.muted),
         (220, 140),
     )?;
 
     root.draw_text(
         &format!("{} ${:.2}B", arrow, total_change.abs() / 1_000_000_000.0),
-        &TextStyle::from(("sans-serif", 48).into_font()).color(&metric_color),
+        &theme.text_style(48).color(&metric_color),
         (180, 190),
     )?;
 
     root.draw_text(
         &format!("{:.2}%", total_pct_change),
-        &TextStyle::from(("sans-serif", 32).into_font()).color(&metric_color),
+        &theme.text_style(32).color(&metric_color),
         (250, 240),
     )?;
 
     // From and To values
     root.draw(&Rectangle::new(
         [(600, 120), (1100, 280)],
-        COLOR_GRAY_LIGHT.filled(),
+        theme.subtle.filled(),
     ))?;
 
     root.draw_text(
         &format!("{}: ${:.2}T", from_date, total_from / 1_000_000_000_000.0),
-        &TextStyle::from(("sans-serif", 20).into_font()),
+        &theme.text_style(20),
         (650, 160),
     )?;
 
     root.draw_text(
         &format!("{}: ${:.2}T", to_date, total_to / 1_000_000_000_000.0),
-        &TextStyle::from(("sans-serif", 20).into_font()),
+        &theme.text_style(20),
         (650, 200),
     )?;
 
     root.draw_text(
         &format!("Companies Analyzed: {}", records.len()),
-        &TextStyle::from(("sans-serif", 16).into_font()).color(&COLOR_SLATE),
+        &theme.text_style(16).color(&theme.muted),
         (650, 240),
     )?;
 
@@ -712,7 +792,7 @@ fn create_summary_dashboard(
 
     root.draw_text(
         "Market Movement Distribution",
-        &TextStyle::from(("sans-serif", 20).into_font()),
+        &theme.text_style(20),
         (180, 350),
     )?;
 
@@ -728,7 +808,7 @@ fn create_summary_dashboard(
         pie_radius,
         -90.0,
         gainers_angle,
-        COLOR_EMERALD,
+        theme.positive,
     )?;
     draw_pie_segment(
         &root,
@@ -736,7 +816,7 @@ fn create_summary_dashboard(
         pie_radius,
         -90.0 + gainers_angle,
         losers_angle,
-        COLOR_ROSE,
+        theme.negative,
     )?;
     draw_pie_segment(
         &root,
@@ -744,13 +824,13 @@ fn create_summary_dashboard(
         pie_radius,
         -90.0 + gainers_angle + losers_angle,
         360.0 - gainers_angle - losers_angle,
-        COLOR_SLATE,
+        theme.muted,
     )?;
 
     // Legend for pie chart
     root.draw(&Rectangle::new(
         [(500, 450), (520, 470)],
-        COLOR_EMERALD.filled(),
+        theme.positive.filled(),
     ))?;
     root.draw_text(
         &format!(
@@ -758,13 +838,13 @@ fn create_summary_dashboard(
             gainers,
             (gainers as f64 / total_companies as f64) * 100.0
         ),
-        &TextStyle::from(("sans-serif", 14).into_font()),
+        &theme.text_style(14),
         (530, 455),
     )?;
 
     root.draw(&Rectangle::new(
         [(500, 490), (520, 510)],
-        COLOR_ROSE.filled(),
+        theme.negative.filled(),
     ))?;
     root.draw_text(
         &format!(
@@ -772,13 +852,13 @@ fn create_summary_dashboard(
             losers,
             (losers as f64 / total_companies as f64) * 100.0
         ),
-        &TextStyle::from(("sans-serif", 14).into_font()),
+        &theme.text_style(14),
         (530, 495),
     )?;
 
     root.draw(&Rectangle::new(
         [(500, 530), (520, 550)],
-        COLOR_SLATE.filled(),
+        theme.muted.filled(),
     ))?;
     root.draw_text(
         &format!(
@@ -786,21 +866,17 @@ fn create_summary_dashboard(
             unchanged,
             (unchanged as f64 / total_companies as f64) * 100.0
         ),
-        &TextStyle::from(("sans-serif", 14).into_font()),
+        &theme.text_style(14),
         (530, 535),
     )?;
 
     // Key statistics box
     root.draw(&Rectangle::new(
         [(750, 400), (1100, 620)],
-        COLOR_GRAY_LIGHT.filled(),
+        theme.subtle.filled(),
     ))?;
 
-    root.draw_text(
-        "Key Statistics",
-        &TextStyle::from(("sans-serif", 20).into_font()),
-        (850, 420),
-    )?;
+    root.draw_text("Key Statistics", &theme.text_style(20), (850, 420))?;
 
     // Calculate average change (avoid division by zero)
     let avg_change: f64 = if records.is_empty() {
@@ -815,7 +891,7 @@ fn create_summary_dashboard(
 
     root.draw_text(
         &format!("Average Change: {:.2}%", avg_change),
-        &TextStyle::from(("sans-serif", 14).into_font()),
+        &theme.text_style(14),
         (780, 460),
     )?;
 
@@ -838,7 +914,7 @@ fn create_summary_dashboard(
         let name = truncate_string(&gainer.name, 20);
         root.draw_text(
             &format!("Top Gainer: {}", name),
-            &TextStyle::from(("sans-serif", 14).into_font()),
+            &theme.text_style(14),
             (780, 490),
         )?;
         root.draw_text(
@@ -846,7 +922,7 @@ fn create_summary_dashboard(
                 "  +{:.1}%",
                 parse_percentage(&gainer.percentage_change).unwrap_or(0.0)
             ),
-            &TextStyle::from(("sans-serif", 14).into_font()).color(&COLOR_EMERALD),
+            &theme.text_style(14).color(&theme.positive),
             (780, 510),
         )?;
     }
@@ -855,7 +931,7 @@ fn create_summary_dashboard(
         let name = truncate_string(&loser.name, 20);
         root.draw_text(
             &format!("Top Loser: {}", name),
-            &TextStyle::from(("sans-serif", 14).into_font()),
+            &theme.text_style(14),
             (780, 540),
         )?;
         root.draw_text(
@@ -863,24 +939,181 @@ fn create_summary_dashboard(
                 "  {:.1}%",
                 parse_percentage(&loser.percentage_change).unwrap_or(0.0)
             ),
-            &TextStyle::from(("sans-serif", 14).into_font()).color(&COLOR_ROSE),
+            &theme.text_style(14).color(&theme.negative),
             (780, 560),
         )?;
     }
 
     // Footer
     root.draw_text(
-        &format!(
-            "Generated on {}",
-            chrono::Local::now().format("%Y-%m-%d %H:%M:%S")
-        ),
-        &TextStyle::from(("sans-serif", 10).into_font()).color(&COLOR_SLATE),
+        &format!("Generated on {}", clock::now().format("%Y-%m-%d %H:%M:%S")),
+        &theme.text_style(10).color(&theme.muted),
         (450, 750),
     )?;
 
     root.present()?;
+    theme.apply_watermark(Path::new(&filename), root.dim_in_pixel())?;
     println!("✅ Generated summary dashboard: {}", filename);
 
+    Ok(PathBuf::from(&filename))
+}
+
+/// Create a pie chart of the market share per region on the end date.
+/// The comparison CSV has no exchange column, so regions follow the currency.
+fn create_region_pie_chart(
+    records: &[ComparisonRecord],
+    from_date: &str,
+    to_date: &str,
+    output: &OutputConfig,
+) -> Result<PathBuf> {
+    let theme = chart_theme::current();
+    let totals = regions::group_totals(records.iter().filter_map(|r| {
+        let share = parse_percentage(&r.market_share_to)?;
+        let region = regions::region_for("", r.currency.as_deref().unwrap_or_default());
+        Some((region.label().to_string(), None, Some(share)))
+    }));
+
+    if vega::enabled() {
+        let segments: Vec<(String, f64, RGBColor)> = totals
+            .iter()
+            .enumerate()
+            .map(|(i, total)| {
+                let color = theme.palette[i % theme.palette.len()];
+                (total.group.clone(), total.share, color)
+            })
+            .collect();
+        let spec = vega::share_arcs(
+            &format!("Market Share by Region: {}", to_date),
+            &segments,
+            false,
+        );
+        let stem = format!("comparison_{}_to_{}_regions", from_date, to_date);
+        return write_vega_chart(output, &stem, &spec, "region chart");
+    }
+
+    let filename = output
+        .named_path(&format!(
+            "comparison_{}_to_{}_regions.svg",
+            from_date, to_date
+        ))
+        .display()
+        .to_string();
+    let root = SVGBackend::new(&filename, (1000, 700)).into_drawing_area();
+    root.fill(&theme.background)?;
+
+    root.draw_text(
+        &format!("Market Share by Region: {}", to_date),
+        &theme.text_style(32),
+        (250, 30),
+    )?;
+
+    let center = (350, 380);
+    let mut start_angle = -90.0; // Start from top
+    for (i, total) in totals.iter().enumerate() {
+        let sweep_angle = total.share / 100.0 * 360.0;
+        draw_pie_segment(
+            &root,
+            center,
+            250.0,
+            start_angle,
+            sweep_angle,
+            theme.palette[i % theme.palette.len()],
+        )?;
+        start_angle += sweep_angle;
+    }
+
+    // Legend with share and number of companies
+    let legend_x = 680;
+    for (i, total) in totals.iter().enumerate() {
+        let y = 200 + (i as i32) * 50;
+        root.draw(&Rectangle::new(
+            [(legend_x, y), (legend_x + 20, y + 20)],
+            theme.palette[i % theme.palette.len()].filled(),
+        ))?;
+        root.draw_text(
+            &format!("{} ({:.1}%)", total.group, total.share),
+            &theme.text_style(16),
+            (legend_x + 30, y + 2),
+        )?;
+        root.draw_text(
+            &format!("{} companies", total.companies),
+            &theme.text_style(12).color(&theme.muted),
+            (legend_x + 30, y + 22),
+        )?;
+    }
+
+    root.present()?;
+    theme.apply_watermark(Path::new(&filename), root.dim_in_pixel())?;
+    println!("✅ Generated region chart: {}", filename);
+
+    Ok(PathBuf::from(&filename))
+}
+
+/// Ranked bar chart of the market share per headquarters country (`geo-report`)
+pub fn create_country_chart(totals: &[GroupTotal], date: &str, path: &Path) -> Result<()> {
+    let theme = chart_theme::current();
+    if vega::enabled() {
+        let rows: Vec<(String, f64, usize)> = totals
+            .iter()
+            .map(|t| (t.group.clone(), t.share, t.companies))
+            .collect();
+        let spec = vega::share_bars(
+            &format!("Market Cap by Headquarters Country: {}", date),
+            &rows,
+            theme.primary,
+        );
+        return vega::write_spec(path, &spec);
+    }
+    let row_height = 36;
+    let height = 140 + row_height * totals.len().max(1) as u32;
+    let root = SVGBackend::new(path, (1000, height)).into_drawing_area();
+    root.fill(&theme.background)?;
+
+    root.draw_text(
+        &format!("Market Cap by Headquarters Country: {}", date),
+        &theme.text_style(28),
+        (40, 30),
+    )?;
+
+    // Bars are scaled to the largest share so the ranking stays readable
+    let max_share = totals.iter().map(|t| t.share).fold(0.0, f64::max);
+    let (bar_x, bar_width) = (260, 560.0);
+    for (i, total) in totals.iter().enumerate() {
+        let y = 90 + (i as i32) * row_height as i32;
+        let width = if max_share > 0.0 {
+            (total.share / max_share * bar_width) as i32
+        } else {
+            0
+        };
+        root.draw_text(
+            &truncate_string(&geo::country_label(&total.group), 28),
+            &theme.text_style(15),
+            (40, y + 6),
+        )?;
+        let color = if total.group == geo::UNKNOWN_COUNTRY {
+            theme.muted
+        } else {
+            theme.palette[i % theme.palette.len()]
+        };
+        root.draw(&Rectangle::new(
+            [(bar_x, y), (bar_x + width.max(1), y + 24)],
+            color.filled(),
+        ))?;
+        root.draw_text(
+            &format!("{:.1}% · {} companies", total.share, total.companies),
+            &theme.text_style(13).color(&theme.muted),
+            (bar_x + width + 10, y + 6),
+        )?;
+    }
+
+    root.draw_text(
+        &format!("Generated on {}", clock::now().format("%Y-%m-%d %H:%M:%S")),
+        &theme.text_style(10).color(&theme.muted),
+        (40, height as i32 - 25),
+    )?;
+
+    root.present()?;
+    theme.apply_watermark(path, root.dim_in_pixel())?;
     Ok(())
 }
 
@@ -911,6 +1144,857 @@ fn draw_pie_segment(
     Ok(())
 }
 
+/// Waterfall from the total market cap on the from-date, through the largest
+/// contributors and "Other", to the total on the to-date. The value axis
+/// starts just below the lowest level so that the steps stay visible.
+pub fn create_waterfall_chart(
+    attribution: &ChangeAttribution,
+    from_date: &str,
+    to_date: &str,
+    path: &Path,
+) -> Result<()> {
+    let theme = chart_theme::current();
+    // (label, start level, end level, color); totals are drawn from the axis floor
+    let mut steps: Vec<(String, f64, f64, RGBColor)> = Vec::new();
+    steps.push((
+        from_date.to_string(),
+        f64::NAN,
+        attribution.total_from,
+        theme.primary,
+    ));
+    let mut level = attribution.total_from;
+    let contributions = attribution
+        .top
+        .iter()
+        .map(|c| (c.ticker.clone(), c.change_usd))
+        .chain(std::iter::once(("Other".to_string(), attribution.other)));
+    for (label, change) in contributions {
+        let color = if label == "Other" {
+            theme.muted
+        } else if change >= 0.0 {
+            theme.positive
+        } else {
+            theme.negative
+        };
+        steps.push((label, level, level + change, color));
+        level += change;
+    }
+    steps.push((
+        to_date.to_string(),
+        f64::NAN,
+        attribution.total_to,
+        theme.primary,
+    ));
+
+    let levels = steps
+        .iter()
+        .flat_map(|(_, start, end, _)| [*start, *end])
+        .filter(|v| v.is_finite());
+    let (low, high) = levels.fold((f64::MAX, f64::MIN), |(lo, hi), v| (lo.min(v), hi.max(v)));
+    let padding = ((high - low) * 0.15).max(high.abs() * 0.01).max(1.0);
+    let (y_min, y_max) = ((low - padding).max(0.0), high + padding);
+
+    if vega::enabled() {
+        let spec = vega::waterfall(
+            &format!("Market Cap Change: {} to {}", from_date, to_date),
+            &steps,
+            y_min,
+        );
+        return vega::write_spec(path, &spec);
+    }
+
+    let (width, height) = (1200u32, 700u32);
+    let (plot_left, plot_right, plot_top, plot_bottom) = (90.0, 1170.0, 110.0, 590.0);
+    let to_y =
+        |v: f64| (plot_bottom - (v - y_min) / (y_max - y_min) * (plot_bottom - plot_top)) as i32;
+
+    let root = SVGBackend::new(path, (width, height)).into_drawing_area();
+    root.fill(&theme.background)?;
+    root.draw_text(
+        &format!("Market Cap Change: {} to {}", from_date, to_date),
+        &theme.text_style(28),
+        (40, 30),
+    )?;
+    let change = attribution.total_to - attribution.total_from;
+    let change_pct = if attribution.total_from > 0.0 {
+        change / attribution.total_from * 100.0
+    } else {
+        0.0
+    };
+    root.draw_text(
+        &format!(
+            "{} ({:+.2}%) · largest contributors in USD · axis starts at {}",
+            utils::format_usd(change),
+            change_pct,
+            utils::format_usd(y_min)
+        ),
+        &theme.text_style(14).color(&theme.muted),
+        (40, 68),
+    )?;
+
+    // Axis floor
+    root.draw(&PathElement::new(
+        vec![
+            (plot_left as i32, plot_bottom as i32),
+            (plot_right as i32, plot_bottom as i32),
+        ],
+        theme.muted.stroke_width(1),
+    ))?;
+
+    let slot = (plot_right - plot_left) / steps.len() as f64;
+    let bar_width = slot * 0.6;
+    let mut previous_top: Option<(i32, i32)> = None;
+    for (i, (label, start, end, color)) in steps.iter().enumerate() {
+        let x0 = (plot_left + slot * i as f64 + (slot - bar_width) / 2.0) as i32;
+        let x1 = x0 + bar_width as i32;
+        let is_total = start.is_nan();
+        let (y_start, y_end) = if is_total {
+            (to_y(y_min), to_y(*end))
+        } else {
+            (to_y(*start), to_y(*end))
+        };
+        // Keep zero-height steps visible as a line
+        let (top, bottom) = (
+            y_start.min(y_end),
+            y_start.max(y_end).max(y_start.min(y_end) + 1),
+        );
+        root.draw(&Rectangle::new([(x0, top), (x1, bottom)], color.filled()))?;
+
+        // Connector from the previous step's end level
+        if let Some((prev_x, prev_y)) = previous_top {
+            root.draw(&PathElement::new(
+                vec![(prev_x, prev_y), (x0, prev_y)],
+                theme.muted.stroke_width(1),
+            ))?;
+        }
+        previous_top = Some((x1, y_end));
+
+        let value_label = if is_total {
+            utils::format_usd(*end)
+        } else {
+            let change = end - start;
+            format!(
+                "{}{}",
+                if change >= 0.0 { "+" } else { "" },
+                utils::format_usd(change)
+            )
+        };
+        root.draw_text(&value_label, &theme.text_style(12), (x0, top - 18))?;
+        root.draw_text(
+            &truncate_string(label, 12),
+            &theme.text_style(13).color(&theme.muted),
+            (x0, plot_bottom as i32 + 12),
+        )?;
+    }
+
+    root.present()?;
+    theme.apply_watermark(path, root.dim_in_pixel())?;
+    Ok(())
+}
+
+/// Companies drawn in the trend bump chart
+pub const BUMP_CHART_COMPANIES: usize = 20;
+
+/// Rank of a trend on the last date, if it was ranked then
+fn last_rank(trend: &TickerTrend) -> Option<usize> {
+    trend.data_points.last()?.rank
+}
+
+/// Bump chart of rank evolution: one line per company of the top 20 on the
+/// last date, dates on the x-axis and rank 1 at the top. Lines break on dates
+/// where a company has no rank.
+pub fn create_bump_chart(trends: &[TickerTrend], dates: &[String], path: &Path) -> Result<()> {
+    let theme = chart_theme::current();
+    let mut shown: Vec<(&TickerTrend, usize)> = trends
+        .iter()
+        .filter_map(|t| Some((t, last_rank(t)?)))
+        .collect();
+    shown.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.ticker.cmp(&b.0.ticker)));
+    shown.truncate(BUMP_CHART_COMPANIES);
+
+    // Earlier ranks of the shown companies can be below the top 20
+    let max_rank = shown
+        .iter()
+        .flat_map(|(t, _)| t.data_points.iter().filter_map(|p| p.rank))
+        .max()
+        .unwrap_or(1)
+        .max(2);
+
+    let (width, height) = (1200u32, 120 + 30 * max_rank.min(40) as u32 + 80);
+    let (plot_left, plot_right) = (160.0, width as f64 - 200.0);
+    let (plot_top, plot_bottom) = (100.0, height as f64 - 70.0);
+    let x_of = |i: usize| {
+        if dates.len() > 1 {
+            (plot_left + (plot_right - plot_left) * i as f64 / (dates.len() - 1) as f64) as i32
+        } else {
+            ((plot_left + plot_right) / 2.0) as i32
+        }
+    };
+    let y_of = |rank: usize| {
+        (plot_top + (plot_bottom - plot_top) * (rank - 1) as f64 / (max_rank - 1) as f64) as i32
+    };
+
+    if vega::enabled() {
+        let points: Vec<(String, String, String, usize)> = shown
+            .iter()
+            .flat_map(|(t, _)| {
+                t.data_points.iter().filter_map(|p| {
+                    Some((t.ticker.clone(), t.name.clone(), p.date.clone(), p.rank?))
+                })
+            })
+            .collect();
+        let spec = vega::bump(
+            &format!(
+                "Rank Evolution: {} to {}",
+                dates.first().map(String::as_str).unwrap_or_default(),
+                dates.last().map(String::as_str).unwrap_or_default()
+            ),
+            &points,
+        );
+        return vega::write_spec(path, &spec);
+    }
+
+    let root = SVGBackend::new(path, (width, height)).into_drawing_area();
+    root.fill(&theme.background)?;
+    root.draw_text(
+        &format!(
+            "Rank Evolution: {} to {}",
+            dates.first().map(String::as_str).unwrap_or_default(),
+            dates.last().map(String::as_str).unwrap_or_default()
+        ),
+        &theme.text_style(28),
+        (40, 30),
+    )?;
+    root.draw_text(
+        &format!(
+            "Top {} companies on the last date · rank 1 at the top",
+            shown.len()
+        ),
+        &theme.text_style(14).color(&theme.muted),
+        (40, 66),
+    )?;
+
+    // Date gridlines and labels
+    for (i, date) in dates.iter().enumerate() {
+        let x = x_of(i);
+        root.draw(&PathElement::new(
+            vec![(x, plot_top as i32 - 10), (x, plot_bottom as i32 + 10)],
+            theme.subtle.stroke_width(1),
+        ))?;
+        root.draw_text(
+            date,
+            &theme.text_style(12).color(&theme.muted),
+            (x - 32, plot_bottom as i32 + 20),
+        )?;
+    }
+
+    for (line, (trend, rank)) in shown.iter().enumerate() {
+        let color = theme.palette[line % theme.palette.len()];
+        let points: Vec<Option<(i32, i32)>> = trend
+            .data_points
+            .iter()
+            .enumerate()
+            .map(|(i, p)| p.rank.map(|r| (x_of(i), y_of(r))))
+            .collect();
+
+        // Consecutive ranked dates form a segment
+        for pair in points.windows(2) {
+            if let [Some(a), Some(b)] = pair {
+                root.draw(&PathElement::new(vec![*a, *b], color.stroke_width(3)))?;
+            }
+        }
+        for point in points.iter().flatten() {
+            root.draw(&Circle::new(*point, 5, color.filled()))?;
+        }
+
+        if let Some((x, y)) = points.iter().flatten().next() {
+            root.draw_text(
+                &truncate_string(&trend.ticker, 14),
+                &theme.text_style(12).color(&color),
+                (x - 120, y - 6),
+            )?;
+        }
+        if let Some((x, y)) = points.iter().flatten().last() {
+            root.draw_text(
+                &format!("#{} {}", rank, truncate_string(&trend.name, 18)),
+                &theme.text_style(12).color(&color),
+                (x + 12, y - 6),
+            )?;
+        }
+    }
+
+    root.present()?;
+    theme.apply_watermark(path, root.dim_in_pixel())?;
+    Ok(())
+}
+
+/// Sparkline chart: one row per series with its values per x label (e.g. a
+/// window end date), a dashed zero line and the last value on the right,
+/// colored by sign. Each row has its own scale, so the shape of a series
+/// shows, not its size against the others. Gaps break the line.
+pub fn create_sparkline_chart(
+    title: &str,
+    subtitle: &str,
+    labels: &[String],
+    rows: &[(String, Vec<Option<f64>>)],
+    path: &Path,
+) -> Result<()> {
+    if vega::enabled() {
+        let points: Vec<(String, String, f64)> = rows
+            .iter()
+            .flat_map(|(name, values)| {
+                labels
+                    .iter()
+                    .zip(values)
+                    .filter_map(|(label, value)| Some((name.clone(), label.clone(), (*value)?)))
+            })
+            .collect();
+        return vega::write_spec(path, &vega::sparklines(title, &points));
+    }
+
+    let theme = chart_theme::current();
+    let row_height = 56u32;
+    let (width, height) = (900u32, 130 + row_height * rows.len() as u32 + 50);
+    let (line_left, line_right) = (240.0, width as f64 - 160.0);
+    let x_of = |i: usize| {
+        if labels.len() > 1 {
+            (line_left + (line_right - line_left) * i as f64 / (labels.len() - 1) as f64) as i32
+        } else {
+            ((line_left + line_right) / 2.0) as i32
+        }
+    };
+
+    let root = SVGBackend::new(path, (width, height)).into_drawing_area();
+    root.fill(&theme.background)?;
+    root.draw_text(title, &theme.text_style(26), (40, 30))?;
+    root.draw_text(
+        subtitle,
+        &theme.text_style(14).color(&theme.muted),
+        (40, 66),
+    )?;
+
+    for (row, (name, values)) in rows.iter().enumerate() {
+        let top = 110.0 + (row_height * row as u32) as f64;
+        let (band_top, band_bottom) = (top + 8.0, top + row_height as f64 - 12.0);
+        // The zero line stays inside the band
+        let (low, high) = values
+            .iter()
+            .flatten()
+            .fold((0.0f64, 0.0f64), |(low, high), v| {
+                (low.min(*v), high.max(*v))
+            });
+        let span = if high > low { high - low } else { 1.0 };
+        let y_of =
+            |v: f64| (band_bottom - (band_bottom - band_top) * (v - low) / span).round() as i32;
+
+        root.draw_text(
+            &truncate_string(name, 24),
+            &theme.text_style(15),
+            (40, (top + row_height as f64 / 2.0) as i32 - 10),
+        )?;
+        let zero = y_of(0.0);
+        for x in (line_left as i32..line_right as i32).step_by(8) {
+            root.draw(&PathElement::new(
+                vec![(x, zero), ((x + 4).min(line_right as i32), zero)],
+                theme.subtle.stroke_width(1),
+            ))?;
+        }
+
+        let points: Vec<Option<(i32, i32)>> = values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| v.map(|v| (x_of(i), y_of(v))))
+            .collect();
+        for pair in points.windows(2) {
+            if let [Some(a), Some(b)] = pair {
+                root.draw(&PathElement::new(
+                    vec![*a, *b],
+                    theme.primary.stroke_width(2),
+                ))?;
+            }
+        }
+        for point in points.iter().flatten() {
+            root.draw(&Circle::new(*point, 3, theme.primary.filled()))?;
+        }
+
+        if let Some(last) = values.last().copied().flatten() {
+            let color = if last >= 0.0 {
+                theme.positive
+            } else {
+                theme.negative
+            };
+            root.draw_text(
+                &format!("{:+.1}", last),
+                &theme.text_style(15).color(&color),
+                (
+                    line_right as i32 + 24,
+                    (top + row_height as f64 / 2.0) as i32 - 10,
+                ),
+            )?;
+        }
+    }
+
+    // Labels of the first and last point under the rows
+    let bottom = (110 + row_height * rows.len() as u32 + 10) as i32;
+    if let Some(first) = labels.first() {
+        root.draw_text(
+            first,
+            &theme.text_style(12).color(&theme.muted),
+            (x_of(0) - 32, bottom),
+        )?;
+    }
+    if labels.len() > 1
+        && let Some(last) = labels.last()
+    {
+        root.draw_text(
+            last,
+            &theme.text_style(12).color(&theme.muted),
+            (x_of(labels.len() - 1) - 32, bottom),
+        )?;
+    }
+
+    root.present()?;
+    theme.apply_watermark(path, root.dim_in_pixel())?;
+    Ok(())
+}
+
+/// One company in a treemap: tile size is the market cap, color the change
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreemapItem {
+    pub ticker: String,
+    pub name: String,
+    pub market_cap: f64,
+    pub change_pct: Option<f64>,
+}
+
+/// Rectangle of a treemap layout, in pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct TreemapRect {
+    x: f64,
+    y: f64,
+    w: f64,
+    h: f64,
+}
+
+/// Largest aspect ratio of a row of areas laid along a side of length `side`
+fn worst_aspect(row: &[f64], side: f64) -> f64 {
+    let sum: f64 = row.iter().sum();
+    let max = row.iter().cloned().fold(f64::MIN, f64::max);
+    let min = row.iter().cloned().fold(f64::MAX, f64::min);
+    let side2 = side * side;
+    let sum2 = sum * sum;
+    (side2 * max / sum2).max(sum2 / (side2 * min))
+}
+
+/// Squarified treemap layout (Bruls, Huizing & van Wijk): one rectangle per
+/// value, in the same order, with areas proportional to the values. Values
+/// should be sorted largest first for the squarest tiles.
+fn squarify(values: &[f64], rect: TreemapRect) -> Vec<TreemapRect> {
+    let total: f64 = values.iter().sum();
+    if values.is_empty() || total <= 0.0 || rect.w <= 0.0 || rect.h <= 0.0 {
+        return vec![
+            TreemapRect {
+                w: 0.0,
+                h: 0.0,
+                ..rect
+            };
+            values.len()
+        ];
+    }
+    let scale = rect.w * rect.h / total;
+    let areas: Vec<f64> = values.iter().map(|v| v.max(0.0) * scale).collect();
+
+    let mut result = Vec::with_capacity(areas.len());
+    let mut free = rect;
+    let mut start = 0;
+    while start < areas.len() {
+        let side = free.w.min(free.h);
+        let mut end = start + 1;
+        while end < areas.len()
+            && worst_aspect(&areas[start..=end], side) <= worst_aspect(&areas[start..end], side)
+        {
+            end += 1;
+        }
+
+        // Lay the row along the shorter side, then shrink the free space
+        let row_sum: f64 = areas[start..end].iter().sum();
+        if free.w >= free.h {
+            let width = if free.h > 0.0 { row_sum / free.h } else { 0.0 };
+            let mut y = free.y;
+            for area in &areas[start..end] {
+                let h = if width > 0.0 { area / width } else { 0.0 };
+                result.push(TreemapRect {
+                    x: free.x,
+                    y,
+                    w: width,
+                    h,
+                });
+                y += h;
+            }
+            free.x += width;
+            free.w = (free.w - width).max(0.0);
+        } else {
+            let height = if free.w > 0.0 { row_sum / free.w } else { 0.0 };
+            let mut x = free.x;
+            for area in &areas[start..end] {
+                let w = if height > 0.0 { area / height } else { 0.0 };
+                result.push(TreemapRect {
+                    x,
+                    y: free.y,
+                    w,
+                    h: height,
+                });
+                x += w;
+            }
+            free.y += height;
+            free.h = (free.h - height).max(0.0);
+        }
+        start = end;
+    }
+    result
+}
+
+/// Tile color for a percentage change: the muted color at 0%, fading to the
+/// positive color for gains and the negative one for losses, saturated at ±10%
+fn change_color(theme: &ChartTheme, change_pct: Option<f64>) -> RGBColor {
+    let Some(pct) = change_pct else {
+        return theme.subtle;
+    };
+    let t = (pct / 10.0).clamp(-1.0, 1.0);
+    let target = if t >= 0.0 {
+        theme.positive
+    } else {
+        theme.negative
+    };
+    let mix = |from: u8, to: u8| (from as f64 + (to as f64 - from as f64) * t.abs()).round() as u8;
+    RGBColor(
+        mix(theme.muted.0, target.0),
+        mix(theme.muted.1, target.1),
+        mix(theme.muted.2, target.2),
+    )
+}
+
+/// Group companies by their first predefined peer group (`Other` if none),
+/// largest group first and largest company first within a group
+fn treemap_groups(items: &[TreemapItem]) -> Vec<(String, Vec<TreemapItem>)> {
+    let peer_groups = get_predefined_peer_groups();
+    let mut groups: Vec<(String, Vec<TreemapItem>)> = Vec::new();
+    for item in items.iter().filter(|i| i.market_cap > 0.0) {
+        let group = peer_groups
+            .iter()
+            .find(|g| g.tickers.contains(&item.ticker))
+            .map(|g| g.name.clone())
+            .unwrap_or_else(|| "Other".to_string());
+        match groups.iter_mut().find(|(name, _)| *name == group) {
+            Some((_, members)) => members.push(item.clone()),
+            None => groups.push((group, vec![item.clone()])),
+        }
+    }
+    for (_, members) in &mut groups {
+        members.sort_by(|a, b| {
+            rankings::rank_order(
+                (Some(a.market_cap), &a.name, &a.ticker),
+                (Some(b.market_cap), &b.name, &b.ticker),
+            )
+        });
+    }
+    let total = |members: &[TreemapItem]| members.iter().map(|m| m.market_cap).sum::<f64>();
+    groups.sort_by(|a, b| {
+        rankings::rank_order(
+            (Some(total(&a.1)), &a.0, &a.0),
+            (Some(total(&b.1)), &b.0, &b.0),
+        )
+    });
+    groups
+}
+
+/// Height of the band with a group's name above its tiles
+const TREEMAP_HEADER: f64 = 20.0;
+
+/// One peer group of the treemap with the tiles of its members
+struct TreemapGroup {
+    name: String,
+    rect: TreemapRect,
+    header: bool,
+    tiles: Vec<(TreemapItem, TreemapRect)>,
+}
+
+/// Lay out the treemap below the title: groups first, then their members
+/// inside each group's rect, below the header band when there is one
+fn treemap_layout(items: &[TreemapItem], width: u32, height: u32) -> Vec<TreemapGroup> {
+    let groups = treemap_groups(items);
+    let area = TreemapRect {
+        x: 20.0,
+        y: 80.0,
+        w: width as f64 - 40.0,
+        h: height as f64 - 100.0,
+    };
+    let group_totals: Vec<f64> = groups
+        .iter()
+        .map(|(_, members)| members.iter().map(|m| m.market_cap).sum())
+        .collect();
+    let group_rects = squarify(&group_totals, area);
+
+    groups
+        .into_iter()
+        .zip(group_rects)
+        .map(|((name, members), rect)| {
+            let header = rect.h > TREEMAP_HEADER * 2.0 && rect.w > 60.0;
+            let body = if header {
+                TreemapRect {
+                    y: rect.y + TREEMAP_HEADER,
+                    h: rect.h - TREEMAP_HEADER,
+                    ..rect
+                }
+            } else {
+                rect
+            };
+            let caps: Vec<f64> = members.iter().map(|m| m.market_cap).collect();
+            let tiles = members.into_iter().zip(squarify(&caps, body)).collect();
+            TreemapGroup {
+                name,
+                rect,
+                header,
+                tiles,
+            }
+        })
+        .collect()
+}
+
+/// Treemap ("market map") of market caps grouped by peer group, tiles colored
+/// by percentage change
+pub fn create_treemap_chart(items: &[TreemapItem], title: &str, path: &Path) -> Result<()> {
+    let theme = chart_theme::current();
+    let (width, height) = (1400u32, 900u32);
+    if vega::enabled() {
+        let tiles: Vec<vega::Tile> = treemap_layout(items, width, height)
+            .into_iter()
+            .flat_map(|group| {
+                let name = group.name;
+                group
+                    .tiles
+                    .into_iter()
+                    .map(move |(member, tile)| vega::Tile {
+                        group: name.clone(),
+                        ticker: member.ticker,
+                        name: member.name,
+                        market_cap: member.market_cap,
+                        change_pct: member.change_pct,
+                        x: tile.x,
+                        y: tile.y,
+                        w: tile.w,
+                        h: tile.h,
+                    })
+            })
+            .collect();
+        return vega::write_spec(path, &vega::treemap(title, &tiles, (width, height)));
+    }
+    let root = SVGBackend::new(path, (width, height)).into_drawing_area();
+    root.fill(&theme.background)?;
+
+    root.draw_text(title, &theme.text_style(28), (20, 20))?;
+    root.draw_text(
+        "Tile size: market cap (USD) · color: change (green up, red down, ±10% saturated)",
+        &theme.text_style(13).color(&theme.muted),
+        (20, 55),
+    )?;
+
+    for group in treemap_layout(items, width, height) {
+        let rect = group.rect;
+        // Group label in a header band, when the group is tall enough for one
+        if group.header {
+            root.draw(&Rectangle::new(
+                [
+                    (rect.x as i32, rect.y as i32),
+                    ((rect.x + rect.w) as i32, (rect.y + TREEMAP_HEADER) as i32),
+                ],
+                theme.muted.filled(),
+            ))?;
+            root.draw_text(
+                &truncate_string(&group.name, (rect.w / 8.0) as usize),
+                &theme.text_style(13).color(&theme.on_accent),
+                (rect.x as i32 + 4, rect.y as i32 + 3),
+            )?;
+        }
+
+        for (member, tile) in &group.tiles {
+            let (x0, y0) = (tile.x as i32, tile.y as i32);
+            let (x1, y1) = ((tile.x + tile.w) as i32, (tile.y + tile.h) as i32);
+            root.draw(&Rectangle::new(
+                [(x0, y0), (x1, y1)],
+                change_color(theme, member.change_pct).filled(),
+            ))?;
+            root.draw(&Rectangle::new(
+                [(x0, y0), (x1, y1)],
+                theme.background.stroke_width(1),
+            ))?;
+
+            if tile.w >= 50.0 && tile.h >= 30.0 {
+                root.draw_text(
+                    &truncate_string(&member.name, (tile.w / 9.0) as usize),
+                    &theme.text_style(14).color(&theme.on_accent),
+                    (x0 + 4, y0 + 4),
+                )?;
+                if let Some(pct) = member.change_pct {
+                    root.draw_text(
+                        &format!("{:+.1}%", pct),
+                        &theme.text_style(12).color(&theme.on_accent),
+                        (x0 + 4, y0 + 20),
+                    )?;
+                }
+            }
+        }
+        root.draw(&Rectangle::new(
+            [
+                (rect.x as i32, rect.y as i32),
+                ((rect.x + rect.w) as i32, (rect.y + rect.h) as i32),
+            ],
+            theme.background.stroke_width(3),
+        ))?;
+    }
+
+    root.present()?;
+    theme.apply_watermark(path, root.dim_in_pixel())?;
+    Ok(())
+}
+
+/// Treemap of a comparison: market cap on the to-date, colored by the change
+fn create_comparison_treemap(
+    records: &[ComparisonRecord],
+    from_date: &str,
+    to_date: &str,
+    output: &OutputConfig,
+) -> Result<PathBuf> {
+    let items: Vec<TreemapItem> = records
+        .iter()
+        .filter_map(|r| {
+            Some(TreemapItem {
+                ticker: r.ticker.clone(),
+                name: r.name.clone(),
+                market_cap: parse_usd_amount(&r.market_cap_to)?,
+                change_pct: parse_percentage(&r.percentage_change),
+            })
+        })
+        .collect();
+
+    let path = output.named_path(&format!(
+        "comparison_{}_to_{}_treemap.{}",
+        from_date,
+        to_date,
+        vega::extension()
+    ));
+    create_treemap_chart(
+        &items,
+        &format!("Market Map: {} to {}", from_date, to_date),
+        &path,
+    )?;
+    println!("✅ Generated treemap: {}", path.display());
+    Ok(path)
+}
+
+/// `treemap [--date]`: treemap of a stored snapshot, colored by the change
+/// since the previous stored snapshot
+pub async fn snapshot_treemap(pool: &SqlitePool, date: Option<&str>) -> Result<()> {
+    let Some(timestamp) = geo::snapshot_timestamp(pool, date).await? else {
+        println!("No market cap snapshots stored yet.");
+        return Ok(());
+    };
+    let listings = geo::load_listings(pool, timestamp).await?;
+    if listings.is_empty() {
+        anyhow::bail!(
+            "No market caps stored for {}; fetch them with fetch-specific-date-market-caps",
+            date.unwrap_or("the latest snapshot")
+        );
+    }
+    let previous = geo::previous_snapshot_timestamp(pool, timestamp).await?;
+    let previous_caps: HashMap<String, f64> = match previous {
+        Some(previous) => geo::load_listings(pool, previous)
+            .await?
+            .into_iter()
+            .filter_map(|l| Some((l.ticker, l.market_cap_usd?)))
+            .collect(),
+        None => HashMap::new(),
+    };
+
+    let items: Vec<TreemapItem> = listings
+        .into_iter()
+        .filter_map(|l| {
+            let market_cap = l.market_cap_usd?;
+            let change_pct = previous_caps
+                .get(&l.ticker)
+                .filter(|from| **from > 0.0)
+                .map(|from| (market_cap - from) / from * 100.0);
+            Some(TreemapItem {
+                ticker: l.ticker,
+                name: l.name,
+                market_cap,
+                change_pct,
+            })
+        })
+        .collect();
+
+    let date = DateTime::from_timestamp(timestamp, 0)
+        .map(|dt| dt.format("%Y-%m-%d").to_string())
+        .unwrap_or_default();
+    let title = match previous.and_then(|p| DateTime::from_timestamp(p, 0)) {
+        Some(previous) => format!(
+            "Market Map: {} (change since {})",
+            date,
+            previous.format("%Y-%m-%d")
+        ),
+        None => format!("Market Map: {}", date),
+    };
+
+    let output = config::load_output_config();
+    output.ensure_directory()?;
+    let path = output.file_path("treemap", &date, vega::extension());
+    create_treemap_chart(&items, &title, &path)?;
+    println!("✅ Generated treemap: {}", path.display());
+    Ok(())
+}
+
+/// Write every chart of a comparison CSV, returning each chart's kind (the
+/// file name suffix, e.g. `gainers_losers`) and path in generation order
+pub fn write_comparison_charts(
+    csv_path: &Path,
+    from_date: &str,
+    to_date: &str,
+    output: &OutputConfig,
+) -> Result<Vec<(&'static str, PathBuf)>> {
+    let records = read_comparison_data(&csv_path.display().to_string())?;
+    println!("Loaded {} companies for visualization", records.len());
+
+    // Generate each chart type
+    println!("\nGenerating charts...");
+
+    Ok(vec![
+        (
+            "gainers_losers",
+            create_gainers_losers_chart(&records, from_date, to_date, output)?,
+        ),
+        (
+            "market_distribution",
+            create_market_distribution_chart(&records, from_date, to_date, output)?,
+        ),
+        (
+            "rank_movements",
+            create_rank_movement_chart(&records, from_date, to_date, output)?,
+        ),
+        (
+            "summary_dashboard",
+            create_summary_dashboard(&records, from_date, to_date, output)?,
+        ),
+        (
+            "regions",
+            create_region_pie_chart(&records, from_date, to_date, output)?,
+        ),
+        (
+            "treemap",
+            create_comparison_treemap(&records, from_date, to_date, output)?,
+        ),
+    ])
+}
+
 /// Main function to generate all charts
 pub async fn generate_all_charts(from_date: &str, to_date: &str) -> Result<()> {
     println!(
@@ -919,19 +2003,11 @@ pub async fn generate_all_charts(from_date: &str, to_date: &str) -> Result<()> {
     );
 
     // Find and read the comparison CSV
-    let csv_path = find_comparison_csv(from_date, to_date)?;
+    let output = config::load_output_config();
+    let csv_path = find_comparison_csv(from_date, to_date, &output)?;
     println!("Reading data from: {}", csv_path);
 
-    let records = read_comparison_data(&csv_path)?;
-    println!("Loaded {} companies for visualization", records.len());
-
-    // Generate each chart type
-    println!("\nGenerating charts...");
-
-    create_gainers_losers_chart(&records, from_date, to_date)?;
-    create_market_distribution_chart(&records, from_date, to_date)?;
-    create_rank_movement_chart(&records, from_date, to_date)?;
-    create_summary_dashboard(&records, from_date, to_date)?;
+    write_comparison_charts(Path::new(&csv_path), from_date, to_date, &output)?;
 
     println!("\n✅ All charts generated successfully!");
 
@@ -941,6 +2017,9 @@ pub async fn generate_all_charts(from_date: &str, to_date: &str) -> Result<()> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::chart_theme::{
+        CHART_COLORS, COLOR_BLUE, COLOR_EMERALD, COLOR_GRAY_LIGHT, COLOR_ROSE, COLOR_SLATE,
+    };
 
     // Tests for parse_percentage
     #[test]
@@ -1117,6 +2196,288 @@ NEWCO,New Company,,1000000000,,,,100,NA,,"#;
         assert_eq!(record.rank_to, Some("100".to_string()));
     }
 
+    #[test]
+    fn test_region_pie_chart() {
+        let csv_data = r#"Ticker,Name,Currency,Market Share To (%)
+AAPL,Apple Inc.,USD,60.0
+MC.PA,LVMH,EUR,30.0
+7203.T,Toyota,JPY,10.0"#;
+        let records: Vec<ComparisonRecord> = csv::Reader::from_reader(csv_data.as_bytes())
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(records[1].currency.as_deref(), Some("EUR"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let output = OutputConfig {
+            directory: dir.path().display().to_string(),
+            ..OutputConfig::default()
+        };
+        create_region_pie_chart(&records, "2025-01-31", "2025-02-28", &output).unwrap();
+        let svg = std::fs::read_to_string(
+            dir.path()
+                .join("comparison_2025-01-31_to_2025-02-28_regions.svg"),
+        )
+        .unwrap();
+        assert!(svg.contains("US (60.0%)"));
+        assert!(svg.contains("Asia (10.0%)"));
+    }
+
+    #[test]
+    fn test_market_distribution_legend_embeds_cached_logos() {
+        let csv_data = r#"Ticker,Name,Market Cap To (USD)
+NKE,Nike,100000000000
+MC.PA,LVMH,300000000000"#;
+        let records: Vec<ComparisonRecord> = csv::Reader::from_reader(csv_data.as_bytes())
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let output = OutputConfig {
+            directory: dir.path().display().to_string(),
+            ..OutputConfig::default()
+        };
+        let logo_dir = logos::logo_dir(&output);
+        std::fs::create_dir_all(&logo_dir).unwrap();
+        std::fs::write(logo_dir.join("MC.PA.png"), b"png").unwrap();
+
+        create_market_distribution_chart(&records, "2025-01-31", "2025-02-28", &output).unwrap();
+        let svg = std::fs::read_to_string(
+            dir.path()
+                .join("comparison_2025-01-31_to_2025-02-28_market_distribution.svg"),
+        )
+        .unwrap();
+        // Only LVMH has a cached logo; it is first in the legend
+        assert_eq!(svg.matches("<image").count(), 1);
+        assert!(svg.contains(r#"<image x="724" y="150" width="20" height="20""#));
+        assert!(svg.trim_end().ends_with("</svg>"));
+    }
+
+    #[test]
+    fn test_squarify_areas_are_proportional_and_fill_the_rect() {
+        let rect = TreemapRect {
+            x: 10.0,
+            y: 20.0,
+            w: 600.0,
+            h: 400.0,
+        };
+        let values = [6.0, 6.0, 4.0, 3.0, 2.0, 2.0, 1.0];
+        let tiles = squarify(&values, rect);
+        assert_eq!(tiles.len(), values.len());
+
+        let total: f64 = values.iter().sum();
+        for (value, tile) in values.iter().zip(&tiles) {
+            let expected = value / total * rect.w * rect.h;
+            assert!((tile.w * tile.h - expected).abs() < 1e-6);
+            assert!(tile.x >= rect.x - 1e-9 && tile.y >= rect.y - 1e-9);
+            assert!(tile.x + tile.w <= rect.x + rect.w + 1e-6);
+            assert!(tile.y + tile.h <= rect.y + rect.h + 1e-6);
+        }
+        // The classic example stays reasonably square
+        assert!(tiles.iter().all(|t| t.w.max(t.h) / t.w.min(t.h) < 4.0));
+    }
+
+    #[test]
+    fn test_squarify_handles_empty_and_zero_totals() {
+        let rect = TreemapRect {
+            x: 0.0,
+            y: 0.0,
+            w: 100.0,
+            h: 100.0,
+        };
+        assert!(squarify(&[], rect).is_empty());
+        let tiles = squarify(&[0.0, 0.0], rect);
+        assert!(tiles.iter().all(|t| t.w == 0.0 && t.h == 0.0));
+    }
+
+    #[test]
+    fn test_change_color_scale() {
+        let theme = ChartTheme::light();
+        assert_eq!(change_color(&theme, None), COLOR_GRAY_LIGHT);
+        assert_eq!(change_color(&theme, Some(0.0)), COLOR_SLATE);
+        assert_eq!(change_color(&theme, Some(10.0)), COLOR_EMERALD);
+        assert_eq!(change_color(&theme, Some(-25.0)), COLOR_ROSE);
+        assert_ne!(
+            change_color(&theme, Some(5.0)),
+            change_color(&theme, Some(10.0))
+        );
+    }
+
+    fn treemap_item(ticker: &str, market_cap: f64, change_pct: Option<f64>) -> TreemapItem {
+        TreemapItem {
+            ticker: ticker.to_string(),
+            name: ticker.to_string(),
+            market_cap,
+            change_pct,
+        }
+    }
+
+    #[test]
+    fn test_treemap_groups_by_peer_group_largest_first() {
+        let items = vec![
+            treemap_item("NKE", 100.0, Some(2.0)),
+            treemap_item("MC.PA", 300.0, Some(-1.0)),
+            treemap_item("ZZZZ", 50.0, None),
+            treemap_item("RMS.PA", 200.0, None),
+            treemap_item("GONE", 0.0, None),
+        ];
+        let groups = treemap_groups(&items);
+        assert_eq!(groups[0].0, "Luxury");
+        let luxury: Vec<_> = groups[0].1.iter().map(|m| m.ticker.as_str()).collect();
+        assert_eq!(luxury, ["MC.PA", "RMS.PA"]);
+        assert!(
+            groups.iter().any(
+                |(name, members)| name == "Other" && members.iter().any(|m| m.ticker == "ZZZZ")
+            )
+        );
+        // Companies without a market cap get no tile
+        assert!(
+            groups
+                .iter()
+                .all(|(_, m)| m.iter().all(|i| i.ticker != "GONE"))
+        );
+    }
+
+    #[test]
+    fn test_treemap_chart_labels_tiles() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("treemap.svg");
+        let items = vec![
+            treemap_item("MC.PA", 300.0, Some(4.2)),
+            treemap_item("NKE", 100.0, Some(-3.0)),
+        ];
+        create_treemap_chart(&items, "Market Map: test", &path).unwrap();
+        let svg = std::fs::read_to_string(&path).unwrap();
+        assert!(svg.contains("Market Map: test"));
+        assert!(svg.contains("MC.PA"));
+        assert!(svg.contains("+4.2%"));
+        assert!(svg.contains("-3.0%"));
+        assert!(svg.contains("Luxury"));
+    }
+
+    #[test]
+    fn test_waterfall_chart_steps() {
+        use crate::compare_marketcaps::Contribution;
+        let attribution = ChangeAttribution {
+            total_from: 1_000e9,
+            total_to: 1_030e9,
+            top: vec![
+                Contribution {
+                    ticker: "MC.PA".to_string(),
+                    name: "LVMH".to_string(),
+                    change_usd: 50e9,
+                },
+                Contribution {
+                    ticker: "NKE".to_string(),
+                    name: "Nike".to_string(),
+                    change_usd: -15e9,
+                },
+            ],
+            other: -5e9,
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("waterfall.svg");
+        create_waterfall_chart(&attribution, "2025-01-31", "2025-02-28", &path).unwrap();
+        let svg = std::fs::read_to_string(&path).unwrap();
+        assert!(svg.contains("$1.00T"));
+        assert!(svg.contains("+$50.0B"));
+        assert!(svg.contains("-$15.0B"));
+        assert!(svg.contains("Other"));
+        assert!(svg.contains("$1.03T"));
+        assert!(svg.contains("(+3.00%)"));
+    }
+
+    fn trend(ticker: &str, ranks: &[Option<usize>]) -> TickerTrend {
+        use crate::advanced_comparisons::TrendDataPoint;
+        TickerTrend {
+            ticker: ticker.to_string(),
+            name: format!("{} Inc", ticker),
+            data_points: ranks
+                .iter()
+                .enumerate()
+                .map(|(i, rank)| TrendDataPoint {
+                    date: format!("2025-0{}-01", i + 1),
+                    market_cap_usd: rank.map(|r| 1000.0 / r as f64),
+                    rank: *rank,
+                    market_share: None,
+                })
+                .collect(),
+            overall_change_pct: None,
+            overall_change_abs: None,
+            cagr: None,
+            volatility: None,
+            return_volatility_ratio: None,
+            downside_deviation: None,
+            max_drawdown: None,
+            corporate_action: None,
+            formerly: None,
+        }
+    }
+
+    #[test]
+    fn test_bump_chart_draws_top_companies_by_last_rank() {
+        let dates: Vec<String> = ["2025-01-01", "2025-02-01", "2025-03-01"]
+            .iter()
+            .map(|d| d.to_string())
+            .collect();
+        let mut trends = vec![
+            trend("AAA", &[Some(1), Some(2), Some(2)]),
+            trend("BBB", &[Some(2), Some(1), Some(1)]),
+            trend("NEW", &[None, Some(3), Some(3)]),
+            trend("GONE", &[Some(3), None, None]),
+        ];
+        // Only companies ranked in the top 20 on the last date are drawn
+        trends.extend((4..=25).map(|r| trend(&format!("T{}", r), &[Some(r), Some(r), Some(r)])));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bump.svg");
+        create_bump_chart(&trends, &dates, &path).unwrap();
+        let svg = std::fs::read_to_string(&path).unwrap();
+        assert!(svg.contains("Rank Evolution: 2025-01-01 to 2025-03-01"));
+        assert!(svg.contains("Top 20 companies"));
+        assert!(svg.contains("#1 BBB Inc"));
+        assert!(svg.contains("#3 NEW Inc"));
+        assert!(svg.contains("#20 T20 Inc"));
+        assert!(!svg.contains("GONE"));
+        assert!(!svg.contains("T21"));
+    }
+
+    #[test]
+    fn test_sparkline_chart_labels_rows_and_last_values() {
+        let labels = ["2025-03-31", "2025-06-30"].map(String::from);
+        let rows = vec![
+            ("Sportswear".to_string(), vec![Some(2.5), Some(4.0)]),
+            ("Luxury".to_string(), vec![None, Some(-1.25)]),
+        ];
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sparklines.svg");
+        create_sparkline_chart("Relative Strength", "pp per window", &labels, &rows, &path)
+            .unwrap();
+        let svg = std::fs::read_to_string(&path).unwrap();
+        assert!(svg.contains("Relative Strength"));
+        assert!(svg.contains("Sportswear"));
+        assert!(svg.contains("+4.0"));
+        assert!(svg.contains("-1.2"));
+        assert!(svg.contains("2025-06-30"));
+    }
+
+    #[test]
+    fn test_country_chart() {
+        let totals = regions::group_totals([
+            ("FR".to_string(), Some(270.0), Some(300.0)),
+            ("US".to_string(), Some(90.0), Some(100.0)),
+            (geo::UNKNOWN_COUNTRY.to_string(), None, Some(100.0)),
+        ]);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("geo.svg");
+        create_country_chart(&totals, "2025-02-28", &path).unwrap();
+        let svg = std::fs::read_to_string(&path).unwrap();
+        assert!(svg.contains("France (FR)"));
+        assert!(svg.contains("60.0% · 1 companies"));
+        assert!(svg.contains("Unknown"));
+    }
+
     // Test color constants are defined correctly
     #[test]
     fn test_color_constants_defined() {
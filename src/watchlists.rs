@@ -0,0 +1,236 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Named ticker watchlists stored in SQLite, separate from the config universe
+//!
+//! Fetching a watchlist stores its market caps in `watchlist_market_caps`, out
+//! of the universe's `market_caps`, and writes `watchlist-<name>_marketcaps`
+//! CSVs; comparison commands read those instead of the universe CSVs when
+//! given `--watchlist`.
+
+use crate::specific_date_marketcaps;
+use anyhow::Result;
+use sqlx::sqlite::SqlitePool;
+
+/// File kind scoped to a watchlist, e.g. `watchlist-ipo-candidates_marketcaps`.
+/// Without a watchlist the universe kind is returned unchanged.
+pub fn scoped_kind(watchlist: Option<&str>, kind: &str) -> String {
+    match watchlist {
+        Some(name) => format!("watchlist-{}_{}", name, kind),
+        None => kind.to_string(),
+    }
+}
+
+fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        anyhow::bail!(
+            "Invalid watchlist name '{}': use letters, digits, '-' and '_' only",
+            name
+        );
+    }
+    Ok(())
+}
+
+async fn exists(pool: &SqlitePool, name: &str) -> Result<bool> {
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM watchlists WHERE name = ?")
+        .bind(name)
+        .fetch_one(pool)
+        .await?;
+    Ok(count > 0)
+}
+
+async fn ensure_exists(pool: &SqlitePool, name: &str) -> Result<()> {
+    if !exists(pool, name).await? {
+        anyhow::bail!(
+            "Watchlist '{}' not found. Create it with 'watchlist create {}'",
+            name,
+            name
+        );
+    }
+    Ok(())
+}
+
+pub async fn create_watchlist(pool: &SqlitePool, name: &str) -> Result<()> {
+    validate_name(name)?;
+    if exists(pool, name).await? {
+        anyhow::bail!("Watchlist '{}' already exists", name);
+    }
+    sqlx::query("INSERT INTO watchlists (name) VALUES (?)")
+        .bind(name)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn delete_watchlist(pool: &SqlitePool, name: &str) -> Result<()> {
+    ensure_exists(pool, name).await?;
+    sqlx::query("DELETE FROM watchlist_tickers WHERE watchlist = ?")
+        .bind(name)
+        .execute(pool)
+        .await?;
+    sqlx::query("DELETE FROM watchlists WHERE name = ?")
+        .bind(name)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Add tickers to a watchlist, returning how many were new
+pub async fn add_tickers(pool: &SqlitePool, name: &str, tickers: &[String]) -> Result<usize> {
+    ensure_exists(pool, name).await?;
+    let mut added = 0;
+    for ticker in tickers {
+        let result = sqlx::query(
+            "INSERT OR IGNORE INTO watchlist_tickers (watchlist, ticker) VALUES (?, ?)",
+        )
+        .bind(name)
+        .bind(ticker.trim())
+        .execute(pool)
+        .await?;
+        added += result.rows_affected() as usize;
+    }
+    Ok(added)
+}
+
+/// Remove tickers from a watchlist, returning how many were removed
+pub async fn remove_tickers(pool: &SqlitePool, name: &str, tickers: &[String]) -> Result<usize> {
+    ensure_exists(pool, name).await?;
+    let mut removed = 0;
+    for ticker in tickers {
+        let result =
+            sqlx::query("DELETE FROM watchlist_tickers WHERE watchlist = ? AND ticker = ?")
+                .bind(name)
+                .bind(ticker.trim())
+                .execute(pool)
+                .await?;
+        removed += result.rows_affected() as usize;
+    }
+    Ok(removed)
+}
+
+/// All watchlists with their ticker counts
+pub async fn list_watchlists(pool: &SqlitePool) -> Result<Vec<(String, i64)>> {
+    let rows = sqlx::query_as::<_, (String, i64)>(
+        r#"
+        SELECT w.name, COUNT(t.ticker)
+        FROM watchlists w
+        LEFT JOIN watchlist_tickers t ON t.watchlist = w.name
+        GROUP BY w.name
+        ORDER BY w.name
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+/// Tickers of a watchlist, sorted
+pub async fn get_tickers(pool: &SqlitePool, name: &str) -> Result<Vec<String>> {
+    ensure_exists(pool, name).await?;
+    let tickers = sqlx::query_scalar(
+        "SELECT ticker FROM watchlist_tickers WHERE watchlist = ? ORDER BY ticker",
+    )
+    .bind(name)
+    .fetch_all(pool)
+    .await?;
+    Ok(tickers)
+}
+
+/// Fetch market caps of a watchlist for a date into `watchlist_market_caps`
+/// and export its CSV. Tickers already stored for that date (e.g. from the
+/// universe) are reused.
+pub async fn fetch_watchlist(
+    pool: &SqlitePool,
+    name: &str,
+    date: &str,
+    report_currencies: &[String],
+    concurrency: usize,
+) -> Result<()> {
+    let tickers = get_tickers(pool, name).await?;
+    if tickers.is_empty() {
+        anyhow::bail!(
+            "Watchlist '{}' is empty. Add tickers with 'watchlist add {} <TICKER>...'",
+            name,
+            name
+        );
+    }
+
+    println!("Fetching watchlist '{}' ({} tickers)", name, tickers.len());
+    specific_date_marketcaps::fetch_marketcaps_for_tickers(
+        pool,
+        date,
+        &tickers,
+        &scoped_kind(Some(name), "marketcaps"),
+        report_currencies,
+        specific_date_marketcaps::Store::Watchlist,
+        concurrency,
+        None,
+        None,
+    )
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    #[test]
+    fn test_scoped_kind() {
+        assert_eq!(scoped_kind(None, "marketcaps"), "marketcaps");
+        assert_eq!(
+            scoped_kind(Some("ipo-candidates"), "comparison"),
+            "watchlist-ipo-candidates_comparison"
+        );
+    }
+
+    #[test]
+    fn test_validate_name() {
+        assert!(validate_name("ipo-candidates").is_ok());
+        assert!(validate_name("luxury_2025").is_ok());
+        assert!(validate_name("").is_err());
+        assert!(validate_name("../etc").is_err());
+        assert!(validate_name("with space").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_watchlist_lifecycle() {
+        let pool = db::create_db_pool("sqlite::memory:").await.unwrap();
+        let tickers = |t: &[&str]| t.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        create_watchlist(&pool, "ipo").await.unwrap();
+        assert!(create_watchlist(&pool, "ipo").await.is_err());
+
+        assert_eq!(
+            add_tickers(&pool, "ipo", &tickers(&["SHEIN", "BIRK", "SHEIN"]))
+                .await
+                .unwrap(),
+            2
+        );
+        assert_eq!(get_tickers(&pool, "ipo").await.unwrap(), ["BIRK", "SHEIN"]);
+        assert_eq!(
+            list_watchlists(&pool).await.unwrap(),
+            vec![("ipo".to_string(), 2)]
+        );
+
+        assert_eq!(
+            remove_tickers(&pool, "ipo", &tickers(&["BIRK"]))
+                .await
+                .unwrap(),
+            1
+        );
+        delete_watchlist(&pool, "ipo").await.unwrap();
+        assert!(get_tickers(&pool, "ipo").await.is_err());
+        assert!(
+            add_tickers(&pool, "missing", &tickers(&["NKE"]))
+                .await
+                .is_err()
+        );
+    }
+}
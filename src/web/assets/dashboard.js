@@ -0,0 +1,104 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+// Built-in dashboard: available snapshot dates and the latest comparison,
+// read from the JSON API of the same server.
+
+const TOP_N = 25;
+
+function escapeHtml(value) {
+    return String(value ?? "").replace(/[&<>"']/g, (c) => ({
+        "&": "&amp;", "<": "&lt;", ">": "&gt;", '"': "&quot;", "'": "&#39;",
+    })[c]);
+}
+
+async function getJson(url) {
+    const response = await fetch(url, { credentials: "same-origin" });
+    if (!response.ok) {
+        throw new Error(`${url} returned ${response.status}`);
+    }
+    return response.json();
+}
+
+function formatMarketCap(value) {
+    const number = Number(value);
+    if (!Number.isFinite(number) || value === "") {
+        return "–";
+    }
+    return (number / 1e9).toLocaleString(undefined, { maximumFractionDigits: 1 }) + " bn";
+}
+
+function formatChange(value) {
+    const number = Number(value);
+    if (!Number.isFinite(number) || value === "") {
+        return '<span class="muted">–</span>';
+    }
+    const cls = number > 0 ? "up" : number < 0 ? "down" : "";
+    const sign = number > 0 ? "+" : "";
+    return `<span class="${cls}">${sign}${number.toFixed(2)}%</span>`;
+}
+
+async function loadDates() {
+    const container = document.getElementById("dates");
+    try {
+        const { snapshots } = await getJson("/api/market-caps");
+        const dates = [...new Set(snapshots.map((s) => s.date))].sort().reverse();
+        container.innerHTML = dates.length
+            ? dates.map((d) => `<a href="/market-caps/${encodeURIComponent(d)}">${escapeHtml(d)}</a>`).join("")
+            : '<span class="muted">No market cap snapshots yet</span>';
+    } catch (e) {
+        container.innerHTML = `<span class="down">${escapeHtml(e.message)}</span>`;
+    }
+}
+
+async function loadLatestComparison() {
+    const container = document.getElementById("comparison");
+    try {
+        const { comparisons } = await getJson("/api/comparisons");
+        if (!comparisons.length) {
+            container.innerHTML = '<span class="muted">No comparisons yet</span>';
+            return;
+        }
+        const latest = comparisons[0];
+        const from = encodeURIComponent(latest.from_date);
+        const to = encodeURIComponent(latest.to_date);
+        document.getElementById("comparison-title").innerHTML =
+            `Latest comparison: <a href="/comparisons/${from}/${to}">${escapeHtml(latest.from_date)} → ${escapeHtml(latest.to_date)}</a>`;
+
+        document.getElementById("charts").innerHTML = latest.chart_paths
+            .map((c) => `<a href="/api/charts/${from}/${to}/${encodeURIComponent(c.chart_type)}" target="_blank">📊 ${escapeHtml(c.chart_type)}</a>`)
+            .join("");
+
+        const { records, summary } = await getJson(`/api/comparisons/${from}/${to}`);
+        const top = records
+            .filter((r) => r["Rank To"] !== "")
+            .sort((a, b) => Number(a["Rank To"]) - Number(b["Rank To"]))
+            .slice(0, TOP_N);
+        container.innerHTML = `
+            <table>
+                <thead><tr>
+                    <th class="num">Rank</th><th>Company</th><th>Ticker</th>
+                    <th class="num">Market cap (USD)</th><th class="num">Change</th><th class="num">Rank change</th>
+                </tr></thead>
+                <tbody>${top.map((r) => `
+                    <tr>
+                        <td class="num">${escapeHtml(r["Rank To"])}</td>
+                        <td>${escapeHtml(r["Name"])}</td>
+                        <td class="muted">${escapeHtml(r["Ticker"])}</td>
+                        <td class="num">${formatMarketCap(r["Market Cap To"])}</td>
+                        <td class="num">${formatChange(r["Percentage Change (%)"])}</td>
+                        <td class="num">${escapeHtml(r["Rank Change"])}</td>
+                    </tr>`).join("")}
+                </tbody>
+            </table>`;
+        if (summary) {
+            document.getElementById("summary").innerHTML = `<h2>Summary</h2><pre>${escapeHtml(summary)}</pre>`;
+        }
+    } catch (e) {
+        container.innerHTML = `<span class="down">${escapeHtml(e.message)}</span>`;
+    }
+}
+
+loadDates();
+loadLatestComparison();
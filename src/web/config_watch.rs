@@ -0,0 +1,167 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Hot reloading of config.toml while `serve` runs
+//!
+//! The server keeps the parsed config behind a lock and swaps in a new one
+//! only after it parses and passes `config::validate_config()`, so a broken
+//! edit leaves the running config in place. A background task polls the
+//! file's modification time; `POST /api/admin/reload-config` reloads on demand.
+
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::task::JoinHandle;
+
+use crate::config::{self, Config};
+
+/// How often the watcher checks config.toml for changes
+pub const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The config of a running server, shared by all requests
+#[derive(Clone)]
+pub struct SharedConfig {
+    path: PathBuf,
+    current: Arc<RwLock<Arc<Config>>>,
+}
+
+impl SharedConfig {
+    pub fn new(path: PathBuf, config: Config) -> Self {
+        Self {
+            path,
+            current: Arc::new(RwLock::new(Arc::new(config))),
+        }
+    }
+
+    /// Snapshot of the current config; later reloads don't change it
+    pub fn get(&self) -> Arc<Config> {
+        self.current
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Read, parse and validate the file, then swap it in. On error the
+    /// current config stays.
+    pub fn reload(&self) -> Result<Arc<Config>> {
+        let config = config::load_config_from(&self.path)?;
+        config::validate_config(&config)?;
+        let config = Arc::new(config);
+        *self
+            .current
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = config.clone();
+        Ok(config)
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Reload the config whenever the file's modification time changes
+pub fn spawn_watcher(shared: SharedConfig, interval: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut last_seen = modified(shared.path());
+        let mut ticker = tokio::time::interval(interval);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            let seen = modified(shared.path());
+            if seen == last_seen {
+                continue;
+            }
+            last_seen = seen;
+            match shared.reload() {
+                Ok(config) => println!(
+                    "✅ Reloaded {} ({} tickers)",
+                    shared.path().display(),
+                    config.us_tickers.len() + config.non_us_tickers.len()
+                ),
+                Err(e) => eprintln!(
+                    "⚠️  Keeping the current config, {} is invalid: {:#}",
+                    shared.path().display(),
+                    e
+                ),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALID: &str = "non_us_tickers = [\"MC.PA\"]\nus_tickers = [\"NKE\"]\n";
+
+    fn shared_config(dir: &tempfile::TempDir) -> SharedConfig {
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, VALID).unwrap();
+        let config = config::load_config_from(&path).unwrap();
+        SharedConfig::new(path, config)
+    }
+
+    #[test]
+    fn test_reload_swaps_only_valid_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let shared = shared_config(&dir);
+        let before = shared.get();
+
+        std::fs::write(
+            shared.path(),
+            "non_us_tickers = [\"MC.PA\"]\nus_tickers = [\"NKE\", \"LULU\"]\n",
+        )
+        .unwrap();
+        shared.reload().unwrap();
+        assert_eq!(shared.get().us_tickers, vec!["NKE", "LULU"]);
+        // Snapshots taken earlier are unaffected
+        assert_eq!(before.us_tickers, vec!["NKE"]);
+
+        for broken in [
+            "us_tickers = [",
+            "non_us_tickers = [\"NKE\"]\nus_tickers = [\"NKE\"]\n",
+        ] {
+            std::fs::write(shared.path(), broken).unwrap();
+            assert!(shared.reload().is_err());
+            assert_eq!(shared.get().us_tickers, vec!["NKE", "LULU"]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_watcher_picks_up_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let shared = shared_config(&dir);
+        let watcher = spawn_watcher(shared.clone(), Duration::from_millis(20));
+
+        // Make sure the new modification time differs on coarse filesystems
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let file = std::fs::File::options()
+            .write(true)
+            .open(shared.path())
+            .unwrap();
+        std::fs::write(
+            shared.path(),
+            "non_us_tickers = []\nus_tickers = [\"NKE\", \"TJX\"]\n",
+        )
+        .unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(5))
+            .unwrap();
+
+        let mut reloaded = false;
+        for _ in 0..100 {
+            if shared.get().us_tickers.len() == 2 {
+                reloaded = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        watcher.abort();
+        assert!(reloaded, "watcher did not reload the config");
+    }
+}
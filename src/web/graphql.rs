@@ -0,0 +1,658 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! GraphQL schema over the stored market caps
+//!
+//! Served on `POST /graphql`. Resolvers read SQLite through `web::queries`, so
+//! values match the REST endpoints and CSV exports. A `currency` argument adds
+//! a converted `marketCap` next to the stored original/EUR/USD values.
+
+use async_graphql::{
+    ComplexObject, Context, EmptyMutation, EmptySubscription, Object, Result, Schema, SimpleObject,
+};
+use axum::{Extension, Json};
+use sqlx::Row;
+use sqlx::sqlite::SqlitePool;
+
+use crate::advanced_comparisons::{self, PeerGroup as PeerGroupConfig};
+use crate::config::ForexConfig;
+use crate::web::config_watch::SharedConfig;
+use crate::web::queries;
+
+pub type MarketCapSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+/// Deepest selection accepted; the schema itself nests at most four levels,
+/// the rest leaves room for introspection queries
+pub const MAX_QUERY_DEPTH: usize = 12;
+
+/// Most fields (one point each, aliases included) a query may select, so a
+/// request can't fan out into thousands of resolver calls
+pub const MAX_QUERY_COMPLEXITY: usize = 500;
+
+/// Build the schema with the pool and the server's config available to
+/// resolvers; peer groups and forex settings follow config reloads
+pub fn build_schema(pool: SqlitePool, config: SharedConfig) -> MarketCapSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .data(pool)
+        .data(config)
+        .limit_depth(MAX_QUERY_DEPTH)
+        .limit_complexity(MAX_QUERY_COMPLEXITY)
+        .finish()
+}
+
+/// `POST /graphql`
+pub async fn graphql_handler(
+    Extension(schema): Extension<MarketCapSchema>,
+    Json(request): Json<async_graphql::Request>,
+) -> Json<async_graphql::Response> {
+    Json(schema.execute(request).await)
+}
+
+/// Market cap of one company on one date
+#[derive(Debug, Clone, SimpleObject)]
+pub struct MarketCapSnapshot {
+    pub date: String,
+    /// Rank by EUR market cap on that date, when known
+    pub rank: Option<i64>,
+    pub ticker: String,
+    pub name: String,
+    pub market_cap_original: Option<f64>,
+    pub original_currency: Option<String>,
+    pub market_cap_eur: Option<f64>,
+    pub market_cap_usd: Option<f64>,
+    pub price: Option<f64>,
+    /// Requested report currency, if any
+    pub currency: Option<String>,
+    /// Market cap converted to `currency` with that date's rates
+    pub market_cap: Option<f64>,
+}
+
+/// Change of one company between two dates, in its original currency
+#[derive(Debug, Clone, SimpleObject)]
+pub struct Comparison {
+    pub ticker: String,
+    pub name: String,
+    pub original_currency: Option<String>,
+    pub market_cap_from: Option<f64>,
+    pub market_cap_to: Option<f64>,
+    pub absolute_change: Option<f64>,
+    pub percentage_change: Option<f64>,
+    pub rank_from: Option<i64>,
+    pub rank_to: Option<i64>,
+    pub rank_change: Option<i32>,
+    pub market_share_from: Option<f64>,
+    pub market_share_to: Option<f64>,
+    pub corporate_action: Option<String>,
+    /// Earnings reports between the two dates
+    pub earnings: Option<String>,
+    /// Requested report currency, if any
+    pub currency: Option<String>,
+    pub market_cap_from_converted: Option<f64>,
+    pub market_cap_to_converted: Option<f64>,
+}
+
+/// A tracked company
+#[derive(Debug, Clone, SimpleObject)]
+#[graphql(complex)]
+pub struct Company {
+    pub ticker: String,
+    /// Name as of the latest stored snapshot
+    pub name: String,
+    pub original_currency: Option<String>,
+}
+
+#[ComplexObject]
+impl Company {
+    /// Stored snapshots, oldest first, optionally limited to a date range
+    async fn history(
+        &self,
+        ctx: &Context<'_>,
+        from: Option<String>,
+        to: Option<String>,
+        currency: Option<String>,
+    ) -> Result<Vec<MarketCapSnapshot>> {
+        let pool = ctx.data::<SqlitePool>()?;
+        let forex = &ctx.data::<SharedConfig>()?.get().forex;
+        company_history(pool, &self.ticker, from, to, currency, forex).await
+    }
+
+    /// Predefined peer groups this company belongs to
+    async fn peer_groups(&self, ctx: &Context<'_>) -> Result<Vec<PeerGroup>> {
+        Ok(peer_groups(ctx)?
+            .into_iter()
+            .filter(|g| g.tickers.contains(&self.ticker))
+            .map(PeerGroup::from)
+            .collect())
+    }
+}
+
+/// A predefined peer group
+#[derive(Debug, Clone, SimpleObject)]
+#[graphql(complex)]
+pub struct PeerGroup {
+    pub name: String,
+    pub description: Option<String>,
+    pub tickers: Vec<String>,
+}
+
+impl From<PeerGroupConfig> for PeerGroup {
+    fn from(group: PeerGroupConfig) -> Self {
+        Self {
+            name: group.name,
+            description: group.description,
+            tickers: group.tickers,
+        }
+    }
+}
+
+#[ComplexObject]
+impl PeerGroup {
+    /// Members' market caps on a date (latest stored date when omitted)
+    async fn market_caps(
+        &self,
+        ctx: &Context<'_>,
+        date: Option<String>,
+        currency: Option<String>,
+    ) -> Result<Vec<MarketCapSnapshot>> {
+        let pool = ctx.data::<SqlitePool>()?;
+        let forex = &ctx.data::<SharedConfig>()?.get().forex;
+        let snapshots = market_caps_on(pool, date, currency, forex).await?;
+        Ok(snapshots
+            .into_iter()
+            .filter(|s| self.tickers.contains(&s.ticker))
+            .collect())
+    }
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// Companies with stored market caps, optionally limited to a peer group
+    async fn companies(
+        &self,
+        ctx: &Context<'_>,
+        peer_group: Option<String>,
+    ) -> Result<Vec<Company>> {
+        let pool = ctx.data::<SqlitePool>()?;
+        let members = peer_group_tickers(ctx, peer_group.as_deref())?;
+        Ok(load_companies(pool)
+            .await?
+            .into_iter()
+            .filter(|c| members.as_ref().is_none_or(|m| m.contains(&c.ticker)))
+            .collect())
+    }
+
+    /// One company by ticker
+    async fn company(&self, ctx: &Context<'_>, ticker: String) -> Result<Option<Company>> {
+        let pool = ctx.data::<SqlitePool>()?;
+        Ok(load_companies(pool)
+            .await?
+            .into_iter()
+            .find(|c| c.ticker == ticker))
+    }
+
+    /// Snapshots on `date`, or on every stored date between `from` and `to`
+    /// (inclusive). Defaults to the latest stored date.
+    async fn market_caps(
+        &self,
+        ctx: &Context<'_>,
+        date: Option<String>,
+        from: Option<String>,
+        to: Option<String>,
+        currency: Option<String>,
+        peer_group: Option<String>,
+    ) -> Result<Vec<MarketCapSnapshot>> {
+        let pool = ctx.data::<SqlitePool>()?;
+        let forex = &ctx.data::<SharedConfig>()?.get().forex;
+        let members = peer_group_tickers(ctx, peer_group.as_deref())?;
+
+        let mut snapshots = Vec::new();
+        if from.is_some() || to.is_some() {
+            for day in stored_dates(pool, from.as_deref(), to.as_deref()).await? {
+                snapshots.extend(market_caps_on(pool, Some(day), currency.clone(), forex).await?);
+            }
+        } else {
+            snapshots = market_caps_on(pool, date, currency, forex).await?;
+        }
+
+        Ok(snapshots
+            .into_iter()
+            .filter(|s| members.as_ref().is_none_or(|m| m.contains(&s.ticker)))
+            .collect())
+    }
+
+    /// Compare two stored dates, sorted by percentage change
+    async fn comparison(
+        &self,
+        ctx: &Context<'_>,
+        from: String,
+        to: String,
+        currency: Option<String>,
+        peer_group: Option<String>,
+    ) -> Result<Vec<Comparison>> {
+        let pool = ctx.data::<SqlitePool>()?;
+        let config = ctx.data::<SharedConfig>()?.get();
+        let members = peer_group_tickers(ctx, peer_group.as_deref())?;
+        let code = report_currency(currency.as_deref())?;
+        let currencies: Vec<String> = code.iter().cloned().collect();
+        let rows = queries::get_comparison(
+            pool,
+            queries::parse_date(&from)?,
+            queries::parse_date(&to)?,
+            &currencies,
+            &config.forex,
+        )
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .filter(|row| {
+                members
+                    .as_ref()
+                    .is_none_or(|m| m.contains(&row.comparison.ticker))
+            })
+            .map(|row| {
+                let converted = |side: &str| {
+                    code.as_ref().and_then(|c| {
+                        row.report_values
+                            .get(&format!("Market Cap {} ({})", side, c))
+                            .copied()
+                            .flatten()
+                    })
+                };
+                let c = &row.comparison;
+                Comparison {
+                    ticker: c.ticker.clone(),
+                    name: c.name.clone(),
+                    original_currency: c.original_currency.clone(),
+                    market_cap_from: c.market_cap_from,
+                    market_cap_to: c.market_cap_to,
+                    absolute_change: c.absolute_change,
+                    percentage_change: c.percentage_change,
+                    rank_from: c.rank_from.map(|r| r as i64),
+                    rank_to: c.rank_to.map(|r| r as i64),
+                    rank_change: c.rank_change,
+                    market_share_from: c.market_share_from,
+                    market_share_to: c.market_share_to,
+                    corporate_action: c.corporate_action.clone(),
+                    earnings: c.earnings.clone(),
+                    currency: code.clone(),
+                    market_cap_from_converted: converted("From"),
+                    market_cap_to_converted: converted("To"),
+                }
+            })
+            .collect())
+    }
+
+    /// Predefined peer groups, optionally a single one by name
+    async fn peer_groups(&self, ctx: &Context<'_>, name: Option<String>) -> Result<Vec<PeerGroup>> {
+        Ok(peer_groups(ctx)?
+            .into_iter()
+            .filter(|g| name.as_ref().is_none_or(|n| g.name.eq_ignore_ascii_case(n)))
+            .map(PeerGroup::from)
+            .collect())
+    }
+}
+
+/// Peer groups of the server's current config
+fn peer_groups(ctx: &Context<'_>) -> Result<Vec<PeerGroupConfig>> {
+    Ok(advanced_comparisons::peer_groups_of(
+        &ctx.data::<SharedConfig>()?.get(),
+    ))
+}
+
+/// Tickers of a peer group by name; `None` when no group was requested
+fn peer_group_tickers(ctx: &Context<'_>, name: Option<&str>) -> Result<Option<Vec<String>>> {
+    let Some(name) = name else {
+        return Ok(None);
+    };
+    peer_groups(ctx)?
+        .into_iter()
+        .find(|g| g.name.eq_ignore_ascii_case(name))
+        .map(|g| Some(g.tickers))
+        .ok_or_else(|| format!("Unknown peer group: {}", name).into())
+}
+
+/// The single report currency requested, if any
+fn report_currency(currency: Option<&str>) -> Result<Option<String>> {
+    let Some(code) = currency.map(|c| c.trim().to_uppercase()) else {
+        return Ok(None);
+    };
+    if code.is_empty() || code.contains(',') {
+        return Err("Exactly one currency code can be requested".into());
+    }
+    Ok(Some(code))
+}
+
+/// Every company with stored market caps, with its latest name and currency
+async fn load_companies(pool: &SqlitePool) -> Result<Vec<Company>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT m.ticker, m.name, m.original_currency
+        FROM market_caps m
+        WHERE m.timestamp = (SELECT MAX(timestamp) FROM market_caps WHERE ticker = m.ticker)
+        ORDER BY m.ticker
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| Company {
+            ticker: row.get("ticker"),
+            name: row.get("name"),
+            original_currency: row.get("original_currency"),
+        })
+        .collect())
+}
+
+/// Stored snapshot dates in an inclusive range
+async fn stored_dates(
+    pool: &SqlitePool,
+    from: Option<&str>,
+    to: Option<&str>,
+) -> Result<Vec<String>> {
+    let from = from.map(queries::parse_date).transpose()?;
+    let to = to.map(queries::parse_date).transpose()?;
+    let timestamps: Vec<i64> =
+        sqlx::query_scalar("SELECT DISTINCT timestamp FROM market_caps ORDER BY timestamp")
+            .fetch_all(pool)
+            .await?;
+    Ok(timestamps
+        .into_iter()
+        .filter_map(|ts| chrono::DateTime::from_timestamp(ts, 0))
+        .map(|dt| dt.date_naive())
+        .filter(|d| from.is_none_or(|f| *d >= f) && to.is_none_or(|t| *d <= t))
+        .map(|d| d.format("%Y-%m-%d").to_string())
+        .collect())
+}
+
+async fn market_caps_on(
+    pool: &SqlitePool,
+    date: Option<String>,
+    currency: Option<String>,
+    forex: &ForexConfig,
+) -> Result<Vec<MarketCapSnapshot>> {
+    let currency = report_currency(currency.as_deref())?;
+    let date = match date {
+        Some(date) => queries::parse_date(&date)?,
+        None => match queries::latest_market_cap_date(pool).await? {
+            Some(date) => date,
+            None => return Ok(Vec::new()),
+        },
+    };
+    let currencies: Vec<String> = currency.iter().cloned().collect();
+    let rows = queries::get_market_caps(pool, date, &currencies, forex).await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| MarketCapSnapshot {
+            market_cap: converted_value(&row.report_values, currency.as_deref()),
+            currency: currency.clone(),
+            date: row.date,
+            rank: Some(row.rank as i64),
+            ticker: row.ticker,
+            name: row.name,
+            market_cap_original: row.market_cap_original,
+            original_currency: row.original_currency,
+            market_cap_eur: row.market_cap_eur,
+            market_cap_usd: row.market_cap_usd,
+            price: row.price,
+        })
+        .collect())
+}
+
+async fn company_history(
+    pool: &SqlitePool,
+    ticker: &str,
+    from: Option<String>,
+    to: Option<String>,
+    currency: Option<String>,
+    forex: &ForexConfig,
+) -> Result<Vec<MarketCapSnapshot>> {
+    let currency = report_currency(currency.as_deref())?;
+    let from = from.as_deref().map(queries::parse_date).transpose()?;
+    let to = to.as_deref().map(queries::parse_date).transpose()?;
+    let currencies: Vec<String> = currency.iter().cloned().collect();
+    let rows = queries::get_company_history(pool, ticker, &currencies, forex).await?;
+    Ok(rows
+        .into_iter()
+        .filter(|row| {
+            queries::parse_date(&row.date)
+                .is_ok_and(|d| from.is_none_or(|f| d >= f) && to.is_none_or(|t| d <= t))
+        })
+        .map(|row| MarketCapSnapshot {
+            market_cap: converted_value(&row.report_values, currency.as_deref()),
+            currency: currency.clone(),
+            date: row.date,
+            rank: row.rank,
+            ticker: row.ticker,
+            name: row.name,
+            market_cap_original: row.market_cap_original,
+            original_currency: row.original_currency,
+            market_cap_eur: row.market_cap_eur,
+            market_cap_usd: row.market_cap_usd,
+            price: row.price,
+        })
+        .collect())
+}
+
+/// Pick the converted value out of the `Market Cap (XXX)` fields
+fn converted_value(
+    values: &std::collections::BTreeMap<String, Option<f64>>,
+    currency: Option<&str>,
+) -> Option<f64> {
+    let currency = currency?;
+    values
+        .get(&format!("Market Cap ({})", currency))
+        .copied()
+        .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::db;
+
+    async fn setup_schema() -> MarketCapSchema {
+        setup_schema_with(SharedConfig::new("config.toml".into(), Config::default())).await
+    }
+
+    async fn setup_schema_with(config: SharedConfig) -> MarketCapSchema {
+        let pool = db::create_db_pool("sqlite::memory:").await.unwrap();
+        sqlx::query("ALTER TABLE ticker_details ADD COLUMN ceo TEXT")
+            .execute(&pool)
+            .await
+            .unwrap();
+        for (ticker, date, usd) in [
+            ("NKE", "2025-01-01", 100.0),
+            ("NKE", "2025-02-01", 120.0),
+            ("MC.PA", "2025-01-01", 300.0),
+            ("MC.PA", "2025-02-01", 270.0),
+        ] {
+            let timestamp = queries::parse_date(date)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+                .and_utc()
+                .timestamp();
+            sqlx::query(
+                "INSERT INTO market_caps (ticker, name, market_cap_original, original_currency, \
+                 market_cap_eur, market_cap_usd, timestamp) VALUES (?, ?, ?, 'USD', ?, ?, ?)",
+            )
+            .bind(ticker)
+            .bind(format!("{} Co", ticker))
+            .bind(usd)
+            .bind(usd)
+            .bind(usd)
+            .bind(timestamp)
+            .execute(&pool)
+            .await
+            .unwrap();
+            sqlx::query(
+                "INSERT OR REPLACE INTO forex_rates (symbol, ask, bid, timestamp) \
+                 VALUES ('USD/EUR', 0.9, 0.9, ?)",
+            )
+            .bind(timestamp)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+        build_schema(pool, config)
+    }
+
+    async fn run(schema: &MarketCapSchema, query: &str) -> serde_json::Value {
+        let response = schema.execute(query).await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        response.data.into_json().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_companies_filtered_by_peer_group() {
+        let schema = setup_schema().await;
+        let data = run(
+            &schema,
+            r#"{ companies(peerGroup: "Luxury") { ticker name } }"#,
+        )
+        .await;
+        assert_eq!(
+            data["companies"],
+            serde_json::json!([{"ticker": "MC.PA", "name": "MC.PA Co"}])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_market_caps_date_range_and_latest() {
+        let schema = setup_schema().await;
+        let data = run(
+            &schema,
+            r#"{ marketCaps(from: "2025-01-01", to: "2025-02-01", peerGroup: "sportswear") { date marketCapUsd } }"#,
+        )
+        .await;
+        assert_eq!(
+            data["marketCaps"],
+            serde_json::json!([
+                {"date": "2025-01-01", "marketCapUsd": 100.0},
+                {"date": "2025-02-01", "marketCapUsd": 120.0}
+            ])
+        );
+
+        let data = run(&schema, r#"{ marketCaps { ticker rank date } }"#).await;
+        assert_eq!(data["marketCaps"][0]["ticker"], "MC.PA");
+        assert_eq!(data["marketCaps"][0]["rank"], 1);
+        assert_eq!(data["marketCaps"][0]["date"], "2025-02-01");
+    }
+
+    #[tokio::test]
+    async fn test_company_history_and_comparison() {
+        let schema = setup_schema().await;
+        let data = run(
+            &schema,
+            r#"{ company(ticker: "NKE") { history(from: "2025-02-01") { date } peerGroups { name } } }"#,
+        )
+        .await;
+        assert_eq!(
+            data["company"]["history"],
+            serde_json::json!([{"date": "2025-02-01"}])
+        );
+        assert_eq!(data["company"]["peerGroups"][0]["name"], "Sportswear");
+
+        let data = run(
+            &schema,
+            r#"{ comparison(from: "2025-01-01", to: "2025-02-01") { ticker percentageChange } }"#,
+        )
+        .await;
+        assert_eq!(data["comparison"][0]["ticker"], "NKE");
+        assert_eq!(data["comparison"][0]["percentageChange"], 20.0);
+        assert_eq!(data["comparison"][1]["percentageChange"], -10.0);
+    }
+
+    #[tokio::test]
+    async fn test_currency_conversion() {
+        let schema = setup_schema().await;
+        let data = run(
+            &schema,
+            r#"{ marketCaps(date: "2025-01-01", currency: "eur") { ticker currency marketCap } }"#,
+        )
+        .await;
+        let nike = &data["marketCaps"][1];
+        assert_eq!(nike["ticker"], "NKE");
+        assert_eq!(nike["currency"], "EUR");
+        assert!((nike["marketCap"].as_f64().unwrap() - 90.0).abs() < 1e-6);
+
+        let response = schema
+            .execute(r#"{ marketCaps(currency: "EUR,GBP") { ticker } }"#)
+            .await;
+        assert!(!response.errors.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_peer_groups_follow_config_reloads() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let tickers = "non_us_tickers = [\"MC.PA\"]\nus_tickers = [\"NKE\"]\n";
+        std::fs::write(&path, tickers).unwrap();
+        let shared = SharedConfig::new(
+            path.clone(),
+            crate::config::load_config_from(&path).unwrap(),
+        );
+        let schema = setup_schema_with(shared.clone()).await;
+        let data = run(&schema, r#"{ peerGroups(name: "Luxury") { name } }"#).await;
+        assert_eq!(data["peerGroups"][0]["name"], "Luxury");
+
+        std::fs::write(
+            &path,
+            format!(
+                "{}[[peer_groups]]\nname = \"Swoosh\"\ntickers = [\"NKE\"]\n",
+                tickers
+            ),
+        )
+        .unwrap();
+        shared.reload().unwrap();
+        let data = run(&schema, r#"{ companies(peerGroup: "swoosh") { ticker } }"#).await;
+        assert_eq!(data["companies"], serde_json::json!([{"ticker": "NKE"}]));
+        let data = run(&schema, r#"{ peerGroups { name } }"#).await;
+        assert_eq!(data["peerGroups"], serde_json::json!([{"name": "Swoosh"}]));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_peer_group_is_an_error() {
+        let schema = setup_schema().await;
+        let response = schema
+            .execute(r#"{ companies(peerGroup: "Nope") { ticker } }"#)
+            .await;
+        assert_eq!(response.errors[0].message, "Unknown peer group: Nope");
+    }
+
+    #[tokio::test]
+    async fn test_deep_and_complex_queries_are_rejected() {
+        let schema = setup_schema().await;
+
+        let mut deep = String::from("name");
+        for _ in 0..MAX_QUERY_DEPTH {
+            deep = format!("ofType {{ {} }}", deep);
+        }
+        let response = schema
+            .execute(format!(
+                "{{ __type(name: \"Company\") {{ fields {{ type {{ {} }} }} }} }}",
+                deep
+            ))
+            .await;
+        assert_eq!(response.errors[0].message, "Query is nested too deep.");
+
+        let aliases: String = (0..=MAX_QUERY_COMPLEXITY)
+            .map(|i| format!("c{}: companies {{ ticker }} ", i))
+            .collect();
+        let response = schema.execute(format!("{{ {} }}", aliases)).await;
+        assert_eq!(response.errors[0].message, "Query is too complex.");
+
+        // Regular queries stay well within the limits
+        run(
+            &schema,
+            r#"{ company(ticker: "NKE") { history { date marketCapUsd } peerGroups { marketCaps { ticker } } } }"#,
+        )
+        .await;
+    }
+}
@@ -10,13 +10,20 @@ use axum::{
 };
 use jsonwebtoken::{DecodingKey, Validation, decode};
 
+use crate::api_keys::{self, Scope};
 use crate::web::{
     models::auth::{Claims, User},
     state::AppState,
 };
 
-/// Middleware extractor for authenticated users
-pub struct AuthUser(pub User);
+/// Middleware extractor for authenticated users: a WorkOS user with a JWT,
+/// or a service presenting an API key
+#[derive(Debug, Clone)]
+pub struct AuthUser {
+    pub user: User,
+    /// Scopes of an API key; None for signed-in users, whose role decides
+    pub scopes: Option<Vec<Scope>>,
+}
 
 #[async_trait]
 impl FromRequestParts<AppState> for AuthUser {
@@ -29,13 +36,20 @@ impl FromRequestParts<AppState> for AuthUser {
         // Extract token from Authorization header or cookie
         let token = extract_token(parts)?;
 
+        if token.starts_with(api_keys::KEY_PREFIX) {
+            return api_keys::authenticate(&state.db_pool, &token)
+                .await
+                .map_err(|_| AuthError::InvalidToken)?
+                .ok_or(AuthError::InvalidToken);
+        }
+
         // Validate JWT
         let claims = validate_jwt(&token, &state.jwt_secret)?;
 
         // Convert claims to User
         let user = User::from_claims(&claims).ok_or(AuthError::InvalidRole)?;
 
-        Ok(AuthUser(user))
+        Ok(AuthUser { user, scopes: None })
     }
 }
 
@@ -3,4 +3,5 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 
 pub mod auth;
+pub mod metrics;
 pub mod roles;
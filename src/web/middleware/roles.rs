@@ -4,12 +4,144 @@
 
 use axum::{
     async_trait,
-    extract::FromRequestParts,
-    http::{StatusCode, request::Parts},
-    response::{IntoResponse, Response},
+    extract::{FromRequestParts, Request, State},
+    http::{StatusCode, header, request::Parts},
+    middleware::Next,
+    response::{IntoResponse, Redirect, Response},
 };
 
-use crate::web::{middleware::auth::AuthUser, models::auth::Role, state::AppState};
+use crate::api_keys::Scope;
+use crate::web::{
+    middleware::auth::AuthUser,
+    models::auth::{Role, User},
+    state::AppState,
+};
+
+/// What a route group requires: a role for signed-in users, and the API key
+/// scope that unlocks it (none: no key reaches it)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Access {
+    pub role: Role,
+    pub scope: Option<Scope>,
+}
+
+/// Read-only routes
+pub const VIEWER: Access = Access {
+    role: Role::Viewer,
+    scope: Some(Scope::Read),
+};
+/// Running comparisons
+pub const ANALYST: Access = Access {
+    role: Role::Analyst,
+    scope: Some(Scope::Compare),
+};
+/// Submitting fetch jobs, which spend API quota
+pub const FETCH: Access = Access {
+    role: Role::Admin,
+    scope: Some(Scope::Fetch),
+};
+/// Other admin operations, for signed-in admins only
+pub const ADMIN: Access = Access {
+    role: Role::Admin,
+    scope: None,
+};
+
+/// Authenticate the request and check it has `required` access
+async fn authorize(
+    parts: &mut Parts,
+    state: &AppState,
+    required: Access,
+) -> Result<AuthUser, RoleError> {
+    let auth = AuthUser::from_request_parts(parts, state)
+        .await
+        .map_err(|_| RoleError::Unauthorized {
+            wants_html: wants_html(parts),
+        })?;
+    check_access(&auth, required)?;
+    Ok(auth)
+}
+
+/// Forbidden unless a signed-in user's role includes `required.role`, or an
+/// API key has `required.scope`
+pub fn check_access(auth: &AuthUser, required: Access) -> Result<(), RoleError> {
+    match &auth.scopes {
+        None => check_role(&auth.user, required.role),
+        Some(scopes) if required.scope.is_some_and(|scope| scopes.contains(&scope)) => Ok(()),
+        Some(_) => Err(RoleError::Forbidden),
+    }
+}
+
+/// Forbidden unless the user's role includes `required`
+pub fn check_role(user: &User, required: Role) -> Result<(), RoleError> {
+    if user.role.includes(required) {
+        Ok(())
+    } else {
+        Err(RoleError::Forbidden)
+    }
+}
+
+/// Browsers navigating to a page are sent to the login page instead of a 401
+fn wants_html(parts: &Parts) -> bool {
+    parts
+        .headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"))
+}
+
+/// Route guard: reject requests without `required` access. The
+/// authenticated `User` is added to the request extensions for handlers.
+async fn require_access(
+    state: AppState,
+    required: Access,
+    request: Request,
+    next: Next,
+) -> Response {
+    let (mut parts, body) = request.into_parts();
+    match authorize(&mut parts, &state, required).await {
+        Ok(auth) => {
+            parts.extensions.insert(auth.user);
+            next.run(Request::from_parts(parts, body)).await
+        }
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Route guard for read-only routes (any signed-in user, `read` keys)
+pub async fn require_viewer(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    require_access(state, VIEWER, request, next).await
+}
+
+/// Route guard for running comparisons (analysts and admins, `compare` keys)
+pub async fn require_analyst(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    require_access(state, ANALYST, request, next).await
+}
+
+/// Route guard for submitting fetch jobs (admins, `fetch` keys)
+pub async fn require_fetch(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    require_access(state, FETCH, request, next).await
+}
+
+/// Route guard for other admin operations (admins only, no API keys)
+pub async fn require_admin(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    require_access(state, ADMIN, request, next).await
+}
 
 /// Middleware extractor that requires admin role
 pub struct RequireAdmin(pub AuthUser);
@@ -22,19 +154,11 @@ impl FromRequestParts<AppState> for RequireAdmin {
         parts: &mut Parts,
         state: &AppState,
     ) -> Result<Self, Self::Rejection> {
-        let AuthUser(user) = AuthUser::from_request_parts(parts, state)
-            .await
-            .map_err(|_| RoleError::Unauthorized)?;
-
-        if user.role != Role::Admin {
-            return Err(RoleError::Forbidden);
-        }
-
-        Ok(RequireAdmin(AuthUser(user)))
+        Ok(RequireAdmin(authorize(parts, state, ADMIN).await?))
     }
 }
 
-/// Middleware extractor that requires at least viewer role (Admin or Viewer)
+/// Middleware extractor that requires at least viewer role (any signed-in user)
 pub struct RequireViewer(pub AuthUser);
 
 #[async_trait]
@@ -45,29 +169,103 @@ impl FromRequestParts<AppState> for RequireViewer {
         parts: &mut Parts,
         state: &AppState,
     ) -> Result<Self, Self::Rejection> {
-        let auth_user = AuthUser::from_request_parts(parts, state)
-            .await
-            .map_err(|_| RoleError::Unauthorized)?;
-
-        // Both Admin and Viewer can access viewer-protected routes
-        Ok(RequireViewer(auth_user))
+        Ok(RequireViewer(authorize(parts, state, VIEWER).await?))
     }
 }
 
 /// Role-based authorization errors
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum RoleError {
-    Unauthorized,
+    Unauthorized { wants_html: bool },
     Forbidden,
 }
 
 impl IntoResponse for RoleError {
     fn into_response(self) -> Response {
-        let (status, message) = match self {
-            RoleError::Unauthorized => (StatusCode::UNAUTHORIZED, "Authentication required"),
-            RoleError::Forbidden => (StatusCode::FORBIDDEN, "Insufficient permissions"),
+        match self {
+            RoleError::Unauthorized { wants_html: true } => Redirect::to("/login").into_response(),
+            RoleError::Unauthorized { wants_html: false } => {
+                (StatusCode::UNAUTHORIZED, "Authentication required").into_response()
+            }
+            RoleError::Forbidden => {
+                (StatusCode::FORBIDDEN, "Insufficient permissions").into_response()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(role: Role) -> User {
+        User {
+            id: "user_1".to_string(),
+            email: "user@example.com".to_string(),
+            name: None,
+            role,
+        }
+    }
+
+    #[test]
+    fn test_check_role() {
+        assert!(check_role(&user(Role::Admin), Role::Admin).is_ok());
+        assert!(check_role(&user(Role::Admin), Role::Viewer).is_ok());
+        assert!(check_role(&user(Role::Analyst), Role::Analyst).is_ok());
+        assert_eq!(
+            check_role(&user(Role::Analyst), Role::Admin),
+            Err(RoleError::Forbidden)
+        );
+        assert_eq!(
+            check_role(&user(Role::Viewer), Role::Analyst),
+            Err(RoleError::Forbidden)
+        );
+    }
+
+    fn api_key(scopes: &[Scope]) -> AuthUser {
+        AuthUser {
+            user: user(Role::Viewer),
+            scopes: Some(scopes.to_vec()),
+        }
+    }
+
+    #[test]
+    fn test_api_keys_reach_only_their_scopes_routes() {
+        let fetch_key = api_key(&[Scope::Fetch]);
+        assert!(check_access(&fetch_key, FETCH).is_ok());
+        assert_eq!(check_access(&fetch_key, VIEWER), Err(RoleError::Forbidden));
+        assert_eq!(check_access(&fetch_key, ANALYST), Err(RoleError::Forbidden));
+        assert!(check_access(&api_key(&[Scope::Read, Scope::Compare]), ANALYST).is_ok());
+
+        // Signed-in users still go by their role
+        let admin = AuthUser {
+            user: user(Role::Admin),
+            scopes: None,
         };
+        assert!(check_access(&admin, ADMIN).is_ok());
+        assert!(check_access(&admin, FETCH).is_ok());
+    }
+
+    #[test]
+    fn test_fetch_key_gets_403_on_reload_config() {
+        // POST /api/admin/reload-config is guarded by require_admin
+        let every_scope = api_key(&[Scope::Read, Scope::Compare, Scope::Fetch]);
+        let denied = check_access(&every_scope, ADMIN).unwrap_err();
+        assert_eq!(denied.into_response().status(), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn test_role_error_responses() {
+        let api = RoleError::Unauthorized { wants_html: false }.into_response();
+        assert_eq!(api.status(), StatusCode::UNAUTHORIZED);
+
+        let page = RoleError::Unauthorized { wants_html: true }.into_response();
+        assert_eq!(page.status(), StatusCode::SEE_OTHER);
+        assert_eq!(page.headers()[header::LOCATION], "/login");
 
-        (status, message).into_response()
+        assert_eq!(
+            RoleError::Forbidden.into_response().status(),
+            StatusCode::FORBIDDEN
+        );
     }
 }
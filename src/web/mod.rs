@@ -2,8 +2,11 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-only
 
+pub mod config_watch;
+pub mod graphql;
 pub mod middleware;
 pub mod models;
+pub mod queries;
 pub mod routes;
 pub mod server;
 pub mod state;
@@ -4,28 +4,84 @@
 
 use serde::{Deserialize, Serialize};
 
-/// User roles for authorization
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// User roles for authorization, ordered from least to most privileged:
+/// viewers read reports, analysts also run comparisons, admins also fetch data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum Role {
-    Admin,
     Viewer,
+    Analyst,
+    Admin,
 }
 
 impl Role {
     pub fn as_str(&self) -> &str {
         match self {
             Role::Admin => "admin",
+            Role::Analyst => "analyst",
             Role::Viewer => "viewer",
         }
     }
 
-    pub fn from_str(s: &str) -> Option<Self> {
+    pub fn parse(s: &str) -> Option<Self> {
         match s.to_lowercase().as_str() {
             "admin" => Some(Role::Admin),
+            "analyst" => Some(Role::Analyst),
             "viewer" => Some(Role::Viewer),
             _ => None,
         }
     }
+
+    /// Whether this role grants everything `required` does
+    pub fn includes(&self, required: Role) -> bool {
+        *self >= required
+    }
+}
+
+/// Which emails and WorkOS directory groups map to elevated roles.
+/// Everyone else is a viewer.
+#[derive(Debug, Clone, Default)]
+pub struct RoleMapping {
+    pub admin_emails: Vec<String>,
+    pub analyst_emails: Vec<String>,
+    pub admin_groups: Vec<String>,
+    pub analyst_groups: Vec<String>,
+}
+
+fn env_list(name: &str) -> Vec<String> {
+    std::env::var(name)
+        .unwrap_or_default()
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+impl RoleMapping {
+    /// Read `ADMIN_EMAILS`, `ANALYST_EMAILS`, `WORKOS_ADMIN_GROUPS` and
+    /// `WORKOS_ANALYST_GROUPS` (comma-separated)
+    pub fn from_env() -> Self {
+        Self {
+            admin_emails: env_list("ADMIN_EMAILS"),
+            analyst_emails: env_list("ANALYST_EMAILS"),
+            admin_groups: env_list("WORKOS_ADMIN_GROUPS"),
+            analyst_groups: env_list("WORKOS_ANALYST_GROUPS"),
+        }
+    }
+
+    /// Highest role granted by the email or any of the directory groups
+    pub fn role_for(&self, email: &str, groups: &[String]) -> Role {
+        let matches =
+            |list: &[String], value: &str| list.iter().any(|v| v.eq_ignore_ascii_case(value));
+        let in_groups = |list: &[String]| groups.iter().any(|g| matches(list, g));
+
+        if matches(&self.admin_emails, email) || in_groups(&self.admin_groups) {
+            Role::Admin
+        } else if matches(&self.analyst_emails, email) || in_groups(&self.analyst_groups) {
+            Role::Analyst
+        } else {
+            Role::Viewer
+        }
+    }
 }
 
 /// JWT Claims
@@ -54,7 +110,7 @@ pub struct User {
 
 impl User {
     pub fn from_claims(claims: &Claims) -> Option<Self> {
-        let role = Role::from_str(&claims.role)?;
+        let role = Role::parse(&claims.role)?;
         Some(Self {
             id: claims.sub.clone(),
             email: claims.email.clone(),
@@ -71,3 +127,45 @@ impl User {
         self.role == Role::Viewer
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_role_hierarchy() {
+        assert!(Role::Admin.includes(Role::Analyst));
+        assert!(Role::Analyst.includes(Role::Viewer));
+        assert!(Role::Analyst.includes(Role::Analyst));
+        assert!(!Role::Viewer.includes(Role::Analyst));
+        assert!(!Role::Analyst.includes(Role::Admin));
+        assert_eq!(Role::parse("Analyst"), Some(Role::Analyst));
+        assert_eq!(Role::parse("owner"), None);
+    }
+
+    #[test]
+    fn test_role_mapping() {
+        let mapping = RoleMapping {
+            admin_emails: vec!["boss@example.com".to_string()],
+            analyst_emails: vec!["analyst@example.com".to_string()],
+            admin_groups: vec!["Platform Admins".to_string()],
+            analyst_groups: vec!["Research".to_string()],
+        };
+
+        assert_eq!(mapping.role_for("Boss@example.com", &[]), Role::Admin);
+        assert_eq!(mapping.role_for("analyst@example.com", &[]), Role::Analyst);
+        assert_eq!(
+            mapping.role_for("someone@example.com", &["research".to_string()]),
+            Role::Analyst
+        );
+        // The highest role wins
+        assert_eq!(
+            mapping.role_for(
+                "analyst@example.com",
+                &["Research".to_string(), "Platform Admins".to_string()]
+            ),
+            Role::Admin
+        );
+        assert_eq!(mapping.role_for("someone@example.com", &[]), Role::Viewer);
+    }
+}
@@ -0,0 +1,629 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! SQLite reads behind the JSON API
+//!
+//! Rows serialize with the column names of the corresponding CSV exports.
+//! Extra report currencies add `Market Cap (XXX)` style fields, converted
+//! with the rates of the row's own date.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, NaiveTime};
+use serde::Serialize;
+use sqlx::Row;
+use sqlx::sqlite::SqlitePool;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::compare_marketcaps::{self, MarketCapComparison, RowAnnotations};
+use crate::config::ForexConfig;
+use crate::corporate_actions::CorporateActionIndex;
+use crate::currencies::{
+    ensure_report_rates, extra_report_currencies, get_forex_history, get_rate_map_with_gaps,
+    nearest_in_history, rate_map_from_nearest, try_convert_currency,
+};
+use crate::earnings::EarningsIndex;
+use crate::ticker_aliases::{AppliedAliases, TickerAliases};
+use crate::universe;
+
+pub const DEFAULT_PER_PAGE: usize = 50;
+pub const MAX_PER_PAGE: usize = 500;
+
+/// One page of results
+#[derive(Debug, Serialize)]
+pub struct Page<T> {
+    pub page: usize,
+    pub per_page: usize,
+    pub total: usize,
+    pub records: Vec<T>,
+}
+
+/// Slice `items` into a 1-based page; `per_page` is capped at `MAX_PER_PAGE`
+pub fn paginate<T>(items: Vec<T>, page: Option<usize>, per_page: Option<usize>) -> Page<T> {
+    let page = page.unwrap_or(1).max(1);
+    let per_page = per_page.unwrap_or(DEFAULT_PER_PAGE).clamp(1, MAX_PER_PAGE);
+    let total = items.len();
+    let records = items
+        .into_iter()
+        .skip((page - 1).saturating_mul(per_page))
+        .take(per_page)
+        .collect();
+    Page {
+        page,
+        per_page,
+        total,
+        records,
+    }
+}
+
+/// Report currencies from a comma-separated `currency` query parameter
+pub fn parse_currencies(param: Option<&str>) -> Vec<String> {
+    let codes: Vec<String> = param
+        .unwrap_or_default()
+        .split(',')
+        .map(|c| c.to_string())
+        .collect();
+    extra_report_currencies(&codes)
+}
+
+/// Parse a `YYYY-MM-DD` query parameter
+pub fn parse_date(value: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .with_context(|| format!("Invalid date format: {}", value))
+}
+
+fn midnight_timestamp(date: NaiveDate) -> i64 {
+    date.and_time(NaiveTime::MIN).and_utc().timestamp()
+}
+
+/// Rates for a date, without the console gap report
+async fn rate_map_for(
+    pool: &SqlitePool,
+    timestamp: i64,
+    currencies: &[String],
+    forex: &ForexConfig,
+) -> Result<HashMap<String, f64>> {
+    if currencies.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let (rate_map, _gaps) = get_rate_map_with_gaps(pool, Some(timestamp), forex).await?;
+    ensure_report_rates(currencies, &rate_map)?;
+    Ok(rate_map)
+}
+
+/// `Market Cap (XXX)` values for an amount in its original currency, None
+/// where the date has no rate for the pair
+fn report_values(
+    amount: Option<f64>,
+    from_currency: Option<&str>,
+    currencies: &[String],
+    rate_map: &HashMap<String, f64>,
+) -> BTreeMap<String, Option<f64>> {
+    currencies
+        .iter()
+        .map(|to| {
+            let value = match (amount, from_currency) {
+                (Some(amount), Some(from)) if !from.is_empty() => {
+                    try_convert_currency(amount, from, to, rate_map)
+                        .ok()
+                        .map(|converted| converted.amount)
+                }
+                _ => None,
+            };
+            (format!("Market Cap ({})", to), value)
+        })
+        .collect()
+}
+
+/// Row of the specific date market cap CSV
+#[derive(Debug, Serialize)]
+pub struct MarketCapRow {
+    #[serde(rename = "Rank")]
+    pub rank: usize,
+    #[serde(rename = "Ticker")]
+    pub ticker: String,
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Market Cap (Original)")]
+    pub market_cap_original: Option<f64>,
+    #[serde(rename = "Original Currency")]
+    pub original_currency: Option<String>,
+    #[serde(rename = "Market Cap (EUR)")]
+    pub market_cap_eur: Option<f64>,
+    #[serde(rename = "EUR Rate")]
+    pub eur_rate: Option<f64>,
+    #[serde(rename = "Market Cap (USD)")]
+    pub market_cap_usd: Option<f64>,
+    #[serde(rename = "USD Rate")]
+    pub usd_rate: Option<f64>,
+    #[serde(rename = "Price")]
+    pub price: Option<f64>,
+    #[serde(rename = "Exchange")]
+    pub exchange: Option<String>,
+    #[serde(rename = "Active")]
+    pub active: bool,
+    #[serde(rename = "Description")]
+    pub description: Option<String>,
+    #[serde(rename = "Homepage URL")]
+    pub homepage_url: Option<String>,
+    #[serde(rename = "Employees")]
+    pub employees: Option<i64>,
+    #[serde(rename = "CEO")]
+    pub ceo: Option<String>,
+    #[serde(rename = "Date")]
+    pub date: String,
+    #[serde(flatten)]
+    pub report_values: BTreeMap<String, Option<f64>>,
+}
+
+/// Date of the most recent stored snapshot
+pub async fn latest_market_cap_date(pool: &SqlitePool) -> Result<Option<NaiveDate>> {
+    let timestamp: Option<i64> = sqlx::query_scalar("SELECT MAX(timestamp) FROM market_caps")
+        .fetch_one(pool)
+        .await?;
+    Ok(timestamp
+        .and_then(|ts| DateTime::from_timestamp(ts, 0))
+        .map(|dt| dt.date_naive()))
+}
+
+/// Market caps stored for a date, ranked by EUR market cap
+pub async fn get_market_caps(
+    pool: &SqlitePool,
+    date: NaiveDate,
+    currencies: &[String],
+    forex: &ForexConfig,
+) -> Result<Vec<MarketCapRow>> {
+    let timestamp = midnight_timestamp(date);
+    let rows = sqlx::query(
+        r#"
+        SELECT
+            m.ticker,
+            m.name,
+            CAST(m.market_cap_original AS REAL) as market_cap_original,
+            m.original_currency,
+            CAST(m.market_cap_eur AS REAL) as market_cap_eur,
+            CAST(m.market_cap_usd AS REAL) as market_cap_usd,
+            CAST(m.eur_rate AS REAL) as eur_rate,
+            CAST(m.usd_rate AS REAL) as usd_rate,
+            m.exchange,
+            m.active,
+            CAST(m.price AS REAL) as price,
+            td.description,
+            td.homepage_url,
+            td.employees,
+            td.ceo
+        FROM market_caps m
+        LEFT JOIN ticker_details td ON m.ticker = td.ticker
+        WHERE m.timestamp = ?
+        ORDER BY m.market_cap_eur DESC
+        "#,
+    )
+    .bind(timestamp)
+    .fetch_all(pool)
+    .await?;
+
+    let date_str = date.format("%Y-%m-%d").to_string();
+    let universe = universe::get_universe(pool, &date_str).await?;
+    let rate_map = rate_map_for(pool, timestamp, currencies, forex).await?;
+
+    let records = rows
+        .into_iter()
+        .filter(|row| {
+            universe
+                .as_ref()
+                .is_none_or(|u| u.contains(&row.get::<String, _>("ticker")))
+        })
+        .enumerate()
+        .map(|(index, row)| {
+            let market_cap_original: Option<f64> = row.get("market_cap_original");
+            let original_currency: Option<String> = row.get("original_currency");
+            MarketCapRow {
+                rank: index + 1,
+                ticker: row.get("ticker"),
+                name: row.get("name"),
+                report_values: report_values(
+                    market_cap_original,
+                    original_currency.as_deref(),
+                    currencies,
+                    &rate_map,
+                ),
+                market_cap_original,
+                original_currency,
+                market_cap_eur: row.get("market_cap_eur"),
+                eur_rate: row.get("eur_rate"),
+                market_cap_usd: row.get("market_cap_usd"),
+                usd_rate: row.get("usd_rate"),
+                price: row.get("price"),
+                exchange: row.get("exchange"),
+                active: row.get::<Option<bool>, _>("active").unwrap_or(true),
+                description: row.get("description"),
+                homepage_url: row.get("homepage_url"),
+                employees: row.get("employees"),
+                ceo: row.get("ceo"),
+                date: date_str.clone(),
+            }
+        })
+        .collect();
+    Ok(records)
+}
+
+/// One stored snapshot of a company
+#[derive(Debug, Serialize)]
+pub struct HistoryRow {
+    #[serde(rename = "Date")]
+    pub date: String,
+    #[serde(rename = "Rank")]
+    pub rank: Option<i64>,
+    #[serde(rename = "Ticker")]
+    pub ticker: String,
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Market Cap (Original)")]
+    pub market_cap_original: Option<f64>,
+    #[serde(rename = "Original Currency")]
+    pub original_currency: Option<String>,
+    #[serde(rename = "Market Cap (EUR)")]
+    pub market_cap_eur: Option<f64>,
+    #[serde(rename = "Market Cap (USD)")]
+    pub market_cap_usd: Option<f64>,
+    #[serde(rename = "Price")]
+    pub price: Option<f64>,
+    #[serde(flatten)]
+    pub report_values: BTreeMap<String, Option<f64>>,
+}
+
+/// All stored snapshots of a ticker, oldest first, with the rank recorded for each
+pub async fn get_company_history(
+    pool: &SqlitePool,
+    ticker: &str,
+    currencies: &[String],
+    forex: &ForexConfig,
+) -> Result<Vec<HistoryRow>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT
+            m.ticker,
+            m.name,
+            m.timestamp,
+            CAST(m.market_cap_original AS REAL) as market_cap_original,
+            m.original_currency,
+            CAST(m.market_cap_eur AS REAL) as market_cap_eur,
+            CAST(m.market_cap_usd AS REAL) as market_cap_usd,
+            CAST(m.price AS REAL) as price,
+            r.rank
+        FROM market_caps m
+        LEFT JOIN rankings r ON r.ticker = m.ticker AND r.timestamp = m.timestamp
+        WHERE m.ticker = ?
+        ORDER BY m.timestamp ASC
+        "#,
+    )
+    .bind(ticker)
+    .fetch_all(pool)
+    .await?;
+
+    // One forex query for all snapshots, resolved per date in memory
+    let forex_history = if currencies.is_empty() {
+        BTreeMap::new()
+    } else {
+        get_forex_history(pool, forex.rate_side).await?
+    };
+
+    let mut history = Vec::with_capacity(rows.len());
+    for row in rows {
+        let timestamp: i64 = row.get("timestamp");
+        let rate_map = if currencies.is_empty() {
+            HashMap::new()
+        } else {
+            let nearest = nearest_in_history(&forex_history, timestamp);
+            let (rate_map, _gaps) = rate_map_from_nearest(nearest, Some(timestamp), forex);
+            ensure_report_rates(currencies, &rate_map)?;
+            rate_map
+        };
+        let market_cap_original: Option<f64> = row.get("market_cap_original");
+        let original_currency: Option<String> = row.get("original_currency");
+        history.push(HistoryRow {
+            date: DateTime::from_timestamp(timestamp, 0)
+                .map(|dt| dt.format("%Y-%m-%d").to_string())
+                .unwrap_or_default(),
+            rank: row.get("rank"),
+            ticker: row.get("ticker"),
+            name: row.get("name"),
+            report_values: report_values(
+                market_cap_original,
+                original_currency.as_deref(),
+                currencies,
+                &rate_map,
+            ),
+            market_cap_original,
+            original_currency,
+            market_cap_eur: row.get("market_cap_eur"),
+            market_cap_usd: row.get("market_cap_usd"),
+            price: row.get("price"),
+        });
+    }
+    Ok(history)
+}
+
+/// Row of the comparison CSV, plus `Market Cap From/To (XXX)` per report currency
+#[derive(Debug, Serialize)]
+pub struct ComparisonRow {
+    #[serde(flatten)]
+    pub comparison: MarketCapComparison,
+    #[serde(flatten)]
+    pub report_values: BTreeMap<String, Option<f64>>,
+}
+
+/// Compare two stored snapshots, sorted by percentage change like the CSV
+pub async fn get_comparison(
+    pool: &SqlitePool,
+    from: NaiveDate,
+    to: NaiveDate,
+    currencies: &[String],
+    forex: &ForexConfig,
+) -> Result<Vec<ComparisonRow>> {
+    let from_date = from.format("%Y-%m-%d").to_string();
+    let to_date = to.format("%Y-%m-%d").to_string();
+    let mut from_records = compare_marketcaps::load_market_cap_records(pool, &from_date).await?;
+    let mut to_records = compare_marketcaps::load_market_cap_records(pool, &to_date).await?;
+    if from_records.is_empty() && to_records.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let aliases = TickerAliases::load(pool).await?;
+    let mut applied_aliases = AppliedAliases::default();
+    aliases.apply(
+        from,
+        &mut from_records,
+        |r| &mut r.ticker,
+        &mut applied_aliases,
+    );
+    aliases.apply(to, &mut to_records, |r| &mut r.ticker, &mut applied_aliases);
+
+    let corporate_actions = CorporateActionIndex::load_for_period(&from_date, &to_date)?;
+    let earnings = EarningsIndex::load_for_period(pool, &from_date, &to_date).await?;
+    let from_rates = rate_map_for(pool, midnight_timestamp(from), currencies, forex).await?;
+    let to_rates = rate_map_for(pool, midnight_timestamp(to), currencies, forex).await?;
+    let comparisons = compare_marketcaps::build_comparisons(
+        &from_records,
+        &to_records,
+        RowAnnotations {
+            corporate_actions: &corporate_actions,
+            aliases: &applied_aliases,
+            earnings: &earnings,
+        },
+        currencies,
+        &from_rates,
+        &to_rates,
+    );
+
+    Ok(comparisons
+        .into_iter()
+        .map(|comparison| {
+            let report_values = currencies
+                .iter()
+                .zip(&comparison.report_values)
+                .flat_map(|(currency, (from, to))| {
+                    [
+                        (format!("Market Cap From ({})", currency), from.parse().ok()),
+                        (format!("Market Cap To ({})", currency), to.parse().ok()),
+                    ]
+                })
+                .collect();
+            ComparisonRow {
+                comparison,
+                report_values,
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    async fn setup_pool() -> SqlitePool {
+        let pool = db::create_db_pool("sqlite::memory:").await.unwrap();
+        sqlx::query("ALTER TABLE ticker_details ADD COLUMN ceo TEXT")
+            .execute(&pool)
+            .await
+            .unwrap();
+        pool
+    }
+
+    async fn insert_market_cap(pool: &SqlitePool, ticker: &str, date: &str, eur: f64, usd: f64) {
+        let timestamp = midnight_timestamp(parse_date(date).unwrap());
+        sqlx::query(
+            "INSERT INTO market_caps (ticker, name, market_cap_original, original_currency, \
+             market_cap_eur, market_cap_usd, timestamp) VALUES (?, ?, ?, 'USD', ?, ?, ?)",
+        )
+        .bind(ticker)
+        .bind(format!("{} Inc", ticker))
+        .bind(usd)
+        .bind(eur)
+        .bind(usd)
+        .bind(timestamp)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    #[test]
+    fn test_paginate() {
+        let page = paginate((1..=120).collect::<Vec<_>>(), Some(3), Some(50));
+        assert_eq!(page.total, 120);
+        assert_eq!(page.records, (101..=120).collect::<Vec<_>>());
+
+        let page = paginate(vec![1, 2, 3], Some(0), Some(10_000));
+        assert_eq!(page.page, 1);
+        assert_eq!(page.per_page, MAX_PER_PAGE);
+
+        let page = paginate(vec![1, 2, 3], Some(5), None);
+        assert!(page.records.is_empty());
+    }
+
+    #[test]
+    fn test_parse_currencies() {
+        assert_eq!(parse_currencies(Some("gbp, CHF,EUR")), vec!["GBP", "CHF"]);
+        assert!(parse_currencies(None).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_market_caps_ranks_and_serializes_like_csv() {
+        let pool = setup_pool().await;
+        insert_market_cap(&pool, "SMALL", "2025-01-01", 10.0, 11.0).await;
+        insert_market_cap(&pool, "BIG", "2025-01-01", 100.0, 110.0).await;
+        insert_market_cap(&pool, "BIG", "2025-02-01", 120.0, 130.0).await;
+
+        let date = parse_date("2025-01-01").unwrap();
+        let rows = get_market_caps(&pool, date, &[], &ForexConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].ticker, "BIG");
+        assert_eq!(rows[0].rank, 1);
+
+        let value = serde_json::to_value(&rows[1]).unwrap();
+        assert_eq!(value["Ticker"], "SMALL");
+        assert_eq!(value["Rank"], 2);
+        assert_eq!(value["Market Cap (EUR)"], 10.0);
+        assert_eq!(value["Date"], "2025-01-01");
+
+        assert_eq!(
+            latest_market_cap_date(&pool).await.unwrap(),
+            Some(parse_date("2025-02-01").unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_market_caps_converts_report_currencies() {
+        let pool = setup_pool().await;
+        insert_market_cap(&pool, "BIG", "2025-01-01", 100.0, 110.0).await;
+        let timestamp = midnight_timestamp(parse_date("2025-01-01").unwrap());
+        sqlx::query(
+            "INSERT INTO forex_rates (symbol, ask, bid, timestamp) VALUES ('USD/GBP', 0.8, 0.8, ?)",
+        )
+        .bind(timestamp)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let currencies = parse_currencies(Some("GBP"));
+        let date = parse_date("2025-01-01").unwrap();
+        let rows = get_market_caps(&pool, date, &currencies, &ForexConfig::default())
+            .await
+            .unwrap();
+        let value = serde_json::to_value(&rows[0]).unwrap();
+        let gbp = value["Market Cap (GBP)"].as_f64().unwrap();
+        assert!((gbp - 88.0).abs() < 1e-6, "GBP value {}", gbp);
+
+        // An unknown currency is a client error, not unconverted amounts
+        let err = get_market_caps(
+            &pool,
+            date,
+            &parse_currencies(Some("XYZ")),
+            &ForexConfig::default(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            crate::error::code_of(&err),
+            Some(crate::error::ErrorCode::CurrencyMissing)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_company_history() {
+        let pool = setup_pool().await;
+        insert_market_cap(&pool, "BIG", "2025-02-01", 120.0, 130.0).await;
+        insert_market_cap(&pool, "BIG", "2025-01-01", 100.0, 110.0).await;
+        insert_market_cap(&pool, "OTHER", "2025-01-01", 1.0, 1.0).await;
+
+        let history = get_company_history(&pool, "BIG", &[], &ForexConfig::default())
+            .await
+            .unwrap();
+        let dates: Vec<&str> = history.iter().map(|h| h.date.as_str()).collect();
+        assert_eq!(dates, vec!["2025-01-01", "2025-02-01"]);
+        assert_eq!(history[1].market_cap_usd, Some(130.0));
+
+        assert!(
+            get_company_history(&pool, "MISSING", &[], &ForexConfig::default())
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_report_values_without_rate_are_none() {
+        let mut rate_map = HashMap::new();
+        rate_map.insert("USD/GBP".to_string(), 0.8);
+        let currencies = vec!["GBP".to_string(), "SEK".to_string()];
+        let values = report_values(Some(100.0), Some("USD"), &currencies, &rate_map);
+        assert!((values["Market Cap (GBP)"].unwrap() - 80.0).abs() < 1e-9);
+        assert_eq!(values["Market Cap (SEK)"], None);
+    }
+
+    #[tokio::test]
+    async fn test_get_company_history_converts_with_each_dates_rates() {
+        let pool = setup_pool().await;
+        insert_market_cap(&pool, "BIG", "2025-01-01", 100.0, 100.0).await;
+        insert_market_cap(&pool, "BIG", "2025-02-01", 100.0, 100.0).await;
+        for (date, rate) in [("2025-01-01", 0.8), ("2025-02-01", 0.5)] {
+            sqlx::query(
+                "INSERT INTO forex_rates (symbol, ask, bid, timestamp) VALUES ('USD/GBP', ?, ?, ?)",
+            )
+            .bind(rate)
+            .bind(rate)
+            .bind(midnight_timestamp(parse_date(date).unwrap()))
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        let history = get_company_history(
+            &pool,
+            "BIG",
+            &parse_currencies(Some("GBP")),
+            &ForexConfig::default(),
+        )
+        .await
+        .unwrap();
+        let gbp: Vec<f64> = history
+            .iter()
+            .map(|h| h.report_values["Market Cap (GBP)"].unwrap())
+            .collect();
+        assert!((gbp[0] - 80.0).abs() < 1e-6 && (gbp[1] - 50.0).abs() < 1e-6);
+    }
+
+    #[tokio::test]
+    async fn test_get_comparison() {
+        let pool = setup_pool().await;
+        insert_market_cap(&pool, "UP", "2025-01-01", 100.0, 100.0).await;
+        insert_market_cap(&pool, "UP", "2025-02-01", 150.0, 150.0).await;
+        insert_market_cap(&pool, "DOWN", "2025-01-01", 200.0, 200.0).await;
+        insert_market_cap(&pool, "DOWN", "2025-02-01", 100.0, 100.0).await;
+
+        let from = parse_date("2025-01-01").unwrap();
+        let to = parse_date("2025-02-01").unwrap();
+        let rows = get_comparison(&pool, from, to, &[], &ForexConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 2);
+
+        let value = serde_json::to_value(&rows[0]).unwrap();
+        assert_eq!(value["Ticker"], "UP");
+        assert_eq!(value["Percentage Change (%)"], 50.0);
+        assert_eq!(value["Rank From"], 2);
+        assert_eq!(value["Rank To"], 1);
+        assert_eq!(value["Rank Change"], 1);
+
+        let empty = parse_date("2024-01-01").unwrap();
+        assert!(
+            get_comparison(&pool, empty, empty, &[], &ForexConfig::default())
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,37 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use serde_json::json;
+
+use crate::web::state::AppState;
+
+/// `POST /api/admin/reload-config`: reload config.toml now instead of waiting
+/// for the file watcher. An invalid file is rejected and the current config kept.
+pub async fn reload_config(State(state): State<AppState>) -> Response {
+    match state.config.reload() {
+        Ok(config) => Json(json!({
+            "status": "reloaded",
+            "path": state.config.path().display().to_string(),
+            "us_tickers": config.us_tickers.len(),
+            "non_us_tickers": config.non_us_tickers.len(),
+        }))
+        .into_response(),
+        Err(e) => {
+            eprintln!("⚠️  Config reload rejected: {:#}", e);
+            (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                Json(json!({
+                    "status": "rejected",
+                    "error": format!("{:#}", e),
+                })),
+            )
+                .into_response()
+        }
+    }
+}
@@ -3,19 +3,82 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
     response::{IntoResponse, Json, Response},
 };
+use serde::Deserialize;
 use serde_json::json;
 
-use crate::web::{state::AppState, utils};
+use crate::error::{self, ErrorCode};
+use crate::nats::history;
+use crate::search;
+use crate::web::{queries, state::AppState, utils};
 
-/// List all available comparisons
+/// Query parameters shared by the SQLite-backed endpoints
+#[derive(Debug, Default, Deserialize)]
+pub struct DataQuery {
+    /// Snapshot date (`/api/marketcaps`), defaults to the latest stored date
+    pub date: Option<String>,
+    /// Comparison start date (`/api/comparisons`)
+    pub from: Option<String>,
+    /// Comparison end date (`/api/comparisons`)
+    pub to: Option<String>,
+    /// Comma-separated extra report currencies, e.g. `GBP,CHF`
+    pub currency: Option<String>,
+    pub page: Option<usize>,
+    pub per_page: Option<usize>,
+}
+
+fn bad_request(e: anyhow::Error) -> StatusCode {
+    eprintln!("Bad request: {:#}", e);
+    StatusCode::BAD_REQUEST
+}
+
+/// Status for a failed query: the one of its `error::ErrorCode` (404 for a
+/// missing CSV or ticker, 422 for a missing exchange rate, ...), else 500
+fn query_error(e: anyhow::Error) -> StatusCode {
+    eprintln!("API query failed: {:#}", e);
+    error::code_of(&e).map_or(StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::http_status)
+}
+
+/// List all available comparisons, or compare two stored snapshots
+/// from SQLite when `from` and `to` are given
 pub async fn list_comparisons(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
+    Query(query): Query<DataQuery>,
 ) -> Result<Json<serde_json::Value>, StatusCode> {
-    let comparisons = utils::list_comparisons().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    match (&query.from, &query.to) {
+        (Some(from), Some(to)) => {
+            let from = queries::parse_date(from).map_err(bad_request)?;
+            let to = queries::parse_date(to).map_err(bad_request)?;
+            let currencies = queries::parse_currencies(query.currency.as_deref());
+            let rows = queries::get_comparison(
+                &state.db_pool,
+                from,
+                to,
+                &currencies,
+                &state.config.get().forex,
+            )
+            .await
+            .map_err(query_error)?;
+            if rows.is_empty() {
+                return Err(StatusCode::NOT_FOUND);
+            }
+            let page = queries::paginate(rows, query.page, query.per_page);
+            return Ok(Json(json!({
+                "from": from,
+                "to": to,
+                "currencies": currencies,
+                "page": page
+            })));
+        }
+        (None, None) => {}
+        _ => return Err(StatusCode::BAD_REQUEST),
+    }
+
+    let comparisons = utils::list_comparisons(&state.config.get().output)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     Ok(Json(json!({
         "comparisons": comparisons
@@ -24,11 +87,12 @@ pub async fn list_comparisons(
 
 /// Get comparison data for specific dates
 pub async fn get_comparison(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
     Path((from_date, to_date)): Path<(String, String)>,
 ) -> Result<Json<serde_json::Value>, StatusCode> {
     // Find the comparison file
-    let comparisons = utils::list_comparisons().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let comparisons = utils::list_comparisons(&state.config.get().output)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     let comparison = comparisons
         .iter()
@@ -54,11 +118,12 @@ pub async fn get_comparison(
 
 /// Get a specific chart for a comparison
 pub async fn get_chart(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
     Path((from_date, to_date, chart_type)): Path<(String, String, String)>,
 ) -> Result<Response, StatusCode> {
     // Find the comparison file
-    let comparisons = utils::list_comparisons().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let comparisons = utils::list_comparisons(&state.config.get().output)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     let comparison = comparisons
         .iter()
@@ -72,16 +137,16 @@ pub async fn get_chart(
         .find(|c| c.chart_type == chart_type)
         .ok_or(StatusCode::NOT_FOUND)?;
 
-    // Read the SVG file
-    let svg_content =
+    // Read the SVG image or Vega-Lite spec
+    let content =
         utils::read_chart_svg(&chart.path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let content_type = if chart.is_vega() {
+        "application/json"
+    } else {
+        "image/svg+xml"
+    };
 
-    Ok((
-        StatusCode::OK,
-        [("Content-Type", "image/svg+xml")],
-        svg_content,
-    )
-        .into_response())
+    Ok((StatusCode::OK, [("Content-Type", content_type)], content).into_response())
 }
 
 // ============================================================================
@@ -90,9 +155,10 @@ pub async fn get_chart(
 
 /// List all available market cap snapshots
 pub async fn list_market_caps(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
 ) -> Result<Json<serde_json::Value>, StatusCode> {
-    let snapshots = utils::list_market_caps().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let snapshots = utils::list_market_caps(&state.config.get().output)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     Ok(Json(json!({
         "snapshots": snapshots
@@ -101,11 +167,12 @@ pub async fn list_market_caps(
 
 /// Get market cap data for a specific date
 pub async fn get_market_cap(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
     Path(date): Path<String>,
 ) -> Result<Json<serde_json::Value>, StatusCode> {
     // Find the market cap file for the date
-    let snapshots = utils::list_market_caps().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let snapshots = utils::list_market_caps(&state.config.get().output)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     let snapshot = snapshots
         .iter()
@@ -122,10 +189,140 @@ pub async fn get_market_cap(
     })))
 }
 
+/// Market caps stored in SQLite for a date (latest when omitted)
+pub async fn get_marketcaps(
+    State(state): State<AppState>,
+    Query(query): Query<DataQuery>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let date = match &query.date {
+        Some(date) => queries::parse_date(date).map_err(bad_request)?,
+        None => queries::latest_market_cap_date(&state.db_pool)
+            .await
+            .map_err(query_error)?
+            .ok_or(StatusCode::NOT_FOUND)?,
+    };
+    let currencies = queries::parse_currencies(query.currency.as_deref());
+    let rows =
+        queries::get_market_caps(&state.db_pool, date, &currencies, &state.config.get().forex)
+            .await
+            .map_err(query_error)?;
+    if rows.is_empty() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let page = queries::paginate(rows, query.page, query.per_page);
+    Ok(Json(json!({
+        "date": date,
+        "currencies": currencies,
+        "page": page
+    })))
+}
+
+/// Stored market cap history of one company
+pub async fn get_company_history(
+    State(state): State<AppState>,
+    Path(ticker): Path<String>,
+    Query(query): Query<DataQuery>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let currencies = queries::parse_currencies(query.currency.as_deref());
+    let rows = queries::get_company_history(
+        &state.db_pool,
+        &ticker,
+        &currencies,
+        &state.config.get().forex,
+    )
+    .await
+    .map_err(query_error)?;
+    if rows.is_empty() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let page = queries::paginate(rows, query.page, query.per_page);
+    Ok(Json(json!({
+        "ticker": ticker,
+        "currencies": currencies,
+        "page": page
+    })))
+}
+
+/// Query parameters of the search endpoint
+#[derive(Debug, Default, Deserialize)]
+pub struct SearchQuery {
+    /// Free text, e.g. `luxury handbags`
+    pub q: Option<String>,
+    /// Maximum number of matches (default 20, at most 100)
+    pub limit: Option<usize>,
+}
+
+/// Companies matching free text, best match first, with their latest market caps
+pub async fn search_companies(
+    State(state): State<AppState>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let text = query.q.unwrap_or_default();
+    if search::fts_query(&text).is_none() {
+        return Err(bad_request(anyhow::anyhow!(
+            "Search query has no words: {:?}",
+            text
+        )));
+    }
+    search::ensure_index(&state.db_pool)
+        .await
+        .map_err(query_error)?;
+    let results = search::search(
+        &state.db_pool,
+        &text,
+        query.limit.unwrap_or(search::DEFAULT_LIMIT),
+    )
+    .await
+    .map_err(query_error)?;
+
+    Ok(Json(json!({
+        "query": text,
+        "count": results.len(),
+        "results": results
+    })))
+}
+
 // ============================================================================
 // NATS Job Management API Endpoints
 // ============================================================================
 
+/// Query parameters of the job history endpoint
+#[derive(Debug, Default, Deserialize)]
+pub struct JobsQuery {
+    /// `running`, `completed` or `failed`
+    pub status: Option<String>,
+    /// Only jobs submitted at or after this date (YYYY-MM-DD) or RFC 3339 time
+    pub since: Option<String>,
+    pub page: Option<usize>,
+    pub per_page: Option<usize>,
+}
+
+/// Recorded jobs, newest first. Bad filters are a 400, database failures a 500.
+pub async fn list_jobs(
+    State(state): State<AppState>,
+    Query(query): Query<JobsQuery>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    if let Some(status) = &query.status {
+        history::validate_status(status).map_err(bad_request)?;
+    }
+    if let Some(since) = &query.since {
+        history::parse_since(since).map_err(bad_request)?;
+    }
+    let jobs = history::list_jobs(
+        &state.db_pool,
+        query.status.as_deref(),
+        query.since.as_deref(),
+    )
+    .await
+    .map_err(query_error)?;
+
+    Ok(Json(json!({
+        "page": queries::paginate(jobs, query.page, query.per_page)
+    })))
+}
+
 /// Get status of a specific job
 pub async fn get_job_status(
     State(state): State<AppState>,
@@ -134,6 +331,8 @@ pub async fn get_job_status(
     use futures::StreamExt;
     use std::time::Duration;
 
+    crate::nats::validate_job_id(&job_id).map_err(|_| StatusCode::BAD_REQUEST)?;
+
     // Subscribe to job status subject
     let status_subject = format!("jobs.{}.status", job_id);
 
@@ -11,12 +11,17 @@ use axum::{
 use chrono::{Duration, Utc};
 use jsonwebtoken::{EncodingKey, Header, encode};
 use serde::Deserialize;
+use workos::PaginationParams;
+use workos::directory_sync::{DirectoryId, ListDirectoryUsers, ListDirectoryUsersParams};
 use workos::sso::{
     AuthorizationCode, ClientId, ConnectionSelector, GetAuthorizationUrl,
     GetAuthorizationUrlParams, GetProfileAndToken, GetProfileAndTokenParams, Provider,
 };
 
-use crate::web::{models::auth::Claims, state::AppState};
+use crate::web::{
+    models::auth::{Claims, RoleMapping},
+    state::AppState,
+};
 
 #[derive(Template)]
 #[template(path = "login.html")]
@@ -89,20 +94,16 @@ pub async fn auth_callback(
 
     let profile = response.profile;
 
-    // Determine user role (default to Viewer, check if admin)
-    // In production, you'd check this against a database or WorkOS organization roles
-    let role = if is_admin_email(&profile.email) {
-        "admin"
-    } else {
-        "viewer"
-    };
+    // Determine user role from the email lists and WorkOS directory groups
+    let groups = directory_groups(&state, &profile.email).await;
+    let role = RoleMapping::from_env().role_for(&profile.email, &groups);
 
     // Create JWT claims
     let now = Utc::now();
     let claims = Claims {
         sub: profile.id.to_string(),
         email: profile.email.clone(),
-        role: role.to_string(),
+        role: role.as_str().to_string(),
         iat: now.timestamp(),
         exp: (now + Duration::days(7)).timestamp(),
     };
@@ -145,13 +146,47 @@ pub async fn logout() -> impl IntoResponse {
     )
 }
 
-/// Helper function to determine if an email should have admin role
-/// In production, this should check against a database or WorkOS directory
-fn is_admin_email(email: &str) -> bool {
-    // Simple example: check if email is in admin list
-    // In production, use database or WorkOS directory roles
-    let admin_emails_env = std::env::var("ADMIN_EMAILS").unwrap_or_default();
-    let admin_emails: Vec<_> = admin_emails_env.split(',').map(|s| s.trim()).collect();
-
-    admin_emails.contains(&email)
+/// Names of the WorkOS directory groups the user belongs to.
+/// Empty unless `WORKOS_DIRECTORY_ID` is set; lookup errors are logged and ignored.
+async fn directory_groups(state: &AppState, email: &str) -> Vec<String> {
+    let Ok(directory_id) = std::env::var("WORKOS_DIRECTORY_ID") else {
+        return Vec::new();
+    };
+    let directory = DirectoryId::from(directory_id.as_str());
+    let directory_sync = state.workos_client.directory_sync();
+
+    let mut after: Option<String> = None;
+    loop {
+        let params = ListDirectoryUsersParams {
+            directory: Some(&directory),
+            group: None,
+            pagination: PaginationParams {
+                after: after.as_deref(),
+                limit: Some(100),
+                ..Default::default()
+            },
+        };
+        let page = match directory_sync.list_directory_users(&params).await {
+            Ok(page) => page,
+            Err(e) => {
+                eprintln!("⚠️  WorkOS directory lookup failed: {:?}", e);
+                return Vec::new();
+            }
+        };
+
+        let user = page.data.into_iter().find(|user| {
+            user.emails
+                .iter()
+                .filter_map(|e| e.value.as_deref())
+                .any(|value| value.eq_ignore_ascii_case(email))
+        });
+        if let Some(user) = user {
+            return user.groups.into_iter().map(|g| g.name).collect();
+        }
+
+        match page.metadata.after {
+            Some(cursor) => after = Some(cursor),
+            None => return Vec::new(),
+        }
+    }
 }
@@ -0,0 +1,45 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Built-in dashboard compiled into the binary
+//!
+//! A single HTML page and script that read the JSON API, so `serve` works
+//! without a template or static directory next to the binary.
+
+use axum::{
+    http::header,
+    response::{Html, IntoResponse},
+};
+
+const DASHBOARD_HTML: &str = include_str!("../assets/dashboard.html");
+const DASHBOARD_JS: &str = include_str!("../assets/dashboard.js");
+
+/// Dashboard page
+pub async fn dashboard() -> Html<&'static str> {
+    Html(DASHBOARD_HTML)
+}
+
+/// Dashboard script
+pub async fn dashboard_js() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/javascript; charset=utf-8")],
+        DASHBOARD_JS,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_dashboard_assets() {
+        assert!(dashboard().await.0.contains("/dashboard/dashboard.js"));
+
+        let response = dashboard_js().await.into_response();
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/javascript; charset=utf-8"
+        );
+    }
+}
@@ -0,0 +1,238 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Liveness and readiness probes
+//!
+//! `/healthz` fails only when SQLite is unreachable, so a NATS outage does not
+//! restart the pod. `/readyz` fails when any required dependency is down; the
+//! FMP check only runs when the server was started with `--check-fmp`.
+
+use async_nats::connection::State as ConnectionState;
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use crate::nats::NatsClient;
+use crate::web::state::AppState;
+
+const FMP_CHECK_URL: &str = "https://financialmodelingprep.com/api/v3/is-the-market-open";
+const FMP_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Status of one dependency
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    Error,
+    Skipped,
+}
+
+/// Result of checking one dependency
+#[derive(Debug, Clone, Serialize)]
+pub struct DependencyCheck {
+    pub status: CheckStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl DependencyCheck {
+    fn from_result(result: Result<(), String>, started: Instant) -> Self {
+        let latency_ms = Some(started.elapsed().as_millis() as u64);
+        match result {
+            Ok(()) => Self {
+                status: CheckStatus::Ok,
+                latency_ms,
+                error: None,
+            },
+            Err(error) => Self {
+                status: CheckStatus::Error,
+                latency_ms,
+                error: Some(error),
+            },
+        }
+    }
+
+    fn skipped() -> Self {
+        Self {
+            status: CheckStatus::Skipped,
+            latency_ms: None,
+            error: None,
+        }
+    }
+}
+
+/// Probe response body
+#[derive(Debug, Serialize)]
+pub struct ProbeReport {
+    pub status: CheckStatus,
+    pub timestamp: String,
+    pub checks: BTreeMap<&'static str, DependencyCheck>,
+}
+
+impl ProbeReport {
+    /// Overall status is an error when any of the `required` checks failed
+    pub fn new(checks: BTreeMap<&'static str, DependencyCheck>, required: &[&str]) -> Self {
+        let failed = required.iter().any(|name| {
+            checks
+                .get(name)
+                .is_some_and(|check| check.status == CheckStatus::Error)
+        });
+        Self {
+            status: if failed {
+                CheckStatus::Error
+            } else {
+                CheckStatus::Ok
+            },
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            checks,
+        }
+    }
+}
+
+impl IntoResponse for ProbeReport {
+    fn into_response(self) -> Response {
+        let code = match self.status {
+            CheckStatus::Error => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::OK,
+        };
+        (code, Json(self)).into_response()
+    }
+}
+
+/// Run a trivial query against the pool
+pub async fn check_sqlite(pool: &SqlitePool) -> DependencyCheck {
+    let started = Instant::now();
+    let result = sqlx::query_scalar::<_, i64>("SELECT 1")
+        .fetch_one(pool)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string());
+    DependencyCheck::from_result(result, started)
+}
+
+/// Check the client is currently connected to the NATS server
+pub fn check_nats(nats_client: &NatsClient) -> DependencyCheck {
+    let started = Instant::now();
+    let result = match nats_client.inner().connection_state() {
+        ConnectionState::Connected => Ok(()),
+        state => Err(format!("NATS connection is {}", state)),
+    };
+    DependencyCheck::from_result(result, started)
+}
+
+/// Check FMP answers at all; no API key is sent, so no quota is used
+pub async fn check_fmp() -> DependencyCheck {
+    let started = Instant::now();
+    let result = async {
+        let client = reqwest::Client::builder()
+            .timeout(FMP_CHECK_TIMEOUT)
+            .build()
+            .map_err(|e| e.to_string())?;
+        let response = client
+            .get(FMP_CHECK_URL)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if response.status().is_server_error() {
+            return Err(format!("FMP returned {}", response.status()));
+        }
+        Ok(())
+    }
+    .await;
+    DependencyCheck::from_result(result, started)
+}
+
+/// Liveness probe
+pub async fn healthz(State(state): State<AppState>) -> ProbeReport {
+    let mut checks = BTreeMap::new();
+    checks.insert("sqlite", check_sqlite(&state.db_pool).await);
+    checks.insert("nats", check_nats(&state.nats_client));
+    ProbeReport::new(checks, &["sqlite"])
+}
+
+/// Readiness probe
+pub async fn readyz(State(state): State<AppState>) -> ProbeReport {
+    let mut checks = BTreeMap::new();
+    checks.insert("sqlite", check_sqlite(&state.db_pool).await);
+    checks.insert("nats", check_nats(&state.nats_client));
+    checks.insert(
+        "fmp",
+        if state.check_fmp {
+            check_fmp().await
+        } else {
+            DependencyCheck::skipped()
+        },
+    );
+    ProbeReport::new(checks, &["sqlite", "nats", "fmp"])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    fn failed() -> DependencyCheck {
+        DependencyCheck::from_result(Err("down".to_string()), Instant::now())
+    }
+
+    #[tokio::test]
+    async fn test_check_sqlite() {
+        let pool = db::create_db_pool("sqlite::memory:").await.unwrap();
+        let check = check_sqlite(&pool).await;
+        assert_eq!(check.status, CheckStatus::Ok);
+        assert!(check.error.is_none());
+
+        pool.close().await;
+        let check = check_sqlite(&pool).await;
+        assert_eq!(check.status, CheckStatus::Error);
+        assert!(check.error.is_some());
+    }
+
+    #[test]
+    fn test_report_only_fails_on_required_checks() {
+        let mut checks = BTreeMap::new();
+        checks.insert(
+            "sqlite",
+            DependencyCheck::from_result(Ok(()), Instant::now()),
+        );
+        checks.insert("nats", failed());
+        checks.insert("fmp", DependencyCheck::skipped());
+
+        let liveness = ProbeReport::new(checks.clone(), &["sqlite"]);
+        assert_eq!(liveness.status, CheckStatus::Ok);
+        assert_eq!(liveness.into_response().status(), StatusCode::OK);
+
+        let readiness = ProbeReport::new(checks, &["sqlite", "nats", "fmp"]);
+        assert_eq!(readiness.status, CheckStatus::Error);
+        assert_eq!(
+            readiness.into_response().status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+
+    #[test]
+    fn test_report_json_shape() {
+        let mut checks = BTreeMap::new();
+        checks.insert("nats", failed());
+        checks.insert("fmp", DependencyCheck::skipped());
+        let report = ProbeReport::new(checks, &["nats"]);
+
+        let value = serde_json::to_value(&report).unwrap();
+        assert_eq!(value["status"], "error");
+        assert_eq!(value["checks"]["nats"]["status"], "error");
+        assert_eq!(value["checks"]["nats"]["error"], "down");
+        assert_eq!(
+            value["checks"]["fmp"],
+            serde_json::json!({"status": "skipped"})
+        );
+    }
+}
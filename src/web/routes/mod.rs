@@ -2,7 +2,11 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-only
 
+pub mod admin;
 pub mod api;
 pub mod auth;
+pub mod dashboard;
+pub mod health;
 pub mod pages;
+pub mod reports;
 pub mod sse;
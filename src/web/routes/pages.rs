@@ -8,7 +8,9 @@ use axum::{
     http::StatusCode,
     response::Html,
 };
+use std::collections::HashMap;
 
+use crate::logos;
 use crate::web::{state::AppState, utils};
 
 #[derive(Template)]
@@ -32,8 +34,9 @@ struct ComparisonsListTemplate {
 }
 
 /// Comparisons list page
-pub async fn comparisons_list(State(_state): State<AppState>) -> Result<Html<String>, StatusCode> {
-    let comparisons = utils::list_comparisons().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+pub async fn comparisons_list(State(state): State<AppState>) -> Result<Html<String>, StatusCode> {
+    let comparisons = utils::list_comparisons(&state.config.get().output)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     let template = ComparisonsListTemplate { comparisons };
 
@@ -52,15 +55,20 @@ struct ComparisonViewTemplate {
     records: Vec<utils::ComparisonRecord>,
     summary: Option<String>,
     charts: Vec<utils::ChartFile>,
+    /// Whether any chart is a Vega-Lite spec, which needs vega-embed
+    has_vega_charts: bool,
+    /// Cached logo URLs by ticker, empty until `fetch-logos` has run
+    logos: HashMap<String, String>,
 }
 
 /// Comparison view page
 pub async fn comparison_view(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
     Path((from_date, to_date)): Path<(String, String)>,
 ) -> Result<Html<String>, StatusCode> {
     // Find the comparison
-    let comparisons = utils::list_comparisons().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let comparisons = utils::list_comparisons(&state.config.get().output)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     let comparison = comparisons
         .iter()
@@ -76,12 +84,19 @@ pub async fn comparison_view(
         .as_ref()
         .and_then(|p| utils::read_summary_markdown(p).ok());
 
+    let logos = logos::report_hrefs(
+        &state.config.get().output,
+        records.iter().map(|r| r.ticker.as_str()),
+    );
+
     let template = ComparisonViewTemplate {
         from_date: from_date.clone(),
         to_date: to_date.clone(),
         records,
         summary,
         charts: comparison.chart_paths.clone(),
+        has_vega_charts: comparison.chart_paths.iter().any(|c| c.is_vega()),
+        logos,
     };
 
     Ok(Html(
@@ -102,8 +117,9 @@ struct MarketCapsListTemplate {
 }
 
 /// Market caps list page
-pub async fn market_caps_list(State(_state): State<AppState>) -> Result<Html<String>, StatusCode> {
-    let snapshots = utils::list_market_caps().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+pub async fn market_caps_list(State(state): State<AppState>) -> Result<Html<String>, StatusCode> {
+    let snapshots = utils::list_market_caps(&state.config.get().output)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     let template = MarketCapsListTemplate { snapshots };
 
@@ -125,11 +141,12 @@ struct MarketCapViewTemplate {
 
 /// Market cap view page
 pub async fn market_cap_view(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
     Path(date): Path<String>,
 ) -> Result<Html<String>, StatusCode> {
     // Find the market cap snapshot
-    let snapshots = utils::list_market_caps().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let snapshots = utils::list_market_caps(&state.config.get().output)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     let snapshot = snapshots
         .iter()
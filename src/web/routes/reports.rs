@@ -0,0 +1,325 @@
+// SPDX-FileCopyrightText: 2025 Joost van der Laan <joost@fashionunited.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-only
+
+//! Browse the output directory over HTTP
+//!
+//! `/reports` lists directories; files are served with their content type.
+//! Markdown is rendered to HTML unless `?raw=1` is given, and SVG charts are
+//! shown inline. Paths are confined to the output directory of the server's
+//! current config, so a reloaded `[output] directory` applies right away.
+
+use askama::Template;
+use axum::{
+    extract::{Path as UrlPath, Query, State},
+    http::{StatusCode, header},
+    response::{Html, IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::config::OutputConfig;
+use crate::web::state::AppState;
+
+/// Link in the path navigation above a listing or document
+pub struct Breadcrumb {
+    pub name: String,
+    pub href: String,
+}
+
+/// One file or subdirectory in a listing
+pub struct ReportEntry {
+    pub name: String,
+    pub href: String,
+    pub is_dir: bool,
+    pub size: String,
+    pub modified: String,
+}
+
+#[derive(Template)]
+#[template(path = "reports/list.html")]
+struct ReportsListTemplate {
+    path: String,
+    breadcrumbs: Vec<Breadcrumb>,
+    entries: Vec<ReportEntry>,
+}
+
+#[derive(Template)]
+#[template(path = "reports/markdown.html")]
+struct MarkdownTemplate {
+    title: String,
+    breadcrumbs: Vec<Breadcrumb>,
+    html: String,
+    raw_href: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ReportQuery {
+    /// Serve markdown as plain text instead of rendering it
+    pub raw: Option<String>,
+}
+
+/// Resolve a request path below `root`, rejecting `..`, absolute paths and
+/// symlinks that lead outside it
+pub fn resolve(root: &Path, relative: &str) -> Option<PathBuf> {
+    let relative = Path::new(relative.trim_matches('/'));
+    if !relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+    {
+        return None;
+    }
+    let root = root.canonicalize().ok()?;
+    let path = root.join(relative).canonicalize().ok()?;
+    path.starts_with(&root).then_some(path)
+}
+
+/// Content type of a served file by extension
+pub fn content_type(path: &Path) -> &'static str {
+    match path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .as_deref()
+    {
+        Some("csv") => "text/csv; charset=utf-8",
+        Some("md") => "text/markdown; charset=utf-8",
+        Some("svg") => "image/svg+xml",
+        Some("json") => "application/json",
+        Some("html") => "text/html; charset=utf-8",
+        Some("png") => "image/png",
+        Some("pdf") => "application/pdf",
+        Some("txt") | Some("log") => "text/plain; charset=utf-8",
+        _ => "application/octet-stream",
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// `/reports`, then one link per path segment
+fn breadcrumbs(relative: &str) -> Vec<Breadcrumb> {
+    let mut crumbs = vec![Breadcrumb {
+        name: "reports".to_string(),
+        href: "/reports".to_string(),
+    }];
+    let mut href = String::from("/reports");
+    for segment in relative.split('/').filter(|s| !s.is_empty()) {
+        href.push('/');
+        href.push_str(segment);
+        crumbs.push(Breadcrumb {
+            name: segment.to_string(),
+            href: href.clone(),
+        });
+    }
+    crumbs
+}
+
+/// Entries of a directory: subdirectories first, then newest files first
+pub fn list_entries(dir: &Path, relative: &str) -> std::io::Result<Vec<ReportEntry>> {
+    let base = relative.trim_matches('/');
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') {
+            continue;
+        }
+        let metadata = entry.metadata()?;
+        let modified = metadata.modified().ok().map(DateTime::<Utc>::from);
+        entries.push((
+            modified,
+            ReportEntry {
+                href: if base.is_empty() {
+                    format!("/reports/{}", name)
+                } else {
+                    format!("/reports/{}/{}", base, name)
+                },
+                is_dir: metadata.is_dir(),
+                size: if metadata.is_dir() {
+                    String::new()
+                } else {
+                    format_size(metadata.len())
+                },
+                modified: modified
+                    .map(|m| m.format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_default(),
+                name,
+            },
+        ));
+    }
+    entries.sort_by(|(a_modified, a), (b_modified, b)| {
+        b.is_dir
+            .cmp(&a.is_dir)
+            .then_with(|| b_modified.cmp(a_modified))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    Ok(entries.into_iter().map(|(_, entry)| entry).collect())
+}
+
+/// Render markdown to HTML and strip scripts, event handlers and other
+/// unsafe markup, since the template outputs it unescaped
+fn render_markdown(markdown: &str) -> String {
+    let parser = pulldown_cmark::Parser::new_ext(markdown, pulldown_cmark::Options::all());
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, parser);
+    ammonia::clean(&html)
+}
+
+fn render<T: Template>(template: T) -> Result<Response, StatusCode> {
+    let html = template
+        .render()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Html(html).into_response())
+}
+
+async fn serve(
+    output: &OutputConfig,
+    relative: String,
+    query: ReportQuery,
+) -> Result<Response, StatusCode> {
+    let path = resolve(output.directory(), &relative).ok_or(StatusCode::NOT_FOUND)?;
+    let relative = relative.trim_matches('/').to_string();
+
+    if path.is_dir() {
+        let entries =
+            list_entries(&path, &relative).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        return render(ReportsListTemplate {
+            breadcrumbs: breadcrumbs(&relative),
+            path: relative,
+            entries,
+        });
+    }
+
+    let content_type = content_type(&path);
+    if content_type.starts_with("text/markdown") && query.raw.is_none() {
+        let markdown = fs::read_to_string(&path).map_err(|_| StatusCode::NOT_FOUND)?;
+        return render(MarkdownTemplate {
+            title: relative.rsplit('/').next().unwrap_or_default().to_string(),
+            breadcrumbs: breadcrumbs(&relative),
+            raw_href: format!("/reports/{}?raw=1", relative),
+            html: render_markdown(&markdown),
+        });
+    }
+
+    let bytes = fs::read(&path).map_err(|_| StatusCode::NOT_FOUND)?;
+    let filename = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let disposition = if content_type == "application/octet-stream" {
+        format!("attachment; filename=\"{}\"", filename)
+    } else {
+        format!("inline; filename=\"{}\"", filename)
+    };
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        bytes,
+    )
+        .into_response())
+}
+
+/// Listing of the output directory
+pub async fn reports_index(
+    State(state): State<AppState>,
+    Query(query): Query<ReportQuery>,
+) -> Result<Response, StatusCode> {
+    serve(&state.config.get().output, String::new(), query).await
+}
+
+/// A subdirectory or file of the output directory
+pub async fn report_file(
+    State(state): State<AppState>,
+    UrlPath(relative): UrlPath<String>,
+    Query(query): Query<ReportQuery>,
+) -> Result<Response, StatusCode> {
+    serve(&state.config.get().output, relative, query).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_resolve_stays_inside_root() {
+        let root = TempDir::new().unwrap();
+        fs::create_dir(root.path().join("charts")).unwrap();
+        fs::write(root.path().join("charts/rank.svg"), "<svg/>").unwrap();
+
+        let canonical = root.path().canonicalize().unwrap();
+        assert_eq!(resolve(root.path(), "").unwrap(), canonical);
+        assert_eq!(
+            resolve(root.path(), "/charts/rank.svg").unwrap(),
+            canonical.join("charts/rank.svg")
+        );
+        assert!(resolve(root.path(), "../etc/passwd").is_none());
+        assert!(resolve(root.path(), "charts/../../secret").is_none());
+        assert!(resolve(root.path(), "missing.csv").is_none());
+    }
+
+    #[test]
+    fn test_list_entries_puts_directories_first() {
+        let root = TempDir::new().unwrap();
+        fs::write(root.path().join("comparison.csv"), "Ticker\nNKE\n").unwrap();
+        fs::write(root.path().join(".hidden"), "").unwrap();
+        fs::create_dir(root.path().join("charts")).unwrap();
+
+        let entries = list_entries(root.path(), "2025").unwrap();
+        let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["charts", "comparison.csv"]);
+        assert_eq!(entries[0].href, "/reports/2025/charts");
+        assert_eq!(entries[1].size, "11 B");
+    }
+
+    #[test]
+    fn test_content_type_and_breadcrumbs() {
+        assert_eq!(
+            content_type(Path::new("a/comparison.CSV")),
+            "text/csv; charset=utf-8"
+        );
+        assert_eq!(content_type(Path::new("rank.svg")), "image/svg+xml");
+        assert_eq!(
+            content_type(Path::new("archive.zip")),
+            "application/octet-stream"
+        );
+        assert_eq!(format_size(2048), "2.0 KB");
+
+        let crumbs = breadcrumbs("2025/charts");
+        let hrefs: Vec<&str> = crumbs.iter().map(|c| c.href.as_str()).collect();
+        assert_eq!(
+            hrefs,
+            vec!["/reports", "/reports/2025", "/reports/2025/charts"]
+        );
+    }
+
+    #[test]
+    fn test_render_markdown_strips_unsafe_html() {
+        let html = render_markdown(
+            "# Summary\n\n| A | B |\n|---|---|\n| 1 | 2 |\n\n<script>alert(1)</script>\n\n<img src=\"x.svg\" onerror=\"alert(2)\">\n\n[link](javascript:alert(3))\n",
+        );
+        assert!(html.contains("<h1>Summary</h1>"));
+        assert!(html.contains("<table>"));
+        assert!(html.contains("<img src=\"x.svg\">"));
+        assert!(!html.contains("<script"));
+        assert!(!html.contains("onerror"));
+        assert!(!html.contains("javascript:"));
+    }
+}
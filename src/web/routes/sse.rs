@@ -4,16 +4,23 @@
 
 use axum::{
     extract::{Query, State},
-    response::sse::{Event, Sse},
+    http::HeaderMap,
+    response::sse::{Event, KeepAlive, Sse},
 };
 use futures::StreamExt;
 use futures::stream::Stream;
 use serde::{Deserialize, Serialize};
 use std::convert::Infallible;
+use std::time::Duration;
 
-use crate::nats::{JobParameters, JobType};
+use crate::nats::{JobEventKind, JobParameters, JobType};
 use crate::web::state::AppState;
 
+/// Interval between SSE heartbeat comments on job event streams
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+/// Reconnect delay suggested to browsers
+const RECONNECT_DELAY: Duration = Duration::from_secs(3);
+
 #[derive(Debug, Deserialize)]
 pub struct GenerateComparisonParams {
     pub from_date: String,
@@ -282,6 +289,11 @@ pub async fn job_progress_sse(
     let nats_client = state.nats_client.clone();
 
     let stream = async_stream::stream! {
+        if let Err(e) = crate::nats::validate_job_id(&job_id) {
+            yield Ok(create_error_event(&e.to_string()));
+            return;
+        }
+
         // Subscribe to job progress and result
         let progress_subject = format!("jobs.{}.progress", job_id);
         let result_subject = format!("jobs.{}.result", job_id);
@@ -342,3 +354,84 @@ pub async fn job_progress_sse(
 
     Sse::new(stream)
 }
+
+/// Stream sequence of the last event a reconnecting client saw
+fn last_event_id(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get("last-event-id")?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// SSE stream of a job's status, progress and result messages.
+///
+/// Messages are replayed from the JOBS_TRACKING stream, so a client that
+/// connects late still sees earlier progress. Event ids are stream sequences:
+/// browsers resend the last one as `Last-Event-ID` on reconnect and the
+/// stream resumes after it. The stream ends after the `result` event.
+pub async fn job_events_sse(
+    State(state): State<AppState>,
+    axum::extract::Path(job_id): axum::extract::Path<String>,
+    headers: HeaderMap,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let nats_client = state.nats_client.clone();
+    let after_sequence = last_event_id(&headers);
+
+    let stream = async_stream::stream! {
+        let messages =
+            match crate::nats::job_event_messages(&nats_client, &job_id, after_sequence).await {
+                Ok(messages) => messages,
+                Err(e) => {
+                    yield Ok(create_error_event(&e.to_string()));
+                    return;
+                }
+            };
+        futures::pin_mut!(messages);
+
+        yield Ok(Event::default().retry(RECONNECT_DELAY).comment("connected"));
+
+        while let Some(message) = messages.next().await {
+            let message = match message {
+                Ok(message) => message,
+                Err(e) => {
+                    yield Ok(create_error_event(&e.to_string()));
+                    break;
+                }
+            };
+            let data = String::from_utf8_lossy(&message.payload).to_string();
+            yield Ok(Event::default()
+                .id(message.sequence.to_string())
+                .event(message.kind.as_str())
+                .data(data));
+            if message.kind == JobEventKind::Result {
+                break;
+            }
+        }
+    };
+
+    Sse::new(stream).keep_alive(
+        KeepAlive::new()
+            .interval(HEARTBEAT_INTERVAL)
+            .text("heartbeat"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_last_event_id() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(last_event_id(&headers), None);
+
+        headers.insert("Last-Event-ID", "42".parse().unwrap());
+        assert_eq!(last_event_id(&headers), Some(42));
+
+        headers.insert("Last-Event-ID", "not-a-sequence".parse().unwrap());
+        assert_eq!(last_event_id(&headers), None);
+    }
+}
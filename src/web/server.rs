@@ -2,38 +2,53 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-only
 
-use axum::{Json, Router, routing::get};
+use axum::{
+    Extension, Json, Router,
+    extract::State,
+    http::{StatusCode, header},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
 use serde_json::json;
 use std::net::SocketAddr;
+use std::time::Duration;
 use tower_http::services::ServeDir;
 
-use crate::web::{routes, state::AppState};
+use crate::shutdown::Shutdown;
+use crate::web::{
+    graphql,
+    middleware::{metrics::track_requests, roles},
+    routes,
+    state::AppState,
+};
+use crate::{metrics, nats};
 
 /// Create the Axum router with all routes
 pub fn create_app(state: AppState) -> Router {
-    Router::new()
-        // Health check endpoint
-        .route("/health", get(health_check))
-        // Authentication routes (no auth required)
-        .route("/login", get(routes::auth::login_page))
-        .route("/api/auth/callback", get(routes::auth::auth_callback))
-        .route("/api/auth/logout", get(routes::auth::logout))
-        // Dashboard page (will require auth later)
+    let schema = graphql::build_schema(state.db_pool.clone(), state.config.clone());
+
+    // Every signed-in user can read reports and follow jobs
+    let viewer_routes = Router::new()
         .route("/", get(routes::pages::dashboard))
+        // Built-in dashboard embedded in the binary
+        .route("/dashboard", get(routes::dashboard::dashboard))
+        .route(
+            "/dashboard/dashboard.js",
+            get(routes::dashboard::dashboard_js),
+        )
         // Comparison pages
         .route("/comparisons", get(routes::pages::comparisons_list))
-        .route("/comparisons/new", get(routes::pages::new_comparison))
         .route(
             "/comparisons/:from/:to",
             get(routes::pages::comparison_view),
         )
         // Market cap pages
         .route("/market-caps", get(routes::pages::market_caps_list))
-        .route(
-            "/market-caps/fetch",
-            get(routes::pages::fetch_market_caps_page),
-        )
         .route("/market-caps/:date", get(routes::pages::market_cap_view))
+        // Generated files in the output directory
+        .route("/reports", get(routes::reports::reports_index))
+        .route("/reports/*path", get(routes::reports::report_file))
         // API endpoints
         .route("/api/comparisons", get(routes::api::list_comparisons))
         .route(
@@ -43,36 +58,124 @@ pub fn create_app(state: AppState) -> Router {
         .route("/api/charts/:from/:to/:type", get(routes::api::get_chart))
         .route("/api/market-caps", get(routes::api::list_market_caps))
         .route("/api/market-caps/:date", get(routes::api::get_market_cap))
+        // SQLite-backed data endpoints
+        .route("/api/marketcaps", get(routes::api::get_marketcaps))
+        .route(
+            "/api/companies/:ticker/history",
+            get(routes::api::get_company_history),
+        )
+        .route("/api/search", get(routes::api::search_companies))
+        // GraphQL endpoint over the same data
+        .route("/graphql", post(graphql::graphql_handler))
         // Job management endpoints
+        .route("/api/jobs", get(routes::api::list_jobs))
         .route("/api/jobs/:job_id", get(routes::api::get_job_status))
-        // SSE endpoints for data generation
+        .route(
+            "/api/jobs/:job_id/progress",
+            get(routes::sse::job_progress_sse),
+        )
+        .route("/api/jobs/:job_id/events", get(routes::sse::job_events_sse))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            roles::require_viewer,
+        ));
+
+    // Analysts and admins can run comparisons
+    let analyst_routes = Router::new()
+        .route("/comparisons/new", get(routes::pages::new_comparison))
         .route(
             "/api/generate-comparison-sse",
             get(routes::sse::generate_comparison_sse),
         )
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            roles::require_analyst,
+        ));
+
+    // Only admins and `fetch` API keys can submit fetch jobs, which spend API quota
+    let fetch_routes = Router::new()
+        .route(
+            "/market-caps/fetch",
+            get(routes::pages::fetch_market_caps_page),
+        )
         .route(
             "/api/fetch-market-caps-sse",
             get(routes::sse::fetch_market_caps_sse),
         )
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            roles::require_fetch,
+        ));
+
+    // Only signed-in admins can reload the config
+    let admin_routes = Router::new()
         .route(
-            "/api/jobs/:job_id/progress",
-            get(routes::sse::job_progress_sse),
+            "/api/admin/reload-config",
+            post(routes::admin::reload_config),
         )
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            roles::require_admin,
+        ));
+
+    Router::new()
+        // Health check endpoint
+        .route("/health", get(health_check))
+        // Kubernetes probes with per-dependency status
+        .route("/healthz", get(routes::health::healthz))
+        .route("/readyz", get(routes::health::readyz))
+        // Prometheus metrics
+        .route("/metrics", get(metrics_handler))
+        // Authentication routes (no auth required)
+        .route("/login", get(routes::auth::login_page))
+        .route("/api/auth/callback", get(routes::auth::auth_callback))
+        .route("/api/auth/logout", get(routes::auth::logout))
+        // Role-protected routes
+        .merge(viewer_routes)
+        .merge(analyst_routes)
+        .merge(fetch_routes)
+        .merge(admin_routes)
         // Static file serving
         .nest_service("/static", ServeDir::new("static"))
+        // Count requests for /metrics
+        .route_layer(middleware::from_fn(track_requests))
+        .layer(Extension(schema))
         // Share app state
         .with_state(state)
 }
 
-/// Start the web server
-pub async fn start_server(state: AppState, port: u16) -> anyhow::Result<()> {
+/// Start the web server. Once `shutdown` is requested it stops accepting
+/// connections and waits up to `drain_timeout` for open requests (including
+/// SSE streams) before returning.
+pub async fn start_server(
+    state: AppState,
+    port: u16,
+    shutdown: Shutdown,
+    drain_timeout: Duration,
+) -> anyhow::Result<()> {
     let app = create_app(state);
 
     let addr = SocketAddr::from(([0, 0, 0, 0], port));
     println!("🚀 Server starting on http://{}", addr);
 
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    let server = axum::serve(listener, app).with_graceful_shutdown({
+        let shutdown = shutdown.clone();
+        async move { shutdown.wait().await }
+    });
+    let server = tokio::spawn(async move { server.await });
+
+    tokio::select! {
+        result = server => result??,
+        _ = async {
+            shutdown.wait().await;
+            tokio::time::sleep(drain_timeout).await;
+        } => eprintln!(
+            "⚠️  Open requests did not finish within {}s, closing them",
+            drain_timeout.as_secs()
+        ),
+    }
+    println!("✓ HTTP server stopped");
 
     Ok(())
 }
@@ -84,3 +187,19 @@ async fn health_check() -> Json<serde_json::Value> {
         "timestamp": chrono::Utc::now().to_rfc3339()
     }))
 }
+
+/// Prometheus metrics endpoint
+async fn metrics_handler(State(state): State<AppState>) -> Response {
+    let metrics = metrics::metrics();
+
+    // Sample the job backlog at scrape time
+    match nats::pending_job_count(&state.nats_client).await {
+        Ok(pending) => metrics.nats_consumer_lag.set(pending as i64),
+        Err(e) => eprintln!("Failed to read job queue depth: {}", e),
+    }
+
+    match metrics.render() {
+        Ok(body) => ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
@@ -2,8 +2,8 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-only
 
-use crate::config::Config;
 use crate::nats::NatsClient;
+use crate::web::config_watch::SharedConfig;
 use sqlx::SqlitePool;
 use workos::WorkOs;
 
@@ -11,19 +11,23 @@ use workos::WorkOs;
 #[derive(Clone)]
 pub struct AppState {
     pub db_pool: SqlitePool,
-    pub config: Config,
+    /// config.toml, reloaded when the file changes
+    pub config: SharedConfig,
     pub workos_client: WorkOs,
     pub jwt_secret: String,
     pub nats_client: NatsClient,
+    /// Include FMP reachability in `/readyz`
+    pub check_fmp: bool,
 }
 
 impl AppState {
     pub fn new(
         db_pool: SqlitePool,
-        config: Config,
+        config: SharedConfig,
         workos_client: WorkOs,
         jwt_secret: String,
         nats_client: NatsClient,
+        check_fmp: bool,
     ) -> Self {
         Self {
             db_pool,
@@ -31,6 +35,7 @@ impl AppState {
             workos_client,
             jwt_secret,
             nats_client,
+            check_fmp,
         }
     }
 }
@@ -2,6 +2,7 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-only
 
+use crate::config::OutputConfig;
 use anyhow::{Context, Result};
 use chrono::NaiveDate;
 use csv::Reader;
@@ -27,6 +28,14 @@ pub struct ChartFile {
     pub path: PathBuf,
 }
 
+impl ChartFile {
+    /// Whether the chart is a Vega-Lite spec (`--chart-backend vega`)
+    /// rather than an SVG image
+    pub fn is_vega(&self) -> bool {
+        self.path.to_string_lossy().ends_with(".vl.json")
+    }
+}
+
 /// Comparison data from CSV
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ComparisonRecord {
@@ -57,8 +66,8 @@ pub struct ComparisonRecord {
 }
 
 /// Scan the output directory for comparison files
-pub fn list_comparisons() -> Result<Vec<ComparisonMetadata>> {
-    let output_dir = Path::new("output");
+pub fn list_comparisons(output: &OutputConfig) -> Result<Vec<ComparisonMetadata>> {
+    let output_dir = output.directory();
 
     if !output_dir.exists() {
         return Ok(Vec::new());
@@ -113,8 +122,9 @@ fn parse_comparison_filename(filename: &str, csv_path: &Path) -> Option<Comparis
 
     // Find associated files
     let base_pattern = format!("comparison_{}_to_{}", from_date, to_date);
-    let summary_path = find_file_with_pattern(&base_pattern, "_summary_", ".md");
-    let chart_paths = find_chart_files(&base_pattern);
+    let output_dir = csv_path.parent().unwrap_or(Path::new("."));
+    let summary_path = find_file_with_pattern(output_dir, &base_pattern, "_summary_", ".md");
+    let chart_paths = find_chart_files(output_dir, &base_pattern);
 
     Some(ComparisonMetadata {
         from_date,
@@ -127,8 +137,12 @@ fn parse_comparison_filename(filename: &str, csv_path: &Path) -> Option<Comparis
 }
 
 /// Find a file matching a pattern
-fn find_file_with_pattern(base: &str, middle: &str, ext: &str) -> Option<PathBuf> {
-    let output_dir = Path::new("output");
+fn find_file_with_pattern(
+    output_dir: &Path,
+    base: &str,
+    middle: &str,
+    ext: &str,
+) -> Option<PathBuf> {
     if let Ok(entries) = fs::read_dir(output_dir) {
         for entry in entries.flatten() {
             if let Some(filename) = entry.file_name().to_str() {
@@ -144,9 +158,9 @@ fn find_file_with_pattern(base: &str, middle: &str, ext: &str) -> Option<PathBuf
     None
 }
 
-/// Find all chart files for a comparison
-fn find_chart_files(base_pattern: &str) -> Vec<ChartFile> {
-    let output_dir = Path::new("output");
+/// Find all chart files for a comparison, both SVG images and Vega-Lite
+/// specs; the SVG wins when a chart exists in both formats
+fn find_chart_files(output_dir: &Path, base_pattern: &str) -> Vec<ChartFile> {
     let mut charts = Vec::new();
 
     let chart_types = vec![
@@ -154,12 +168,15 @@ fn find_chart_files(base_pattern: &str) -> Vec<ChartFile> {
         "market_distribution",
         "rank_movements",
         "summary_dashboard",
+        "treemap",
+        "waterfall",
     ];
 
     if let Ok(entries) = fs::read_dir(output_dir) {
         for entry in entries.flatten() {
             if let Some(filename) = entry.file_name().to_str() {
-                if filename.starts_with(base_pattern) && filename.ends_with(".svg") {
+                let is_chart = filename.ends_with(".svg") || filename.ends_with(".vl.json");
+                if filename.starts_with(base_pattern) && is_chart {
                     // Determine chart type from filename
                     for chart_type in &chart_types {
                         if filename.contains(chart_type) {
@@ -175,6 +192,9 @@ fn find_chart_files(base_pattern: &str) -> Vec<ChartFile> {
         }
     }
 
+    charts.sort_by_key(|chart| chart.is_vega());
+    let mut seen = std::collections::HashSet::new();
+    charts.retain(|chart| seen.insert(chart.chart_type.clone()));
     charts
 }
 
@@ -254,8 +274,8 @@ pub struct MarketCapRecord {
 }
 
 /// Scan the output directory for market cap snapshot files
-pub fn list_market_caps() -> Result<Vec<MarketCapMetadata>> {
-    let output_dir = Path::new("output");
+pub fn list_market_caps(output: &OutputConfig) -> Result<Vec<MarketCapMetadata>> {
+    let output_dir = output.directory();
 
     if !output_dir.exists() {
         return Ok(Vec::new());
@@ -299,7 +319,12 @@ fn parse_marketcap_filename(filename: &str, csv_path: &Path) -> Option<MarketCap
         return None;
     }
 
-    // Parse date: YYYY-MM-DD (first part)
+    // Parse date: YYYY-MM-DD (first part), then a timestamp; skips e.g.
+    // marketcaps_{date}_by_region.csv
+    parse_date(parts[0]).ok()?;
+    if !parts[1].starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
     let date = parts[0].to_string();
     let timestamp = parts[1..].join("_");
 
@@ -365,5 +390,32 @@ mod tests {
         let metadata = metadata.unwrap();
         assert_eq!(metadata.date, "2025-01-01");
         assert_eq!(metadata.timestamp, "20250101_120000");
+
+        let filename = "marketcaps_2025-01-01_by_region.csv";
+        assert!(parse_marketcap_filename(filename, path).is_none());
+    }
+
+    #[test]
+    fn test_find_chart_files_lists_vega_specs() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = "comparison_2025-01-01_to_2025-02-01";
+        for name in [
+            "comparison_2025-01-01_to_2025-02-01_treemap.vl.json",
+            "comparison_2025-01-01_to_2025-02-01_waterfall.vl.json",
+            "comparison_2025-01-01_to_2025-02-01_waterfall.svg",
+            "comparison_2025-01-01_to_2025-02-01_treemap.png",
+        ] {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+
+        let mut charts = find_chart_files(dir.path(), base);
+        charts.sort_by(|a, b| a.chart_type.cmp(&b.chart_type));
+        assert_eq!(
+            charts
+                .iter()
+                .map(|c| (c.chart_type.as_str(), c.is_vega()))
+                .collect::<Vec<_>>(),
+            vec![("treemap", true), ("waterfall", false)]
+        );
     }
 }
@@ -5,15 +5,51 @@
 //! Integration tests for the web interface
 //!
 //! These tests verify that the web server responds correctly to requests
-//! and that the UI pages render without errors.
+//! and that the UI pages render without errors. They need a server on
+//! `BASE_URL`; role-guarded routes are requested with a JWT signed with the
+//! server's `JWT_SECRET` (the server's default when unset).
 
 use chrono::Utc;
+use jsonwebtoken::{EncodingKey, Header, encode};
 use reqwest;
+use reqwest::header::{self, HeaderMap, HeaderValue};
 use std::time::Duration;
 use tokio;
+use top200_rs::web::models::auth::Claims;
 
 const BASE_URL: &str = "http://localhost:3001";
 
+/// Client authenticated as a user with `role` (viewer, analyst or admin)
+fn client_as(role: &str) -> reqwest::Client {
+    let secret = std::env::var("JWT_SECRET")
+        .unwrap_or_else(|_| "default-secret-change-in-production".to_string());
+    let now = Utc::now();
+    let claims = Claims {
+        sub: format!("test-{}", role),
+        email: format!("{}@example.com", role),
+        role: role.to_string(),
+        iat: now.timestamp(),
+        exp: (now + chrono::Duration::hours(1)).timestamp(),
+    };
+    let token = encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(secret.as_bytes()),
+    )
+    .expect("Failed to sign token");
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::AUTHORIZATION,
+        HeaderValue::from_str(&format!("Bearer {}", token)).unwrap(),
+    );
+    reqwest::Client::builder()
+        .default_headers(headers)
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .expect("Failed to build client")
+}
+
 /// Test that the server is running and health check works
 #[tokio::test]
 async fn test_health_check() {
@@ -35,7 +71,7 @@ async fn test_health_check() {
 /// Test that the dashboard page loads
 #[tokio::test]
 async fn test_dashboard_loads() {
-    let client = reqwest::Client::new();
+    let client = client_as("viewer");
     let response = client
         .get(format!("{}/", BASE_URL))
         .timeout(Duration::from_secs(5))
@@ -54,7 +90,7 @@ async fn test_dashboard_loads() {
 /// Test that the comparisons list page loads
 #[tokio::test]
 async fn test_comparisons_list_loads() {
-    let client = reqwest::Client::new();
+    let client = client_as("viewer");
     let response = client
         .get(format!("{}/comparisons", BASE_URL))
         .timeout(Duration::from_secs(5))
@@ -71,7 +107,7 @@ async fn test_comparisons_list_loads() {
 /// Test that the new comparison form page loads
 #[tokio::test]
 async fn test_new_comparison_form_loads() {
-    let client = reqwest::Client::new();
+    let client = client_as("analyst");
     let response = client
         .get(format!("{}/comparisons/new", BASE_URL))
         .timeout(Duration::from_secs(5))
@@ -91,7 +127,7 @@ async fn test_new_comparison_form_loads() {
 /// Test that the market caps list page loads
 #[tokio::test]
 async fn test_market_caps_list_loads() {
-    let client = reqwest::Client::new();
+    let client = client_as("viewer");
     let response = client
         .get(format!("{}/market-caps", BASE_URL))
         .timeout(Duration::from_secs(5))
@@ -108,7 +144,7 @@ async fn test_market_caps_list_loads() {
 /// Test that the fetch market caps form page loads
 #[tokio::test]
 async fn test_fetch_market_caps_form_loads() {
-    let client = reqwest::Client::new();
+    let client = client_as("admin");
     let response = client
         .get(format!("{}/market-caps/fetch", BASE_URL))
         .timeout(Duration::from_secs(5))
@@ -126,7 +162,7 @@ async fn test_fetch_market_caps_form_loads() {
 /// Test that the API comparisons endpoint returns valid JSON
 #[tokio::test]
 async fn test_api_comparisons_list() {
-    let client = reqwest::Client::new();
+    let client = client_as("viewer");
     let response = client
         .get(format!("{}/api/comparisons", BASE_URL))
         .timeout(Duration::from_secs(5))
@@ -147,7 +183,7 @@ async fn test_api_comparisons_list() {
 /// Test that the API market caps endpoint returns valid JSON
 #[tokio::test]
 async fn test_api_market_caps_list() {
-    let client = reqwest::Client::new();
+    let client = client_as("viewer");
     let response = client
         .get(format!("{}/api/market-caps", BASE_URL))
         .timeout(Duration::from_secs(5))
@@ -165,10 +201,30 @@ async fn test_api_market_caps_list() {
     assert!(snapshots.is_array());
 }
 
+/// Test that bad job filters are client errors
+#[tokio::test]
+async fn test_api_jobs_filters() {
+    let client = client_as("viewer");
+    for (query, status) in [
+        ("", 200),
+        ("?status=failed", 200),
+        ("?status=lost", 400),
+        ("?since=March", 400),
+    ] {
+        let response = client
+            .get(format!("{}/api/jobs{}", BASE_URL, query))
+            .timeout(Duration::from_secs(5))
+            .send()
+            .await
+            .expect("Failed to connect to server");
+        assert_eq!(response.status(), status, "/api/jobs{}", query);
+    }
+}
+
 /// Test that SSE endpoint for comparison generation is accessible
 #[tokio::test]
 async fn test_sse_comparison_endpoint_accessible() {
-    let client = reqwest::Client::new();
+    let client = client_as("analyst");
 
     let today = Utc::now().format("%Y-%m-%d").to_string();
     let yesterday = (Utc::now() - chrono::Duration::days(1))
@@ -208,7 +264,7 @@ async fn test_sse_comparison_endpoint_accessible() {
 /// Test that SSE endpoint for market caps fetch is accessible
 #[tokio::test]
 async fn test_sse_market_caps_endpoint_accessible() {
-    let client = reqwest::Client::new();
+    let client = client_as("admin");
 
     let today = Utc::now().format("%Y-%m-%d").to_string();
 
@@ -271,10 +327,7 @@ async fn test_static_css_loads() {
 /// Test navigation between pages
 #[tokio::test]
 async fn test_page_navigation() {
-    let client = reqwest::Client::builder()
-        .redirect(reqwest::redirect::Policy::none())
-        .build()
-        .expect("Failed to build client");
+    let client = client_as("viewer");
 
     // Dashboard should load
     let response = client
@@ -307,7 +360,7 @@ async fn test_page_navigation() {
 /// Test that invalid routes return 404
 #[tokio::test]
 async fn test_invalid_routes_return_404() {
-    let client = reqwest::Client::new();
+    let client = client_as("viewer");
 
     let invalid_routes = vec![
         "/nonexistent",
@@ -327,3 +380,46 @@ async fn test_invalid_routes_return_404() {
         assert_eq!(response.status(), 404, "Route {} should return 404", route);
     }
 }
+
+/// Test that role-guarded routes reject missing tokens and lower roles
+#[tokio::test]
+async fn test_role_guards() {
+    let anonymous = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .expect("Failed to build client");
+
+    // API clients get a 401, browsers are sent to the login page
+    let response = anonymous
+        .get(format!("{}/api/comparisons", BASE_URL))
+        .timeout(Duration::from_secs(5))
+        .send()
+        .await
+        .expect("Failed to connect to server");
+    assert_eq!(response.status(), 401);
+
+    let response = anonymous
+        .get(format!("{}/comparisons", BASE_URL))
+        .header(header::ACCEPT, "text/html")
+        .timeout(Duration::from_secs(5))
+        .send()
+        .await
+        .expect("Failed to connect to server");
+    assert!(response.status().is_redirection());
+    assert_eq!(response.headers()[header::LOCATION], "/login");
+
+    // Signed-in users below the required role are forbidden
+    for (role, route) in [
+        ("viewer", "/comparisons/new"),
+        ("viewer", "/market-caps/fetch"),
+        ("analyst", "/market-caps/fetch"),
+    ] {
+        let response = client_as(role)
+            .get(format!("{}{}", BASE_URL, route))
+            .timeout(Duration::from_secs(5))
+            .send()
+            .await
+            .expect("Failed to connect to server");
+        assert_eq!(response.status(), 403, "{} on {}", role, route);
+    }
+}